@@ -1671,7 +1671,9 @@ export default {
       "ReceivedInvalidProof",
       "FileKeyAlreadyStored",
       "RequestExpired",
-      "InternalError"
+      "InternalError",
+      "ReachedBucketDataLimit",
+      "MaxConcurrentUploadsReached"
     ]
   },
   /**
@@ -1361,9 +1361,12 @@ import type {
   StorageDataUnit,
   StorageProviderId,
   TrieRemoveMutation,
+  UploadProgress,
+  UploadState,
   ValuePropId,
   ValueProposition,
-  ValuePropositionWithId
+  ValuePropositionWithId,
+  VerifiedFileProof
 } from "@storagehub/api-augment/interfaces/storagehubclient";
 declare module "@polkadot/types/types/registry" {
   interface InterfaceTypes {
@@ -2547,6 +2550,8 @@ declare module "@polkadot/types/types/registry" {
     UnrewardedRelayersState: UnrewardedRelayersState;
     UpgradeGoAhead: UpgradeGoAhead;
     UpgradeRestriction: UpgradeRestriction;
+    UploadProgress: UploadProgress;
+    UploadState: UploadState;
     UpwardMessage: UpwardMessage;
     usize: usize;
     USize: USize;
@@ -2575,6 +2580,7 @@ declare module "@polkadot/types/types/registry" {
     ValueProposition: ValueProposition;
     ValuePropositionWithId: ValuePropositionWithId;
     VecInboundHrmpMessage: VecInboundHrmpMessage;
+    VerifiedFileProof: VerifiedFileProof;
     VersionedMultiAsset: VersionedMultiAsset;
     VersionedMultiAssets: VersionedMultiAssets;
     VersionedMultiLocation: VersionedMultiLocation;
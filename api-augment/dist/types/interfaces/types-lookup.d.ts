@@ -2144,12 +2144,16 @@ declare module "@polkadot/types/lookup" {
     readonly isFileKeyAlreadyStored: boolean;
     readonly isRequestExpired: boolean;
     readonly isInternalError: boolean;
+    readonly isReachedBucketDataLimit: boolean;
+    readonly isMaxConcurrentUploadsReached: boolean;
     readonly type:
       | "ReachedMaximumCapacity"
       | "ReceivedInvalidProof"
       | "FileKeyAlreadyStored"
       | "RequestExpired"
-      | "InternalError";
+      | "InternalError"
+      | "ReachedBucketDataLimit"
+      | "MaxConcurrentUploadsReached";
   }
   /** @name PalletFileSystemEitherAccountIdOrMspId (139) */
   interface PalletFileSystemEitherAccountIdOrMspId extends Enum {
@@ -1807,12 +1807,26 @@ declare module "@polkadot/api-base/types/events" {
       /**
        * Event emitted when a Backup Storage Provider has confirmed its sign up successfully. Provides information about
        * that BSP's account id, the initial root of the Merkle Patricia Trie that it stores, the total data it can store
-       * according to its stake, and its multiaddress.
+       * according to its stake, its multiaddress and the account that receives its payments.
        **/
       BspSignUpSuccess: AugmentedEvent<
         ApiType,
-        [who: AccountId32, bspId: H256, root: H256, multiaddresses: Vec<Bytes>, capacity: u64],
-        { who: AccountId32; bspId: H256; root: H256; multiaddresses: Vec<Bytes>; capacity: u64 }
+        [
+          who: AccountId32,
+          bspId: H256,
+          root: H256,
+          multiaddresses: Vec<Bytes>,
+          capacity: u64,
+          paymentAccount: AccountId32
+        ],
+        {
+          who: AccountId32;
+          bspId: H256;
+          root: H256;
+          multiaddresses: Vec<Bytes>;
+          capacity: u64;
+          paymentAccount: AccountId32;
+        }
       >;
       /**
        * Event emitted when a bucket's root has been changed.
@@ -1913,7 +1927,8 @@ declare module "@polkadot/api-base/types/events" {
       >;
       /**
        * Event emitted when a Main Storage Provider has confirmed its sign up successfully. Provides information about
-       * that MSP's account id, the total data it can store according to its stake, its multiaddress, and its value proposition.
+       * that MSP's account id, the total data it can store according to its stake, its multiaddress, its value proposition
+       * and the account that receives its payments.
        **/
       MspSignUpSuccess: AugmentedEvent<
         ApiType,
@@ -1922,7 +1937,8 @@ declare module "@polkadot/api-base/types/events" {
           mspId: H256,
           multiaddresses: Vec<Bytes>,
           capacity: u64,
-          valueProp: PalletStorageProvidersValuePropositionWithId
+          valueProp: PalletStorageProvidersValuePropositionWithId,
+          paymentAccount: AccountId32
         ],
         {
           who: AccountId32;
@@ -1930,6 +1946,7 @@ declare module "@polkadot/api-base/types/events" {
           multiaddresses: Vec<Bytes>;
           capacity: u64;
           valueProp: PalletStorageProvidersValuePropositionWithId;
+          paymentAccount: AccountId32;
         }
       >;
       /**
@@ -104,7 +104,9 @@ import type {
   GetFileFromFileStorageResult,
   LoadFileInStorageResult,
   RemoveFilesFromForestStorageResult,
-  SaveFileToDisk
+  SaveFileToDisk,
+  UploadProgress,
+  VerifiedFileProof
 } from "@storagehub/api-augment/interfaces/storagehubclient";
 
 export type __AugmentedRpc = AugmentedRpc<() => unknown>;
@@ -1076,6 +1078,15 @@ declare module "@polkadot/rpc-core/types/jsonrpc" {
           file_key: H256 | string | Uint8Array
         ) => Observable<Bytes>
       >;
+      /**
+       * Generate a SCALE-encoded proof that this node holds some chunks of a file. Rate limited.
+       **/
+      generateFileProof: AugmentedRpc<
+        (
+          file_key: H256 | string | Uint8Array,
+          chunk_ids: Vec<u64> | (u64 | AnyNumber | Uint8Array)[]
+        ) => Observable<Bytes>
+      >;
       /**
        * Generate a SCALE-encoded proof for a group of file keys that might or might not be in the forest.
        **/
@@ -1192,6 +1203,32 @@ declare module "@polkadot/rpc-core/types/jsonrpc" {
           file_path: Text | string
         ) => Observable<SaveFileToDisk>
       >;
+      /**
+       * Load a file in the local storage and upload it to the network, issuing its storage request.
+       **/
+      uploadFile: AugmentedRpc<
+        (
+          file_path: Text | string,
+          location: Text | string,
+          bucket_id: H256 | string | Uint8Array,
+          replication_target: Option<u32> | null | Uint8Array | u32 | AnyNumber
+        ) => Observable<LoadFileInStorageResult>
+      >;
+      /**
+       * Get the progress of a file being uploaded to this node.
+       **/
+      uploadProgress: AugmentedRpc<
+        (file_key: H256 | string | Uint8Array) => Observable<Option<UploadProgress>>
+      >;
+      /**
+       * Verify a SCALE-encoded file proof against the fingerprint of its file.
+       **/
+      verifyFileProof: AugmentedRpc<
+        (
+          proof: Bytes | string | Uint8Array,
+          fingerprint: H256 | string | Uint8Array
+        ) => Observable<VerifiedFileProof>
+      >;
     };
     syncstate: {
       /**
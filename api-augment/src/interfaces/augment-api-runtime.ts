@@ -6,7 +6,7 @@
 import "@polkadot/api-base/types/calls";
 
 import type { ApiTypes, AugmentedCall, DecoratedCallBase } from "@polkadot/api-base/types";
-import type { Bytes, Null, Option, Result, Vec, bool, u128, u32 } from "@polkadot/types-codec";
+import type { Bytes, Null, Option, Result, Vec, bool, u128, u32, u64 } from "@polkadot/types-codec";
 import type { AnyNumber, IMethod, ITuple } from "@polkadot/types-codec/types";
 import type { CheckInherentsResult, InherentData } from "@polkadot/types/interfaces/blockbuilder";
 import type { BlockHash } from "@polkadot/types/interfaces/chain";
@@ -508,6 +508,10 @@ declare module "@polkadot/api-base/types/calls" {
        * Get the current tick.
        **/
       getCurrentTick: AugmentedCall<ApiType, () => Observable<BlockNumber>>;
+      /**
+       * Get the file size (in bytes) for which a single chunk of a file is challenged.
+       **/
+      getFileSizeToChallenges: AugmentedCall<ApiType, () => Observable<u64>>;
       /**
        * Get forest challenges from a seed.
        **/
@@ -1367,9 +1367,12 @@ import type {
   StorageDataUnit,
   StorageProviderId,
   TrieRemoveMutation,
+  UploadProgress,
+  UploadState,
   ValuePropId,
   ValueProposition,
-  ValuePropositionWithId
+  ValuePropositionWithId,
+  VerifiedFileProof
 } from "@storagehub/api-augment/interfaces/storagehubclient";
 
 declare module "@polkadot/types/types/registry" {
@@ -2554,6 +2557,8 @@ declare module "@polkadot/types/types/registry" {
     UnrewardedRelayersState: UnrewardedRelayersState;
     UpgradeGoAhead: UpgradeGoAhead;
     UpgradeRestriction: UpgradeRestriction;
+    UploadProgress: UploadProgress;
+    UploadState: UploadState;
     UpwardMessage: UpwardMessage;
     usize: usize;
     USize: USize;
@@ -2582,6 +2587,7 @@ declare module "@polkadot/types/types/registry" {
     ValueProposition: ValueProposition;
     ValuePropositionWithId: ValuePropositionWithId;
     VecInboundHrmpMessage: VecInboundHrmpMessage;
+    VerifiedFileProof: VerifiedFileProof;
     VersionedMultiAsset: VersionedMultiAsset;
     VersionedMultiAssets: VersionedMultiAssets;
     VersionedMultiLocation: VersionedMultiLocation;
@@ -1351,7 +1351,8 @@ export default {
         mspId: "H256",
         multiaddresses: "Vec<Bytes>",
         capacity: "u64",
-        valueProp: "PalletStorageProvidersValuePropositionWithId"
+        valueProp: "PalletStorageProvidersValuePropositionWithId",
+        paymentAccount: "AccountId32"
       },
       BspRequestSignUpSuccess: {
         who: "AccountId32",
@@ -1363,7 +1364,8 @@ export default {
         bspId: "H256",
         root: "H256",
         multiaddresses: "Vec<Bytes>",
-        capacity: "u64"
+        capacity: "u64",
+        paymentAccount: "AccountId32"
       },
       SignUpRequestCanceled: {
         who: "AccountId32"
@@ -1673,7 +1675,9 @@ export default {
       "ReceivedInvalidProof",
       "FileKeyAlreadyStored",
       "RequestExpired",
-      "InternalError"
+      "InternalError",
+      "ReachedBucketDataLimit",
+      "MaxConcurrentUploadsReached"
     ]
   },
   /**
@@ -118,6 +118,11 @@ const PROOFS_DEALER_V1: Record<string, DefinitionCall> = {
       }
     ],
     type: "Result<BlockNumber, GetNextDeadlineTickError>"
+  },
+  get_file_size_to_challenges: {
+    description: "Get the file size (in bytes) for which a single chunk of a file is challenged.",
+    params: [],
+    type: "u64"
   }
 };
 
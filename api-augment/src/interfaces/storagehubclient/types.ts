@@ -5,6 +5,7 @@ import type {
   Bytes,
   Enum,
   Null,
+  Option,
   Struct,
   U8aFixed,
   Vec,
@@ -326,6 +327,24 @@ export interface StorageProviderId extends Enum {
 /** @name TrieRemoveMutation */
 export interface TrieRemoveMutation extends Null {}
 
+/** @name UploadProgress */
+export interface UploadProgress extends Struct {
+  readonly expected_chunks: u64;
+  readonly received_chunks: u64;
+  readonly last_chunk_at: Option<u64>;
+  readonly state: UploadState;
+}
+
+/** @name UploadState */
+export interface UploadState extends Enum {
+  readonly isAwaitingVolunteer: boolean;
+  readonly isReceiving: boolean;
+  readonly isComplete: boolean;
+  readonly isConfirmed: boolean;
+  readonly isRejected: boolean;
+  readonly type: "AwaitingVolunteer" | "Receiving" | "Complete" | "Confirmed" | "Rejected";
+}
+
 /** @name ValuePropId */
 export interface ValuePropId extends H256 {}
 
@@ -341,4 +360,10 @@ export interface ValuePropositionWithId extends Struct {
   readonly value_prop: ValueProposition;
 }
 
+/** @name VerifiedFileProof */
+export interface VerifiedFileProof extends Struct {
+  readonly file_key: H256;
+  readonly chunk_ids: Vec<u64>;
+}
+
 export type PHANTOM_STORAGEHUBCLIENT = "storagehubclient";
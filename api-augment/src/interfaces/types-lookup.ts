@@ -1816,6 +1816,7 @@ declare module "@polkadot/types/lookup" {
       readonly multiaddresses: Vec<Bytes>;
       readonly capacity: u64;
       readonly valueProp: PalletStorageProvidersValuePropositionWithId;
+      readonly paymentAccount: AccountId32;
     } & Struct;
     readonly isBspRequestSignUpSuccess: boolean;
     readonly asBspRequestSignUpSuccess: {
@@ -1830,6 +1831,7 @@ declare module "@polkadot/types/lookup" {
       readonly root: H256;
       readonly multiaddresses: Vec<Bytes>;
       readonly capacity: u64;
+      readonly paymentAccount: AccountId32;
     } & Struct;
     readonly isSignUpRequestCanceled: boolean;
     readonly asSignUpRequestCanceled: {
@@ -2233,12 +2235,16 @@ declare module "@polkadot/types/lookup" {
     readonly isFileKeyAlreadyStored: boolean;
     readonly isRequestExpired: boolean;
     readonly isInternalError: boolean;
+    readonly isReachedBucketDataLimit: boolean;
+    readonly isMaxConcurrentUploadsReached: boolean;
     readonly type:
       | "ReachedMaximumCapacity"
       | "ReceivedInvalidProof"
       | "FileKeyAlreadyStored"
       | "RequestExpired"
-      | "InternalError";
+      | "InternalError"
+      | "ReachedBucketDataLimit"
+      | "MaxConcurrentUploadsReached";
   }
 
   /** @name PalletFileSystemEitherAccountIdOrMspId (139) */
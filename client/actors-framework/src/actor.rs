@@ -4,6 +4,7 @@ use futures::prelude::*;
 use crate::{
     constants::DEFAULT_ACTOR_COMMAND_QUEUE_WARNING_SIZE,
     event_bus::{EventBusMessage, ProvidesEventBus},
+    shutdown::GracefulShutdown,
 };
 
 /// The [`Actor`] trait represents an actor, which runs on its own event loop and can handle messages.
@@ -120,6 +121,9 @@ pub struct TaskSpawner {
     name: &'static str,
     group: Option<&'static str>,
     queue_size_warning: usize,
+    /// Shared by all the clones of this spawner, so that the event handlers spawned through any
+    /// of them are drained when shutting down.
+    graceful_shutdown: GracefulShutdown,
 }
 
 impl Debug for TaskSpawner {
@@ -139,6 +143,7 @@ impl TaskSpawner {
             name,
             group: None,
             queue_size_warning: DEFAULT_ACTOR_COMMAND_QUEUE_WARNING_SIZE,
+            graceful_shutdown: GracefulShutdown::new(),
         }
     }
 
@@ -163,6 +168,11 @@ impl TaskSpawner {
         }
     }
 
+    /// The [`GracefulShutdown`] of the event handlers spawned through this spawner.
+    pub fn graceful_shutdown(&self) -> &GracefulShutdown {
+        &self.graceful_shutdown
+    }
+
     pub fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
         self.spawner.spawn(self.name, self.group, task);
     }
@@ -102,9 +102,31 @@ impl<T: EventBusMessage, E: EventHandler<T> + Send + 'static> EventBusListener<T
     }
 
     async fn run(&mut self) {
+        let graceful_shutdown = self.spawner.graceful_shutdown().clone();
         loop {
-            match self.receiver.recv().await {
+            let received = tokio::select! {
+                // Stop waiting for events once shutting down, even if none arrives anymore.
+                _ = graceful_shutdown.shutdown_started() => {
+                    warn!(
+                        "Closing listener. Shutting down. (events type {})",
+                        std::any::type_name::<T>()
+                    );
+                    break;
+                }
+                received = self.receiver.recv() => received,
+            };
+
+            match received {
                 Ok(event) => {
+                    // Stop accepting new events once shutting down, keeping track of the ones
+                    // being handled so that the shutdown can wait for them.
+                    let Some(in_flight) = self.spawner.graceful_shutdown().track() else {
+                        warn!(
+                            "Closing listener. Shutting down. (events type {})",
+                            std::any::type_name::<T>()
+                        );
+                        break;
+                    };
                     let mut cloned_event_handler = self.event_handler.clone();
                     let permit = Arc::clone(&self.semaphore)
                         .acquire_owned()
@@ -118,6 +140,7 @@ impl<T: EventBusMessage, E: EventHandler<T> + Send + 'static> EventBusListener<T
                             }
                         };
                         drop(permit);
+                        drop(in_flight);
                     });
                 }
                 Err(broadcast::error::RecvError::Lagged(_)) if self.critical => {
@@ -1,3 +1,4 @@
 pub mod actor;
 pub mod constants;
 pub mod event_bus;
+pub mod shutdown;
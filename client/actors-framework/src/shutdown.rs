@@ -0,0 +1,178 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::watch;
+
+/// Coordinates the graceful shutdown of the event handlers spawned through a
+/// [`TaskSpawner`](crate::actor::TaskSpawner).
+///
+/// Every event handled by an [`EventBusListener`](crate::event_bus::EventBusListener) is tracked
+/// while in flight. Once [`GracefulShutdown::shutdown`] is called, listeners stop accepting new
+/// events (see [`GracefulShutdown::shutdown_started`]), and the shutdown resolves when all the events in flight have been handled (or the
+/// timeout is reached), so that handlers are not dropped halfway through their work.
+#[derive(Debug, Clone)]
+pub struct GracefulShutdown {
+    state: Arc<ShutdownState>,
+}
+
+#[derive(Debug)]
+struct ShutdownState {
+    /// Whether the shutdown started, in which case no new work is accepted.
+    shutting_down: watch::Sender<bool>,
+    /// Number of tracked tasks in flight.
+    in_flight: watch::Sender<usize>,
+}
+
+impl Default for GracefulShutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GracefulShutdown {
+    pub fn new() -> Self {
+        let (shutting_down, _) = watch::channel(false);
+        let (in_flight, _) = watch::channel(0);
+        Self {
+            state: Arc::new(ShutdownState {
+                shutting_down,
+                in_flight,
+            }),
+        }
+    }
+
+    /// Whether the shutdown started.
+    pub fn is_shutting_down(&self) -> bool {
+        *self.state.shutting_down.borrow()
+    }
+
+    /// Resolves once the shutdown started, so that long-running loops can stop waiting for work.
+    pub async fn shutdown_started(&self) {
+        let mut shutting_down = self.state.shutting_down.subscribe();
+        // The sender is owned by `self`, so it can't be dropped while waiting.
+        let _ = shutting_down.wait_for(|shutting_down| *shutting_down).await;
+    }
+
+    /// Number of tracked tasks in flight.
+    pub fn in_flight(&self) -> usize {
+        *self.state.in_flight.borrow()
+    }
+
+    /// Tracks a new task in flight until the returned [`InFlightGuard`] is dropped.
+    ///
+    /// Returns `None` if the shutdown started, in which case the task should not be started.
+    pub fn track(&self) -> Option<InFlightGuard> {
+        // Count the task before checking for the shutdown, so that a shutdown starting
+        // concurrently either sees this task in flight or makes this check fail.
+        self.state
+            .in_flight
+            .send_modify(|in_flight| *in_flight += 1);
+        let guard = InFlightGuard {
+            state: self.state.clone(),
+        };
+
+        if self.is_shutting_down() {
+            return None;
+        }
+
+        Some(guard)
+    }
+
+    /// Stops accepting new work and waits for the tasks in flight to finish, for at most `timeout`.
+    ///
+    /// Returns whether all the tasks in flight finished before the timeout.
+    pub async fn shutdown(&self, timeout: Duration) -> bool {
+        self.state.shutting_down.send_replace(true);
+
+        let mut in_flight = self.state.in_flight.subscribe();
+        tokio::time::timeout(timeout, in_flight.wait_for(|in_flight| *in_flight == 0))
+            .await
+            .is_ok()
+    }
+}
+
+/// Keeps a task tracked by a [`GracefulShutdown`] in flight until dropped.
+#[derive(Debug)]
+pub struct InFlightGuard {
+    state: Arc<ShutdownState>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.state
+            .in_flight
+            .send_modify(|in_flight| *in_flight -= 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("Failed to build runtime")
+            .block_on(future)
+    }
+
+    #[test]
+    fn shutdown_waits_for_tasks_in_flight() {
+        block_on(async {
+            let shutdown = GracefulShutdown::new();
+            let guard = shutdown.track().expect("Not shutting down yet");
+
+            let handler = tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                drop(guard);
+            });
+
+            assert!(shutdown.shutdown(Duration::from_secs(5)).await);
+            assert_eq!(shutdown.in_flight(), 0);
+            assert!(handler.is_finished());
+        });
+    }
+
+    #[test]
+    fn shutdown_gives_up_after_timeout() {
+        block_on(async {
+            let shutdown = GracefulShutdown::new();
+            let _guard = shutdown.track().expect("Not shutting down yet");
+
+            assert!(!shutdown.shutdown(Duration::from_millis(50)).await);
+            assert_eq!(shutdown.in_flight(), 1);
+        });
+    }
+
+    #[test]
+    fn shutdown_start_is_notified() {
+        block_on(async {
+            let shutdown = GracefulShutdown::new();
+            let notified = tokio::spawn({
+                let shutdown = shutdown.clone();
+                async move { shutdown.shutdown_started().await }
+            });
+
+            assert!(shutdown.shutdown(Duration::from_secs(5)).await);
+            tokio::time::timeout(Duration::from_secs(5), notified)
+                .await
+                .expect("Shutdown start should be notified")
+                .unwrap();
+
+            // Waiting once the shutdown started resolves right away.
+            shutdown.shutdown_started().await;
+        });
+    }
+
+    #[test]
+    fn no_new_tasks_are_tracked_once_shutting_down() {
+        block_on(async {
+            let shutdown = GracefulShutdown::new();
+            assert!(shutdown.shutdown(Duration::from_secs(5)).await);
+
+            assert!(shutdown.is_shutting_down());
+            assert!(shutdown.track().is_none());
+            assert_eq!(shutdown.in_flight(), 0);
+        });
+    }
+}
@@ -32,6 +32,10 @@ pub struct CapacityRequestQueue {
     total_required: StorageData,
     /// The last submitted transaction which `requests_waiting_for_inclusion` is waiting for.
     last_submitted_transaction: Option<SubmittedTransaction>,
+    /// The block number at which the last capacity change was submitted.
+    ///
+    /// Used to enforce the [`CapacityConfig::min_capacity_change_interval`] between capacity changes.
+    last_capacity_change_block: Option<BlockNumber>,
 }
 
 impl CapacityRequestQueue {
@@ -42,6 +46,7 @@ impl CapacityRequestQueue {
             requests_waiting_for_inclusion: Vec::new(),
             total_required: 0,
             last_submitted_transaction: None,
+            last_capacity_change_block: None,
         }
     }
 
@@ -106,6 +111,26 @@ impl CapacityRequestQueue {
         new_capacity.min(self.capacity_config.max_capacity)
     }
 
+    /// Check if a new capacity change can be submitted at `block_number`.
+    ///
+    /// A capacity change is rate limited locally if less than `min_capacity_change_interval` blocks
+    /// have passed since the last one was submitted. Pending requests keep being queued in the meantime,
+    /// so they are coalesced into a single capacity change once the interval has elapsed.
+    pub fn can_change_capacity(&self, block_number: BlockNumber) -> bool {
+        match self.last_capacity_change_block {
+            Some(last_block) => {
+                block_number.saturating_sub(last_block)
+                    >= self.capacity_config.min_capacity_change_interval
+            }
+            None => true,
+        }
+    }
+
+    /// Register that a capacity change was submitted at `block_number`.
+    pub fn register_capacity_change(&mut self, block_number: BlockNumber) {
+        self.last_capacity_change_block = Some(block_number);
+    }
+
     /// Check if there are any pending requests
     pub fn has_pending_requests(&self) -> bool {
         !self.pending_requests.is_empty()
@@ -169,13 +194,23 @@ pub struct CapacityConfig {
     /// node needs 100 units of storage more to store a file, the node will automatically increase
     /// its on-chain capacity by 1k units.
     jump_capacity: StorageData,
+    /// Minimum number of blocks between two consecutive capacity changes submitted by this node.
+    ///
+    /// This is enforced locally, on top of the earliest block to change capacity set by the runtime,
+    /// to avoid spamming `change_capacity` extrinsics in case of a burst of capacity requests.
+    min_capacity_change_interval: BlockNumber,
 }
 
 impl CapacityConfig {
-    pub fn new(max_capacity: StorageData, jump_capacity: StorageData) -> Self {
+    pub fn new(
+        max_capacity: StorageData,
+        jump_capacity: StorageData,
+        min_capacity_change_interval: BlockNumber,
+    ) -> Self {
         Self {
             max_capacity,
             jump_capacity,
+            min_capacity_change_interval,
         }
     }
 
@@ -293,6 +328,12 @@ where
             return Ok(());
         }
 
+        if !capacity_manager_ref.can_change_capacity(block_number) {
+            debug!(target: LOG_TARGET, "[process_capacity_requests] Capacity change rate limited, waiting for minimum interval to pass");
+            // Pending requests stay queued and will be coalesced into the next capacity change.
+            return Ok(());
+        }
+
         let required_capacity = capacity_manager_ref.total_required;
 
         // Calculate new capacity based on configuration
@@ -312,6 +353,7 @@ where
                     capacity_manager.add_pending_requests_to_waiting_for_inclusion(
                         SubmittedTransaction::new(output.receiver, output.hash, output.nonce),
                     );
+                    capacity_manager.register_capacity_change(block_number);
                 } else {
                     error!(target: LOG_TARGET, "Capacity manager not initialized");
                 }
@@ -381,3 +423,60 @@ where
         Ok((current_block_hash, current_capacity, provider_id))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue_request(queue: &mut CapacityRequestQueue, required: StorageData) {
+        let (callback, _rx) = tokio::sync::oneshot::channel();
+        queue.queue_capacity_request(
+            CapacityRequest::new(CapacityRequestData::new(required), callback),
+            0,
+        );
+    }
+
+    #[test]
+    fn capacity_changes_are_rate_limited() {
+        let min_capacity_change_interval = 10;
+        let mut queue = CapacityRequestQueue::new(CapacityConfig::new(
+            u64::MAX,
+            1024,
+            min_capacity_change_interval,
+        ));
+
+        let mut capacity_changes = Vec::new();
+        for block_number in 0..min_capacity_change_interval {
+            // Fire many requests in every block.
+            for _ in 0..5 {
+                queue_request(&mut queue, 100);
+            }
+
+            if queue.has_pending_requests() && queue.can_change_capacity(block_number) {
+                capacity_changes.push((block_number, queue.total_required));
+                queue.register_capacity_change(block_number);
+                queue.reset_queue();
+            }
+        }
+
+        // Only one capacity change should have been made within the interval.
+        assert_eq!(capacity_changes, vec![(0, 500)]);
+
+        // All requests made while rate limited are coalesced into the next capacity change.
+        assert!(queue.can_change_capacity(min_capacity_change_interval));
+        assert_eq!(
+            queue.total_required,
+            500 * (min_capacity_change_interval as StorageData - 1)
+        );
+    }
+
+    #[test]
+    fn no_rate_limit_with_zero_interval() {
+        let mut queue = CapacityRequestQueue::new(CapacityConfig::new(u64::MAX, 1024, 0));
+
+        queue.register_capacity_change(5);
+
+        assert!(queue.can_change_capacity(5));
+        assert!(queue.can_change_capacity(6));
+    }
+}
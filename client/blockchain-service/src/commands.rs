@@ -1,7 +1,10 @@
+use std::time::Instant;
+
 use anyhow::Result;
 use async_trait::async_trait;
 use log::{debug, warn};
 use sc_network::Multiaddr;
+use sc_tracing::tracing::Span;
 use serde_json::Number;
 use shc_forest_manager::traits::ForestStorageHandler;
 use sp_api::ApiError;
@@ -9,22 +12,25 @@ use sp_core::H256;
 
 use pallet_file_system_runtime_api::{
     IsStorageRequestOpenToVolunteersError, QueryBspConfirmChunksToProveForFileError,
-    QueryFileEarliestVolunteerTickError, QueryMspConfirmChunksToProveForFileError,
+    QueryConfirmChunksToProveForFilesError, QueryFileEarliestVolunteerTickError,
+    QueryMspConfirmChunksToProveForFileError,
 };
 use pallet_payment_streams_runtime_api::GetUsersWithDebtOverThresholdError;
 use pallet_proofs_dealer_runtime_api::{
     GetChallengePeriodError, GetCheckpointChallengesError, GetProofSubmissionRecordError,
 };
 use pallet_storage_providers_runtime_api::{
-    GetBspInfoError, QueryAvailableStorageCapacityError, QueryBucketsOfUserStoredByMspError,
+    BucketReadAccess, GetBspInfoError, QueryAvailableStorageCapacityError,
+    QueryBucketReadAccessError, QueryBucketRootError, QueryBucketSizeAndDataLimitError,
+    QueryBucketsForMspError, QueryBucketsOfUserStoredByMspError,
     QueryEarliestChangeCapacityBlockError, QueryMspIdOfBucketIdError,
     QueryProviderMultiaddressesError, QueryStorageProviderCapacityError,
 };
 use shc_actors_framework::actor::ActorHandle;
 use shc_common::types::{
-    BlockNumber, BucketId, ChunkId, CustomChallenge, ForestLeaf, MainStorageProviderId,
-    ProofsDealerProviderId, ProviderId, RandomnessOutput, StorageHubEventsVec, StorageProviderId,
-    TickNumber,
+    Block, BlockNumber, BucketId, ChunkId, CustomChallenge, ForestLeaf, HasherOutT,
+    MainStorageProviderId, ProofsDealerProviderId, ProviderId, RandomnessOutput,
+    StorageHubEventsVec, StorageProofsMerkleTrieLayout, StorageProviderId, Tick, TickNumber,
 };
 use storage_hub_runtime::{AccountId, Balance, StorageDataUnit};
 
@@ -34,7 +40,7 @@ use crate::{
     transaction::SubmittedTransaction,
     types::{
         ConfirmStoringRequest, Extrinsic, ExtrinsicResult, FileDeletionRequest, MinimalBlockInfo,
-        RespondStorageRequest, RetryStrategy, SendExtrinsicOptions,
+        PendingRequestsQueueDepths, RespondStorageRequest, RetryStrategy, SendExtrinsicOptions,
         StopStoringForInsolventUserRequest, SubmitProofRequest, WatchTransactionError,
     },
 };
@@ -46,6 +52,8 @@ pub enum BlockchainServiceCommand {
     SendExtrinsic {
         call: storage_hub_runtime::RuntimeCall,
         options: SendExtrinsicOptions,
+        /// The span of the caller, in which the extrinsic is sent.
+        span: Span,
         callback: tokio::sync::oneshot::Sender<Result<SubmittedTransaction>>,
     },
     GetExtrinsicFromBlock {
@@ -95,6 +103,16 @@ pub enum BlockchainServiceCommand {
             Result<Vec<ChunkId>, QueryBspConfirmChunksToProveForFileError>,
         >,
     },
+    QueryConfirmChunksToProveForFiles {
+        provider_id: StorageProviderId,
+        file_keys: Vec<H256>,
+        callback: tokio::sync::oneshot::Sender<
+            Vec<(
+                H256,
+                Result<Vec<ChunkId>, QueryConfirmChunksToProveForFilesError>,
+            )>,
+        >,
+    },
     QueryMspConfirmChunksToProveForFile {
         msp_id: ProofsDealerProviderId,
         file_key: H256,
@@ -115,6 +133,10 @@ pub enum BlockchainServiceCommand {
         request: ConfirmStoringRequest,
         callback: tokio::sync::oneshot::Sender<Result<()>>,
     },
+    QueueConfirmBspRequestBatch {
+        requests: Vec<ConfirmStoringRequest>,
+        callback: tokio::sync::oneshot::Sender<Result<()>>,
+    },
     QueueMspRespondStorageRequest {
         request: RespondStorageRequest,
         callback: tokio::sync::oneshot::Sender<Result<()>>,
@@ -123,6 +145,31 @@ pub enum BlockchainServiceCommand {
         request: StopStoringForInsolventUserRequest,
         callback: tokio::sync::oneshot::Sender<Result<()>>,
     },
+    QueryPendingRequestsQueueDepths {
+        callback: tokio::sync::oneshot::Sender<PendingRequestsQueueDepths>,
+    },
+    FlushPersistentState {
+        callback: tokio::sync::oneshot::Sender<Result<()>>,
+    },
+    RecordPendingCheckpointRemovals {
+        file_keys: Vec<H256>,
+        tick: BlockNumber,
+        callback: tokio::sync::oneshot::Sender<Result<()>>,
+    },
+    ClearPendingCheckpointRemovals {
+        file_keys: Vec<H256>,
+        callback: tokio::sync::oneshot::Sender<Result<()>>,
+    },
+    QueryPendingCheckpointRemovals {
+        callback: tokio::sync::oneshot::Sender<Vec<(H256, BlockNumber)>>,
+    },
+    SetStorageVerificationCheckpoint {
+        checkpoint: Option<H256>,
+        callback: tokio::sync::oneshot::Sender<Result<()>>,
+    },
+    QueryStorageVerificationCheckpoint {
+        callback: tokio::sync::oneshot::Sender<Option<H256>>,
+    },
     QueryChallengesFromSeed {
         seed: RandomnessOutput,
         provider_id: ProofsDealerProviderId,
@@ -149,6 +196,9 @@ pub enum BlockchainServiceCommand {
     QueryLastCheckpointChallengeTick {
         callback: tokio::sync::oneshot::Sender<Result<BlockNumber, ApiError>>,
     },
+    QueryFileSizeToChallenges {
+        callback: tokio::sync::oneshot::Sender<Result<u64, ApiError>>,
+    },
     QueryLastCheckpointChallenges {
         tick: BlockNumber,
         callback: tokio::sync::oneshot::Sender<
@@ -159,6 +209,16 @@ pub enum BlockchainServiceCommand {
         provider_id: ProviderId,
         callback: tokio::sync::oneshot::Sender<Result<H256, GetBspInfoError>>,
     },
+    QueryForestRootAtBlock {
+        provider_id: ProviderId,
+        block_hash: H256,
+        callback: tokio::sync::oneshot::Sender<Result<HasherOutT<StorageProofsMerkleTrieLayout>>>,
+    },
+    QueryIsBspVolunteer {
+        bsp_id: ProviderId,
+        file_key: H256,
+        callback: tokio::sync::oneshot::Sender<Result<bool>>,
+    },
     QueryStorageProviderCapacity {
         provider_id: ProviderId,
         callback: tokio::sync::oneshot::Sender<
@@ -195,6 +255,27 @@ pub enum BlockchainServiceCommand {
             Result<Option<MainStorageProviderId>, QueryMspIdOfBucketIdError>,
         >,
     },
+    QueryBucketSizeAndDataLimit {
+        bucket_id: BucketId,
+        callback: tokio::sync::oneshot::Sender<
+            Result<(StorageDataUnit, StorageDataUnit), QueryBucketSizeAndDataLimitError>,
+        >,
+    },
+    QueryBucketRoot {
+        bucket_id: BucketId,
+        callback: tokio::sync::oneshot::Sender<Result<H256, QueryBucketRootError>>,
+    },
+    QueryBucketsForMsp {
+        msp_id: ProviderId,
+        callback: tokio::sync::oneshot::Sender<Result<Vec<BucketId>, QueryBucketsForMspError>>,
+    },
+    QueryBucketReadAccess {
+        bucket_id: BucketId,
+        user: AccountId,
+        callback: tokio::sync::oneshot::Sender<
+            Result<BucketReadAccess<AccountId>, QueryBucketReadAccessError>,
+        >,
+    },
     ReleaseForestRootWriteLock {
         forest_root_write_tx: tokio::sync::oneshot::Sender<()>,
         callback: tokio::sync::oneshot::Sender<Result<()>>,
@@ -203,6 +284,10 @@ pub enum BlockchainServiceCommand {
         request: FileDeletionRequest,
         callback: tokio::sync::oneshot::Sender<Result<()>>,
     },
+    RepairFile {
+        file_key: H256,
+        callback: tokio::sync::oneshot::Sender<Result<()>>,
+    },
     IncreaseCapacity {
         request: CapacityRequestData,
         callback:
@@ -236,11 +321,14 @@ pub trait BlockchainServiceInterface {
     /// Unwatch an extrinsic.
     async fn unwatch_extrinsic(&self, subscription_id: Number) -> Result<()>;
 
+    /// Get the best block information (number and hash) known by the BlockchainService.
+    async fn get_best_block_info(&self) -> MinimalBlockInfo;
+
     /// Wait for a block number.
-    async fn wait_for_block(&self, block_number: BlockNumber) -> Result<()>;
+    async fn wait_for_block(&self, block: Block) -> Result<()>;
 
     /// Wait for a tick number.
-    async fn wait_for_tick(&self, tick_number: TickNumber) -> Result<(), ApiError>;
+    async fn wait_for_tick(&self, tick: Tick) -> Result<(), ApiError>;
 
     /// Determine if a storage request is still open to volunteers.
     async fn is_storage_request_open_to_volunteers(
@@ -253,12 +341,13 @@ pub trait BlockchainServiceInterface {
         &self,
         bsp_id: ProofsDealerProviderId,
         file_key: H256,
-    ) -> Result<BlockNumber, QueryFileEarliestVolunteerTickError>;
+    ) -> Result<Tick, QueryFileEarliestVolunteerTickError>;
 
+    /// Query the earliest block number at which a BSP can change its capacity.
     async fn query_earliest_change_capacity_block(
         &self,
         bsp_id: ProviderId,
-    ) -> Result<BlockNumber, QueryEarliestChangeCapacityBlockError>;
+    ) -> Result<Block, QueryEarliestChangeCapacityBlockError>;
 
     /// Get the node's public key.
     async fn get_node_public_key(&self) -> sp_core::sr25519::Public;
@@ -277,6 +366,20 @@ pub trait BlockchainServiceInterface {
         file_key: H256,
     ) -> Result<Vec<ChunkId>, QueryMspConfirmChunksToProveForFileError>;
 
+    /// Query the chunks that a Provider needs to prove to confirm storing each of the files, all
+    /// at once.
+    ///
+    /// Returns the chunks to prove (or the error querying them) of every file in `file_keys`, in
+    /// the same order.
+    async fn query_confirm_chunks_to_prove_for_files(
+        &self,
+        provider_id: StorageProviderId,
+        file_keys: &[H256],
+    ) -> Vec<(
+        H256,
+        Result<Vec<ChunkId>, QueryConfirmChunksToProveForFilesError>,
+    )>;
+
     /// Query the a Provider's multiaddresses.
     async fn query_provider_multiaddresses(
         &self,
@@ -289,6 +392,12 @@ pub trait BlockchainServiceInterface {
     /// Queue a ConfirmBspRequest to be processed.
     async fn queue_confirm_bsp_request(&self, request: ConfirmStoringRequest) -> Result<()>;
 
+    /// Queue several ConfirmBspRequests at once, to be processed together.
+    async fn queue_confirm_bsp_request_batch(
+        &self,
+        requests: Vec<ConfirmStoringRequest>,
+    ) -> Result<()>;
+
     // Queue a BspStopStoringForInsolventUserRequest to be processed.
     async fn queue_stop_storing_for_insolvent_user_request(
         &self,
@@ -302,6 +411,40 @@ pub trait BlockchainServiceInterface {
     /// Queue a FileDeletionRequest to be processed.
     async fn queue_file_deletion_request(&self, request: FileDeletionRequest) -> Result<()>;
 
+    /// Request the repair of the file with `file_key` stored by this node, emitting a
+    /// [`RepairFileRequested`](crate::events::RepairFileRequested) event for the tasks to handle.
+    async fn repair_file(&self, file_key: H256) -> Result<()>;
+
+    /// Query the number of requests waiting in each of the queues of the Blockchain Service.
+    async fn query_pending_requests_queue_depths(&self) -> PendingRequestsQueueDepths;
+
+    /// Flush the persisted state of the BlockchainService (i.e. the pending requests queues) to
+    /// disk, to be called when shutting down.
+    async fn flush_persistent_state(&self) -> Result<()>;
+
+    /// Persist that the BSP is removing `file_keys` from its Forest and File Storage, because
+    /// they were challenged with a `TrieRemoveMutation` in a checkpoint challenge answered by the
+    /// proof for `tick`.
+    async fn record_pending_checkpoint_removals(
+        &self,
+        file_keys: Vec<H256>,
+        tick: BlockNumber,
+    ) -> Result<()>;
+
+    /// Forget the pending checkpoint removals of `file_keys`, once they are fully removed.
+    async fn clear_pending_checkpoint_removals(&self, file_keys: Vec<H256>) -> Result<()>;
+
+    /// Query the pending checkpoint removals, with the tick of the proof that answered them.
+    async fn query_pending_checkpoint_removals(&self) -> Vec<(H256, BlockNumber)>;
+
+    /// Persist the key of the last file verified by the ongoing verification of the whole File
+    /// Storage, or forget it if `None`, once the verification finishes.
+    async fn set_storage_verification_checkpoint(&self, checkpoint: Option<H256>) -> Result<()>;
+
+    /// Query the key of the last file verified by an interrupted verification of the whole File
+    /// Storage, if any.
+    async fn query_storage_verification_checkpoint(&self) -> Option<H256>;
+
     /// Query the challenges that a Provider needs to submit for a given seed.
     async fn query_challenges_from_seed(
         &self,
@@ -341,6 +484,11 @@ pub trait BlockchainServiceInterface {
     /// Query the last checkpoint tick.
     async fn query_last_checkpoint_challenge_tick(&self) -> Result<BlockNumber, ApiError>;
 
+    /// Query the file size (in bytes) for which a single chunk of a file is challenged.
+    ///
+    /// Files get one challenge for every this many bytes, up to a maximum number of challenges.
+    async fn query_file_size_to_challenges(&self) -> Result<u64, ApiError>;
+
     /// Query the checkpoint challenges for a given tick.
     async fn query_last_checkpoint_challenges(
         &self,
@@ -353,6 +501,20 @@ pub trait BlockchainServiceInterface {
         provider_id: ProviderId,
     ) -> Result<H256, GetBspInfoError>;
 
+    /// Query the Merkle Patricia Forest root of a BSP as of the block with `block_hash`.
+    ///
+    /// The root is read from the state of that block, which does not need to be in the
+    /// canonical chain, so it can be used to check the Forest against a fork point.
+    async fn query_forest_root_at_block(
+        &self,
+        provider_id: ProviderId,
+        block_hash: H256,
+    ) -> Result<HasherOutT<StorageProofsMerkleTrieLayout>>;
+
+    /// Query whether the BSP with `bsp_id` is a volunteer for the storage request of `file_key`,
+    /// as of the current best block.
+    async fn query_is_bsp_volunteer(&self, bsp_id: ProviderId, file_key: H256) -> Result<bool>;
+
     /// Query the storage capacity for a Provider.
     async fn query_storage_provider_capacity(
         &self,
@@ -411,6 +573,28 @@ pub trait BlockchainServiceInterface {
         bucket_id: BucketId,
     ) -> Result<Option<MainStorageProviderId>, QueryMspIdOfBucketIdError>;
 
+    /// Helper function to get the current size of a bucket and the data limit of its value proposition.
+    async fn query_bucket_size_and_data_limit(
+        &self,
+        bucket_id: BucketId,
+    ) -> Result<(StorageDataUnit, StorageDataUnit), QueryBucketSizeAndDataLimitError>;
+
+    /// Query the Merkle Patricia Forest root of a bucket, as currently stored on-chain.
+    async fn query_bucket_root(&self, bucket_id: BucketId) -> Result<H256, QueryBucketRootError>;
+
+    /// Query the buckets stored by an MSP.
+    async fn query_buckets_for_msp(
+        &self,
+        msp_id: ProviderId,
+    ) -> Result<Vec<BucketId>, QueryBucketsForMspError>;
+
+    /// Helper function to get what determines whether `user` can read the files of a bucket.
+    async fn query_bucket_read_access(
+        &self,
+        bucket_id: BucketId,
+        user: AccountId,
+    ) -> Result<BucketReadAccess<AccountId>, QueryBucketReadAccessError>;
+
     /// Helper function to release the Forest root write lock.
     async fn release_forest_root_write_lock(
         &self,
@@ -441,6 +625,7 @@ where
         let message = BlockchainServiceCommand::SendExtrinsic {
             call: call.into(),
             options,
+            span: Span::current(),
             callback,
         };
         self.send(message).await;
@@ -474,11 +659,19 @@ where
         rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
     }
 
-    async fn wait_for_block(&self, block_number: BlockNumber) -> Result<()> {
+    async fn get_best_block_info(&self) -> MinimalBlockInfo {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        // Build command to send to blockchain service.
+        let message = BlockchainServiceCommand::GetBestBlockInfo { callback };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+    }
+
+    async fn wait_for_block(&self, block: Block) -> Result<()> {
         let (callback, rx) = tokio::sync::oneshot::channel();
         // Build command to send to blockchain service.
         let message = BlockchainServiceCommand::WaitForBlock {
-            block_number,
+            block_number: block.0,
             callback,
         };
         self.send(message).await;
@@ -487,11 +680,11 @@ where
         Ok(())
     }
 
-    async fn wait_for_tick(&self, tick_number: TickNumber) -> Result<(), ApiError> {
+    async fn wait_for_tick(&self, tick: Tick) -> Result<(), ApiError> {
         let (callback, rx) = tokio::sync::oneshot::channel();
         // Build command to send to blockchain service.
         let message = BlockchainServiceCommand::WaitForTick {
-            tick_number,
+            tick_number: tick.0,
             callback,
         };
         self.send(message).await;
@@ -515,7 +708,7 @@ where
         &self,
         bsp_id: ProviderId,
         file_key: H256,
-    ) -> Result<BlockNumber, QueryFileEarliestVolunteerTickError> {
+    ) -> Result<Tick, QueryFileEarliestVolunteerTickError> {
         let (callback, rx) = tokio::sync::oneshot::channel();
         // Build command to send to blockchain service.
         let message = BlockchainServiceCommand::QueryFileEarliestVolunteerTick {
@@ -524,18 +717,20 @@ where
             callback,
         };
         self.send(message).await;
-        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+        let earliest_volunteer_tick = rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")?;
+        Ok(Tick(earliest_volunteer_tick))
     }
 
     async fn query_earliest_change_capacity_block(
         &self,
         bsp_id: ProviderId,
-    ) -> Result<BlockNumber, QueryEarliestChangeCapacityBlockError> {
+    ) -> Result<Block, QueryEarliestChangeCapacityBlockError> {
         let (callback, rx) = tokio::sync::oneshot::channel();
         let message =
             BlockchainServiceCommand::QueryEarliestChangeCapacityBlock { bsp_id, callback };
         self.send(message).await;
-        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+        let earliest_block = rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")?;
+        Ok(Block(earliest_block))
     }
 
     /// Get the node's public key.
@@ -579,6 +774,25 @@ where
         rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
     }
 
+    async fn query_confirm_chunks_to_prove_for_files(
+        &self,
+        provider_id: StorageProviderId,
+        file_keys: &[H256],
+    ) -> Vec<(
+        H256,
+        Result<Vec<ChunkId>, QueryConfirmChunksToProveForFilesError>,
+    )> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        // Build command to send to blockchain service.
+        let message = BlockchainServiceCommand::QueryConfirmChunksToProveForFiles {
+            provider_id,
+            file_keys: file_keys.to_vec(),
+            callback,
+        };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+    }
+
     async fn query_provider_multiaddresses(
         &self,
         provider_id: ProviderId,
@@ -606,6 +820,16 @@ where
         rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
     }
 
+    async fn queue_confirm_bsp_request_batch(
+        &self,
+        requests: Vec<ConfirmStoringRequest>,
+    ) -> Result<()> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let message = BlockchainServiceCommand::QueueConfirmBspRequestBatch { requests, callback };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+    }
+
     async fn queue_msp_respond_storage_request(
         &self,
         request: RespondStorageRequest,
@@ -623,6 +847,13 @@ where
         rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
     }
 
+    async fn repair_file(&self, file_key: H256) -> Result<()> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let message = BlockchainServiceCommand::RepairFile { file_key, callback };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+    }
+
     async fn queue_stop_storing_for_insolvent_user_request(
         &self,
         request: StopStoringForInsolventUserRequest,
@@ -634,6 +865,69 @@ where
         rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
     }
 
+    async fn query_pending_requests_queue_depths(&self) -> PendingRequestsQueueDepths {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let message = BlockchainServiceCommand::QueryPendingRequestsQueueDepths { callback };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+    }
+
+    async fn flush_persistent_state(&self) -> Result<()> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let message = BlockchainServiceCommand::FlushPersistentState { callback };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+    }
+
+    async fn record_pending_checkpoint_removals(
+        &self,
+        file_keys: Vec<H256>,
+        tick: BlockNumber,
+    ) -> Result<()> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let message = BlockchainServiceCommand::RecordPendingCheckpointRemovals {
+            file_keys,
+            tick,
+            callback,
+        };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+    }
+
+    async fn clear_pending_checkpoint_removals(&self, file_keys: Vec<H256>) -> Result<()> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let message = BlockchainServiceCommand::ClearPendingCheckpointRemovals {
+            file_keys,
+            callback,
+        };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+    }
+
+    async fn query_pending_checkpoint_removals(&self) -> Vec<(H256, BlockNumber)> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let message = BlockchainServiceCommand::QueryPendingCheckpointRemovals { callback };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+    }
+
+    async fn set_storage_verification_checkpoint(&self, checkpoint: Option<H256>) -> Result<()> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let message = BlockchainServiceCommand::SetStorageVerificationCheckpoint {
+            checkpoint,
+            callback,
+        };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+    }
+
+    async fn query_storage_verification_checkpoint(&self) -> Option<H256> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let message = BlockchainServiceCommand::QueryStorageVerificationCheckpoint { callback };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+    }
+
     async fn query_challenges_from_seed(
         &self,
         seed: RandomnessOutput,
@@ -713,6 +1007,13 @@ where
         rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
     }
 
+    async fn query_file_size_to_challenges(&self) -> Result<u64, ApiError> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let message = BlockchainServiceCommand::QueryFileSizeToChallenges { callback };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+    }
+
     async fn query_last_checkpoint_challenges(
         &self,
         tick: BlockNumber,
@@ -736,6 +1037,32 @@ where
         rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
     }
 
+    async fn query_forest_root_at_block(
+        &self,
+        provider_id: ProviderId,
+        block_hash: H256,
+    ) -> Result<HasherOutT<StorageProofsMerkleTrieLayout>> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let message = BlockchainServiceCommand::QueryForestRootAtBlock {
+            provider_id,
+            block_hash,
+            callback,
+        };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+    }
+
+    async fn query_is_bsp_volunteer(&self, bsp_id: ProviderId, file_key: H256) -> Result<bool> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let message = BlockchainServiceCommand::QueryIsBspVolunteer {
+            bsp_id,
+            file_key,
+            callback,
+        };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+    }
+
     async fn query_storage_provider_id(
         &self,
         maybe_node_pub_key: Option<sp_core::sr25519::Public>,
@@ -864,6 +1191,7 @@ where
             let extrinsic_options = SendExtrinsicOptions::new()
                 .with_tip(tip as u128)
                 .with_nonce(nonce);
+            let submitted_at = Instant::now();
 
             let mut transaction = self
                 .send_extrinsic(call.clone(), extrinsic_options)
@@ -881,7 +1209,7 @@ where
 
             match result {
                 Ok(maybe_events) => {
-                    debug!(target: LOG_TARGET, "Transaction with hash {:?} succeeded", transaction.hash());
+                    debug!(target: LOG_TARGET, "Transaction with hash {:?} succeeded after {:?}", transaction.hash(), submitted_at.elapsed());
                     return Ok(maybe_events);
                 }
                 Err(err) => {
@@ -927,6 +1255,54 @@ where
         rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
     }
 
+    async fn query_bucket_size_and_data_limit(
+        &self,
+        bucket_id: BucketId,
+    ) -> Result<(StorageDataUnit, StorageDataUnit), QueryBucketSizeAndDataLimitError> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let message = BlockchainServiceCommand::QueryBucketSizeAndDataLimit {
+            bucket_id,
+            callback,
+        };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+    }
+
+    async fn query_bucket_root(&self, bucket_id: BucketId) -> Result<H256, QueryBucketRootError> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let message = BlockchainServiceCommand::QueryBucketRoot {
+            bucket_id,
+            callback,
+        };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+    }
+
+    async fn query_buckets_for_msp(
+        &self,
+        msp_id: ProviderId,
+    ) -> Result<Vec<BucketId>, QueryBucketsForMspError> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let message = BlockchainServiceCommand::QueryBucketsForMsp { msp_id, callback };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+    }
+
+    async fn query_bucket_read_access(
+        &self,
+        bucket_id: BucketId,
+        user: AccountId,
+    ) -> Result<BucketReadAccess<AccountId>, QueryBucketReadAccessError> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let message = BlockchainServiceCommand::QueryBucketReadAccess {
+            bucket_id,
+            user,
+            callback,
+        };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from BlockchainService. Probably means BlockchainService has crashed.")
+    }
+
     async fn release_forest_root_write_lock(
         &self,
         forest_root_write_tx: tokio::sync::oneshot::Sender<()>,
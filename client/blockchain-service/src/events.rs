@@ -12,7 +12,8 @@ use std::sync::Arc;
 use tokio::sync::{oneshot, Mutex};
 
 use crate::types::{
-    ConfirmStoringRequest, FileDeletionRequest as FileDeletionRequestType, RespondStorageRequest,
+    ConfirmStoringRequest, FileDeletionRequest as FileDeletionRequestType, MinimalBlockInfo,
+    RespondStorageRequest,
 };
 
 // TODO: Add the events from the `pallet-cr-randomness` here to process them in the BlockchainService.
@@ -71,6 +72,45 @@ pub struct NewStorageRequest {
 
 impl EventBusMessage for NewStorageRequest {}
 
+/// Storage request revoked event.
+///
+/// This event is emitted when a user revokes a storage request on-chain, before it was fulfilled.
+/// Providers that were in the middle of receiving the file should clean up any local state they
+/// hold for it.
+#[derive(Debug, Clone)]
+pub struct StorageRequestRevoked {
+    /// File key of the revoked storage request.
+    pub file_key: FileKey,
+}
+
+impl EventBusMessage for StorageRequestRevoked {}
+
+/// Storage request expired event.
+///
+/// This event is emitted when a storage request expires on-chain without being fulfilled.
+/// Providers that were in the middle of receiving the file should clean up any local state they
+/// hold for it.
+#[derive(Debug, Clone)]
+pub struct StorageRequestExpired {
+    /// File key of the expired storage request.
+    pub file_key: FileKey,
+}
+
+impl EventBusMessage for StorageRequestExpired {}
+
+/// Storage request fulfilled event.
+///
+/// This event is emitted when a storage request is fulfilled on-chain, i.e. the MSP accepted it
+/// and the replication target of BSPs was reached. It is only emitted for user nodes, so that they
+/// know when they can stop serving the file.
+#[derive(Debug, Clone)]
+pub struct StorageRequestFulfilled {
+    /// File key of the fulfilled storage request.
+    pub file_key: FileKey,
+}
+
+impl EventBusMessage for StorageRequestFulfilled {}
+
 /// MSP stopped storing bucket event.
 ///
 /// This event is emitted when an MSP stops storing a bucket.
@@ -362,6 +402,17 @@ pub struct FinalisedBspConfirmStoppedStoring {
 
 impl EventBusMessage for FinalisedBspConfirmStoppedStoring {}
 
+/// Priority challenge for file deletion queued event.
+///
+/// This event is emitted when a priority challenge for the deletion of a file has been queued
+/// on-chain, meaning that the file is about to be removed from the Forests of the Providers storing it.
+#[derive(Debug, Clone)]
+pub struct PriorityChallengeForFileDeletionQueued {
+    pub file_key: FileKey,
+}
+
+impl EventBusMessage for PriorityChallengeForFileDeletionQueued {}
+
 /// Notify period event.
 ///
 /// This event is emitted when a X amount of block has passed. It is configured at the start of the service.
@@ -452,6 +503,58 @@ pub struct FinalisedBucketMovedAway {
 
 impl EventBusMessage for FinalisedBucketMovedAway {}
 
+/// Event emitted when the local root of a Forest no longer matches the one on-chain, after applying
+/// the mutations of a `MutationsApplied` event to it.
+///
+/// The Forest Storage is out of sync with the chain from then on, so it has to be rebuilt from
+/// authoritative data for the provider to be able to submit valid proofs again.
+#[derive(Debug, Clone)]
+pub struct ForestRootMismatch {
+    /// The Provider managing the Forest.
+    pub provider_id: ProofsDealerProviderId,
+    /// The Bucket whose Forest diverged, if the Provider is an MSP.
+    pub bucket_id: Option<BucketId>,
+}
+
+impl EventBusMessage for ForestRootMismatch {}
+
+/// Event emitted when a new best block causes a reorg, i.e. the previous best block is not one of
+/// its ancestors.
+///
+/// Extrinsics included only in the blocks of the retracted fork are not part of the canonical chain
+/// anymore, so providers might have to send them again.
+#[derive(Debug, Clone)]
+pub struct Reorg {
+    /// The best block before the reorg, in the now non-best fork.
+    pub old_best_block: MinimalBlockInfo,
+    /// The new best block.
+    pub new_best_block: MinimalBlockInfo,
+}
+
+impl EventBusMessage for Reorg {}
+
+/// Event emitted when the on-chain capacity of the BSP managed by this node changes in an
+/// imported block, or the part of it in use does, i.e. when it confirms storing files or stops
+/// storing them.
+#[derive(Debug, Clone)]
+pub struct BspCapacityChanged {
+    pub bsp_id: H256,
+}
+
+impl EventBusMessage for BspCapacityChanged {}
+
+/// Event emitted when the repair of a file stored by this node is requested, e.g. by the operator
+/// after its local copy was found to be corrupt.
+///
+/// The file is verified against its fingerprint, and downloaded again from the other providers
+/// storing it if its local copy is corrupt or missing.
+#[derive(Debug, Clone)]
+pub struct RepairFileRequested {
+    pub file_key: H256,
+}
+
+impl EventBusMessage for RepairFileRequested {}
+
 /// The event bus provider for the BlockchainService actor.
 ///
 /// It holds the event buses for the different events that the BlockchainService actor
@@ -461,6 +564,9 @@ pub struct BlockchainServiceEventBusProvider {
     new_challenge_seed_event_bus: EventBus<NewChallengeSeed>,
     multiple_new_challenge_seeds_event_bus: EventBus<MultipleNewChallengeSeeds>,
     new_storage_request_event_bus: EventBus<NewStorageRequest>,
+    storage_request_revoked_event_bus: EventBus<StorageRequestRevoked>,
+    storage_request_expired_event_bus: EventBus<StorageRequestExpired>,
+    storage_request_fulfilled_event_bus: EventBus<StorageRequestFulfilled>,
     accepted_bsp_volunteer_event_bus: EventBus<AcceptedBspVolunteer>,
     process_submit_proof_request_event_bus: EventBus<ProcessSubmitProofRequest>,
     process_confirm_storage_request_event_bus: EventBus<ProcessConfirmStoringRequest>,
@@ -484,12 +590,18 @@ pub struct BlockchainServiceEventBusProvider {
     move_bucket_requested_for_new_msp_event_bus: EventBus<MoveBucketRequestedForMsp>,
     bsp_stop_storing_event_bus: EventBus<BspConfirmStoppedStoring>,
     finalised_bsp_stop_storing_event_bus: EventBus<FinalisedBspConfirmStoppedStoring>,
+    priority_challenge_for_file_deletion_queued_event_bus:
+        EventBus<PriorityChallengeForFileDeletionQueued>,
     notify_period_event_bus: EventBus<NotifyPeriod>,
     file_deletion_request_event_bus: EventBus<FileDeletionRequest>,
     finalised_file_deletion_request_event_bus:
         EventBus<FinalisedProofSubmittedForPendingFileDeletionRequest>,
     start_moved_bucket_download_event_bus: EventBus<StartMovedBucketDownload>,
     finalised_bucket_moved_away_event_bus: EventBus<FinalisedBucketMovedAway>,
+    forest_root_mismatch_event_bus: EventBus<ForestRootMismatch>,
+    reorg_event_bus: EventBus<Reorg>,
+    bsp_capacity_changed_event_bus: EventBus<BspCapacityChanged>,
+    repair_file_requested_event_bus: EventBus<RepairFileRequested>,
 }
 
 impl BlockchainServiceEventBusProvider {
@@ -498,6 +610,9 @@ impl BlockchainServiceEventBusProvider {
             new_challenge_seed_event_bus: EventBus::new(),
             multiple_new_challenge_seeds_event_bus: EventBus::new(),
             new_storage_request_event_bus: EventBus::new(),
+            storage_request_revoked_event_bus: EventBus::new(),
+            storage_request_expired_event_bus: EventBus::new(),
+            storage_request_fulfilled_event_bus: EventBus::new(),
             accepted_bsp_volunteer_event_bus: EventBus::new(),
             process_submit_proof_request_event_bus: EventBus::new(),
             process_confirm_storage_request_event_bus: EventBus::new(),
@@ -519,11 +634,16 @@ impl BlockchainServiceEventBusProvider {
             move_bucket_requested_for_new_msp_event_bus: EventBus::new(),
             bsp_stop_storing_event_bus: EventBus::new(),
             finalised_bsp_stop_storing_event_bus: EventBus::new(),
+            priority_challenge_for_file_deletion_queued_event_bus: EventBus::new(),
             notify_period_event_bus: EventBus::new(),
             file_deletion_request_event_bus: EventBus::new(),
             finalised_file_deletion_request_event_bus: EventBus::new(),
             start_moved_bucket_download_event_bus: EventBus::new(),
             finalised_bucket_moved_away_event_bus: EventBus::new(),
+            forest_root_mismatch_event_bus: EventBus::new(),
+            reorg_event_bus: EventBus::new(),
+            bsp_capacity_changed_event_bus: EventBus::new(),
+            repair_file_requested_event_bus: EventBus::new(),
         }
     }
 }
@@ -546,6 +666,24 @@ impl ProvidesEventBus<NewStorageRequest> for BlockchainServiceEventBusProvider {
     }
 }
 
+impl ProvidesEventBus<StorageRequestRevoked> for BlockchainServiceEventBusProvider {
+    fn event_bus(&self) -> &EventBus<StorageRequestRevoked> {
+        &self.storage_request_revoked_event_bus
+    }
+}
+
+impl ProvidesEventBus<StorageRequestExpired> for BlockchainServiceEventBusProvider {
+    fn event_bus(&self) -> &EventBus<StorageRequestExpired> {
+        &self.storage_request_expired_event_bus
+    }
+}
+
+impl ProvidesEventBus<StorageRequestFulfilled> for BlockchainServiceEventBusProvider {
+    fn event_bus(&self) -> &EventBus<StorageRequestFulfilled> {
+        &self.storage_request_fulfilled_event_bus
+    }
+}
+
 impl ProvidesEventBus<AcceptedBspVolunteer> for BlockchainServiceEventBusProvider {
     fn event_bus(&self) -> &EventBus<AcceptedBspVolunteer> {
         &self.accepted_bsp_volunteer_event_bus
@@ -670,6 +808,14 @@ impl ProvidesEventBus<FinalisedBspConfirmStoppedStoring> for BlockchainServiceEv
     }
 }
 
+impl ProvidesEventBus<PriorityChallengeForFileDeletionQueued>
+    for BlockchainServiceEventBusProvider
+{
+    fn event_bus(&self) -> &EventBus<PriorityChallengeForFileDeletionQueued> {
+        &self.priority_challenge_for_file_deletion_queued_event_bus
+    }
+}
+
 impl ProvidesEventBus<NotifyPeriod> for BlockchainServiceEventBusProvider {
     fn event_bus(&self) -> &EventBus<NotifyPeriod> {
         &self.notify_period_event_bus
@@ -707,3 +853,27 @@ impl ProvidesEventBus<FinalisedBucketMovedAway> for BlockchainServiceEventBusPro
         &self.finalised_bucket_moved_away_event_bus
     }
 }
+
+impl ProvidesEventBus<ForestRootMismatch> for BlockchainServiceEventBusProvider {
+    fn event_bus(&self) -> &EventBus<ForestRootMismatch> {
+        &self.forest_root_mismatch_event_bus
+    }
+}
+
+impl ProvidesEventBus<Reorg> for BlockchainServiceEventBusProvider {
+    fn event_bus(&self) -> &EventBus<Reorg> {
+        &self.reorg_event_bus
+    }
+}
+
+impl ProvidesEventBus<BspCapacityChanged> for BlockchainServiceEventBusProvider {
+    fn event_bus(&self) -> &EventBus<BspCapacityChanged> {
+        &self.bsp_capacity_changed_event_bus
+    }
+}
+
+impl ProvidesEventBus<RepairFileRequested> for BlockchainServiceEventBusProvider {
+    fn event_bus(&self) -> &EventBus<RepairFileRequested> {
+        &self.repair_file_requested_event_bus
+    }
+}
@@ -6,7 +6,7 @@ use sc_client_api::{
     BlockImportNotification, BlockchainEvents, FinalityNotification, HeaderBackend,
 };
 use sc_service::RpcHandlers;
-use sc_tracing::tracing::{debug, error, info, trace, warn};
+use sc_tracing::tracing::{debug, error, info, trace, warn, Instrument};
 use shc_forest_manager::traits::ForestStorageHandler;
 use sp_api::{ApiError, ProvideRuntimeApi};
 use sp_blockchain::TreeRoute;
@@ -24,29 +24,34 @@ use pallet_proofs_dealer_runtime_api::{
     ProofsDealerApi,
 };
 use pallet_storage_providers_runtime_api::{
-    GetBspInfoError, QueryAvailableStorageCapacityError, QueryBucketsOfUserStoredByMspError,
-    QueryEarliestChangeCapacityBlockError, QueryMspIdOfBucketIdError,
-    QueryProviderMultiaddressesError, QueryStorageProviderCapacityError, StorageProvidersApi,
+    GetBspInfoError, QueryAvailableStorageCapacityError, QueryBucketReadAccessError,
+    QueryBucketRootError, QueryBucketSizeAndDataLimitError, QueryBucketsForMspError,
+    QueryBucketsOfUserStoredByMspError, QueryEarliestChangeCapacityBlockError,
+    QueryMspIdOfBucketIdError, QueryProviderMultiaddressesError, QueryStorageProviderCapacityError,
+    StorageProvidersApi,
 };
 use shc_actors_framework::actor::{Actor, ActorEventLoop};
 use shc_common::{
-    blockchain_utils::{convert_raw_multiaddresses_to_multiaddr, get_events_at_block},
+    blockchain_utils::{
+        convert_raw_multiaddresses_to_multiaddr, get_events_at_block, is_bsp_volunteer_at_block,
+    },
     types::{BlockNumber, ParachainClient, TickNumber},
 };
 
 use crate::{
     capacity_manager::{CapacityRequest, CapacityRequestQueue},
     commands::BlockchainServiceCommand,
-    events::BlockchainServiceEventBusProvider,
+    events::{BlockchainServiceEventBusProvider, Reorg, RepairFileRequested},
     state::{
         BlockchainServiceStateStore, LastProcessedBlockNumberCf,
         OngoingProcessConfirmStoringRequestCf, OngoingProcessMspRespondStorageRequestCf,
-        OngoingProcessStopStoringForInsolventUserRequestCf,
+        OngoingProcessStopStoringForInsolventUserRequestCf, PendingCheckpointRemovalCf,
+        PendingSubmitProofRequestCf, StorageVerificationCheckpointCf,
     },
     transaction::SubmittedTransaction,
-    typed_store::{CFDequeAPI, ProvidesTypedDbSingleAccess},
+    typed_store::{CFDequeAPI, ProvidesTypedDbAccess, ProvidesTypedDbSingleAccess},
     types::{
-        ManagedProvider, MinimalBlockInfo, NewBlockNotificationKind,
+        ConfirmStoringBatchConfig, ManagedProvider, MinimalBlockInfo, NewBlockNotificationKind,
         StopStoringForInsolventUserRequest,
     },
 };
@@ -126,6 +131,8 @@ where
     ///
     /// Only required if the node is running as a provider.
     pub(crate) capacity_manager: Option<CapacityRequestQueue>,
+    /// How pending BSP confirm storing requests are batched into a single extrinsic.
+    pub(crate) confirm_storing_batch_config: ConfirmStoringBatchConfig,
 }
 
 /// Event loop for the BlockchainService actor.
@@ -220,8 +227,9 @@ where
                 BlockchainServiceCommand::SendExtrinsic {
                     call,
                     options,
+                    span,
                     callback,
-                } => match self.send_extrinsic(call, options).await {
+                } => match self.send_extrinsic(call, options).instrument(span).await {
                     Ok(output) => {
                         debug!(target: LOG_TARGET, "Extrinsic sent successfully: {:?}", output);
                         match callback.send(Ok(SubmittedTransaction::new(
@@ -517,6 +525,28 @@ where
                         }
                     }
                 }
+                BlockchainServiceCommand::QueryConfirmChunksToProveForFiles {
+                    provider_id,
+                    file_keys,
+                    callback,
+                } => {
+                    let current_block_hash = self.client.info().best_hash;
+
+                    let chunks_to_prove = self.query_confirm_chunks_to_prove_for_files(
+                        current_block_hash,
+                        &provider_id,
+                        file_keys,
+                    );
+
+                    match callback.send(chunks_to_prove) {
+                        Ok(_) => {
+                            trace!(target: LOG_TARGET, "Chunks to prove files sent successfully");
+                        }
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to send chunks to prove files: {:?}", e);
+                        }
+                    }
+                }
                 BlockchainServiceCommand::QueryMspConfirmChunksToProveForFile {
                     msp_id,
                     file_key,
@@ -694,6 +724,23 @@ where
                         }
                     }
                 }
+                BlockchainServiceCommand::QueryFileSizeToChallenges { callback } => {
+                    let current_block_hash = self.client.info().best_hash;
+
+                    let file_size_to_challenges = self
+                        .client
+                        .runtime_api()
+                        .get_file_size_to_challenges(current_block_hash);
+
+                    match callback.send(file_size_to_challenges) {
+                        Ok(_) => {
+                            trace!(target: LOG_TARGET, "File size to challenges sent successfully");
+                        }
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to send file size to challenges: {:?}", e);
+                        }
+                    }
+                }
                 BlockchainServiceCommand::QueryLastCheckpointChallenges { tick, callback } => {
                     let current_block_hash = self.client.info().best_hash;
 
@@ -735,6 +782,52 @@ where
                         }
                     }
                 }
+                BlockchainServiceCommand::QueryForestRootAtBlock {
+                    provider_id,
+                    block_hash,
+                    callback,
+                } => {
+                    let root = self.forest_root_at_block(&provider_id, &block_hash);
+
+                    match callback.send(root) {
+                        Ok(_) => {
+                            trace!(target: LOG_TARGET, "Forest root at block sent successfully");
+                        }
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to send Forest root at block: {:?}", e);
+                        }
+                    }
+                }
+                BlockchainServiceCommand::QueryIsBspVolunteer {
+                    bsp_id,
+                    file_key,
+                    callback,
+                } => {
+                    let current_block_hash = self.best_block.hash;
+                    let is_volunteer = is_bsp_volunteer_at_block(
+                        &self.client,
+                        &current_block_hash,
+                        &file_key,
+                        &bsp_id,
+                    )
+                    .map_err(|e| {
+                        anyhow!(
+                            "Failed to query if BSP [{:?}] is a volunteer for file key [{:?}]: {}",
+                            bsp_id,
+                            file_key,
+                            e
+                        )
+                    });
+
+                    match callback.send(is_volunteer) {
+                        Ok(_) => {
+                            trace!(target: LOG_TARGET, "BSP volunteer status sent successfully");
+                        }
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to send BSP volunteer status: {:?}", e);
+                        }
+                    }
+                }
                 BlockchainServiceCommand::QueryStorageProviderCapacity {
                     provider_id,
                     callback,
@@ -803,6 +896,36 @@ where
                     }
                     }
                 }
+                BlockchainServiceCommand::QueueConfirmBspRequestBatch { requests, callback } => {
+                    if let Some(ManagedProvider::Bsp(_)) = &self.maybe_managed_provider {
+                        // All the requests are pushed in the same state store transaction, so that
+                        // they are picked up together in the same `ProcessConfirmStoringRequest`.
+                        let state_store_context =
+                            self.persistent_state.open_rw_context_with_overlay();
+                        for request in requests {
+                            state_store_context
+                                .pending_confirm_storing_request_deque()
+                                .push_back(request);
+                        }
+                        state_store_context.commit();
+                        // We check right away if we can process the requests so we don't waste time.
+                        self.bsp_assign_forest_root_write_lock();
+                        match callback.send(Ok(())) {
+                            Ok(_) => {}
+                            Err(e) => {
+                                error!(target: LOG_TARGET, "Failed to send receiver: {:?}", e);
+                            }
+                        }
+                    } else {
+                        error!(target: LOG_TARGET, "Received a QueueConfirmBspRequestBatch command while not managing a BSP. This should never happen. Please report it to the StorageHub team.");
+                        match callback.send(Err(anyhow!("Received a QueueConfirmBspRequestBatch command while not managing a BSP. This should never happen. Please report it to the StorageHub team."))) {
+                            Ok(_) => {}
+                            Err(e) => {
+                                error!(target: LOG_TARGET, "Failed to send receiver: {:?}", e);
+                            }
+                        }
+                    }
+                }
                 BlockchainServiceCommand::QueueMspRespondStorageRequest { request, callback } => {
                     let state_store_context = self.persistent_state.open_rw_context_with_overlay();
                     state_store_context
@@ -832,6 +955,14 @@ where
                             trace!(target: LOG_TARGET, "Replacing pending submit proof request {:?} with {:?}", replaced_request, request);
                         }
 
+                        // Persist the request so that it is not missed if the node restarts before submitting the proof.
+                        let state_store_context =
+                            self.persistent_state.open_rw_context_with_overlay();
+                        state_store_context
+                            .access(&PendingSubmitProofRequestCf)
+                            .put(&request.tick, &request);
+                        state_store_context.commit();
+
                         // We check right away if we can process the request so we don't waste time.
                         self.bsp_assign_forest_root_write_lock();
                         match callback.send(Ok(())) {
@@ -1015,6 +1146,92 @@ where
                         }
                     }
                 }
+                BlockchainServiceCommand::QueryBucketSizeAndDataLimit {
+                    bucket_id,
+                    callback,
+                } => {
+                    let current_block_hash = self.client.info().best_hash;
+
+                    let size_and_limit = self
+                        .client
+                        .runtime_api()
+                        .query_bucket_size_and_data_limit(current_block_hash, &bucket_id)
+                        .unwrap_or_else(|e| {
+                            error!(target: LOG_TARGET, "{}", e);
+                            Err(QueryBucketSizeAndDataLimitError::InternalError)
+                        });
+
+                    match callback.send(size_and_limit) {
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to send back bucket size and data limit: {:?}", e);
+                        }
+                    }
+                }
+                BlockchainServiceCommand::QueryBucketRoot {
+                    bucket_id,
+                    callback,
+                } => {
+                    let current_block_hash = self.client.info().best_hash;
+
+                    let root = self
+                        .client
+                        .runtime_api()
+                        .query_bucket_root(current_block_hash, &bucket_id)
+                        .unwrap_or_else(|e| {
+                            error!(target: LOG_TARGET, "{}", e);
+                            Err(QueryBucketRootError::InternalError)
+                        });
+
+                    match callback.send(root) {
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to send back bucket root: {:?}", e);
+                        }
+                    }
+                }
+                BlockchainServiceCommand::QueryBucketsForMsp { msp_id, callback } => {
+                    let current_block_hash = self.client.info().best_hash;
+
+                    let buckets = self
+                        .client
+                        .runtime_api()
+                        .query_buckets_for_msp(current_block_hash, &msp_id)
+                        .unwrap_or_else(|e| {
+                            error!(target: LOG_TARGET, "{}", e);
+                            Err(QueryBucketsForMspError::InternalError)
+                        });
+
+                    match callback.send(buckets) {
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to send back buckets for MSP: {:?}", e);
+                        }
+                    }
+                }
+                BlockchainServiceCommand::QueryBucketReadAccess {
+                    bucket_id,
+                    user,
+                    callback,
+                } => {
+                    let current_block_hash = self.client.info().best_hash;
+
+                    let read_access = self
+                        .client
+                        .runtime_api()
+                        .query_bucket_read_access(current_block_hash, &bucket_id, &user)
+                        .unwrap_or_else(|e| {
+                            error!(target: LOG_TARGET, "{}", e);
+                            Err(QueryBucketReadAccessError::InternalError)
+                        });
+
+                    match callback.send(read_access) {
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to send back bucket read access: {:?}", e);
+                        }
+                    }
+                }
                 BlockchainServiceCommand::QueryBucketsOfUserStoredByMsp {
                     msp_id,
                     user,
@@ -1078,6 +1295,113 @@ where
                         }
                     }
                 }
+                BlockchainServiceCommand::QueryPendingRequestsQueueDepths { callback } => {
+                    let state_store_context = self.persistent_state.open_rw_context_with_overlay();
+                    let mut depths = state_store_context.pending_requests_queue_depths();
+                    if let Some(ManagedProvider::Bsp(bsp_handler)) = &self.maybe_managed_provider {
+                        depths.submit_proof =
+                            bsp_handler.pending_submit_proof_requests.len() as u64;
+                    }
+                    match callback.send(depths) {
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to send pending requests queue depths: {:?}", e);
+                        }
+                    }
+                }
+                BlockchainServiceCommand::FlushPersistentState { callback } => {
+                    let result = self
+                        .persistent_state
+                        .flush()
+                        .map_err(|e| anyhow!("Failed to flush persistent state: {:?}", e));
+                    match callback.send(result) {
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to send flush result: {:?}", e);
+                        }
+                    }
+                }
+                BlockchainServiceCommand::RecordPendingCheckpointRemovals {
+                    file_keys,
+                    tick,
+                    callback,
+                } => {
+                    let state_store_context = self.persistent_state.open_rw_context_with_overlay();
+                    for file_key in &file_keys {
+                        state_store_context
+                            .access(&PendingCheckpointRemovalCf)
+                            .put(file_key, &tick);
+                    }
+                    state_store_context.commit();
+                    match callback.send(Ok(())) {
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to send receiver: {:?}", e);
+                        }
+                    }
+                }
+                BlockchainServiceCommand::ClearPendingCheckpointRemovals {
+                    file_keys,
+                    callback,
+                } => {
+                    let state_store_context = self.persistent_state.open_rw_context_with_overlay();
+                    for file_key in &file_keys {
+                        state_store_context
+                            .access(&PendingCheckpointRemovalCf)
+                            .delete(file_key);
+                    }
+                    state_store_context.commit();
+                    match callback.send(Ok(())) {
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to send receiver: {:?}", e);
+                        }
+                    }
+                }
+                BlockchainServiceCommand::QueryPendingCheckpointRemovals { callback } => {
+                    let state_store_context = self.persistent_state.open_rw_context_with_overlay();
+                    let removals = state_store_context.pending_checkpoint_removals();
+                    match callback.send(removals) {
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to send pending checkpoint removals: {:?}", e);
+                        }
+                    }
+                }
+                BlockchainServiceCommand::SetStorageVerificationCheckpoint {
+                    checkpoint,
+                    callback,
+                } => {
+                    let state_store_context = self.persistent_state.open_rw_context_with_overlay();
+                    match checkpoint {
+                        Some(file_key) => state_store_context
+                            .access_value(&StorageVerificationCheckpointCf)
+                            .write(&file_key),
+                        None => state_store_context
+                            .access_value(&StorageVerificationCheckpointCf)
+                            .delete(),
+                    }
+                    state_store_context.commit();
+                    match callback.send(Ok(())) {
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to send receiver: {:?}", e);
+                        }
+                    }
+                }
+                BlockchainServiceCommand::QueryStorageVerificationCheckpoint { callback } => {
+                    let checkpoint = self
+                        .persistent_state
+                        .open_rw_context_with_overlay()
+                        .access_value(&StorageVerificationCheckpointCf)
+                        .read();
+                    match callback.send(checkpoint) {
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to send storage verification checkpoint: {:?}", e);
+                        }
+                    }
+                }
                 BlockchainServiceCommand::QueueFileDeletionRequest { request, callback } => {
                     let state_store_context = self.persistent_state.open_rw_context_with_overlay();
                     state_store_context
@@ -1093,6 +1417,15 @@ where
                         }
                     }
                 }
+                BlockchainServiceCommand::RepairFile { file_key, callback } => {
+                    self.emit(RepairFileRequested { file_key });
+                    match callback.send(Ok(())) {
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to send receiver: {:?}", e);
+                        }
+                    }
+                }
             }
         }
     }
@@ -1115,6 +1448,7 @@ where
         rocksdb_root_path: impl Into<PathBuf>,
         notify_period: Option<u32>,
         capacity_request_queue: Option<CapacityRequestQueue>,
+        confirm_storing_batch_config: ConfirmStoringBatchConfig,
     ) -> Self {
         Self {
             event_bus_provider: BlockchainServiceEventBusProvider::new(),
@@ -1130,6 +1464,7 @@ where
             persistent_state: BlockchainServiceStateStore::new(rocksdb_root_path.into()),
             notify_period,
             capacity_manager: capacity_request_queue,
+            confirm_storing_batch_config,
         }
     }
 
@@ -1146,18 +1481,25 @@ where
 
         // Get the new best block info, and the `TreeRoute`, i.e. the blocks from the old best block to the new best block.
         // A new non-best block is ignored and not processed.
-        let (block_info, tree_route) = match new_block_notification_kind {
+        let (block_info, tree_route, reorg) = match new_block_notification_kind {
             NewBlockNotificationKind::NewBestBlock {
                 last_best_block_processed: _,
                 new_best_block,
                 tree_route,
-            } => (new_best_block, tree_route),
+            } => (new_best_block, tree_route, None),
             NewBlockNotificationKind::NewNonBestBlock(_) => return,
             NewBlockNotificationKind::Reorg {
-                old_best_block: _,
+                old_best_block,
+                new_best_block,
+                tree_route,
+            } => (
                 new_best_block,
                 tree_route,
-            } => (new_best_block, tree_route),
+                Some(Reorg {
+                    old_best_block,
+                    new_best_block,
+                }),
+            ),
         };
         let MinimalBlockInfo {
             number: block_number,
@@ -1179,6 +1521,11 @@ where
 
         self.process_block_import(&block_hash, &block_number, tree_route)
             .await;
+
+        // Notify the reorg once the local state (e.g. the Forests) follows the new best fork.
+        if let Some(reorg) = reorg {
+            self.emit(reorg);
+        }
     }
 
     /// Initialises the Blockchain Service with variables that should be checked and
@@ -1,34 +1,36 @@
 use std::sync::Arc;
 
-use log::{debug, error, info, trace};
+use log::{debug, error, info, trace, warn};
 use pallet_proofs_dealer_runtime_api::ProofsDealerApi;
 use pallet_proofs_dealer_runtime_api::{GetChallengePeriodError, GetChallengeSeedError};
 use sc_client_api::HeaderBackend;
 use sp_api::ProvideRuntimeApi;
 use sp_blockchain::TreeRoute;
-use sp_core::{Get, H256};
+use sp_core::H256;
 use sp_runtime::traits::Zero;
 use storage_hub_runtime::RuntimeEvent;
 use tokio::sync::oneshot::error::TryRecvError;
 
 use shc_actors_framework::actor::Actor;
 use shc_common::consts::CURRENT_FOREST_KEY;
-use shc_common::types::{BlockNumber, MaxBatchConfirmStorageRequests};
+use shc_common::types::{BlockNumber, StorageProviderId};
 use shc_forest_manager::traits::ForestStorageHandler;
 use tokio::sync::Mutex;
 
 use crate::events::{
-    BspConfirmStoppedStoring, FinalisedBspConfirmStoppedStoring, FinalisedBucketMovedAway,
-    FinalisedTrieRemoveMutationsApplied, ForestWriteLockTaskData, MoveBucketAccepted,
-    MoveBucketExpired, MoveBucketRejected, MoveBucketRequested, ProcessConfirmStoringRequest,
+    BspCapacityChanged, BspConfirmStoppedStoring, FinalisedBspConfirmStoppedStoring,
+    FinalisedBucketMovedAway, FinalisedTrieRemoveMutationsApplied, ForestRootMismatch,
+    ForestWriteLockTaskData, MoveBucketAccepted, MoveBucketExpired, MoveBucketRejected,
+    MoveBucketRequested, PriorityChallengeForFileDeletionQueued, ProcessConfirmStoringRequest,
     ProcessConfirmStoringRequestData, ProcessStopStoringForInsolventUserRequest,
     ProcessStopStoringForInsolventUserRequestData, ProcessSubmitProofRequest,
     ProcessSubmitProofRequestData,
 };
 use crate::state::{
     OngoingProcessConfirmStoringRequestCf, OngoingProcessStopStoringForInsolventUserRequestCf,
+    PendingSubmitProofRequestCf,
 };
-use crate::typed_store::{CFDequeAPI, ProvidesTypedDbSingleAccess};
+use crate::typed_store::{CFDequeAPI, ProvidesTypedDbAccess, ProvidesTypedDbSingleAccess};
 use crate::{
     events::MultipleNewChallengeSeeds,
     handler::{CHECK_FOR_PENDING_PROOFS_PERIOD, LOG_TARGET},
@@ -43,13 +45,83 @@ where
     /// Handles the initial sync of a BSP, after coming out of syncing mode.
     ///
     /// Steps:
-    /// 1. Catch up to the latest proof submissions that were missed due to a node restart.
-    pub(crate) fn bsp_initial_sync(&self) {
-        self.proof_submission_catch_up(&self.client.info().best_hash);
+    /// 1. Restore the submit proof requests that were pending when the node stopped.
+    /// 2. Catch up to the latest proof submissions that were missed due to a node restart.
+    pub(crate) fn bsp_initial_sync(&mut self) {
+        let best_block_hash = self.client.info().best_hash;
+        self.restore_pending_submit_proof_requests(&best_block_hash);
+        self.proof_submission_catch_up(&best_block_hash);
         // TODO: Send events to check that this node has a Forest Storage for the BSP that it manages.
         // TODO: Catch up to Forest root writes in the BSP Forest.
     }
 
+    /// Re-queues the submit proof requests persisted before the node stopped.
+    ///
+    /// Requests whose proof can no longer be submitted (i.e. `tick + grace_period < current_tick`,
+    /// where the grace period is the challenge ticks tolerance), or that are for a Provider other
+    /// than the managed BSP, are dropped from the persistent state.
+    fn restore_pending_submit_proof_requests(&mut self, block_hash: &H256) {
+        let bsp_id = match &self.maybe_managed_provider {
+            Some(ManagedProvider::Bsp(bsp_handler)) => bsp_handler.bsp_id,
+            _ => {
+                error!(target: LOG_TARGET, "`restore_pending_submit_proof_requests` should only be called if the node is managing a BSP. Found [{:?}] instead.", self.maybe_managed_provider);
+                return;
+            }
+        };
+
+        let current_tick = match self.client.runtime_api().get_current_tick(*block_hash) {
+            Ok(current_tick) => current_tick,
+            Err(e) => {
+                error!(target: LOG_TARGET, "Runtime API error while getting current tick: {:?}", e);
+                return;
+            }
+        };
+
+        // The grace period is the number of ticks between the next tick to prove and its deadline.
+        // If it can't be computed, no request is considered expired: the ones that are not the
+        // next to be submitted are skipped anyway when processing the queue.
+        let grace_period = match (
+            self.get_next_challenge_tick_for_provider(&bsp_id),
+            self.client
+                .runtime_api()
+                .get_next_deadline_tick(*block_hash, &bsp_id),
+        ) {
+            (Ok(next_challenge_tick), Ok(Ok(next_deadline_tick))) => {
+                next_deadline_tick.saturating_sub(next_challenge_tick)
+            }
+            (next_challenge_tick, next_deadline_tick) => {
+                warn!(target: LOG_TARGET, "Failed to compute the grace period to submit proofs for Provider [{:?}] (next challenge tick: {:?}, next deadline tick: {:?}). Restoring all pending submit proof requests.", bsp_id, next_challenge_tick, next_deadline_tick);
+                BlockNumber::MAX
+            }
+        };
+
+        let state_store_context = self.persistent_state.open_rw_context_with_overlay();
+        let persisted_requests = state_store_context.pending_submit_proof_requests();
+        let bsp_handler = match &mut self.maybe_managed_provider {
+            Some(ManagedProvider::Bsp(bsp_handler)) => bsp_handler,
+            _ => unreachable!("We just checked this is a BSP"),
+        };
+
+        let mut restored_requests = 0;
+        for request in persisted_requests {
+            if request.provider_id != bsp_id || request.is_expired(current_tick, grace_period) {
+                debug!(target: LOG_TARGET, "Dropping persisted submit proof request for Provider [{:?}] and tick [{:?}]", request.provider_id, request.tick);
+                state_store_context
+                    .access(&PendingSubmitProofRequestCf)
+                    .delete(&request.tick);
+                continue;
+            }
+
+            bsp_handler.pending_submit_proof_requests.insert(request);
+            restored_requests += 1;
+        }
+        state_store_context.commit();
+
+        if restored_requests > 0 {
+            info!(target: LOG_TARGET, "📥 Restored {} pending submit proof requests", restored_requests);
+        }
+    }
+
     /// Initialises the block processing flow for a BSP.
     ///
     /// Steps:
@@ -136,8 +208,36 @@ where
                         file_key: file_key.into(),
                         new_root,
                     });
+                    self.emit(BspCapacityChanged { bsp_id });
+                }
+            }
+            RuntimeEvent::FileSystem(pallet_file_system::Event::BspConfirmedStoring {
+                bsp_id,
+                ..
+            }) => {
+                if managed_bsp_id == &bsp_id {
+                    self.emit(BspCapacityChanged { bsp_id });
+                }
+            }
+            RuntimeEvent::Providers(pallet_storage_providers::Event::CapacityChanged {
+                provider_id: StorageProviderId::BackupStorageProvider(bsp_id),
+                ..
+            }) => {
+                if managed_bsp_id == &bsp_id {
+                    self.emit(BspCapacityChanged { bsp_id });
                 }
             }
+            RuntimeEvent::FileSystem(
+                pallet_file_system::Event::PriorityChallengeForFileDeletionQueued {
+                    issuer: _,
+                    file_key,
+                },
+            ) => {
+                // This BSP might be storing the file, so it needs to be notified.
+                self.emit(PriorityChallengeForFileDeletionQueued {
+                    file_key: file_key.into(),
+                });
+            }
             // Ignore all other events.
             _ => {}
         }
@@ -323,11 +423,25 @@ where
             if next_challenge_tick != request.tick {
                 // If the proof is not the next one to be submitted, we can skip it
                 trace!(target: LOG_TARGET, "Proof for tick [{:?}] is not the next one to be submitted. Skipping it.", request.tick);
+                state_store_context
+                    .access(&PendingSubmitProofRequestCf)
+                    .delete(&request.tick);
                 continue 'submit_proof_requests_loop;
             }
 
             // If the proof is still the next one to be submitted, we can process it.
+            // It is kept in the persistent state until it is no longer the next one to be submitted,
+            // so that it is resumed if the node restarts before the proof is included in a block.
             trace!(target: LOG_TARGET, "Proof for tick [{:?}] is the next one to be submitted. Processing it.", request.tick);
+            for stale_request in state_store_context
+                .pending_submit_proof_requests()
+                .into_iter()
+                .filter(|stale_request| stale_request.tick < request.tick)
+            {
+                state_store_context
+                    .access(&PendingSubmitProofRequestCf)
+                    .delete(&stale_request.tick);
+            }
             next_event_data = Some(ForestWriteLockTaskData::SubmitProofRequest(
                 ProcessSubmitProofRequestData {
                     seed: request.seed,
@@ -344,24 +458,74 @@ where
 
         // If we have no pending submit proof requests, we can also check for pending confirm storing requests.
         if next_event_data.is_none() {
-            let max_batch_confirm = <MaxBatchConfirmStorageRequests as Get<u32>>::get();
+            let current_tick = match self.client.runtime_api().get_current_tick(client_best_hash) {
+                Ok(current_tick) => Some(current_tick),
+                Err(e) => {
+                    error!(target: LOG_TARGET, "Runtime API error while getting current tick: {:?}", e);
+                    None
+                }
+            };
 
-            // Batch multiple confirm file storing taking the runtime maximum.
-            let mut confirm_storing_requests = Vec::new();
-            for _ in 0..max_batch_confirm {
-                if let Some(request) = state_store_context
-                    .pending_confirm_storing_request_deque()
-                    .pop_front()
-                {
-                    trace!(target: LOG_TARGET, "Processing confirm storing request for file [{:?}]", request.file_key);
-                    confirm_storing_requests.push(request);
-                } else {
-                    break;
+            let bsp_handler = match &mut self.maybe_managed_provider {
+                Some(ManagedProvider::Bsp(bsp_handler)) => bsp_handler,
+                _ => unreachable!("We just checked this is a BSP"),
+            };
+
+            let mut pending_requests = Vec::new();
+            while let Some(request) = state_store_context
+                .pending_confirm_storing_request_deque()
+                .pop_front()
+            {
+                pending_requests.push(request);
+            }
+
+            // Without the current tick, batches are confirmed right away as if they were full.
+            let should_confirm = match current_tick {
+                Some(current_tick) => {
+                    // Forget about storage requests that already expired.
+                    bsp_handler
+                        .storage_request_expirations
+                        .retain(|_, expires_at| *expires_at >= current_tick);
+                    let waiting_since = *bsp_handler
+                        .confirm_storing_waiting_since
+                        .get_or_insert(current_tick);
+
+                    self.confirm_storing_batch_config.should_confirm(
+                        &pending_requests,
+                        waiting_since,
+                        current_tick,
+                        &bsp_handler.storage_request_expirations,
+                    )
                 }
+                None => !pending_requests.is_empty(),
+            };
+
+            // Take a batch of confirm storing requests, up to the configured batch size (within
+            // the bound of the extrinsic). The rest stay queued for the next batches.
+            let (confirm_storing_requests, requests_left) = if should_confirm {
+                self.confirm_storing_batch_config
+                    .split_batch(pending_requests)
+            } else {
+                trace!(target: LOG_TARGET, "Waiting for more confirm storing requests to batch, {} pending", pending_requests.len());
+                (Vec::new(), pending_requests)
+            };
+
+            // The requests left after taking a batch start waiting for the next one from now.
+            if requests_left.is_empty() {
+                bsp_handler.confirm_storing_waiting_since = None;
+            } else if should_confirm {
+                bsp_handler.confirm_storing_waiting_since = current_tick;
+            }
+
+            for request in requests_left {
+                state_store_context
+                    .pending_confirm_storing_request_deque()
+                    .push_back(request);
             }
 
             // If we have at least 1 confirm storing request, send the process event.
-            if confirm_storing_requests.len() > 0 {
+            if !confirm_storing_requests.is_empty() {
+                trace!(target: LOG_TARGET, "Processing confirm storing requests for files [{:?}]", confirm_storing_requests.iter().map(|request| request.file_key).collect::<Vec<_>>());
                 next_event_data = Some(
                     ProcessConfirmStoringRequestData {
                         confirm_storing_requests,
@@ -442,6 +606,12 @@ where
                     .await
                 {
                     error!(target: LOG_TARGET, "CRITICAL ❗️❗️ Failed to apply mutations and verify root for BSP [{:?}]. \nError: {:?}", provider_id, e);
+
+                    // The local Forest diverged from the on-chain one, so it has to be recovered.
+                    self.emit(ForestRootMismatch {
+                        provider_id,
+                        bucket_id: None,
+                    });
                     return;
                 };
 
@@ -18,10 +18,11 @@ use crate::{
     events::{
         FileDeletionRequest, FinalisedBucketMovedAway, FinalisedMspStopStoringBucketInsolventUser,
         FinalisedMspStoppedStoringBucket, FinalisedProofSubmittedForPendingFileDeletionRequest,
-        ForestWriteLockTaskData, MoveBucketRequestedForMsp, ProcessFileDeletionRequest,
-        ProcessFileDeletionRequestData, ProcessMspRespondStoringRequest,
-        ProcessMspRespondStoringRequestData, ProcessStopStoringForInsolventUserRequest,
-        ProcessStopStoringForInsolventUserRequestData, StartMovedBucketDownload,
+        ForestRootMismatch, ForestWriteLockTaskData, MoveBucketRequestedForMsp,
+        ProcessFileDeletionRequest, ProcessFileDeletionRequestData,
+        ProcessMspRespondStoringRequest, ProcessMspRespondStoringRequestData,
+        ProcessStopStoringForInsolventUserRequest, ProcessStopStoringForInsolventUserRequestData,
+        StartMovedBucketDownload,
     },
     handler::LOG_TARGET,
     state::{
@@ -468,6 +469,12 @@ where
                     .await
                 {
                     error!(target: LOG_TARGET, "CRITICAL ❗️❗️ Failed to apply mutations and verify root for Bucket [{:?}]. \nError: {:?}", bucket_id, e);
+
+                    // The local Forest diverged from the on-chain one, so it has to be recovered.
+                    self.emit(ForestRootMismatch {
+                        provider_id: *managed_msp_id,
+                        bucket_id: Some(bucket_id),
+                    });
                     return;
                 };
 
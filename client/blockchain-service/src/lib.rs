@@ -18,6 +18,7 @@ use sp_keystore::KeystorePtr;
 
 use shc_actors_framework::actor::{ActorHandle, ActorSpawner, TaskSpawner};
 use shc_common::types::ParachainClient;
+use types::ConfirmStoringBatchConfig;
 
 pub use self::handler::BlockchainService;
 
@@ -30,6 +31,7 @@ pub async fn spawn_blockchain_service<FSH>(
     rocksdb_root_path: impl Into<PathBuf>,
     notify_period: Option<u32>,
     capacity_config: Option<CapacityConfig>,
+    confirm_storing_batch_config: ConfirmStoringBatchConfig,
 ) -> ActorHandle<BlockchainService<FSH>>
 where
     FSH: shc_forest_manager::traits::ForestStorageHandler + Clone + Send + Sync + 'static,
@@ -46,6 +48,7 @@ where
         rocksdb_root_path,
         notify_period,
         capacity_config.map(CapacityRequestQueue::new),
+        confirm_storing_batch_config,
     );
 
     task_spawner.spawn_actor(blockchain_service)
@@ -1,20 +1,23 @@
 use std::path::PathBuf;
 
+use codec::{Decode, Encode};
 use log::info;
 use rocksdb::{ColumnFamilyDescriptor, Options, DB};
 use shc_common::types::BlockNumber;
+use sp_core::H256;
 
 use crate::events::{ProcessFileDeletionRequestData, ProcessMspRespondStoringRequestData};
 use crate::{
     events::{ProcessConfirmStoringRequestData, ProcessStopStoringForInsolventUserRequestData},
     typed_store::{
         BufferedWriteSupport, CFDequeAPI, ProvidesDbContext, ProvidesTypedDbAccess,
-        ProvidesTypedDbSingleAccess, ScaleEncodedCf, SingleScaleEncodedValueCf, TypedCf,
-        TypedDbContext, TypedRocksDB,
+        ProvidesTypedDbSingleAccess, ReadableRocks, ScaleEncodedCf, SingleScaleEncodedValueCf,
+        TypedCf, TypedDbContext, TypedRocksDB,
     },
     types::{
-        ConfirmStoringRequest, FileDeletionRequest, RespondStorageRequest,
-        StopStoringForInsolventUserRequest,
+        unix_timestamp_secs, ConfirmStoringRequest, FileDeletionRequest, MspRespondStorageRequest,
+        PendingRequestsQueueDepths, RespondStorageRequest, StopStoringForInsolventUserRequest,
+        SubmitProofRequest,
     },
 };
 
@@ -31,7 +34,8 @@ pub struct OngoingProcessConfirmStoringRequestCf;
 impl SingleScaleEncodedValueCf for OngoingProcessConfirmStoringRequestCf {
     type Value = ProcessConfirmStoringRequestData;
 
-    const SINGLE_SCALE_ENCODED_VALUE_NAME: &'static str = "ongoing_process_confirm_storing_request";
+    const SINGLE_SCALE_ENCODED_VALUE_NAME: &'static str =
+        "ongoing_process_confirm_storing_request_v2";
 }
 
 /// Current ongoing task which requires a forest write lock.
@@ -50,7 +54,7 @@ impl ScaleEncodedCf for PendingConfirmStoringRequestCf {
     type Key = u64;
     type Value = ConfirmStoringRequest;
 
-    const SCALE_ENCODED_NAME: &'static str = "pending_confirm_storing_request";
+    const SCALE_ENCODED_NAME: &'static str = "pending_confirm_storing_request_v2";
 }
 
 /// Pending stop storing requests.
@@ -70,7 +74,7 @@ impl SingleScaleEncodedValueCf for PendingConfirmStoringRequestLeftIndexCf {
     type Value = u64;
 
     const SINGLE_SCALE_ENCODED_VALUE_NAME: &'static str =
-        "pending_confirm_storing_request_left_index";
+        "pending_confirm_storing_request_left_index_v2";
 }
 
 /// Pending submit proof requests right side (exclusive) index for the [`PendingConfirmStoringRequestCf`] CF.
@@ -79,6 +83,67 @@ pub struct PendingConfirmStoringRequestRightIndexCf;
 impl SingleScaleEncodedValueCf for PendingConfirmStoringRequestRightIndexCf {
     type Value = u64;
 
+    const SINGLE_SCALE_ENCODED_VALUE_NAME: &'static str =
+        "pending_confirm_storing_request_right_index_v2";
+}
+
+/// A [`ConfirmStoringRequest`] as persisted before it kept track of when it was queued.
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct LegacyConfirmStoringRequest {
+    pub file_key: H256,
+    pub try_count: u32,
+}
+
+impl LegacyConfirmStoringRequest {
+    /// Converts the request to a [`ConfirmStoringRequest`] queued at `enqueued_at`, keeping its
+    /// `try_count`.
+    fn migrate(self, enqueued_at: u64) -> ConfirmStoringRequest {
+        ConfirmStoringRequest {
+            file_key: self.file_key,
+            try_count: self.try_count,
+            enqueued_at,
+        }
+    }
+}
+
+/// Ongoing confirm storing requests, in the format used before [`OngoingProcessConfirmStoringRequestCf`].
+///
+/// Only read to migrate them when opening the store.
+pub struct LegacyOngoingProcessConfirmStoringRequestCf;
+impl SingleScaleEncodedValueCf for LegacyOngoingProcessConfirmStoringRequestCf {
+    type Value = Vec<LegacyConfirmStoringRequest>;
+
+    const SINGLE_SCALE_ENCODED_VALUE_NAME: &'static str = "ongoing_process_confirm_storing_request";
+}
+
+/// Pending confirm storing requests, in the format used before [`PendingConfirmStoringRequestCf`].
+///
+/// Only read to migrate them when opening the store.
+#[derive(Default)]
+pub struct LegacyPendingConfirmStoringRequestCf;
+impl ScaleEncodedCf for LegacyPendingConfirmStoringRequestCf {
+    type Key = u64;
+    type Value = LegacyConfirmStoringRequest;
+
+    const SCALE_ENCODED_NAME: &'static str = "pending_confirm_storing_request";
+}
+
+/// Left side (inclusive) index for the [`LegacyPendingConfirmStoringRequestCf`] CF.
+#[derive(Default)]
+pub struct LegacyPendingConfirmStoringRequestLeftIndexCf;
+impl SingleScaleEncodedValueCf for LegacyPendingConfirmStoringRequestLeftIndexCf {
+    type Value = u64;
+
+    const SINGLE_SCALE_ENCODED_VALUE_NAME: &'static str =
+        "pending_confirm_storing_request_left_index";
+}
+
+/// Right side (exclusive) index for the [`LegacyPendingConfirmStoringRequestCf`] CF.
+#[derive(Default)]
+pub struct LegacyPendingConfirmStoringRequestRightIndexCf;
+impl SingleScaleEncodedValueCf for LegacyPendingConfirmStoringRequestRightIndexCf {
+    type Value = u64;
+
     const SINGLE_SCALE_ENCODED_VALUE_NAME: &'static str =
         "pending_confirm_storing_request_right_index";
 }
@@ -88,7 +153,7 @@ impl SingleScaleEncodedValueCf for OngoingProcessMspRespondStorageRequestCf {
     type Value = ProcessMspRespondStoringRequestData;
 
     const SINGLE_SCALE_ENCODED_VALUE_NAME: &'static str =
-        "ongoing_process_msp_respond_storage_request";
+        "ongoing_process_msp_respond_storage_request_v2";
 }
 
 /// Pending respond storage requests.
@@ -98,7 +163,7 @@ impl ScaleEncodedCf for PendingMspRespondStorageRequestCf {
     type Key = u64;
     type Value = RespondStorageRequest;
 
-    const SCALE_ENCODED_NAME: &'static str = "pending_msp_respond_storage_request";
+    const SCALE_ENCODED_NAME: &'static str = "pending_msp_respond_storage_request_v2";
 }
 
 /// Pending respond storage requests left side (inclusive) index for the [`PendingMspRespondStorageRequestCf`] CF.
@@ -108,7 +173,7 @@ impl SingleScaleEncodedValueCf for PendingMspRespondStorageRequestLeftIndexCf {
     type Value = u64;
 
     const SINGLE_SCALE_ENCODED_VALUE_NAME: &'static str =
-        "pending_msp_respond_storage_request_left_index";
+        "pending_msp_respond_storage_request_left_index_v2";
 }
 
 /// Pending respond storage requests right side (exclusive) index for the [`PendingMspRespondStorageRequestCf`] CF.
@@ -117,6 +182,70 @@ pub struct PendingMspRespondStorageRequestRightIndexCf;
 impl SingleScaleEncodedValueCf for PendingMspRespondStorageRequestRightIndexCf {
     type Value = u64;
 
+    const SINGLE_SCALE_ENCODED_VALUE_NAME: &'static str =
+        "pending_msp_respond_storage_request_right_index_v2";
+}
+
+/// A [`RespondStorageRequest`] as persisted before it kept the bucket of its file.
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct LegacyRespondStorageRequest {
+    pub file_key: H256,
+    pub response: MspRespondStorageRequest,
+    pub try_count: u32,
+}
+
+impl LegacyRespondStorageRequest {
+    /// Converts the request to a [`RespondStorageRequest`] keeping its `try_count`, whose bucket
+    /// is taken from the file's metadata when it is processed.
+    fn migrate(self) -> RespondStorageRequest {
+        RespondStorageRequest {
+            file_key: self.file_key,
+            response: self.response,
+            try_count: self.try_count,
+            bucket_id: None,
+        }
+    }
+}
+
+/// Ongoing respond storage requests, in the format used before [`OngoingProcessMspRespondStorageRequestCf`].
+///
+/// Only read to migrate them when opening the store.
+pub struct LegacyOngoingProcessMspRespondStorageRequestCf;
+impl SingleScaleEncodedValueCf for LegacyOngoingProcessMspRespondStorageRequestCf {
+    type Value = Vec<LegacyRespondStorageRequest>;
+
+    const SINGLE_SCALE_ENCODED_VALUE_NAME: &'static str =
+        "ongoing_process_msp_respond_storage_request";
+}
+
+/// Pending respond storage requests, in the format used before [`PendingMspRespondStorageRequestCf`].
+///
+/// Only read to migrate them when opening the store.
+#[derive(Default)]
+pub struct LegacyPendingMspRespondStorageRequestCf;
+impl ScaleEncodedCf for LegacyPendingMspRespondStorageRequestCf {
+    type Key = u64;
+    type Value = LegacyRespondStorageRequest;
+
+    const SCALE_ENCODED_NAME: &'static str = "pending_msp_respond_storage_request";
+}
+
+/// Left side (inclusive) index for the [`LegacyPendingMspRespondStorageRequestCf`] CF.
+#[derive(Default)]
+pub struct LegacyPendingMspRespondStorageRequestLeftIndexCf;
+impl SingleScaleEncodedValueCf for LegacyPendingMspRespondStorageRequestLeftIndexCf {
+    type Value = u64;
+
+    const SINGLE_SCALE_ENCODED_VALUE_NAME: &'static str =
+        "pending_msp_respond_storage_request_left_index";
+}
+
+/// Right side (exclusive) index for the [`LegacyPendingMspRespondStorageRequestCf`] CF.
+#[derive(Default)]
+pub struct LegacyPendingMspRespondStorageRequestRightIndexCf;
+impl SingleScaleEncodedValueCf for LegacyPendingMspRespondStorageRequestRightIndexCf {
+    type Value = u64;
+
     const SINGLE_SCALE_ENCODED_VALUE_NAME: &'static str =
         "pending_msp_respond_storage_request_right_index";
 }
@@ -179,12 +308,58 @@ impl SingleScaleEncodedValueCf for FileDeletionRequestRightIndexCf {
         "pending_file_deletion_request_right_index";
 }
 
-const ALL_COLUMN_FAMILIES: [&str; 17] = [
+/// Pending submit proof requests, by the tick they are for.
+///
+/// A BSP has at most one submit proof request per tick, so the tick identifies the request
+/// (see [`SubmitProofRequest`]'s `Eq` implementation).
+#[derive(Default)]
+pub struct PendingSubmitProofRequestCf;
+impl ScaleEncodedCf for PendingSubmitProofRequestCf {
+    type Key = BlockNumber;
+    type Value = SubmitProofRequest;
+
+    const SCALE_ENCODED_NAME: &'static str = "pending_submit_proof_request";
+}
+
+/// File keys being removed from the BSP's Forest and File Storage, because they were challenged
+/// with a `TrieRemoveMutation` in a checkpoint challenge, by the tick of the proof answering it.
+///
+/// They are recorded before the proof is submitted, so that the removal can be completed after a
+/// restart if the node stops between the proof being accepted and the file being removed.
+#[derive(Default)]
+pub struct PendingCheckpointRemovalCf;
+impl ScaleEncodedCf for PendingCheckpointRemovalCf {
+    type Key = H256;
+    type Value = BlockNumber;
+
+    const SCALE_ENCODED_NAME: &'static str = "pending_checkpoint_removal";
+}
+
+/// Key of the last file verified by an interrupted verification of the whole File Storage.
+///
+/// Files are verified in ascending order of their keys, so a verification interrupted by a
+/// restart resumes after this key. It is removed once the verification finishes.
+pub struct StorageVerificationCheckpointCf;
+impl SingleScaleEncodedValueCf for StorageVerificationCheckpointCf {
+    type Value = H256;
+
+    const SINGLE_SCALE_ENCODED_VALUE_NAME: &'static str = "storage_verification_checkpoint";
+}
+
+const ALL_COLUMN_FAMILIES: [&str; 28] = [
     LastProcessedBlockNumberCf::NAME,
+    LegacyOngoingProcessConfirmStoringRequestCf::NAME,
+    LegacyPendingConfirmStoringRequestLeftIndexCf::NAME,
+    LegacyPendingConfirmStoringRequestRightIndexCf::NAME,
+    LegacyPendingConfirmStoringRequestCf::NAME,
     OngoingProcessConfirmStoringRequestCf::NAME,
     PendingConfirmStoringRequestLeftIndexCf::NAME,
     PendingConfirmStoringRequestRightIndexCf::NAME,
     PendingConfirmStoringRequestCf::NAME,
+    LegacyOngoingProcessMspRespondStorageRequestCf::NAME,
+    LegacyPendingMspRespondStorageRequestLeftIndexCf::NAME,
+    LegacyPendingMspRespondStorageRequestRightIndexCf::NAME,
+    LegacyPendingMspRespondStorageRequestCf::NAME,
     OngoingProcessMspRespondStorageRequestCf::NAME,
     PendingMspRespondStorageRequestLeftIndexCf::NAME,
     PendingMspRespondStorageRequestRightIndexCf::NAME,
@@ -197,6 +372,9 @@ const ALL_COLUMN_FAMILIES: [&str; 17] = [
     FileDeletionRequestLeftIndexCf::NAME,
     FileDeletionRequestRightIndexCf::NAME,
     FileDeletionRequestCf::NAME,
+    PendingSubmitProofRequestCf::NAME,
+    PendingCheckpointRemovalCf::NAME,
+    StorageVerificationCheckpointCf::NAME,
 ];
 
 /// A persistent blockchain service state store.
@@ -225,9 +403,109 @@ impl BlockchainServiceStateStore {
 
         let db = DB::open_cf_descriptors(&db_opts, db_path_str, column_families).unwrap();
 
-        BlockchainServiceStateStore {
+        let store = BlockchainServiceStateStore {
             rocks: TypedRocksDB { db },
+        };
+        store.migrate_legacy_confirm_storing_requests();
+        store.migrate_legacy_msp_respond_storage_requests();
+        store
+    }
+
+    /// Moves the confirm storing requests persisted in the legacy format, which can't be decoded
+    /// as [`ConfirmStoringRequest`]s, to the pending confirm storing requests queue.
+    ///
+    /// Their queueing time wasn't persisted, so they are considered queued now. Ongoing requests
+    /// are queued first, as they were the first to be popped from the queue.
+    fn migrate_legacy_confirm_storing_requests(&self) {
+        let context = self.open_rw_context_with_overlay();
+        let now = unix_timestamp_secs();
+
+        let mut requests = context
+            .access_value(&LegacyOngoingProcessConfirmStoringRequestCf)
+            .read()
+            .unwrap_or_default();
+        let mut legacy_deque = LegacyPendingConfirmStoringRequestDequeAPI {
+            db_context: &context.db_context,
+        };
+        while let Some(request) = legacy_deque.pop_front() {
+            requests.push(request);
+        }
+        if requests.is_empty() {
+            return;
+        }
+
+        info!(
+            "Migrating {} confirm storing request(s) to the current format",
+            requests.len()
+        );
+        for request in requests {
+            context
+                .pending_confirm_storing_request_deque()
+                .push_back(request.migrate(now));
         }
+        context
+            .access_value(&LegacyOngoingProcessConfirmStoringRequestCf)
+            .delete();
+        context
+            .access_value(&LegacyPendingConfirmStoringRequestLeftIndexCf)
+            .delete();
+        context
+            .access_value(&LegacyPendingConfirmStoringRequestRightIndexCf)
+            .delete();
+        context.commit();
+    }
+
+    /// Moves the respond storage requests persisted in the legacy format, which can't be decoded
+    /// as [`RespondStorageRequest`]s, to the pending respond storage requests queue.
+    ///
+    /// Ongoing requests are queued first, as they were the first to be popped from the queue.
+    fn migrate_legacy_msp_respond_storage_requests(&self) {
+        let context = self.open_rw_context_with_overlay();
+
+        let mut requests = context
+            .access_value(&LegacyOngoingProcessMspRespondStorageRequestCf)
+            .read()
+            .unwrap_or_default();
+        let mut legacy_deque = LegacyPendingMspRespondStorageRequestDequeAPI {
+            db_context: &context.db_context,
+        };
+        while let Some(request) = legacy_deque.pop_front() {
+            requests.push(request);
+        }
+        if requests.is_empty() {
+            return;
+        }
+
+        info!(
+            "Migrating {} respond storage request(s) to the current format",
+            requests.len()
+        );
+        for request in requests {
+            context
+                .pending_msp_respond_storage_request_deque()
+                .push_back(request.migrate());
+        }
+        context
+            .access_value(&LegacyOngoingProcessMspRespondStorageRequestCf)
+            .delete();
+        context
+            .access_value(&LegacyPendingMspRespondStorageRequestLeftIndexCf)
+            .delete();
+        context
+            .access_value(&LegacyPendingMspRespondStorageRequestRightIndexCf)
+            .delete();
+        context.commit();
+    }
+
+    /// Flushes all the column families (including the pending requests queues) to disk.
+    ///
+    /// Writes are already durable through RocksDB's write-ahead log, but flushing before shutting
+    /// down avoids having to replay it on the next start.
+    pub fn flush(&self) -> Result<(), rocksdb::Error> {
+        for cf in ALL_COLUMN_FAMILIES {
+            self.rocks.db.flush_cf(self.rocks.cf_handle(cf))?;
+        }
+        Ok(())
     }
 
     /// Starts a read/buffered-write interaction with the DB through per-CF type-safe APIs.
@@ -281,6 +559,45 @@ impl<'a> BlockchainServiceStateStoreRwContext<'a> {
         }
     }
 
+    /// The submit proof requests persisted to survive a node restart, ordered by tick.
+    ///
+    /// Only committed writes are taken into account.
+    pub fn pending_submit_proof_requests(&'a self) -> Vec<SubmitProofRequest> {
+        let mut requests: Vec<SubmitProofRequest> = self
+            .access(&PendingSubmitProofRequestCf)
+            .iterate_without_overlay()
+            .map(|(_, request)| request)
+            .collect();
+        // Keys are SCALE-encoded, so they are not iterated in tick order.
+        requests.sort();
+        requests
+    }
+
+    /// The pending checkpoint removals, with the tick of the proof that answered them.
+    ///
+    /// Only committed writes are taken into account.
+    pub fn pending_checkpoint_removals(&'a self) -> Vec<(H256, BlockNumber)> {
+        self.access(&PendingCheckpointRemovalCf)
+            .iterate_without_overlay()
+            .collect()
+    }
+
+    /// The number of requests in each of the persistent queues.
+    ///
+    /// Submit proof requests are queued in memory by the BSP handler (and only persisted to
+    /// survive a restart), so their count is left at 0.
+    pub fn pending_requests_queue_depths(&'a self) -> PendingRequestsQueueDepths {
+        PendingRequestsQueueDepths {
+            submit_proof: 0,
+            confirm_storing: self.pending_confirm_storing_request_deque().size(),
+            msp_respond_storage: self.pending_msp_respond_storage_request_deque().size(),
+            stop_storing_for_insolvent_user: self
+                .pending_stop_storing_for_insolvent_user_request_deque()
+                .size(),
+            file_deletion: self.pending_file_deletion_request_deque().size(),
+        }
+    }
+
     /// Flushes the buffered writes to the DB.
     pub fn commit(self) {
         self.db_context.flush();
@@ -316,6 +633,44 @@ impl<'a> CFDequeAPI for PendingConfirmStoringRequestDequeAPI<'a> {
     type DataCF = PendingConfirmStoringRequestCf;
 }
 
+struct LegacyPendingConfirmStoringRequestDequeAPI<'a> {
+    db_context: &'a TypedDbContext<'a, TypedRocksDB, BufferedWriteSupport<'a, TypedRocksDB>>,
+}
+
+impl<'a> ProvidesDbContext for LegacyPendingConfirmStoringRequestDequeAPI<'a> {
+    fn db_context(&self) -> &TypedDbContext<TypedRocksDB, BufferedWriteSupport<TypedRocksDB>> {
+        &self.db_context
+    }
+}
+
+impl<'a> ProvidesTypedDbSingleAccess for LegacyPendingConfirmStoringRequestDequeAPI<'a> {}
+
+impl<'a> CFDequeAPI for LegacyPendingConfirmStoringRequestDequeAPI<'a> {
+    type Value = LegacyConfirmStoringRequest;
+    type LeftIndexCF = LegacyPendingConfirmStoringRequestLeftIndexCf;
+    type RightIndexCF = LegacyPendingConfirmStoringRequestRightIndexCf;
+    type DataCF = LegacyPendingConfirmStoringRequestCf;
+}
+
+struct LegacyPendingMspRespondStorageRequestDequeAPI<'a> {
+    db_context: &'a TypedDbContext<'a, TypedRocksDB, BufferedWriteSupport<'a, TypedRocksDB>>,
+}
+
+impl<'a> ProvidesDbContext for LegacyPendingMspRespondStorageRequestDequeAPI<'a> {
+    fn db_context(&self) -> &TypedDbContext<TypedRocksDB, BufferedWriteSupport<TypedRocksDB>> {
+        &self.db_context
+    }
+}
+
+impl<'a> ProvidesTypedDbSingleAccess for LegacyPendingMspRespondStorageRequestDequeAPI<'a> {}
+
+impl<'a> CFDequeAPI for LegacyPendingMspRespondStorageRequestDequeAPI<'a> {
+    type Value = LegacyRespondStorageRequest;
+    type LeftIndexCF = LegacyPendingMspRespondStorageRequestLeftIndexCf;
+    type RightIndexCF = LegacyPendingMspRespondStorageRequestRightIndexCf;
+    type DataCF = LegacyPendingMspRespondStorageRequestCf;
+}
+
 pub struct PendingMspRespondStorageRequestDequeAPI<'a> {
     db_context: &'a TypedDbContext<'a, TypedRocksDB, BufferedWriteSupport<'a, TypedRocksDB>>,
 }
@@ -372,3 +727,295 @@ impl<'a> CFDequeAPI for PendingFileDeletionRequestDequeAPI<'a> {
     type RightIndexCF = FileDeletionRequestRightIndexCf;
     type DataCF = FileDeletionRequestCf;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shc_common::types::RejectedStorageRequestReason;
+    use sp_runtime::AccountId32;
+
+    #[test]
+    fn pending_requests_queue_depths_follow_the_queues() {
+        let path = std::env::temp_dir().join(format!(
+            "sh-blockchain-service-state-queue-depths-{}",
+            std::process::id()
+        ));
+        let store = BlockchainServiceStateStore::new(path.clone());
+
+        {
+            let context = store.open_rw_context_with_overlay();
+            assert_eq!(
+                context.pending_requests_queue_depths(),
+                PendingRequestsQueueDepths::default()
+            );
+        }
+
+        {
+            let context = store.open_rw_context_with_overlay();
+            for i in 0..3 {
+                context
+                    .pending_confirm_storing_request_deque()
+                    .push_back(ConfirmStoringRequest::new(H256::from_low_u64_be(i)));
+            }
+            context
+                .pending_msp_respond_storage_request_deque()
+                .push_back(RespondStorageRequest::new(
+                    H256::from_low_u64_be(3),
+                    MspRespondStorageRequest::Accept,
+                ));
+            context
+                .pending_msp_respond_storage_request_deque()
+                .push_back(RespondStorageRequest::new(
+                    H256::from_low_u64_be(4),
+                    MspRespondStorageRequest::Reject(
+                        RejectedStorageRequestReason::ReachedMaximumCapacity,
+                    ),
+                ));
+            context
+                .pending_stop_storing_for_insolvent_user_request_deque()
+                .push_back(StopStoringForInsolventUserRequest::new(AccountId32::new(
+                    [0u8; 32],
+                )));
+            context.commit();
+        }
+
+        {
+            let context = store.open_rw_context_with_overlay();
+            assert!(context
+                .pending_confirm_storing_request_deque()
+                .pop_front()
+                .is_some());
+            context.commit();
+        }
+
+        let context = store.open_rw_context_with_overlay();
+        assert_eq!(
+            context.pending_requests_queue_depths(),
+            PendingRequestsQueueDepths {
+                submit_proof: 0,
+                confirm_storing: 2,
+                msp_respond_storage: 2,
+                stop_storing_for_insolvent_user: 1,
+                file_deletion: 0,
+            }
+        );
+
+        drop(context);
+        drop(store);
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn legacy_confirm_storing_requests_are_migrated_on_open() {
+        let path = std::env::temp_dir().join(format!(
+            "sh-blockchain-service-state-legacy-confirm-storing-{}",
+            std::process::id()
+        ));
+        let legacy_request = |byte, try_count| LegacyConfirmStoringRequest {
+            file_key: H256::repeat_byte(byte),
+            try_count,
+        };
+
+        {
+            let store = BlockchainServiceStateStore::new(path.clone());
+            let context = store.open_rw_context_with_overlay();
+            context
+                .access_value(&LegacyOngoingProcessConfirmStoringRequestCf)
+                .write(&vec![legacy_request(1, 2)]);
+            let mut legacy_deque = LegacyPendingConfirmStoringRequestDequeAPI {
+                db_context: &context.db_context,
+            };
+            legacy_deque.push_back(legacy_request(2, 0));
+            legacy_deque.push_back(legacy_request(3, 1));
+            assert!(legacy_deque.pop_front().is_some());
+            legacy_deque.push_back(legacy_request(4, 0));
+            context.commit();
+        }
+
+        let before_reopening = unix_timestamp_secs();
+        let store = BlockchainServiceStateStore::new(path.clone());
+        let context = store.open_rw_context_with_overlay();
+        let mut migrated = Vec::new();
+        while let Some(request) = context.pending_confirm_storing_request_deque().pop_front() {
+            migrated.push(request);
+        }
+        assert_eq!(
+            migrated
+                .iter()
+                .map(|r| (r.file_key, r.try_count))
+                .collect::<Vec<_>>(),
+            vec![
+                (H256::repeat_byte(1), 2),
+                (H256::repeat_byte(3), 1),
+                (H256::repeat_byte(4), 0),
+            ]
+        );
+        assert!(migrated.iter().all(|r| r.enqueued_at >= before_reopening));
+
+        // The legacy requests are removed, so they are not migrated again.
+        assert!(context
+            .access_value(&LegacyOngoingProcessConfirmStoringRequestCf)
+            .read()
+            .is_none());
+        let legacy_deque = LegacyPendingConfirmStoringRequestDequeAPI {
+            db_context: &context.db_context,
+        };
+        assert_eq!(legacy_deque.size(), 0);
+
+        drop(context);
+        drop(store);
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn legacy_msp_respond_storage_requests_are_migrated_on_open() {
+        let path = std::env::temp_dir().join(format!(
+            "sh-blockchain-service-state-legacy-msp-respond-storage-{}",
+            std::process::id()
+        ));
+        let legacy_request = |byte, response, try_count| LegacyRespondStorageRequest {
+            file_key: H256::repeat_byte(byte),
+            response,
+            try_count,
+        };
+        let reject = || {
+            MspRespondStorageRequest::Reject(RejectedStorageRequestReason::ReachedMaximumCapacity)
+        };
+
+        {
+            let store = BlockchainServiceStateStore::new(path.clone());
+            let context = store.open_rw_context_with_overlay();
+            context
+                .access_value(&LegacyOngoingProcessMspRespondStorageRequestCf)
+                .write(&vec![legacy_request(
+                    1,
+                    MspRespondStorageRequest::Accept,
+                    2,
+                )]);
+            let mut legacy_deque = LegacyPendingMspRespondStorageRequestDequeAPI {
+                db_context: &context.db_context,
+            };
+            legacy_deque.push_back(legacy_request(2, MspRespondStorageRequest::Accept, 0));
+            legacy_deque.push_back(legacy_request(3, reject(), 1));
+            assert!(legacy_deque.pop_front().is_some());
+            legacy_deque.push_back(legacy_request(4, MspRespondStorageRequest::Accept, 0));
+            context.commit();
+        }
+
+        let store = BlockchainServiceStateStore::new(path.clone());
+        let context = store.open_rw_context_with_overlay();
+        let mut migrated = Vec::new();
+        while let Some(request) = context
+            .pending_msp_respond_storage_request_deque()
+            .pop_front()
+        {
+            migrated.push(request);
+        }
+        assert_eq!(
+            migrated
+                .iter()
+                .map(|r| (r.file_key, r.try_count))
+                .collect::<Vec<_>>(),
+            vec![
+                (H256::repeat_byte(1), 2),
+                (H256::repeat_byte(3), 1),
+                (H256::repeat_byte(4), 0),
+            ]
+        );
+        assert!(matches!(
+            migrated[1].response,
+            MspRespondStorageRequest::Reject(RejectedStorageRequestReason::ReachedMaximumCapacity)
+        ));
+        assert!(migrated.iter().all(|r| r.bucket_id.is_none()));
+
+        // The legacy requests are removed, so they are not migrated again.
+        assert!(context
+            .access_value(&LegacyOngoingProcessMspRespondStorageRequestCf)
+            .read()
+            .is_none());
+        let legacy_deque = LegacyPendingMspRespondStorageRequestDequeAPI {
+            db_context: &context.db_context,
+        };
+        assert_eq!(legacy_deque.size(), 0);
+
+        drop(context);
+        drop(store);
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn pending_submit_proof_requests_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!(
+            "sh-blockchain-service-state-submit-proof-requests-{}",
+            std::process::id()
+        ));
+        let request = |tick| {
+            SubmitProofRequest::new(
+                H256::repeat_byte(1),
+                tick,
+                H256::repeat_byte(2),
+                vec![H256::repeat_byte(3)],
+                vec![],
+            )
+        };
+
+        {
+            let store = BlockchainServiceStateStore::new(path.clone());
+            let context = store.open_rw_context_with_overlay();
+            // Keys are SCALE-encoded (little endian), so 256 sorts before 20 in the DB.
+            for tick in [256, 20, 30] {
+                context
+                    .access(&PendingSubmitProofRequestCf)
+                    .put(&tick, &request(tick));
+            }
+            context.access(&PendingSubmitProofRequestCf).delete(&30);
+            context.commit();
+        }
+
+        let store = BlockchainServiceStateStore::new(path.clone());
+        let context = store.open_rw_context_with_overlay();
+        let requests = context.pending_submit_proof_requests();
+        assert_eq!(
+            requests.iter().map(|r| r.tick).collect::<Vec<_>>(),
+            vec![20, 256]
+        );
+        assert_eq!(requests[0].forest_challenges, vec![H256::repeat_byte(3)]);
+
+        drop(context);
+        drop(store);
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn pending_checkpoint_removals_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!(
+            "sh-blockchain-service-state-checkpoint-removals-{}",
+            std::process::id()
+        ));
+
+        {
+            let store = BlockchainServiceStateStore::new(path.clone());
+            let context = store.open_rw_context_with_overlay();
+            for (file_key, tick) in [(H256::repeat_byte(1), 10), (H256::repeat_byte(2), 20)] {
+                context
+                    .access(&PendingCheckpointRemovalCf)
+                    .put(&file_key, &tick);
+            }
+            context
+                .access(&PendingCheckpointRemovalCf)
+                .delete(&H256::repeat_byte(1));
+            context.commit();
+        }
+
+        let store = BlockchainServiceStateStore::new(path.clone());
+        let context = store.open_rw_context_with_overlay();
+        assert_eq!(
+            context.pending_checkpoint_removals(),
+            vec![(H256::repeat_byte(2), 20)]
+        );
+
+        drop(context);
+        drop(store);
+        let _ = std::fs::remove_dir_all(path);
+    }
+}
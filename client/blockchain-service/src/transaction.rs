@@ -1,14 +1,17 @@
 use std::{
+    future::Future,
     str::FromStr,
+    sync::Arc,
     time::{Duration, Instant},
 };
 
 use log::{debug, error, info, warn};
+use serde_json::Number;
 use shc_actors_framework::actor::ActorHandle;
 use shc_common::types::StorageHubEventsVec;
 use shc_forest_manager::traits::ForestStorageHandler;
 use sp_core::H256;
-use tokio::sync::mpsc::Receiver;
+use tokio::sync::{mpsc::Receiver, watch};
 
 use crate::{
     commands::BlockchainServiceInterface,
@@ -18,12 +21,54 @@ use crate::{
 
 const LOG_TARGET: &str = "blockchain-transaction";
 
+/// Cancels the watch of the [`SubmittedTransaction`]s it is given to with
+/// [`SubmittedTransaction::with_cancellation`].
+///
+/// Clones share the same cancellation, so a task can keep a clone to stop waiting for a
+/// transaction that became pointless, e.g. because the storage request it was sent for was closed.
+#[derive(Debug, Clone)]
+pub struct TransactionWatchCancellation {
+    cancelled: Arc<watch::Sender<bool>>,
+}
+
+impl TransactionWatchCancellation {
+    pub fn new() -> Self {
+        let (cancelled, _) = watch::channel(false);
+        Self {
+            cancelled: Arc::new(cancelled),
+        }
+    }
+
+    /// Cancels the watches of the transactions this was given to, including future ones.
+    pub fn cancel(&self) {
+        self.cancelled.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow()
+    }
+
+    /// Resolves once [`Self::cancel`] has been called.
+    async fn cancelled(&self) {
+        let mut cancelled = self.cancelled.subscribe();
+        // The sender lives as long as `self`, so waiting cannot fail.
+        let _ = cancelled.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+impl Default for TransactionWatchCancellation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// A struct that handles the lifecycle of a submitted transaction.
 ///
 /// It holds a `watcher` that is used to query the state of the transaction from
 /// the blockchain node, a `hash` that is used to identify the transaction, and an
 /// optional `timeout` that specifies the maximum amount of time to wait for the
-/// transaction to either be successful or fail.
+/// transaction to either be successful or fail. The watch can also be cancelled early
+/// through an optional [`TransactionWatchCancellation`].
 #[derive(Debug)]
 pub struct SubmittedTransaction {
     /// The watcher used to query the state of the transaction from the blockchain node.
@@ -34,6 +79,8 @@ pub struct SubmittedTransaction {
     timeout: Option<Duration>,
     /// The nonce of the transaction.
     nonce: u32,
+    /// Cancels waiting for the transaction before the timeout.
+    cancellation: Option<TransactionWatchCancellation>,
 }
 
 const NO_TIMEOUT_INTERVAL_WARNING: Duration = Duration::from_secs(60);
@@ -45,6 +92,7 @@ impl SubmittedTransaction {
             hash,
             timeout: None,
             nonce,
+            cancellation: None,
         }
     }
 
@@ -67,11 +115,22 @@ impl SubmittedTransaction {
         self
     }
 
+    /// Sets the cancellation of the watch of the transaction.
+    ///
+    /// Once cancelled, waiting for the transaction stops, the extrinsic is unwatched and a
+    /// [`WatchTransactionError::Cancelled`] error is returned. The transaction itself stays in
+    /// the transaction pool, and may still be included in a block.
+    pub fn with_cancellation(mut self, cancellation: TransactionWatchCancellation) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
     /// Handles the lifecycle of a submitted transaction.
     ///
     /// Waits for the transaction to be included in a block AND the checks the transaction is successful.
     /// If the transaction is not included in a block within the specified timeout, it will be
     /// considered failed and an error will be returned.
+    /// If the watch is cancelled first, a [`WatchTransactionError::Cancelled`] error is returned.
     pub async fn watch_for_success<FSH>(
         &mut self,
         blockchain: &ActorHandle<BlockchainService<FSH>>,
@@ -114,6 +173,7 @@ impl SubmittedTransaction {
     /// Waits for the transaction to be included in a block AND the checks the transaction is successful.
     /// If the transaction is not included in a block within the specified timeout, it will be
     /// considered failed and an error will be returned.
+    /// If the watch is cancelled first, a [`WatchTransactionError::Cancelled`] error is returned.
     ///
     /// Returns the events emitted by the transaction.
     pub async fn watch_for_success_with_events<FSH>(
@@ -161,8 +221,36 @@ impl SubmittedTransaction {
     where
         FSH: ForestStorageHandler + Clone + Send + Sync + 'static,
     {
-        let block_hash;
+        let block_hash = self
+            .wait_for_in_block(|subscription_id| blockchain.unwatch_extrinsic(subscription_id))
+            .await?;
+
+        // Get the extrinsic from the block, with its events.
+        let extrinsic_in_block = blockchain
+            .get_extrinsic_from_block(block_hash, self.hash)
+            .await
+            .map_err(|e| {
+                let err_msg = format!("Error getting extrinsic from block: {:?}", e);
+                error!(target: LOG_TARGET, "{}", err_msg);
+                WatchTransactionError::Internal(err_msg)
+            })?;
+        Ok(extrinsic_in_block)
+    }
+
+    /// Waits for the transaction to be included in a block, and returns the hash of that block.
+    ///
+    /// The extrinsic is unwatched with `unwatch` once it is included in a block, or if the watch
+    /// is cancelled. If it is cancelled before the first status update of the transaction, its
+    /// subscription is not known yet, so the first update is awaited (within the timeout) to
+    /// unwatch it.
+    async fn wait_for_in_block<U, Fut>(&mut self, unwatch: U) -> Result<H256, WatchTransactionError>
+    where
+        U: Fn(Number) -> Fut,
+        Fut: Future<Output = anyhow::Result<()>>,
+    {
         let start_time = Instant::now();
+        // The subscription of the watch, sent along every status update of the transaction.
+        let mut subscription_id: Option<Number> = None;
         loop {
             // Get the elapsed time since submit.
             let elapsed = start_time.elapsed();
@@ -180,8 +268,35 @@ impl SubmittedTransaction {
                 None => NO_TIMEOUT_INTERVAL_WARNING,
             };
 
-            // Wait for either a new message from the watcher, or the timeout to be reached.
-            let result = match tokio::time::timeout(remaining, self.watcher.recv()).await {
+            let cancellation = self.cancellation.as_ref();
+            let cancelled = async move {
+                match cancellation {
+                    Some(cancellation) => cancellation.cancelled().await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            // Wait for either a new message from the watcher, the timeout to be reached, or the
+            // watch to be cancelled.
+            let received = tokio::select! {
+                received = tokio::time::timeout(remaining, self.watcher.recv()) => received,
+                _ = cancelled => {
+                    warn!(target: LOG_TARGET, "Watch of transaction {} cancelled", self.hash);
+                    let subscription_id = match subscription_id {
+                        Some(subscription_id) => Some(subscription_id),
+                        None => self.wait_for_subscription(remaining).await,
+                    };
+                    match subscription_id {
+                        Some(subscription_id) => Self::unwatch(&unwatch, subscription_id).await?,
+                        None => {
+                            warn!(target: LOG_TARGET, "No status update received for transaction {}, so it could not be unwatched", self.hash);
+                        }
+                    }
+                    return Err(WatchTransactionError::Cancelled);
+                }
+            };
+
+            let result = match received {
                 Ok(result) => match result {
                     Some(result) => result,
                     None => {
@@ -217,54 +332,213 @@ impl SubmittedTransaction {
 
             debug!(target: LOG_TARGET, "Transaction information: {:?}", json);
 
+            if let Some(id) = Self::subscription_of(&json) {
+                subscription_id = Some(id);
+            }
+
             // Checking if the transaction is included in a block.
             // TODO: Consider if we might want to wait for "finalized".
             // TODO: Handle other lifetime extrinsic edge cases. See https://github.com/paritytech/polkadot-sdk/blob/master/substrate/client/transaction-pool/api/src/lib.rs#L131
             if let Some(in_block) = json["params"]["result"]["inBlock"].as_str() {
-                block_hash = Some(H256::from_str(in_block).map_err(|_| {
+                let block_hash = H256::from_str(in_block).map_err(|_| {
                     error!(target: LOG_TARGET, "Block hash should be a valid H256; qed");
                     WatchTransactionError::Internal("Block hash should be a valid H256".to_string())
-                })?);
-                let subscription_id =
-                    json["params"]["subscription"].as_number().ok_or_else(|| {
-                        let err_msg = "Subscription should exist and be a number; qed";
-                        error!(target: LOG_TARGET, "{}", err_msg);
-                        WatchTransactionError::Internal(err_msg.to_string())
-                    })?;
+                })?;
+                let subscription_id = subscription_id.ok_or_else(|| {
+                    let err_msg = "Subscription should exist and be a number; qed";
+                    error!(target: LOG_TARGET, "{}", err_msg);
+                    WatchTransactionError::Internal(err_msg.to_string())
+                })?;
 
                 // Unwatch extrinsic to release tx_watcher.
-                blockchain
-                    .unwatch_extrinsic(subscription_id.to_owned())
-                    .await
-                    .map_err(|e| {
-                        let err_msg = format!("Error unwatching extrinsic: {:?}", e);
-                        error!(target: LOG_TARGET, "{}", err_msg);
-                        WatchTransactionError::Internal(err_msg)
-                    })?;
-
-                // Breaking while loop.
-                // Even though we unwatch the transaction, and the loop should break, we still break manually
-                // in case we continue to receive updates. This should not happen, but it is a safety measure,
-                // and we already have what we need.
-                break;
+                Self::unwatch(&unwatch, subscription_id).await?;
+
+                // Returning rather than waiting for more updates, since we already have what we
+                // need. Updates should stop after unwatching anyway.
+                return Ok(block_hash);
             }
         }
+    }
 
-        // Get the extrinsic from the block, with its events.
-        let block_hash = block_hash.ok_or_else(
-            || {
-                let err_msg = "Block hash should exist after waiting for extrinsic to be included in a block; qed";
-                error!(target: LOG_TARGET, "{}", err_msg);
-                WatchTransactionError::Internal(err_msg.to_string())
-            })?;
-        let extrinsic_in_block = blockchain
-            .get_extrinsic_from_block(block_hash, self.hash)
+    /// Waits up to `remaining` for the next status update of the transaction, and returns the
+    /// subscription of the watch it was sent along.
+    async fn wait_for_subscription(&mut self, remaining: Duration) -> Option<Number> {
+        let result = tokio::time::timeout(remaining, self.watcher.recv())
             .await
-            .map_err(|e| {
-                let err_msg = format!("Error getting extrinsic from block: {:?}", e);
-                error!(target: LOG_TARGET, "{}", err_msg);
-                WatchTransactionError::Internal(err_msg)
-            })?;
-        Ok(extrinsic_in_block)
+            .ok()??;
+        let json: serde_json::Value = serde_json::from_str(&result).ok()?;
+        Self::subscription_of(&json)
+    }
+
+    /// The subscription of the watch a status update of the transaction was sent along.
+    fn subscription_of(json: &serde_json::Value) -> Option<Number> {
+        json["params"]["subscription"].as_number().cloned()
+    }
+
+    async fn unwatch<U, Fut>(
+        unwatch: &U,
+        subscription_id: Number,
+    ) -> Result<(), WatchTransactionError>
+    where
+        U: Fn(Number) -> Fut,
+        Fut: Future<Output = anyhow::Result<()>>,
+    {
+        unwatch(subscription_id).await.map_err(|e| {
+            let err_msg = format!("Error unwatching extrinsic: {:?}", e);
+            error!(target: LOG_TARGET, "{}", err_msg);
+            WatchTransactionError::Internal(err_msg)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("Failed to build runtime")
+            .block_on(f)
+    }
+
+    const SUBSCRIPTION_ID: u64 = 7;
+
+    /// A status update of the transaction, as sent by `author_submitAndWatchExtrinsic`.
+    fn status_update(result: serde_json::Value) -> String {
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "author_extrinsicUpdate",
+            "params": { "subscription": SUBSCRIPTION_ID, "result": result },
+        })
+        .to_string()
+    }
+
+    fn watched_transaction(
+        cancellation: &TransactionWatchCancellation,
+    ) -> (mpsc::Sender<String>, SubmittedTransaction) {
+        let (sender, watcher) = mpsc::channel(10);
+        let transaction = SubmittedTransaction::new(watcher, H256::repeat_byte(1), 0)
+            .with_timeout(Duration::from_secs(60))
+            .with_cancellation(cancellation.clone());
+        (sender, transaction)
+    }
+
+    #[test]
+    fn cancelling_mid_wait_unwatches_and_returns_promptly() {
+        let cancellation = TransactionWatchCancellation::new();
+        let (sender, mut transaction) = watched_transaction(&cancellation);
+        let unwatched = Mutex::new(Vec::new());
+
+        let start = Instant::now();
+        let (result, _) = block_on(async {
+            tokio::join!(
+                transaction.wait_for_in_block(|subscription_id| {
+                    unwatched.lock().unwrap().push(subscription_id);
+                    async { Ok(()) }
+                }),
+                async {
+                    sender
+                        .send(status_update(serde_json::json!("ready")))
+                        .await
+                        .unwrap();
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    cancellation.cancel();
+                }
+            )
+        });
+
+        assert!(matches!(result, Err(WatchTransactionError::Cancelled)));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert_eq!(
+            unwatched.into_inner().unwrap(),
+            vec![Number::from(SUBSCRIPTION_ID)]
+        );
+    }
+
+    #[test]
+    fn cancelling_before_any_update_unwatches_once_the_first_update_arrives() {
+        let cancellation = TransactionWatchCancellation::new();
+        let (sender, mut transaction) = watched_transaction(&cancellation);
+        let unwatched = Mutex::new(Vec::new());
+
+        cancellation.cancel();
+        let (result, _) = block_on(async {
+            tokio::join!(
+                transaction.wait_for_in_block(|subscription_id| {
+                    unwatched.lock().unwrap().push(subscription_id);
+                    async { Ok(()) }
+                }),
+                async {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    sender
+                        .send(status_update(serde_json::json!("ready")))
+                        .await
+                        .unwrap();
+                }
+            )
+        });
+
+        assert!(matches!(result, Err(WatchTransactionError::Cancelled)));
+        assert_eq!(
+            unwatched.into_inner().unwrap(),
+            vec![Number::from(SUBSCRIPTION_ID)]
+        );
+    }
+
+    #[test]
+    fn cancelling_without_any_update_returns_once_the_timeout_is_reached() {
+        let cancellation = TransactionWatchCancellation::new();
+        let (_sender, transaction) = watched_transaction(&cancellation);
+        let mut transaction = transaction.with_timeout(Duration::from_millis(100));
+        let unwatched = Mutex::new(Vec::new());
+
+        cancellation.cancel();
+        let result = block_on(transaction.wait_for_in_block(|subscription_id| {
+            unwatched.lock().unwrap().push(subscription_id);
+            async { Ok(()) }
+        }));
+
+        assert!(matches!(result, Err(WatchTransactionError::Cancelled)));
+        assert!(unwatched.into_inner().unwrap().is_empty());
+    }
+
+    #[test]
+    fn inclusion_in_a_block_unwatches_and_returns_the_block_hash() {
+        let cancellation = TransactionWatchCancellation::new();
+        let (sender, mut transaction) = watched_transaction(&cancellation);
+        let unwatched = Mutex::new(Vec::new());
+        let block_hash = H256::repeat_byte(2);
+
+        let result = block_on(async {
+            sender
+                .send(status_update(serde_json::json!("ready")))
+                .await
+                .unwrap();
+            sender
+                .send(status_update(
+                    serde_json::json!({ "inBlock": format!("{:?}", block_hash) }),
+                ))
+                .await
+                .unwrap();
+            transaction
+                .wait_for_in_block(|subscription_id| {
+                    unwatched.lock().unwrap().push(subscription_id);
+                    async { Ok(()) }
+                })
+                .await
+        });
+
+        assert_eq!(result.unwrap(), block_hash);
+        assert_eq!(
+            unwatched.into_inner().unwrap(),
+            vec![Number::from(SUBSCRIPTION_ID)]
+        );
+        assert!(!cancellation.is_cancelled());
     }
 }
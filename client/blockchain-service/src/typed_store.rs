@@ -305,7 +305,7 @@ impl<'r, 'o, 'w, CF: TypedCf, R: WriteableRocks>
     }
 
     /// Iterates over the column family. This only supports `Start` mode and does not take the overlay into account.
-    pub fn iterate_without_overlay(&'r self) -> impl Iterator<Item = (CF::Key, CF::Value)> + 'r {
+    pub fn iterate_without_overlay(&self) -> impl Iterator<Item = (CF::Key, CF::Value)> + '_ {
         self.rocks
             .iterator_cf(self.cf.handle, IteratorMode::Start)
             .map(|(key, value)| (CF::KeyCodec::decode(&key), CF::ValueCodec::decode(&value)))
@@ -1,22 +1,23 @@
 use std::{
     cmp::{min, Ordering},
-    collections::{BTreeMap, BTreeSet},
+    collections::{BTreeMap, BTreeSet, HashMap},
     future::Future,
     pin::Pin,
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use codec::{Decode, Encode};
 use frame_support::dispatch::DispatchInfo;
-use log::warn;
+use log::{trace, warn};
 use sc_client_api::BlockImportNotification;
 use shc_common::types::{
     BackupStorageProviderId, BlockNumber, BucketId, CustomChallenge, HasherOutT,
-    MainStorageProviderId, ProofsDealerProviderId, RandomnessOutput, RejectedStorageRequestReason,
-    StorageData, StorageHubEventsVec, StorageProofsMerkleTrieLayout, StorageProviderId,
+    MainStorageProviderId, MaxBatchConfirmStorageRequests, ProofsDealerProviderId,
+    RandomnessOutput, RejectedStorageRequestReason, StorageData, StorageHubEventsVec,
+    StorageProofsMerkleTrieLayout, StorageProviderId, TickNumber,
 };
 use sp_blockchain::{HashAndNumber, TreeRoute};
-use sp_core::H256;
+use sp_core::{Get, H256};
 use sp_runtime::{
     traits::{Header, NumberFor},
     AccountId32, DispatchError, SaturatedConversion,
@@ -52,6 +53,12 @@ impl SubmitProofRequest {
             checkpoint_challenges,
         }
     }
+
+    /// Whether the deadline to submit this proof has passed at `current_tick`, given the
+    /// `grace_period` (in ticks) Providers have to submit a proof after the tick it is for.
+    pub fn is_expired(&self, current_tick: BlockNumber, grace_period: BlockNumber) -> bool {
+        self.tick.saturating_add(grace_period) < current_tick
+    }
 }
 
 impl Ord for SubmitProofRequest {
@@ -80,6 +87,11 @@ impl Eq for SubmitProofRequest {}
 pub struct ConfirmStoringRequest {
     pub file_key: H256,
     pub try_count: u32,
+    /// The UNIX timestamp, in seconds, at which the request was first queued.
+    ///
+    /// Kept when the request is queued again, so that it can be dropped once it has been waiting
+    /// for too long, regardless of its `try_count`.
+    pub enqueued_at: u64,
 }
 
 impl ConfirmStoringRequest {
@@ -87,12 +99,124 @@ impl ConfirmStoringRequest {
         Self {
             file_key,
             try_count: 0,
+            enqueued_at: unix_timestamp_secs(),
         }
     }
 
     pub fn increment_try_count(&mut self) {
         self.try_count += 1;
     }
+
+    /// Resets the `try_count`, for requests queued again after being part of a successful
+    /// submission, so that the failures of previous attempts do not count against them.
+    pub fn reset_try_count(&mut self) {
+        self.try_count = 0;
+    }
+
+    /// Whether the request has been queued for longer than `max_queue_age_secs` at `now`, a UNIX
+    /// timestamp in seconds. A `max_queue_age_secs` of `0` means requests never get too old.
+    pub fn is_too_old(&self, max_queue_age_secs: u64, now: u64) -> bool {
+        max_queue_age_secs != 0 && now.saturating_sub(self.enqueued_at) > max_queue_age_secs
+    }
+}
+
+/// Configuration of how pending [`ConfirmStoringRequest`]s are batched into a single
+/// `bsp_confirm_storing` extrinsic.
+///
+/// Waiting for more requests to accumulate saves fees, at the cost of a longer time to
+/// confirmation. The default configuration confirms pending requests right away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfirmStoringBatchConfig {
+    /// Maximum number of files confirmed in a single extrinsic.
+    ///
+    /// Capped to the runtime's [`MaxBatchConfirmStorageRequests`], the bound of the extrinsic.
+    pub max_batch_size: u32,
+    /// Maximum number of ticks to wait for a batch to fill up before confirming it anyway.
+    pub max_wait_ticks: TickNumber,
+    /// Requests for files whose storage request expires within this number of ticks are
+    /// confirmed right away, without waiting for the batch to fill up.
+    pub expiry_margin_ticks: TickNumber,
+}
+
+impl Default for ConfirmStoringBatchConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_size: MaxBatchConfirmStorageRequests::get(),
+            max_wait_ticks: 0,
+            expiry_margin_ticks: 10,
+        }
+    }
+}
+
+impl ConfirmStoringBatchConfig {
+    /// The maximum number of files in a batch, within the bound of the extrinsic.
+    pub fn batch_size_limit(&self) -> usize {
+        self.max_batch_size
+            .clamp(1, MaxBatchConfirmStorageRequests::get()) as usize
+    }
+
+    /// Whether the `pending_requests`, waiting to be confirmed since `waiting_since`, should be
+    /// confirmed at `current_tick`, or wait for more requests to accumulate.
+    ///
+    /// `expirations` holds the tick at which the storage request of each file expires, if known.
+    pub fn should_confirm(
+        &self,
+        pending_requests: &[ConfirmStoringRequest],
+        waiting_since: TickNumber,
+        current_tick: TickNumber,
+        expirations: &HashMap<H256, TickNumber>,
+    ) -> bool {
+        if pending_requests.is_empty() {
+            return false;
+        }
+
+        let batch_is_full = pending_requests.len() >= self.batch_size_limit();
+        let waited_long_enough = current_tick.saturating_sub(waiting_since) >= self.max_wait_ticks;
+        let nearing_expiry = pending_requests.iter().any(|request| {
+            expirations
+                .get(&request.file_key)
+                .is_some_and(|expires_at| {
+                    expires_at.saturating_sub(current_tick) <= self.expiry_margin_ticks
+                })
+        });
+
+        batch_is_full || waited_long_enough || nearing_expiry
+    }
+
+    /// Splits the `pending_requests` into the batch to confirm, within the batch size limit, and
+    /// the requests left for later batches, in their original order.
+    ///
+    /// Requests for a file key already in the batch are dropped, since a file can only be
+    /// confirmed once per extrinsic.
+    pub fn split_batch(
+        &self,
+        pending_requests: Vec<ConfirmStoringRequest>,
+    ) -> (Vec<ConfirmStoringRequest>, Vec<ConfirmStoringRequest>) {
+        let batch_size_limit = self.batch_size_limit();
+        let mut batch = Vec::new();
+        let mut batch_file_keys = BTreeSet::new();
+        let mut rest = Vec::new();
+
+        for request in pending_requests {
+            if batch.len() >= batch_size_limit {
+                rest.push(request);
+            } else if batch_file_keys.insert(request.file_key) {
+                batch.push(request);
+            } else {
+                trace!(target: LOG_TARGET, "Dropping duplicated confirm storing request for file [{:?}]", request.file_key);
+            }
+        }
+
+        (batch, rest)
+    }
+}
+
+/// The current UNIX timestamp, in seconds.
+pub fn unix_timestamp_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
 }
 
 #[derive(Debug, Clone, Encode, Decode)]
@@ -106,6 +230,12 @@ pub struct RespondStorageRequest {
     pub file_key: H256,
     pub response: MspRespondStorageRequest,
     pub try_count: u32,
+    /// The bucket the file belongs to.
+    ///
+    /// If not set, it is taken from the file's metadata in the file storage when processing the
+    /// request. It has to be set for rejections of files that are deleted from the file storage
+    /// before the response is sent.
+    pub bucket_id: Option<H256>,
 }
 
 impl RespondStorageRequest {
@@ -114,14 +244,38 @@ impl RespondStorageRequest {
             file_key,
             response,
             try_count: 0,
+            bucket_id: None,
         }
     }
 
+    pub fn with_bucket_id(mut self, bucket_id: H256) -> Self {
+        self.bucket_id = Some(bucket_id);
+        self
+    }
+
     pub fn increment_try_count(&mut self) {
         self.try_count += 1;
     }
 }
 
+/// The number of requests waiting in each of the queues of the Blockchain Service.
+///
+/// Used to observe whether the provider is falling behind processing them, e.g. to apply
+/// backpressure.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PendingRequestsQueueDepths {
+    /// Pending submit proof requests. Always 0 for an MSP.
+    pub submit_proof: u64,
+    /// Pending confirm storing requests.
+    pub confirm_storing: u64,
+    /// Pending MSP respond storage requests.
+    pub msp_respond_storage: u64,
+    /// Pending stop storing for insolvent user requests.
+    pub stop_storing_for_insolvent_user: u64,
+    /// Pending file deletion requests.
+    pub file_deletion: u64,
+}
+
 /// A struct that holds the information to stop storing all files from an insolvent user.
 /// (Which is only the user's account ID).
 ///
@@ -434,6 +588,8 @@ pub enum WatchTransactionError {
         dispatch_error: String,
         dispatch_info: String,
     },
+    #[error("Watch of the transaction was cancelled")]
+    Cancelled,
     #[error("Unexpected error: {0}")]
     Internal(String),
 }
@@ -596,8 +752,12 @@ impl Ord for ForestStorageSnapshotInfo {
 pub struct BspHandler {
     /// The BSP ID.
     pub(crate) bsp_id: BackupStorageProviderId,
-    /// Pending submit proof requests. Note: this is not kept in the persistent state because of
-    /// various edge cases when restarting the node.
+    /// Pending submit proof requests.
+    ///
+    /// They are mirrored in the persistent state (see [`PendingSubmitProofRequestCf`]), and the
+    /// ones that haven't expired are restored on the initial sync after a node restart.
+    ///
+    /// [`PendingSubmitProofRequestCf`]: crate::state::PendingSubmitProofRequestCf
     pub(crate) pending_submit_proof_requests: BTreeSet<SubmitProofRequest>,
     /// A lock to prevent multiple tasks from writing to the runtime Forest root (send transactions) at the same time.
     ///
@@ -611,6 +771,13 @@ pub struct BspHandler {
     /// TODO: Remove this `allow(dead_code)` once we have implemented the Forest Storage snapshots.
     #[allow(dead_code)]
     pub(crate) forest_root_snapshots: BTreeSet<ForestStorageSnapshotInfo>,
+    /// The tick at which the pending confirm storing requests started waiting for a batch to
+    /// fill up, if there are any. See [`ConfirmStoringBatchConfig`].
+    pub(crate) confirm_storing_waiting_since: Option<TickNumber>,
+    /// The tick at which the storage request of each file expires, for the storage requests
+    /// seen since the node started. Used to confirm files nearing expiry without waiting for
+    /// their batch to fill up.
+    pub(crate) storage_request_expirations: HashMap<H256, TickNumber>,
 }
 
 impl BspHandler {
@@ -620,6 +787,8 @@ impl BspHandler {
             pending_submit_proof_requests: BTreeSet::new(),
             forest_root_write_lock: None,
             forest_root_snapshots: BTreeSet::new(),
+            confirm_storing_waiting_since: None,
+            storage_request_expirations: HashMap::new(),
         }
     }
 }
@@ -674,3 +843,110 @@ impl ManagedProvider {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn submit_proof_requests_expire_after_the_grace_period() {
+        let request = SubmitProofRequest::new(H256::zero(), 10, H256::zero(), vec![], vec![]);
+
+        assert!(!request.is_expired(10, 5));
+        assert!(!request.is_expired(15, 5));
+        assert!(request.is_expired(16, 5));
+        assert!(request.is_expired(11, 0));
+    }
+
+    #[test]
+    fn confirm_storing_requests_are_dropped_once_too_old() {
+        let mut request = ConfirmStoringRequest::new(H256::zero());
+        request.enqueued_at = 1_000;
+
+        assert!(!request.is_too_old(60, 1_060));
+        assert!(request.is_too_old(60, 1_061));
+        // A clock going backwards does not make the request too old.
+        assert!(!request.is_too_old(60, 900));
+        // Without a maximum age, requests are never too old.
+        assert!(!request.is_too_old(0, u64::MAX));
+
+        request.increment_try_count();
+        request.increment_try_count();
+        request.reset_try_count();
+        assert_eq!(request.try_count, 0);
+        assert_eq!(request.enqueued_at, 1_000);
+    }
+
+    fn requests(file_keys: impl IntoIterator<Item = u64>) -> Vec<ConfirmStoringRequest> {
+        file_keys
+            .into_iter()
+            .map(|i| ConfirmStoringRequest::new(H256::from_low_u64_be(i)))
+            .collect()
+    }
+
+    fn file_keys(requests: &[ConfirmStoringRequest]) -> Vec<H256> {
+        requests.iter().map(|request| request.file_key).collect()
+    }
+
+    #[test]
+    fn oversized_batches_are_split() {
+        let config = ConfirmStoringBatchConfig {
+            max_batch_size: 2,
+            ..Default::default()
+        };
+
+        // The repeated file key 1 is dropped from the batch.
+        let (batch, rest) = config.split_batch(requests([1, 1, 2, 3, 4]));
+
+        assert_eq!(file_keys(&batch), file_keys(&requests([1, 2])));
+        assert_eq!(file_keys(&rest), file_keys(&requests([3, 4])));
+    }
+
+    #[test]
+    fn batch_size_is_capped_to_the_extrinsic_bound() {
+        let max_batch_confirm = MaxBatchConfirmStorageRequests::get();
+        let config = ConfirmStoringBatchConfig {
+            max_batch_size: max_batch_confirm + 10,
+            ..Default::default()
+        };
+
+        let (batch, rest) = config.split_batch(requests(0..max_batch_confirm as u64 + 5));
+
+        assert_eq!(batch.len(), max_batch_confirm as usize);
+        assert_eq!(rest.len(), 5);
+    }
+
+    #[test]
+    fn batches_wait_to_fill_up_until_the_max_wait() {
+        let config = ConfirmStoringBatchConfig {
+            max_batch_size: 3,
+            max_wait_ticks: 10,
+            expiry_margin_ticks: 5,
+        };
+        let expirations = HashMap::new();
+
+        assert!(!config.should_confirm(&requests([]), 100, 200, &expirations));
+        assert!(!config.should_confirm(&requests([1, 2]), 100, 109, &expirations));
+        assert!(config.should_confirm(&requests([1, 2]), 100, 110, &expirations));
+        // A full batch is confirmed right away.
+        assert!(config.should_confirm(&requests([1, 2, 3]), 100, 100, &expirations));
+    }
+
+    #[test]
+    fn files_nearing_expiry_bypass_the_wait() {
+        let config = ConfirmStoringBatchConfig {
+            max_batch_size: 3,
+            max_wait_ticks: 10,
+            expiry_margin_ticks: 5,
+        };
+        let expirations = HashMap::from([
+            (H256::from_low_u64_be(1), 200),
+            (H256::from_low_u64_be(2), 104),
+        ]);
+
+        assert!(!config.should_confirm(&requests([1]), 100, 100, &expirations));
+        assert!(config.should_confirm(&requests([1, 2]), 100, 100, &expirations));
+        // Files of unknown expiry wait as usual.
+        assert!(!config.should_confirm(&requests([3]), 100, 100, &expirations));
+    }
+}
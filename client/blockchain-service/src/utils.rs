@@ -4,6 +4,7 @@ use anyhow::{anyhow, Result};
 use codec::{Decode, Encode};
 use cumulus_primitives_core::BlockT;
 use log::{debug, error, info, trace, warn};
+use pallet_file_system_runtime_api::{FileSystemApi, QueryConfirmChunksToProveForFilesError};
 use pallet_proofs_dealer_runtime_api::{
     GetChallengePeriodError, GetProofSubmissionRecordError, ProofsDealerApi,
 };
@@ -16,15 +17,19 @@ use sc_network::Multiaddr;
 use serde_json::Number;
 use shc_actors_framework::actor::Actor;
 use shc_common::{
-    blockchain_utils::{convert_raw_multiaddresses_to_multiaddr, get_events_at_block},
+    blockchain_utils::{
+        convert_raw_multiaddresses_to_multiaddr, get_bsp_forest_root_at_block, get_events_at_block,
+    },
+    consts::CURRENT_FOREST_KEY,
     types::{
-        BlockNumber, FileKey, Fingerprint, ForestRoot, ParachainClient, ProofsDealerProviderId,
-        TrieAddMutation, TrieMutation, TrieRemoveMutation, BCSV_KEY_TYPE,
+        BackupStorageProviderId, BlockNumber, BucketId, ChunkId, FileKey, Fingerprint, ForestRoot,
+        MainStorageProviderId, OpaqueBlock, ParachainClient, ProofsDealerProviderId, ProviderId,
+        StorageProviderId, TrieAddMutation, TrieMutation, TrieRemoveMutation, BCSV_KEY_TYPE,
     },
 };
 use shc_forest_manager::traits::{ForestStorage, ForestStorageHandler};
 use shp_file_metadata::FileMetadata;
-use sp_api::ProvideRuntimeApi;
+use sp_api::{ApiExt, ProvideRuntimeApi};
 use sp_blockchain::{HashAndNumber, TreeRoute};
 use sp_core::{Blake2Hasher, Hasher, H256};
 use sp_keystore::KeystorePtr;
@@ -38,10 +43,12 @@ use substrate_frame_rpc_system::AccountNonceApi;
 
 use crate::{
     events::{
-        AcceptedBspVolunteer, LastChargeableInfoUpdated, NewStorageRequest, NotifyPeriod,
-        SlashableProvider, SpStopStoringInsolventUser, UserWithoutFunds,
+        AcceptedBspVolunteer, BspCapacityChanged, ForestRootMismatch, LastChargeableInfoUpdated,
+        NewStorageRequest, NotifyPeriod, SlashableProvider, SpStopStoringInsolventUser,
+        StorageRequestExpired, StorageRequestFulfilled, StorageRequestRevoked, UserWithoutFunds,
     },
     handler::{LOG_TARGET, MAX_BLOCKS_BEHIND_TO_CATCH_UP_ROOT_CHANGES},
+    typed_store::CFDequeAPI,
     types::{
         BspHandler, Extrinsic, ManagedProvider, MinimalBlockInfo, NewBlockNotificationKind,
         SendExtrinsicOptions, Tip,
@@ -750,6 +757,13 @@ where
             self.apply_forest_root_changes(block, true).await;
         }
 
+        // Once the retracted blocks are reverted, the Forest should be back at the root it had in
+        // the last block both forks have in common.
+        if !tree_route.retracted().is_empty() {
+            self.verify_bsp_forest_root_at_block(&tree_route.common_block().hash)
+                .await;
+        }
+
         // Enacted blocks, i.e. the blocks from the `TreeRoute` that are applied in the reorg.
         for block in tree_route.enacted() {
             self.apply_forest_root_changes(block, false).await;
@@ -758,6 +772,70 @@ where
         trace!(target: LOG_TARGET, "Applied Forest root changes for tree route {:?}", tree_route);
     }
 
+    /// Gets the Forest root of the BSP with `provider_id` from the state of the block with
+    /// `block_hash`, whether it is in the canonical chain or not.
+    pub(crate) fn forest_root_at_block(
+        &self,
+        provider_id: &ProviderId,
+        block_hash: &H256,
+    ) -> Result<H256> {
+        get_bsp_forest_root_at_block(&self.client, block_hash, provider_id).map_err(|e| {
+            anyhow!(
+                "Failed to get the Forest root of BSP [{:?}] at block [{:?}]: {}",
+                provider_id,
+                block_hash,
+                e
+            )
+        })
+    }
+
+    /// Checks that the local Forest of the managed BSP has the root it had on-chain at the block
+    /// with `block_hash`, emitting a [`ForestRootMismatch`] event for it to be recovered otherwise.
+    ///
+    /// Used after reverting the blocks retracted by a reorg, with the block both forks have in
+    /// common. MSPs are not checked here, as the root of each Bucket is verified while reverting
+    /// its mutations.
+    async fn verify_bsp_forest_root_at_block(&self, block_hash: &H256) {
+        let bsp_id = match &self.maybe_managed_provider {
+            Some(ManagedProvider::Bsp(bsp_handler)) => bsp_handler.bsp_id,
+            _ => return,
+        };
+
+        let on_chain_root = match self.forest_root_at_block(&bsp_id, block_hash) {
+            Ok(root) => root,
+            Err(e) => {
+                warn!(target: LOG_TARGET, "Skipping the check of the local Forest root after the reorg: {:?}", e);
+                return;
+            }
+        };
+
+        let current_forest_key = CURRENT_FOREST_KEY.to_vec();
+        let fs = match self
+            .forest_storage_handler
+            .get(&current_forest_key.into())
+            .await
+        {
+            Some(fs) => fs,
+            None => {
+                error!(target: LOG_TARGET, "CRITICAL❗️❗️ Failed to get Forest Storage.");
+                return;
+            }
+        };
+
+        let local_root = fs.read().await.root();
+        if local_root != on_chain_root {
+            error!(target: LOG_TARGET, "CRITICAL ❗️❗️ Local Forest root [{:?}] of BSP [{:?}] does not match its on-chain root [{:?}] at the fork point [{:?}] after reverting the reorg.", local_root, bsp_id, on_chain_root, block_hash);
+
+            self.emit(ForestRootMismatch {
+                provider_id: bsp_id,
+                bucket_id: None,
+            });
+            return;
+        }
+
+        debug!(target: LOG_TARGET, "Local Forest root of BSP [{:?}] matches its on-chain root at the fork point [{:?}]", bsp_id, block_hash);
+    }
+
     /// Gets the next tick for which a Provider (BSP) should submit a proof.
     pub(crate) fn get_next_challenge_tick_for_provider(
         &self,
@@ -780,6 +858,91 @@ where
         }
     }
 
+    /// Queries the chunks that `provider_id` has to prove to confirm storing each of `file_keys`.
+    ///
+    /// All the files are queried in a single runtime API call, unless the runtime at
+    /// `block_hash` does not support it yet, in which case they are queried one by one.
+    pub(crate) fn query_confirm_chunks_to_prove_for_files(
+        &self,
+        block_hash: H256,
+        provider_id: &StorageProviderId,
+        file_keys: Vec<H256>,
+    ) -> Vec<(
+        H256,
+        Result<Vec<ChunkId>, QueryConfirmChunksToProveForFilesError>,
+    )> {
+        let supports_batching = self
+            .client
+            .runtime_api()
+            .api_version::<dyn FileSystemApi<
+                OpaqueBlock,
+                BackupStorageProviderId,
+                MainStorageProviderId,
+                H256,
+                BlockNumber,
+                ChunkId,
+                BucketId,
+            >>(block_hash)
+            .ok()
+            .flatten()
+            .map_or(false, |version| version >= 2);
+
+        if supports_batching {
+            let chunks_to_prove = match provider_id {
+                StorageProviderId::BackupStorageProvider(bsp_id) => self
+                    .client
+                    .runtime_api()
+                    .query_bsp_confirm_chunks_to_prove_for_files(
+                        block_hash,
+                        *bsp_id,
+                        file_keys.clone(),
+                    ),
+                StorageProviderId::MainStorageProvider(msp_id) => self
+                    .client
+                    .runtime_api()
+                    .query_msp_confirm_chunks_to_prove_for_files(
+                        block_hash,
+                        *msp_id,
+                        file_keys.clone(),
+                    ),
+            };
+
+            return chunks_to_prove.unwrap_or_else(|e| {
+                error!(target: LOG_TARGET, "Runtime API error while querying chunks to prove for {} files: {:?}", file_keys.len(), e);
+                file_keys
+                    .into_iter()
+                    .map(|file_key| {
+                        (
+                            file_key,
+                            Err(QueryConfirmChunksToProveForFilesError::InternalError),
+                        )
+                    })
+                    .collect()
+            });
+        }
+
+        file_keys
+            .into_iter()
+            .map(|file_key| {
+                let chunks_to_prove = match provider_id {
+                    StorageProviderId::BackupStorageProvider(bsp_id) => self
+                        .client
+                        .runtime_api()
+                        .query_bsp_confirm_chunks_to_prove_for_file(block_hash, *bsp_id, file_key)
+                        .map(|result| result.map_err(Into::into)),
+                    StorageProviderId::MainStorageProvider(msp_id) => self
+                        .client
+                        .runtime_api()
+                        .query_msp_confirm_chunks_to_prove_for_file(block_hash, *msp_id, file_key)
+                        .map(|result| result.map_err(Into::into)),
+                }
+                .unwrap_or_else(|_| Err(QueryConfirmChunksToProveForFilesError::InternalError));
+
+                (file_key, chunks_to_prove)
+            })
+            .collect()
+    }
+
     /// Checks if `block_number` is one where this Blockchain Service should emit a `NotifyPeriod` event.
     pub(crate) fn check_for_notify(&self, block_number: &BlockNumber) {
         if let Some(np) = self.notify_period {
@@ -1021,16 +1184,43 @@ where
                 size,
                 peer_ids,
                 expires_at,
-            }) => self.emit(NewStorageRequest {
-                who,
-                file_key: FileKey::from(file_key.as_ref()),
-                bucket_id,
-                location,
-                fingerprint: fingerprint.as_ref().into(),
-                size,
-                user_peer_ids: peer_ids,
-                expires_at,
-            }),
+            }) => {
+                // Keep track of when the storage request expires, to confirm the file in time
+                // if this BSP ends up storing it.
+                if let Some(ManagedProvider::Bsp(bsp_handler)) = &mut self.maybe_managed_provider {
+                    bsp_handler
+                        .storage_request_expirations
+                        .insert(file_key, expires_at);
+                }
+                self.emit(NewStorageRequest {
+                    who,
+                    file_key: FileKey::from(file_key.as_ref()),
+                    bucket_id,
+                    location,
+                    fingerprint: fingerprint.as_ref().into(),
+                    size,
+                    user_peer_ids: peer_ids,
+                    expires_at,
+                })
+            }
+            // A storage request has been revoked by the user that issued it.
+            RuntimeEvent::FileSystem(pallet_file_system::Event::StorageRequestRevoked {
+                file_key,
+            }) => {
+                self.remove_pending_requests_for_file_key(&file_key);
+                self.emit(StorageRequestRevoked {
+                    file_key: file_key.into(),
+                })
+            }
+            // A storage request has expired without being fulfilled.
+            RuntimeEvent::FileSystem(pallet_file_system::Event::StorageRequestExpired {
+                file_key,
+            }) => {
+                self.remove_pending_requests_for_file_key(&file_key);
+                self.emit(StorageRequestExpired {
+                    file_key: file_key.into(),
+                })
+            }
             // A Provider's challenge cycle has been initialised.
             RuntimeEvent::ProofsDealer(
                 pallet_proofs_dealer::Event::NewChallengeCycleInitialised {
@@ -1126,7 +1316,10 @@ where
                             owner,
                             location,
                             new_root,
-                        })
+                        });
+                        if let Some(ManagedProvider::Bsp(_)) = &self.maybe_managed_provider {
+                            self.emit(BspCapacityChanged { bsp_id: sp_id });
+                        }
                     }
                 }
             }
@@ -1134,6 +1327,51 @@ where
         }
     }
 
+    /// Removes any pending confirm storing or MSP respond storage request for `file_key`
+    /// from the persistent queues.
+    ///
+    /// This is used when the storage request for `file_key` is no longer open on-chain (i.e. it
+    /// was revoked or it expired), in which case confirming or responding to it would fail anyway.
+    pub(crate) fn remove_pending_requests_for_file_key(&self, file_key: &H256) {
+        let state_store_context = self.persistent_state.open_rw_context_with_overlay();
+
+        let mut confirm_storing_requests_to_keep = Vec::new();
+        while let Some(request) = state_store_context
+            .pending_confirm_storing_request_deque()
+            .pop_front()
+        {
+            if &request.file_key == file_key {
+                debug!(target: LOG_TARGET, "Dropping pending confirm storing request for file key [{:?}]", file_key);
+            } else {
+                confirm_storing_requests_to_keep.push(request);
+            }
+        }
+        for request in confirm_storing_requests_to_keep {
+            state_store_context
+                .pending_confirm_storing_request_deque()
+                .push_back(request);
+        }
+
+        let mut respond_storage_requests_to_keep = Vec::new();
+        while let Some(request) = state_store_context
+            .pending_msp_respond_storage_request_deque()
+            .pop_front()
+        {
+            if &request.file_key == file_key {
+                debug!(target: LOG_TARGET, "Dropping pending respond storage request for file key [{:?}]", file_key);
+            } else {
+                respond_storage_requests_to_keep.push(request);
+            }
+        }
+        for request in respond_storage_requests_to_keep {
+            state_store_context
+                .pending_msp_respond_storage_request_deque()
+                .push_back(request);
+        }
+
+        state_store_context.commit();
+    }
+
     pub(crate) fn process_common_finality_events(&self, event: RuntimeEvent) {
         match event {
             _ => {}
@@ -1176,6 +1414,17 @@ where
                     })
                 }
             }
+            // A storage request has been fulfilled. The user node doesn't know the owner of the
+            // file from the event, so the tasks filter the file keys they are uploading.
+            RuntimeEvent::FileSystem(pallet_file_system::Event::StorageRequestFulfilled {
+                file_key,
+            }) => {
+                if self.maybe_managed_provider.is_none() {
+                    self.emit(StorageRequestFulfilled {
+                        file_key: file_key.into(),
+                    })
+                }
+            }
             _ => {}
         }
     }
@@ -1,4 +1,4 @@
-use frame_support::{StorageHasher, Twox128};
+use frame_support::{Blake2_128Concat, StorageHasher, Twox128};
 use lazy_static::lazy_static;
 use log::error;
 use sc_network::Multiaddr;
@@ -6,9 +6,11 @@ use std::{str::FromStr, sync::Arc};
 use thiserror::Error;
 
 use codec::Decode;
-use sc_client_api::{backend::StorageProvider, StorageKey};
+use sc_client_api::{backend::StorageProvider, StateBackend, StorageKey};
 use sp_core::H256;
 
+use storage_hub_runtime::Runtime;
+
 use crate::types::{Multiaddresses, ParachainClient, StorageHubEventsVec};
 
 lazy_static! {
@@ -24,6 +26,26 @@ lazy_static! {
         .concat();
         key
     };
+
+    // Static and lazily initialised prefix of the `BackupStorageProviders` map storage keys.
+    static ref BACKUP_STORAGE_PROVIDERS_STORAGE_PREFIX: Vec<u8> = {
+        let key = [
+            Twox128::hash(b"Providers").to_vec(),
+            Twox128::hash(b"BackupStorageProviders").to_vec(),
+        ]
+        .concat();
+        key
+    };
+
+    // Static and lazily initialised prefix of the `StorageRequestBsps` map storage keys.
+    static ref STORAGE_REQUEST_BSPS_STORAGE_PREFIX: Vec<u8> = {
+        let key = [
+            Twox128::hash(b"FileSystem").to_vec(),
+            Twox128::hash(b"StorageRequestBsps").to_vec(),
+        ]
+        .concat();
+        key
+    };
 }
 
 #[derive(Error, Debug)]
@@ -51,6 +73,86 @@ pub fn get_events_at_block(
         .ok_or(EventsRetrievalError::StorageNotFound)
 }
 
+#[derive(Error, Debug)]
+pub enum ForestRootRetrievalError {
+    #[error("State of the block is not available: {0}")]
+    StateNotAvailable(#[from] sp_blockchain::Error),
+    #[error("Failed to read the BSP storage element: {0}")]
+    StorageRetrievalError(String),
+    #[error("Failed to decode the BSP storage element: {0}")]
+    DecodeError(#[from] codec::Error),
+    #[error("BSP not found in the state of the block")]
+    BspNotFound,
+}
+
+/// The key of the BSP with `bsp_id` in the `BackupStorageProviders` map of the Providers pallet.
+fn bsp_storage_key(bsp_id: &H256) -> Vec<u8> {
+    [
+        BACKUP_STORAGE_PROVIDERS_STORAGE_PREFIX.as_slice(),
+        Blake2_128Concat::hash(bsp_id.as_ref()).as_slice(),
+    ]
+    .concat()
+}
+
+/// Get the root of the Forest of the BSP with `bsp_id`, as stored in the state of a given block.
+///
+/// The state is read directly at `block_hash`, so this also works for blocks that are not in the
+/// canonical chain, e.g. the blocks retracted by a reorg, as long as their state is not pruned.
+pub fn get_bsp_forest_root_at_block(
+    client: &Arc<ParachainClient>,
+    block_hash: &H256,
+    bsp_id: &H256,
+) -> Result<H256, ForestRootRetrievalError> {
+    let state = client.state_at(*block_hash)?;
+
+    let storage_key = bsp_storage_key(bsp_id);
+    let raw_bsp = state
+        .storage(&storage_key)
+        .map_err(|e| ForestRootRetrievalError::StorageRetrievalError(e.to_string()))?
+        .ok_or(ForestRootRetrievalError::BspNotFound)?;
+
+    let bsp = pallet_storage_providers::types::BackupStorageProvider::<Runtime>::decode(
+        &mut raw_bsp.as_slice(),
+    )?;
+
+    Ok(bsp.root)
+}
+
+#[derive(Error, Debug)]
+pub enum VolunteerRetrievalError {
+    #[error("State of the block is not available: {0}")]
+    StateNotAvailable(#[from] sp_blockchain::Error),
+    #[error("Failed to read the storage request BSP storage element: {0}")]
+    StorageRetrievalError(String),
+}
+
+/// The key of the BSP with `bsp_id` volunteering for the storage request of `file_key`, in the
+/// `StorageRequestBsps` map of the File System pallet.
+fn storage_request_bsp_storage_key(file_key: &H256, bsp_id: &H256) -> Vec<u8> {
+    [
+        STORAGE_REQUEST_BSPS_STORAGE_PREFIX.as_slice(),
+        Blake2_128Concat::hash(file_key.as_ref()).as_slice(),
+        Blake2_128Concat::hash(bsp_id.as_ref()).as_slice(),
+    ]
+    .concat()
+}
+
+/// Whether the BSP with `bsp_id` is a volunteer for the storage request of `file_key`, as stored
+/// in the state of a given block.
+pub fn is_bsp_volunteer_at_block(
+    client: &Arc<ParachainClient>,
+    block_hash: &H256,
+    file_key: &H256,
+    bsp_id: &H256,
+) -> Result<bool, VolunteerRetrievalError> {
+    let state = client.state_at(*block_hash)?;
+
+    let storage_key = storage_request_bsp_storage_key(file_key, bsp_id);
+    state
+        .exists_storage(&storage_key)
+        .map_err(|e| VolunteerRetrievalError::StorageRetrievalError(e.to_string()))
+}
+
 /// Attempt to convert BoundedVec of BoundedVecs of bytes.
 ///
 /// Returns a list of [`Multiaddr`] objects that have successfully been parsed from the raw bytes.
@@ -79,3 +181,29 @@ pub fn convert_raw_multiaddress_to_multiaddr(raw_multiaddr: &[u8]) -> Option<Mul
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bsp_storage_key_matches_the_runtime_storage_key() {
+        let bsp_id = H256::repeat_byte(7);
+
+        assert_eq!(
+            bsp_storage_key(&bsp_id),
+            pallet_storage_providers::BackupStorageProviders::<Runtime>::hashed_key_for(bsp_id)
+        );
+    }
+
+    #[test]
+    fn storage_request_bsp_storage_key_matches_the_runtime_storage_key() {
+        let file_key = H256::repeat_byte(3);
+        let bsp_id = H256::repeat_byte(7);
+
+        assert_eq!(
+            storage_request_bsp_storage_key(&file_key, &bsp_id),
+            pallet_file_system::StorageRequestBsps::<Runtime>::hashed_key_for(file_key, bsp_id)
+        );
+    }
+}
@@ -1,3 +1,5 @@
 pub mod blockchain_utils;
 pub mod consts;
 pub mod types;
+pub mod upload_progress;
+pub mod user_uploads;
@@ -1,5 +1,5 @@
 use std::{
-    fmt::Debug,
+    fmt::{self, Debug, Display},
     sync::atomic::{AtomicU64, Ordering},
 };
 
@@ -8,13 +8,14 @@ use frame_system::EventRecord;
 use sc_executor::WasmExecutor;
 use sc_service::TFullClient;
 pub use shp_constants::{FILE_CHUNK_SIZE, FILE_SIZE_TO_CHALLENGES, H_LENGTH};
-pub use shp_file_metadata::{Chunk, ChunkId, ChunkWithId, Leaf};
+pub use shp_file_key_verifier::types::ProvenFileKeyError;
+pub use shp_file_metadata::{Chunk, ChunkId, ChunkWithId, FileMetadataError, Leaf};
 use shp_traits::CommitmentVerifier;
 use sp_core::Hasher;
 use sp_runtime::{traits::Block as BlockT, KeyTypeId};
 use sp_std::collections::btree_map::BTreeMap;
 use sp_trie::CompactProof;
-use storage_hub_runtime::{apis::RuntimeApi, opaque::Block, Runtime};
+use storage_hub_runtime::{apis::RuntimeApi, Runtime};
 use trie_db::TrieLayout;
 
 /// Size of each batch in bytes (2 MiB)
@@ -22,6 +23,10 @@ use trie_db::TrieLayout;
 /// (request-response round-trip).
 pub const BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE: usize = 2 * 1024 * 1024;
 
+/// Maximum number of chunks in a batch of [`BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE`] bytes.
+pub const BATCH_CHUNK_FILE_TRANSFER_MAX_CHUNKS: usize =
+    BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE / FILE_CHUNK_SIZE as usize;
+
 /// The hash type of trie node keys
 pub type HashT<T> = <T as TrieLayout>::Hash;
 pub type HasherOutT<T> = <<T as TrieLayout>::Hash as Hasher>::Out;
@@ -78,6 +83,8 @@ pub type BlockHash = <OpaqueBlock as BlockT>::Hash;
 pub type PeerId = pallet_file_system::types::PeerId<Runtime>;
 pub type MaxBatchConfirmStorageRequests =
     <Runtime as pallet_file_system::Config>::MaxBatchConfirmStorageRequests;
+pub type ChallengeTicksTolerance =
+    <Runtime as pallet_proofs_dealer::Config>::ChallengeTicksTolerance;
 
 /// Type alias for the events vector.
 ///
@@ -102,7 +109,70 @@ type HostFunctions = (
 );
 
 pub type ParachainExecutor = WasmExecutor<HostFunctions>;
-pub type ParachainClient = TFullClient<Block, RuntimeApi, ParachainExecutor>;
+pub type ParachainClient = TFullClient<OpaqueBlock, RuntimeApi, ParachainExecutor>;
+
+/// A block number, as opposed to a [`Tick`].
+///
+/// Ticks advance with blocks, but not while the chain is considered spammed, so a tick can be
+/// behind the block number. Waiting for a tick as if it were a block (or vice versa) is a timing
+/// bug, so the two are wrapped in different types that cannot be used in place of each other:
+///
+/// ```compile_fail
+/// use shc_common::types::{Block, Tick};
+///
+/// fn wait_for_block(_block: Block) {}
+///
+/// wait_for_block(Tick(1));
+/// ```
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Encode, Decode)]
+pub struct Block(pub BlockNumber);
+
+impl Block {
+    pub fn saturating_add(self, blocks: BlockNumber) -> Self {
+        Block(self.0.saturating_add(blocks))
+    }
+
+    pub fn saturating_sub(self, blocks: BlockNumber) -> Self {
+        Block(self.0.saturating_sub(blocks))
+    }
+}
+
+impl Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// A tick number, as opposed to a [`Block`] number.
+///
+/// Ticks are the unit of time of the proofs dealer and of the deadlines based on it, such as
+/// the earliest tick at which a BSP can volunteer for a file.
+///
+/// ```compile_fail
+/// use shc_common::types::{Block, Tick};
+///
+/// fn wait_for_tick(_tick: Tick) {}
+///
+/// wait_for_tick(Block(1));
+/// ```
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Encode, Decode)]
+pub struct Tick(pub TickNumber);
+
+impl Tick {
+    pub fn saturating_add(self, ticks: TickNumber) -> Self {
+        Tick(self.0.saturating_add(ticks))
+    }
+
+    pub fn saturating_sub(self, ticks: TickNumber) -> Self {
+        Tick(self.0.saturating_sub(ticks))
+    }
+}
+
+impl Display for Tick {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tick {}", self.0)
+    }
+}
 
 /// The type of key used for [`BlockchainService`]` operations.
 pub const BCSV_KEY_TYPE: KeyTypeId = KeyTypeId(*b"bcsv");
@@ -210,3 +280,94 @@ impl UploadRequestId {
         UploadRequestId(COUNTER.fetch_add(1, Ordering::SeqCst))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_and_tick_arithmetic_saturates() {
+        assert_eq!(Block(5).saturating_add(3), Block(8));
+        assert_eq!(Block(5).saturating_sub(6), Block(0));
+        assert_eq!(
+            Block(BlockNumber::MAX).saturating_add(1),
+            Block(BlockNumber::MAX)
+        );
+        assert_eq!(Tick(5).saturating_add(3), Tick(8));
+        assert_eq!(Tick(5).saturating_sub(6), Tick(0));
+        assert_eq!(
+            Tick(TickNumber::MAX).saturating_add(1),
+            Tick(TickNumber::MAX)
+        );
+    }
+
+    #[test]
+    fn blocks_and_ticks_are_displayed_differently() {
+        assert_eq!(Block(42).to_string(), "#42");
+        assert_eq!(Tick(42).to_string(), "tick 42");
+    }
+
+    #[test]
+    fn blocks_and_ticks_are_ordered_by_their_number() {
+        assert!(Block(1) < Block(2));
+        assert_eq!(Block(1).max(Block(2)), Block(2));
+        assert!(Tick(1) < Tick(2));
+        assert_eq!(Tick(3).max(Tick(2)), Tick(3));
+    }
+
+    fn metadata_of_size(file_size: u64) -> FileMetadata {
+        FileMetadata::validated(
+            vec![1u8; 32],
+            vec![2u8; 32],
+            b"location".to_vec(),
+            file_size,
+            Fingerprint::from([3u8; 32]),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn chunks_count_rounds_up_to_whole_chunks() {
+        assert_eq!(metadata_of_size(1).chunks_count(), 1);
+        assert_eq!(metadata_of_size(FILE_CHUNK_SIZE - 1).chunks_count(), 1);
+        assert_eq!(metadata_of_size(FILE_CHUNK_SIZE).chunks_count(), 1);
+        assert_eq!(metadata_of_size(FILE_CHUNK_SIZE + 1).chunks_count(), 2);
+        assert_eq!(metadata_of_size(FILE_CHUNK_SIZE * 3).chunks_count(), 3);
+
+        // The last chunk of a file that is an exact multiple of the chunk size is a full chunk.
+        let metadata = metadata_of_size(FILE_CHUNK_SIZE * 3);
+        assert_eq!(metadata.last_chunk_id(), ChunkId::new(2));
+        assert_eq!(metadata.chunk_size_at(2), Ok(FILE_CHUNK_SIZE as usize));
+        let metadata = metadata_of_size(FILE_CHUNK_SIZE * 3 + 1);
+        assert_eq!(metadata.last_chunk_id(), ChunkId::new(3));
+        assert_eq!(metadata.chunk_size_at(3), Ok(1));
+    }
+
+    #[test]
+    fn chunks_to_check_grows_with_the_file_size_up_to_a_maximum() {
+        assert_eq!(metadata_of_size(1).chunks_to_check(), 1);
+        assert_eq!(
+            metadata_of_size(FILE_SIZE_TO_CHALLENGES).chunks_to_check(),
+            1
+        );
+        assert_eq!(
+            metadata_of_size(FILE_SIZE_TO_CHALLENGES + 1).chunks_to_check(),
+            2
+        );
+        assert_eq!(
+            metadata_of_size(FILE_SIZE_TO_CHALLENGES * 2).chunks_to_check(),
+            2
+        );
+
+        // Capped at 10 chunks, however big the file is.
+        assert_eq!(
+            metadata_of_size(FILE_SIZE_TO_CHALLENGES * 10).chunks_to_check(),
+            10
+        );
+        assert_eq!(
+            metadata_of_size(FILE_SIZE_TO_CHALLENGES * 10 + 1).chunks_to_check(),
+            10
+        );
+        assert_eq!(metadata_of_size(u64::MAX).chunks_to_check(), 10);
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use sp_core::H256;
+
+/// How long the progress of an upload is kept after it reached a terminal state.
+pub const DEFAULT_UPLOAD_PROGRESS_RETENTION: Duration = Duration::from_secs(10 * 60);
+
+/// The state of an upload to this provider.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum UploadState {
+    /// The provider is interested in the file but hasn't received any chunk yet.
+    AwaitingVolunteer,
+    /// The provider is receiving chunks of the file.
+    Receiving,
+    /// All chunks of the file have been received.
+    Complete,
+    /// The storage of the file has been confirmed on-chain.
+    Confirmed,
+    /// The provider rejected or stopped storing the file.
+    Rejected,
+}
+
+impl UploadState {
+    /// Whether the upload can no longer progress.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, UploadState::Confirmed | UploadState::Rejected)
+    }
+}
+
+/// The progress of an upload to this provider, as exposed through RPC.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UploadProgress {
+    pub expected_chunks: u64,
+    pub received_chunks: u64,
+    /// Unix timestamp (in milliseconds) of the last chunk received, if any.
+    pub last_chunk_at: Option<u64>,
+    pub state: UploadState,
+}
+
+struct UploadProgressEntry {
+    progress: UploadProgress,
+    /// When the upload reached a terminal state, used to garbage collect the entry.
+    terminal_since: Option<Instant>,
+}
+
+/// Registry of the progress of the uploads to this provider, keyed by file key.
+///
+/// It is shared between the upload tasks, which update it, and the RPC, which reads it.
+/// Entries are removed [`DEFAULT_UPLOAD_PROGRESS_RETENTION`] after they reach a terminal state.
+#[derive(Clone)]
+pub struct UploadProgressRegistry {
+    entries: Arc<RwLock<HashMap<H256, UploadProgressEntry>>>,
+    retention: Duration,
+}
+
+impl Default for UploadProgressRegistry {
+    fn default() -> Self {
+        Self::new(DEFAULT_UPLOAD_PROGRESS_RETENTION)
+    }
+}
+
+impl UploadProgressRegistry {
+    pub fn new(retention: Duration) -> Self {
+        Self {
+            entries: Arc::new(RwLock::new(HashMap::new())),
+            retention,
+        }
+    }
+
+    /// Starts tracking an upload of `expected_chunks` chunks for `file_key`.
+    ///
+    /// Any previous progress tracked for `file_key` is reset.
+    pub fn register(&self, file_key: H256, expected_chunks: u64) {
+        let mut entries = self.entries.write().expect("Upload progress lock poisoned");
+        Self::prune(&mut entries, self.retention);
+        entries.insert(
+            file_key,
+            UploadProgressEntry {
+                progress: UploadProgress {
+                    expected_chunks,
+                    received_chunks: 0,
+                    last_chunk_at: None,
+                    state: UploadState::AwaitingVolunteer,
+                },
+                terminal_since: None,
+            },
+        );
+    }
+
+    /// Records that `received_chunks` chunks of `file_key` are now stored.
+    ///
+    /// The upload moves to [`UploadState::Receiving`], or [`UploadState::Complete`] once all
+    /// expected chunks are received. Untracked or terminal uploads are left untouched.
+    pub fn record_chunks(&self, file_key: &H256, received_chunks: u64) {
+        let mut entries = self.entries.write().expect("Upload progress lock poisoned");
+        if let Some(entry) = entries.get_mut(file_key) {
+            if entry.progress.state.is_terminal() {
+                return;
+            }
+            entry.progress.received_chunks = received_chunks;
+            entry.progress.last_chunk_at = Some(now_millis());
+            entry.progress.state = if received_chunks >= entry.progress.expected_chunks {
+                UploadState::Complete
+            } else {
+                UploadState::Receiving
+            };
+        }
+    }
+
+    /// Sets the state of the upload of `file_key`, if it is tracked.
+    pub fn set_state(&self, file_key: &H256, state: UploadState) {
+        let mut entries = self.entries.write().expect("Upload progress lock poisoned");
+        if let Some(entry) = entries.get_mut(file_key) {
+            entry.progress.state = state;
+            entry.terminal_since = state.is_terminal().then(Instant::now);
+        }
+    }
+
+    /// Get the progress of the upload of `file_key`, if it is tracked.
+    pub fn get(&self, file_key: &H256) -> Option<UploadProgress> {
+        let mut entries = self.entries.write().expect("Upload progress lock poisoned");
+        Self::prune(&mut entries, self.retention);
+        entries.get(file_key).map(|entry| entry.progress.clone())
+    }
+
+    /// Number of tracked uploads that haven't received all their chunks yet.
+    pub fn in_progress_count(&self) -> usize {
+        self.entries
+            .read()
+            .expect("Upload progress lock poisoned")
+            .values()
+            .filter(|entry| {
+                matches!(
+                    entry.progress.state,
+                    UploadState::AwaitingVolunteer | UploadState::Receiving
+                )
+            })
+            .count()
+    }
+
+    /// Removes the entries which reached a terminal state more than `retention` ago.
+    fn prune(entries: &mut HashMap<H256, UploadProgressEntry>, retention: Duration) {
+        entries.retain(|_, entry| match entry.terminal_since {
+            Some(terminal_since) => terminal_since.elapsed() < retention,
+            None => true,
+        });
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upload_progress_follows_received_chunks() {
+        let registry = UploadProgressRegistry::default();
+        let file_key = H256::from_low_u64_be(1);
+
+        assert_eq!(registry.get(&file_key), None);
+
+        registry.register(file_key, 3);
+        let progress = registry.get(&file_key).unwrap();
+        assert_eq!(progress.state, UploadState::AwaitingVolunteer);
+        assert_eq!(progress.received_chunks, 0);
+        assert_eq!(progress.last_chunk_at, None);
+
+        registry.record_chunks(&file_key, 2);
+        let progress = registry.get(&file_key).unwrap();
+        assert_eq!(progress.state, UploadState::Receiving);
+        assert_eq!(progress.received_chunks, 2);
+        assert!(progress.last_chunk_at.is_some());
+
+        registry.record_chunks(&file_key, 3);
+        assert_eq!(
+            registry.get(&file_key).unwrap().state,
+            UploadState::Complete
+        );
+
+        registry.set_state(&file_key, UploadState::Confirmed);
+        assert_eq!(
+            registry.get(&file_key).unwrap().state,
+            UploadState::Confirmed
+        );
+
+        // Chunks received after the upload is terminal are ignored.
+        registry.record_chunks(&file_key, 1);
+        assert_eq!(registry.get(&file_key).unwrap().received_chunks, 3);
+    }
+
+    #[test]
+    fn only_uploads_missing_chunks_are_in_progress() {
+        let registry = UploadProgressRegistry::default();
+        let (awaiting, receiving, complete, rejected) = (
+            H256::from_low_u64_be(1),
+            H256::from_low_u64_be(2),
+            H256::from_low_u64_be(3),
+            H256::from_low_u64_be(4),
+        );
+
+        for file_key in [awaiting, receiving, complete, rejected] {
+            registry.register(file_key, 2);
+        }
+        registry.record_chunks(&receiving, 1);
+        registry.record_chunks(&complete, 2);
+        registry.set_state(&rejected, UploadState::Rejected);
+
+        assert_eq!(registry.in_progress_count(), 2);
+    }
+
+    #[test]
+    fn untracked_uploads_are_ignored() {
+        let registry = UploadProgressRegistry::default();
+        let file_key = H256::from_low_u64_be(1);
+
+        registry.record_chunks(&file_key, 1);
+        registry.set_state(&file_key, UploadState::Rejected);
+
+        assert_eq!(registry.get(&file_key), None);
+    }
+
+    #[test]
+    fn terminal_uploads_are_garbage_collected_after_retention() {
+        let registry = UploadProgressRegistry::new(Duration::from_millis(50));
+        let rejected = H256::from_low_u64_be(1);
+        let receiving = H256::from_low_u64_be(2);
+
+        registry.register(rejected, 2);
+        registry.register(receiving, 2);
+        registry.record_chunks(&receiving, 1);
+        registry.set_state(&rejected, UploadState::Rejected);
+
+        // Still visible right after reaching the terminal state.
+        assert!(registry.get(&rejected).is_some());
+
+        std::thread::sleep(Duration::from_millis(100));
+
+        assert_eq!(registry.get(&rejected), None);
+        // Uploads that did not reach a terminal state are kept.
+        assert!(registry.get(&receiving).is_some());
+    }
+}
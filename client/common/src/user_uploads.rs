@@ -0,0 +1,97 @@
+use std::sync::{Arc, Mutex};
+
+use sp_core::H256;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::types::FileMetadata;
+
+/// A file loaded in the file storage of a user node, waiting for its storage request to be issued.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserUploadRequest {
+    pub file_key: H256,
+    pub file_metadata: FileMetadata,
+    /// Number of BSPs that should store the file. If `None`, the standard replication target of
+    /// the runtime is used.
+    pub replication_target: Option<u32>,
+}
+
+/// Queue of the files a user node should upload to the network.
+///
+/// It is shared between the RPC, which pushes the files loaded in the file storage, and the
+/// user tasks, which take the receiving end once to issue the storage requests.
+#[derive(Clone)]
+pub struct UserUploadQueue {
+    sender: UnboundedSender<UserUploadRequest>,
+    receiver: Arc<Mutex<Option<UnboundedReceiver<UserUploadRequest>>>>,
+}
+
+impl Default for UserUploadQueue {
+    fn default() -> Self {
+        let (sender, receiver) = unbounded_channel();
+        Self {
+            sender,
+            receiver: Arc::new(Mutex::new(Some(receiver))),
+        }
+    }
+}
+
+impl UserUploadQueue {
+    /// Queues `request` to be uploaded.
+    ///
+    /// Returns `false` if nothing is consuming the queue anymore, in which case the request is
+    /// dropped.
+    pub fn push(&self, request: UserUploadRequest) -> bool {
+        self.sender.send(request).is_ok()
+    }
+
+    /// Takes the receiving end of the queue.
+    ///
+    /// Only the first call gets it, so that each request is handled by a single consumer.
+    pub fn take_receiver(&self) -> Option<UnboundedReceiver<UserUploadRequest>> {
+        self.receiver
+            .lock()
+            .expect("User upload queue lock poisoned")
+            .take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(location: &str) -> UserUploadRequest {
+        let file_metadata = FileMetadata::new(
+            [1u8; 32].to_vec(),
+            [2u8; 32].to_vec(),
+            location.as_bytes().to_vec(),
+            1024,
+            [3u8; 32].into(),
+        )
+        .expect("Valid file metadata");
+        UserUploadRequest {
+            file_key: H256::from_low_u64_be(location.len() as u64),
+            file_metadata,
+            replication_target: None,
+        }
+    }
+
+    #[test]
+    fn requests_are_received_in_order_by_a_single_consumer() {
+        let queue = UserUploadQueue::default();
+        let rpc_handle = queue.clone();
+
+        assert!(rpc_handle.push(request("a")));
+        assert!(rpc_handle.push(request("bb")));
+
+        let mut receiver = queue.take_receiver().expect("Receiver not taken yet");
+        assert!(rpc_handle.take_receiver().is_none());
+
+        assert_eq!(receiver.try_recv().ok(), Some(request("a")));
+        assert_eq!(receiver.try_recv().ok(), Some(request("bb")));
+        assert!(receiver.try_recv().is_err());
+
+        // Once the consumer is gone, requests are no longer accepted.
+        drop(receiver);
+        assert!(!rpc_handle.push(request("ccc")));
+    }
+}
@@ -0,0 +1,115 @@
+//! Proofs that a File Storage holds all the files of a bucket, e.g. for an MSP taking over a
+//! bucket to show that it has every file of it before the move is accepted.
+//!
+//! A [`BucketProof`] holds the metadata of every file of the bucket, along with a proof of two
+//! chunks of each of them against its fingerprint: one derived from a seed chosen by the verifier
+//! (see [`challenged_chunk`]), so that the prover can't get away with keeping only some chunks
+//! of each file, and the last one, which binds the size of the file. The forest of a bucket has
+//! exactly one leaf per file (its file key, with its encoded metadata as value), so the verifier
+//! rebuilds it from the metadata in the proof, and checks its root against the root of the bucket.
+
+use std::collections::BTreeMap;
+
+use codec::{Decode, Encode};
+use hash_db::Hasher;
+use sp_trie::{MemoryDB, TrieLayout, TrieMut};
+use trie_db::TrieDBMutBuilder;
+
+use shc_common::types::{
+    ChunkId, FileKeyProof, FileMetadata, HashT, HasherOutT, ProvenFileKeyError, H_LENGTH,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BucketProofError {
+    /// A file of the proof is in another bucket.
+    FileOfAnotherBucket,
+    /// A file is proven more than once.
+    DuplicateFile,
+    /// The proof of a file does not match its fingerprint.
+    InvalidFileKeyProof(ProvenFileKeyError),
+    /// The proof of a file does not prove its challenged chunk or its last chunk.
+    ChunkNotProven,
+    /// The files of the proof are not the files of the bucket, as the forest built from them
+    /// does not have the root of the bucket.
+    ForestRootMismatch,
+}
+
+/// Proof that all the files of a bucket are held, generated with
+/// [`FileStorage::generate_bucket_proof`](crate::traits::FileStorage::generate_bucket_proof).
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct BucketProof {
+    pub bucket_id: [u8; 32],
+    /// A proof of the challenged chunk and the last chunk of each file of the bucket, in ascending
+    /// order of file key.
+    pub file_key_proofs: Vec<FileKeyProof>,
+}
+
+/// The chunk of the file with `file_key` and `metadata` challenged by `seed`, as the hash of the
+/// seed and the file key, modulo the number of chunks of the file.
+pub fn challenged_chunk<T: TrieLayout>(
+    seed: &[u8; 32],
+    file_key: &HasherOutT<T>,
+    metadata: &FileMetadata,
+) -> ChunkId {
+    let challenge = HashT::<T>::hash(&[seed.as_slice(), file_key.as_ref()].concat());
+    ChunkId::from_challenge(challenge.as_ref(), metadata.chunks_count())
+}
+
+impl BucketProof {
+    /// Verifies the proof against `forest_root`, the root of the forest of the bucket, and the
+    /// `seed` the proof was requested with.
+    ///
+    /// Returns the keys of the files of the bucket, in ascending order.
+    pub fn verify<T: TrieLayout>(
+        &self,
+        forest_root: &HasherOutT<T>,
+        seed: &[u8; 32],
+    ) -> Result<Vec<HasherOutT<T>>, BucketProofError>
+    where
+        HasherOutT<T>: TryFrom<[u8; H_LENGTH]>,
+    {
+        let mut files = BTreeMap::new();
+        for file_key_proof in &self.file_key_proofs {
+            let metadata = &file_key_proof.file_metadata;
+            if metadata.bucket_id().as_slice() != self.bucket_id.as_slice() {
+                return Err(BucketProofError::FileOfAnotherBucket);
+            }
+
+            let proven = file_key_proof
+                .proven::<T>()
+                .map_err(BucketProofError::InvalidFileKeyProof)?;
+            let file_key = metadata.file_key::<HashT<T>>();
+            for chunk_id in [
+                challenged_chunk::<T>(seed, &file_key, metadata),
+                metadata.last_chunk_id(),
+            ] {
+                if !proven.iter().any(|leaf| leaf.key == chunk_id) {
+                    return Err(BucketProofError::ChunkNotProven);
+                }
+            }
+
+            if files.insert(file_key, metadata.encode()).is_some() {
+                return Err(BucketProofError::DuplicateFile);
+            }
+        }
+
+        // Built as the Forest Storage does, so that an empty bucket has the same root.
+        let (mut memdb, mut root) = MemoryDB::<HashT<T>>::default_with_root();
+        {
+            let mut forest = TrieDBMutBuilder::<T>::from_existing(&mut memdb, &mut root).build();
+            for (file_key, metadata) in &files {
+                // Inserting in an in-memory trie can only fail on missing nodes, and every node
+                // of this trie was created by the previous insertions.
+                forest
+                    .insert(file_key.as_ref(), metadata)
+                    .expect("Trie built in memory has all its nodes; qed");
+            }
+        }
+
+        if &root != forest_root {
+            return Err(BucketProofError::ForestRootMismatch);
+        }
+
+        Ok(files.into_keys().collect())
+    }
+}
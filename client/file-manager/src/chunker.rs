@@ -0,0 +1,193 @@
+//! Splits files into [`FILE_CHUNK_SIZE`] chunks and builds their file trie.
+//!
+//! This is the canonical chunking of StorageHub: the root of the resulting trie is the
+//! fingerprint of the file, so every file loaded by the node (and every client computing a file
+//! key through RPC) should go through it.
+
+use std::io::{self, Read};
+
+use log::debug;
+use shc_common::types::{ChunkId, FileMetadata, FileMetadataError, FILE_CHUNK_SIZE};
+use sp_trie::TrieLayout;
+
+use crate::{
+    in_memory::InMemoryFileDataTrie,
+    traits::{FileDataTrie, FileStorageError},
+    LOG_TARGET,
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ChunkerError {
+    #[error("Failed to read file: {0}")]
+    Read(#[from] io::Error),
+    #[error("Failed to write file chunk: {0:?}")]
+    Write(FileStorageError),
+    #[error("File is empty")]
+    FileIsEmpty,
+    #[error("Invalid file metadata: {0:?}")]
+    InvalidFileMetadata(FileMetadataError),
+}
+
+/// Reads `file` until EOF in chunks of [`FILE_CHUNK_SIZE`] bytes, writing them into
+/// `file_data_trie` with increasing [`ChunkId`]s starting from 0.
+///
+/// Returns the number of bytes read, i.e. the size of the file. Only the last chunk can be
+/// shorter than [`FILE_CHUNK_SIZE`].
+pub fn write_chunks_from_reader<T: TrieLayout>(
+    file_data_trie: &mut impl FileDataTrie<T>,
+    mut file: impl Read,
+) -> Result<u64, ChunkerError> {
+    // A chunk id is simply an integer index.
+    let mut chunk_id: u64 = 0;
+    let mut file_size: u64 = 0;
+
+    // Read file in chunks of [`FILE_CHUNK_SIZE`] into buffer then push buffer into a vector.
+    // Loops until EOF or until some error that is NOT `ErrorKind::Interrupted` is found.
+    // If `ErrorKind::Interrupted` is found, the operation is simply retried, as per
+    // https://doc.rust-lang.org/std/io/trait.Read.html#errors-1
+    loop {
+        let mut chunk = Vec::with_capacity(FILE_CHUNK_SIZE as usize);
+        let bytes_read = Read::by_ref(&mut file)
+            .take(FILE_CHUNK_SIZE)
+            .read_to_end(&mut chunk)?;
+
+        // Reached EOF.
+        if bytes_read == 0 {
+            debug!(target: LOG_TARGET, "Finished reading file");
+            return Ok(file_size);
+        }
+
+        debug!(target: LOG_TARGET, "Read {} bytes from file", bytes_read);
+
+        file_data_trie
+            .write_chunk(&ChunkId::new(chunk_id), &chunk)
+            .map_err(ChunkerError::Write)?;
+        chunk_id += 1;
+        file_size += bytes_read as u64;
+    }
+}
+
+/// Chunks `file` and returns its [`FileMetadata`], as owned by `owner` at `location` in
+/// `bucket_id`, without storing anything.
+///
+/// The file trie is built in memory, and dropped once its root (the fingerprint of the file) is
+/// computed. The file key is then given by [`FileMetadata::file_key`].
+pub fn compute_file_metadata<T: TrieLayout + 'static>(
+    file: impl Read,
+    owner: Vec<u8>,
+    bucket_id: Vec<u8>,
+    location: Vec<u8>,
+) -> Result<FileMetadata, ChunkerError> {
+    let mut file_data_trie = InMemoryFileDataTrie::<T>::new();
+    let file_size = write_chunks_from_reader(&mut file_data_trie, file)?;
+
+    if file_size == 0 {
+        return Err(ChunkerError::FileIsEmpty);
+    }
+
+    FileMetadata::new(
+        owner,
+        bucket_id,
+        location,
+        file_size,
+        file_data_trie.get_root().as_ref().into(),
+    )
+    .map_err(ChunkerError::InvalidFileMetadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shc_common::types::{HashT, StorageProofsMerkleTrieLayout};
+    use sp_core::H256;
+
+    type Layout = StorageProofsMerkleTrieLayout;
+
+    fn file_of(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn computed_file_key_matches_file_metadata_file_key() {
+        let file = file_of(FILE_CHUNK_SIZE as usize * 3 + 17);
+        let owner = vec![1u8; 32];
+        let bucket_id = vec![2u8; 32];
+        let location = b"dir/file.txt".to_vec();
+
+        let computed = compute_file_metadata::<Layout>(
+            file.as_slice(),
+            owner.clone(),
+            bucket_id.clone(),
+            location.clone(),
+        )
+        .unwrap();
+
+        // Build the same file trie chunk by chunk, and derive the file key from its metadata.
+        let mut file_data_trie = InMemoryFileDataTrie::<Layout>::new();
+        for (chunk_id, chunk) in file.chunks(FILE_CHUNK_SIZE as usize).enumerate() {
+            file_data_trie
+                .write_chunk(&ChunkId::new(chunk_id as u64), &chunk.to_vec())
+                .unwrap();
+        }
+        let expected = FileMetadata::new(
+            owner,
+            bucket_id,
+            location,
+            file.len() as u64,
+            file_data_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+
+        assert_eq!(computed, expected);
+        assert_eq!(
+            computed.file_key::<HashT<Layout>>(),
+            expected.file_key::<HashT<Layout>>()
+        );
+        assert_eq!(computed.chunks_count(), 4);
+    }
+
+    #[test]
+    fn file_key_depends_on_owner_bucket_and_location() {
+        let file = file_of(100);
+        let file_key = |owner: u8, bucket: u8, location: &[u8]| -> H256 {
+            compute_file_metadata::<Layout>(
+                file.as_slice(),
+                vec![owner; 32],
+                vec![bucket; 32],
+                location.to_vec(),
+            )
+            .unwrap()
+            .file_key::<HashT<Layout>>()
+        };
+
+        let base = file_key(1, 2, b"file");
+        assert_ne!(base, file_key(3, 2, b"file"));
+        assert_ne!(base, file_key(1, 3, b"file"));
+        assert_ne!(base, file_key(1, 2, b"other"));
+        assert_eq!(base, file_key(1, 2, b"file"));
+    }
+
+    #[test]
+    fn files_are_split_in_chunks_of_file_chunk_size() {
+        for size in [1, FILE_CHUNK_SIZE as usize, FILE_CHUNK_SIZE as usize + 1] {
+            let mut file_data_trie = InMemoryFileDataTrie::<Layout>::new();
+            let file_size =
+                write_chunks_from_reader(&mut file_data_trie, file_of(size).as_slice()).unwrap();
+
+            assert_eq!(file_size, size as u64);
+            let expected_chunks = size.div_ceil(FILE_CHUNK_SIZE as usize);
+            assert_eq!(file_data_trie.chunk_ids().unwrap().len(), expected_chunks);
+        }
+    }
+
+    #[test]
+    fn empty_files_have_no_metadata() {
+        let result = compute_file_metadata::<Layout>(
+            io::empty(),
+            vec![1u8; 32],
+            vec![2u8; 32],
+            b"file".to_vec(),
+        );
+        assert!(matches!(result, Err(ChunkerError::FileIsEmpty)));
+    }
+}
@@ -3,7 +3,7 @@ use std::io;
 use shc_common::types::HasherOutT;
 use trie_db::CError;
 
-use crate::traits::{FileStorageError, FileStorageWriteError};
+use crate::traits::FileStorageError;
 
 pub(crate) type ErrorT<T> = Error<HasherOutT<T>, CError<T>>;
 
@@ -13,8 +13,6 @@ type BoxTrieError<H, CodecError> = Box<trie_db::TrieError<H, CodecError>>;
 pub enum Error<H, CodecError> {
     #[error("File storage error: {0:?}")]
     FileStorage(FileStorageError),
-    #[error("File storage write error: {0:?}")]
-    FileStorageWrite(FileStorageWriteError),
     #[error(transparent)]
     Codec(#[from] codec::Error),
     #[error(transparent)]
@@ -35,12 +33,6 @@ impl<H, CodecError> From<FileStorageError> for Error<H, CodecError> {
     }
 }
 
-impl<H, CodecError> From<FileStorageWriteError> for Error<H, CodecError> {
-    fn from(x: FileStorageWriteError) -> Self {
-        Error::FileStorageWrite(x)
-    }
-}
-
 pub fn other_io_error(err: String) -> io::Error {
     io::Error::new(io::ErrorKind::Other, err)
 }
@@ -0,0 +1,42 @@
+//! Verification of a [`FileKeyProof`] against the metadata of the file it is meant to prove,
+//! without any File Storage, e.g. for a light client or an auditor that does not store files.
+
+use sp_trie::TrieLayout;
+
+use shc_common::types::{
+    Chunk, ChunkId, FileKeyProof, FileMetadata, HashT, HasherOutT, ProvenFileKeyError, H_LENGTH,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileKeyProofError {
+    /// The proof is for another file, i.e. the file key of its metadata is not the file key of
+    /// the file. Any mismatch of the owner, bucket, location, size or fingerprint leads to it.
+    FileKeyMismatch,
+    /// The proof does not match its fingerprint.
+    InvalidProof(ProvenFileKeyError),
+}
+
+/// Verifies that `proof` proves chunks of the file with `metadata`, as obtained independently of
+/// the proof (e.g. from the chain).
+///
+/// Returns the proven chunks, along with their IDs.
+pub fn verify_file_key_proof<T: TrieLayout>(
+    proof: &FileKeyProof,
+    metadata: &FileMetadata,
+) -> Result<Vec<(ChunkId, Chunk)>, FileKeyProofError>
+where
+    HasherOutT<T>: TryFrom<[u8; H_LENGTH]>,
+{
+    if proof.file_metadata.file_key::<HashT<T>>() != metadata.file_key::<HashT<T>>() {
+        return Err(FileKeyProofError::FileKeyMismatch);
+    }
+
+    let proven = proof
+        .proven::<T>()
+        .map_err(FileKeyProofError::InvalidProof)?;
+
+    Ok(proven
+        .into_iter()
+        .map(|leaf| (leaf.key, leaf.data))
+        .collect())
+}
@@ -1,7 +1,14 @@
 use codec::{Decode, Encode};
 use log::{error, info};
 use sp_trie::{recorder::Recorder, MemoryDB, Trie, TrieDBBuilder, TrieLayout, TrieMut};
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard, PoisonError, RwLock,
+    },
+    time::Duration,
+};
 use trie_db::TrieDBMutBuilder;
 
 use shc_common::types::{
@@ -9,11 +16,12 @@ use shc_common::types::{
 };
 
 use crate::{
+    age_since, check_chunks_per_proof, chunk_ids_in_trie,
     traits::{
-        ExcludeType, FileDataTrie, FileStorage, FileStorageError, FileStorageWriteError,
-        FileStorageWriteOutcome,
+        ExcludeType, FileDataTrie, FileStorage, FileStorageError, FileStorageStats,
+        FileStorageWriteOutcome, FileVerification, IncompleteFile, DEFAULT_MAX_CHUNKS_PER_PROOF,
     },
-    LOG_TARGET,
+    unix_timestamp_now, LOG_TARGET,
 };
 
 pub struct InMemoryFileDataTrie<T: TrieLayout + 'static> {
@@ -22,7 +30,7 @@ pub struct InMemoryFileDataTrie<T: TrieLayout + 'static> {
 }
 
 impl<T: TrieLayout + 'static> InMemoryFileDataTrie<T> {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         let (memdb, root) = MemoryDB::<HashT<T>>::default_with_root();
 
         Self { root, memdb }
@@ -92,11 +100,7 @@ impl<T: TrieLayout> FileDataTrie<T> for InMemoryFileDataTrie<T> {
         Ok(decoded_chunk.data)
     }
 
-    fn write_chunk(
-        &mut self,
-        chunk_id: &ChunkId,
-        data: &Chunk,
-    ) -> Result<(), FileStorageWriteError> {
+    fn write_chunk(&mut self, chunk_id: &ChunkId, data: &Chunk) -> Result<(), FileStorageError> {
         let mut trie = if self.memdb.keys().is_empty() {
             // If the database is empty, create a new trie.
             TrieDBMutBuilder::<T>::new(&mut self.memdb, &mut self.root).build()
@@ -108,9 +112,9 @@ impl<T: TrieLayout> FileDataTrie<T> for InMemoryFileDataTrie<T> {
         // Check that we don't have a chunk already stored.
         if trie
             .contains(&chunk_id.as_trie_key())
-            .map_err(|_| FileStorageWriteError::FailedToGetFileChunk)?
+            .map_err(|_| FileStorageError::FailedToGetFileChunk)?
         {
-            return Err(FileStorageWriteError::FileChunkAlreadyExists);
+            return Err(FileStorageError::FileChunkAlreadyExists);
         }
 
         // Insert the encoded chunk with its ID into the file trie.
@@ -120,7 +124,7 @@ impl<T: TrieLayout> FileDataTrie<T> for InMemoryFileDataTrie<T> {
         };
         let encoded_chunk = decoded_chunk.encode();
         trie.insert(&chunk_id.as_trie_key(), &encoded_chunk)
-            .map_err(|_| FileStorageWriteError::FailedToInsertFileChunk)?;
+            .map_err(|_| FileStorageError::FailedToInsertFileChunk)?;
 
         // dropping the trie automatically commits changes to the underlying db
         drop(trie);
@@ -128,13 +132,25 @@ impl<T: TrieLayout> FileDataTrie<T> for InMemoryFileDataTrie<T> {
         Ok(())
     }
 
-    fn delete(&mut self) -> Result<(), FileStorageWriteError> {
+    fn delete(&mut self) -> Result<(), FileStorageError> {
         let (memdb, root) = MemoryDB::<HashT<T>>::default_with_root();
         self.root = root;
         self.memdb = memdb;
 
         Ok(())
     }
+
+    fn chunk_ids(&self) -> Result<Vec<ChunkId>, FileStorageError> {
+        chunk_ids_in_trie::<T>(&self.memdb, &self.root)
+    }
+}
+
+/// Locks the data of a file.
+///
+/// The lock only serialises the operations on the file, which leave it consistent when failing,
+/// so a poisoned lock is still usable.
+fn lock<D>(file_data: &Mutex<D>) -> MutexGuard<'_, D> {
+    file_data.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
 pub struct InMemoryFileStorage<T: TrieLayout + 'static>
@@ -142,10 +158,18 @@ where
     HasherOutT<T>: TryFrom<[u8; H_LENGTH]>,
 {
     pub metadata: HashMap<HasherOutT<T>, FileMetadata>,
-    pub file_data: HashMap<HasherOutT<T>, InMemoryFileDataTrie<T>>,
+    /// The data of each file, locked to serialise the operations on the same file.
+    pub file_data: HashMap<HasherOutT<T>, Mutex<InMemoryFileDataTrie<T>>>,
     pub bucket_prefix_map: HashSet<[u8; 64]>,
     pub exclude_list: HashMap<ExcludeType, HashSet<HasherOutT<T>>>,
-    pub chunk_counts: HashMap<HasherOutT<T>, u64>,
+    /// Only updated holding the lock of the data of the file.
+    pub chunk_counts: HashMap<HasherOutT<T>, AtomicU64>,
+    /// The unix time (in seconds) at which each file was inserted.
+    pub created_at: HashMap<HasherOutT<T>, u64>,
+    /// The sealed files, to which no more chunks can be written.
+    pub sealed: RwLock<HashSet<HasherOutT<T>>>,
+    /// Maximum number of chunks proven in a single proof.
+    max_chunks_per_proof: u64,
 }
 
 impl<T: TrieLayout> InMemoryFileStorage<T>
@@ -167,8 +191,84 @@ where
             bucket_prefix_map: HashSet::new(),
             exclude_list,
             chunk_counts: HashMap::new(),
+            created_at: HashMap::new(),
+            sealed: RwLock::new(HashSet::new()),
+            max_chunks_per_proof: DEFAULT_MAX_CHUNKS_PER_PROOF,
         }
     }
+
+    /// Sets the maximum number of chunks proven in a single [`FileStorage::generate_proof`] call.
+    ///
+    /// Defaults to [`DEFAULT_MAX_CHUNKS_PER_PROOF`].
+    pub fn with_max_chunks_per_proof(mut self, max_chunks_per_proof: u64) -> Self {
+        self.max_chunks_per_proof = max_chunks_per_proof;
+        self
+    }
+
+    /// The current root of the trie of the file with `key`, if it exists.
+    pub(crate) fn file_root(&self, key: &HasherOutT<T>) -> Option<HasherOutT<T>> {
+        self.file_data
+            .get(key)
+            .map(|file_data| *lock(file_data).get_root())
+    }
+
+    /// Moves all the files of `other` into this storage, along with their data.
+    ///
+    /// Fails with [`FileStorageError::FileAlreadyExists`] if any of the files of `other` is
+    /// already in this storage, in which case nothing is merged.
+    pub fn merge(&mut self, other: InMemoryFileStorage<T>) -> Result<(), FileStorageError> {
+        if other
+            .metadata
+            .keys()
+            .any(|key| self.metadata.contains_key(key))
+        {
+            return Err(FileStorageError::FileAlreadyExists);
+        }
+
+        let InMemoryFileStorage {
+            metadata,
+            mut file_data,
+            bucket_prefix_map,
+            exclude_list,
+            created_at,
+            sealed,
+            ..
+        } = other;
+
+        for (key, metadata) in metadata {
+            let data = file_data
+                .remove(&key)
+                .expect(
+                    format!(
+                        "Invariant broken! Metadata for file key {:?} found but no associated trie",
+                        key
+                    )
+                    .as_str(),
+                )
+                .into_inner()
+                .unwrap_or_else(PoisonError::into_inner);
+            self.insert_file_with_data(key, metadata, data)?;
+
+            // Keep the original insertion time, instead of the time of the merge.
+            if let Some(created_at) = created_at.get(&key) {
+                self.created_at.insert(key, *created_at);
+            }
+        }
+
+        self.bucket_prefix_map.extend(bucket_prefix_map);
+        self.sealed
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend(sealed.into_inner().unwrap_or_else(PoisonError::into_inner));
+        for (exclude_type, keys) in exclude_list {
+            self.exclude_list
+                .entry(exclude_type)
+                .or_default()
+                .extend(keys);
+        }
+
+        Ok(())
+    }
 }
 
 impl<T: TrieLayout + 'static> FileStorage<T> for InMemoryFileStorage<T>
@@ -186,17 +286,21 @@ where
         file_key: &HasherOutT<T>,
         chunk_ids: &HashSet<ChunkId>,
     ) -> Result<FileKeyProof, FileStorageError> {
+        check_chunks_per_proof(chunk_ids, self.max_chunks_per_proof)?;
+
         let metadata = self
             .metadata
             .get(file_key)
             .ok_or(FileStorageError::FileDoesNotExist)?;
 
-        let file_data = self.file_data.get(file_key).expect(
-            format!(
-                "Invariant broken! Metadata for file key {:?} found but no associated trie",
-                file_key
-            )
-            .as_str(),
+        let file_data = lock(
+            self.file_data.get(file_key).expect(
+                format!(
+                    "Invariant broken! Metadata for file key {:?} found but no associated trie",
+                    file_key
+                )
+                .as_str(),
+            ),
         );
 
         let stored_chunks = self.stored_chunks_count(file_key)?;
@@ -220,14 +324,29 @@ where
     fn stored_chunks_count(&self, key: &HasherOutT<T>) -> Result<u64, FileStorageError> {
         self.chunk_counts
             .get(key)
-            .copied()
+            .map(|chunk_count| chunk_count.load(Ordering::SeqCst))
             .ok_or(FileStorageError::FileDoesNotExist)
     }
 
+    fn present_chunk_ids(&self, key: &HasherOutT<T>) -> Result<Vec<ChunkId>, FileStorageError> {
+        let file_data = lock(
+            self.file_data
+                .get(key)
+                .ok_or(FileStorageError::FileDoesNotExist)?,
+        );
+
+        file_data.chunk_ids()
+    }
+
     fn delete_file(&mut self, key: &HasherOutT<T>) -> Result<(), FileStorageError> {
         self.metadata.remove(key);
         self.file_data.remove(key);
         self.chunk_counts.remove(key);
+        self.created_at.remove(key);
+        self.sealed
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(key);
 
         Ok(())
     }
@@ -239,18 +358,24 @@ where
         Ok(self.metadata.get(file_key).cloned())
     }
 
+    fn file_exists(&self, key: &HasherOutT<T>) -> Result<bool, FileStorageError> {
+        Ok(self.metadata.contains_key(key))
+    }
+
     fn is_file_complete(&self, key: &HasherOutT<T>) -> Result<bool, FileStorageError> {
         let metadata = self
             .metadata
             .get(key)
             .ok_or(FileStorageError::FileDoesNotExist)?;
 
-        let file_data = self.file_data.get(key).expect(
-            format!(
-                "Invariant broken! Metadata for file key {:?} found but no associated trie",
-                key
-            )
-            .as_str(),
+        let file_data = lock(
+            self.file_data.get(key).expect(
+                format!(
+                    "Invariant broken! Metadata for file key {:?} found but no associated trie",
+                    key
+                )
+                .as_str(),
+            ),
         );
 
         if metadata.fingerprint() != file_data.get_root().as_ref() {
@@ -265,19 +390,23 @@ where
         key: HasherOutT<T>,
         metadata: FileMetadata,
     ) -> Result<(), FileStorageError> {
+        metadata
+            .validate_strict()
+            .map_err(FileStorageError::InvalidMetadata)?;
         if self.metadata.contains_key(&key) {
             return Err(FileStorageError::FileAlreadyExists);
         }
         self.metadata.insert(key, metadata.clone());
 
         let empty_file_trie = self.new_file_data_trie();
-        let previous = self.file_data.insert(key, empty_file_trie);
+        let previous = self.file_data.insert(key, Mutex::new(empty_file_trie));
         if previous.is_some() {
             panic!("Key already associated with File Data, but not with File Metadata. Possible inconsistency between them.");
         }
 
         // Initialize chunk count to 0
-        self.chunk_counts.insert(key, 0);
+        self.chunk_counts.insert(key, AtomicU64::new(0));
+        self.created_at.insert(key, unix_timestamp_now());
 
         let full_key = [metadata.bucket_id().as_slice(), key.as_ref()].concat();
         self.bucket_prefix_map.insert(full_key.try_into().unwrap());
@@ -291,6 +420,9 @@ where
         metadata: FileMetadata,
         file_data: Self::FileDataTrie,
     ) -> Result<(), FileStorageError> {
+        metadata
+            .validate_strict()
+            .map_err(FileStorageError::InvalidMetadata)?;
         if self.metadata.contains_key(&key) {
             return Err(FileStorageError::FileAlreadyExists);
         }
@@ -303,9 +435,11 @@ where
             .map_err(|_| FileStorageError::FailedToConstructTrieIter)?
             .count();
 
-        self.chunk_counts.insert(key, chunk_count as u64);
+        self.chunk_counts
+            .insert(key, AtomicU64::new(chunk_count as u64));
+        self.created_at.insert(key, unix_timestamp_now());
 
-        let previous = self.file_data.insert(key, file_data);
+        let previous = self.file_data.insert(key, Mutex::new(file_data));
         if previous.is_some() {
             panic!("Key already associated with File Data, but not with File Metadata. Possible inconsistency between them.");
         }
@@ -316,29 +450,266 @@ where
         Ok(())
     }
 
+    fn atomic_delete_and_insert(
+        &mut self,
+        old_key: HasherOutT<T>,
+        new_key: HasherOutT<T>,
+        new_metadata: FileMetadata,
+        new_data: Self::FileDataTrie,
+    ) -> Result<(), FileStorageError> {
+        let old_metadata = self
+            .metadata
+            .get(&old_key)
+            .ok_or(FileStorageError::FileDoesNotExist)?;
+
+        if new_key != old_key && self.metadata.contains_key(&new_key) {
+            return Err(FileStorageError::FileAlreadyExists);
+        }
+
+        // Everything that can fail is done before touching the maps, so that they are swapped
+        // from the old file to the new one without leaving the storage half-updated.
+        let trie = TrieDBBuilder::<T>::new(&new_data.memdb, &new_data.get_root()).build();
+        let chunk_count = trie
+            .iter()
+            .map_err(|_| FileStorageError::FailedToConstructTrieIter)?
+            .count();
+        drop(trie);
+
+        let old_full_key: [u8; 64] = [old_metadata.bucket_id().as_slice(), old_key.as_ref()]
+            .concat()
+            .try_into()
+            .unwrap();
+        let new_full_key: [u8; 64] = [new_metadata.bucket_id().as_slice(), new_key.as_ref()]
+            .concat()
+            .try_into()
+            .unwrap();
+
+        self.metadata.remove(&old_key);
+        self.file_data.remove(&old_key);
+        self.chunk_counts.remove(&old_key);
+        self.created_at.remove(&old_key);
+        self.sealed
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&old_key);
+        self.bucket_prefix_map.remove(&old_full_key);
+
+        self.metadata.insert(new_key, new_metadata);
+        self.file_data.insert(new_key, Mutex::new(new_data));
+        self.chunk_counts
+            .insert(new_key, AtomicU64::new(chunk_count as u64));
+        self.created_at.insert(new_key, unix_timestamp_now());
+        self.bucket_prefix_map.insert(new_full_key);
+
+        Ok(())
+    }
+
+    fn copy_file(
+        &mut self,
+        src_key: &HasherOutT<T>,
+        new_metadata: FileMetadata,
+    ) -> Result<HasherOutT<T>, FileStorageError> {
+        let src_metadata = self
+            .metadata
+            .get(src_key)
+            .ok_or(FileStorageError::FileDoesNotExist)?;
+
+        if src_metadata.fingerprint() != new_metadata.fingerprint()
+            || src_metadata.file_size() != new_metadata.file_size()
+        {
+            return Err(FileStorageError::FingerprintAndStoredFileMismatch);
+        }
+
+        if !self.is_file_complete(src_key)? {
+            return Err(FileStorageError::IncompleteFile);
+        }
+
+        let new_file_key = new_metadata.file_key::<HashT<T>>();
+        if self.metadata.contains_key(&new_file_key) {
+            return Err(FileStorageError::FileAlreadyExists);
+        }
+
+        let src_file_data = lock(
+            self.file_data.get(src_key).expect(
+                format!(
+                    "Invariant broken! Metadata for file key {:?} found but no associated trie",
+                    src_key
+                )
+                .as_str(),
+            ),
+        );
+
+        // The in-memory backend has no shared chunk storage, so the file data is cloned.
+        let file_data = InMemoryFileDataTrie {
+            root: src_file_data.root,
+            memdb: src_file_data.memdb.clone(),
+        };
+        drop(src_file_data);
+
+        self.insert_file_with_data(new_file_key, new_metadata, file_data)?;
+
+        Ok(new_file_key)
+    }
+
+    fn incomplete_files_older_than(
+        &self,
+        created_before: u64,
+    ) -> Result<Vec<HasherOutT<T>>, FileStorageError> {
+        let mut old_files = Vec::new();
+        for (file_key, created_at) in &self.created_at {
+            if *created_at < created_before && !self.is_file_complete(file_key)? {
+                old_files.push(*file_key);
+            }
+        }
+
+        Ok(old_files)
+    }
+
+    fn incomplete_files(&self) -> Result<Vec<IncompleteFile<HasherOutT<T>>>, FileStorageError> {
+        let mut incomplete_files = Vec::new();
+        for file_key in self.metadata.keys() {
+            if !self.is_file_complete(file_key)? {
+                incomplete_files.push(IncompleteFile {
+                    file_key: *file_key,
+                    age: self.created_at.get(file_key).copied().map(age_since),
+                });
+            }
+        }
+
+        Ok(incomplete_files)
+    }
+
+    fn partial_file_age(&self, key: &HasherOutT<T>) -> Result<Option<Duration>, FileStorageError> {
+        if self.is_file_complete(key)? {
+            return Ok(None);
+        }
+
+        Ok(self.created_at.get(key).copied().map(age_since))
+    }
+
+    fn file_keys(&self) -> Result<Vec<HasherOutT<T>>, FileStorageError> {
+        let mut file_keys: Vec<_> = self.metadata.keys().copied().collect();
+        file_keys.sort();
+
+        Ok(file_keys)
+    }
+
+    fn get_files_after_key(
+        &self,
+        start_key: Option<HasherOutT<T>>,
+        limit: usize,
+    ) -> Result<Vec<HasherOutT<T>>, FileStorageError> {
+        let mut file_keys: Vec<_> = self
+            .metadata
+            .keys()
+            .filter(|file_key| start_key.map_or(true, |start_key| **file_key > start_key))
+            .copied()
+            .collect();
+        file_keys.sort();
+        file_keys.truncate(limit);
+
+        Ok(file_keys)
+    }
+
+    /// The root of an in-memory file trie is never out of date, so only the stored chunks count
+    /// can be repaired.
+    fn verify_and_repair_file(
+        &self,
+        key: &HasherOutT<T>,
+    ) -> Result<FileVerification, FileStorageError> {
+        let metadata = self
+            .metadata
+            .get(key)
+            .ok_or(FileStorageError::FileDoesNotExist)?;
+        let file_data = lock(
+            self.file_data
+                .get(key)
+                .ok_or(FileStorageError::FileDoesNotExist)?,
+        );
+        let chunk_count = self
+            .chunk_counts
+            .get(key)
+            .ok_or(FileStorageError::FileDoesNotExist)?;
+
+        let stored_chunks = file_data.chunk_ids()?.len() as u64;
+        let repaired = chunk_count.swap(stored_chunks, Ordering::SeqCst) != stored_chunks;
+
+        Ok(FileVerification {
+            complete: metadata.fingerprint() == file_data.get_root().as_ref()
+                && metadata.chunks_count() == stored_chunks,
+            repaired,
+        })
+    }
+
+    fn stats(&self) -> Result<FileStorageStats, FileStorageError> {
+        Ok(FileStorageStats {
+            files: self.metadata.len() as u64,
+            total_size: self
+                .metadata
+                .values()
+                .map(|metadata| metadata.file_size())
+                .sum(),
+        })
+    }
+
     fn get_chunk(
         &self,
         file_key: &HasherOutT<T>,
         chunk_id: &ChunkId,
     ) -> Result<Chunk, FileStorageError> {
         let file_data = self.file_data.get(file_key);
-        let file_data = file_data.ok_or(FileStorageError::FileDoesNotExist)?;
+        let file_data = lock(file_data.ok_or(FileStorageError::FileDoesNotExist)?);
 
         file_data.get_chunk(chunk_id)
     }
 
+    fn seal_file(&self, key: &HasherOutT<T>) -> Result<(), FileStorageError> {
+        let metadata = self
+            .metadata
+            .get(key)
+            .ok_or(FileStorageError::FileDoesNotExist)?;
+        let file_data = lock(
+            self.file_data
+                .get(key)
+                .ok_or(FileStorageError::FileDoesNotExist)?,
+        );
+
+        if metadata.fingerprint() != file_data.get_root().as_ref() {
+            return Err(FileStorageError::FingerprintAndStoredFileMismatch);
+        }
+
+        if metadata.chunks_count() != self.stored_chunks_count(key)? {
+            return Err(FileStorageError::IncompleteFile);
+        }
+
+        self.sealed
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(*key);
+
+        Ok(())
+    }
+
     fn write_chunk(
-        &mut self,
+        &self,
         file_key: &HasherOutT<T>,
         chunk_id: &ChunkId,
         data: &Chunk,
-    ) -> Result<FileStorageWriteOutcome, FileStorageWriteError> {
-        let file_data = self
-            .file_data
-            .get_mut(file_key)
-            .ok_or(FileStorageWriteError::FileDoesNotExist)?;
+    ) -> Result<FileStorageWriteOutcome, FileStorageError> {
+        let mut file_data = lock(
+            self.file_data
+                .get(file_key)
+                .ok_or(FileStorageError::FileDoesNotExist)?,
+        );
 
-        file_data.write_chunk(chunk_id, data)?;
+        if self
+            .sealed
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains(file_key)
+        {
+            return Err(FileStorageError::FileSealed);
+        }
 
         let metadata = self.metadata.get(file_key).expect(
             format!("Key {:?} already associated with File Trie, but no File Metadata. Possible inconsistency between them.",
@@ -347,25 +718,37 @@ where
             .as_str(),
         );
 
+        // Chunks outside of the file's range would count towards the stored chunks, preventing
+        // the file from ever being complete.
+        if chunk_id.as_u64() >= metadata.chunks_count() {
+            return Err(FileStorageError::ChunkIdOutOfRange);
+        }
+
+        file_data.write_chunk(chunk_id, data)?;
+
         // Increment chunk count
-        let current_count = self
+        let chunk_count = self
             .chunk_counts
             .get(file_key)
-            .ok_or(FileStorageWriteError::FailedToGetStoredChunksCount)?;
-        let new_count = current_count
+            .ok_or(FileStorageError::FailedToGetStoredChunksCount)?;
+        let new_count = chunk_count
+            .load(Ordering::SeqCst)
             .checked_add(1)
-            .ok_or(FileStorageWriteError::ChunkCountOverflow)?;
-        self.chunk_counts.insert(*file_key, new_count);
+            .ok_or(FileStorageError::ChunkCountOverflow)?;
+        chunk_count.store(new_count, Ordering::SeqCst);
 
         // Check if we have all the chunks for the file using the count
         if metadata.chunks_count() != new_count {
-            return Ok(FileStorageWriteOutcome::FileIncomplete);
+            return Ok(FileStorageWriteOutcome::FileIncomplete {
+                stored: new_count,
+                total: metadata.chunks_count(),
+            });
         }
 
         // If we have all the chunks, check if the file metadata fingerprint and the file trie
         // root matches.
         if metadata.fingerprint() != file_data.get_root().as_ref() {
-            return Err(FileStorageWriteError::FingerprintAndStoredFileMismatch);
+            return Err(FileStorageError::FingerprintAndStoredFileMismatch);
         }
 
         Ok(FileStorageWriteOutcome::FileComplete)
@@ -396,11 +779,43 @@ where
             self.metadata.remove(&key);
             self.file_data.remove(&key);
             self.chunk_counts.remove(&key);
+            self.created_at.remove(&key);
+            self.sealed
+                .get_mut()
+                .unwrap_or_else(PoisonError::into_inner)
+                .remove(&key);
         }
 
         Ok(())
     }
 
+    /// Files are looked up in the bucket prefix map, which keeps the entries of deleted files,
+    /// so only the ones with metadata are returned.
+    fn bucket_file_keys(
+        &self,
+        bucket_id: &[u8; 32],
+    ) -> Result<Vec<HasherOutT<T>>, FileStorageError> {
+        let mut file_keys = Vec::new();
+        for full_key in &self.bucket_prefix_map {
+            if !full_key.starts_with(bucket_id) {
+                continue;
+            }
+
+            let file_key: [u8; 32] = full_key[32..]
+                .try_into()
+                .expect("Bucket prefixed keys are 64 bytes long; qed");
+            let file_key: HasherOutT<T> = file_key
+                .try_into()
+                .map_err(|_| FileStorageError::FailedToParseKey)?;
+            if self.metadata.contains_key(&file_key) {
+                file_keys.push(file_key);
+            }
+        }
+        file_keys.sort();
+
+        Ok(file_keys)
+    }
+
     fn is_allowed(
         &self,
         key: &HasherOutT<T>,
@@ -449,6 +864,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use shc_common::types::{Fingerprint, FILE_CHUNK_SIZE};
     use sp_core::H256;
     use sp_runtime::traits::BlakeTwo256;
     use sp_runtime::AccountId32;
@@ -638,6 +1054,222 @@ mod tests {
         assert!(file_storage.get_chunk(&key, &chunk_ids[2]).is_ok());
     }
 
+    #[test]
+    fn file_storage_write_chunk_reports_progress() {
+        let chunks = vec![
+            Chunk::from([5u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([6u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([7u8; FILE_CHUNK_SIZE as usize]),
+        ];
+
+        let chunk_ids: Vec<ChunkId> = chunks
+            .iter()
+            .enumerate()
+            .map(|(id, _)| ChunkId::new(id as u64))
+            .collect();
+
+        let mut file_trie = InMemoryFileDataTrie::<LayoutV1<BlakeTwo256>>::new();
+        for (chunk_id, chunk) in chunk_ids.iter().zip(chunks.iter()) {
+            file_trie.write_chunk(chunk_id, chunk).unwrap();
+        }
+
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            FILE_CHUNK_SIZE * chunks.len() as u64,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+
+        let key = file_metadata.file_key::<BlakeTwo256>();
+        let mut file_storage = InMemoryFileStorage::<LayoutV1<BlakeTwo256>>::new();
+        file_storage.insert_file(key, file_metadata).unwrap();
+
+        for (i, (chunk_id, chunk)) in chunk_ids.iter().zip(chunks.iter()).enumerate().take(2) {
+            let outcome = file_storage.write_chunk(&key, chunk_id, chunk).unwrap();
+            assert_eq!(
+                outcome,
+                FileStorageWriteOutcome::FileIncomplete {
+                    stored: i as u64 + 1,
+                    total: 3
+                }
+            );
+            assert_eq!(
+                file_storage.stored_chunks_count(&key).unwrap(),
+                i as u64 + 1
+            );
+        }
+        let outcome = file_storage
+            .write_chunk(&key, &chunk_ids[2], &chunks[2])
+            .unwrap();
+        assert_eq!(outcome, FileStorageWriteOutcome::FileComplete);
+        assert_eq!(outcome.progress_percentage(), 100.0);
+    }
+
+    #[test]
+    fn file_storage_write_chunk_rejects_out_of_range_chunk_id() {
+        let chunks = vec![
+            Chunk::from([5u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([6u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([7u8; FILE_CHUNK_SIZE as usize]),
+        ];
+
+        let chunk_ids: Vec<ChunkId> = chunks
+            .iter()
+            .enumerate()
+            .map(|(id, _)| ChunkId::new(id as u64))
+            .collect();
+
+        let mut file_trie = InMemoryFileDataTrie::<LayoutV1<BlakeTwo256>>::new();
+        for (chunk_id, chunk) in chunk_ids.iter().zip(chunks.iter()) {
+            file_trie.write_chunk(chunk_id, chunk).unwrap();
+        }
+
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            FILE_CHUNK_SIZE * chunks.len() as u64,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+
+        let key = file_metadata.file_key::<BlakeTwo256>();
+        let mut file_storage = InMemoryFileStorage::<LayoutV1<BlakeTwo256>>::new();
+        file_storage
+            .insert_file(key, file_metadata.clone())
+            .unwrap();
+
+        // A chunk ID equal to the chunks count is out of range.
+        let out_of_range_chunk_id = ChunkId::new(file_metadata.chunks_count());
+        assert!(matches!(
+            file_storage.write_chunk(&key, &out_of_range_chunk_id, &chunks[0]),
+            Err(FileStorageError::ChunkIdOutOfRange)
+        ));
+        assert_eq!(file_storage.stored_chunks_count(&key).unwrap(), 0);
+
+        // The file can still be completed with the valid chunks.
+        for (chunk_id, chunk) in chunk_ids.iter().zip(chunks.iter()).take(2) {
+            assert!(matches!(
+                file_storage.write_chunk(&key, chunk_id, chunk),
+                Ok(FileStorageWriteOutcome::FileIncomplete { .. })
+            ));
+        }
+        assert!(matches!(
+            file_storage.write_chunk(&key, &chunk_ids[2], &chunks[2]),
+            Ok(FileStorageWriteOutcome::FileComplete)
+        ));
+        assert!(file_storage.is_file_complete(&key).unwrap());
+    }
+
+    #[test]
+    fn file_storage_present_chunk_ids_works() {
+        // A file with more than 64 chunks, so that some chunk IDs take more than one byte in
+        // the trie keys.
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            FILE_CHUNK_SIZE * 70,
+            H256::repeat_byte(9).as_ref().into(),
+        )
+        .unwrap();
+
+        let key = file_metadata.file_key::<BlakeTwo256>();
+        let mut file_storage = InMemoryFileStorage::<LayoutV1<BlakeTwo256>>::new();
+        file_storage.insert_file(key, file_metadata).unwrap();
+        assert!(file_storage.present_chunk_ids(&key).unwrap().is_empty());
+
+        // Write non-contiguous chunks, out of order.
+        for chunk_id in [65, 0, 7, 3] {
+            file_storage
+                .write_chunk(
+                    &key,
+                    &ChunkId::new(chunk_id),
+                    &Chunk::from([chunk_id as u8; FILE_CHUNK_SIZE as usize]),
+                )
+                .unwrap();
+        }
+
+        assert_eq!(file_storage.stored_chunks_count(&key).unwrap(), 4);
+        assert_eq!(
+            file_storage.present_chunk_ids(&key).unwrap(),
+            vec![
+                ChunkId::new(0),
+                ChunkId::new(3),
+                ChunkId::new(7),
+                ChunkId::new(65)
+            ]
+        );
+
+        assert!(matches!(
+            file_storage.present_chunk_ids(&H256::repeat_byte(1)),
+            Err(FileStorageError::FileDoesNotExist)
+        ));
+    }
+
+    #[test]
+    fn file_storage_seal_file_rejects_further_writes() {
+        let chunks = vec![
+            Chunk::from([5u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([6u8; FILE_CHUNK_SIZE as usize]),
+        ];
+
+        let chunk_ids: Vec<ChunkId> = chunks
+            .iter()
+            .enumerate()
+            .map(|(id, _)| ChunkId::new(id as u64))
+            .collect();
+
+        let mut file_trie = InMemoryFileDataTrie::<LayoutV1<BlakeTwo256>>::new();
+        for (chunk_id, chunk) in chunk_ids.iter().zip(chunks.iter()) {
+            file_trie.write_chunk(chunk_id, chunk).unwrap();
+        }
+
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            FILE_CHUNK_SIZE * chunks.len() as u64,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+
+        let key = file_metadata.file_key::<BlakeTwo256>();
+        let mut file_storage = InMemoryFileStorage::<LayoutV1<BlakeTwo256>>::new();
+        file_storage.insert_file(key, file_metadata).unwrap();
+
+        // An incomplete file cannot be sealed.
+        file_storage
+            .write_chunk(&key, &chunk_ids[0], &chunks[0])
+            .unwrap();
+        assert!(matches!(
+            file_storage.seal_file(&key),
+            Err(FileStorageError::IncompleteFile)
+        ));
+
+        assert!(matches!(
+            file_storage.write_chunk(&key, &chunk_ids[1], &chunks[1]),
+            Ok(FileStorageWriteOutcome::FileComplete)
+        ));
+        file_storage.seal_file(&key).unwrap();
+        // Sealing twice is a no-op.
+        file_storage.seal_file(&key).unwrap();
+
+        // No chunk can be written to a sealed file, whether it already exists or not.
+        assert!(matches!(
+            file_storage.write_chunk(&key, &chunk_ids[1], &chunks[1]),
+            Err(FileStorageError::FileSealed)
+        ));
+        assert!(matches!(
+            file_storage.write_chunk(&key, &ChunkId::new(2), &chunks[0]),
+            Err(FileStorageError::FileSealed)
+        ));
+        assert_eq!(file_storage.stored_chunks_count(&key).unwrap(), 2);
+        assert!(file_storage.is_file_complete(&key).unwrap());
+    }
+
     #[test]
     fn file_storage_delete_file_works() {
         let chunks = vec![
@@ -692,6 +1324,139 @@ mod tests {
         assert!(file_storage.get_chunk(&key, &chunk_ids[2]).is_err());
     }
 
+    #[test]
+    fn file_storage_atomic_delete_and_insert_works() {
+        let new_file = |chunks: &[Chunk], location: &str| {
+            let mut file_trie = InMemoryFileDataTrie::<LayoutV1<BlakeTwo256>>::new();
+            for (id, chunk) in chunks.iter().enumerate() {
+                file_trie
+                    .write_chunk(&ChunkId::new(id as u64), chunk)
+                    .unwrap();
+            }
+            let file_metadata = FileMetadata::new(
+                <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+                [1u8; 32].to_vec(),
+                location.to_string().into_bytes(),
+                FILE_CHUNK_SIZE * chunks.len() as u64,
+                file_trie.get_root().as_ref().into(),
+            )
+            .unwrap();
+            (
+                file_metadata.file_key::<BlakeTwo256>(),
+                file_metadata,
+                file_trie,
+            )
+        };
+
+        let old_chunks = vec![
+            Chunk::from([5u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([6u8; FILE_CHUNK_SIZE as usize]),
+        ];
+        // The new version of the file shares its first chunk with the old one.
+        let new_chunks = vec![
+            Chunk::from([5u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([7u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([8u8; FILE_CHUNK_SIZE as usize]),
+        ];
+        let (old_key, old_metadata, old_trie) = new_file(&old_chunks, "location");
+        let (new_key, new_metadata, new_trie) = new_file(&new_chunks, "location_v2");
+
+        let mut file_storage = InMemoryFileStorage::<LayoutV1<BlakeTwo256>>::new();
+        file_storage
+            .insert_file_with_data(old_key, old_metadata, old_trie)
+            .unwrap();
+
+        file_storage
+            .atomic_delete_and_insert(old_key, new_key, new_metadata.clone(), new_trie)
+            .unwrap();
+
+        assert!(file_storage.get_metadata(&old_key).unwrap().is_none());
+        assert!(file_storage.get_chunk(&old_key, &ChunkId::new(0)).is_err());
+        assert_eq!(
+            file_storage.get_metadata(&new_key).unwrap(),
+            Some(new_metadata)
+        );
+        assert_eq!(file_storage.stored_chunks_count(&new_key).unwrap(), 3);
+        assert!(file_storage.is_file_complete(&new_key).unwrap());
+        assert_eq!(
+            file_storage.get_chunk(&new_key, &ChunkId::new(2)).unwrap(),
+            new_chunks[2]
+        );
+        assert!(file_storage
+            .generate_proof(&new_key, &HashSet::from([ChunkId::new(0), ChunkId::new(2)]))
+            .is_ok());
+        assert_eq!(file_storage.bucket_prefix_map.len(), 1);
+
+        // A replacement that fails leaves the storage untouched.
+        let (other_key, other_metadata, other_trie) = new_file(&old_chunks, "location_v3");
+        assert!(matches!(
+            file_storage.atomic_delete_and_insert(old_key, other_key, other_metadata, other_trie),
+            Err(FileStorageError::FileDoesNotExist)
+        ));
+        assert!(file_storage.get_metadata(&other_key).unwrap().is_none());
+        assert!(file_storage.is_file_complete(&new_key).unwrap());
+        assert_eq!(file_storage.metadata.len(), 1);
+    }
+
+    #[test]
+    fn file_storage_copy_file_works() {
+        let chunks = vec![
+            Chunk::from([5u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([6u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([7u8; FILE_CHUNK_SIZE as usize]),
+        ];
+
+        let chunk_ids: Vec<ChunkId> = chunks
+            .iter()
+            .enumerate()
+            .map(|(id, _)| ChunkId::new(id as u64))
+            .collect();
+
+        let mut file_trie = InMemoryFileDataTrie::<LayoutV1<BlakeTwo256>>::new();
+        for (chunk_id, chunk) in chunk_ids.iter().zip(chunks.iter()) {
+            file_trie.write_chunk(chunk_id, chunk).unwrap();
+        }
+
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            FILE_CHUNK_SIZE * chunks.len() as u64,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+
+        let key = file_metadata.file_key::<BlakeTwo256>();
+        let mut file_storage = InMemoryFileStorage::<LayoutV1<BlakeTwo256>>::new();
+        file_storage
+            .insert_file_with_data(key, file_metadata.clone(), file_trie)
+            .unwrap();
+
+        // Copy the file into a second bucket.
+        let new_file_metadata = FileMetadata::new(
+            file_metadata.owner().clone(),
+            [2u8; 32].to_vec(),
+            file_metadata.location().clone(),
+            file_metadata.file_size(),
+            *file_metadata.fingerprint(),
+        )
+        .unwrap();
+
+        let new_key = file_storage
+            .copy_file(&key, new_file_metadata.clone())
+            .unwrap();
+        assert_eq!(new_key, new_file_metadata.file_key::<BlakeTwo256>());
+
+        assert!(file_storage.is_file_complete(&new_key).unwrap());
+        for (chunk_id, chunk) in chunk_ids.iter().zip(chunks.iter()) {
+            assert_eq!(&file_storage.get_chunk(&new_key, chunk_id).unwrap(), chunk);
+        }
+
+        // Deleting the source file keeps the chunks of the copy.
+        file_storage.delete_file(&key).unwrap();
+        assert!(file_storage.get_chunk(&new_key, &chunk_ids[0]).is_ok());
+    }
+
     #[test]
     fn file_storage_generate_proof_works() {
         let chunks = vec![
@@ -746,6 +1511,108 @@ mod tests {
         }
     }
 
+    #[test]
+    fn file_storage_generate_proof_rejects_too_many_chunks() {
+        let chunks: Vec<_> = (0..3u8).map(|i| Chunk::from([i; 1024])).collect();
+        let chunk_ids: HashSet<ChunkId> = (0..chunks.len() as u64).map(ChunkId::new).collect();
+
+        let mut file_trie = InMemoryFileDataTrie::<LayoutV1<BlakeTwo256>>::new();
+        for (id, chunk) in chunks.iter().enumerate() {
+            file_trie
+                .write_chunk(&ChunkId::new(id as u64), chunk)
+                .unwrap();
+        }
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            1024u64 * chunks.len() as u64,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+        let key = file_metadata.file_key::<BlakeTwo256>();
+
+        let mut file_storage =
+            InMemoryFileStorage::<LayoutV1<BlakeTwo256>>::new().with_max_chunks_per_proof(2);
+        file_storage
+            .insert_file_with_data(key, file_metadata, file_trie)
+            .unwrap();
+
+        assert!(matches!(
+            file_storage.generate_proof(&key, &chunk_ids),
+            Err(FileStorageError::TooManyChunksRequested {
+                requested: 3,
+                max: 2
+            })
+        ));
+
+        // Splitting the request in proofs within the maximum works.
+        let first_chunks = HashSet::from([ChunkId::new(0), ChunkId::new(1)]);
+        let last_chunk = HashSet::from([ChunkId::new(2)]);
+        assert!(file_storage.generate_proof(&key, &first_chunks).is_ok());
+        assert!(file_storage.generate_proof(&key, &last_chunk).is_ok());
+    }
+
+    #[test]
+    fn merge_works() {
+        fn insert_file(
+            file_storage: &mut InMemoryFileStorage<LayoutV1<BlakeTwo256>>,
+            location: &str,
+            bucket_id: [u8; 32],
+        ) -> H256 {
+            let chunk = Chunk::from([3u8; 1024]);
+            let mut file_trie = InMemoryFileDataTrie::<LayoutV1<BlakeTwo256>>::new();
+            file_trie.write_chunk(&ChunkId::new(0), &chunk).unwrap();
+
+            let file_metadata = FileMetadata::new(
+                <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+                bucket_id.to_vec(),
+                location.to_string().into_bytes(),
+                1024,
+                file_trie.get_root().as_ref().into(),
+            )
+            .unwrap();
+
+            let file_key = file_metadata.file_key::<BlakeTwo256>();
+            file_storage
+                .insert_file_with_data(file_key, file_metadata, file_trie)
+                .unwrap();
+            file_key
+        }
+
+        let mut file_storage = InMemoryFileStorage::<LayoutV1<BlakeTwo256>>::new();
+        let file_key_1 = insert_file(&mut file_storage, "location_1", [1u8; 32]);
+
+        let mut other = InMemoryFileStorage::<LayoutV1<BlakeTwo256>>::new();
+        let file_key_2 = insert_file(&mut other, "location_2", [2u8; 32]);
+        let file_key_3 = insert_file(&mut other, "location_3", [1u8; 32]);
+
+        file_storage.merge(other).unwrap();
+
+        for file_key in [file_key_1, file_key_2, file_key_3] {
+            assert!(file_storage.is_file_complete(&file_key).unwrap());
+            assert!(file_storage
+                .get_chunk(&file_key, &ChunkId::new(0u64))
+                .is_ok());
+        }
+
+        // The files merged are deleted along with the files of the same bucket.
+        file_storage.delete_files_with_prefix(&[1u8; 32]).unwrap();
+        assert!(file_storage.get_metadata(&file_key_1).unwrap().is_none());
+        assert!(file_storage.get_metadata(&file_key_3).unwrap().is_none());
+        assert!(file_storage.get_metadata(&file_key_2).unwrap().is_some());
+
+        // Merging a file already in the storage fails without merging anything.
+        let mut other = InMemoryFileStorage::<LayoutV1<BlakeTwo256>>::new();
+        insert_file(&mut other, "location_2", [2u8; 32]);
+        let file_key_4 = insert_file(&mut other, "location_4", [2u8; 32]);
+        assert!(matches!(
+            file_storage.merge(other),
+            Err(FileStorageError::FileAlreadyExists)
+        ));
+        assert!(file_storage.get_metadata(&file_key_4).unwrap().is_none());
+    }
+
     #[test]
     fn delete_files_with_prefix_works() {
         fn create_file_data_trie(
@@ -860,4 +1727,36 @@ mod tests {
             .is_allowed(&hash, ExcludeType::Fingerprint)
             .unwrap())
     }
+
+    #[test]
+    fn insert_file_rejects_metadata_that_cannot_be_stored() {
+        let mut file_storage = InMemoryFileStorage::<LayoutV1<BlakeTwo256>>::new();
+
+        let metadata = |bucket_id: Vec<u8>, fingerprint: Fingerprint| {
+            FileMetadata::new(
+                <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+                bucket_id,
+                b"location".to_vec(),
+                FILE_CHUNK_SIZE,
+                fingerprint,
+            )
+            .unwrap()
+        };
+        let short_bucket = metadata(b"bucket".to_vec(), Fingerprint::from([1u8; 32]));
+        let no_fingerprint = metadata([1u8; 32].to_vec(), Fingerprint::default());
+
+        for metadata in [short_bucket, no_fingerprint] {
+            let file_key = metadata.file_key::<BlakeTwo256>();
+            assert!(matches!(
+                file_storage.insert_file(file_key, metadata.clone()),
+                Err(FileStorageError::InvalidMetadata(_))
+            ));
+            let file_data = file_storage.new_file_data_trie();
+            assert!(matches!(
+                file_storage.insert_file_with_data(file_key, metadata, file_data),
+                Err(FileStorageError::InvalidMetadata(_))
+            ));
+            assert!(file_storage.get_metadata(&file_key).unwrap().is_none());
+        }
+    }
 }
@@ -1,6 +1,85 @@
+pub mod bucket_proof;
+pub mod chunker;
 mod error;
+pub mod file_key_proof;
 pub mod in_memory;
+pub mod locks;
+pub mod metadata_cache;
 pub mod rocksdb;
+pub mod sharded;
 pub mod traits;
 
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashSet;
+
+use hash_db::HashDBRef;
+use log::error;
+use shc_common::types::{ChunkId, HashT, HasherOutT};
+use sp_trie::TrieLayout;
+use trie_db::{DBValue, Trie, TrieDBBuilder};
+
+use crate::traits::FileStorageError;
+
 const LOG_TARGET: &str = "file-manager";
+
+/// The current unix time in seconds, used to record when files are inserted in the File Storage.
+pub(crate) fn unix_timestamp_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// The time elapsed since `inserted_at`, a unix time in seconds as returned by
+/// [`unix_timestamp_now`].
+///
+/// Clocks going backwards make files look just inserted, rather than failing.
+pub(crate) fn age_since(inserted_at: u64) -> std::time::Duration {
+    std::time::Duration::from_secs(unix_timestamp_now().saturating_sub(inserted_at))
+}
+
+/// Fails with [`FileStorageError::TooManyChunksRequested`] if more than `max` chunks are requested
+/// to be proven at once.
+pub(crate) fn check_chunks_per_proof(
+    chunk_ids: &HashSet<ChunkId>,
+    max: u64,
+) -> Result<(), FileStorageError> {
+    let requested = chunk_ids.len() as u64;
+    if requested > max {
+        return Err(FileStorageError::TooManyChunksRequested { requested, max });
+    }
+    Ok(())
+}
+
+/// The IDs of the chunks in the file trie with `root`, reading its nodes from `db`, in ascending
+/// order.
+///
+/// The IDs are decoded from the keys of the trie, so the chunks themselves are not read.
+pub(crate) fn chunk_ids_in_trie<T: TrieLayout>(
+    db: &dyn HashDBRef<HashT<T>, DBValue>,
+    root: &HasherOutT<T>,
+) -> Result<Vec<ChunkId>, FileStorageError> {
+    let trie = TrieDBBuilder::<T>::new(db, root).build();
+
+    let mut chunk_ids = trie
+        .key_iter()
+        .map_err(|e| {
+            error!(target: LOG_TARGET, "Failed to construct Trie iterator: {}", e);
+            FileStorageError::FailedToConstructTrieIter
+        })?
+        .map(|key| {
+            let key = key.map_err(|e| {
+                error!(target: LOG_TARGET, "Failed to read file chunk key: {}", e);
+                FileStorageError::FailedToGetFileChunk
+            })?;
+            ChunkId::from_trie_key(&key).map_err(|_| FileStorageError::FailedToParseChunkWithId)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Chunk IDs are compact encoded in the keys, so the trie is not in chunk ID order.
+    chunk_ids.sort();
+
+    Ok(chunk_ids)
+}
@@ -0,0 +1,117 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// Number of locks a [`FileKeyLocks`] is sharded into.
+///
+/// File keys are hashes, so they spread evenly over the shards, and two distinct files only share
+/// a lock with a probability of `1 / FILE_KEY_LOCK_SHARDS`.
+pub const FILE_KEY_LOCK_SHARDS: usize = 256;
+
+/// Sharded map of locks by file key.
+///
+/// Used by the [`FileStorage`](crate::traits::FileStorage) implementations to serialise the
+/// operations on the same file, while operations on distinct files proceed concurrently.
+/// Clones share the same locks.
+#[derive(Clone)]
+pub struct FileKeyLocks {
+    shards: Arc<Vec<Mutex<()>>>,
+}
+
+impl Default for FileKeyLocks {
+    fn default() -> Self {
+        Self {
+            shards: Arc::new((0..FILE_KEY_LOCK_SHARDS).map(|_| Mutex::new(())).collect()),
+        }
+    }
+}
+
+impl FileKeyLocks {
+    /// Locks the file with `file_key`, until the returned guard is dropped.
+    pub fn lock(&self, file_key: &[u8]) -> MutexGuard<'_, ()> {
+        // The lock only guards the consistency of the file across operations, which are
+        // responsible for leaving it consistent when failing, so a poisoned lock is still usable.
+        self.shards[Self::shard(file_key)]
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The index of the shard of the lock of `file_key`.
+    pub(crate) fn shard(file_key: &[u8]) -> usize {
+        let mut hasher = DefaultHasher::new();
+        file_key.hash(&mut hasher);
+        (hasher.finish() % FILE_KEY_LOCK_SHARDS as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, thread, time::Duration};
+
+    use super::*;
+
+    /// Returns `count` file keys with distinct shards.
+    fn keys_in_distinct_shards(count: usize) -> Vec<[u8; 32]> {
+        let mut shards = Vec::new();
+        let mut keys = Vec::new();
+        for i in 0u8..=255 {
+            let key = [i; 32];
+            if !shards.contains(&FileKeyLocks::shard(&key)) {
+                shards.push(FileKeyLocks::shard(&key));
+                keys.push(key);
+            }
+            if keys.len() == count {
+                break;
+            }
+        }
+        assert_eq!(keys.len(), count, "Not enough keys in distinct shards");
+        keys
+    }
+
+    #[test]
+    fn distinct_file_keys_are_locked_concurrently() {
+        let locks = FileKeyLocks::default();
+        let keys = keys_in_distinct_shards(8);
+        let (locked_tx, locked_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let release_rx = Mutex::new(release_rx);
+
+        thread::scope(|scope| {
+            for key in &keys {
+                let (locks, locked_tx, release_rx) = (&locks, locked_tx.clone(), &release_rx);
+                scope.spawn(move || {
+                    let _guard = locks.lock(key);
+                    locked_tx.send(()).unwrap();
+                    // Hold the lock until all the other files are locked too.
+                    let _ = release_rx.lock().unwrap().recv();
+                });
+            }
+
+            // If locking a file blocked the others, not all of them would be held at once.
+            let all_locked =
+                (0..keys.len()).all(|_| locked_rx.recv_timeout(Duration::from_secs(5)).is_ok());
+            drop(release_tx);
+            assert!(all_locked, "Locks of distinct file keys were serialised");
+        });
+    }
+
+    #[test]
+    fn same_file_key_is_locked_exclusively() {
+        let locks = FileKeyLocks::default();
+        let key = [7u8; 32];
+        let (locked_tx, locked_rx) = mpsc::channel();
+
+        let guard = locks.lock(&key);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let _guard = locks.lock(&key);
+                locked_tx.send(()).unwrap();
+            });
+
+            assert!(locked_rx.recv_timeout(Duration::from_millis(100)).is_err());
+            drop(guard);
+            assert!(locked_rx.recv_timeout(Duration::from_secs(5)).is_ok());
+        });
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+use shc_common::types::FileMetadata;
+
+/// Default number of file metadata kept in memory by the File Storage.
+///
+/// Metadata is read for every chunk written, so only the files being uploaded at the same time
+/// need to fit.
+pub const DEFAULT_METADATA_CACHE_CAPACITY: usize = 1024;
+
+/// Least recently used cache of the metadata of files, so that it is not read and deserialised
+/// from the database for every chunk of a file.
+///
+/// The cache is not aware of the database: whoever writes or deletes the metadata of a file has
+/// to [`MetadataCache::remove`] it.
+#[derive(Debug)]
+pub(crate) struct MetadataCache<K> {
+    capacity: usize,
+    /// The cached metadata, with the last time it was used.
+    entries: HashMap<K, (FileMetadata, u64)>,
+    /// The cached keys, by the last time they were used.
+    by_last_use: BTreeMap<u64, K>,
+    /// Incremented on every use, to order them.
+    clock: u64,
+}
+
+impl<K: Hash + Eq + Copy> MetadataCache<K> {
+    /// Creates a cache holding up to `capacity` metadata. A `capacity` of 0 disables it.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            by_last_use: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<FileMetadata> {
+        let (metadata, last_use) = self.entries.get_mut(key)?;
+
+        self.by_last_use.remove(last_use);
+        self.clock += 1;
+        *last_use = self.clock;
+        self.by_last_use.insert(self.clock, *key);
+
+        Some(metadata.clone())
+    }
+
+    /// Caches `metadata`, evicting the least recently used metadata if the cache is full.
+    pub fn insert(&mut self, key: K, metadata: FileMetadata) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.remove(&key);
+        if self.entries.len() >= self.capacity {
+            if let Some((_, evicted)) = self.by_last_use.pop_first() {
+                self.entries.remove(&evicted);
+            }
+        }
+
+        self.clock += 1;
+        self.entries.insert(key, (metadata, self.clock));
+        self.by_last_use.insert(self.clock, key);
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        if let Some((_, last_use)) = self.entries.remove(key) {
+            self.by_last_use.remove(&last_use);
+        }
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shc_common::types::Fingerprint;
+
+    fn metadata(size: u64) -> FileMetadata {
+        FileMetadata::new(
+            vec![1u8; 32],
+            vec![2u8; 32],
+            b"location".to_vec(),
+            size,
+            Fingerprint::from([3u8; 32]),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn least_recently_used_metadata_is_evicted() {
+        let mut cache = MetadataCache::new(2);
+        cache.insert(1, metadata(1));
+        cache.insert(2, metadata(2));
+
+        // Using the first one makes the second one the least recently used.
+        assert_eq!(cache.get(&1), Some(metadata(1)));
+        cache.insert(3, metadata(3));
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some(metadata(1)));
+        assert_eq!(cache.get(&3), Some(metadata(3)));
+
+        cache.remove(&1);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn cache_without_capacity_holds_nothing() {
+        let mut cache = MetadataCache::new(0);
+        cache.insert(1, metadata(1));
+        assert_eq!(cache.get(&1), None);
+    }
+}
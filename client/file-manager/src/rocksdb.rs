@@ -1,23 +1,39 @@
 use log::info;
-use std::{collections::HashSet, io, path::PathBuf, sync::Arc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    time::Duration,
+};
 
-use hash_db::{AsHashDB, HashDB, Prefix};
+use hash_db::{AsHashDB, HashDB, HashDBRef, Hasher, Prefix, EMPTY_PREFIX};
 use kvdb::{DBTransaction, KeyValueDB};
 use log::{debug, error};
 use shc_common::types::{
-    Chunk, ChunkId, ChunkWithId, FileKeyProof, FileMetadata, FileProof, HashT, HasherOutT, H_LENGTH,
+    Chunk, ChunkId, ChunkWithId, FileKeyProof, FileMetadata, FileProof, Fingerprint, HashT,
+    HasherOutT, H_LENGTH,
 };
 use sp_state_machine::{warn, Storage};
 use sp_trie::{prefixed_key, recorder::Recorder, PrefixedMemoryDB, TrieLayout, TrieMut};
 use trie_db::{DBValue, Trie, TrieDBBuilder, TrieDBMutBuilder};
 
 use crate::{
+    age_since, check_chunks_per_proof, chunk_ids_in_trie,
     error::{other_io_error, ErrorT},
+    in_memory::InMemoryFileStorage,
+    locks::FileKeyLocks,
+    metadata_cache::{MetadataCache, DEFAULT_METADATA_CACHE_CAPACITY},
+    sharded::ShardManifest,
     traits::{
-        ExcludeType, FileDataTrie, FileStorage, FileStorageError, FileStorageWriteError,
-        FileStorageWriteOutcome,
+        ExcludeType, FileDataTrie, FileStorage, FileStorageError, FileStorageStats,
+        FileStorageWriteOutcome, FileVerification, IncompleteFile, DEFAULT_MAX_CHUNKS_PER_PROOF,
     },
-    LOG_TARGET,
+    unix_timestamp_now, LOG_TARGET,
 };
 use codec::{Decode, Encode};
 use strum::EnumCount;
@@ -50,6 +66,41 @@ pub enum Column {
     ExcludeUser,
     ExcludeBucket,
     ExcludeFingerprint,
+    /// Stores keys of 32 bytes representing the [`FileMetadata::fingerprint`] with values being the number
+    /// of copies (see [`FileStorage::copy_file`]) sharing the file trie of that fingerprint.
+    ///
+    /// Used for keeping the shared file trie and its root in [`Column::Roots`] until the last file key referencing it is deleted.
+    SharedTrieCopies,
+    /// Stores keys of 32 bytes representing the `file_key` with values being the unix time (in
+    /// seconds) at which the file was inserted.
+    ///
+    /// Used for finding abandoned incomplete files. Files inserted before this column existed have
+    /// no entry, and are considered of unknown age.
+    CreatedAt,
+    /// Stores keys of 32 bytes representing the `file_key` with empty values.
+    ///
+    /// Used to mark complete files as sealed (see [`FileStorage::seal_file`]), rejecting any
+    /// further chunk written to them.
+    Sealed,
+    /// Stores the single [`CRASH_RECOVERY_FLAG`] key with an empty value.
+    ///
+    /// Used to detect that the storage was not shut down cleanly the last time it was opened
+    /// with [`RocksDbFileStorage::with_crash_recovery_flag`], in which case it may hold trie
+    /// nodes to be deleted with [`RocksDbFileStorage::check_and_repair`].
+    CrashRecovery,
+    /// Stores the single [`SHARD_MANIFEST_KEY`] key with the SCALE-encoded [`ShardManifest`] of
+    /// the storage.
+    ///
+    /// Used to refuse opening the shards of a [`ShardedFileStorage`] in another order, or with
+    /// shards added or removed, which would look files up in the wrong shard.
+    ///
+    /// [`ShardedFileStorage`]: crate::sharded::ShardedFileStorage
+    ShardManifest,
+    /// Stores the keys of the nodes of [`Column::Chunks`] reached from a root with empty values.
+    ///
+    /// Used by [`RocksDbFileStorage::check_and_repair`] to mark the nodes to keep without holding
+    /// them in memory. Emptied once the repair is done.
+    RepairMarks,
 }
 
 impl Into<u32> for Column {
@@ -61,6 +112,19 @@ impl Into<u32> for Column {
 // Replace NUMBER_OF_COLUMNS definition
 const NUMBER_OF_COLUMNS: u32 = Column::COUNT as u32;
 
+/// Number of trie nodes written at once when re-anchoring a file trie onto another storage.
+const REANCHOR_BATCH_SIZE: usize = 1024;
+
+/// Number of nodes marked, or deleted, at once when repairing the storage.
+const REPAIR_BATCH_SIZE: usize = 1024;
+
+/// Key of [`Column::CrashRecovery`] set while a [`RocksDbFileStorage`] opened with
+/// [`RocksDbFileStorage::with_crash_recovery_flag`] is open, and removed once it is shut down.
+const CRASH_RECOVERY_FLAG: &[u8] = b"crash_recovery";
+
+/// Key of [`Column::ShardManifest`] holding the [`ShardManifest`] of the storage.
+const SHARD_MANIFEST_KEY: &[u8] = b"shard_manifest";
+
 // Helper function to map ExcludeType enum to their matching rocksdb column.
 fn get_exclude_type_db_column(exclude_type: ExcludeType) -> u32 {
     match exclude_type {
@@ -89,10 +153,76 @@ fn open_or_creating_rocksdb(db_path: String) -> io::Result<kvdb_rocksdb::Databas
     Ok(db)
 }
 
+/// Compression applied to the values stored in [`Column::Chunks`].
+///
+/// It is applied to the encoded trie nodes when they are written to the database, i.e. below the
+/// file trie, so the roots and proofs of a file are the same whether its chunks are compressed
+/// or not.
+///
+/// Values are stored with a leading tag byte when compression is enabled, so the compression of a
+/// database can't be changed once chunks have been written to it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChunkCompression {
+    /// Values are stored as they are.
+    #[default]
+    None,
+    /// Values are compressed with zstd at the given level, unless that doesn't make them smaller.
+    Zstd { level: i32 },
+}
+
+/// Tag of a value stored as it is, with [`ChunkCompression`] enabled.
+const UNCOMPRESSED_VALUE_TAG: u8 = 0;
+/// Tag of a value compressed with zstd.
+const ZSTD_VALUE_TAG: u8 = 1;
+
+/// Prepends `tag` to `value`.
+fn tagged(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut stored = Vec::with_capacity(value.len() + 1);
+    stored.push(tag);
+    stored.extend_from_slice(value);
+    stored
+}
+
+impl ChunkCompression {
+    /// Encodes `value` to be stored in [`Column::Chunks`].
+    fn compress(&self, value: &[u8]) -> Vec<u8> {
+        let level = match self {
+            ChunkCompression::None => return value.to_vec(),
+            ChunkCompression::Zstd { level } => *level,
+        };
+
+        match zstd::encode_all(value, level) {
+            Ok(compressed) if compressed.len() < value.len() => tagged(ZSTD_VALUE_TAG, &compressed),
+            Ok(_) => tagged(UNCOMPRESSED_VALUE_TAG, value),
+            Err(e) => {
+                warn!(target: LOG_TARGET, "Failed to compress value, storing it uncompressed: {}", e);
+                tagged(UNCOMPRESSED_VALUE_TAG, value)
+            }
+        }
+    }
+
+    /// Decodes a value read from [`Column::Chunks`].
+    fn decompress(&self, stored: Vec<u8>) -> Result<Vec<u8>, String> {
+        if *self == ChunkCompression::None {
+            return Ok(stored);
+        }
+
+        match stored.split_first() {
+            Some((&UNCOMPRESSED_VALUE_TAG, value)) => Ok(value.to_vec()),
+            Some((&ZSTD_VALUE_TAG, compressed)) => zstd::decode_all(compressed)
+                .map_err(|e| format!("Failed to decompress value: {}", e)),
+            Some((tag, _)) => Err(format!("Unknown compression tag of stored value: {}", tag)),
+            None => Err("Stored value is missing its compression tag".to_string()),
+        }
+    }
+}
+
 /// Storage backend implementation for RocksDB.
 /// Provides low-level storage operations for the file system.
 pub struct StorageDb<T, DB> {
     pub db: Arc<DB>,
+    /// Compression of the values stored in [`Column::Chunks`].
+    pub compression: ChunkCompression,
     pub _marker: std::marker::PhantomData<T>,
 }
 
@@ -102,9 +232,18 @@ where
     DB: KeyValueDB,
     HasherOutT<T>: TryFrom<[u8; H_LENGTH]>,
 {
+    /// Creates a new [`StorageDb`] over `db`, storing chunks uncompressed.
+    pub fn new(db: Arc<DB>) -> Self {
+        Self {
+            db,
+            compression: ChunkCompression::None,
+            _marker: Default::default(),
+        }
+    }
+
     /// Writes a transaction to the database.
     /// Returns an error if the write operation fails.
-    fn write(&mut self, transaction: DBTransaction) -> Result<(), ErrorT<T>> {
+    fn write(&self, transaction: DBTransaction) -> Result<(), ErrorT<T>> {
         self.db.write(transaction).map_err(|e| {
             error!(target: LOG_TARGET, "Failed to write to DB: {}", e);
             FileStorageError::FailedToWriteToStorage
@@ -125,10 +264,39 @@ where
     }
 }
 
+impl<T: TrieLayout> StorageDb<T, kvdb_rocksdb::Database> {
+    /// Returns an approximation of the number of files stored, from RocksDB's
+    /// `rocksdb.estimate-num-keys` property of [`Column::Metadata`].
+    ///
+    /// The estimate is computed without scanning the column, so it is cheap enough for capacity
+    /// planning and dashboards, but it is only approximate: a typical error is around ±10%, and
+    /// it can be further off right after many files are deleted, until compaction catches up.
+    /// Use [`FileStorage::stats`] when an exact count is needed.
+    pub fn approximate_file_count(&self) -> Result<u64, ErrorT<T>> {
+        let count = self.db.num_keys(Column::Metadata.into()).map_err(|e| {
+            warn!(target: LOG_TARGET, "Failed to estimate the number of files: {}", e);
+            FileStorageError::FailedToReadStorage
+        })?;
+
+        Ok(count)
+    }
+}
+
+impl<T, DB> StorageDb<T, DB> {
+    /// Sets the compression of the values stored in [`Column::Chunks`].
+    ///
+    /// See [`ChunkCompression`] for why it has to be the same every time the database is opened.
+    pub fn with_compression(mut self, compression: ChunkCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+}
+
 impl<T, DB> Clone for StorageDb<T, DB> {
     fn clone(&self) -> Self {
         Self {
             db: self.db.clone(),
+            compression: self.compression,
             _marker: self._marker,
         }
     }
@@ -142,8 +310,104 @@ impl<T: TrieLayout + Send + Sync, DB: KeyValueDB> Storage<HashT<T>> for StorageD
             .map_err(|e| {
                 warn!(target: LOG_TARGET, "Failed to read from DB: {}", e);
                 format!("Failed to read from DB: {}", e)
+            })?
+            .map(|stored| {
+                self.compression.decompress(stored).map_err(|e| {
+                    warn!(target: LOG_TARGET, "{}", e);
+                    e
+                })
             })
+            .transpose()
+    }
+}
+
+/// Reads at most `limit` keys of `column` that are greater than `start_key` (or all of them if
+/// `None`), in ascending order.
+///
+/// [`KeyValueDB`] cannot seek to an arbitrary key, only to the first key with a given prefix, so
+/// the keys after `start_key` are reached through the prefixes of those that share fewer bytes
+/// with it: first the keys extending it, then, from the longest shared prefix down, the keys
+/// whose first byte differing from it is greater. The length of the longest prefix shared with
+/// another key is found by a binary search, so the keys before `start_key` are never iterated
+/// over and a page costs `O(limit)` reads, plus at most a few hundred seeks.
+fn keys_after<DB: KeyValueDB>(
+    db: &DB,
+    column: u32,
+    start_key: Option<&[u8]>,
+    limit: usize,
+) -> io::Result<Vec<Vec<u8>>> {
+    let mut keys = Vec::new();
+    if limit == 0 {
+        return Ok(keys);
+    }
+
+    let Some(start_key) = start_key else {
+        for entry in db.iter(column).take(limit) {
+            let (key, _) = entry?;
+            keys.push(key.to_vec());
+        }
+        return Ok(keys);
+    };
+
+    // Whether a key other than `start_key` starts with its first `prefix_len` bytes.
+    let has_other_key = |prefix_len: usize| -> io::Result<bool> {
+        for entry in db
+            .iter_with_prefix(column, &start_key[..prefix_len])
+            .take(2)
+        {
+            let (key, _) = entry?;
+            if key.as_ref() != start_key {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    };
+    if !has_other_key(0)? {
+        return Ok(keys);
+    }
+
+    // Length of the longest prefix of `start_key` shared by another key, of which all the shorter
+    // prefixes are shared as well.
+    let (mut shared, mut not_shared) = (0, start_key.len() + 1);
+    while not_shared - shared > 1 {
+        let prefix_len = (shared + not_shared) / 2;
+        if has_other_key(prefix_len)? {
+            shared = prefix_len;
+        } else {
+            not_shared = prefix_len;
+        }
+    }
+
+    // Keys extending the start key come right after it.
+    if shared == start_key.len() {
+        for entry in db.iter_with_prefix(column, start_key) {
+            let (key, _) = entry?;
+            if key.as_ref() == start_key {
+                continue;
+            }
+            keys.push(key.to_vec());
+            if keys.len() == limit {
+                return Ok(keys);
+            }
+        }
     }
+
+    // Then the keys whose first byte differing from the start key, at `position`, is greater.
+    for position in (0..(shared + 1).min(start_key.len())).rev() {
+        let mut prefix = start_key[..=position].to_vec();
+        for byte in (start_key[position]..=u8::MAX).skip(1) {
+            prefix[position] = byte;
+            for entry in db.iter_with_prefix(column, &prefix) {
+                let (key, _) = entry?;
+                keys.push(key.to_vec());
+                if keys.len() == limit {
+                    return Ok(keys);
+                }
+            }
+        }
+    }
+
+    Ok(keys)
 }
 
 /// Converts raw bytes into a [`HasherOutT<T>`].
@@ -165,6 +429,156 @@ where
     Ok(key)
 }
 
+/// Decodes a unix time (in seconds) stored in [`Column::CreatedAt`].
+fn decode_created_at(raw_created_at: &[u8]) -> Result<u64, FileStorageError> {
+    let bytes: [u8; 8] = raw_created_at.try_into().map_err(|e| {
+        error!(target: LOG_TARGET, "{:?}", e);
+        FileStorageError::FailedToReadStorage
+    })?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Builds a database transaction writing the nodes of `overlay` to [`Column::Chunks`], and
+/// clears it.
+fn overlay_changes<T: TrieLayout>(
+    overlay: &mut PrefixedMemoryDB<HashT<T>>,
+    compression: ChunkCompression,
+) -> DBTransaction {
+    let mut transaction = DBTransaction::new();
+
+    for (key, (value, rc)) in overlay.drain() {
+        if rc <= 0 {
+            transaction.delete(Column::Chunks.into(), &key);
+        } else {
+            transaction.put_vec(Column::Chunks.into(), &key, compression.compress(&value));
+        }
+    }
+
+    transaction
+}
+
+/// Counts the chunks of the file trie with `root`, reading its nodes from `db`.
+fn count_chunks<T: TrieLayout>(
+    db: &dyn HashDBRef<HashT<T>, DBValue>,
+    root: &HasherOutT<T>,
+) -> Result<u64, FileStorageError> {
+    let trie = TrieDBBuilder::<T>::new(db, root).build();
+
+    let chunk_count = trie
+        .iter()
+        .map_err(|e| {
+            error!(target: LOG_TARGET, "Failed to construct Trie iterator: {}", e);
+            FileStorageError::FailedToConstructTrieIter
+        })?
+        .count();
+
+    Ok(chunk_count as u64)
+}
+
+/// Counts the chunks of the file trie with `root`, reading its nodes from `db`.
+///
+/// Unlike [`count_chunks`], every chunk is read, so it fails if any node of the trie is missing.
+fn count_readable_chunks<T: TrieLayout>(
+    db: &dyn HashDBRef<HashT<T>, DBValue>,
+    root: &HasherOutT<T>,
+) -> Result<u64, FileStorageError> {
+    let trie = TrieDBBuilder::<T>::new(db, root).build();
+
+    let mut chunk_count = 0;
+    for chunk in trie.iter().map_err(|e| {
+        error!(target: LOG_TARGET, "Failed to construct Trie iterator: {}", e);
+        FileStorageError::FailedToConstructTrieIter
+    })? {
+        chunk.map_err(|e| {
+            error!(target: LOG_TARGET, "Failed to read file chunk: {}", e);
+            FileStorageError::FailedToGetFileChunk
+        })?;
+        chunk_count += 1;
+    }
+
+    Ok(chunk_count)
+}
+
+/// Read-only view of the nodes of a file trie, which queues a copy of every node read to be
+/// written to the [`Column::Chunks`] of another storage.
+///
+/// Traversing a trie through it copies exactly the nodes reachable from its root, with the same
+/// prefixed keys.
+struct NodeCopier<'a, T: TrieLayout> {
+    source: &'a dyn HashDB<HashT<T>, DBValue>,
+    copied: RefCell<DBTransaction>,
+    /// Compression of the storage the nodes are copied to.
+    compression: ChunkCompression,
+}
+
+impl<'a, T: TrieLayout> HashDBRef<HashT<T>, DBValue> for NodeCopier<'a, T> {
+    fn get(&self, key: &HasherOutT<T>, prefix: Prefix) -> Option<DBValue> {
+        let value = HashDB::get(self.source, key, prefix)?;
+        self.copied.borrow_mut().put_vec(
+            Column::Chunks.into(),
+            &prefixed_key::<HashT<T>>(key, prefix),
+            self.compression.compress(&value),
+        );
+        Some(value)
+    }
+
+    fn contains(&self, key: &HasherOutT<T>, prefix: Prefix) -> bool {
+        HashDB::contains(self.source, key, prefix)
+    }
+}
+
+/// Read-only view of the nodes of a file trie, which marks the key in [`Column::Chunks`] of
+/// every node read in [`Column::RepairMarks`].
+///
+/// Traversing tries through it marks exactly the nodes reachable from their roots. The marks are
+/// written every [`REPAIR_BATCH_SIZE`] nodes, and a node whose mark fails to be written is
+/// reported as missing, so that the traversal fails.
+struct NodeMarker<'a, T: TrieLayout, DB> {
+    source: &'a dyn HashDB<HashT<T>, DBValue>,
+    storage: &'a StorageDb<T, DB>,
+    marks: &'a RefCell<DBTransaction>,
+}
+
+impl<'a, T, DB> NodeMarker<'a, T, DB>
+where
+    T: TrieLayout,
+    DB: KeyValueDB,
+    HasherOutT<T>: TryFrom<[u8; H_LENGTH]>,
+{
+    /// Writes the marks queued so far.
+    fn write_marks(&self) -> Result<(), FileStorageError> {
+        let marks = std::mem::take(&mut *self.marks.borrow_mut());
+        self.storage.write(marks).map_err(|e| {
+            error!(target: LOG_TARGET, "Failed to write the repair marks: {:?}", e);
+            FileStorageError::FailedToWriteToStorage
+        })
+    }
+}
+
+impl<'a, T, DB> HashDBRef<HashT<T>, DBValue> for NodeMarker<'a, T, DB>
+where
+    T: TrieLayout,
+    DB: KeyValueDB,
+    HasherOutT<T>: TryFrom<[u8; H_LENGTH]>,
+{
+    fn get(&self, key: &HasherOutT<T>, prefix: Prefix) -> Option<DBValue> {
+        let value = HashDB::get(self.source, key, prefix)?;
+        self.marks.borrow_mut().put(
+            Column::RepairMarks.into(),
+            &prefixed_key::<HashT<T>>(key, prefix),
+            &[],
+        );
+        if self.marks.borrow().ops.len() >= REPAIR_BATCH_SIZE {
+            self.write_marks().ok()?;
+        }
+        Some(value)
+    }
+
+    fn contains(&self, key: &HasherOutT<T>, prefix: Prefix) -> bool {
+        HashDB::contains(self.source, key, prefix)
+    }
+}
+
 /// File data trie implementation using RocksDB for persistent storage.
 /// Manages file chunks and their proofs in a merkle trie structure.
 pub struct RocksDbFileDataTrie<T: TrieLayout, DB> {
@@ -224,19 +638,168 @@ where
         Ok(())
     }
 
-    /// Builds a database transaction from the overlay and clears it.
-    fn changes(&mut self) -> DBTransaction {
-        let mut transaction = DBTransaction::new();
+    /// Moves this trie onto `new_storage`, returning a trie with the same root bound to it.
+    ///
+    /// All the nodes reachable from the root are copied while traversing the trie, and written in
+    /// batches of [`REANCHOR_BATCH_SIZE`] nodes, so that files of any size can be moved without
+    /// holding them in memory. The nodes are left in the current storage.
+    ///
+    /// Fails if the root node copied to `new_storage` does not hash to the root of this trie. The
+    /// nodes copied so far are not removed from `new_storage` in that case.
+    pub fn reanchor(self, new_storage: StorageDb<T, DB>) -> Result<Self, ErrorT<T>> {
+        let copier = NodeCopier::<T> {
+            source: self.as_hash_db(),
+            copied: RefCell::new(DBTransaction::new()),
+            compression: new_storage.compression,
+        };
 
-        for (key, (value, rc)) in self.overlay.drain() {
-            if rc <= 0 {
-                transaction.delete(Column::Chunks.into(), &key);
-            } else {
-                transaction.put_vec(Column::Chunks.into(), &key, value);
+        {
+            let trie = TrieDBBuilder::<T>::new(&copier, &self.root).build();
+            let trie_iter = trie.iter().map_err(|e| {
+                error!(target: LOG_TARGET, "Failed to construct Trie iterator: {}", e);
+                FileStorageError::FailedToConstructTrieIter
+            })?;
+
+            // Reading every chunk visits every node of the trie, including the values stored
+            // out of their leaves.
+            for item in trie_iter {
+                item.map_err(|e| {
+                    error!(target: LOG_TARGET, "Failed to read file chunk to re-anchor: {}", e);
+                    FileStorageError::FailedToGetFileChunk
+                })?;
+
+                if copier.copied.borrow().ops.len() >= REANCHOR_BATCH_SIZE {
+                    new_storage.write(copier.copied.take())?;
+                }
             }
         }
 
-        transaction
+        new_storage.write(copier.copied.take())?;
+
+        // Check that the root node made it to the new storage unchanged.
+        let root_node = new_storage
+            .get(&self.root, EMPTY_PREFIX)
+            .map_err(|e| {
+                error!(target: LOG_TARGET, "{}", e);
+                FileStorageError::FailedToReadStorage
+            })?
+            .ok_or(FileStorageError::ReanchoredRootMismatch)?;
+        if <HashT<T> as Hasher>::hash(&root_node) != self.root {
+            error!(
+                target: LOG_TARGET,
+                "Root of re-anchored file trie does not match the original root {:?}", self.root
+            );
+            return Err(FileStorageError::ReanchoredRootMismatch.into());
+        }
+
+        Ok(Self::from_existing(new_storage, &self.root))
+    }
+
+    /// Removes all the chunks of this file trie, returning the deletion of its nodes as a
+    /// transaction instead of writing it, so that it can be committed along with other changes.
+    ///
+    /// The root of the trie is set to the empty root.
+    fn removal(&mut self) -> Result<DBTransaction, FileStorageError> {
+        let mut root = self.root;
+        let db = self.as_hash_db_mut();
+        let trie_root_key = root;
+        let mut trie = TrieDBMutBuilder::<T>::from_existing(db, &mut root).build();
+
+        let mut chunk_id = 0;
+        loop {
+            let chunk_id_struct = ChunkId::new(chunk_id as u64);
+            if !trie.contains(&chunk_id_struct.as_trie_key()).map_err(|e| {
+                error!(target: LOG_TARGET, "Failed to check if chunk exists: {}", e);
+                FileStorageError::FailedToDeleteFileChunk
+            })? {
+                break;
+            }
+
+            trie.remove(&chunk_id_struct.as_trie_key()).map_err(|e| {
+                error!(target: LOG_TARGET, "Failed to delete chunk from RocksDb: {}", e);
+                FileStorageError::FailedToDeleteFileChunk
+            })?;
+
+            chunk_id += 1;
+        }
+
+        // Remove the root from the trie.
+        trie.remove(trie_root_key.as_ref()).map_err(|e| {
+            error!(target: LOG_TARGET, "Failed to delete root from RocksDb: {}", e);
+            FileStorageError::FailedToDeleteRoot
+        })?;
+
+        let new_root = *trie.root();
+
+        drop(trie);
+
+        // Set new internal root (empty trie root)
+        self.root = new_root;
+
+        Ok(self.changes())
+    }
+
+    /// Inserts the chunk with `chunk_id` in this file trie, returning the new nodes as a
+    /// transaction instead of writing it, so that it can be committed along with other changes.
+    ///
+    /// Fails if the chunk is already in the trie.
+    fn insert_chunk(
+        &mut self,
+        chunk_id: &ChunkId,
+        data: &Chunk,
+    ) -> Result<DBTransaction, FileStorageError> {
+        self.insert_chunk_in_overlay(chunk_id, data)?;
+
+        Ok(self.changes())
+    }
+
+    /// Inserts the chunk with `chunk_id` in this file trie, keeping the new nodes in the overlay
+    /// until [`Self::changes`] are taken.
+    ///
+    /// Fails if the chunk is already in the trie.
+    fn insert_chunk_in_overlay(
+        &mut self,
+        chunk_id: &ChunkId,
+        data: &Chunk,
+    ) -> Result<(), FileStorageError> {
+        let mut current_root = self.root;
+        let db = self.as_hash_db_mut();
+        let mut trie = TrieDBMutBuilder::<T>::from_existing(db, &mut current_root).build();
+
+        // Check that we don't have a chunk already stored.
+        if trie.contains(&chunk_id.as_trie_key()).map_err(|e| {
+            error!(target: LOG_TARGET, "Failed to fetch chunk: {}", e);
+            FileStorageError::FailedToGetFileChunk
+        })? {
+            return Err(FileStorageError::FileChunkAlreadyExists);
+        }
+
+        // Insert the encoded chunk with its ID into the file trie.
+        let decoded_chunk = ChunkWithId {
+            chunk_id: *chunk_id,
+            data: data.clone(),
+        };
+        let encoded_chunk = decoded_chunk.encode();
+        trie.insert(&chunk_id.as_trie_key(), &encoded_chunk)
+            .map_err(|e| {
+                error!(target: LOG_TARGET, "{}", e);
+                FileStorageError::FailedToInsertFileChunk
+            })?;
+
+        // Get new root after trie modifications
+        let new_root = *trie.root();
+
+        // Drop trie to commit to the overlay and release `self`
+        drop(trie);
+
+        self.root = new_root;
+
+        Ok(())
+    }
+
+    /// Builds a database transaction from the overlay and clears it.
+    fn changes(&mut self) -> DBTransaction {
+        overlay_changes::<T>(&mut self.overlay, self.storage.compression)
     }
 
     /// Open the RocksDB database at `db_path` and return a new instance of [`StorageDb`].
@@ -248,10 +811,7 @@ where
             FileStorageError::FailedToReadStorage
         })?;
 
-        Ok(StorageDb {
-            db: Arc::new(db),
-            _marker: Default::default(),
-        })
+        Ok(StorageDb::new(Arc::new(db)))
     }
 }
 
@@ -341,98 +901,36 @@ where
     // TODO: make it accept a list of chunks to be written
     /// Writes a chunk to the trie with its ID.
     /// Returns error if write fails or chunk already exists.
-    fn write_chunk(
-        &mut self,
-        chunk_id: &ChunkId,
-        data: &Chunk,
-    ) -> Result<(), FileStorageWriteError> {
-        let mut current_root = self.root;
-        let db = self.as_hash_db_mut();
-        let mut trie = TrieDBMutBuilder::<T>::from_existing(db, &mut current_root).build();
+    fn write_chunk(&mut self, chunk_id: &ChunkId, data: &Chunk) -> Result<(), FileStorageError> {
+        let transaction = self.insert_chunk(chunk_id, data)?;
 
-        // Check that we don't have a chunk already stored.
-        if trie.contains(&chunk_id.as_trie_key()).map_err(|e| {
-            error!(target: LOG_TARGET, "Failed to fetch chunk: {}", e);
-            FileStorageWriteError::FailedToGetFileChunk
-        })? {
-            return Err(FileStorageWriteError::FileChunkAlreadyExists);
-        }
-
-        // Insert the encoded chunk with its ID into the file trie.
-        let decoded_chunk = ChunkWithId {
-            chunk_id: *chunk_id,
-            data: data.clone(),
-        };
-        let encoded_chunk = decoded_chunk.encode();
-        trie.insert(&chunk_id.as_trie_key(), &encoded_chunk)
-            .map_err(|e| {
-                error!(target: LOG_TARGET, "{}", e);
-                FileStorageWriteError::FailedToInsertFileChunk
-            })?;
-
-        // Get new root after trie modifications
-        let new_root = *trie.root();
-
-        // Drop trie to commit to underlying db and release `self`
-        drop(trie);
-
-        // TODO: improve error handling
-        // Commit the changes to disk.
-        self.commit(new_root).map_err(|e| {
-            error!(target: LOG_TARGET, "Failed to commit changes to persistent storage: {}", e);
-            FileStorageWriteError::FailedToPersistChanges
-        })?;
+        // TODO: improve error handling
+        // Commit the changes to disk.
+        self.storage.write(transaction).map_err(|e| {
+            error!(target: LOG_TARGET, "Failed to commit changes to persistent storage: {}", e);
+            FileStorageError::FailedToWriteToStorage
+        })?;
 
         Ok(())
     }
 
     /// Deletes all chunks and data associated with this file trie.
-    fn delete(&mut self) -> Result<(), FileStorageWriteError> {
-        let mut root = self.root;
-        let db = self.as_hash_db_mut();
-        let trie_root_key = root;
-        let mut trie = TrieDBMutBuilder::<T>::from_existing(db, &mut root).build();
-
-        let mut chunk_id = 0;
-        loop {
-            let chunk_id_struct = ChunkId::new(chunk_id as u64);
-            if !trie.contains(&chunk_id_struct.as_trie_key()).map_err(|e| {
-                error!(target: LOG_TARGET, "Failed to check if chunk exists: {}", e);
-                FileStorageWriteError::FailedToDeleteChunk
-            })? {
-                break;
-            }
-
-            trie.remove(&chunk_id_struct.as_trie_key()).map_err(|e| {
-                error!(target: LOG_TARGET, "Failed to delete chunk from RocksDb: {}", e);
-                FileStorageWriteError::FailedToDeleteChunk
-            })?;
-
-            chunk_id += 1;
-        }
-
-        // Remove the root from the trie.
-        trie.remove(trie_root_key.as_ref()).map_err(|e| {
-            error!(target: LOG_TARGET, "Failed to delete root from RocksDb: {}", e);
-            FileStorageWriteError::FailedToDeleteRoot
-        })?;
-
-        let new_root = *trie.root();
-
-        drop(trie);
+    fn delete(&mut self) -> Result<(), FileStorageError> {
+        let transaction = self.removal()?;
 
         // TODO: improve error handling
         // Commit the changes to disk.
-        self.commit(new_root).map_err(|e| {
+        self.storage.write(transaction).map_err(|e| {
             error!(target: LOG_TARGET, "Failed to commit changes to persistent storage: {}", e);
-            FileStorageWriteError::FailedToPersistChanges
+            FileStorageError::FailedToWriteToStorage
         })?;
 
-        // Set new internal root (empty trie root)
-        self.root = new_root;
-
         Ok(())
     }
+
+    fn chunk_ids(&self) -> Result<Vec<ChunkId>, FileStorageError> {
+        chunk_ids_in_trie::<T>(&self.as_hash_db(), &self.root)
+    }
 }
 
 impl<T, DB> AsHashDB<HashT<T>, DBValue> for RocksDbFileDataTrie<T, DB>
@@ -481,6 +979,125 @@ where
     }
 }
 
+/// Configuration of the coalescing of chunk writes, see
+/// [`RocksDbFileStorage::with_write_coalescing`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WriteCoalescing {
+    /// Time after which the chunks written are flushed, however few they are.
+    ///
+    /// The File Storage doesn't flush them by itself: whoever owns it is expected to call
+    /// [`RocksDbFileStorage::flush`] at this interval, e.g. from a task of the node.
+    pub flush_interval: Duration,
+    /// Number of chunks written after which they are flushed, without waiting for the interval.
+    pub max_pending_chunks: usize,
+}
+
+/// Outcome of [`RocksDbFileStorage::check_and_repair`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Number of nodes deleted from [`Column::Chunks`] as they were not reachable from any root.
+    pub orphaned_nodes_deleted: u64,
+    /// Number of roots in [`Column::Roots`] whose trie was fully traversed.
+    pub roots_validated: u64,
+}
+
+/// A file with chunks written since the last flush.
+struct PendingFile<T: TrieLayout> {
+    /// The nodes of the trie of the file changed since the last flush.
+    overlay: PrefixedMemoryDB<HashT<T>>,
+    root: HasherOutT<T>,
+    fingerprint: Fingerprint,
+    chunk_count: u64,
+    /// Number of chunks of the file written since the last flush.
+    pending_chunks: usize,
+}
+
+impl<T: TrieLayout> PendingFile<T> {
+    /// Adds to `transaction` the writes of the nodes of the pending chunks, along with the
+    /// partial root and chunk count of the file with `file_key`.
+    fn write_to(
+        mut self,
+        file_key: &HasherOutT<T>,
+        transaction: &mut DBTransaction,
+        compression: ChunkCompression,
+    ) {
+        transaction
+            .ops
+            .extend(overlay_changes::<T>(&mut self.overlay, compression).ops);
+        transaction.put(
+            Column::Roots.into(),
+            self.fingerprint.as_ref(),
+            self.root.as_ref(),
+        );
+        transaction.put(
+            Column::ChunkCount.into(),
+            file_key.as_ref(),
+            &self.chunk_count.to_le_bytes(),
+        );
+    }
+}
+
+/// A file with chunks written since the last flush, locked on its own so that the chunks of
+/// different files are inserted in their tries concurrently.
+///
+/// It is `None` until the first chunk written since the last flush reads the file from the
+/// database.
+type PendingFileLock<T> = Arc<Mutex<Option<PendingFile<T>>>>;
+
+/// Keeps the chunks written in memory, and writes them to the database in a single transaction
+/// once enough of them are pending or [`RocksDbFileStorage::flush`] is called.
+struct WriteCoalescer<T: TrieLayout, DB: KeyValueDB> {
+    storage: StorageDb<T, DB>,
+    max_pending_chunks: usize,
+    /// The files with chunks written since the last flush.
+    ///
+    /// It is locked only to look a file up, and while flushing, so that no file is read from the
+    /// database before its pending writes are written to it.
+    files: Mutex<HashMap<HasherOutT<T>, PendingFileLock<T>>>,
+    /// Number of chunks written since the last flush, across all files.
+    pending_chunks: AtomicUsize,
+}
+
+impl<T, DB> WriteCoalescer<T, DB>
+where
+    T: TrieLayout,
+    DB: KeyValueDB,
+    HasherOutT<T>: TryFrom<[u8; H_LENGTH]>,
+{
+    fn files(&self) -> MutexGuard<'_, HashMap<HasherOutT<T>, PendingFileLock<T>>> {
+        self.files.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Writes all the pending chunk writes in a single transaction, waiting for the chunks being
+    /// inserted in their tries.
+    ///
+    /// The pending writes are dropped even if the transaction fails, as if the node had crashed,
+    /// so that the next chunks written are inserted in the tries as stored in the database.
+    fn flush(&self) -> Result<(), ErrorT<T>> {
+        let mut files = self.files();
+        if files.is_empty() {
+            return Ok(());
+        }
+
+        let mut transaction = DBTransaction::new();
+        let mut flushed_chunks = 0;
+        for (file_key, pending_file) in files.drain() {
+            let pending_file = pending_file
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take();
+            if let Some(pending_file) = pending_file {
+                flushed_chunks += pending_file.pending_chunks;
+                pending_file.write_to(&file_key, &mut transaction, self.storage.compression);
+            }
+        }
+        self.pending_chunks
+            .fetch_sub(flushed_chunks, Ordering::Relaxed);
+
+        self.storage.write(transaction)
+    }
+}
+
 /// Manages file metadata, chunks, and proofs using RocksDB as backend.
 pub struct RocksDbFileStorage<T, DB>
 where
@@ -489,6 +1106,33 @@ where
     HasherOutT<T>: TryFrom<[u8; H_LENGTH]>,
 {
     storage: StorageDb<T, DB>,
+    /// Serialise the writes of each file, which read and update its partial root and chunk count.
+    locks: FileKeyLocks,
+    /// Maximum number of chunks proven in a single proof.
+    max_chunks_per_proof: u64,
+    /// Metadata of the files recently read, which is needed for every chunk written.
+    metadata_cache: Mutex<MetadataCache<HasherOutT<T>>>,
+    /// Chunk writes kept in memory until they are flushed, if coalescing them is enabled.
+    coalescer: Option<WriteCoalescer<T, DB>>,
+    /// Whether this instance set the crash recovery flag, which it clears once shut down.
+    crash_recovery_flag: bool,
+    /// Whether the crash recovery flag was left set by the last instance opened with it.
+    shut_down_uncleanly: bool,
+}
+
+impl<T, DB> Drop for RocksDbFileStorage<T, DB>
+where
+    T: TrieLayout + 'static,
+    DB: KeyValueDB,
+    HasherOutT<T>: TryFrom<[u8; H_LENGTH]>,
+{
+    fn drop(&mut self) {
+        // Dropping the storage doesn't mean that it was shut down cleanly (e.g. while unwinding
+        // a panic), so the crash recovery flag is only cleared by `FileStorage::shut_down`.
+        if let Err(e) = self.flush() {
+            error!(target: LOG_TARGET, "{:?}", e);
+        }
+    }
 }
 
 impl<T: TrieLayout, DB> RocksDbFileStorage<T, DB>
@@ -499,91 +1143,226 @@ where
 {
     /// Creates a new file storage instance with the given storage backend.
     pub fn new(storage: StorageDb<T, DB>) -> Self {
-        Self { storage }
+        Self {
+            storage,
+            locks: FileKeyLocks::default(),
+            max_chunks_per_proof: DEFAULT_MAX_CHUNKS_PER_PROOF,
+            metadata_cache: Mutex::new(MetadataCache::new(DEFAULT_METADATA_CACHE_CAPACITY)),
+            coalescer: None,
+            crash_recovery_flag: false,
+            shut_down_uncleanly: false,
+        }
     }
 
-    /// Open the RocksDB database at `db_path` and return a new instance of [`StorageDb`].
-    pub fn rocksdb_storage(
-        db_path: String,
-    ) -> Result<StorageDb<T, kvdb_rocksdb::Database>, ErrorT<T>> {
-        let db = open_or_creating_rocksdb(db_path).map_err(|e| {
-            warn!(target: LOG_TARGET, "Failed to open RocksDB: {}", e);
-            FileStorageError::FailedToReadStorage
-        })?;
+    /// Sets the crash recovery flag of the storage until it is shut down with
+    /// [`FileStorage::shut_down`], so that a crash can be detected when it is opened again.
+    ///
+    /// Tells whether the last instance opened with the flag was not shut down cleanly, see
+    /// [`Self::was_shut_down_cleanly`]. Only the main instance of a database should set the flag:
+    /// other instances opened on the same database leave it untouched.
+    pub fn with_crash_recovery_flag(mut self) -> Self {
+        match self
+            .storage
+            .read(Column::CrashRecovery.into(), CRASH_RECOVERY_FLAG)
+        {
+            Ok(flag) => self.shut_down_uncleanly = flag.is_some(),
+            Err(e) => error!(target: LOG_TARGET, "Failed to read the crash recovery flag: {:?}", e),
+        }
 
-        Ok(StorageDb {
-            db: Arc::new(db),
-            _marker: Default::default(),
-        })
+        let mut transaction = DBTransaction::new();
+        transaction.put(Column::CrashRecovery.into(), CRASH_RECOVERY_FLAG, &[]);
+        match self.storage.write(transaction) {
+            Ok(()) => self.crash_recovery_flag = true,
+            Err(e) => error!(target: LOG_TARGET, "Failed to set the crash recovery flag: {:?}", e),
+        }
+
+        self
     }
 
-    /// Constructs a [`RocksDbFileDataTrie`] from the given [`FileMetadata`].
+    /// Whether the storage was shut down cleanly the last time it was opened with
+    /// [`Self::with_crash_recovery_flag`]. If not, e.g. because the node crashed, it may hold
+    /// trie nodes left orphaned, which [`Self::check_and_repair`] deletes.
+    pub fn was_shut_down_cleanly(&self) -> bool {
+        !self.shut_down_uncleanly
+    }
+
+    /// Sets the maximum number of chunks proven in a single [`FileStorage::generate_proof`] call.
     ///
-    /// Since files can be partially uploaded (i.e. not all chunks have been inserted to result in the root being the file metadata's fingerprint),
-    /// the constructed trie is based on the current `partial_root` representing the current state of the file we are interested in.
-    fn get_file_trie(
-        &self,
-        metadata: &FileMetadata,
-    ) -> Result<RocksDbFileDataTrie<T, DB>, FileStorageError>
+    /// Defaults to [`DEFAULT_MAX_CHUNKS_PER_PROOF`].
+    pub fn with_max_chunks_per_proof(mut self, max_chunks_per_proof: u64) -> Self {
+        self.max_chunks_per_proof = max_chunks_per_proof;
+        self
+    }
+
+    /// Sets the number of file metadata kept in memory, so that it is not read from the database
+    /// for every chunk written. A capacity of 0 disables the cache.
+    ///
+    /// Defaults to [`DEFAULT_METADATA_CACHE_CAPACITY`].
+    pub fn with_metadata_cache_capacity(mut self, capacity: usize) -> Self {
+        self.metadata_cache = Mutex::new(MetadataCache::new(capacity));
+        self
+    }
+
+    /// Coalesces the chunks written into a single transaction, written once
+    /// `max_pending_chunks` chunks are pending or [`Self::flush`] is called, which the owner of
+    /// the storage is expected to do every `flush_interval`.
+    ///
+    /// Until then, the chunks are only in memory: a crash loses the chunks written since the last
+    /// flush. They were already acknowledged to the peers uploading them, which don't send them
+    /// again, so the file stays incomplete until it is uploaded again or deleted. A file is
+    /// flushed as soon as it is complete, and everything is flushed before reading the data of a
+    /// file (e.g. to check whether it is complete or to prove its chunks) and when the storage is
+    /// dropped.
+    ///
+    /// The chunks of a file are inserted in its trie under the lock of the file only, so the
+    /// chunks of different files are still written concurrently.
+    ///
+    /// Coalescing is disabled by default, writing every chunk in its own transaction.
+    pub fn with_write_coalescing(mut self, config: WriteCoalescing) -> Self {
+        self.coalescer = Some(WriteCoalescer {
+            storage: self.storage.clone(),
+            max_pending_chunks: config.max_pending_chunks,
+            files: Mutex::new(HashMap::new()),
+            pending_chunks: AtomicUsize::new(0),
+        });
+        self
+    }
+
+    /// Deletes the trie nodes of [`Column::Chunks`] that are not reachable from any root of
+    /// [`Column::Roots`], e.g. the nodes of a file whose deletion was interrupted.
+    ///
+    /// Every trie is traversed, marking the nodes reached in [`Column::RepairMarks`], and the
+    /// nodes left unmarked are then deleted. Both are written in batches of
+    /// [`REPAIR_BATCH_SIZE`] nodes, so the memory used doesn't grow with the size of the storage.
+    /// Nothing is deleted if any trie cannot be fully traversed, as the nodes below a missing one
+    /// would be taken for orphaned.
+    ///
+    /// Takes as long as reading the whole storage, during which nothing else can be written to
+    /// it.
+    pub fn check_and_repair(&mut self) -> Result<RepairReport, FileStorageError>
     where
         T: TrieLayout + Send + Sync + 'static,
         DB: KeyValueDB + 'static,
     {
-        let b_fingerprint = metadata.fingerprint().as_ref();
-        let h_fingerprint =
-            convert_raw_bytes_to_hasher_out::<T>(b_fingerprint.to_vec()).map_err(|e| {
-                error!(target: LOG_TARGET, "{:?}", e);
-                FileStorageError::FailedToParseFingerprint
-            })?;
-        let raw_partial_root = self
-            .storage
-            .read(Column::Roots.into(), h_fingerprint.as_ref())
-            .map_err(|e| {
+        self.flush()?;
+
+        // Marks left by an interrupted repair could keep orphaned nodes.
+        self.clear_repair_marks()?;
+        let result = self.mark_and_sweep();
+        self.clear_repair_marks()?;
+        result
+    }
+
+    fn mark_and_sweep(&self) -> Result<RepairReport, FileStorageError>
+    where
+        T: TrieLayout + Send + Sync + 'static,
+        DB: KeyValueDB + 'static,
+    {
+        let marks = RefCell::new(DBTransaction::new());
+        let mut roots_validated = 0;
+        for entry in self.storage.db.iter(Column::Roots.into()) {
+            let (_, raw_root) = entry.map_err(|e| {
                 error!(target: LOG_TARGET, "{:?}", e);
                 FileStorageError::FailedToReadStorage
-            })?
-            .expect("Failed to find partial root");
-        let mut partial_root =
-            convert_raw_bytes_to_hasher_out::<T>(raw_partial_root).map_err(|e| {
+            })?;
+            let root = convert_raw_bytes_to_hasher_out::<T>(raw_root.to_vec()).map_err(|e| {
                 error!(target: LOG_TARGET, "{:?}", e);
                 FileStorageError::FailedToParsePartialRoot
             })?;
-        let file_trie =
-            RocksDbFileDataTrie::<T, DB>::from_existing(self.storage.clone(), &mut partial_root);
-        Ok(file_trie)
+
+            let file_trie =
+                RocksDbFileDataTrie::<T, DB>::from_existing(self.storage.clone(), &root);
+            let marker = NodeMarker::<T, DB> {
+                source: file_trie.as_hash_db(),
+                storage: &self.storage,
+                marks: &marks,
+            };
+            // Reading every chunk visits every node of the trie, including the values stored
+            // out of their leaves.
+            count_readable_chunks::<T>(&marker, &root)?;
+            marker.write_marks()?;
+            roots_validated += 1;
+        }
+
+        let mut orphaned_nodes_deleted = 0;
+        let mut transaction = DBTransaction::new();
+        for entry in self.storage.db.iter(Column::Chunks.into()) {
+            let (key, _) = entry.map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToReadStorage
+            })?;
+            let marked = self
+                .storage
+                .db
+                .has_key(Column::RepairMarks.into(), &key)
+                .map_err(|e| {
+                    error!(target: LOG_TARGET, "{:?}", e);
+                    FileStorageError::FailedToReadStorage
+                })?;
+            if !marked {
+                transaction.delete(Column::Chunks.into(), &key);
+            }
+
+            if transaction.ops.len() >= REPAIR_BATCH_SIZE {
+                orphaned_nodes_deleted += transaction.ops.len() as u64;
+                self.write_repair_batch(std::mem::take(&mut transaction))?;
+            }
+        }
+        orphaned_nodes_deleted += transaction.ops.len() as u64;
+        self.write_repair_batch(transaction)?;
+
+        if orphaned_nodes_deleted > 0 {
+            warn!(target: LOG_TARGET, "Deleted {} orphaned trie nodes", orphaned_nodes_deleted);
+        }
+
+        Ok(RepairReport {
+            orphaned_nodes_deleted,
+            roots_validated,
+        })
     }
-}
 
-impl<T, DB> FileStorage<T> for RocksDbFileStorage<T, DB>
-where
-    T: TrieLayout + Send + Sync + 'static,
-    DB: KeyValueDB + 'static,
-    HasherOutT<T>: TryFrom<[u8; H_LENGTH]>,
-{
-    type FileDataTrie = RocksDbFileDataTrie<T, DB>;
+    /// Deletes every mark of [`Column::RepairMarks`].
+    fn clear_repair_marks(&self) -> Result<(), FileStorageError> {
+        let mut transaction = DBTransaction::new();
+        for entry in self.storage.db.iter(Column::RepairMarks.into()) {
+            let (key, _) = entry.map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToReadStorage
+            })?;
+            transaction.delete(Column::RepairMarks.into(), &key);
 
-    /// Creates a new empty file data trie instance.
-    fn new_file_data_trie(&self) -> Self::FileDataTrie {
-        RocksDbFileDataTrie::new(self.storage.clone())
+            if transaction.ops.len() >= REPAIR_BATCH_SIZE {
+                self.write_repair_batch(std::mem::take(&mut transaction))?;
+            }
+        }
+        self.write_repair_batch(transaction)
     }
 
-    /// Retrieves a chunk by file key and chunk ID.
-    fn get_chunk(
-        &self,
-        file_key: &HasherOutT<T>,
-        chunk_id: &ChunkId,
-    ) -> Result<Chunk, FileStorageError> {
-        let metadata = self
-            .get_metadata(file_key)?
-            .ok_or(FileStorageError::FileDoesNotExist)?;
+    fn write_repair_batch(&self, transaction: DBTransaction) -> Result<(), FileStorageError> {
+        if transaction.ops.is_empty() {
+            return Ok(());
+        }
+        self.storage.write(transaction).map_err(|e| {
+            error!(target: LOG_TARGET, "{:?}", e);
+            FileStorageError::FailedToWriteToStorage
+        })
+    }
 
-        let file_trie = self.get_file_trie(&metadata)?;
+    /// Writes the chunks kept in memory by [`Self::with_write_coalescing`] to the database.
+    pub fn flush(&self) -> Result<(), FileStorageError> {
+        let Some(coalescer) = &self.coalescer else {
+            return Ok(());
+        };
 
-        file_trie.get_chunk(chunk_id)
+        coalescer.flush().map_err(|e| {
+            error!(target: LOG_TARGET, "Failed to flush the pending chunk writes: {:?}", e);
+            FileStorageError::FailedToWriteToStorage
+        })
     }
 
-    /// Returns the number of chunks currently stored for a given file key tracked by [`CHUNK_COUNT_COLUMN`].
-    fn stored_chunks_count(&self, file_key: &HasherOutT<T>) -> Result<u64, FileStorageError> {
+    /// Returns the number of chunks of a file as stored in [`Column::ChunkCount`], not counting
+    /// the chunks pending to be flushed.
+    fn read_chunk_count(&self, file_key: &HasherOutT<T>) -> Result<u64, FileStorageError> {
         // Read from CHUNK_COUNT_COLUMN using the file key
         let current_count = self
             .storage
@@ -598,34 +1377,31 @@ where
         Ok(current_count)
     }
 
-    /// Writes a chunk to storage with file key and chunk ID.
+    /// Writes a chunk of the file with `file_key` and `metadata` in its own transaction.
     ///
-    /// Returns [`FileStorageWriteOutcome`] indicating if file is complete. This outcome is based on
-    /// the current number of chunks stored (tracked by [`CHUNK_COUNT_COLUMN`]) and the file metadata's [`FileMetadata::chunks_count`].
-    fn write_chunk(
-        &mut self,
+    /// Returns the new chunk count and root of the file.
+    fn write_chunk_in_transaction(
+        &self,
         file_key: &HasherOutT<T>,
+        metadata: &FileMetadata,
         chunk_id: &ChunkId,
         data: &Chunk,
-    ) -> Result<FileStorageWriteOutcome, FileStorageWriteError> {
-        let metadata = self
-            .get_metadata(file_key)
-            .map_err(|_| FileStorageWriteError::FailedToParseFileMetadata)?
-            .ok_or(FileStorageWriteError::FileDoesNotExist)?;
-
-        let mut file_trie = self.get_file_trie(&metadata).map_err(|e| {
+    ) -> Result<(u64, HasherOutT<T>), FileStorageError>
+    where
+        T: TrieLayout + Send + Sync + 'static,
+        DB: KeyValueDB + 'static,
+    {
+        let mut file_trie = self.get_file_trie(metadata).map_err(|e| {
             error!(target: LOG_TARGET, "{:?}", e);
-            FileStorageWriteError::FailedToContructFileTrie
+            FileStorageError::FailedToContructFileTrie
         })?;
 
-        file_trie.write_chunk(chunk_id, data).map_err(|e| {
-            error!(target: LOG_TARGET, "{:?}", e);
-            FileStorageWriteError::FailedToInsertFileChunk
-        })?;
+        // The nodes of the chunk, the partial root and the chunk count are written in a single
+        // transaction, so they can't get out of sync.
+        let mut transaction = file_trie.insert_chunk(chunk_id, data)?;
 
         // Update partial root.
-        let new_partial_root = file_trie.get_root();
-        let mut transaction = DBTransaction::new();
+        let new_partial_root = *file_trie.get_root();
         transaction.put(
             Column::Roots.into(),
             metadata.fingerprint().as_ref(),
@@ -633,17 +1409,17 @@ where
         );
 
         // Get current chunk count or initialize to 0
-        let current_count = self.stored_chunks_count(file_key).map_err(|e| {
+        let current_count = self.read_chunk_count(file_key).map_err(|e| {
             error!(target: LOG_TARGET, "{:?}", e);
-            FileStorageWriteError::FailedToGetStoredChunksCount
+            FileStorageError::FailedToGetStoredChunksCount
         })?;
 
         // Increment chunk count.
         // This should never overflow unless there is a bug or we support file sizes as large as 16 exabytes.
-        // Since this is executed within the context of a write lock in the layer above, we should not have any chunk count syncing issues.
+        // Since this is executed holding the lock of the file, we should not have any chunk count syncing issues.
         let new_count = current_count
             .checked_add(1)
-            .ok_or(FileStorageWriteError::ChunkCountOverflow)?;
+            .ok_or(FileStorageError::ChunkCountOverflow)?;
         transaction.put(
             Column::ChunkCount.into(),
             file_key.as_ref(),
@@ -652,108 +1428,354 @@ where
 
         self.storage.write(transaction).map_err(|e| {
             error!(target: LOG_TARGET,"{:?}", e);
-            FileStorageWriteError::FailedToUpdatePartialRoot
+            FileStorageError::FailedToUpdatePartialRoot
         })?;
 
-        // Check if we have all the chunks for the file using the count
-        if metadata.chunks_count() != new_count {
-            return Ok(FileStorageWriteOutcome::FileIncomplete);
+        Ok((new_count, new_partial_root))
+    }
+
+    /// Writes a chunk of the file with `file_key` and `metadata` to the trie of the file kept in
+    /// memory by `coalescer`, flushing the pending writes if the file is now complete or enough
+    /// chunks are pending.
+    ///
+    /// Returns the new chunk count and root of the file.
+    fn write_chunk_coalesced(
+        &self,
+        coalescer: &WriteCoalescer<T, DB>,
+        file_key: &HasherOutT<T>,
+        metadata: &FileMetadata,
+        chunk_id: &ChunkId,
+        data: &Chunk,
+    ) -> Result<(u64, HasherOutT<T>), FileStorageError>
+    where
+        T: TrieLayout + Send + Sync + 'static,
+        DB: KeyValueDB + 'static,
+    {
+        // The file is locked before releasing the pending files, so that a flush waits for this
+        // chunk to be inserted instead of dropping it.
+        let mut files = coalescer.files();
+        let pending_file_lock = files.entry(*file_key).or_default().clone();
+        let mut pending = pending_file_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        drop(files);
+
+        // The first chunk since the last flush starts from the file as stored in the database.
+        if pending.is_none() {
+            let file_trie = self.get_file_trie(metadata).map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToContructFileTrie
+            })?;
+            let chunk_count = self.read_chunk_count(file_key).map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToGetStoredChunksCount
+            })?;
+            *pending = Some(PendingFile {
+                overlay: Default::default(),
+                root: *file_trie.get_root(),
+                fingerprint: *metadata.fingerprint(),
+                chunk_count,
+                pending_chunks: 0,
+            });
         }
+        let pending_file = pending
+            .as_mut()
+            .expect("Pending file read above if missing; qed");
 
-        // Verify that the final root matches the expected fingerprint
-        if metadata.fingerprint() != file_trie.get_root().as_ref() {
-            error!(
-                target: LOG_TARGET,
-                "Fingerprint mismatch. Expected: {:?}, got: {:?}",
-                metadata.fingerprint(),
-                file_trie.get_root()
-            );
-            return Err(FileStorageWriteError::FingerprintAndStoredFileMismatch);
+        let new_count = pending_file
+            .chunk_count
+            .checked_add(1)
+            .ok_or(FileStorageError::ChunkCountOverflow)?;
+
+        let mut file_trie = RocksDbFileDataTrie::<T, DB> {
+            storage: self.storage.clone(),
+            overlay: std::mem::take(&mut pending_file.overlay),
+            root: pending_file.root,
+        };
+        let inserted = file_trie.insert_chunk_in_overlay(chunk_id, data);
+        pending_file.overlay = file_trie.overlay;
+        inserted?;
+        pending_file.root = file_trie.root;
+        pending_file.chunk_count = new_count;
+        pending_file.pending_chunks += 1;
+        let pending_chunks = coalescer.pending_chunks.fetch_add(1, Ordering::Relaxed) + 1;
+        drop(pending);
+
+        // Complete files are flushed right away, as they are about to be proven.
+        if new_count == metadata.chunks_count() || pending_chunks >= coalescer.max_pending_chunks {
+            coalescer.flush().map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToUpdatePartialRoot
+            })?;
         }
 
-        Ok(FileStorageWriteOutcome::FileComplete)
+        Ok((new_count, file_trie.root))
     }
 
-    /// Checks if all chunks are stored for a given file key.
-    fn is_file_complete(&self, file_key: &HasherOutT<T>) -> Result<bool, FileStorageError> {
-        let metadata = self
-            .get_metadata(file_key)?
-            .ok_or(FileStorageError::FileDoesNotExist)?;
+    fn metadata_cache(&self) -> MutexGuard<'_, MetadataCache<HasherOutT<T>>> {
+        self.metadata_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
 
-        let stored_chunks = self.stored_chunks_count(file_key)?;
+    /// The [`ShardManifest`] persisted when this File Storage was first opened as a shard, if any.
+    pub fn shard_manifest(&self) -> Result<Option<ShardManifest>, FileStorageError> {
+        self.storage
+            .db
+            .get(Column::ShardManifest.into(), SHARD_MANIFEST_KEY)
+            .map_err(|e| {
+                error!(target: LOG_TARGET, "Failed to read the shard manifest: {:?}", e);
+                FileStorageError::FailedToReadStorage
+            })?
+            .map(|raw| {
+                ShardManifest::decode(&mut &raw[..]).map_err(|e| {
+                    error!(target: LOG_TARGET, "Failed to decode the shard manifest: {:?}", e);
+                    FileStorageError::ShardManifestMismatch
+                })
+            })
+            .transpose()
+    }
 
-        let file_trie = self.get_file_trie(&metadata)?;
+    /// Persists `manifest` as the [`ShardManifest`] of this File Storage.
+    pub fn write_shard_manifest(&self, manifest: ShardManifest) -> Result<(), FileStorageError> {
+        let mut transaction = DBTransaction::new();
+        transaction.put(
+            Column::ShardManifest.into(),
+            SHARD_MANIFEST_KEY,
+            &manifest.encode(),
+        );
+        self.storage.db.write(transaction).map_err(|e| {
+            error!(target: LOG_TARGET, "Failed to write the shard manifest: {:?}", e);
+            FileStorageError::FailedToWriteToStorage
+        })
+    }
 
-        if metadata.fingerprint() != file_trie.get_root().as_ref() {
-            return Ok(false);
-        }
+    /// Open the RocksDB database at `db_path` and return a new instance of [`StorageDb`].
+    pub fn rocksdb_storage(
+        db_path: String,
+    ) -> Result<StorageDb<T, kvdb_rocksdb::Database>, ErrorT<T>> {
+        let db = open_or_creating_rocksdb(db_path).map_err(|e| {
+            warn!(target: LOG_TARGET, "Failed to open RocksDB: {}", e);
+            FileStorageError::FailedToReadStorage
+        })?;
 
-        Ok(metadata.chunks_count() == stored_chunks)
+        Ok(StorageDb::new(Arc::new(db)))
     }
 
-    /// Stores file metadata with an empty root.
-    /// Should be used before writing any chunks using [`Self::write_chunk`].
-    fn insert_file(
-        &mut self,
-        file_key: HasherOutT<T>,
-        metadata: FileMetadata,
-    ) -> Result<(), FileStorageError> {
-        let mut transaction = DBTransaction::new();
-        let serialized_metadata = serde_json::to_vec(&metadata).map_err(|e| {
-            error!(target: LOG_TARGET,"{:?}", e);
-            FileStorageError::FailedToParseFileMetadata
-        })?;
+    /// Whether `file_data` is built on the storage of this File Storage.
+    pub(crate) fn holds_file_data(&self, file_data: &RocksDbFileDataTrie<T, DB>) -> bool {
+        Arc::ptr_eq(&file_data.storage.db, &self.storage.db)
+    }
+
+    /// Moves `file_data` onto the storage of this File Storage, if it was built on the storage of
+    /// another one (e.g. another shard of a
+    /// [`ShardedFileStorage`](crate::sharded::ShardedFileStorage)).
+    ///
+    /// The nodes of the trie are left in its original storage, where they may be shared with the
+    /// tries of other files, as nodes are not reference counted. The ones no file references are
+    /// deleted by [`Self::check_and_repair`] of that storage.
+    pub fn adopt_file_data(
+        &self,
+        file_data: RocksDbFileDataTrie<T, DB>,
+    ) -> Result<RocksDbFileDataTrie<T, DB>, FileStorageError>
+    where
+        T: TrieLayout + Send + Sync + 'static,
+        DB: KeyValueDB + 'static,
+    {
+        if self.holds_file_data(&file_data) {
+            return Ok(file_data);
+        }
 
+        // An empty trie has no nodes to move.
+        let root = *file_data.get_root();
         let (_, empty_root) = PrefixedMemoryDB::<HashT<T>>::default_with_root();
-        transaction.put(
-            Column::Metadata.into(),
-            file_key.as_ref(),
-            &serialized_metadata,
-        );
-        // Stores an empty root to allow for later initialization of the trie.
-        transaction.put(
-            Column::Roots.into(),
-            metadata.fingerprint().as_ref(),
-            empty_root.as_ref(),
-        );
-        // Initialize chunk count to 0
-        transaction.put(
-            Column::ChunkCount.into(),
-            file_key.as_ref(),
-            &0u64.to_le_bytes(),
-        );
+        if root == empty_root {
+            return Ok(RocksDbFileDataTrie::from_existing(
+                self.storage.clone(),
+                &root,
+            ));
+        }
 
-        self.storage.write(transaction).map_err(|e| {
-            error!(target: LOG_TARGET,"{:?}", e);
-            FileStorageError::FailedToWriteToStorage
-        })?;
+        let adopted = file_data
+            .reanchor(self.storage.clone())
+            .map_err(|e| match e {
+                crate::error::Error::FileStorage(e) => e,
+                e => {
+                    error!(target: LOG_TARGET, "Failed to move file trie: {:?}", e);
+                    FileStorageError::FailedToWriteToStorage
+                }
+            })?;
 
-        Ok(())
+        Ok(adopted)
     }
 
-    /// Stores file information with its (partial or final) root.
-    /// Should be used if any chunks have already been written.
-    /// Otherwise use [`Self::insert_file`].
+    /// Constructs a [`RocksDbFileDataTrie`] from the given [`FileMetadata`].
     ///
-    /// This is an expensive operation since it assumes that the file chunks were written
-    /// via the [`RocksDbFileDataTrie::write_chunk`] method instead of [`Self::write_chunk`] and
-    /// therefore iterates over all keys in `file_data` to count the number of chunks and update
-    /// the chunk count in the [`CHUNK_COUNT_COLUMN`] column. This data is necessary to
-    /// [`Self::generate_proof`]s for the file.
-    fn insert_file_with_data(
-        &mut self,
-        file_key: HasherOutT<T>,
-        metadata: FileMetadata,
-        file_data: Self::FileDataTrie,
-    ) -> Result<(), FileStorageError> {
-        let raw_metadata = serde_json::to_vec(&metadata).map_err(|e| {
+    /// Since files can be partially uploaded (i.e. not all chunks have been inserted to result in the root being the file metadata's fingerprint),
+    /// the constructed trie is based on the current `partial_root` representing the current state of the file we are interested in.
+    fn get_file_trie(
+        &self,
+        metadata: &FileMetadata,
+    ) -> Result<RocksDbFileDataTrie<T, DB>, FileStorageError>
+    where
+        T: TrieLayout + Send + Sync + 'static,
+        DB: KeyValueDB + 'static,
+    {
+        let b_fingerprint = metadata.fingerprint().as_ref();
+        let h_fingerprint =
+            convert_raw_bytes_to_hasher_out::<T>(b_fingerprint.to_vec()).map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToParseFingerprint
+            })?;
+        let raw_partial_root = self
+            .storage
+            .read(Column::Roots.into(), h_fingerprint.as_ref())
+            .map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToReadStorage
+            })?
+            .expect("Failed to find partial root");
+        let mut partial_root =
+            convert_raw_bytes_to_hasher_out::<T>(raw_partial_root).map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToParsePartialRoot
+            })?;
+        let file_trie =
+            RocksDbFileDataTrie::<T, DB>::from_existing(self.storage.clone(), &mut partial_root);
+        Ok(file_trie)
+    }
+
+    /// Returns the number of copies sharing the file trie of the given fingerprint.
+    fn shared_trie_copies(&self, fingerprint: &HasherOutT<T>) -> Result<u64, FileStorageError> {
+        let raw_copies = self
+            .storage
+            .read(Column::SharedTrieCopies.into(), fingerprint.as_ref())
+            .map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToReadStorage
+            })?;
+
+        match raw_copies {
+            None => Ok(0),
+            Some(raw_copies) => {
+                let bytes: [u8; 8] = raw_copies.as_slice().try_into().map_err(|e| {
+                    error!(target: LOG_TARGET, "{:?}", e);
+                    FileStorageError::FailedToReadStorage
+                })?;
+                Ok(u64::from_le_bytes(bytes))
+            }
+        }
+    }
+
+    /// Returns the unix time (in seconds) at which the file was inserted, tracked by
+    /// [`Column::CreatedAt`], or `None` if it is of unknown age.
+    fn created_at(&self, file_key: &HasherOutT<T>) -> Result<Option<u64>, FileStorageError> {
+        let raw_created_at = self
+            .storage
+            .read(Column::CreatedAt.into(), file_key.as_ref())
+            .map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToReadStorage
+            })?;
+
+        raw_created_at
+            .map(|raw_created_at| decode_created_at(&raw_created_at))
+            .transpose()
+    }
+
+    /// Appends to `transaction` the operations deleting the file with `file_key` and `metadata`.
+    ///
+    /// Unless `keep_trie` is set, the file trie and its root are deleted as well, once no other
+    /// copy references them. Returns whether the nodes of the file trie were deleted.
+    fn delete_file_ops(
+        &self,
+        file_key: &HasherOutT<T>,
+        metadata: &FileMetadata,
+        keep_trie: bool,
+        transaction: &mut DBTransaction,
+    ) -> Result<bool, FileStorageError>
+    where
+        T: TrieLayout + Send + Sync + 'static,
+        DB: KeyValueDB + 'static,
+    {
+        let mut trie_deleted = false;
+
+        if !keep_trie {
+            let b_fingerprint = metadata.fingerprint().as_ref();
+            let h_fingerprint = convert_raw_bytes_to_hasher_out::<T>(b_fingerprint.to_vec())
+                .map_err(|e| {
+                    error!(target: LOG_TARGET, "{:?}", e);
+                    FileStorageError::FailedToParseFingerprint
+                })?;
+
+            let shared_copies = self.shared_trie_copies(&h_fingerprint)?;
+
+            // The file trie and its root are only deleted once no other copy references them.
+            if shared_copies > 0 {
+                if shared_copies == 1 {
+                    transaction.delete(Column::SharedTrieCopies.into(), h_fingerprint.as_ref());
+                } else {
+                    transaction.put(
+                        Column::SharedTrieCopies.into(),
+                        h_fingerprint.as_ref(),
+                        &(shared_copies - 1).to_le_bytes(),
+                    );
+                }
+            } else {
+                let mut file_trie = self.get_file_trie(metadata)?;
+
+                transaction.ops.extend(file_trie.removal()?.ops);
+                trie_deleted = true;
+
+                transaction.delete(Column::Roots.into(), h_fingerprint.as_ref());
+            }
+        }
+
+        transaction.delete(Column::Metadata.into(), file_key.as_ref());
+        self.metadata_cache().remove(file_key);
+        transaction.delete(Column::ChunkCount.into(), file_key.as_ref());
+        transaction.delete(Column::CreatedAt.into(), file_key.as_ref());
+        transaction.delete(Column::Sealed.into(), file_key.as_ref());
+
+        let bucket_prefixed_file_key = metadata
+            .bucket_id()
+            .iter()
+            .copied()
+            .chain(file_key.as_ref().iter().copied())
+            .collect::<Vec<_>>();
+        transaction.delete(
+            Column::BucketPrefix.into(),
+            bucket_prefixed_file_key.as_ref(),
+        );
+
+        Ok(trie_deleted)
+    }
+
+    /// Appends to `transaction` the operations inserting the file with `file_key`, `metadata` and
+    /// the (partial or final) root of `file_data`.
+    ///
+    /// The nodes of `file_data` are expected to be in storage already, unless `rewrite_nodes` is
+    /// set, in which case they are all read and written again. This is needed when the same
+    /// transaction deletes nodes the trie may share.
+    fn insert_file_with_data_ops(
+        &self,
+        file_key: &HasherOutT<T>,
+        metadata: &FileMetadata,
+        file_data: &RocksDbFileDataTrie<T, DB>,
+        rewrite_nodes: bool,
+        transaction: &mut DBTransaction,
+    ) -> Result<(), FileStorageError>
+    where
+        T: TrieLayout + Send + Sync + 'static,
+        DB: KeyValueDB + 'static,
+    {
+        let raw_metadata = serde_json::to_vec(metadata).map_err(|e| {
             error!(target: LOG_TARGET,"{:?}", e);
             FileStorageError::FailedToParseFileMetadata
         })?;
 
-        let mut transaction = DBTransaction::new();
-
         transaction.put(Column::Metadata.into(), file_key.as_ref(), &raw_metadata);
+        self.metadata_cache().remove(file_key);
 
         // Stores the current root of the trie.
         // if the file is complete, key and value will be equal.
@@ -763,22 +1785,29 @@ where
             file_data.get_root().as_ref(),
         );
 
-        let mem_db = file_data.as_hash_db();
-        let trie = TrieDBBuilder::<T>::new(&mem_db, file_data.get_root()).build();
-
-        let chunk_count = trie
-            .iter()
-            .map_err(|e| {
-                error!(target: LOG_TARGET, "Failed to construct Trie iterator: {}", e);
-                FileStorageError::FailedToConstructTrieIter
-            })?
-            .count();
+        let chunk_count = if rewrite_nodes {
+            let copier = NodeCopier::<T> {
+                source: file_data.as_hash_db(),
+                copied: RefCell::new(DBTransaction::new()),
+                compression: self.storage.compression,
+            };
+            let chunk_count = count_chunks::<T>(&copier, file_data.get_root())?;
+            transaction.ops.extend(copier.copied.into_inner().ops);
+            chunk_count
+        } else {
+            count_chunks::<T>(&file_data.as_hash_db(), file_data.get_root())?
+        };
 
         transaction.put(
             Column::ChunkCount.into(),
             file_key.as_ref(),
             &chunk_count.to_le_bytes(),
         );
+        transaction.put(
+            Column::CreatedAt.into(),
+            file_key.as_ref(),
+            &unix_timestamp_now().to_le_bytes(),
+        );
 
         let bucket_prefixed_file_key = metadata
             .bucket_id()
@@ -794,775 +1823,3429 @@ where
             &[],
         );
 
-        self.storage.write(transaction).map_err(|e| {
-            error!(target: LOG_TARGET,"{:?}", e);
-            FileStorageError::FailedToWriteToStorage
-        })?;
-
         Ok(())
     }
+}
 
-    /// Retrieves file metadata by file key.
-    fn get_metadata(
-        &self,
-        file_key: &HasherOutT<T>,
-    ) -> Result<Option<FileMetadata>, FileStorageError> {
-        let raw_metadata = self
-            .storage
-            .read(Column::Metadata.into(), file_key.as_ref())
-            .map_err(|e| {
-                error!(target: LOG_TARGET,"{:?}", e);
-                FileStorageError::FailedToReadStorage
-            })?;
-        match raw_metadata {
-            None => return Ok(None),
-            Some(metadata) => {
-                let metadata: FileMetadata = serde_json::from_slice(&metadata).map_err(|e| {
-                    error!(target: LOG_TARGET,"{:?}", e);
-                    FileStorageError::FailedToParseFileMetadata
-                })?;
-                Ok(Some(metadata))
-            }
+/// Copies all the files of `in_memory` to a new [`RocksDbFileStorage`] at `db_path`, along with
+/// their chunks, exclude lists and seals.
+///
+/// Used to persist the files of a node that started with an in-memory File Storage. The chunks
+/// of each file are read and written back one by one, and the current root of every migrated
+/// file is checked against its in-memory root. Fails with
+/// [`FileStorageError::FileAlreadyExists`] if a file is already stored at `db_path`.
+pub fn migrate_to_rocksdb<T>(
+    in_memory: &InMemoryFileStorage<T>,
+    db_path: String,
+) -> Result<RocksDbFileStorage<T, kvdb_rocksdb::Database>, ErrorT<T>>
+where
+    T: TrieLayout + Send + Sync + 'static,
+    HasherOutT<T>: TryFrom<[u8; H_LENGTH]>,
+{
+    let storage = RocksDbFileStorage::<T, kvdb_rocksdb::Database>::rocksdb_storage(db_path)?;
+    let mut file_storage = RocksDbFileStorage::new(storage);
+
+    let file_keys = in_memory.file_keys()?;
+    let mut files_per_fingerprint = HashMap::<Vec<u8>, u64>::new();
+    for file_key in &file_keys {
+        if file_storage.get_metadata(file_key)?.is_some() {
+            return Err(FileStorageError::FileAlreadyExists.into());
+        }
+        let metadata = in_memory
+            .get_metadata(file_key)?
+            .ok_or(FileStorageError::FileDoesNotExist)?;
+        *files_per_fingerprint
+            .entry(metadata.fingerprint().as_ref().to_vec())
+            .or_default() += 1;
+        file_storage.insert_file(*file_key, metadata)?;
+
+        for chunk_id in in_memory.present_chunk_ids(file_key)? {
+            let chunk = in_memory.get_chunk(file_key, &chunk_id)?;
+            file_storage.write_chunk(file_key, &chunk_id, &chunk)?;
         }
     }
 
-    /// Generates a proof for specified chunks of a file.
+    // Files with the same fingerprint share their trie in RocksDB, which is tracked in
+    // `Column::SharedTrieCopies` as it is for copied files, so that deleting one of them does not
+    // remove the trie of the others.
+    let mut transaction = DBTransaction::new();
+    for (fingerprint, files) in files_per_fingerprint {
+        if files > 1 {
+            transaction.put(
+                Column::SharedTrieCopies.into(),
+                &fingerprint,
+                &(files - 1).to_le_bytes(),
+            );
+        }
+    }
+    file_storage.storage.write(transaction)?;
+
+    // Their roots are only checked once all of them are migrated.
+    for file_key in &file_keys {
+        let metadata = file_storage
+            .get_metadata(file_key)?
+            .ok_or(FileStorageError::FileDoesNotExist)?;
+        let migrated_root = *file_storage.get_file_trie(&metadata)?.get_root();
+        if in_memory.file_root(file_key) != Some(migrated_root) {
+            error!(target: LOG_TARGET, "Root of migrated file [{:?}] does not match its in-memory root", file_key);
+            return Err(FileStorageError::ReanchoredRootMismatch.into());
+        }
+    }
+
+    for (exclude_type, keys) in &in_memory.exclude_list {
+        for key in keys {
+            file_storage.add_to_exclude_list(*key, *exclude_type)?;
+        }
+    }
+
+    let sealed = in_memory
+        .sealed
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    for file_key in sealed {
+        file_storage.seal_file(&file_key)?;
+    }
+
+    info!(target: LOG_TARGET, "Migrated {} file(s) from the in-memory File Storage to RocksDB", file_keys.len());
+
+    Ok(file_storage)
+}
+
+impl<T: TrieLayout> RocksDbFileStorage<T, kvdb_rocksdb::Database> {
+    /// Returns an approximation of the number of files stored, without scanning their metadata.
     ///
-    /// Returns error if file is incomplete or proof generation fails.
-    fn generate_proof(
+    /// See [`StorageDb::approximate_file_count`] for how approximate it is.
+    pub fn get_file_count(&self) -> Result<u64, ErrorT<T>> {
+        self.storage.approximate_file_count()
+    }
+}
+
+impl<T, DB> FileStorage<T> for RocksDbFileStorage<T, DB>
+where
+    T: TrieLayout + Send + Sync + 'static,
+    DB: KeyValueDB + 'static,
+    HasherOutT<T>: TryFrom<[u8; H_LENGTH]>,
+{
+    type FileDataTrie = RocksDbFileDataTrie<T, DB>;
+
+    /// Creates a new empty file data trie instance.
+    fn new_file_data_trie(&self) -> Self::FileDataTrie {
+        RocksDbFileDataTrie::new(self.storage.clone())
+    }
+
+    /// Retrieves a chunk by file key and chunk ID.
+    fn get_chunk(
         &self,
-        key: &HasherOutT<T>,
-        chunk_ids: &HashSet<ChunkId>,
-    ) -> Result<FileKeyProof, FileStorageError> {
+        file_key: &HasherOutT<T>,
+        chunk_id: &ChunkId,
+    ) -> Result<Chunk, FileStorageError> {
+        self.flush()?;
+
         let metadata = self
-            .get_metadata(key)?
+            .get_metadata(file_key)?
             .ok_or(FileStorageError::FileDoesNotExist)?;
 
         let file_trie = self.get_file_trie(&metadata)?;
 
-        let stored_chunks = self.stored_chunks_count(key)?;
-        if metadata.chunks_count() != stored_chunks {
-            return Err(FileStorageError::IncompleteFile);
-        }
+        file_trie.get_chunk(chunk_id)
+    }
 
-        if metadata.fingerprint() != file_trie.get_root().as_ref() {
-            return Err(FileStorageError::FingerprintAndStoredFileMismatch);
-        }
+    /// Returns the number of chunks currently stored for a given file key tracked by [`CHUNK_COUNT_COLUMN`].
+    fn stored_chunks_count(&self, file_key: &HasherOutT<T>) -> Result<u64, FileStorageError> {
+        self.flush()?;
 
-        file_trie
-            .generate_proof(chunk_ids)?
-            .to_file_key_proof(metadata.clone())
-            .map_err(|e| {
-                error!(target: LOG_TARGET, "{:?}", e);
-                FileStorageError::FailedToConstructFileKeyProof
-            })
+        self.read_chunk_count(file_key)
     }
 
-    /// Deletes a file and all its associated data.
-    fn delete_file(&mut self, file_key: &HasherOutT<T>) -> Result<(), FileStorageError> {
+    /// Returns the IDs of the chunks in the file trie of a given file key, as of its current
+    /// partial root tracked by [`Column::Roots`].
+    fn present_chunk_ids(
+        &self,
+        file_key: &HasherOutT<T>,
+    ) -> Result<Vec<ChunkId>, FileStorageError> {
+        self.flush()?;
+
         let metadata = self
             .get_metadata(file_key)?
             .ok_or(FileStorageError::FileDoesNotExist)?;
 
-        let b_fingerprint = metadata.fingerprint().as_ref();
-        let h_fingerprint =
-            convert_raw_bytes_to_hasher_out::<T>(b_fingerprint.to_vec()).map_err(|e| {
+        let file_trie = self.get_file_trie(&metadata)?;
+
+        file_trie.chunk_ids()
+    }
+
+    /// Returns the incomplete files whose creation time, tracked by [`Column::CreatedAt`], is
+    /// before `created_before`.
+    fn incomplete_files_older_than(
+        &self,
+        created_before: u64,
+    ) -> Result<Vec<HasherOutT<T>>, FileStorageError> {
+        self.flush()?;
+
+        let mut old_files = Vec::new();
+        for entry in self.storage.db.iter(Column::CreatedAt.into()) {
+            let (raw_file_key, raw_created_at) = entry.map_err(|e| {
                 error!(target: LOG_TARGET, "{:?}", e);
-                FileStorageError::FailedToParseFingerprint
+                FileStorageError::FailedToReadStorage
             })?;
 
-        let mut file_trie = self.get_file_trie(&metadata)?;
+            if decode_created_at(&raw_created_at)? >= created_before {
+                continue;
+            }
 
-        file_trie.delete().map_err(|e| {
-            error!(target: LOG_TARGET,"{:?}", e);
-            FileStorageError::FailedToDeleteFileChunk
-        })?;
+            let file_key =
+                convert_raw_bytes_to_hasher_out::<T>(raw_file_key.to_vec()).map_err(|e| {
+                    error!(target: LOG_TARGET, "{:?}", e);
+                    FileStorageError::FailedToParseKey
+                })?;
+            if !self.is_file_complete(&file_key)? {
+                old_files.push(file_key);
+            }
+        }
 
-        let mut transaction = DBTransaction::new();
+        Ok(old_files)
+    }
 
-        transaction.delete(Column::Metadata.into(), file_key.as_ref());
-        transaction.delete(Column::Roots.into(), h_fingerprint.as_ref());
-        transaction.delete(Column::ChunkCount.into(), file_key.as_ref());
+    fn incomplete_files(&self) -> Result<Vec<IncompleteFile<HasherOutT<T>>>, FileStorageError> {
+        self.flush()?;
 
-        let bucket_prefixed_file_key = metadata
-            .bucket_id()
-            .iter()
-            .copied()
-            .chain(file_key.as_ref().iter().copied())
-            .collect::<Vec<_>>();
-        transaction.delete(
-            Column::BucketPrefix.into(),
-            bucket_prefixed_file_key.as_ref(),
-        );
+        let mut incomplete_files = Vec::new();
+        for entry in self.storage.db.iter(Column::Metadata.into()) {
+            let (raw_file_key, _) = entry.map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToReadStorage
+            })?;
 
-        self.storage.write(transaction).map_err(|e| {
-            error!(target: LOG_TARGET,"{:?}", e);
-            FileStorageError::FailedToWriteToStorage
-        })?;
+            let file_key =
+                convert_raw_bytes_to_hasher_out::<T>(raw_file_key.to_vec()).map_err(|e| {
+                    error!(target: LOG_TARGET, "{:?}", e);
+                    FileStorageError::FailedToParseKey
+                })?;
+            if !self.is_file_complete(&file_key)? {
+                incomplete_files.push(IncompleteFile {
+                    file_key,
+                    age: self.created_at(&file_key)?.map(age_since),
+                });
+            }
+        }
 
-        Ok(())
+        Ok(incomplete_files)
     }
 
-    /// Deletes all files with a matching bucket ID prefix.
-    fn delete_files_with_prefix(
-        &mut self,
-        bucket_id_prefix: &[u8; 32],
-    ) -> Result<(), FileStorageError> {
-        let mut file_keys_to_delete = Vec::new();
-
-        {
-            let mut iter = self
-                .storage
-                .db
-                .iter_with_prefix(Column::BucketPrefix.into(), bucket_id_prefix);
+    fn partial_file_age(
+        &self,
+        file_key: &HasherOutT<T>,
+    ) -> Result<Option<Duration>, FileStorageError> {
+        if self.is_file_complete(file_key)? {
+            return Ok(None);
+        }
 
-            while let Some(Ok((key, _))) = iter.next() {
-                // Remove the prefix from the key.
-                let file_key = key
-                    .iter()
-                    .skip(bucket_id_prefix.len())
-                    .copied()
-                    .collect::<Vec<u8>>();
+        Ok(self.created_at(file_key)?.map(age_since))
+    }
 
-                let h_file_key = convert_raw_bytes_to_hasher_out::<T>(file_key).map_err(|e| {
+    /// Returns the file keys in [`Column::Metadata`], which are iterated in ascending order.
+    fn file_keys(&self) -> Result<Vec<HasherOutT<T>>, FileStorageError> {
+        let mut file_keys = Vec::new();
+        for entry in self.storage.db.iter(Column::Metadata.into()) {
+            let (raw_file_key, _) = entry.map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToReadStorage
+            })?;
+            let file_key =
+                convert_raw_bytes_to_hasher_out::<T>(raw_file_key.to_vec()).map_err(|e| {
                     error!(target: LOG_TARGET, "{:?}", e);
                     FileStorageError::FailedToParseKey
                 })?;
-
-                file_keys_to_delete.push(h_file_key);
-            }
+            file_keys.push(file_key);
         }
 
-        for h_file_key in file_keys_to_delete {
-            self.delete_file(&h_file_key)?;
+        Ok(file_keys)
+    }
+
+    /// Reads the file keys in [`Column::Metadata`] after `start_key` by seeking to the prefixes
+    /// of the keys after it (see [`keys_after`]), so the cost of a page does not grow with the
+    /// number of files before it.
+    fn get_files_after_key(
+        &self,
+        start_key: Option<HasherOutT<T>>,
+        limit: usize,
+    ) -> Result<Vec<HasherOutT<T>>, FileStorageError> {
+        let raw_file_keys = keys_after(
+            self.storage.db.as_ref(),
+            Column::Metadata.into(),
+            start_key.as_ref().map(|key| key.as_ref()),
+            limit,
+        )
+        .map_err(|e| {
+            error!(target: LOG_TARGET, "{:?}", e);
+            FileStorageError::FailedToReadStorage
+        })?;
+
+        let mut file_keys = Vec::with_capacity(raw_file_keys.len());
+        for raw_file_key in raw_file_keys {
+            let file_key = convert_raw_bytes_to_hasher_out::<T>(raw_file_key).map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToParseKey
+            })?;
+            file_keys.push(file_key);
         }
 
-        Ok(())
+        Ok(file_keys)
     }
 
-    /// Checks if a key is allowed based on the exclude type.
-    fn is_allowed(
+    /// Rebuilds the current root in [`Column::Roots`] and the count in [`Column::ChunkCount`] of
+    /// a file from its trie nodes in [`Column::Chunks`].
+    ///
+    /// The current root is taken to be, in order of preference, the fingerprint of the file, the
+    /// root currently tracked for it, or the empty root, keeping the first one whose trie can be
+    /// fully read.
+    fn verify_and_repair_file(
         &self,
         file_key: &HasherOutT<T>,
-        exclude_type: ExcludeType,
-    ) -> Result<bool, FileStorageError> {
-        let exclude_column = get_exclude_type_db_column(exclude_type);
-        let find = self
+    ) -> Result<FileVerification, FileStorageError> {
+        self.flush()?;
+
+        let _file_lock = self.locks.lock(file_key.as_ref());
+
+        let metadata = self
+            .get_metadata(file_key)?
+            .ok_or(FileStorageError::FileDoesNotExist)?;
+        let fingerprint =
+            convert_raw_bytes_to_hasher_out::<T>(metadata.fingerprint().as_ref().to_vec())
+                .map_err(|e| {
+                    error!(target: LOG_TARGET, "{:?}", e);
+                    FileStorageError::FailedToParseFingerprint
+                })?;
+
+        let tracked_root = self
             .storage
-            .db
-            .get(exclude_column, file_key.as_ref())
+            .read(Column::Roots.into(), fingerprint.as_ref())
             .map_err(|e| {
                 error!(target: LOG_TARGET, "{:?}", e);
                 FileStorageError::FailedToReadStorage
+            })?
+            .map(convert_raw_bytes_to_hasher_out::<T>)
+            .transpose()
+            .map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToParsePartialRoot
             })?;
+        let tracked_count = self.stored_chunks_count(file_key)?;
+
+        let empty_trie = RocksDbFileDataTrie::<T, DB>::new(self.storage.clone());
+        let empty_root = *empty_trie.get_root();
+
+        let (root, chunk_count) = [Some(fingerprint), tracked_root]
+            .into_iter()
+            .flatten()
+            .find_map(|root| {
+                let file_trie =
+                    RocksDbFileDataTrie::<T, DB>::from_existing(self.storage.clone(), &root);
+                count_readable_chunks::<T>(&file_trie.as_hash_db(), &root)
+                    .ok()
+                    .map(|chunk_count| (root, chunk_count))
+            })
+            .unwrap_or((empty_root, 0));
 
-        match find {
-            Some(_) => return Ok(false),
-            None => return Ok(true),
+        let mut transaction = DBTransaction::new();
+        if tracked_root != Some(root) {
+            warn!(target: LOG_TARGET, "Rebuilt the current root of file [{:?}]: {:?} -> {:?}", file_key, tracked_root, root);
+            transaction.put(Column::Roots.into(), fingerprint.as_ref(), root.as_ref());
+        }
+        if tracked_count != chunk_count {
+            warn!(target: LOG_TARGET, "Rebuilt the stored chunks count of file [{:?}]: {} -> {}", file_key, tracked_count, chunk_count);
+            transaction.put(
+                Column::ChunkCount.into(),
+                file_key.as_ref(),
+                &chunk_count.to_le_bytes(),
+            );
         }
-    }
 
-    /// Adds a key to the specified exclude list.
-    fn add_to_exclude_list(
-        &mut self,
-        file_key: HasherOutT<T>,
-        exclude_type: ExcludeType,
-    ) -> Result<(), FileStorageError> {
-        let exclude_column = get_exclude_type_db_column(exclude_type);
+        let repaired = !transaction.ops.is_empty();
+        if repaired {
+            self.storage.write(transaction).map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToWriteToStorage
+            })?;
+        }
 
-        let mut transaction = DBTransaction::new();
-        transaction.put(exclude_column, file_key.as_ref(), &[]);
+        Ok(FileVerification {
+            complete: root == fingerprint && chunk_count == metadata.chunks_count(),
+            repaired,
+        })
+    }
 
-        self.storage.db.write(transaction).map_err(|e| {
-            error!(target: LOG_TARGET, "Failed to write to DB: {}", e);
-            FileStorageError::FailedToWriteToStorage
-        })?;
+    fn stats(&self) -> Result<FileStorageStats, FileStorageError> {
+        let mut stats = FileStorageStats::default();
+        for entry in self.storage.db.iter(Column::Metadata.into()) {
+            let (_, raw_metadata) = entry.map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToReadStorage
+            })?;
+            let metadata: FileMetadata = serde_json::from_slice(&raw_metadata).map_err(|e| {
+                error!(target: LOG_TARGET, "Corrupt metadata: {:?}", e);
+                FileStorageError::CorruptMetadata
+            })?;
 
-        info!("Key added to the exclude list : {:?}", file_key);
-        Ok(())
+            stats.files += 1;
+            stats.total_size = stats.total_size.saturating_add(metadata.file_size());
+        }
+
+        Ok(stats)
     }
 
-    /// Removes a key from the specified exclude list.
-    fn remove_from_exclude_list(
-        &mut self,
+    /// Writes a chunk to storage with file key and chunk ID.
+    ///
+    /// Returns [`FileStorageWriteOutcome`] indicating if file is complete. This outcome is based on
+    /// the current number of chunks stored (tracked by [`CHUNK_COUNT_COLUMN`]) and the file metadata's [`FileMetadata::chunks_count`].
+    fn write_chunk(
+        &self,
         file_key: &HasherOutT<T>,
-        exclude_type: ExcludeType,
-    ) -> Result<(), FileStorageError> {
-        let exclude_column = get_exclude_type_db_column(exclude_type);
+        chunk_id: &ChunkId,
+        data: &Chunk,
+    ) -> Result<FileStorageWriteOutcome, FileStorageError> {
+        let _file_lock = self.locks.lock(file_key.as_ref());
 
-        let mut transaction = DBTransaction::new();
-        transaction.delete(exclude_column, file_key.as_ref());
+        let metadata = self
+            .get_metadata(file_key)?
+            .ok_or(FileStorageError::FileDoesNotExist)?;
 
-        self.storage.db.write(transaction).map_err(|e| {
-            error!(target: LOG_TARGET, "Failed to write to DB: {}", e);
-            FileStorageError::FailedToWriteToStorage
-        })?;
+        let sealed = self
+            .storage
+            .read(Column::Sealed.into(), file_key.as_ref())
+            .map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToReadStorage
+            })?;
+        if sealed.is_some() {
+            return Err(FileStorageError::FileSealed);
+        }
 
-        info!("Key removed to the exclude list : {:?}", file_key);
-        Ok(())
-    }
+        // Chunks outside of the file's range would count towards the stored chunks, preventing
+        // the file from ever being complete.
+        let chunks_count = metadata.chunks_count();
+        if chunk_id.as_u64() >= chunks_count {
+            return Err(FileStorageError::ChunkIdOutOfRange);
+        }
+
+        let (new_count, new_root) = match &self.coalescer {
+            Some(coalescer) => {
+                self.write_chunk_coalesced(coalescer, file_key, &metadata, chunk_id, data)?
+            }
+            None => self.write_chunk_in_transaction(file_key, &metadata, chunk_id, data)?,
+        };
+
+        // Check if we have all the chunks for the file using the count
+        if chunks_count != new_count {
+            return Ok(FileStorageWriteOutcome::FileIncomplete {
+                stored: new_count,
+                total: chunks_count,
+            });
+        }
+
+        // Verify that the final root matches the expected fingerprint
+        if metadata.fingerprint() != new_root.as_ref() {
+            error!(
+                target: LOG_TARGET,
+                "Fingerprint mismatch. Expected: {:?}, got: {:?}",
+                metadata.fingerprint(),
+                new_root
+            );
+            return Err(FileStorageError::FingerprintAndStoredFileMismatch);
+        }
+
+        Ok(FileStorageWriteOutcome::FileComplete)
+    }
+
+    /// Marks a complete file as sealed in [`Column::Sealed`].
+    fn seal_file(&self, file_key: &HasherOutT<T>) -> Result<(), FileStorageError> {
+        self.flush()?;
+
+        let _file_lock = self.locks.lock(file_key.as_ref());
+
+        let metadata = self
+            .get_metadata(file_key)?
+            .ok_or(FileStorageError::FileDoesNotExist)?;
+
+        let file_trie = self.get_file_trie(&metadata)?;
+        if metadata.fingerprint() != file_trie.get_root().as_ref() {
+            return Err(FileStorageError::FingerprintAndStoredFileMismatch);
+        }
+
+        if metadata.chunks_count() != self.stored_chunks_count(file_key)? {
+            return Err(FileStorageError::IncompleteFile);
+        }
+
+        let mut transaction = DBTransaction::new();
+        transaction.put(Column::Sealed.into(), file_key.as_ref(), &[]);
+
+        self.storage.write(transaction).map_err(|e| {
+            error!(target: LOG_TARGET,"{:?}", e);
+            FileStorageError::FailedToWriteToStorage
+        })?;
+
+        Ok(())
+    }
+
+    /// Checks if all chunks are stored for a given file key.
+    fn is_file_complete(&self, file_key: &HasherOutT<T>) -> Result<bool, FileStorageError> {
+        self.flush()?;
+
+        let metadata = self
+            .get_metadata(file_key)?
+            .ok_or(FileStorageError::FileDoesNotExist)?;
+
+        let stored_chunks = self.stored_chunks_count(file_key)?;
+
+        let file_trie = self.get_file_trie(&metadata)?;
+
+        if metadata.fingerprint() != file_trie.get_root().as_ref() {
+            return Ok(false);
+        }
+
+        Ok(metadata.chunks_count() == stored_chunks)
+    }
+
+    /// Stores file metadata with an empty root.
+    /// Should be used before writing any chunks using [`Self::write_chunk`].
+    fn insert_file(
+        &mut self,
+        file_key: HasherOutT<T>,
+        metadata: FileMetadata,
+    ) -> Result<(), FileStorageError> {
+        metadata
+            .validate_strict()
+            .map_err(FileStorageError::InvalidMetadata)?;
+
+        let mut transaction = DBTransaction::new();
+        let serialized_metadata = serde_json::to_vec(&metadata).map_err(|e| {
+            error!(target: LOG_TARGET,"{:?}", e);
+            FileStorageError::FailedToParseFileMetadata
+        })?;
+
+        let (_, empty_root) = PrefixedMemoryDB::<HashT<T>>::default_with_root();
+        transaction.put(
+            Column::Metadata.into(),
+            file_key.as_ref(),
+            &serialized_metadata,
+        );
+        self.metadata_cache().remove(&file_key);
+        // Stores an empty root to allow for later initialization of the trie.
+        transaction.put(
+            Column::Roots.into(),
+            metadata.fingerprint().as_ref(),
+            empty_root.as_ref(),
+        );
+        // Initialize chunk count to 0
+        transaction.put(
+            Column::ChunkCount.into(),
+            file_key.as_ref(),
+            &0u64.to_le_bytes(),
+        );
+        transaction.put(
+            Column::CreatedAt.into(),
+            file_key.as_ref(),
+            &unix_timestamp_now().to_le_bytes(),
+        );
+        // Store the key prefixed by bucket id, as files inserted with data do.
+        let bucket_prefixed_file_key =
+            [metadata.bucket_id().as_slice(), file_key.as_ref()].concat();
+        transaction.put(
+            Column::BucketPrefix.into(),
+            bucket_prefixed_file_key.as_ref(),
+            &[],
+        );
+
+        self.storage.write(transaction).map_err(|e| {
+            error!(target: LOG_TARGET,"{:?}", e);
+            FileStorageError::FailedToWriteToStorage
+        })?;
+
+        Ok(())
+    }
+
+    /// Stores file information with its (partial or final) root.
+    /// Should be used if any chunks have already been written.
+    /// Otherwise use [`Self::insert_file`].
+    ///
+    /// This is an expensive operation since it assumes that the file chunks were written
+    /// via the [`RocksDbFileDataTrie::write_chunk`] method instead of [`Self::write_chunk`] and
+    /// therefore iterates over all keys in `file_data` to count the number of chunks and update
+    /// the chunk count in the [`CHUNK_COUNT_COLUMN`] column. This data is necessary to
+    /// [`Self::generate_proof`]s for the file.
+    fn insert_file_with_data(
+        &mut self,
+        file_key: HasherOutT<T>,
+        metadata: FileMetadata,
+        file_data: Self::FileDataTrie,
+    ) -> Result<(), FileStorageError> {
+        metadata
+            .validate_strict()
+            .map_err(FileStorageError::InvalidMetadata)?;
+
+        let mut transaction = DBTransaction::new();
+        self.insert_file_with_data_ops(&file_key, &metadata, &file_data, false, &mut transaction)?;
+
+        self.storage.write(transaction).map_err(|e| {
+            error!(target: LOG_TARGET,"{:?}", e);
+            FileStorageError::FailedToWriteToStorage
+        })?;
+
+        Ok(())
+    }
+
+    /// Copies a complete file under the file key computed from `new_metadata`.
+    ///
+    /// Chunks are stored in a trie shared by all files with the same fingerprint, so only the
+    /// metadata, chunk count and bucket prefix entries are written for the new file key.
+    /// The copy is tracked in [`Column::SharedTrieCopies`] so that deleting either file
+    /// does not remove the shared trie.
+    fn copy_file(
+        &mut self,
+        src_key: &HasherOutT<T>,
+        new_metadata: FileMetadata,
+    ) -> Result<HasherOutT<T>, FileStorageError> {
+        self.flush()?;
+
+        let src_metadata = self
+            .get_metadata(src_key)?
+            .ok_or(FileStorageError::FileDoesNotExist)?;
+
+        if !self.is_file_complete(src_key)? {
+            return Err(FileStorageError::IncompleteFile);
+        }
+
+        if src_metadata.fingerprint() != new_metadata.fingerprint()
+            || src_metadata.file_size() != new_metadata.file_size()
+        {
+            return Err(FileStorageError::FingerprintAndStoredFileMismatch);
+        }
+
+        let new_file_key = new_metadata.file_key::<HashT<T>>();
+        if self.get_metadata(&new_file_key)?.is_some() {
+            return Err(FileStorageError::FileAlreadyExists);
+        }
+
+        let h_fingerprint =
+            convert_raw_bytes_to_hasher_out::<T>(new_metadata.fingerprint().as_ref().to_vec())
+                .map_err(|e| {
+                    error!(target: LOG_TARGET, "{:?}", e);
+                    FileStorageError::FailedToParseFingerprint
+                })?;
+        let shared_copies = self.shared_trie_copies(&h_fingerprint)?;
+        let stored_chunks = self.stored_chunks_count(src_key)?;
+
+        let raw_metadata = serde_json::to_vec(&new_metadata).map_err(|e| {
+            error!(target: LOG_TARGET,"{:?}", e);
+            FileStorageError::FailedToParseFileMetadata
+        })?;
+
+        let mut transaction = DBTransaction::new();
+
+        transaction.put(
+            Column::Metadata.into(),
+            new_file_key.as_ref(),
+            &raw_metadata,
+        );
+        self.metadata_cache().remove(&new_file_key);
+        transaction.put(
+            Column::ChunkCount.into(),
+            new_file_key.as_ref(),
+            &stored_chunks.to_le_bytes(),
+        );
+        transaction.put(
+            Column::CreatedAt.into(),
+            new_file_key.as_ref(),
+            &unix_timestamp_now().to_le_bytes(),
+        );
+        transaction.put(
+            Column::SharedTrieCopies.into(),
+            h_fingerprint.as_ref(),
+            &(shared_copies + 1).to_le_bytes(),
+        );
+
+        let bucket_prefixed_file_key = new_metadata
+            .bucket_id()
+            .iter()
+            .copied()
+            .chain(new_file_key.as_ref().iter().copied())
+            .collect::<Vec<_>>();
+
+        // Store the key prefixed by bucket id
+        transaction.put(
+            Column::BucketPrefix.into(),
+            bucket_prefixed_file_key.as_ref(),
+            &[],
+        );
+
+        self.storage.write(transaction).map_err(|e| {
+            error!(target: LOG_TARGET,"{:?}", e);
+            FileStorageError::FailedToWriteToStorage
+        })?;
+
+        Ok(new_file_key)
+    }
+
+    /// Retrieves file metadata by file key, from the metadata cache if it was recently read.
+    fn get_metadata(
+        &self,
+        file_key: &HasherOutT<T>,
+    ) -> Result<Option<FileMetadata>, FileStorageError> {
+        if let Some(metadata) = self.metadata_cache().get(file_key) {
+            return Ok(Some(metadata));
+        }
+
+        let raw_metadata = self
+            .storage
+            .read(Column::Metadata.into(), file_key.as_ref())
+            .map_err(|e| {
+                error!(target: LOG_TARGET,"{:?}", e);
+                FileStorageError::FailedToReadStorage
+            })?;
+        match raw_metadata {
+            None => return Ok(None),
+            Some(metadata) => {
+                // Catch corrupted records here rather than letting them panic further down the line.
+                let metadata: FileMetadata = serde_json::from_slice(&metadata).map_err(|e| {
+                    error!(target: LOG_TARGET, "Corrupt metadata for file key {:?}: {:?}", file_key, e);
+                    FileStorageError::CorruptMetadata
+                })?;
+                metadata.validate().map_err(|e| {
+                    error!(target: LOG_TARGET, "Corrupt metadata for file key {:?}: {:?}", file_key, e);
+                    FileStorageError::CorruptMetadata
+                })?;
+                self.metadata_cache().insert(*file_key, metadata.clone());
+                Ok(Some(metadata))
+            }
+        }
+    }
+
+    /// Checks for the key of the file in the metadata cache, then in [`Column::Metadata`],
+    /// without deserialising its metadata.
+    ///
+    /// The KeyValueDB backends don't check for a key alone, so the metadata of a file that isn't
+    /// cached is still read from the database.
+    fn file_exists(&self, file_key: &HasherOutT<T>) -> Result<bool, FileStorageError> {
+        if self.metadata_cache().get(file_key).is_some() {
+            return Ok(true);
+        }
+
+        self.storage
+            .db
+            .has_key(Column::Metadata.into(), file_key.as_ref())
+            .map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToReadStorage
+            })
+    }
+
+    /// Generates a proof for specified chunks of a file.
+    ///
+    /// Returns error if file is incomplete or proof generation fails.
+    fn generate_proof(
+        &self,
+        key: &HasherOutT<T>,
+        chunk_ids: &HashSet<ChunkId>,
+    ) -> Result<FileKeyProof, FileStorageError> {
+        self.flush()?;
+
+        check_chunks_per_proof(chunk_ids, self.max_chunks_per_proof)?;
+
+        let metadata = self
+            .get_metadata(key)?
+            .ok_or(FileStorageError::FileDoesNotExist)?;
+
+        let file_trie = self.get_file_trie(&metadata)?;
+
+        let stored_chunks = self.stored_chunks_count(key)?;
+        if metadata.chunks_count() != stored_chunks {
+            return Err(FileStorageError::IncompleteFile);
+        }
+
+        if metadata.fingerprint() != file_trie.get_root().as_ref() {
+            return Err(FileStorageError::FingerprintAndStoredFileMismatch);
+        }
+
+        file_trie
+            .generate_proof(chunk_ids)?
+            .to_file_key_proof(metadata.clone())
+            .map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToConstructFileKeyProof
+            })
+    }
+
+    /// Deletes a file and all its associated data.
+    fn delete_file(&mut self, file_key: &HasherOutT<T>) -> Result<(), FileStorageError> {
+        self.flush()?;
+
+        let metadata = self
+            .get_metadata(file_key)?
+            .ok_or(FileStorageError::FileDoesNotExist)?;
+
+        let mut transaction = DBTransaction::new();
+        self.delete_file_ops(file_key, &metadata, false, &mut transaction)?;
+
+        self.storage.write(transaction).map_err(|e| {
+            error!(target: LOG_TARGET,"{:?}", e);
+            FileStorageError::FailedToWriteToStorage
+        })?;
+
+        Ok(())
+    }
+
+    /// Replaces the file with `old_key` by the file with `new_key` in a single transaction.
+    ///
+    /// If both files have the same fingerprint, the file trie of the old file is kept for the new
+    /// one. Otherwise, the nodes of the new file trie are written again in the same transaction,
+    /// after the deletion of the old ones, so that the nodes both tries share are not lost.
+    fn atomic_delete_and_insert(
+        &mut self,
+        old_key: HasherOutT<T>,
+        new_key: HasherOutT<T>,
+        new_metadata: FileMetadata,
+        new_data: Self::FileDataTrie,
+    ) -> Result<(), FileStorageError> {
+        self.flush()?;
+
+        let old_metadata = self
+            .get_metadata(&old_key)?
+            .ok_or(FileStorageError::FileDoesNotExist)?;
+
+        if new_key != old_key && self.get_metadata(&new_key)?.is_some() {
+            return Err(FileStorageError::FileAlreadyExists);
+        }
+
+        let keep_trie = old_metadata.fingerprint() == new_metadata.fingerprint();
+
+        let mut transaction = DBTransaction::new();
+        let trie_deleted =
+            self.delete_file_ops(&old_key, &old_metadata, keep_trie, &mut transaction)?;
+        self.insert_file_with_data_ops(
+            &new_key,
+            &new_metadata,
+            &new_data,
+            trie_deleted,
+            &mut transaction,
+        )?;
+
+        self.storage.write(transaction).map_err(|e| {
+            error!(target: LOG_TARGET,"{:?}", e);
+            FileStorageError::FailedToWriteToStorage
+        })?;
+
+        Ok(())
+    }
+
+    /// Deletes all files with a matching bucket ID prefix.
+    fn delete_files_with_prefix(
+        &mut self,
+        bucket_id_prefix: &[u8; 32],
+    ) -> Result<(), FileStorageError> {
+        self.flush()?;
+
+        let mut file_keys_to_delete = Vec::new();
+
+        {
+            let mut iter = self
+                .storage
+                .db
+                .iter_with_prefix(Column::BucketPrefix.into(), bucket_id_prefix);
+
+            while let Some(Ok((key, _))) = iter.next() {
+                // Remove the prefix from the key.
+                let file_key = key
+                    .iter()
+                    .skip(bucket_id_prefix.len())
+                    .copied()
+                    .collect::<Vec<u8>>();
+
+                let h_file_key = convert_raw_bytes_to_hasher_out::<T>(file_key).map_err(|e| {
+                    error!(target: LOG_TARGET, "{:?}", e);
+                    FileStorageError::FailedToParseKey
+                })?;
+
+                file_keys_to_delete.push(h_file_key);
+            }
+        }
+
+        for h_file_key in file_keys_to_delete {
+            self.delete_file(&h_file_key)?;
+        }
+
+        Ok(())
+    }
+
+    fn bucket_file_keys(
+        &self,
+        bucket_id: &[u8; 32],
+    ) -> Result<Vec<HasherOutT<T>>, FileStorageError> {
+        let mut file_keys = Vec::new();
+        for entry in self
+            .storage
+            .db
+            .iter_with_prefix(Column::BucketPrefix.into(), bucket_id)
+        {
+            let (key, _) = entry.map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToReadStorage
+            })?;
+            let file_key = convert_raw_bytes_to_hasher_out::<T>(key[bucket_id.len()..].to_vec())
+                .map_err(|e| {
+                    error!(target: LOG_TARGET, "{:?}", e);
+                    FileStorageError::FailedToParseKey
+                })?;
+            file_keys.push(file_key);
+        }
+        file_keys.sort();
+
+        Ok(file_keys)
+    }
+
+    /// Checks if a key is allowed based on the exclude type.
+    fn is_allowed(
+        &self,
+        file_key: &HasherOutT<T>,
+        exclude_type: ExcludeType,
+    ) -> Result<bool, FileStorageError> {
+        let exclude_column = get_exclude_type_db_column(exclude_type);
+        let find = self
+            .storage
+            .db
+            .get(exclude_column, file_key.as_ref())
+            .map_err(|e| {
+                error!(target: LOG_TARGET, "{:?}", e);
+                FileStorageError::FailedToReadStorage
+            })?;
+
+        match find {
+            Some(_) => return Ok(false),
+            None => return Ok(true),
+        }
+    }
+
+    /// Adds a key to the specified exclude list.
+    fn add_to_exclude_list(
+        &mut self,
+        file_key: HasherOutT<T>,
+        exclude_type: ExcludeType,
+    ) -> Result<(), FileStorageError> {
+        let exclude_column = get_exclude_type_db_column(exclude_type);
+
+        let mut transaction = DBTransaction::new();
+        transaction.put(exclude_column, file_key.as_ref(), &[]);
+
+        self.storage.db.write(transaction).map_err(|e| {
+            error!(target: LOG_TARGET, "Failed to write to DB: {}", e);
+            FileStorageError::FailedToWriteToStorage
+        })?;
+
+        info!("Key added to the exclude list : {:?}", file_key);
+        Ok(())
+    }
+
+    /// Removes a key from the specified exclude list.
+    fn remove_from_exclude_list(
+        &mut self,
+        file_key: &HasherOutT<T>,
+        exclude_type: ExcludeType,
+    ) -> Result<(), FileStorageError> {
+        let exclude_column = get_exclude_type_db_column(exclude_type);
+
+        let mut transaction = DBTransaction::new();
+        transaction.delete(exclude_column, file_key.as_ref());
+
+        self.storage.db.write(transaction).map_err(|e| {
+            error!(target: LOG_TARGET, "Failed to write to DB: {}", e);
+            FileStorageError::FailedToWriteToStorage
+        })?;
+
+        info!("Key removed to the exclude list : {:?}", file_key);
+        Ok(())
+    }
+
+    fn shut_down(&mut self) -> Result<(), FileStorageError> {
+        self.flush()?;
+        if !self.crash_recovery_flag {
+            return Ok(());
+        }
+
+        let mut transaction = DBTransaction::new();
+        transaction.delete(Column::CrashRecovery.into(), CRASH_RECOVERY_FLAG);
+        self.storage.write(transaction).map_err(|e| {
+            error!(target: LOG_TARGET, "Failed to clear the crash recovery flag: {:?}", e);
+            FileStorageError::FailedToWriteToStorage
+        })?;
+        self.crash_recovery_flag = false;
+
+        Ok(())
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use kvdb_memorydb::InMemory;
-    use shc_common::types::{Fingerprint, FILE_CHUNK_SIZE};
-    use sp_core::H256;
-    use sp_runtime::traits::BlakeTwo256;
-    use sp_runtime::AccountId32;
-    use sp_trie::LayoutV1;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kvdb_memorydb::InMemory;
+    use shc_common::types::{Fingerprint, FILE_CHUNK_SIZE};
+    use sp_core::H256;
+    use sp_runtime::traits::BlakeTwo256;
+    use sp_runtime::AccountId32;
+    use sp_trie::LayoutV1;
+
+    use crate::error::Error;
+
+    fn stored_chunks_count(
+        trie: &RocksDbFileDataTrie<LayoutV1<BlakeTwo256>, InMemory>,
+    ) -> Result<u64, FileStorageError> {
+        let db = trie.as_hash_db();
+        let trie = TrieDBBuilder::<LayoutV1<BlakeTwo256>>::new(&db, &trie.root).build();
+
+        let count = trie
+            .iter()
+            .map_err(|e| {
+                error!(target: LOG_TARGET, "Failed to construct Trie iterator: {}", e);
+                FileStorageError::FailedToConstructTrieIter
+            })?
+            .count();
+
+        Ok(count as u64)
+    }
+
+    #[test]
+    fn file_trie_create_empty_works() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+
+        let file_trie = RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+
+        // expected hash is the root hash of an empty tree.
+        let expected_hash = HasherOutT::<LayoutV1<BlakeTwo256>>::try_from([
+            3, 23, 10, 46, 117, 151, 183, 183, 227, 216, 76, 5, 57, 29, 19, 154, 98, 177, 87, 231,
+            135, 134, 216, 192, 130, 242, 157, 207, 76, 17, 19, 20,
+        ])
+        .unwrap();
+
+        assert_eq!(
+            H256::from(*file_trie.get_root()),
+            expected_hash,
+            "Root should be initialized to default."
+        );
+    }
+
+    #[test]
+    fn file_trie_write_chunk_works() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+
+        let mut file_trie = RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+        let old_root = *file_trie.get_root();
+        file_trie
+            .write_chunk(&ChunkId::new(0u64), &Chunk::from([1u8; 1024]))
+            .unwrap();
+        let new_root = file_trie.get_root();
+        assert_ne!(&old_root, new_root);
+
+        let chunk = file_trie.get_chunk(&ChunkId::new(0u64)).unwrap();
+        assert_eq!(chunk.as_slice(), [1u8; 1024]);
+    }
+
+    #[test]
+    fn file_trie_get_chunk_works() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+
+        let mut file_trie = RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+
+        let chunk = Chunk::from([3u8; 32]);
+        let chunk_id = ChunkId::new(3);
+        file_trie.write_chunk(&chunk_id, &chunk).unwrap();
+        let chunk = file_trie.get_chunk(&chunk_id).unwrap();
+        assert_eq!(chunk.as_slice(), [3u8; 32]);
+    }
+
+    #[test]
+    fn file_trie_chunks_in_order_works() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+
+        let mut file_trie = RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+
+        // The compact encoded key of chunk 64 sorts before the key of chunk 1.
+        for id in [64u64, 1, 300, 0, 63] {
+            file_trie
+                .write_chunk(&ChunkId::new(id), &Chunk::from([id as u8; 32]))
+                .unwrap();
+        }
+
+        let chunks = file_trie
+            .chunks_in_order()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let expected = [0u64, 1, 63, 64, 300]
+            .map(|id| (ChunkId::new(id), Chunk::from([id as u8; 32])))
+            .to_vec();
+        assert_eq!(chunks, expected);
+    }
+
+    #[test]
+    fn file_trie_reanchor_works() {
+        let source_storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+        let destination_storage = StorageDb::<LayoutV1<BlakeTwo256>, InMemory>::new(Arc::new(
+            kvdb_memorydb::create(NUMBER_OF_COLUMNS),
+        ));
+
+        let mut file_trie =
+            RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(source_storage.clone());
+
+        // Chunks big enough for their values to be stored out of the leaves.
+        let chunks: Vec<Chunk> = (0..10u8).map(|i| Chunk::from([i; 1024])).collect();
+        for (id, chunk) in chunks.iter().enumerate() {
+            file_trie
+                .write_chunk(&ChunkId::new(id as u64), chunk)
+                .unwrap();
+        }
+        let root = *file_trie.get_root();
+        let source_nodes = source_storage.db.iter(Column::Chunks.into()).count();
+
+        let file_trie = file_trie.reanchor(destination_storage.clone()).unwrap();
+        assert_eq!(*file_trie.get_root(), root);
+
+        // Only the nodes reachable from the root are copied.
+        assert_eq!(
+            destination_storage.db.iter(Column::Chunks.into()).count(),
+            source_nodes
+        );
+
+        // The chunks are read from the destination alone, even once the source is dropped.
+        drop(source_storage);
+        let file_trie = RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::from_existing(
+            destination_storage,
+            &root,
+        );
+        for (id, chunk) in chunks.iter().enumerate() {
+            assert_eq!(
+                &file_trie.get_chunk(&ChunkId::new(id as u64)).unwrap(),
+                chunk
+            );
+        }
+        assert_eq!(
+            stored_chunks_count(&file_trie).unwrap(),
+            chunks.len() as u64
+        );
+        assert!(file_trie
+            .generate_proof(&HashSet::from([ChunkId::new(3)]))
+            .is_ok());
+    }
+
+    #[test]
+    fn file_trie_compressed_chunks_keep_roots_and_data() {
+        let storage = StorageDb::<LayoutV1<BlakeTwo256>, InMemory>::new(Arc::new(
+            kvdb_memorydb::create(NUMBER_OF_COLUMNS),
+        ));
+        let compressed_storage = StorageDb::<LayoutV1<BlakeTwo256>, InMemory>::new(Arc::new(
+            kvdb_memorydb::create(NUMBER_OF_COLUMNS),
+        ))
+        .with_compression(ChunkCompression::Zstd { level: 3 });
+
+        // Log-like chunks, which compress well.
+        let chunks: Vec<Chunk> = (0..10u8)
+            .map(|i| {
+                format!("INFO chunk {} written\n", i)
+                    .repeat(64)
+                    .into_bytes()
+            })
+            .collect();
+
+        let mut file_trie =
+            RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
+        let mut compressed_file_trie =
+            RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(compressed_storage.clone());
+        for (id, chunk) in chunks.iter().enumerate() {
+            file_trie
+                .write_chunk(&ChunkId::new(id as u64), chunk)
+                .unwrap();
+            compressed_file_trie
+                .write_chunk(&ChunkId::new(id as u64), chunk)
+                .unwrap();
+        }
+
+        // Compression happens below the trie, so the root and proofs are the same.
+        let root = *file_trie.get_root();
+        assert_eq!(*compressed_file_trie.get_root(), root);
+        let chunk_ids = HashSet::from([ChunkId::new(2), ChunkId::new(7)]);
+        assert_eq!(
+            compressed_file_trie
+                .generate_proof(&chunk_ids)
+                .unwrap()
+                .encode(),
+            file_trie.generate_proof(&chunk_ids).unwrap().encode()
+        );
+
+        let stored_bytes = |storage: &StorageDb<LayoutV1<BlakeTwo256>, InMemory>| {
+            storage
+                .db
+                .iter(Column::Chunks.into())
+                .map(|entry| entry.unwrap().1.len())
+                .sum::<usize>()
+        };
+        assert!(stored_bytes(&compressed_storage) < stored_bytes(&storage));
+
+        // Reading from the storage alone returns the original chunks.
+        drop(compressed_file_trie);
+        let compressed_file_trie =
+            RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::from_existing(
+                compressed_storage,
+                &root,
+            );
+        for (id, chunk) in chunks.iter().enumerate() {
+            assert_eq!(
+                &compressed_file_trie
+                    .get_chunk(&ChunkId::new(id as u64))
+                    .unwrap(),
+                chunk
+            );
+        }
+    }
+
+    #[test]
+    fn chunk_compression_round_trips_values() {
+        let compression = ChunkCompression::Zstd { level: 3 };
+
+        for value in [vec![], vec![7u8; 3], vec![42u8; 4096]] {
+            let stored = compression.compress(&value);
+            assert_eq!(compression.decompress(stored).unwrap(), value);
+        }
+
+        // Values that don't get smaller are stored as they are, behind their tag.
+        assert_eq!(compression.compress(&[7u8; 3]), vec![0, 7, 7, 7]);
+        assert!(compression.decompress(vec![]).is_err());
+        assert!(compression.decompress(vec![9, 1, 2]).is_err());
+        assert_eq!(ChunkCompression::None.compress(&[7u8; 3]), vec![7u8; 3]);
+    }
+
+    #[test]
+    fn file_trie_stored_chunks_count_works() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+
+        let chunk_ids = vec![ChunkId::new(0u64), ChunkId::new(1u64)];
+        let chunks = vec![Chunk::from([0u8; 1024]), Chunk::from([1u8; 1024])];
+
+        let mut file_trie = RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+
+        file_trie.write_chunk(&chunk_ids[0], &chunks[0]).unwrap();
+        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 1);
+        assert!(file_trie.get_chunk(&chunk_ids[0]).is_ok());
+
+        file_trie.write_chunk(&chunk_ids[1], &chunks[1]).unwrap();
+        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 2);
+        assert!(file_trie.get_chunk(&chunk_ids[1]).is_ok());
+    }
+
+    #[test]
+    fn file_trie_generate_proof_works() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+
+        let chunk_ids = vec![ChunkId::new(0u64), ChunkId::new(1u64), ChunkId::new(2u64)];
+        let chunk_ids_set: HashSet<ChunkId> = chunk_ids.iter().cloned().collect();
+        let chunks = vec![
+            Chunk::from([0u8; 1024]),
+            Chunk::from([1u8; 1024]),
+            Chunk::from([2u8; 1024]),
+        ];
+
+        let mut file_trie = RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+
+        file_trie.write_chunk(&chunk_ids[0], &chunks[0]).unwrap();
+        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 1);
+        assert!(file_trie.get_chunk(&chunk_ids[0]).is_ok());
+
+        file_trie.write_chunk(&chunk_ids[1], &chunks[1]).unwrap();
+        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 2);
+        assert!(file_trie.get_chunk(&chunk_ids[1]).is_ok());
+
+        file_trie.write_chunk(&chunk_ids[2], &chunks[2]).unwrap();
+        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 3);
+        assert!(file_trie.get_chunk(&chunk_ids[2]).is_ok());
+
+        let file_proof = file_trie.generate_proof(&chunk_ids_set).unwrap();
+
+        assert_eq!(
+            file_proof.fingerprint.as_ref(),
+            file_trie.get_root().as_ref()
+        );
+    }
+
+    #[test]
+    fn file_trie_delete_works() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+
+        let chunk_ids = vec![ChunkId::new(0u64), ChunkId::new(1u64), ChunkId::new(2u64)];
+
+        let chunks = vec![
+            Chunk::from([0u8; 1024]),
+            Chunk::from([1u8; 1024]),
+            Chunk::from([2u8; 1024]),
+        ];
+
+        let mut file_trie = RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+
+        file_trie.write_chunk(&chunk_ids[0], &chunks[0]).unwrap();
+        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 1);
+        assert!(file_trie.get_chunk(&chunk_ids[0]).is_ok());
+
+        file_trie.write_chunk(&chunk_ids[1], &chunks[1]).unwrap();
+        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 2);
+        assert!(file_trie.get_chunk(&chunk_ids[1]).is_ok());
+
+        file_trie.write_chunk(&chunk_ids[2], &chunks[2]).unwrap();
+        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 3);
+        assert!(file_trie.get_chunk(&chunk_ids[2]).is_ok());
+
+        file_trie.delete().unwrap();
+        assert!(file_trie.get_chunk(&chunk_ids[0]).is_err());
+        assert!(file_trie.get_chunk(&chunk_ids[1]).is_err());
+        assert!(file_trie.get_chunk(&chunk_ids[2]).is_err());
+
+        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 0);
+    }
+
+    #[test]
+    fn file_storage_write_chunk_works() {
+        let chunks = vec![
+            Chunk::from([5u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([6u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([7u8; FILE_CHUNK_SIZE as usize]),
+        ];
+
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+
+        let chunk_ids: Vec<ChunkId> = chunks
+            .iter()
+            .enumerate()
+            .map(|(id, _)| ChunkId::new(id as u64))
+            .collect();
+
+        // Create a file trie to get the expected fingerprint
+        let mut file_trie =
+            RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
+
+        for (chunk_id, chunk) in chunk_ids.iter().zip(chunks.iter()) {
+            file_trie.write_chunk(chunk_id, chunk).unwrap();
+        }
+
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            FILE_CHUNK_SIZE * chunks.len() as u64,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+
+        let key = file_metadata.file_key::<BlakeTwo256>();
+        let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+
+        // Insert file metadata first
+        file_storage.insert_file(key, file_metadata).unwrap();
+
+        // Write chunks one by one and verify
+        for (chunk_id, chunk) in chunk_ids.iter().zip(chunks.iter()) {
+            let result = file_storage.write_chunk(&key, chunk_id, chunk);
+            assert!(result.is_ok());
+            assert!(file_storage.get_chunk(&key, chunk_id).is_ok());
+        }
+
+        // Verify final state
+        assert!(file_storage.get_metadata(&key).is_ok());
+        assert!(file_storage.get_chunk(&key, &chunk_ids[0]).is_ok());
+        assert!(file_storage.get_chunk(&key, &chunk_ids[1]).is_ok());
+        assert!(file_storage.get_chunk(&key, &chunk_ids[2]).is_ok());
+    }
+
+    #[test]
+    fn file_storage_write_chunk_reports_progress() {
+        let chunks = vec![
+            Chunk::from([5u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([6u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([7u8; FILE_CHUNK_SIZE as usize]),
+        ];
+
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+
+        let chunk_ids: Vec<ChunkId> = chunks
+            .iter()
+            .enumerate()
+            .map(|(id, _)| ChunkId::new(id as u64))
+            .collect();
+
+        let mut file_trie =
+            RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
+        for (chunk_id, chunk) in chunk_ids.iter().zip(chunks.iter()) {
+            file_trie.write_chunk(chunk_id, chunk).unwrap();
+        }
+
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            FILE_CHUNK_SIZE * chunks.len() as u64,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+
+        let key = file_metadata.file_key::<BlakeTwo256>();
+        let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+        file_storage.insert_file(key, file_metadata).unwrap();
+
+        for (i, (chunk_id, chunk)) in chunk_ids.iter().zip(chunks.iter()).enumerate().take(2) {
+            let outcome = file_storage.write_chunk(&key, chunk_id, chunk).unwrap();
+            assert_eq!(
+                outcome,
+                FileStorageWriteOutcome::FileIncomplete {
+                    stored: i as u64 + 1,
+                    total: 3
+                }
+            );
+            assert_eq!(
+                file_storage.stored_chunks_count(&key).unwrap(),
+                i as u64 + 1
+            );
+        }
+        let outcome = file_storage
+            .write_chunk(&key, &chunk_ids[2], &chunks[2])
+            .unwrap();
+        assert_eq!(outcome, FileStorageWriteOutcome::FileComplete);
+        assert_eq!(outcome.progress_percentage(), 100.0);
+    }
+
+    #[test]
+    fn file_storage_write_chunk_rejects_out_of_range_chunk_id() {
+        let chunks = vec![
+            Chunk::from([5u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([6u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([7u8; FILE_CHUNK_SIZE as usize]),
+        ];
+
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+
+        let chunk_ids: Vec<ChunkId> = chunks
+            .iter()
+            .enumerate()
+            .map(|(id, _)| ChunkId::new(id as u64))
+            .collect();
+
+        let mut file_trie =
+            RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
+
+        for (chunk_id, chunk) in chunk_ids.iter().zip(chunks.iter()) {
+            file_trie.write_chunk(chunk_id, chunk).unwrap();
+        }
+
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            FILE_CHUNK_SIZE * chunks.len() as u64,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+
+        let key = file_metadata.file_key::<BlakeTwo256>();
+        let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+        file_storage
+            .insert_file(key, file_metadata.clone())
+            .unwrap();
+
+        // A chunk ID equal to the chunks count is out of range.
+        let out_of_range_chunk_id = ChunkId::new(file_metadata.chunks_count());
+        assert!(matches!(
+            file_storage.write_chunk(&key, &out_of_range_chunk_id, &chunks[0]),
+            Err(FileStorageError::ChunkIdOutOfRange)
+        ));
+        assert_eq!(file_storage.stored_chunks_count(&key).unwrap(), 0);
+
+        // The file can still be completed with the valid chunks.
+        for (chunk_id, chunk) in chunk_ids.iter().zip(chunks.iter()).take(2) {
+            assert!(matches!(
+                file_storage.write_chunk(&key, chunk_id, chunk),
+                Ok(FileStorageWriteOutcome::FileIncomplete { .. })
+            ));
+        }
+        assert!(matches!(
+            file_storage.write_chunk(&key, &chunk_ids[2], &chunks[2]),
+            Ok(FileStorageWriteOutcome::FileComplete)
+        ));
+        assert!(file_storage.is_file_complete(&key).unwrap());
+    }
+
+    #[test]
+    fn file_storage_present_chunk_ids_works() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+
+        // A file with more than 64 chunks, so that some chunk IDs take more than one byte in
+        // the trie keys.
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            FILE_CHUNK_SIZE * 70,
+            H256::repeat_byte(9).as_ref().into(),
+        )
+        .unwrap();
+
+        let key = file_metadata.file_key::<BlakeTwo256>();
+        let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+        file_storage.insert_file(key, file_metadata).unwrap();
+        assert!(file_storage.present_chunk_ids(&key).unwrap().is_empty());
+
+        // Write non-contiguous chunks, out of order.
+        for chunk_id in [65, 0, 7, 3] {
+            file_storage
+                .write_chunk(
+                    &key,
+                    &ChunkId::new(chunk_id),
+                    &Chunk::from([chunk_id as u8; FILE_CHUNK_SIZE as usize]),
+                )
+                .unwrap();
+        }
+
+        assert_eq!(file_storage.stored_chunks_count(&key).unwrap(), 4);
+        assert_eq!(
+            file_storage.present_chunk_ids(&key).unwrap(),
+            vec![
+                ChunkId::new(0),
+                ChunkId::new(3),
+                ChunkId::new(7),
+                ChunkId::new(65)
+            ]
+        );
+
+        assert!(matches!(
+            file_storage.present_chunk_ids(&H256::repeat_byte(1)),
+            Err(FileStorageError::FileDoesNotExist)
+        ));
+    }
+
+    #[test]
+    fn file_storage_seal_file_rejects_further_writes() {
+        let chunks = vec![
+            Chunk::from([5u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([6u8; FILE_CHUNK_SIZE as usize]),
+        ];
+
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+        let chunk_ids: Vec<ChunkId> = chunks
+            .iter()
+            .enumerate()
+            .map(|(id, _)| ChunkId::new(id as u64))
+            .collect();
+
+        let mut file_trie =
+            RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
+        for (chunk_id, chunk) in chunk_ids.iter().zip(chunks.iter()) {
+            file_trie.write_chunk(chunk_id, chunk).unwrap();
+        }
+
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            FILE_CHUNK_SIZE * chunks.len() as u64,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+
+        let key = file_metadata.file_key::<BlakeTwo256>();
+        let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+        file_storage.insert_file(key, file_metadata).unwrap();
+
+        // An incomplete file cannot be sealed.
+        file_storage
+            .write_chunk(&key, &chunk_ids[0], &chunks[0])
+            .unwrap();
+        assert!(matches!(
+            file_storage.seal_file(&key),
+            Err(FileStorageError::IncompleteFile)
+        ));
+
+        assert!(matches!(
+            file_storage.write_chunk(&key, &chunk_ids[1], &chunks[1]),
+            Ok(FileStorageWriteOutcome::FileComplete)
+        ));
+        file_storage.seal_file(&key).unwrap();
+        // Sealing twice is a no-op.
+        file_storage.seal_file(&key).unwrap();
+
+        // No chunk can be written to a sealed file, whether it already exists or not.
+        assert!(matches!(
+            file_storage.write_chunk(&key, &chunk_ids[1], &chunks[1]),
+            Err(FileStorageError::FileSealed)
+        ));
+        assert!(matches!(
+            file_storage.write_chunk(&key, &ChunkId::new(2), &chunks[0]),
+            Err(FileStorageError::FileSealed)
+        ));
+        assert_eq!(file_storage.stored_chunks_count(&key).unwrap(), 2);
+        assert!(file_storage.is_file_complete(&key).unwrap());
+    }
+
+    #[test]
+    fn file_storage_insert_file_works() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+
+        let chunks = vec![
+            Chunk::from([5u8; 32]),
+            Chunk::from([6u8; 32]),
+            Chunk::from([7u8; 32]),
+        ];
+
+        let chunk_ids: Vec<ChunkId> = chunks
+            .iter()
+            .enumerate()
+            .map(|(id, _)| ChunkId::new(id as u64))
+            .collect();
+
+        let mut file_trie =
+            RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
+
+        file_trie.write_chunk(&chunk_ids[0], &chunks[0]).unwrap();
+        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 1);
+        assert!(file_trie.get_chunk(&chunk_ids[0]).is_ok());
+
+        file_trie.write_chunk(&chunk_ids[1], &chunks[1]).unwrap();
+        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 2);
+        assert!(file_trie.get_chunk(&chunk_ids[1]).is_ok());
+
+        file_trie.write_chunk(&chunk_ids[2], &chunks[2]).unwrap();
+        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 3);
+        assert!(file_trie.get_chunk(&chunk_ids[2]).is_ok());
+
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            32u64 * chunks.len() as u64,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+
+        let key = file_metadata.file_key::<BlakeTwo256>();
+        let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+        file_storage
+            .insert_file_with_data(key, file_metadata, file_trie)
+            .unwrap();
+
+        assert!(file_storage.get_metadata(&key).is_ok());
+        assert!(file_storage.get_chunk(&key, &chunk_ids[0]).is_ok());
+        assert!(file_storage.get_chunk(&key, &chunk_ids[1]).is_ok());
+        assert!(file_storage.get_chunk(&key, &chunk_ids[2]).is_ok());
+    }
+
+    #[test]
+    fn file_storage_delete_file_works() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+
+        let chunks = vec![
+            Chunk::from([5u8; 32]),
+            Chunk::from([6u8; 32]),
+            Chunk::from([7u8; 32]),
+        ];
+
+        let chunk_ids: Vec<ChunkId> = chunks
+            .iter()
+            .enumerate()
+            .map(|(id, _)| ChunkId::new(id as u64))
+            .collect();
+
+        let mut file_trie =
+            RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
+        file_trie.write_chunk(&chunk_ids[0], &chunks[0]).unwrap();
+        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 1);
+        assert!(file_trie.get_chunk(&chunk_ids[0]).is_ok());
+
+        file_trie.write_chunk(&chunk_ids[1], &chunks[1]).unwrap();
+        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 2);
+        assert!(file_trie.get_chunk(&chunk_ids[1]).is_ok());
+
+        file_trie.write_chunk(&chunk_ids[2], &chunks[2]).unwrap();
+        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 3);
+        assert!(file_trie.get_chunk(&chunk_ids[2]).is_ok());
+
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            32u64 * chunks.len() as u64,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+
+        let key = file_metadata.file_key::<BlakeTwo256>();
+        let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+        file_storage
+            .insert_file_with_data(key, file_metadata, file_trie)
+            .unwrap();
+        assert!(file_storage.get_metadata(&key).is_ok());
+
+        assert!(file_storage.delete_file(&key).is_ok());
+
+        // Should get a None option here when trying to get File Metadata.
+        assert!(file_storage
+            .get_metadata(&key)
+            .is_ok_and(|metadata| metadata.is_none()));
+        assert!(file_storage.get_chunk(&key, &chunk_ids[0]).is_err());
+        assert!(file_storage.get_chunk(&key, &chunk_ids[1]).is_err());
+        assert!(file_storage.get_chunk(&key, &chunk_ids[2]).is_err());
+    }
+
+    #[test]
+    fn file_storage_atomic_delete_and_insert_works() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+
+        let new_file = |chunks: &[Chunk], location: &str| {
+            let mut file_trie =
+                RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
+            for (id, chunk) in chunks.iter().enumerate() {
+                file_trie
+                    .write_chunk(&ChunkId::new(id as u64), chunk)
+                    .unwrap();
+            }
+            let file_metadata = FileMetadata::new(
+                <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+                [1u8; 32].to_vec(),
+                location.to_string().into_bytes(),
+                FILE_CHUNK_SIZE * chunks.len() as u64,
+                file_trie.get_root().as_ref().into(),
+            )
+            .unwrap();
+            (
+                file_metadata.file_key::<BlakeTwo256>(),
+                file_metadata,
+                file_trie,
+            )
+        };
+
+        let old_chunks = vec![
+            Chunk::from([5u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([6u8; FILE_CHUNK_SIZE as usize]),
+        ];
+        // The new version of the file shares its first chunk with the old one, so deleting the
+        // nodes of the old file must not remove it.
+        let new_chunks = vec![
+            Chunk::from([5u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([7u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([8u8; FILE_CHUNK_SIZE as usize]),
+        ];
+        let (old_key, old_metadata, old_trie) = new_file(&old_chunks, "location");
+        let (new_key, new_metadata, new_trie) = new_file(&new_chunks, "location_v2");
+
+        let mut file_storage =
+            RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
+        file_storage
+            .insert_file_with_data(old_key, old_metadata, old_trie)
+            .unwrap();
+
+        file_storage
+            .atomic_delete_and_insert(old_key, new_key, new_metadata.clone(), new_trie)
+            .unwrap();
+
+        assert!(file_storage.get_metadata(&old_key).unwrap().is_none());
+        assert!(file_storage.get_chunk(&old_key, &ChunkId::new(0)).is_err());
+        assert_eq!(
+            file_storage.get_metadata(&new_key).unwrap(),
+            Some(new_metadata)
+        );
+        assert_eq!(file_storage.stored_chunks_count(&new_key).unwrap(), 3);
+        assert!(file_storage.is_file_complete(&new_key).unwrap());
+        for (id, chunk) in new_chunks.iter().enumerate() {
+            assert_eq!(
+                &file_storage
+                    .get_chunk(&new_key, &ChunkId::new(id as u64))
+                    .unwrap(),
+                chunk
+            );
+        }
+        assert!(file_storage
+            .generate_proof(&new_key, &HashSet::from([ChunkId::new(0), ChunkId::new(2)]))
+            .is_ok());
+
+        // The old file is no longer in its bucket, and the new one is.
+        let bucket_files: Vec<_> = storage
+            .db
+            .iter_with_prefix(Column::BucketPrefix.into(), &[1u8; 32])
+            .map(|entry| entry.unwrap().0[32..].to_vec())
+            .collect();
+        assert_eq!(bucket_files, vec![new_key.as_ref().to_vec()]);
+
+        // A replacement that fails leaves the storage untouched.
+        let (other_key, other_metadata, other_trie) = new_file(&old_chunks, "location_v3");
+        assert!(matches!(
+            file_storage.atomic_delete_and_insert(old_key, other_key, other_metadata, other_trie),
+            Err(FileStorageError::FileDoesNotExist)
+        ));
+        assert!(file_storage.get_metadata(&other_key).unwrap().is_none());
+        assert!(file_storage.is_file_complete(&new_key).unwrap());
+    }
+
+    #[test]
+    fn file_storage_copy_file_works() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+
+        let chunks = vec![
+            Chunk::from([5u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([6u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([7u8; FILE_CHUNK_SIZE as usize]),
+        ];
+
+        let chunk_ids: Vec<ChunkId> = chunks
+            .iter()
+            .enumerate()
+            .map(|(id, _)| ChunkId::new(id as u64))
+            .collect();
+
+        let mut file_trie =
+            RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
+        for (chunk_id, chunk) in chunk_ids.iter().zip(chunks.iter()) {
+            file_trie.write_chunk(chunk_id, chunk).unwrap();
+        }
+
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            FILE_CHUNK_SIZE * chunks.len() as u64,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+
+        let key = file_metadata.file_key::<BlakeTwo256>();
+        let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+        file_storage
+            .insert_file_with_data(key, file_metadata.clone(), file_trie)
+            .unwrap();
+
+        // Copy the file into a second bucket.
+        let new_file_metadata = FileMetadata::new(
+            file_metadata.owner().clone(),
+            [2u8; 32].to_vec(),
+            file_metadata.location().clone(),
+            file_metadata.file_size(),
+            *file_metadata.fingerprint(),
+        )
+        .unwrap();
+
+        let new_key = file_storage
+            .copy_file(&key, new_file_metadata.clone())
+            .unwrap();
+        assert_eq!(new_key, new_file_metadata.file_key::<BlakeTwo256>());
+        assert_ne!(new_key, key);
+
+        assert_eq!(
+            file_storage.get_metadata(&new_key).unwrap(),
+            Some(new_file_metadata.clone())
+        );
+        assert!(file_storage.is_file_complete(&new_key).unwrap());
+        for (chunk_id, chunk) in chunk_ids.iter().zip(chunks.iter()) {
+            assert_eq!(&file_storage.get_chunk(&new_key, chunk_id).unwrap(), chunk);
+        }
+
+        // Copying again to the same key fails.
+        assert!(matches!(
+            file_storage.copy_file(&key, new_file_metadata),
+            Err(FileStorageError::FileAlreadyExists)
+        ));
+
+        // Deleting the source file keeps the chunks of the copy.
+        file_storage.delete_file(&key).unwrap();
+        assert!(file_storage.get_metadata(&key).unwrap().is_none());
+        for (chunk_id, chunk) in chunk_ids.iter().zip(chunks.iter()) {
+            assert_eq!(&file_storage.get_chunk(&new_key, chunk_id).unwrap(), chunk);
+        }
+
+        // Deleting the copy removes the shared chunks.
+        file_storage.delete_file(&new_key).unwrap();
+        assert!(file_storage.get_chunk(&new_key, &chunk_ids[0]).is_err());
+    }
+
+    #[test]
+    fn file_storage_generate_proof_works() {
+        let chunks = vec![
+            Chunk::from([5u8; 32]),
+            Chunk::from([6u8; 32]),
+            Chunk::from([7u8; 32]),
+        ];
+
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+
+        let user_storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+
+        let mut user_file_trie =
+            RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(user_storage.clone());
+
+        for (id, chunk) in chunks.iter().enumerate() {
+            user_file_trie
+                .write_chunk(&ChunkId::new(id as u64), chunk)
+                .unwrap();
+        }
+
+        let fingerprint = Fingerprint::from(user_file_trie.get_root().as_ref());
+
+        let chunk_ids: Vec<ChunkId> = chunks
+            .iter()
+            .enumerate()
+            .map(|(id, _)| ChunkId::new(id as u64))
+            .collect();
+
+        let chunk_ids_set: HashSet<ChunkId> = chunk_ids.iter().cloned().collect();
+
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            1024u64 * chunks.len() as u64,
+            fingerprint,
+        )
+        .unwrap();
+
+        let key = file_metadata.file_key::<BlakeTwo256>();
+
+        let mut file_storage =
+            RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
+        file_storage.insert_file(key, file_metadata).unwrap();
+        assert!(file_storage.get_metadata(&key).is_ok());
+
+        file_storage
+            .write_chunk(&key, &chunk_ids[0], &chunks[0])
+            .unwrap();
+        assert!(file_storage.get_chunk(&key, &chunk_ids[0]).is_ok());
+
+        file_storage
+            .write_chunk(&key, &chunk_ids[1], &chunks[1])
+            .unwrap();
+        assert!(file_storage.get_chunk(&key, &chunk_ids[1]).is_ok());
+
+        file_storage
+            .write_chunk(&key, &chunk_ids[2], &chunks[2])
+            .unwrap();
+        assert!(file_storage.get_chunk(&key, &chunk_ids[2]).is_ok());
+
+        let file_proof = file_storage.generate_proof(&key, &chunk_ids_set).unwrap();
+        let proven_leaves = file_proof.proven::<LayoutV1<BlakeTwo256>>().unwrap();
+        for (id, leaf) in proven_leaves.iter().enumerate() {
+            assert_eq!(chunk_ids[id], leaf.key);
+            assert_eq!(chunks[id], leaf.data);
+        }
+    }
+
+    #[test]
+    fn same_chunk_id_with_different_data_produces_different_roots() {
+        use sp_trie::MemoryDB;
+
+        let mut memdb = MemoryDB::<BlakeTwo256>::default();
+        let mut root1 = Default::default();
+        let mut root2 = Default::default();
+        let chunks1 = vec![0u8; 32];
+        let chunks2 = vec![1u8; 32];
+
+        {
+            let mut t1 =
+                TrieDBMutBuilder::<LayoutV1<BlakeTwo256>>::new(&mut memdb, &mut root1).build();
+            t1.insert(&[0u8; 32], &chunks1).unwrap();
+        }
+        {
+            let mut t2 =
+                TrieDBMutBuilder::<LayoutV1<BlakeTwo256>>::new(&mut memdb, &mut root2).build();
+            t2.insert(&[0u8; 32], &chunks2).unwrap();
+        }
+
+        assert_ne!(root1, root2)
+    }
+
+    #[test]
+    fn delete_files_with_prefix_works() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+
+        fn create_file_and_metadata(
+            storage: StorageDb<LayoutV1<BlakeTwo256>, InMemory>,
+            chunks: Vec<Chunk>,
+            bucket_id: [u8; 32],
+            location: &str,
+        ) -> (
+            FileMetadata,
+            H256,
+            Vec<ChunkId>,
+            RocksDbFileDataTrie<LayoutV1<BlakeTwo256>, InMemory>,
+        ) {
+            // Convert chunks into chunk IDs for referencing each chunk in the trie.
+            let chunk_ids: Vec<ChunkId> = chunks
+                .iter()
+                .enumerate()
+                .map(|(id, _)| ChunkId::new(id as u64))
+                .collect();
+
+            // Create a new file trie for storing file chunks.
+            let mut file_trie =
+                RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
+
+            // Write each chunk into the trie.
+            for (i, chunk) in chunks.iter().enumerate() {
+                file_trie.write_chunk(&chunk_ids[i], chunk).unwrap();
+            }
+
+            // Create metadata for the file, including bucket ID, location, and owner.
+            let file_metadata = FileMetadata::new(
+                <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+                bucket_id.to_vec(),
+                location.to_string().into_bytes(),
+                32u64 * chunks.len() as u64,
+                file_trie.get_root().as_ref().into(),
+            )
+            .unwrap();
+
+            let key = file_metadata.file_key::<BlakeTwo256>();
+
+            // Return the metadata, key, chunk IDs, and the trie.
+            (file_metadata, key, chunk_ids, file_trie)
+        }
+
+        // Step 2: Define test data for three files.
+        // These are the chunks of data that will be stored in the file trie.
+        let chunks_1 = vec![
+            Chunk::from([5u8; 32]),
+            Chunk::from([6u8; 32]),
+            Chunk::from([7u8; 32]),
+        ];
+        let chunks_2 = vec![
+            Chunk::from([8u8; 32]),
+            Chunk::from([9u8; 32]),
+            Chunk::from([10u8; 32]),
+        ];
+        let chunks_3 = vec![
+            Chunk::from([11u8; 32]),
+            Chunk::from([12u8; 32]),
+            Chunk::from([13u8; 32]),
+        ];
+
+        // Step 3: Create file metadata, keys, and file tries for each of the three files.
+        let (file_metadata_1, key_1, chunk_ids_1, file_trie_1) =
+            create_file_and_metadata(storage.clone(), chunks_1, [1u8; 32], "location");
+        let (file_metadata_2, key_2, chunk_ids_2, file_trie_2) =
+            create_file_and_metadata(storage.clone(), chunks_2, [2u8; 32], "location_2");
+        let (file_metadata_3, key_3, chunk_ids_3, file_trie_3) =
+            create_file_and_metadata(storage.clone(), chunks_3, [3u8; 32], "location_3");
 
-    fn stored_chunks_count(
-        trie: &RocksDbFileDataTrie<LayoutV1<BlakeTwo256>, InMemory>,
-    ) -> Result<u64, FileStorageError> {
-        let db = trie.as_hash_db();
-        let trie = TrieDBBuilder::<LayoutV1<BlakeTwo256>>::new(&db, &trie.root).build();
+        // Step 4: Create a file storage and insert all three files into the storage.
+        let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
 
-        let count = trie
-            .iter()
-            .map_err(|e| {
-                error!(target: LOG_TARGET, "Failed to construct Trie iterator: {}", e);
-                FileStorageError::FailedToConstructTrieIter
-            })?
-            .count();
+        file_storage
+            .insert_file_with_data(key_1, file_metadata_1.clone(), file_trie_1)
+            .unwrap();
+        file_storage
+            .insert_file_with_data(key_2, file_metadata_2.clone(), file_trie_2)
+            .unwrap();
+        file_storage
+            .insert_file_with_data(key_3, file_metadata_3.clone(), file_trie_3)
+            .unwrap();
 
-        Ok(count as u64)
+        // Step 5: Verify that all files and their chunks are inserted properly.
+        assert!(file_storage.get_metadata(&key_1).is_ok());
+        assert!(file_storage.get_metadata(&key_2).is_ok());
+        assert!(file_storage.get_metadata(&key_3).is_ok());
+
+        // Step 6: Delete files with the prefix [1u8; 32], which corresponds to bucket ID 1.
+        file_storage.delete_files_with_prefix(&[1u8; 32]).unwrap();
+
+        // Step 7: Assert that files with bucket_id 1 are deleted.
+        // We expect no metadata or chunks for the file with key_1 after deletion.
+        assert!(file_storage
+            .get_metadata(&key_1)
+            .is_ok_and(|metadata| metadata.is_none()));
+        assert!(file_storage.get_chunk(&key_1, &chunk_ids_1[0]).is_err());
+
+        // Step 8: Assert that files with other bucket_ids (bucket_id 2 and 3) are not deleted.
+        // Files with key_2 and key_3 should remain accessible.
+        assert!(file_storage.get_metadata(&key_2).is_ok());
+        assert!(file_storage.get_metadata(&key_3).is_ok());
+        assert!(file_storage.get_chunk(&key_2, &chunk_ids_2[0]).is_ok());
+        assert!(file_storage.get_chunk(&key_3, &chunk_ids_3[0]).is_ok());
     }
 
     #[test]
-    fn file_trie_create_empty_works() {
-        let storage = StorageDb {
-            db: Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)),
-            _marker: Default::default(),
-        };
+    fn get_metadata_rejects_corrupt_metadata() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
 
-        let file_trie = RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
-
-        // expected hash is the root hash of an empty tree.
-        let expected_hash = HasherOutT::<LayoutV1<BlakeTwo256>>::try_from([
-            3, 23, 10, 46, 117, 151, 183, 183, 227, 216, 76, 5, 57, 29, 19, 154, 98, 177, 87, 231,
-            135, 134, 216, 192, 130, 242, 157, 207, 76, 17, 19, 20,
-        ])
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            FILE_CHUNK_SIZE * 2,
+            Fingerprint::from([2u8; 32]),
+        )
         .unwrap();
+        let key = file_metadata.file_key::<BlakeTwo256>();
 
+        // The metadata is corrupted behind the back of the storage, so it must not be cached.
+        let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage)
+            .with_metadata_cache_capacity(0);
+        file_storage
+            .insert_file(key, file_metadata.clone())
+            .unwrap();
         assert_eq!(
-            H256::from(*file_trie.get_root()),
-            expected_hash,
-            "Root should be initialized to default."
+            file_storage.get_metadata(&key).unwrap(),
+            Some(file_metadata.clone())
         );
+
+        let corruptions: Vec<(&str, serde_json::Value)> = vec![
+            ("fingerprint", serde_json::json!([])),
+            ("fingerprint", serde_json::json!([1, 2, 3])),
+            ("bucket_id", serde_json::json!([])),
+            ("file_size", serde_json::json!(0)),
+        ];
+
+        for (field, value) in corruptions {
+            let mut raw_metadata = serde_json::to_value(&file_metadata).unwrap();
+            raw_metadata[field] = value;
+
+            let mut transaction = DBTransaction::new();
+            transaction.put(
+                Column::Metadata.into(),
+                key.as_ref(),
+                &serde_json::to_vec(&raw_metadata).unwrap(),
+            );
+            file_storage.storage.write(transaction).unwrap();
+
+            assert!(
+                matches!(
+                    file_storage.get_metadata(&key),
+                    Err(FileStorageError::CorruptMetadata)
+                ),
+                "Corrupt {} should be rejected",
+                field
+            );
+            // Writing chunks for the file fails cleanly instead of panicking.
+            assert!(file_storage
+                .write_chunk(&key, &ChunkId::new(0), &Chunk::from([1u8; 32]))
+                .is_err());
+        }
     }
 
-    #[test]
-    fn file_trie_write_chunk_works() {
-        let storage = StorageDb {
-            db: Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)),
-            _marker: Default::default(),
-        };
+    /// In-memory database counting the reads of each column, the iterations over any of them,
+    /// the keys iterated over and the transactions written.
+    struct ReadsCountingDb {
+        inner: InMemory,
+        reads: Mutex<HashMap<u32, usize>>,
+        iterations: std::sync::atomic::AtomicUsize,
+        iterated_keys: std::sync::atomic::AtomicUsize,
+        writes: std::sync::atomic::AtomicUsize,
+    }
 
-        let mut file_trie = RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
-        let old_root = *file_trie.get_root();
-        file_trie
-            .write_chunk(&ChunkId::new(0u64), &Chunk::from([1u8; 1024]))
-            .unwrap();
-        let new_root = file_trie.get_root();
-        assert_ne!(&old_root, new_root);
+    impl ReadsCountingDb {
+        fn new() -> Self {
+            Self {
+                inner: kvdb_memorydb::create(NUMBER_OF_COLUMNS),
+                reads: Default::default(),
+                iterations: Default::default(),
+                iterated_keys: Default::default(),
+                writes: Default::default(),
+            }
+        }
 
-        let chunk = file_trie.get_chunk(&ChunkId::new(0u64)).unwrap();
-        assert_eq!(chunk.as_slice(), [1u8; 1024]);
+        /// The number of reads of `column` so far.
+        fn reads(&self, column: Column) -> usize {
+            let column: u32 = column.into();
+            self.reads
+                .lock()
+                .unwrap()
+                .get(&column)
+                .copied()
+                .unwrap_or_default()
+        }
+    }
+
+    impl KeyValueDB for ReadsCountingDb {
+        fn get(&self, col: u32, key: &[u8]) -> io::Result<Option<kvdb::DBValue>> {
+            *self.reads.lock().unwrap().entry(col).or_default() += 1;
+            self.inner.get(col, key)
+        }
+
+        fn get_by_prefix(&self, col: u32, prefix: &[u8]) -> io::Result<Option<kvdb::DBValue>> {
+            self.inner.get_by_prefix(col, prefix)
+        }
+
+        fn write(&self, transaction: DBTransaction) -> io::Result<()> {
+            self.writes
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.inner.write(transaction)
+        }
+
+        fn iter<'a>(
+            &'a self,
+            col: u32,
+        ) -> Box<dyn Iterator<Item = io::Result<kvdb::DBKeyValue>> + 'a> {
+            self.iterations
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Box::new(self.inner.iter(col).inspect(|_| {
+                self.iterated_keys
+                    .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            }))
+        }
+
+        fn iter_with_prefix<'a>(
+            &'a self,
+            col: u32,
+            prefix: &'a [u8],
+        ) -> Box<dyn Iterator<Item = io::Result<kvdb::DBKeyValue>> + 'a> {
+            self.iterations
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Box::new(self.inner.iter_with_prefix(col, prefix).inspect(|_| {
+                self.iterated_keys
+                    .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            }))
+        }
     }
 
     #[test]
-    fn file_trie_get_chunk_works() {
-        let storage = StorageDb {
-            db: Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)),
-            _marker: Default::default(),
-        };
+    fn metadata_is_read_once_across_chunk_writes() {
+        const CHUNKS: u64 = 100;
 
-        let mut file_trie = RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+        let db = Arc::new(ReadsCountingDb::new());
+        let metadata_reads = || db.reads(Column::Metadata);
+        let storage = StorageDb::new(db.clone());
+        let mut file_storage =
+            RocksDbFileStorage::<LayoutV1<BlakeTwo256>, ReadsCountingDb>::new(storage);
 
-        let chunk = Chunk::from([3u8; 32]);
-        let chunk_id = ChunkId::new(3);
-        file_trie.write_chunk(&chunk_id, &chunk).unwrap();
-        let chunk = file_trie.get_chunk(&chunk_id).unwrap();
-        assert_eq!(chunk.as_slice(), [3u8; 32]);
+        let chunks: Vec<Chunk> = (0..CHUNKS)
+            .map(|i| vec![i as u8; FILE_CHUNK_SIZE as usize])
+            .collect();
+        let mut file_trie =
+            InMemoryFileStorage::<LayoutV1<BlakeTwo256>>::new().new_file_data_trie();
+        for (chunk_id, chunk) in chunks.iter().enumerate() {
+            file_trie
+                .write_chunk(&ChunkId::new(chunk_id as u64), chunk)
+                .unwrap();
+        }
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            FILE_CHUNK_SIZE * CHUNKS,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+        let key = file_metadata.file_key::<BlakeTwo256>();
+        file_storage.insert_file(key, file_metadata).unwrap();
+        assert_eq!(metadata_reads(), 0);
+        let writes_before_chunks = db.writes.load(std::sync::atomic::Ordering::SeqCst);
+
+        for (chunk_id, chunk) in chunks.iter().enumerate() {
+            let outcome = file_storage
+                .write_chunk(&key, &ChunkId::new(chunk_id as u64), chunk)
+                .unwrap();
+            assert_eq!(
+                outcome == FileStorageWriteOutcome::FileComplete,
+                chunk_id as u64 == CHUNKS - 1
+            );
+        }
+        assert_eq!(metadata_reads(), 1);
+        // Each chunk is written along with the partial root and chunk count in one transaction.
+        assert_eq!(
+            db.writes.load(std::sync::atomic::Ordering::SeqCst) - writes_before_chunks,
+            CHUNKS as usize
+        );
+
+        // Deleting the file invalidates its cached metadata.
+        file_storage.delete_file(&key).unwrap();
+        assert!(file_storage.get_metadata(&key).unwrap().is_none());
+        assert_eq!(metadata_reads(), 2);
     }
 
     #[test]
-    fn file_trie_stored_chunks_count_works() {
-        let storage = StorageDb {
-            db: Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)),
-            _marker: Default::default(),
-        };
+    fn write_chunk_cost_does_not_grow_with_the_file() {
+        const CHUNKS: u64 = 10_000;
 
-        let chunk_ids = vec![ChunkId::new(0u64), ChunkId::new(1u64)];
-        let chunks = vec![Chunk::from([0u8; 1024]), Chunk::from([1u8; 1024])];
+        let db = Arc::new(ReadsCountingDb::new());
+        let storage = StorageDb::new(db.clone());
+        let mut file_storage =
+            RocksDbFileStorage::<LayoutV1<BlakeTwo256>, ReadsCountingDb>::new(storage);
 
-        let mut file_trie = RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+        let chunks: Vec<Chunk> = (0..CHUNKS)
+            .map(|i| i.to_le_bytes().repeat(FILE_CHUNK_SIZE as usize / 8))
+            .collect();
+        let mut file_trie =
+            InMemoryFileStorage::<LayoutV1<BlakeTwo256>>::new().new_file_data_trie();
+        for (chunk_id, chunk) in chunks.iter().enumerate() {
+            file_trie
+                .write_chunk(&ChunkId::new(chunk_id as u64), chunk)
+                .unwrap();
+        }
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            FILE_CHUNK_SIZE * CHUNKS,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+        let key = file_metadata.file_key::<BlakeTwo256>();
+        file_storage.insert_file(key, file_metadata).unwrap();
 
-        file_trie.write_chunk(&chunk_ids[0], &chunks[0]).unwrap();
-        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 1);
-        assert!(file_trie.get_chunk(&chunk_ids[0]).is_ok());
+        let iterations_before = db.iterations.load(std::sync::atomic::Ordering::SeqCst);
+        let mut max_trie_node_reads = 0;
+        for (chunk_id, chunk) in chunks.iter().enumerate() {
+            let roots_before = db.reads(Column::Roots);
+            let chunk_counts_before = db.reads(Column::ChunkCount);
+            let trie_nodes_before = db.reads(Column::Chunks);
 
-        file_trie.write_chunk(&chunk_ids[1], &chunks[1]).unwrap();
-        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 2);
-        assert!(file_trie.get_chunk(&chunk_ids[1]).is_ok());
+            file_storage
+                .write_chunk(&key, &ChunkId::new(chunk_id as u64), chunk)
+                .unwrap();
+
+            // The trie of the file is opened once from its partial root, and the chunk count is
+            // read from its counter.
+            assert_eq!(db.reads(Column::Roots) - roots_before, 1);
+            assert_eq!(db.reads(Column::ChunkCount) - chunk_counts_before, 1);
+            max_trie_node_reads =
+                max_trie_node_reads.max(db.reads(Column::Chunks) - trie_nodes_before);
+        }
+
+        // No chunk write iterates over the trie: only the nodes on the path of the chunk are
+        // read, and the depth of the trie grows logarithmically with the chunks of the file.
+        assert_eq!(
+            db.iterations.load(std::sync::atomic::Ordering::SeqCst),
+            iterations_before
+        );
+        assert!(file_storage.is_file_complete(&key).unwrap());
+        assert!(
+            max_trie_node_reads <= 16,
+            "A chunk write read {} trie nodes",
+            max_trie_node_reads
+        );
     }
 
+    /// Chunks of a file of `chunks` chunks, along with its metadata.
+    fn coalesced_file(chunks: u64) -> (Vec<Chunk>, FileMetadata) {
+        let chunks: Vec<Chunk> = (0..chunks)
+            .map(|i| vec![i as u8 + 1; FILE_CHUNK_SIZE as usize])
+            .collect();
+        let mut file_trie =
+            InMemoryFileStorage::<LayoutV1<BlakeTwo256>>::new().new_file_data_trie();
+        for (chunk_id, chunk) in chunks.iter().enumerate() {
+            file_trie
+                .write_chunk(&ChunkId::new(chunk_id as u64), chunk)
+                .unwrap();
+        }
+        let metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            FILE_CHUNK_SIZE * chunks.len() as u64,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+
+        (chunks, metadata)
+    }
+
+    /// The interval isn't used by the File Storage itself, which only flushes the chunks once
+    /// enough of them are pending or they are read.
+    const NO_PERIODIC_FLUSH: Duration = Duration::from_secs(3600);
+
     #[test]
-    fn file_trie_generate_proof_works() {
-        let storage = StorageDb {
-            db: Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)),
-            _marker: Default::default(),
+    fn coalesced_chunk_writes_are_flushed_before_being_read() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+        let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage)
+            .with_write_coalescing(WriteCoalescing {
+                flush_interval: NO_PERIODIC_FLUSH,
+                max_pending_chunks: 100,
+            });
+        let stored_count = |file_storage: &RocksDbFileStorage<_, _>, key: &H256| {
+            file_storage
+                .storage
+                .read(Column::ChunkCount.into(), key.as_ref())
+                .unwrap()
+                .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
         };
 
-        let chunk_ids = vec![ChunkId::new(0u64), ChunkId::new(1u64), ChunkId::new(2u64)];
-        let chunk_ids_set: HashSet<ChunkId> = chunk_ids.iter().cloned().collect();
-        let chunks = vec![
-            Chunk::from([0u8; 1024]),
-            Chunk::from([1u8; 1024]),
-            Chunk::from([2u8; 1024]),
-        ];
+        let (chunks, metadata) = coalesced_file(4);
+        let key = metadata.file_key::<BlakeTwo256>();
+        file_storage.insert_file(key, metadata).unwrap();
+
+        for chunk_id in 0..2 {
+            assert_eq!(
+                file_storage
+                    .write_chunk(&key, &ChunkId::new(chunk_id), &chunks[chunk_id as usize])
+                    .unwrap(),
+                FileStorageWriteOutcome::FileIncomplete {
+                    stored: chunk_id + 1,
+                    total: 4
+                }
+            );
+        }
+        // The chunks are only in memory until they are read.
+        assert_eq!(stored_count(&file_storage, &key), Some(0));
+        assert_eq!(file_storage.stored_chunks_count(&key).unwrap(), 2);
+        assert_eq!(stored_count(&file_storage, &key), Some(2));
 
-        let mut file_trie = RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+        file_storage
+            .write_chunk(&key, &ChunkId::new(2), &chunks[2])
+            .unwrap();
+        assert_eq!(
+            file_storage.get_chunk(&key, &ChunkId::new(2)).unwrap(),
+            chunks[2]
+        );
+        assert!(!file_storage.is_file_complete(&key).unwrap());
+        assert!(matches!(
+            file_storage.write_chunk(&key, &ChunkId::new(2), &chunks[2]),
+            Err(FileStorageError::FileChunkAlreadyExists)
+        ));
 
-        file_trie.write_chunk(&chunk_ids[0], &chunks[0]).unwrap();
-        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 1);
-        assert!(file_trie.get_chunk(&chunk_ids[0]).is_ok());
+        // Complete files are flushed right away.
+        assert_eq!(
+            file_storage
+                .write_chunk(&key, &ChunkId::new(3), &chunks[3])
+                .unwrap(),
+            FileStorageWriteOutcome::FileComplete
+        );
+        assert_eq!(stored_count(&file_storage, &key), Some(4));
+        assert!(file_storage.is_file_complete(&key).unwrap());
+        file_storage
+            .generate_proof(&key, &HashSet::from([ChunkId::new(3)]))
+            .unwrap();
 
-        file_trie.write_chunk(&chunk_ids[1], &chunks[1]).unwrap();
-        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 2);
-        assert!(file_trie.get_chunk(&chunk_ids[1]).is_ok());
+        // Deleting the file leaves nothing pending to be flushed for it.
+        file_storage.delete_file(&key).unwrap();
+        file_storage.flush().unwrap();
+        assert_eq!(stored_count(&file_storage, &key), None);
+    }
 
-        file_trie.write_chunk(&chunk_ids[2], &chunks[2]).unwrap();
-        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 3);
-        assert!(file_trie.get_chunk(&chunk_ids[2]).is_ok());
+    #[test]
+    fn coalesced_chunk_writes_not_flushed_are_lost_in_a_crash() {
+        let db = Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS));
+        let storage = || StorageDb::new(db.clone());
+        let coalescing = WriteCoalescing {
+            flush_interval: NO_PERIODIC_FLUSH,
+            max_pending_chunks: 2,
+        };
 
-        let file_proof = file_trie.generate_proof(&chunk_ids_set).unwrap();
+        let (chunks, metadata) = coalesced_file(5);
+        let key = metadata.file_key::<BlakeTwo256>();
+        let mut file_storage =
+            RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage())
+                .with_write_coalescing(coalescing);
+        file_storage.insert_file(key, metadata).unwrap();
+        for chunk_id in 0..3 {
+            file_storage
+                .write_chunk(&key, &ChunkId::new(chunk_id), &chunks[chunk_id as usize])
+                .unwrap();
+        }
 
+        // Crash without flushing the last chunk, which is all that is lost.
+        std::mem::forget(file_storage);
+        let file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage())
+            .with_write_coalescing(coalescing);
+        assert_eq!(file_storage.stored_chunks_count(&key).unwrap(), 2);
         assert_eq!(
-            file_proof.fingerprint.as_ref(),
-            file_trie.get_root().as_ref()
+            file_storage.present_chunk_ids(&key).unwrap(),
+            vec![ChunkId::new(0), ChunkId::new(1)]
         );
+
+        // The lost chunk has to be uploaded again, and the pending writes are flushed when dropped.
+        file_storage
+            .write_chunk(&key, &ChunkId::new(2), &chunks[2])
+            .unwrap();
+        drop(file_storage);
+        let file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage())
+            .with_write_coalescing(coalescing);
+        assert_eq!(file_storage.stored_chunks_count(&key).unwrap(), 3);
+
+        for chunk_id in 3..5 {
+            file_storage
+                .write_chunk(&key, &ChunkId::new(chunk_id), &chunks[chunk_id as usize])
+                .unwrap();
+        }
+        assert!(file_storage.is_file_complete(&key).unwrap());
     }
 
     #[test]
-    fn file_trie_delete_works() {
-        let storage = StorageDb {
-            db: Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)),
-            _marker: Default::default(),
-        };
+    fn coalesced_chunk_writes_only_lock_their_file() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+        let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage)
+            .with_write_coalescing(WriteCoalescing {
+                flush_interval: NO_PERIODIC_FLUSH,
+                max_pending_chunks: 100,
+            });
+
+        let (chunks, metadata) = coalesced_file(2);
+        let key = metadata.file_key::<BlakeTwo256>();
+        file_storage.insert_file(key, metadata).unwrap();
+        let (other_chunks, other_metadata) = coalesced_file(3);
+        let other_key = other_metadata.file_key::<BlakeTwo256>();
+        file_storage.insert_file(other_key, other_metadata).unwrap();
+
+        file_storage
+            .write_chunk(&key, &ChunkId::new(0), &chunks[0])
+            .unwrap();
 
-        let chunk_ids = vec![ChunkId::new(0u64), ChunkId::new(1u64), ChunkId::new(2u64)];
+        // A chunk of another file is written while the first file is being written.
+        let pending_file = file_storage.coalescer.as_ref().unwrap().files()[&key].clone();
+        let pending_file_guard = pending_file.lock().unwrap();
+        assert_eq!(
+            file_storage
+                .write_chunk(&other_key, &ChunkId::new(0), &other_chunks[0])
+                .unwrap(),
+            FileStorageWriteOutcome::FileIncomplete {
+                stored: 1,
+                total: 3
+            }
+        );
+        drop(pending_file_guard);
 
-        let chunks = vec![
-            Chunk::from([0u8; 1024]),
-            Chunk::from([1u8; 1024]),
-            Chunk::from([2u8; 1024]),
-        ];
+        assert_eq!(file_storage.stored_chunks_count(&key).unwrap(), 1);
+        assert_eq!(file_storage.stored_chunks_count(&other_key).unwrap(), 1);
+    }
 
+    /// Writes the nodes of a file trie with a single chunk, without tracking its root.
+    fn write_orphaned_nodes(storage: StorageDb<LayoutV1<BlakeTwo256>, InMemory>) -> usize {
+        let nodes_before = storage.db.iter(Column::Chunks.into()).count();
         let mut file_trie = RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+        file_trie
+            .write_chunk(
+                &ChunkId::new(0),
+                &Chunk::from([9u8; FILE_CHUNK_SIZE as usize]),
+            )
+            .unwrap();
 
-        file_trie.write_chunk(&chunk_ids[0], &chunks[0]).unwrap();
-        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 1);
-        assert!(file_trie.get_chunk(&chunk_ids[0]).is_ok());
+        file_trie.storage.db.iter(Column::Chunks.into()).count() - nodes_before
+    }
 
-        file_trie.write_chunk(&chunk_ids[1], &chunks[1]).unwrap();
-        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 2);
-        assert!(file_trie.get_chunk(&chunk_ids[1]).is_ok());
+    #[test]
+    fn check_and_repair_deletes_orphaned_trie_nodes() {
+        let db = Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS));
+        let storage = || StorageDb::new(db.clone());
 
-        file_trie.write_chunk(&chunk_ids[2], &chunks[2]).unwrap();
-        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 3);
-        assert!(file_trie.get_chunk(&chunk_ids[2]).is_ok());
+        let (chunks, metadata) = coalesced_file(3);
+        let key = metadata.file_key::<BlakeTwo256>();
+        let mut file_storage =
+            RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage());
+        file_storage.insert_file(key, metadata).unwrap();
+        for chunk_id in 0..2 {
+            file_storage
+                .write_chunk(&key, &ChunkId::new(chunk_id), &chunks[chunk_id as usize])
+                .unwrap();
+        }
 
-        file_trie.delete().unwrap();
-        assert!(file_trie.get_chunk(&chunk_ids[0]).is_err());
-        assert!(file_trie.get_chunk(&chunk_ids[1]).is_err());
-        assert!(file_trie.get_chunk(&chunk_ids[2]).is_err());
+        let orphaned_nodes = write_orphaned_nodes(storage());
+        assert!(orphaned_nodes > 0);
+        assert_eq!(
+            file_storage.check_and_repair().unwrap(),
+            RepairReport {
+                orphaned_nodes_deleted: orphaned_nodes as u64,
+                roots_validated: 1,
+            }
+        );
+        assert_eq!(
+            file_storage.check_and_repair().unwrap(),
+            RepairReport {
+                orphaned_nodes_deleted: 0,
+                roots_validated: 1,
+            }
+        );
 
-        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 0);
+        // The marks of the nodes kept are deleted once the repair is done.
+        assert_eq!(db.iter(Column::RepairMarks.into()).count(), 0);
+
+        // The nodes of the partial file are all kept.
+        assert_eq!(
+            file_storage.present_chunk_ids(&key).unwrap(),
+            vec![ChunkId::new(0), ChunkId::new(1)]
+        );
+        file_storage
+            .write_chunk(&key, &ChunkId::new(2), &chunks[2])
+            .unwrap();
+        assert!(file_storage.is_file_complete(&key).unwrap());
     }
 
     #[test]
-    fn file_storage_write_chunk_works() {
-        let chunks = vec![
-            Chunk::from([5u8; FILE_CHUNK_SIZE as usize]),
-            Chunk::from([6u8; FILE_CHUNK_SIZE as usize]),
-            Chunk::from([7u8; FILE_CHUNK_SIZE as usize]),
-        ];
-
-        let storage = StorageDb {
-            db: Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)),
-            _marker: Default::default(),
+    fn storage_not_shut_down_cleanly_is_detected_when_opened() {
+        let db = Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS));
+        let storage = || StorageDb::new(db.clone());
+        let open = || {
+            RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage())
+                .with_crash_recovery_flag()
+        };
+        let crash_recovery_flag = || {
+            db.get(Column::CrashRecovery.into(), CRASH_RECOVERY_FLAG)
+                .unwrap()
         };
 
-        let chunk_ids: Vec<ChunkId> = chunks
-            .iter()
-            .enumerate()
-            .map(|(id, _)| ChunkId::new(id as u64))
-            .collect();
+        let mut file_storage = open();
+        assert!(file_storage.was_shut_down_cleanly());
+        assert!(crash_recovery_flag().is_some());
+
+        // Other instances on the same database leave the flag untouched.
+        let mut other_file_storage =
+            RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage());
+        other_file_storage.shut_down().unwrap();
+        drop(other_file_storage);
+        assert!(crash_recovery_flag().is_some());
+
+        file_storage.shut_down().unwrap();
+        assert!(crash_recovery_flag().is_none());
+        drop(file_storage);
+        assert!(open().was_shut_down_cleanly());
+
+        // The last instance was dropped without being shut down, e.g. because the node crashed.
+        let orphaned_nodes = write_orphaned_nodes(storage());
+        let mut file_storage = open();
+        assert!(!file_storage.was_shut_down_cleanly());
+        assert_eq!(
+            file_storage
+                .check_and_repair()
+                .unwrap()
+                .orphaned_nodes_deleted,
+            orphaned_nodes as u64
+        );
 
-        // Create a file trie to get the expected fingerprint
-        let mut file_trie =
-            RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
+        file_storage.shut_down().unwrap();
+        drop(file_storage);
+        assert!(open().was_shut_down_cleanly());
+    }
 
-        for (chunk_id, chunk) in chunk_ids.iter().zip(chunks.iter()) {
-            file_trie.write_chunk(chunk_id, chunk).unwrap();
-        }
+    #[test]
+    fn incomplete_files_older_than_works() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
 
-        let file_metadata = FileMetadata::new(
+        // A complete file, whose chunks are written to its trie before inserting it.
+        let chunk = Chunk::from([1u8; FILE_CHUNK_SIZE as usize]);
+        let mut complete_file_trie =
+            RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
+        complete_file_trie
+            .write_chunk(&ChunkId::new(0), &chunk)
+            .unwrap();
+        let complete_metadata = FileMetadata::new(
             <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
             [1u8; 32].to_vec(),
-            "location".to_string().into_bytes(),
-            FILE_CHUNK_SIZE * chunks.len() as u64,
-            file_trie.get_root().as_ref().into(),
+            "complete".to_string().into_bytes(),
+            FILE_CHUNK_SIZE,
+            complete_file_trie.get_root().as_ref().into(),
         )
         .unwrap();
+        let complete_key = complete_metadata.file_key::<BlakeTwo256>();
 
-        let key = file_metadata.file_key::<BlakeTwo256>();
-        let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+        let incomplete_metadata = |location: &str| {
+            FileMetadata::new(
+                <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+                [1u8; 32].to_vec(),
+                location.to_string().into_bytes(),
+                FILE_CHUNK_SIZE * 2,
+                Fingerprint::from([9u8; 32]),
+            )
+            .unwrap()
+        };
+        let old_metadata = incomplete_metadata("old");
+        let old_key = old_metadata.file_key::<BlakeTwo256>();
+        let recent_metadata = incomplete_metadata("recent");
+        let recent_key = recent_metadata.file_key::<BlakeTwo256>();
+        let unknown_age_metadata = incomplete_metadata("unknown_age");
+        let unknown_age_key = unknown_age_metadata.file_key::<BlakeTwo256>();
 
-        // Insert file metadata first
-        file_storage.insert_file(key, file_metadata).unwrap();
+        let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+        file_storage
+            .insert_file_with_data(complete_key, complete_metadata, complete_file_trie)
+            .unwrap();
+        file_storage.insert_file(old_key, old_metadata).unwrap();
+        file_storage
+            .insert_file(recent_key, recent_metadata)
+            .unwrap();
+        file_storage
+            .insert_file(unknown_age_key, unknown_age_metadata)
+            .unwrap();
 
-        // Write chunks one by one and verify
-        for (chunk_id, chunk) in chunk_ids.iter().zip(chunks.iter()) {
-            let result = file_storage.write_chunk(&key, chunk_id, chunk);
-            assert!(result.is_ok());
-            assert!(file_storage.get_chunk(&key, chunk_id).is_ok());
+        // Backdate the files, and drop the creation time of the file inserted before it was recorded.
+        let mut transaction = DBTransaction::new();
+        for (key, created_at) in [(complete_key, 100u64), (old_key, 100), (recent_key, 1_000)] {
+            transaction.put(
+                Column::CreatedAt.into(),
+                key.as_ref(),
+                &created_at.to_le_bytes(),
+            );
         }
+        transaction.delete(Column::CreatedAt.into(), unknown_age_key.as_ref());
+        file_storage.storage.write(transaction).unwrap();
 
-        // Verify final state
-        assert!(file_storage.get_metadata(&key).is_ok());
-        assert!(file_storage.get_chunk(&key, &chunk_ids[0]).is_ok());
-        assert!(file_storage.get_chunk(&key, &chunk_ids[1]).is_ok());
-        assert!(file_storage.get_chunk(&key, &chunk_ids[2]).is_ok());
+        assert_eq!(
+            file_storage.incomplete_files_older_than(500).unwrap(),
+            vec![old_key]
+        );
+        let mut all_old_files = file_storage.incomplete_files_older_than(1_001).unwrap();
+        all_old_files.sort();
+        let mut expected = vec![old_key, recent_key];
+        expected.sort();
+        assert_eq!(all_old_files, expected);
+        assert!(file_storage
+            .incomplete_files_older_than(100)
+            .unwrap()
+            .is_empty());
+
+        // Files of unknown age are still listed among all the incomplete files.
+        let mut incomplete_files = file_storage.incomplete_files().unwrap();
+        incomplete_files.sort_by_key(|incomplete_file| incomplete_file.file_key);
+        let mut expected = vec![old_key, recent_key, unknown_age_key];
+        expected.sort();
+        assert_eq!(
+            incomplete_files
+                .iter()
+                .map(|incomplete_file| incomplete_file.file_key)
+                .collect::<Vec<_>>(),
+            expected
+        );
+        let age_of = |key| {
+            incomplete_files
+                .iter()
+                .find(|incomplete_file| incomplete_file.file_key == key)
+                .unwrap()
+                .age
+        };
+        let now = unix_timestamp_now();
+        assert!(age_of(old_key).unwrap() >= Duration::from_secs(now - 100));
+        assert!(age_of(recent_key).unwrap() < age_of(old_key).unwrap());
+        assert_eq!(age_of(unknown_age_key), None);
+
+        // Complete files have no partial file age, whatever their age.
+        assert_eq!(file_storage.partial_file_age(&complete_key).unwrap(), None);
+        assert!(file_storage.partial_file_age(&old_key).unwrap() >= age_of(old_key));
+        assert_eq!(
+            file_storage.partial_file_age(&unknown_age_key).unwrap(),
+            None
+        );
+        assert!(matches!(
+            file_storage.partial_file_age(&H256::from([7u8; 32])),
+            Err(FileStorageError::FileDoesNotExist)
+        ));
+
+        // Deleting a file also deletes its creation time.
+        file_storage.delete_file(&old_key).unwrap();
+        assert!(file_storage
+            .storage
+            .read(Column::CreatedAt.into(), old_key.as_ref())
+            .unwrap()
+            .is_none());
     }
 
     #[test]
-    fn file_storage_insert_file_works() {
-        let storage = StorageDb {
-            db: Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)),
-            _marker: Default::default(),
-        };
-
+    fn verify_and_repair_file_rebuilds_a_stale_roots_column() {
         let chunks = vec![
-            Chunk::from([5u8; 32]),
-            Chunk::from([6u8; 32]),
-            Chunk::from([7u8; 32]),
+            Chunk::from([5u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([6u8; FILE_CHUNK_SIZE as usize]),
         ];
+        let chunk_ids: Vec<ChunkId> = (0..chunks.len() as u64).map(ChunkId::new).collect();
 
-        let chunk_ids: Vec<ChunkId> = chunks
-            .iter()
-            .enumerate()
-            .map(|(id, _)| ChunkId::new(id as u64))
-            .collect();
-
-        let mut file_trie =
-            RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
-
-        file_trie.write_chunk(&chunk_ids[0], &chunks[0]).unwrap();
-        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 1);
-        assert!(file_trie.get_chunk(&chunk_ids[0]).is_ok());
-
-        file_trie.write_chunk(&chunk_ids[1], &chunks[1]).unwrap();
-        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 2);
-        assert!(file_trie.get_chunk(&chunk_ids[1]).is_ok());
-
-        file_trie.write_chunk(&chunk_ids[2], &chunks[2]).unwrap();
-        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 3);
-        assert!(file_trie.get_chunk(&chunk_ids[2]).is_ok());
+        // The fingerprint is computed apart, so that the nodes of the full trie are only in the
+        // File Storage once its chunks are written to it.
+        let mut full_trie = RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(
+            StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS))),
+        );
+        for (chunk_id, chunk) in chunk_ids.iter().zip(chunks.iter()) {
+            full_trie.write_chunk(chunk_id, chunk).unwrap();
+        }
+        let fingerprint = *full_trie.get_root();
 
         let file_metadata = FileMetadata::new(
             <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
             [1u8; 32].to_vec(),
             "location".to_string().into_bytes(),
-            32u64 * chunks.len() as u64,
-            file_trie.get_root().as_ref().into(),
+            FILE_CHUNK_SIZE * chunks.len() as u64,
+            fingerprint.as_ref().into(),
         )
         .unwrap();
-
         let key = file_metadata.file_key::<BlakeTwo256>();
-        let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+        let mut file_storage =
+            RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
+        file_storage.insert_file(key, file_metadata).unwrap();
+
         file_storage
-            .insert_file_with_data(key, file_metadata, file_trie)
+            .write_chunk(&key, &chunk_ids[0], &chunks[0])
             .unwrap();
+        let stale_root = storage
+            .read(Column::Roots.into(), fingerprint.as_ref())
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            file_storage.write_chunk(&key, &chunk_ids[1], &chunks[1]),
+            Ok(FileStorageWriteOutcome::FileComplete)
+        ));
+
+        // Restore a backup of the derived data taken before the file was complete.
+        let restore = |root: &[u8], chunk_count: u64| {
+            let mut transaction = DBTransaction::new();
+            transaction.put(Column::Roots.into(), fingerprint.as_ref(), root);
+            transaction.put(
+                Column::ChunkCount.into(),
+                key.as_ref(),
+                &chunk_count.to_le_bytes(),
+            );
+            storage.write(transaction).unwrap();
+        };
+        restore(&stale_root, 1);
+        assert!(!file_storage.is_file_complete(&key).unwrap());
 
-        assert!(file_storage.get_metadata(&key).is_ok());
-        assert!(file_storage.get_chunk(&key, &chunk_ids[0]).is_ok());
-        assert!(file_storage.get_chunk(&key, &chunk_ids[1]).is_ok());
-        assert!(file_storage.get_chunk(&key, &chunk_ids[2]).is_ok());
+        assert_eq!(
+            file_storage.verify_and_repair_file(&key).unwrap(),
+            FileVerification {
+                complete: true,
+                repaired: true,
+            }
+        );
+        assert!(file_storage.is_file_complete(&key).unwrap());
+        assert_eq!(file_storage.stored_chunks_count(&key).unwrap(), 2);
+        // Once repaired, there is nothing left to repair.
+        assert_eq!(
+            file_storage.verify_and_repair_file(&key).unwrap(),
+            FileVerification {
+                complete: true,
+                repaired: false,
+            }
+        );
+
+        // A root whose trie is not stored is replaced by the fingerprint, whose trie is.
+        restore(H256::repeat_byte(9).as_ref(), 5);
+        assert_eq!(
+            file_storage.verify_and_repair_file(&key).unwrap(),
+            FileVerification {
+                complete: true,
+                repaired: true,
+            }
+        );
+        assert_eq!(file_storage.stored_chunks_count(&key).unwrap(), 2);
     }
 
     #[test]
-    fn file_storage_delete_file_works() {
-        let storage = StorageDb {
-            db: Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)),
-            _marker: Default::default(),
-        };
+    fn file_storage_stats_works() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+        let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+        assert_eq!(file_storage.stats().unwrap(), FileStorageStats::default());
 
-        let chunks = vec![
-            Chunk::from([5u8; 32]),
-            Chunk::from([6u8; 32]),
-            Chunk::from([7u8; 32]),
-        ];
+        let metadata = |location: &str, size: u64, fingerprint: [u8; 32]| {
+            FileMetadata::new(
+                <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+                [1u8; 32].to_vec(),
+                location.to_string().into_bytes(),
+                size,
+                Fingerprint::from(fingerprint),
+            )
+            .unwrap()
+        };
+        let small_metadata = metadata("small", FILE_CHUNK_SIZE, [8u8; 32]);
+        let small_key = small_metadata.file_key::<BlakeTwo256>();
+        file_storage.insert_file(small_key, small_metadata).unwrap();
+        let big_metadata = metadata("big", FILE_CHUNK_SIZE * 3, [9u8; 32]);
+        let big_key = big_metadata.file_key::<BlakeTwo256>();
+        file_storage.insert_file(big_key, big_metadata).unwrap();
 
-        let chunk_ids: Vec<ChunkId> = chunks
-            .iter()
-            .enumerate()
-            .map(|(id, _)| ChunkId::new(id as u64))
-            .collect();
+        assert_eq!(
+            file_storage.stats().unwrap(),
+            FileStorageStats {
+                files: 2,
+                total_size: FILE_CHUNK_SIZE * 4,
+            }
+        );
+    }
 
-        let mut file_trie =
-            RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
-        file_trie.write_chunk(&chunk_ids[0], &chunks[0]).unwrap();
-        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 1);
-        assert!(file_trie.get_chunk(&chunk_ids[0]).is_ok());
+    #[test]
+    fn keys_after_returns_the_keys_greater_than_the_start_key() {
+        let db = kvdb_memorydb::create(NUMBER_OF_COLUMNS);
+        let column = Column::Metadata.into();
+        let keys: Vec<Vec<u8>> = vec![
+            vec![0x00, 0x00],
+            vec![0x00, 0xff],
+            vec![0x01, 0x00],
+            vec![0x01, 0x05],
+            vec![0x01, 0x05, 0x00],
+            vec![0x7f, 0xff],
+            vec![0xff, 0x00],
+            vec![0xff, 0xff],
+        ];
+        let mut transaction = DBTransaction::new();
+        for key in &keys {
+            transaction.put(column, key, &[]);
+        }
+        db.write(transaction).unwrap();
 
-        file_trie.write_chunk(&chunk_ids[1], &chunks[1]).unwrap();
-        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 2);
-        assert!(file_trie.get_chunk(&chunk_ids[1]).is_ok());
+        // From the start.
+        assert_eq!(keys_after(&db, column, None, 3).unwrap(), keys[..3]);
+        // After a stored key, with a last byte that cannot be incremented.
+        assert_eq!(
+            keys_after(&db, column, Some(&[0x00, 0xff]), 3).unwrap(),
+            keys[2..5]
+        );
+        // Keys extending the start key come right after it.
+        assert_eq!(
+            keys_after(&db, column, Some(&[0x01, 0x05]), 2).unwrap(),
+            keys[4..6]
+        );
+        // After a key that is not stored.
+        assert_eq!(
+            keys_after(&db, column, Some(&[0x01, 0x02]), 10).unwrap(),
+            keys[3..]
+        );
+        // After the last key.
+        assert!(keys_after(&db, column, Some(&[0xff, 0xff]), 10)
+            .unwrap()
+            .is_empty());
+        assert!(keys_after(&db, column, None, 0).unwrap().is_empty());
+    }
 
-        file_trie.write_chunk(&chunk_ids[2], &chunks[2]).unwrap();
-        assert_eq!(stored_chunks_count(&file_trie).unwrap(), 3);
-        assert!(file_trie.get_chunk(&chunk_ids[2]).is_ok());
+    #[test]
+    fn keys_after_does_not_iterate_over_the_keys_before_the_start_key() {
+        const KEYS: u32 = 1_000;
+        const LIMIT: usize = 10;
+
+        let db = ReadsCountingDb::new();
+        let column = Column::Metadata.into();
+        let mut keys: Vec<Vec<u8>> = (0..KEYS)
+            .map(|i| {
+                <BlakeTwo256 as Hasher>::hash(&i.to_le_bytes())
+                    .as_ref()
+                    .to_vec()
+            })
+            .collect();
+        keys.sort();
+        let mut transaction = DBTransaction::new();
+        for key in &keys {
+            transaction.put(column, key, &[]);
+        }
+        db.write(transaction).unwrap();
 
-        let file_metadata = FileMetadata::new(
-            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
-            [1u8; 32].to_vec(),
-            "location".to_string().into_bytes(),
-            32u64 * chunks.len() as u64,
-            file_trie.get_root().as_ref().into(),
-        )
-        .unwrap();
+        let start = KEYS as usize / 2;
+        let iterated_keys = || db.iterated_keys.load(std::sync::atomic::Ordering::SeqCst);
+        let iterated_keys_before = iterated_keys();
+        assert_eq!(
+            keys_after(&db, column, Some(&keys[start]), LIMIT).unwrap(),
+            keys[start + 1..][..LIMIT]
+        );
+        // Besides the page, at most two keys are read for each prefix of the start key probed.
+        assert!(iterated_keys() - iterated_keys_before <= LIMIT + 2 * 8);
+    }
 
-        let key = file_metadata.file_key::<BlakeTwo256>();
+    #[test]
+    fn get_files_after_key_paginates_through_all_files() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
         let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
-        file_storage
-            .insert_file_with_data(key, file_metadata, file_trie)
-            .unwrap();
-        assert!(file_storage.get_metadata(&key).is_ok());
 
-        assert!(file_storage.delete_file(&key).is_ok());
+        for i in 0..10u8 {
+            let metadata = FileMetadata::new(
+                <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+                [1u8; 32].to_vec(),
+                format!("location_{}", i).into_bytes(),
+                FILE_CHUNK_SIZE,
+                Fingerprint::from([i + 1; 32]),
+            )
+            .unwrap();
+            let file_key = metadata.file_key::<BlakeTwo256>();
+            file_storage.insert_file(file_key, metadata).unwrap();
+        }
 
-        // Should get a None option here when trying to get File Metadata.
-        assert!(file_storage
-            .get_metadata(&key)
-            .is_ok_and(|metadata| metadata.is_none()));
-        assert!(file_storage.get_chunk(&key, &chunk_ids[0]).is_err());
-        assert!(file_storage.get_chunk(&key, &chunk_ids[1]).is_err());
-        assert!(file_storage.get_chunk(&key, &chunk_ids[2]).is_err());
+        let mut paginated = Vec::new();
+        let mut start_key = None;
+        loop {
+            let page = file_storage.get_files_after_key(start_key, 3).unwrap();
+            assert!(page.len() <= 3);
+            if page.is_empty() {
+                break;
+            }
+            start_key = page.last().copied();
+            paginated.extend(page);
+        }
+
+        assert_eq!(paginated, file_storage.file_keys().unwrap());
+        assert_eq!(paginated.len(), 10);
     }
 
     #[test]
-    fn file_storage_generate_proof_works() {
-        let chunks = vec![
-            Chunk::from([5u8; 32]),
-            Chunk::from([6u8; 32]),
-            Chunk::from([7u8; 32]),
-        ];
+    fn bucket_file_keys_lists_the_files_of_the_bucket_in_order() {
+        let storage = StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+        let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
 
-        let storage = StorageDb {
-            db: Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)),
-            _marker: Default::default(),
-        };
+        let bucket = [1u8; 32];
+        // Differs from `bucket` in its last byte only.
+        let mut other_bucket = [1u8; 32];
+        other_bucket[31] = 2;
 
-        let user_storage = StorageDb {
-            db: Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)),
-            _marker: Default::default(),
+        let mut insert = |bucket_id: [u8; 32], i: u8| {
+            let metadata = FileMetadata::new(
+                <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+                bucket_id.to_vec(),
+                format!("location_{}", i).into_bytes(),
+                FILE_CHUNK_SIZE,
+                Fingerprint::from([i + 1; 32]),
+            )
+            .unwrap();
+            let file_key = metadata.file_key::<BlakeTwo256>();
+            file_storage.insert_file(file_key, metadata).unwrap();
+            file_key
         };
+        let mut bucket_files: Vec<_> = (0..5).map(|i| insert(bucket, i)).collect();
+        let other_bucket_files: Vec<_> = (5..8).map(|i| insert(other_bucket, i)).collect();
+        bucket_files.sort();
 
-        let mut user_file_trie =
-            RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(user_storage.clone());
-
-        for (id, chunk) in chunks.iter().enumerate() {
-            user_file_trie
-                .write_chunk(&ChunkId::new(id as u64), chunk)
-                .unwrap();
-        }
+        assert_eq!(
+            file_storage.bucket_file_keys(&bucket).unwrap(),
+            bucket_files
+        );
+        assert_eq!(
+            file_storage.bucket_file_keys(&other_bucket).unwrap().len(),
+            other_bucket_files.len()
+        );
+        assert!(file_storage
+            .bucket_file_keys(&[3u8; 32])
+            .unwrap()
+            .is_empty());
 
-        let fingerprint = Fingerprint::from(user_file_trie.get_root().as_ref());
+        // Deleted files are no longer listed.
+        file_storage.delete_file(&bucket_files[0]).unwrap();
+        assert_eq!(
+            file_storage.bucket_file_keys(&bucket).unwrap(),
+            bucket_files[1..]
+        );
+    }
 
-        let chunk_ids: Vec<ChunkId> = chunks
-            .iter()
-            .enumerate()
-            .map(|(id, _)| ChunkId::new(id as u64))
-            .collect();
+    #[test]
+    fn approximate_file_count_is_close_to_the_stored_files() {
+        const FILES: u64 = 100;
+
+        let path = std::env::temp_dir().join(format!(
+            "sh-file-manager-approximate-file-count-{}",
+            std::process::id()
+        ));
+        let storage =
+            RocksDbFileStorage::<LayoutV1<BlakeTwo256>, kvdb_rocksdb::Database>::rocksdb_storage(
+                path.to_string_lossy().to_string(),
+            )
+            .unwrap();
+        let mut file_storage = RocksDbFileStorage::new(storage);
 
-        let chunk_ids_set: HashSet<ChunkId> = chunk_ids.iter().cloned().collect();
+        for i in 0..FILES {
+            let metadata = FileMetadata::new(
+                <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+                [1u8; 32].to_vec(),
+                format!("location_{}", i).into_bytes(),
+                FILE_CHUNK_SIZE,
+                Fingerprint::from([i as u8 + 1; 32]),
+            )
+            .unwrap();
+            let file_key = metadata.file_key::<BlakeTwo256>();
+            file_storage.insert_file(file_key, metadata).unwrap();
+        }
 
-        let file_metadata = FileMetadata::new(
-            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
-            [1u8; 32].to_vec(),
-            "location".to_string().into_bytes(),
-            1024u64 * chunks.len() as u64,
-            fingerprint,
-        )
-        .unwrap();
+        // The count is an estimate, so it only has to be in the ballpark of the stored files.
+        let count = file_storage.get_file_count().unwrap();
+        assert!(
+            (FILES / 2..=FILES * 2).contains(&count),
+            "Estimated {} files, but {} are stored",
+            count,
+            FILES
+        );
 
-        let key = file_metadata.file_key::<BlakeTwo256>();
+        drop(file_storage);
+        let _ = std::fs::remove_dir_all(path);
+    }
 
-        let mut file_storage =
-            RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
-        file_storage.insert_file(key, file_metadata).unwrap();
-        assert!(file_storage.get_metadata(&key).is_ok());
+    #[test]
+    fn migrate_to_rocksdb_copies_all_files() {
+        let mut in_memory = InMemoryFileStorage::<LayoutV1<BlakeTwo256>>::new();
+
+        // Files of 1 to 3 chunks, of which only the first chunk of the last one is written.
+        let mut files = Vec::new();
+        for chunks_count in 1..=3u8 {
+            let chunks: Vec<Chunk> = (0..chunks_count)
+                .map(|i| Chunk::from([chunks_count * 10 + i; FILE_CHUNK_SIZE as usize]))
+                .collect();
 
-        file_storage
-            .write_chunk(&key, &chunk_ids[0], &chunks[0])
-            .unwrap();
-        assert!(file_storage.get_chunk(&key, &chunk_ids[0]).is_ok());
+            let mut file_trie = in_memory.new_file_data_trie();
+            for (id, chunk) in chunks.iter().enumerate() {
+                file_trie
+                    .write_chunk(&ChunkId::new(id as u64), chunk)
+                    .unwrap();
+            }
 
-        file_storage
-            .write_chunk(&key, &chunk_ids[1], &chunks[1])
+            let metadata = FileMetadata::new(
+                <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+                [1u8; 32].to_vec(),
+                format!("location_{}", chunks_count).into_bytes(),
+                FILE_CHUNK_SIZE * chunks_count as u64,
+                file_trie.get_root().as_ref().into(),
+            )
             .unwrap();
-        assert!(file_storage.get_chunk(&key, &chunk_ids[1]).is_ok());
-
-        file_storage
-            .write_chunk(&key, &chunk_ids[2], &chunks[2])
+            let file_key = metadata.file_key::<BlakeTwo256>();
+            in_memory.insert_file(file_key, metadata).unwrap();
+
+            let written = if chunks_count == 3 { 1 } else { chunks.len() };
+            for (id, chunk) in chunks.iter().take(written).enumerate() {
+                in_memory
+                    .write_chunk(&file_key, &ChunkId::new(id as u64), chunk)
+                    .unwrap();
+            }
+            files.push((file_key, chunks, written));
+        }
+        in_memory.seal_file(&files[0].0).unwrap();
+        in_memory
+            .add_to_exclude_list(H256::repeat_byte(7), ExcludeType::User)
             .unwrap();
-        assert!(file_storage.get_chunk(&key, &chunk_ids[2]).is_ok());
 
-        let file_proof = file_storage.generate_proof(&key, &chunk_ids_set).unwrap();
-        let proven_leaves = file_proof.proven::<LayoutV1<BlakeTwo256>>().unwrap();
-        for (id, leaf) in proven_leaves.iter().enumerate() {
-            assert_eq!(chunk_ids[id], leaf.key);
-            assert_eq!(chunks[id], leaf.data);
+        let path = std::env::temp_dir().join(format!(
+            "sh-file-manager-migrate-to-rocksdb-{}",
+            std::process::id()
+        ));
+        let file_storage =
+            migrate_to_rocksdb(&in_memory, path.to_string_lossy().to_string()).unwrap();
+
+        for (file_key, chunks, written) in &files {
+            assert_eq!(
+                file_storage.get_metadata(file_key).unwrap(),
+                in_memory.get_metadata(file_key).unwrap()
+            );
+            assert_eq!(
+                file_storage.stored_chunks_count(file_key).unwrap(),
+                *written as u64
+            );
+            assert_eq!(
+                file_storage.is_file_complete(file_key).unwrap(),
+                *written == chunks.len()
+            );
+            for (id, chunk) in chunks.iter().take(*written).enumerate() {
+                assert_eq!(
+                    &file_storage
+                        .get_chunk(file_key, &ChunkId::new(id as u64))
+                        .unwrap(),
+                    chunk
+                );
+            }
         }
+        assert!(matches!(
+            file_storage.write_chunk(&files[0].0, &ChunkId::new(0), &files[0].1[0]),
+            Err(FileStorageError::FileSealed)
+        ));
+        assert!(!file_storage
+            .is_allowed(&H256::repeat_byte(7), ExcludeType::User)
+            .unwrap());
+
+        // Migrating twice to the same database would overwrite the files.
+        drop(file_storage);
+        assert!(matches!(
+            migrate_to_rocksdb(&in_memory, path.to_string_lossy().to_string()),
+            Err(Error::FileStorage(FileStorageError::FileAlreadyExists))
+        ));
+
+        let _ = std::fs::remove_dir_all(path);
     }
 
     #[test]
-    fn same_chunk_id_with_different_data_produces_different_roots() {
-        use sp_trie::MemoryDB;
+    fn migrated_files_with_the_same_fingerprint_keep_their_trie_when_one_is_deleted() {
+        let mut in_memory = InMemoryFileStorage::<LayoutV1<BlakeTwo256>>::new();
 
-        let mut memdb = MemoryDB::<BlakeTwo256>::default();
-        let mut root1 = Default::default();
-        let mut root2 = Default::default();
-        let chunks1 = vec![0u8; 32];
-        let chunks2 = vec![1u8; 32];
+        let chunks: Vec<Chunk> = (0..2u8)
+            .map(|i| Chunk::from([i + 1; FILE_CHUNK_SIZE as usize]))
+            .collect();
+        let mut file_trie = in_memory.new_file_data_trie();
+        for (id, chunk) in chunks.iter().enumerate() {
+            file_trie
+                .write_chunk(&ChunkId::new(id as u64), chunk)
+                .unwrap();
+        }
+        let fingerprint: Fingerprint = file_trie.get_root().as_ref().into();
 
-        {
-            let mut t1 =
-                TrieDBMutBuilder::<LayoutV1<BlakeTwo256>>::new(&mut memdb, &mut root1).build();
-            t1.insert(&[0u8; 32], &chunks1).unwrap();
+        // Two files with the same content, stored at different locations.
+        let mut file_keys = Vec::new();
+        for location in ["location_1", "location_2"] {
+            let metadata = FileMetadata::new(
+                <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+                [1u8; 32].to_vec(),
+                location.as_bytes().to_vec(),
+                FILE_CHUNK_SIZE * chunks.len() as u64,
+                fingerprint,
+            )
+            .unwrap();
+            let file_key = metadata.file_key::<BlakeTwo256>();
+            in_memory.insert_file(file_key, metadata).unwrap();
+            for (id, chunk) in chunks.iter().enumerate() {
+                in_memory
+                    .write_chunk(&file_key, &ChunkId::new(id as u64), chunk)
+                    .unwrap();
+            }
+            file_keys.push(file_key);
         }
-        {
-            let mut t2 =
-                TrieDBMutBuilder::<LayoutV1<BlakeTwo256>>::new(&mut memdb, &mut root2).build();
-            t2.insert(&[0u8; 32], &chunks2).unwrap();
+
+        let path = std::env::temp_dir().join(format!(
+            "sh-file-manager-migrate-shared-trie-to-rocksdb-{}",
+            std::process::id()
+        ));
+        let mut file_storage =
+            migrate_to_rocksdb(&in_memory, path.to_string_lossy().to_string()).unwrap();
+
+        file_storage.delete_file(&file_keys[0]).unwrap();
+
+        // The other file still reads its chunks from the shared trie.
+        let metadata = file_storage.get_metadata(&file_keys[1]).unwrap().unwrap();
+        assert_eq!(
+            file_storage
+                .get_file_trie(&metadata)
+                .unwrap()
+                .get_root()
+                .as_ref(),
+            fingerprint.as_ref()
+        );
+        assert!(file_storage.is_file_complete(&file_keys[1]).unwrap());
+        for (id, chunk) in chunks.iter().enumerate() {
+            assert_eq!(
+                &file_storage
+                    .get_chunk(&file_keys[1], &ChunkId::new(id as u64))
+                    .unwrap(),
+                chunk
+            );
         }
 
-        assert_ne!(root1, root2)
+        // Deleting the last file removes the trie.
+        file_storage.delete_file(&file_keys[1]).unwrap();
+        assert_eq!(
+            file_storage
+                .shared_trie_copies(&H256::from_slice(fingerprint.as_ref()))
+                .unwrap(),
+            0
+        );
+
+        drop(file_storage);
+        let _ = std::fs::remove_dir_all(path);
     }
 
     #[test]
-    fn delete_files_with_prefix_works() {
-        let storage = StorageDb {
-            db: Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)),
-            _marker: Default::default(),
-        };
+    fn concurrent_writes_to_disjoint_files_are_not_serialised() {
+        const FILES: u8 = 8;
+        const CHUNKS: u8 = 16;
 
-        fn create_file_and_metadata(
-            storage: StorageDb<LayoutV1<BlakeTwo256>, InMemory>,
-            chunks: Vec<Chunk>,
-            bucket_id: [u8; 32],
-            location: &str,
-        ) -> (
-            FileMetadata,
-            H256,
-            Vec<ChunkId>,
-            RocksDbFileDataTrie<LayoutV1<BlakeTwo256>, InMemory>,
-        ) {
-            // Convert chunks into chunk IDs for referencing each chunk in the trie.
-            let chunk_ids: Vec<ChunkId> = chunks
-                .iter()
-                .enumerate()
-                .map(|(id, _)| ChunkId::new(id as u64))
-                .collect();
+        let storage = || StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+        let chunk = |file: u8, chunk: u8| -> Chunk {
+            vec![file * CHUNKS + chunk; FILE_CHUNK_SIZE as usize]
+        };
 
-            // Create a new file trie for storing file chunks.
+        let mut file_storage =
+            RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage());
+        let mut file_keys = Vec::new();
+        for file in 0..FILES {
             let mut file_trie =
-                RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage.clone());
-
-            // Write each chunk into the trie.
-            for (i, chunk) in chunks.iter().enumerate() {
-                file_trie.write_chunk(&chunk_ids[i], chunk).unwrap();
+                RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage());
+            for chunk_id in 0..CHUNKS {
+                file_trie
+                    .write_chunk(&ChunkId::new(chunk_id as u64), &chunk(file, chunk_id))
+                    .unwrap();
             }
-
-            // Create metadata for the file, including bucket ID, location, and owner.
             let file_metadata = FileMetadata::new(
                 <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
-                bucket_id.to_vec(),
-                location.to_string().into_bytes(),
-                32u64 * chunks.len() as u64,
+                [1u8; 32].to_vec(),
+                format!("location{}", file).into_bytes(),
+                FILE_CHUNK_SIZE * CHUNKS as u64,
                 file_trie.get_root().as_ref().into(),
             )
             .unwrap();
+            let file_key = file_metadata.file_key::<BlakeTwo256>();
+            file_storage.insert_file(file_key, file_metadata).unwrap();
+            file_keys.push(file_key);
+        }
+        let file_storage = &file_storage;
 
-            let key = file_metadata.file_key::<BlakeTwo256>();
+        // Hold the lock of the first file while the others are written: if writes to distinct
+        // files were serialised, none of them could complete. Only files sharing its lock shard
+        // have to wait for it.
+        let (blocked_key, other_keys) = file_keys.split_first().unwrap();
+        let independent_files = other_keys
+            .iter()
+            .filter(|key| {
+                FileKeyLocks::shard(key.as_ref()) != FileKeyLocks::shard(blocked_key.as_ref())
+            })
+            .count();
+        assert!(independent_files > 0);
+        let blocked_file_lock = file_storage.locks.lock(blocked_key.as_ref());
+
+        let (complete_tx, complete_rx) = std::sync::mpsc::channel();
+        std::thread::scope(|scope| {
+            for (file, file_key) in other_keys.iter().enumerate() {
+                let complete_tx = complete_tx.clone();
+                scope.spawn(move || {
+                    for chunk_id in 0..CHUNKS {
+                        file_storage
+                            .write_chunk(
+                                file_key,
+                                &ChunkId::new(chunk_id as u64),
+                                &chunk(file as u8 + 1, chunk_id),
+                            )
+                            .unwrap();
+                    }
+                    complete_tx.send(*file_key).unwrap();
+                });
+            }
 
-            // Return the metadata, key, chunk IDs, and the trie.
-            (file_metadata, key, chunk_ids, file_trie)
-        }
+            let completed = (0..independent_files)
+                .filter(|_| {
+                    complete_rx
+                        .recv_timeout(std::time::Duration::from_secs(10))
+                        .is_ok()
+                })
+                .count();
+            drop(blocked_file_lock);
+            assert_eq!(completed, independent_files);
+        });
+
+        // Concurrent writes to the same file are serialised, so none of them is lost.
+        std::thread::scope(|scope| {
+            for chunk_ids in (0..CHUNKS).collect::<Vec<_>>().chunks(4) {
+                scope.spawn(move || {
+                    for chunk_id in chunk_ids {
+                        file_storage
+                            .write_chunk(
+                                blocked_key,
+                                &ChunkId::new(*chunk_id as u64),
+                                &chunk(0, *chunk_id),
+                            )
+                            .unwrap();
+                    }
+                });
+            }
+        });
 
-        // Step 2: Define test data for three files.
-        // These are the chunks of data that will be stored in the file trie.
-        let chunks_1 = vec![
-            Chunk::from([5u8; 32]),
-            Chunk::from([6u8; 32]),
-            Chunk::from([7u8; 32]),
-        ];
-        let chunks_2 = vec![
-            Chunk::from([8u8; 32]),
-            Chunk::from([9u8; 32]),
-            Chunk::from([10u8; 32]),
-        ];
-        let chunks_3 = vec![
-            Chunk::from([11u8; 32]),
-            Chunk::from([12u8; 32]),
-            Chunk::from([13u8; 32]),
-        ];
+        for file_key in &file_keys {
+            assert_eq!(
+                file_storage.stored_chunks_count(file_key).unwrap(),
+                CHUNKS as u64
+            );
+            assert!(file_storage.is_file_complete(file_key).unwrap());
+        }
+    }
 
-        // Step 3: Create file metadata, keys, and file tries for each of the three files.
-        let (file_metadata_1, key_1, chunk_ids_1, file_trie_1) =
-            create_file_and_metadata(storage.clone(), chunks_1, [1u8; 32], "location");
-        let (file_metadata_2, key_2, chunk_ids_2, file_trie_2) =
-            create_file_and_metadata(storage.clone(), chunks_2, [2u8; 32], "location_2");
-        let (file_metadata_3, key_3, chunk_ids_3, file_trie_3) =
-            create_file_and_metadata(storage.clone(), chunks_3, [3u8; 32], "location_3");
+    #[test]
+    fn concurrent_uploads_of_distinct_files_write_all_their_chunks() {
+        const FILES: usize = 8;
+        const CHUNKS: u8 = 16;
 
-        // Step 4: Create a file storage and insert all three files into the storage.
-        let mut file_storage = RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage);
+        let storage = || StorageDb::new(Arc::new(kvdb_memorydb::create(NUMBER_OF_COLUMNS)));
+        let chunk = |file: usize, chunk: u8| -> Chunk {
+            vec![file as u8 * CHUNKS + chunk; FILE_CHUNK_SIZE as usize]
+        };
 
-        file_storage
-            .insert_file_with_data(key_1, file_metadata_1.clone(), file_trie_1)
-            .unwrap();
-        file_storage
-            .insert_file_with_data(key_2, file_metadata_2.clone(), file_trie_2)
-            .unwrap();
-        file_storage
-            .insert_file_with_data(key_3, file_metadata_3.clone(), file_trie_3)
+        let mut file_storage =
+            RocksDbFileStorage::<LayoutV1<BlakeTwo256>, InMemory>::new(storage());
+        let mut file_keys = Vec::new();
+        for file in 0..FILES {
+            let mut file_trie =
+                RocksDbFileDataTrie::<LayoutV1<BlakeTwo256>, InMemory>::new(storage());
+            for chunk_id in 0..CHUNKS {
+                file_trie
+                    .write_chunk(&ChunkId::new(chunk_id as u64), &chunk(file, chunk_id))
+                    .unwrap();
+            }
+            let file_metadata = FileMetadata::new(
+                <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+                [1u8; 32].to_vec(),
+                format!("location{}", file).into_bytes(),
+                FILE_CHUNK_SIZE * CHUNKS as u64,
+                file_trie.get_root().as_ref().into(),
+            )
             .unwrap();
+            let file_key = file_metadata.file_key::<BlakeTwo256>();
+            file_storage.insert_file(file_key, file_metadata).unwrap();
+            file_keys.push(file_key);
+        }
 
-        // Step 5: Verify that all files and their chunks are inserted properly.
-        assert!(file_storage.get_metadata(&key_1).is_ok());
-        assert!(file_storage.get_metadata(&key_2).is_ok());
-        assert!(file_storage.get_metadata(&key_3).is_ok());
+        let file_storage = &file_storage;
+
+        // The uploads write their chunks in lockstep, so that the writes of every file are
+        // interleaved with those of the others, as they are when uploads run concurrently.
+        let barrier = std::sync::Barrier::new(FILES);
+        std::thread::scope(|scope| {
+            for (file, file_key) in file_keys.iter().enumerate() {
+                let barrier = &barrier;
+                scope.spawn(move || {
+                    for chunk_id in 0..CHUNKS {
+                        barrier.wait();
+                        file_storage
+                            .write_chunk(
+                                file_key,
+                                &ChunkId::new(chunk_id as u64),
+                                &chunk(file, chunk_id),
+                            )
+                            .unwrap();
+                    }
+                    file_storage.seal_file(file_key).unwrap();
+                });
+            }
+        });
 
-        // Step 6: Delete files with the prefix [1u8; 32], which corresponds to bucket ID 1.
-        file_storage.delete_files_with_prefix(&[1u8; 32]).unwrap();
+        // No chunk is lost or written to the wrong file.
+        for (file, file_key) in file_keys.iter().enumerate() {
+            assert_eq!(
+                file_storage.stored_chunks_count(file_key).unwrap(),
+                CHUNKS as u64
+            );
+            assert!(file_storage.is_file_complete(file_key).unwrap());
+            for chunk_id in 0..CHUNKS {
+                assert_eq!(
+                    file_storage
+                        .get_chunk(file_key, &ChunkId::new(chunk_id as u64))
+                        .unwrap(),
+                    chunk(file, chunk_id)
+                );
+            }
+        }
+    }
 
-        // Step 7: Assert that files with bucket_id 1 are deleted.
-        // We expect no metadata or chunks for the file with key_1 after deletion.
+    #[test]
+    fn inserting_a_stored_file_if_absent_keeps_its_chunks() {
+        let db = Arc::new(ReadsCountingDb::new());
+        let metadata_reads = || db.reads(Column::Metadata);
+        let storage = StorageDb::new(db.clone());
+        let mut file_storage =
+            RocksDbFileStorage::<LayoutV1<BlakeTwo256>, ReadsCountingDb>::new(storage);
+
+        let chunks: Vec<Chunk> = (0..2)
+            .map(|i| vec![i as u8 + 1; FILE_CHUNK_SIZE as usize])
+            .collect();
+        let mut file_trie =
+            InMemoryFileStorage::<LayoutV1<BlakeTwo256>>::new().new_file_data_trie();
+        for (chunk_id, chunk) in chunks.iter().enumerate() {
+            file_trie
+                .write_chunk(&ChunkId::new(chunk_id as u64), chunk)
+                .unwrap();
+        }
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            "location".to_string().into_bytes(),
+            FILE_CHUNK_SIZE * chunks.len() as u64,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+        let key = file_metadata.file_key::<BlakeTwo256>();
+
+        assert!(!file_storage.file_exists(&key).unwrap());
         assert!(file_storage
-            .get_metadata(&key_1)
-            .is_ok_and(|metadata| metadata.is_none()));
-        assert!(file_storage.get_chunk(&key_1, &chunk_ids_1[0]).is_err());
+            .insert_file_if_absent(key, file_metadata.clone())
+            .unwrap());
+        file_storage
+            .write_chunk(&key, &ChunkId::new(0), &chunks[0])
+            .unwrap();
 
-        // Step 8: Assert that files with other bucket_ids (bucket_id 2 and 3) are not deleted.
-        // Files with key_2 and key_3 should remain accessible.
-        assert!(file_storage.get_metadata(&key_2).is_ok());
-        assert!(file_storage.get_metadata(&key_3).is_ok());
-        assert!(file_storage.get_chunk(&key_2, &chunk_ids_2[0]).is_ok());
-        assert!(file_storage.get_chunk(&key_3, &chunk_ids_3[0]).is_ok());
+        // Handling the same storage request again leaves the file being uploaded untouched,
+        // where inserting it again would have reset its chunk count.
+        assert!(!file_storage
+            .insert_file_if_absent(key, file_metadata)
+            .unwrap());
+        assert_eq!(file_storage.stored_chunks_count(&key).unwrap(), 1);
+
+        // The metadata cached when writing the chunk is enough to tell that the file exists.
+        let reads_before = metadata_reads();
+        assert!(file_storage.file_exists(&key).unwrap());
+        assert_eq!(metadata_reads(), reads_before);
+
+        file_storage.delete_file(&key).unwrap();
+        assert!(!file_storage.file_exists(&key).unwrap());
+        assert_eq!(metadata_reads(), reads_before + 1);
     }
 }
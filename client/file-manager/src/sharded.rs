@@ -0,0 +1,649 @@
+//! A File Storage spreading its files across several [`RocksDbFileStorage`]s, e.g. one per disk.
+//!
+//! Each file is stored, along with its trie, in the shard picked by [`shard_index`] from its file
+//! key, so a file is always read from the shard it was written to, as long as the shards are
+//! opened in the same order. The number and order of the shards can't be changed once files are
+//! stored: each shard keeps its [`ShardManifest`], and the storage refuses to open shards given
+//! in another order, or with shards added or removed.
+//!
+//! The exclude lists are not tied to a file, and are kept in the first shard.
+
+use std::{collections::HashSet, time::Duration};
+
+use codec::{Decode, Encode};
+use kvdb::KeyValueDB;
+use log::error;
+use shc_common::types::{Chunk, ChunkId, FileKeyProof, FileMetadata, HashT, HasherOutT, H_LENGTH};
+use sp_trie::TrieLayout;
+
+use crate::{
+    rocksdb::{RepairReport, RocksDbFileDataTrie, RocksDbFileStorage},
+    traits::{
+        ExcludeType, FileStorage, FileStorageError, FileStorageStats, FileStorageWriteOutcome,
+        FileVerification, IncompleteFile,
+    },
+    LOG_TARGET,
+};
+
+/// Index of the shard storing the file with `file_key`, out of `shards` shards.
+///
+/// File keys are hashes, so their first bytes are already uniformly distributed.
+pub fn shard_index(file_key: &[u8], shards: usize) -> usize {
+    let mut bytes = [0u8; 8];
+    let len = file_key.len().min(bytes.len());
+    bytes[..len].copy_from_slice(&file_key[..len]);
+    (u64::from_le_bytes(bytes) % shards.max(1) as u64) as usize
+}
+
+/// The position of a shard in a [`ShardedFileStorage`], persisted in the shard the first time it
+/// is opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct ShardManifest {
+    /// Index of the shard.
+    pub index: u32,
+    /// Number of shards of the File Storage.
+    pub shards: u32,
+}
+
+/// File Storage sharding its files across several [`RocksDbFileStorage`]s by file key.
+///
+/// With a single shard, it behaves as that shard.
+pub struct ShardedFileStorage<T, DB>
+where
+    T: TrieLayout + 'static,
+    DB: KeyValueDB,
+    HasherOutT<T>: TryFrom<[u8; H_LENGTH]>,
+{
+    shards: Vec<RocksDbFileStorage<T, DB>>,
+}
+
+impl<T, DB> ShardedFileStorage<T, DB>
+where
+    T: TrieLayout + Send + Sync + 'static,
+    DB: KeyValueDB + 'static,
+    HasherOutT<T>: TryFrom<[u8; H_LENGTH]>,
+{
+    /// Creates a File Storage over `shards`, which have to be given in the same order every time.
+    ///
+    /// Fails with [`FileStorageError::ShardManifestMismatch`] if any of the shards was opened at
+    /// another position, or with another number of shards, before. The shards opened for the
+    /// first time get their [`ShardManifest`] persisted.
+    ///
+    /// Panics if `shards` is empty.
+    pub fn new(shards: Vec<RocksDbFileStorage<T, DB>>) -> Result<Self, FileStorageError> {
+        assert!(
+            !shards.is_empty(),
+            "A sharded File Storage needs at least one shard"
+        );
+
+        let manifest = |index: usize| ShardManifest {
+            index: index as u32,
+            shards: shards.len() as u32,
+        };
+        // Check every shard before persisting any manifest, so that a mismatch leaves them as
+        // they were.
+        let mut new_shards = Vec::new();
+        for (index, shard) in shards.iter().enumerate() {
+            match shard.shard_manifest()? {
+                Some(found) if found != manifest(index) => {
+                    error!(target: LOG_TARGET, "Shard {} out of {} of the File Storage was opened as shard {} out of {} before. The data paths have to be given in the same order every time, and can't be added or removed once files are stored.", index, shards.len(), found.index, found.shards);
+                    return Err(FileStorageError::ShardManifestMismatch);
+                }
+                Some(_) => {}
+                None => new_shards.push(index),
+            }
+        }
+        for index in new_shards {
+            shards[index].write_shard_manifest(manifest(index))?;
+        }
+
+        Ok(Self { shards })
+    }
+
+    pub fn shards(&self) -> &[RocksDbFileStorage<T, DB>] {
+        &self.shards
+    }
+
+    /// Writes the chunks kept in memory by every shard to its database, see
+    /// [`RocksDbFileStorage::with_write_coalescing`].
+    pub fn flush(&self) -> Result<(), FileStorageError> {
+        for shard in &self.shards {
+            shard.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Whether every shard was shut down cleanly the last time it was opened, see
+    /// [`RocksDbFileStorage::was_shut_down_cleanly`].
+    pub fn was_shut_down_cleanly(&self) -> bool {
+        self.shards
+            .iter()
+            .all(RocksDbFileStorage::was_shut_down_cleanly)
+    }
+
+    /// Deletes the orphaned trie nodes of the shards not shut down cleanly, see
+    /// [`RocksDbFileStorage::check_and_repair`].
+    pub fn check_and_repair(&mut self) -> Result<RepairReport, FileStorageError> {
+        let mut report = RepairReport::default();
+        for shard in &mut self.shards {
+            if shard.was_shut_down_cleanly() {
+                continue;
+            }
+            let shard_report = shard.check_and_repair()?;
+            report.orphaned_nodes_deleted += shard_report.orphaned_nodes_deleted;
+            report.roots_validated += shard_report.roots_validated;
+        }
+        Ok(report)
+    }
+
+    /// The shard storing the file with `file_key`.
+    pub fn shard(&self, file_key: &HasherOutT<T>) -> &RocksDbFileStorage<T, DB> {
+        &self.shards[shard_index(file_key.as_ref(), self.shards.len())]
+    }
+
+    fn shard_mut(&mut self, file_key: &HasherOutT<T>) -> &mut RocksDbFileStorage<T, DB> {
+        let index = shard_index(file_key.as_ref(), self.shards.len());
+        &mut self.shards[index]
+    }
+
+    /// The shard holding the exclude lists.
+    fn first_shard(&self) -> &RocksDbFileStorage<T, DB> {
+        &self.shards[0]
+    }
+
+    /// Runs `f` on every shard, collecting the file keys they return in ascending order.
+    fn sorted_file_keys(
+        &self,
+        f: impl Fn(&RocksDbFileStorage<T, DB>) -> Result<Vec<HasherOutT<T>>, FileStorageError>,
+    ) -> Result<Vec<HasherOutT<T>>, FileStorageError> {
+        let mut file_keys = Vec::new();
+        for shard in &self.shards {
+            file_keys.extend(f(shard)?);
+        }
+        file_keys.sort();
+        Ok(file_keys)
+    }
+
+    /// Copies the complete file with `src_key` to the file with `new_key` and `new_metadata`, in
+    /// another shard, chunk by chunk.
+    fn copy_file_across_shards(
+        &mut self,
+        src_key: &HasherOutT<T>,
+        new_key: HasherOutT<T>,
+        new_metadata: FileMetadata,
+    ) -> Result<HasherOutT<T>, FileStorageError> {
+        let src_shard = self.shard(src_key);
+        let src_metadata = src_shard
+            .get_metadata(src_key)?
+            .ok_or(FileStorageError::FileDoesNotExist)?;
+        if !src_shard.is_file_complete(src_key)? {
+            return Err(FileStorageError::IncompleteFile);
+        }
+        if src_metadata.fingerprint() != new_metadata.fingerprint()
+            || src_metadata.file_size() != new_metadata.file_size()
+        {
+            return Err(FileStorageError::FingerprintAndStoredFileMismatch);
+        }
+        if self.shard(&new_key).get_metadata(&new_key)?.is_some() {
+            return Err(FileStorageError::FileAlreadyExists);
+        }
+
+        self.shard_mut(&new_key)
+            .insert_file(new_key, new_metadata)?;
+
+        let src_shard = self.shard(src_key);
+        let new_shard = self.shard(&new_key);
+        let copied = src_shard
+            .present_chunk_ids(src_key)?
+            .into_iter()
+            .try_for_each(|chunk_id| {
+                let chunk = src_shard.get_chunk(src_key, &chunk_id)?;
+                new_shard
+                    .write_chunk(&new_key, &chunk_id, &chunk)
+                    .map(|_| ())
+                    .map_err(|e| {
+                        error!(target: LOG_TARGET, "Failed to copy chunk {:?} of file [{:?}] to another shard: {:?}", chunk_id, src_key, e);
+                        FileStorageError::FailedToWriteToStorage
+                    })
+            });
+
+        if let Err(e) = copied {
+            // Do not leave an incomplete copy behind.
+            self.shard_mut(&new_key).delete_file(&new_key)?;
+            return Err(e);
+        }
+
+        Ok(new_key)
+    }
+}
+
+impl<T, DB> FileStorage<T> for ShardedFileStorage<T, DB>
+where
+    T: TrieLayout + Send + Sync + 'static,
+    DB: KeyValueDB + 'static,
+    HasherOutT<T>: TryFrom<[u8; H_LENGTH]>,
+{
+    type FileDataTrie = RocksDbFileDataTrie<T, DB>;
+
+    /// Creates a new file trie in the first shard. It is copied to the shard of its file when
+    /// inserted, unless it is in that shard already, leaving its nodes in the first shard until
+    /// it is repaired.
+    ///
+    /// Prefer [`FileStorage::new_file_data_trie_for`] when the file key is known beforehand.
+    fn new_file_data_trie(&self) -> Self::FileDataTrie {
+        self.first_shard().new_file_data_trie()
+    }
+
+    /// Creates a new file trie in the shard of the file with `key`, so that it is not moved when
+    /// inserted.
+    fn new_file_data_trie_for(&self, key: &HasherOutT<T>) -> Self::FileDataTrie {
+        self.shard(key).new_file_data_trie()
+    }
+
+    fn generate_proof(
+        &self,
+        key: &HasherOutT<T>,
+        chunk_ids: &HashSet<ChunkId>,
+    ) -> Result<FileKeyProof, FileStorageError> {
+        self.shard(key).generate_proof(key, chunk_ids)
+    }
+
+    fn delete_file(&mut self, key: &HasherOutT<T>) -> Result<(), FileStorageError> {
+        self.shard_mut(key).delete_file(key)
+    }
+
+    fn delete_files_with_prefix(&mut self, prefix: &[u8; 32]) -> Result<(), FileStorageError> {
+        for shard in &mut self.shards {
+            shard.delete_files_with_prefix(prefix)?;
+        }
+        Ok(())
+    }
+
+    fn bucket_file_keys(
+        &self,
+        bucket_id: &[u8; 32],
+    ) -> Result<Vec<HasherOutT<T>>, FileStorageError> {
+        self.sorted_file_keys(|shard| shard.bucket_file_keys(bucket_id))
+    }
+
+    fn get_metadata(&self, key: &HasherOutT<T>) -> Result<Option<FileMetadata>, FileStorageError> {
+        self.shard(key).get_metadata(key)
+    }
+
+    fn file_exists(&self, key: &HasherOutT<T>) -> Result<bool, FileStorageError> {
+        self.shard(key).file_exists(key)
+    }
+
+    fn is_file_complete(&self, key: &HasherOutT<T>) -> Result<bool, FileStorageError> {
+        self.shard(key).is_file_complete(key)
+    }
+
+    fn insert_file(
+        &mut self,
+        key: HasherOutT<T>,
+        metadata: FileMetadata,
+    ) -> Result<(), FileStorageError> {
+        self.shard_mut(&key).insert_file(key, metadata)
+    }
+
+    fn insert_file_with_data(
+        &mut self,
+        key: HasherOutT<T>,
+        metadata: FileMetadata,
+        file_data: Self::FileDataTrie,
+    ) -> Result<(), FileStorageError> {
+        let shard = self.shard_mut(&key);
+        let file_data = shard.adopt_file_data(file_data)?;
+        shard.insert_file_with_data(key, metadata, file_data)
+    }
+
+    /// Replaces the file with `old_key` atomically if both files are in the same shard.
+    ///
+    /// Otherwise, the new file is inserted before the old one is deleted, so that a file is
+    /// never missing, but both of them exist in between.
+    fn atomic_delete_and_insert(
+        &mut self,
+        old_key: HasherOutT<T>,
+        new_key: HasherOutT<T>,
+        new_metadata: FileMetadata,
+        new_data: Self::FileDataTrie,
+    ) -> Result<(), FileStorageError> {
+        let shards = self.shards.len();
+        if shard_index(old_key.as_ref(), shards) == shard_index(new_key.as_ref(), shards) {
+            let shard = self.shard_mut(&new_key);
+            let new_data = shard.adopt_file_data(new_data)?;
+            return shard.atomic_delete_and_insert(old_key, new_key, new_metadata, new_data);
+        }
+
+        if self.shard(&old_key).get_metadata(&old_key)?.is_none() {
+            return Err(FileStorageError::FileDoesNotExist);
+        }
+        if self.shard(&new_key).get_metadata(&new_key)?.is_some() {
+            return Err(FileStorageError::FileAlreadyExists);
+        }
+
+        self.insert_file_with_data(new_key, new_metadata, new_data)?;
+        self.shard_mut(&old_key).delete_file(&old_key)
+    }
+
+    /// Copies the file within its shard, sharing its trie, if the copy belongs to the same
+    /// shard. Otherwise, its chunks are copied to the shard of the copy.
+    fn copy_file(
+        &mut self,
+        src_key: &HasherOutT<T>,
+        new_metadata: FileMetadata,
+    ) -> Result<HasherOutT<T>, FileStorageError> {
+        let new_key = new_metadata.file_key::<HashT<T>>();
+        let shards = self.shards.len();
+        if shard_index(src_key.as_ref(), shards) == shard_index(new_key.as_ref(), shards) {
+            return self.shard_mut(src_key).copy_file(src_key, new_metadata);
+        }
+
+        self.copy_file_across_shards(src_key, new_key, new_metadata)
+    }
+
+    fn incomplete_files_older_than(
+        &self,
+        created_before: u64,
+    ) -> Result<Vec<HasherOutT<T>>, FileStorageError> {
+        self.sorted_file_keys(|shard| shard.incomplete_files_older_than(created_before))
+    }
+
+    fn incomplete_files(&self) -> Result<Vec<IncompleteFile<HasherOutT<T>>>, FileStorageError> {
+        let mut incomplete_files = Vec::new();
+        for shard in &self.shards {
+            incomplete_files.extend(shard.incomplete_files()?);
+        }
+        incomplete_files.sort_by_key(|incomplete_file| incomplete_file.file_key);
+        Ok(incomplete_files)
+    }
+
+    fn partial_file_age(&self, key: &HasherOutT<T>) -> Result<Option<Duration>, FileStorageError> {
+        self.shard(key).partial_file_age(key)
+    }
+
+    fn seal_file(&self, key: &HasherOutT<T>) -> Result<(), FileStorageError> {
+        self.shard(key).seal_file(key)
+    }
+
+    fn file_keys(&self) -> Result<Vec<HasherOutT<T>>, FileStorageError> {
+        self.sorted_file_keys(|shard| shard.file_keys())
+    }
+
+    /// Merges the first `limit` files after `start_key` of every shard, keeping the first
+    /// `limit` of them.
+    fn get_files_after_key(
+        &self,
+        start_key: Option<HasherOutT<T>>,
+        limit: usize,
+    ) -> Result<Vec<HasherOutT<T>>, FileStorageError> {
+        let mut file_keys =
+            self.sorted_file_keys(|shard| shard.get_files_after_key(start_key, limit))?;
+        file_keys.truncate(limit);
+        Ok(file_keys)
+    }
+
+    fn verify_and_repair_file(
+        &self,
+        key: &HasherOutT<T>,
+    ) -> Result<FileVerification, FileStorageError> {
+        self.shard(key).verify_and_repair_file(key)
+    }
+
+    fn stats(&self) -> Result<FileStorageStats, FileStorageError> {
+        let mut stats = FileStorageStats::default();
+        for shard in &self.shards {
+            let shard_stats = shard.stats()?;
+            stats.files += shard_stats.files;
+            stats.total_size = stats.total_size.saturating_add(shard_stats.total_size);
+        }
+        Ok(stats)
+    }
+
+    fn stored_chunks_count(&self, key: &HasherOutT<T>) -> Result<u64, FileStorageError> {
+        self.shard(key).stored_chunks_count(key)
+    }
+
+    fn present_chunk_ids(&self, key: &HasherOutT<T>) -> Result<Vec<ChunkId>, FileStorageError> {
+        self.shard(key).present_chunk_ids(key)
+    }
+
+    fn get_chunk(
+        &self,
+        key: &HasherOutT<T>,
+        chunk_id: &ChunkId,
+    ) -> Result<Chunk, FileStorageError> {
+        self.shard(key).get_chunk(key, chunk_id)
+    }
+
+    fn write_chunk(
+        &self,
+        key: &HasherOutT<T>,
+        chunk_id: &ChunkId,
+        data: &Chunk,
+    ) -> Result<FileStorageWriteOutcome, FileStorageError> {
+        self.shard(key).write_chunk(key, chunk_id, data)
+    }
+
+    fn is_allowed(
+        &self,
+        key: &HasherOutT<T>,
+        exclude_type: ExcludeType,
+    ) -> Result<bool, FileStorageError> {
+        self.first_shard().is_allowed(key, exclude_type)
+    }
+
+    fn add_to_exclude_list(
+        &mut self,
+        key: HasherOutT<T>,
+        exclude_type: ExcludeType,
+    ) -> Result<(), FileStorageError> {
+        self.shards[0].add_to_exclude_list(key, exclude_type)
+    }
+
+    fn remove_from_exclude_list(
+        &mut self,
+        key: &HasherOutT<T>,
+        exclude_type: ExcludeType,
+    ) -> Result<(), FileStorageError> {
+        self.shards[0].remove_from_exclude_list(key, exclude_type)
+    }
+
+    fn shut_down(&mut self) -> Result<(), FileStorageError> {
+        for shard in &mut self.shards {
+            shard.shut_down()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hash_db::Hasher;
+    use shc_common::types::FILE_CHUNK_SIZE;
+    use sp_runtime::{traits::BlakeTwo256, AccountId32};
+    use sp_trie::LayoutV1;
+
+    use crate::traits::FileDataTrie;
+
+    type Layout = LayoutV1<BlakeTwo256>;
+
+    fn open_shards(
+        paths: &[&std::path::Path],
+    ) -> Result<ShardedFileStorage<Layout, kvdb_rocksdb::Database>, FileStorageError> {
+        let shards = paths
+            .iter()
+            .map(|path| {
+                let storage =
+                    RocksDbFileStorage::<Layout, kvdb_rocksdb::Database>::rocksdb_storage(
+                        path.to_string_lossy().to_string(),
+                    )
+                    .unwrap();
+                RocksDbFileStorage::new(storage)
+            })
+            .collect();
+        ShardedFileStorage::new(shards)
+    }
+
+    #[test]
+    fn files_are_stored_in_and_read_back_from_their_shard() {
+        let dirs: Vec<_> = (0..2).map(|_| tempfile::tempdir().unwrap()).collect();
+        let paths: Vec<_> = dirs.iter().map(|dir| dir.path()).collect();
+
+        let mut files = Vec::new();
+        {
+            let mut file_storage = open_shards(&paths).unwrap();
+            for i in 0..8u8 {
+                let chunk = vec![i; FILE_CHUNK_SIZE as usize];
+                let mut file_data = file_storage.new_file_data_trie();
+                file_data.write_chunk(&ChunkId::new(0), &chunk).unwrap();
+
+                let metadata = FileMetadata::new(
+                    <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+                    [1u8; 32].to_vec(),
+                    format!("location_{}", i).into_bytes(),
+                    FILE_CHUNK_SIZE,
+                    file_data.get_root().as_ref().into(),
+                )
+                .unwrap();
+                let file_key = metadata.file_key::<BlakeTwo256>();
+
+                // Half of the files are written chunk by chunk, and the other half with their
+                // data, moving their trie from the first shard to theirs.
+                if i % 2 == 0 {
+                    file_storage.insert_file(file_key, metadata).unwrap();
+                    file_storage
+                        .write_chunk(&file_key, &ChunkId::new(0), &chunk)
+                        .unwrap();
+                } else {
+                    file_storage
+                        .insert_file_with_data(file_key, metadata, file_data)
+                        .unwrap();
+                }
+                files.push((file_key, chunk));
+            }
+        }
+
+        // Reopening the shards finds every file in its own shard only.
+        let file_storage = open_shards(&paths).unwrap();
+        for (file_key, chunk) in &files {
+            let index = shard_index(file_key.as_ref(), 2);
+            let shards = file_storage.shards();
+            assert!(shards[index].get_metadata(file_key).unwrap().is_some());
+            assert!(shards[1 - index].get_metadata(file_key).unwrap().is_none());
+
+            assert!(file_storage.is_file_complete(file_key).unwrap());
+            assert_eq!(
+                &file_storage.get_chunk(file_key, &ChunkId::new(0)).unwrap(),
+                chunk
+            );
+        }
+
+        let mut file_keys: Vec<_> = files.iter().map(|(file_key, _)| *file_key).collect();
+        file_keys.sort();
+        assert_eq!(file_storage.file_keys().unwrap(), file_keys);
+        assert_eq!(
+            file_storage
+                .get_files_after_key(Some(file_keys[2]), 3)
+                .unwrap(),
+            file_keys[3..6].to_vec()
+        );
+    }
+
+    #[test]
+    fn shards_cannot_be_reordered_added_or_removed() {
+        let dirs: Vec<_> = (0..3).map(|_| tempfile::tempdir().unwrap()).collect();
+        let paths: Vec<_> = dirs.iter().map(|dir| dir.path()).collect();
+
+        drop(open_shards(&paths[..2]).unwrap());
+
+        for shards in [
+            vec![paths[1], paths[0]],
+            vec![paths[0], paths[1], paths[2]],
+            vec![paths[0]],
+        ] {
+            assert!(matches!(
+                open_shards(&shards),
+                Err(FileStorageError::ShardManifestMismatch)
+            ));
+        }
+        // The third shard was never opened successfully, so it can still be used elsewhere.
+        drop(open_shards(&paths[2..]).unwrap());
+
+        assert!(open_shards(&paths[..2]).is_ok());
+    }
+
+    #[test]
+    fn file_tries_for_a_known_file_key_are_created_in_its_shard() {
+        let dirs: Vec<_> = (0..2).map(|_| tempfile::tempdir().unwrap()).collect();
+        let paths: Vec<_> = dirs.iter().map(|dir| dir.path()).collect();
+        let file_storage = open_shards(&paths).unwrap();
+
+        for byte in 0..4u8 {
+            let file_key = <Layout as TrieLayout>::Hash::hash(&[byte]);
+            let index = shard_index(file_key.as_ref(), 2);
+            let file_data = file_storage.new_file_data_trie_for(&file_key);
+
+            // Adopting a trie of the same shard is a no-op, so nothing is copied on insertion.
+            assert!(file_storage.shards()[index].holds_file_data(&file_data));
+            assert!(!file_storage.shards()[1 - index].holds_file_data(&file_data));
+        }
+    }
+
+    #[test]
+    fn moving_a_file_trie_keeps_the_nodes_shared_with_files_of_the_first_shard() {
+        let dirs: Vec<_> = (0..2).map(|_| tempfile::tempdir().unwrap()).collect();
+        let paths: Vec<_> = dirs.iter().map(|dir| dir.path()).collect();
+        let mut file_storage = open_shards(&paths).unwrap();
+
+        // Files with the same single chunk, and so the same trie, at different locations.
+        let chunk = vec![7u8; FILE_CHUNK_SIZE as usize];
+        let file_with_data_in = |file_storage: &ShardedFileStorage<_, _>, shard: usize| {
+            (0u32..)
+                .find_map(|i| {
+                    let mut file_data = file_storage.new_file_data_trie();
+                    file_data.write_chunk(&ChunkId::new(0), &chunk).unwrap();
+                    let metadata = FileMetadata::new(
+                        <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+                        [1u8; 32].to_vec(),
+                        format!("location_{}", i).into_bytes(),
+                        FILE_CHUNK_SIZE,
+                        file_data.get_root().as_ref().into(),
+                    )
+                    .unwrap();
+                    let file_key = metadata.file_key::<BlakeTwo256>();
+                    (shard_index(file_key.as_ref(), 2) == shard)
+                        .then_some((file_key, metadata, file_data))
+                })
+                .unwrap()
+        };
+
+        let (first_key, metadata, file_data) = file_with_data_in(&file_storage, 0);
+        file_storage
+            .insert_file_with_data(first_key, metadata, file_data)
+            .unwrap();
+
+        // The trie of the second file is built in the first shard and moved to the second one.
+        let (second_key, metadata, file_data) = file_with_data_in(&file_storage, 1);
+        file_storage
+            .insert_file_with_data(second_key, metadata, file_data)
+            .unwrap();
+
+        for file_key in [first_key, second_key] {
+            assert!(file_storage.is_file_complete(&file_key).unwrap());
+            assert_eq!(
+                file_storage.get_chunk(&file_key, &ChunkId::new(0)).unwrap(),
+                chunk
+            );
+        }
+    }
+
+    #[test]
+    fn shard_index_is_within_the_shards() {
+        for byte in 0..=255u8 {
+            assert!(shard_index(&[byte; 32], 3) < 3);
+            assert_eq!(shard_index(&[byte; 32], 1), 0);
+        }
+    }
+}
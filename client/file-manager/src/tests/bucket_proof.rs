@@ -0,0 +1,185 @@
+//! Generation of [`BucketProof`]s from a File Storage, and their verification against the root
+//! of the forest of the bucket, as built by the Forest Storage.
+
+use std::collections::HashSet;
+
+use codec::{Decode, Encode};
+use sp_runtime::{traits::BlakeTwo256, AccountId32};
+use sp_trie::LayoutV1;
+
+use shc_common::types::{ChunkId, FileMetadata, HasherOutT, FILE_CHUNK_SIZE};
+use shc_forest_manager::{in_memory::InMemoryForestStorage, traits::ForestStorage};
+
+use crate::{
+    bucket_proof::{challenged_chunk, BucketProof, BucketProofError},
+    in_memory::InMemoryFileStorage,
+    traits::{FileDataTrie, FileStorage, FileStorageError},
+};
+
+type Layout = LayoutV1<BlakeTwo256>;
+
+const BUCKET: [u8; 32] = [1u8; 32];
+const OTHER_BUCKET: [u8; 32] = [2u8; 32];
+const SEED: [u8; 32] = [7u8; 32];
+
+/// Stores a file of `bucket_id` with `chunks` chunks of [`FILE_CHUNK_SIZE`] bytes, writing only
+/// its first `written` chunks.
+fn store_file(
+    file_storage: &mut InMemoryFileStorage<Layout>,
+    bucket_id: [u8; 32],
+    location: &str,
+    chunks: u64,
+    written: u64,
+) -> (HasherOutT<Layout>, FileMetadata) {
+    let mut file_data = file_storage.new_file_data_trie();
+    for chunk_id in 0..chunks {
+        let chunk = vec![chunk_id as u8 + location.len() as u8; FILE_CHUNK_SIZE as usize];
+        file_data
+            .write_chunk(&ChunkId::new(chunk_id), &chunk)
+            .unwrap();
+    }
+
+    let metadata = FileMetadata::new(
+        <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+        bucket_id.to_vec(),
+        location.as_bytes().to_vec(),
+        FILE_CHUNK_SIZE * chunks,
+        file_data.get_root().as_ref().into(),
+    )
+    .unwrap();
+    let file_key = metadata.file_key::<BlakeTwo256>();
+
+    file_storage
+        .insert_file(file_key, metadata.clone())
+        .unwrap();
+    for chunk_id in 0..written {
+        let chunk = file_data.get_chunk(&ChunkId::new(chunk_id)).unwrap();
+        file_storage
+            .write_chunk(&file_key, &ChunkId::new(chunk_id), &chunk)
+            .unwrap();
+    }
+
+    (file_key, metadata)
+}
+
+/// The root of a bucket forest holding the files with `metadata`.
+fn forest_root(metadata: &[FileMetadata]) -> HasherOutT<Layout> {
+    let mut forest = InMemoryForestStorage::<Layout>::new();
+    forest.insert_files_metadata(metadata).unwrap();
+    forest.root()
+}
+
+#[test]
+fn bucket_proof_of_three_files_verifies_against_the_bucket_forest_root() {
+    let mut file_storage = InMemoryFileStorage::<Layout>::new();
+    let bucket_files: Vec<_> = [("a", 1), ("bb", 3), ("ccc", 5)]
+        .into_iter()
+        .map(|(location, chunks)| store_file(&mut file_storage, BUCKET, location, chunks, chunks))
+        .collect();
+    let (_, other_file) = store_file(&mut file_storage, OTHER_BUCKET, "d", 2, 2);
+
+    let proof = file_storage.generate_bucket_proof(&BUCKET, &SEED).unwrap();
+    assert_eq!(proof.file_key_proofs.len(), 3);
+    // The proof is meant to be sent to the verifier, so it has to survive a round trip.
+    let proof = BucketProof::decode(&mut proof.encode().as_slice()).unwrap();
+
+    let mut file_keys: Vec<_> = bucket_files.iter().map(|(file_key, _)| *file_key).collect();
+    file_keys.sort();
+    let metadata: Vec<_> = bucket_files
+        .iter()
+        .map(|(_, metadata)| metadata.clone())
+        .collect();
+    assert_eq!(
+        proof.verify::<Layout>(&forest_root(&metadata), &SEED),
+        Ok(file_keys)
+    );
+
+    // Files missing from the proof, or from the bucket, are detected.
+    assert_eq!(
+        proof.verify::<Layout>(&forest_root(&metadata[..2]), &SEED),
+        Err(BucketProofError::ForestRootMismatch)
+    );
+    let mut with_other_file = metadata.clone();
+    with_other_file.push(other_file);
+    assert_eq!(
+        proof.verify::<Layout>(&forest_root(&with_other_file), &SEED),
+        Err(BucketProofError::ForestRootMismatch)
+    );
+
+    let mut duplicated = proof.clone();
+    duplicated
+        .file_key_proofs
+        .push(proof.file_key_proofs[0].clone());
+    assert_eq!(
+        duplicated.verify::<Layout>(&forest_root(&metadata), &SEED),
+        Err(BucketProofError::DuplicateFile)
+    );
+
+    let mut of_other_bucket = proof;
+    of_other_bucket.bucket_id = OTHER_BUCKET;
+    assert_eq!(
+        of_other_bucket.verify::<Layout>(&forest_root(&metadata), &SEED),
+        Err(BucketProofError::FileOfAnotherBucket)
+    );
+}
+
+#[test]
+fn bucket_proof_is_not_generated_with_incomplete_files() {
+    let mut file_storage = InMemoryFileStorage::<Layout>::new();
+    store_file(&mut file_storage, BUCKET, "a", 2, 2);
+    store_file(&mut file_storage, BUCKET, "bb", 3, 2);
+
+    assert!(matches!(
+        file_storage.generate_bucket_proof(&BUCKET, &SEED),
+        Err(FileStorageError::IncompleteFile)
+    ));
+
+    // An empty bucket has the root of an empty forest.
+    let proof = file_storage
+        .generate_bucket_proof(&OTHER_BUCKET, &SEED)
+        .unwrap();
+    assert_eq!(
+        proof.verify::<Layout>(&forest_root(&[]), &SEED),
+        Ok(Vec::new())
+    );
+}
+
+#[test]
+fn bucket_proof_proves_the_chunks_challenged_by_the_seed() {
+    let mut file_storage = InMemoryFileStorage::<Layout>::new();
+    let (file_key, metadata) = store_file(&mut file_storage, BUCKET, "a", 16, 16);
+    let root = forest_root(&[metadata.clone()]);
+
+    // Seeds challenging a chunk other than the last one, and another chunk than each other.
+    let last_chunk = metadata.last_chunk_id();
+    let seeds: Vec<[u8; 32]> = (0..=u8::MAX)
+        .map(|i| [i; 32])
+        .filter(|seed| challenged_chunk::<Layout>(seed, &file_key, &metadata) != last_chunk)
+        .take(2)
+        .collect();
+    let (seed, other_seed) = (seeds[0], seeds[1]);
+    assert_ne!(
+        challenged_chunk::<Layout>(&seed, &file_key, &metadata),
+        challenged_chunk::<Layout>(&other_seed, &file_key, &metadata)
+    );
+
+    let proof = file_storage.generate_bucket_proof(&BUCKET, &seed).unwrap();
+    assert_eq!(proof.verify::<Layout>(&root, &seed), Ok(vec![file_key]));
+    // A proof generated for a seed doesn't prove the chunks challenged by another one.
+    assert_eq!(
+        proof.verify::<Layout>(&root, &other_seed),
+        Err(BucketProofError::ChunkNotProven)
+    );
+
+    // Keeping only the last chunk of each file isn't enough to prove the bucket.
+    let last_chunk_only = BucketProof {
+        bucket_id: BUCKET,
+        file_key_proofs: vec![file_storage
+            .generate_proof(&file_key, &HashSet::from([last_chunk]))
+            .unwrap()],
+    };
+    assert_eq!(
+        last_chunk_only.verify::<Layout>(&root, &seed),
+        Err(BucketProofError::ChunkNotProven)
+    );
+}
@@ -0,0 +1,145 @@
+//! Round-trips of [`ChunkId::as_trie_key`], which is the key of every chunk in a file trie.
+//!
+//! Chunk IDs are compact encoded in their keys, so an encoding bug would silently store chunks
+//! at the wrong position. These tests write chunks under random IDs and check that they are
+//! read back from the same position, that the IDs decoded from the keys of the trie match, and
+//! that chunks are iterated in chunk ID order regardless of the order of their keys.
+
+use std::collections::{BTreeSet, HashSet};
+
+use proptest::prelude::*;
+use sp_runtime::traits::BlakeTwo256;
+use sp_trie::LayoutV1;
+
+use shc_common::types::{Chunk, ChunkId};
+
+use crate::{
+    in_memory::{InMemoryFileDataTrie, InMemoryFileStorage},
+    traits::{FileDataTrie, FileStorage},
+};
+
+type Layout = LayoutV1<BlakeTwo256>;
+
+/// Chunk IDs at the boundaries of the compact encoding, where its length changes.
+const EDGE_CASES: [u64; 11] = [
+    0,
+    1,
+    63,
+    64,
+    (1 << 14) - 1,
+    1 << 14,
+    (1 << 30) - 1,
+    1 << 30,
+    u64::MAX / 2,
+    u64::MAX - 1,
+    u64::MAX,
+];
+
+/// A chunk whose contents identify the chunk ID it was written under.
+fn chunk_for(id: u64) -> Chunk {
+    id.to_le_bytes().repeat(4)
+}
+
+fn new_file_data_trie() -> InMemoryFileDataTrie<Layout> {
+    InMemoryFileStorage::<Layout>::new().new_file_data_trie()
+}
+
+/// Writes a chunk under each of `ids`, and checks that each is read back from its own key and
+/// that the IDs decoded from the keys of the trie are exactly `ids`.
+fn assert_chunks_round_trip(ids: &BTreeSet<u64>) {
+    let mut file_data_trie = new_file_data_trie();
+    for id in ids {
+        file_data_trie
+            .write_chunk(&ChunkId::new(*id), &chunk_for(*id))
+            .unwrap();
+    }
+
+    for id in ids {
+        assert_eq!(
+            file_data_trie.get_chunk(&ChunkId::new(*id)).unwrap(),
+            chunk_for(*id),
+            "chunk {} read back from the wrong position",
+            id
+        );
+    }
+
+    let decoded_ids: Vec<u64> = file_data_trie
+        .chunk_ids()
+        .unwrap()
+        .iter()
+        .map(ChunkId::as_u64)
+        .collect();
+    assert_eq!(decoded_ids, ids.iter().copied().collect::<Vec<_>>());
+}
+
+#[test]
+fn edge_case_ids_round_trip_through_their_trie_key() {
+    for id in EDGE_CASES {
+        let chunk_id = ChunkId::new(id);
+        assert_eq!(
+            ChunkId::from_trie_key(&chunk_id.as_trie_key()).unwrap(),
+            chunk_id
+        );
+    }
+}
+
+#[test]
+fn edge_case_ids_are_stored_at_their_own_position() {
+    assert_chunks_round_trip(&EDGE_CASES.into_iter().collect());
+}
+
+#[test]
+fn edge_case_ids_have_distinct_trie_keys() {
+    let keys: HashSet<Vec<u8>> = EDGE_CASES
+        .iter()
+        .map(|id| ChunkId::new(*id).as_trie_key())
+        .collect();
+    assert_eq!(keys.len(), EDGE_CASES.len());
+}
+
+#[test]
+fn chunks_written_out_of_order_are_iterated_in_chunk_id_order() {
+    let mut file_data_trie = new_file_data_trie();
+    // Neither the write order nor the order of the keys in the trie is the chunk ID order.
+    let written = [u64::MAX, 64, 1, 1 << 14, 0, 63, u64::MAX / 2];
+    for id in written {
+        file_data_trie
+            .write_chunk(&ChunkId::new(id), &chunk_for(id))
+            .unwrap();
+    }
+
+    let chunks = file_data_trie
+        .chunks_in_order()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+    let mut expected_ids = written.to_vec();
+    expected_ids.sort();
+    let expected: Vec<(ChunkId, Chunk)> = expected_ids
+        .into_iter()
+        .map(|id| (ChunkId::new(id), chunk_for(id)))
+        .collect();
+    assert_eq!(chunks, expected);
+}
+
+proptest! {
+    #[test]
+    fn ids_round_trip_through_their_trie_key(id in any::<u64>()) {
+        let chunk_id = ChunkId::new(id);
+        prop_assert_eq!(ChunkId::from_trie_key(&chunk_id.as_trie_key()).unwrap(), chunk_id);
+    }
+
+    #[test]
+    fn different_ids_have_different_trie_keys(a in any::<u64>(), b in any::<u64>()) {
+        prop_assume!(a != b);
+        prop_assert_ne!(ChunkId::new(a).as_trie_key(), ChunkId::new(b).as_trie_key());
+    }
+
+    #[test]
+    fn chunks_are_stored_at_their_own_position(
+        ids in prop::collection::btree_set(any::<u64>(), 1..32)
+    ) {
+        assert_chunks_round_trip(&ids);
+    }
+}
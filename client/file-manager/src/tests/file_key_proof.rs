@@ -0,0 +1,149 @@
+//! Offline verification of [`FileKeyProof`]s generated by a File Storage.
+
+use std::collections::HashSet;
+
+use sp_runtime::{traits::BlakeTwo256, AccountId32};
+use sp_trie::LayoutV1;
+
+use shc_common::types::{ChunkId, FileMetadata, Fingerprint, ProvenFileKeyError, FILE_CHUNK_SIZE};
+
+use crate::{
+    file_key_proof::{verify_file_key_proof, FileKeyProofError},
+    in_memory::InMemoryFileStorage,
+    traits::{FileDataTrie, FileStorage},
+};
+
+type Layout = LayoutV1<BlakeTwo256>;
+
+const CHUNKS: u64 = 4;
+
+/// Stores a complete file of [`CHUNKS`] chunks, with contents derived from `seed`, returning its
+/// metadata.
+fn store_file(file_storage: &mut InMemoryFileStorage<Layout>, seed: u8) -> FileMetadata {
+    let mut file_data = file_storage.new_file_data_trie();
+    for chunk_id in 0..CHUNKS {
+        let chunk = vec![chunk_id as u8 + seed; FILE_CHUNK_SIZE as usize];
+        file_data
+            .write_chunk(&ChunkId::new(chunk_id), &chunk)
+            .unwrap();
+    }
+
+    let metadata = FileMetadata::new(
+        <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+        [1u8; 32].to_vec(),
+        format!("location_{}", seed).into_bytes(),
+        FILE_CHUNK_SIZE * CHUNKS,
+        file_data.get_root().as_ref().into(),
+    )
+    .unwrap();
+    file_storage
+        .insert_file_with_data(
+            metadata.file_key::<BlakeTwo256>(),
+            metadata.clone(),
+            file_data,
+        )
+        .unwrap();
+
+    metadata
+}
+
+#[test]
+fn file_key_proof_verifies_against_the_file_metadata() {
+    let mut file_storage = InMemoryFileStorage::<Layout>::new();
+    let metadata = store_file(&mut file_storage, 0);
+
+    let chunk_ids = HashSet::from([ChunkId::new(1), ChunkId::new(3)]);
+    let proof = file_storage
+        .generate_proof(&metadata.file_key::<BlakeTwo256>(), &chunk_ids)
+        .unwrap();
+
+    let mut proven = verify_file_key_proof::<Layout>(&proof, &metadata).unwrap();
+    proven.sort_by_key(|(chunk_id, _)| *chunk_id);
+    assert_eq!(
+        proven,
+        vec![
+            (ChunkId::new(1), vec![1u8; FILE_CHUNK_SIZE as usize]),
+            (ChunkId::new(3), vec![3u8; FILE_CHUNK_SIZE as usize]),
+        ]
+    );
+}
+
+#[test]
+fn file_key_proof_of_another_file_is_rejected() {
+    let mut file_storage = InMemoryFileStorage::<Layout>::new();
+    let metadata = store_file(&mut file_storage, 0);
+
+    let proof = file_storage
+        .generate_proof(
+            &metadata.file_key::<BlakeTwo256>(),
+            &HashSet::from([ChunkId::new(0)]),
+        )
+        .unwrap();
+
+    // Any field of the metadata differing makes it another file, not only its fingerprint.
+    let other_fingerprint = Fingerprint::from([9u8; 32]);
+    let other_owner = AccountId32::new([1u8; 32]);
+    for (owner, location, size, fingerprint) in [
+        (
+            metadata.owner().clone(),
+            metadata.location().clone(),
+            metadata.file_size(),
+            other_fingerprint,
+        ),
+        (
+            <AccountId32 as AsRef<[u8]>>::as_ref(&other_owner).to_vec(),
+            metadata.location().clone(),
+            metadata.file_size(),
+            *metadata.fingerprint(),
+        ),
+        (
+            metadata.owner().clone(),
+            b"another_location".to_vec(),
+            metadata.file_size(),
+            *metadata.fingerprint(),
+        ),
+        (
+            metadata.owner().clone(),
+            metadata.location().clone(),
+            metadata.file_size() - 1,
+            *metadata.fingerprint(),
+        ),
+    ] {
+        let other_metadata = FileMetadata::new(
+            owner,
+            metadata.bucket_id().clone(),
+            location,
+            size,
+            fingerprint,
+        )
+        .unwrap();
+        assert_eq!(
+            verify_file_key_proof::<Layout>(&proof, &other_metadata),
+            Err(FileKeyProofError::FileKeyMismatch)
+        );
+    }
+}
+
+#[test]
+fn file_key_proof_not_matching_its_fingerprint_is_rejected() {
+    let mut file_storage = InMemoryFileStorage::<Layout>::new();
+    let metadata = store_file(&mut file_storage, 0);
+    let other_metadata = store_file(&mut file_storage, 100);
+
+    let chunk_ids = HashSet::from([ChunkId::new(0)]);
+    let mut proof = file_storage
+        .generate_proof(&metadata.file_key::<BlakeTwo256>(), &chunk_ids)
+        .unwrap();
+    // The proof of the chunks of another file doesn't lead to the fingerprint of this one.
+    proof.proof = file_storage
+        .generate_proof(&other_metadata.file_key::<BlakeTwo256>(), &chunk_ids)
+        .unwrap()
+        .proof;
+
+    assert!(matches!(
+        verify_file_key_proof::<Layout>(&proof, &metadata),
+        Err(FileKeyProofError::InvalidProof(
+            ProvenFileKeyError::TrieAndExpectedRootMismatch
+        ))
+    ));
+}
@@ -0,0 +1,5 @@
+//! Test suites exercising the File Storage implementations beyond their unit tests.
+
+mod bucket_proof;
+mod chunk_id;
+mod file_key_proof;
@@ -1,46 +1,12 @@
-use std::{collections::HashSet, str::FromStr};
+use std::{collections::HashSet, str::FromStr, time::Duration};
 
 use trie_db::TrieLayout;
 
-use shc_common::types::{Chunk, ChunkId, FileKeyProof, FileMetadata, FileProof, HasherOutT};
+use shc_common::types::{
+    Chunk, ChunkId, FileKeyProof, FileMetadata, FileMetadataError, FileProof, HasherOutT,
+};
 
-#[derive(Debug)]
-pub enum FileStorageWriteError {
-    /// The requested file does not exist.
-    FileDoesNotExist,
-    /// File chunk ID already exists.
-    FileChunkAlreadyExists,
-    /// Failed to insert the file chunk.
-    FailedToInsertFileChunk,
-    /// Failed to get file chunk.
-    FailedToGetFileChunk,
-    /// File metadata fingerprint does not match the stored file fingerprint.
-    FingerprintAndStoredFileMismatch,
-    /// Failed to construct file trie.
-    FailedToContructFileTrie,
-    /// Failed to construct iterator for trie.
-    FailedToConstructTrieIter,
-    /// Failed to commit changes in overlay to disk.
-    FailedToPersistChanges,
-    /// Failed to delete root.
-    FailedToDeleteRoot,
-    /// Failed to delete chunk.
-    FailedToDeleteChunk,
-    /// Failed to convert raw bytes into [`FileMetadata`].
-    FailedToParseFileMetadata,
-    /// Failed to access storage for reading.
-    FailedToReadStorage,
-    /// Failed to convert raw bytes into [`Fingerprint`].
-    FailedToParseFingerprint,
-    /// Failed to update root after a chunk was written.
-    FailedToUpdatePartialRoot,
-    /// Failed to convert raw bytes into partial root.
-    FailedToParsePartialRoot,
-    /// Failed to get chunks count in storage.
-    FailedToGetStoredChunksCount,
-    /// Reached chunk count limit (overflow)
-    ChunkCountOverflow,
-}
+use crate::bucket_proof::{challenged_chunk, BucketProof};
 
 #[derive(Debug)]
 pub enum FileStorageError {
@@ -92,18 +58,166 @@ pub enum FileStorageError {
     ErrorParsingExcludeType,
     /// Failed to get file key proof from file metadata.
     FailedToConstructFileKeyProof,
+    /// The stored [`FileMetadata`] is malformed, i.e. it cannot be parsed or fails
+    /// [`FileMetadata::validate`].
+    CorruptMetadata,
+    /// The root of a file trie copied to another storage does not match the one of the original
+    /// trie.
+    ReanchoredRootMismatch,
+    /// More chunks were requested to be proven at once than the configured maximum (see
+    /// [`DEFAULT_MAX_CHUNKS_PER_PROOF`]). The request should be split.
+    TooManyChunksRequested { requested: u64, max: u64 },
+    /// The [`FileMetadata`] of a file to insert fails [`FileMetadata::validate_strict`].
+    InvalidMetadata(FileMetadataError),
+    /// Failed to construct file trie.
+    FailedToContructFileTrie,
+    /// Failed to delete root.
+    FailedToDeleteRoot,
+    /// Failed to update root after a chunk was written.
+    FailedToUpdatePartialRoot,
+    /// Failed to get chunks count in storage.
+    FailedToGetStoredChunksCount,
+    /// Reached chunk count limit (overflow)
+    ChunkCountOverflow,
+    /// The chunk ID is not within the range of chunks of the file.
+    ChunkIdOutOfRange,
+    /// The file is sealed (see [`FileStorage::seal_file`]), so no more chunks can be written to it.
+    FileSealed,
+    /// A shard of a [`ShardedFileStorage`] was opened at another position, or with another number
+    /// of shards, than the first time it was opened.
+    ///
+    /// [`ShardedFileStorage`]: crate::sharded::ShardedFileStorage
+    ShardManifestMismatch,
 }
 
-#[derive(Debug)]
+/// Category of a [`FileStorageError`], see [`FileStorageError::kind`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileStorageErrorKind {
+    /// The file or chunk operated on is not stored.
+    NotFound,
+    /// The file or chunk to store is already stored, or the file takes no more chunks.
+    AlreadyExists,
+    /// The stored data is inconsistent or cannot be parsed, which should never happen.
+    Corruption,
+    /// The storage backend failed, which may not happen again if retried.
+    Backend,
+    /// The operation was given data that the storage refuses, whatever its state.
+    InvalidInput,
+}
+
+impl FileStorageError {
+    /// Categorises this error, so that callers can handle whole categories alike.
+    pub fn kind(&self) -> FileStorageErrorKind {
+        match self {
+            FileStorageError::FileDoesNotExist | FileStorageError::FileChunkDoesNotExist => {
+                FileStorageErrorKind::NotFound
+            }
+            FileStorageError::FileAlreadyExists
+            | FileStorageError::FileChunkAlreadyExists
+            // A sealed file is complete, so whatever is written to it is already stored.
+            | FileStorageError::FileSealed => FileStorageErrorKind::AlreadyExists,
+            FileStorageError::FingerprintAndStoredFileMismatch
+            | FileStorageError::FailedToParseKey
+            | FileStorageError::FailedToConstructTrieIter
+            | FileStorageError::FailedToContructFileTrie
+            | FileStorageError::FailedToParseFileMetadata
+            | FileStorageError::FailedToParseFingerprint
+            | FileStorageError::FailedToParseChunkWithId
+            | FileStorageError::FailedToParsePartialRoot
+            | FileStorageError::FailedToHasherOutput
+            | FileStorageError::CorruptMetadata
+            | FileStorageError::ReanchoredRootMismatch
+            | FileStorageError::ChunkCountOverflow
+            | FileStorageError::ShardManifestMismatch => FileStorageErrorKind::Corruption,
+            FileStorageError::FailedToInsertFileChunk
+            | FileStorageError::FailedToGetFileChunk
+            | FileStorageError::FailedToGenerateCompactProof
+            | FileStorageError::FailedToReadStorage
+            | FileStorageError::FailedToWriteToStorage
+            | FileStorageError::FailedToDeleteFileChunk
+            | FileStorageError::FailedToDeleteRoot
+            | FileStorageError::FailedToUpdatePartialRoot
+            | FileStorageError::FailedToGetStoredChunksCount
+            | FileStorageError::FailedToAddEntityToExcludeList
+            | FileStorageError::FailedToAddEntityFromExcludeList
+            | FileStorageError::FailedToConstructFileKeyProof => FileStorageErrorKind::Backend,
+            FileStorageError::IncompleteFile
+            | FileStorageError::FileIsEmpty
+            | FileStorageError::ErrorParsingExcludeType
+            | FileStorageError::TooManyChunksRequested { .. }
+            | FileStorageError::InvalidMetadata(_)
+            | FileStorageError::ChunkIdOutOfRange => FileStorageErrorKind::InvalidInput,
+        }
+    }
+
+    /// Whether a storage request whose upload fails with this error should be rejected, as the
+    /// data uploaded for it is invalid.
+    ///
+    /// Failures of the storage itself are not the user's fault, and are either retried or
+    /// rejected as internal errors instead.
+    pub fn should_reject_request(&self) -> bool {
+        self.kind() == FileStorageErrorKind::InvalidInput
+    }
+}
+
+/// Aggregated statistics of the files in a [`FileStorage`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileStorageStats {
+    /// Number of files in storage, complete or not.
+    pub files: u64,
+    /// Sum of the sizes of those files, as declared in their metadata.
+    pub total_size: u64,
+}
+
+/// An incomplete file of a [`FileStorage`], as returned by [`FileStorage::incomplete_files`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IncompleteFile<K> {
+    pub file_key: K,
+    /// Time since the file was inserted, or `None` if it was inserted before the insertion time
+    /// was recorded.
+    pub age: Option<Duration>,
+}
+
+/// Outcome of [`FileStorage::verify_and_repair_file`] for a file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileVerification {
+    /// Whether all the chunks of the file are stored and make up its fingerprint.
+    pub complete: bool,
+    /// Whether the data derived from the chunks of the file (i.e. its stored chunks count and
+    /// current root) was out of date, and was rebuilt.
+    pub repaired: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
 pub enum FileStorageWriteOutcome {
     /// The file storage was completed after this write.
     /// All chunks for the file are stored and the fingerprints match too.
     FileComplete,
     /// The file was not completed after this chunk write.
-    FileIncomplete,
+    FileIncomplete {
+        /// Number of chunks of the file stored, including this one.
+        stored: u64,
+        /// Number of chunks of the file.
+        total: u64,
+    },
 }
 
-#[derive(Eq, Hash, PartialEq)]
+impl FileStorageWriteOutcome {
+    /// Percentage of the chunks of the file stored after this write.
+    pub fn progress_percentage(&self) -> f64 {
+        match self {
+            FileStorageWriteOutcome::FileComplete => 100.0,
+            FileStorageWriteOutcome::FileIncomplete { stored, total } => {
+                if *total == 0 {
+                    return 0.0;
+                }
+                *stored as f64 * 100.0 / *total as f64
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
 pub enum ExcludeType {
     File,
     User,
@@ -138,18 +252,51 @@ pub trait FileDataTrie<T: TrieLayout> {
 
     // TODO: make it accept a list of chunks to be retrieved
     /// Write a file chunk in storage updating the root hash of the trie.
-    fn write_chunk(
-        &mut self,
-        chunk_id: &ChunkId,
-        data: &Chunk,
-    ) -> Result<(), FileStorageWriteError>;
+    fn write_chunk(&mut self, chunk_id: &ChunkId, data: &Chunk) -> Result<(), FileStorageError>;
 
     /// Removes all references to chunks in the trie data and removes
     /// chunks themselves from storage.
-    fn delete(&mut self) -> Result<(), FileStorageWriteError>;
+    fn delete(&mut self) -> Result<(), FileStorageError>;
+
+    /// Get the IDs of the chunks in the trie, in ascending numeric order.
+    ///
+    /// Chunks are keyed by [`ChunkId::as_trie_key`], a compact encoding whose byte order is not
+    /// the numeric order of the IDs (e.g. the key of chunk 64 sorts before the key of chunk 1),
+    /// so the IDs are sorted after being decoded from the keys.
+    fn chunk_ids(&self) -> Result<Vec<ChunkId>, FileStorageError>;
+
+    /// Iterate over the chunks in the trie in ascending numeric order of their [`ChunkId`],
+    /// regardless of the order of their keys in the trie.
+    ///
+    /// This is the order in which the chunks make up the file, so it can be used to stream the
+    /// file. Chunks are read from storage one at a time, as the iterator advances.
+    fn chunks_in_order(
+        &self,
+    ) -> Result<
+        impl Iterator<Item = Result<(ChunkId, Chunk), FileStorageError>> + '_,
+        FileStorageError,
+    > {
+        Ok(self
+            .chunk_ids()?
+            .into_iter()
+            .map(move |chunk_id| Ok((chunk_id, self.get_chunk(&chunk_id)?))))
+    }
 }
 
+/// Default maximum number of chunks that can be proven in a single [`FileStorage::generate_proof`]
+/// call, so that proofs don't grow beyond what can be submitted or sent at once.
+///
+/// It is well above the chunks of a batch sent in a single file transfer
+/// ([`BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE`](shc_common::types::BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE)
+/// bytes, i.e. 2048 chunks).
+pub const DEFAULT_MAX_CHUNKS_PER_PROOF: u64 = 16 * 1024;
+
 /// Storage interface to be implemented by the storage providers.
+///
+/// Operations on a single file that are frequent while storing files ([`FileStorage::write_chunk`]
+/// and [`FileStorage::seal_file`]) only take `&self`: implementations serialise them by file key
+/// internally (see [`FileKeyLocks`](crate::locks::FileKeyLocks)), so that callers sharing the
+/// storage can write chunks of distinct files, or generate proofs, concurrently.
 pub trait FileStorage<T: TrieLayout>: 'static {
     type FileDataTrie: FileDataTrie<T> + Send + Sync;
 
@@ -157,8 +304,19 @@ pub trait FileStorage<T: TrieLayout>: 'static {
     /// Should be used as the default way of generating new tries.
     fn new_file_data_trie(&self) -> Self::FileDataTrie;
 
+    /// Creates a new [`FileDataTrie`] for the file with `key`, when it is known beforehand.
+    ///
+    /// Storages that spread their files across several backends create it where the file is
+    /// stored, so that it doesn't have to be moved once the file is inserted.
+    fn new_file_data_trie_for(&self, _key: &HasherOutT<T>) -> Self::FileDataTrie {
+        self.new_file_data_trie()
+    }
+
     /// Generate proof for a chunk of a file. If the file does not exists or any chunk is missing,
     /// no proof will be returned.
+    ///
+    /// Fails with [`FileStorageError::TooManyChunksRequested`] if more chunks are requested than
+    /// the maximum configured for the storage, before doing any work.
     fn generate_proof(
         &self,
         key: &HasherOutT<T>,
@@ -170,23 +328,87 @@ pub trait FileStorage<T: TrieLayout>: 'static {
 
     fn delete_files_with_prefix(&mut self, prefix: &[u8; 32]) -> Result<(), FileStorageError>;
 
+    /// Get the keys of the files of `bucket_id`, complete or not, in ascending order.
+    fn bucket_file_keys(
+        &self,
+        bucket_id: &[u8; 32],
+    ) -> Result<Vec<HasherOutT<T>>, FileStorageError>;
+
+    /// Generates a [`BucketProof`] of all the files of `bucket_id`, proving the chunk of each of
+    /// them challenged by `seed`, along with their last chunk.
+    ///
+    /// Fails with [`FileStorageError::IncompleteFile`] if any file of the bucket is incomplete.
+    fn generate_bucket_proof(
+        &self,
+        bucket_id: &[u8; 32],
+        seed: &[u8; 32],
+    ) -> Result<BucketProof, FileStorageError> {
+        let mut file_key_proofs = Vec::new();
+        for file_key in self.bucket_file_keys(bucket_id)? {
+            if !self.is_file_complete(&file_key)? {
+                return Err(FileStorageError::IncompleteFile);
+            }
+            let metadata = self
+                .get_metadata(&file_key)?
+                .ok_or(FileStorageError::FileDoesNotExist)?;
+
+            let chunk_ids = HashSet::from([
+                challenged_chunk::<T>(seed, &file_key, &metadata),
+                metadata.last_chunk_id(),
+            ]);
+            file_key_proofs.push(self.generate_proof(&file_key, &chunk_ids)?);
+        }
+
+        Ok(BucketProof {
+            bucket_id: *bucket_id,
+            file_key_proofs,
+        })
+    }
+
     /// Get metadata for a file.
     fn get_metadata(&self, key: &HasherOutT<T>) -> Result<Option<FileMetadata>, FileStorageError>;
 
+    /// Check if a file is stored, complete or not, without deserialising its metadata.
+    fn file_exists(&self, key: &HasherOutT<T>) -> Result<bool, FileStorageError>;
+
     /// Check if a file is completely stored.
     fn is_file_complete(&self, key: &HasherOutT<T>) -> Result<bool, FileStorageError>;
 
     /// Inserts a new file. If the file already exists, it will return an error.
     /// It is expected that the file key is indeed computed from the [Metadata].
     /// This method does not require the actual data, file [`Chunk`]s being inserted separately.
+    ///
+    /// Fails with [`FileStorageError::InvalidMetadata`] if the metadata is not that of a file
+    /// that can be stored.
     fn insert_file(
         &mut self,
         key: HasherOutT<T>,
         metadata: FileMetadata,
     ) -> Result<(), FileStorageError>;
 
+    /// Inserts a new file like [`FileStorage::insert_file`], unless it is already stored, in which
+    /// case it is left untouched along with its chunks.
+    ///
+    /// Returns whether the file was inserted. Checking and inserting under the same exclusive
+    /// borrow means that, out of concurrent callers inserting the same file, only one does.
+    fn insert_file_if_absent(
+        &mut self,
+        key: HasherOutT<T>,
+        metadata: FileMetadata,
+    ) -> Result<bool, FileStorageError> {
+        if self.file_exists(&key)? {
+            return Ok(false);
+        }
+
+        self.insert_file(key, metadata)?;
+        Ok(true)
+    }
+
     /// Inserts a new file with the associated trie data. If the file already exists, it will
     /// return an error.
+    ///
+    /// Fails with [`FileStorageError::InvalidMetadata`] if the metadata is not that of a file
+    /// that can be stored.
     fn insert_file_with_data(
         &mut self,
         key: HasherOutT<T>,
@@ -194,9 +416,93 @@ pub trait FileStorage<T: TrieLayout>: 'static {
         file_data: Self::FileDataTrie,
     ) -> Result<(), FileStorageError>;
 
+    /// Replaces the file with `old_key` by a new file with its associated trie data, atomically:
+    /// either both the deletion and the insertion happen, or none of them does, so the new file
+    /// is never missing while the old one is gone.
+    ///
+    /// Fails if the old file does not exist, or if the new file already exists (unless it is the
+    /// old file being replaced).
+    fn atomic_delete_and_insert(
+        &mut self,
+        old_key: HasherOutT<T>,
+        new_key: HasherOutT<T>,
+        new_metadata: FileMetadata,
+        new_data: Self::FileDataTrie,
+    ) -> Result<(), FileStorageError>;
+
+    /// Copies a complete file to a new file key, computed from `new_metadata`.
+    ///
+    /// The file data of `src_key` is reused for the copy (sharing chunk storage where the backend
+    /// supports it), so `new_metadata` must have the same fingerprint and size as the source.
+    /// Returns the file key of the copy.
+    fn copy_file(
+        &mut self,
+        src_key: &HasherOutT<T>,
+        new_metadata: FileMetadata,
+    ) -> Result<HasherOutT<T>, FileStorageError>;
+
+    /// Get the keys of the incomplete files inserted before `created_before`, a unix timestamp in
+    /// seconds.
+    ///
+    /// Used to clean up abandoned uploads. Files without a recorded creation time (i.e. inserted
+    /// before it was recorded) are of unknown age, and are never returned.
+    fn incomplete_files_older_than(
+        &self,
+        created_before: u64,
+    ) -> Result<Vec<HasherOutT<T>>, FileStorageError>;
+
+    /// Get all the incomplete files, whatever their age, along with their age.
+    fn incomplete_files(&self) -> Result<Vec<IncompleteFile<HasherOutT<T>>>, FileStorageError>;
+
+    /// Get the time since the file with `key` was inserted, if it is still incomplete.
+    ///
+    /// Returns `None` for complete files, and for files of unknown age (see
+    /// [`FileStorage::incomplete_files_older_than`]).
+    fn partial_file_age(&self, key: &HasherOutT<T>) -> Result<Option<Duration>, FileStorageError>;
+
+    /// Seals a complete file, marking it as immutable: any later [`FileStorage::write_chunk`]
+    /// for it fails with [`FileStorageError::FileSealed`].
+    ///
+    /// Fails if the file is incomplete or its stored data does not match its fingerprint.
+    /// Sealing an already sealed file is a no-op.
+    fn seal_file(&self, key: &HasherOutT<T>) -> Result<(), FileStorageError>;
+
+    /// Get the keys of all the files in storage, complete or not, in ascending order.
+    fn file_keys(&self) -> Result<Vec<HasherOutT<T>>, FileStorageError>;
+
+    /// Get the keys of at most `limit` files in storage, complete or not, in ascending order,
+    /// starting after `start_key`, or from the first file if it is `None`.
+    ///
+    /// Meant to paginate through all the files in storage: the last key of a page is the
+    /// `start_key` of the next one. `start_key` does not have to be the key of a stored file.
+    fn get_files_after_key(
+        &self,
+        start_key: Option<HasherOutT<T>>,
+        limit: usize,
+    ) -> Result<Vec<HasherOutT<T>>, FileStorageError>;
+
+    /// Checks the stored chunks of a file against its fingerprint, rebuilding the data derived
+    /// from them (i.e. its stored chunks count and current root) if it is out of date.
+    ///
+    /// Meant to be run over the whole storage after restoring it from a backup, in which the
+    /// derived data may have been saved at a different time than the chunks.
+    fn verify_and_repair_file(
+        &self,
+        key: &HasherOutT<T>,
+    ) -> Result<FileVerification, FileStorageError>;
+
+    /// Get aggregated statistics of the files in storage.
+    fn stats(&self) -> Result<FileStorageStats, FileStorageError>;
+
     /// Get the number of stored chunks for a file key.
     fn stored_chunks_count(&self, key: &HasherOutT<T>) -> Result<u64, FileStorageError>;
 
+    /// Get the IDs of the chunks stored for a file key, in ascending order.
+    ///
+    /// Unlike [`FileStorage::stored_chunks_count`], this tells which chunks of a partially
+    /// stored file are present, even if they were not written in order.
+    fn present_chunk_ids(&self, key: &HasherOutT<T>) -> Result<Vec<ChunkId>, FileStorageError>;
+
     // TODO: Return Result<Option> instead of Result only
     /// Get a file chunk from storage.
     fn get_chunk(&self, key: &HasherOutT<T>, chunk_id: &ChunkId)
@@ -205,11 +511,11 @@ pub trait FileStorage<T: TrieLayout>: 'static {
     /// Write a file chunk in storage. It is expected that you verify the associated proof that the
     /// [`Chunk`] is part of the file before writing it.
     fn write_chunk(
-        &mut self,
+        &self,
         key: &HasherOutT<T>,
         chunk_id: &ChunkId,
         data: &Chunk,
-    ) -> Result<FileStorageWriteOutcome, FileStorageWriteError>;
+    ) -> Result<FileStorageWriteOutcome, FileStorageError>;
 
     fn is_allowed(
         &self,
@@ -228,4 +534,11 @@ pub trait FileStorage<T: TrieLayout>: 'static {
         key: &HasherOutT<T>,
         exclude_type: ExcludeType,
     ) -> Result<(), FileStorageError>;
+
+    /// Marks the storage as shut down cleanly, writing whatever it still keeps in memory.
+    ///
+    /// To be called when the node shuts down, once nothing else is written to the storage.
+    fn shut_down(&mut self) -> Result<(), FileStorageError> {
+        Ok(())
+    }
 }
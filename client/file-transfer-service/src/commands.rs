@@ -13,7 +13,7 @@ use shc_common::types::{
     BucketId, ChunkId, DownloadRequestId, FileKey, FileKeyProof, UploadRequestId,
 };
 
-use super::{schema, FileTransferService};
+use super::{retrieval::RetrievalAuthorization, schema, FileTransferService};
 
 const LOG_TARGET: &str = "file-transfer-service";
 
@@ -60,6 +60,9 @@ pub enum FileTransferServiceCommand {
         /// Note: The task that handles the event is responsible for checking if the file is
         /// part of the specified bucket.
         bucket_id: Option<BucketId>,
+        /// Authorization to retrieve the file on behalf of an account, used instead of the allow
+        /// list check of the peer to retrieve files from a Main Storage Provider.
+        retrieval_authorization: Option<RetrievalAuthorization>,
         callback: tokio::sync::oneshot::Sender<
             futures::channel::oneshot::Receiver<Result<(Vec<u8>, ProtocolName), RequestFailure>>,
         >,
@@ -69,11 +72,26 @@ pub enum FileTransferServiceCommand {
         file_key_proof: FileKeyProof,
         callback: tokio::sync::oneshot::Sender<Result<(), RequestError>>,
     },
+    RejectDownloadRequest {
+        request_id: DownloadRequestId,
+        callback: tokio::sync::oneshot::Sender<Result<(), RequestError>>,
+    },
     AddKnownAddress {
         peer_id: PeerId,
         multiaddress: Multiaddr,
         callback: tokio::sync::oneshot::Sender<Result<(), RequestError>>,
     },
+    ReportPeer {
+        peer_id: PeerId,
+        /// Reason for the report, shown in the reputation change.
+        reason: &'static str,
+        callback: tokio::sync::oneshot::Sender<Result<(), RequestError>>,
+    },
+    ReportDuplicateChunks {
+        peer_id: PeerId,
+        file_key: FileKey,
+        callback: tokio::sync::oneshot::Sender<Result<(), RequestError>>,
+    },
     RegisterNewFile {
         peer_id: PeerId,
         file_key: FileKey,
@@ -83,6 +101,15 @@ pub enum FileTransferServiceCommand {
         file_key: FileKey,
         callback: tokio::sync::oneshot::Sender<Result<(), RequestError>>,
     },
+    GetActiveUploadCount {
+        callback: tokio::sync::oneshot::Sender<usize>,
+    },
+    GetRegisteredFileKeys {
+        callback: tokio::sync::oneshot::Sender<Vec<FileKey>>,
+    },
+    GetLocalPeerId {
+        callback: tokio::sync::oneshot::Sender<PeerId>,
+    },
     RegisterNewBucketPeer {
         peer_id: PeerId,
         bucket_id: BucketId,
@@ -158,18 +185,39 @@ pub trait FileTransferServiceInterface {
         bucket_id: Option<BucketId>,
     ) -> Result<schema::v1::provider::RemoteDownloadDataResponse, RequestError>;
 
+    async fn retrieval_request(
+        &self,
+        peer_id: PeerId,
+        file_key: FileKey,
+        chunk_ids: std::collections::HashSet<ChunkId>,
+        retrieval_authorization: RetrievalAuthorization,
+    ) -> Result<schema::v1::provider::RemoteDownloadDataResponse, RequestError>;
+
     async fn download_response(
         &self,
         file_key_proof: FileKeyProof,
         request_id: DownloadRequestId,
     ) -> Result<schema::v1::provider::RemoteDownloadDataResponse, RequestError>;
 
+    async fn reject_download_request(
+        &self,
+        request_id: DownloadRequestId,
+    ) -> Result<(), RequestError>;
+
     async fn add_known_address(
         &self,
         peer_id: PeerId,
         multiaddress: Multiaddr,
     ) -> Result<(), RequestError>;
 
+    async fn report_peer(&self, peer_id: PeerId, reason: &'static str) -> Result<(), RequestError>;
+
+    async fn report_duplicate_chunks(
+        &self,
+        peer_id: PeerId,
+        file_key: FileKey,
+    ) -> Result<(), RequestError>;
+
     async fn register_new_file_peer(
         &self,
         peer_id: PeerId,
@@ -178,6 +226,12 @@ pub trait FileTransferServiceInterface {
 
     async fn unregister_file(&self, file_key: FileKey) -> Result<(), RequestError>;
 
+    async fn get_active_upload_count(&self) -> usize;
+
+    async fn get_registered_file_keys(&self) -> Vec<FileKey>;
+
+    async fn get_local_peer_id(&self) -> PeerId;
+
     async fn register_new_bucket_peer(
         &self,
         peer_id: PeerId,
@@ -275,42 +329,28 @@ impl FileTransferServiceInterface for ActorHandle<FileTransferService> {
         chunk_ids: std::collections::HashSet<ChunkId>,
         bucket_id: Option<BucketId>,
     ) -> Result<schema::v1::provider::RemoteDownloadDataResponse, RequestError> {
-        let (callback, file_transfer_rx) = tokio::sync::oneshot::channel();
-        let command = FileTransferServiceCommand::DownloadRequest {
+        send_download_request(self, peer_id, file_key, chunk_ids, bucket_id, None).await
+    }
+
+    /// Request to retrieve a batch of file chunks from a Main Storage Provider on behalf of the
+    /// requester of `retrieval_authorization`.
+    /// This returns after receiving and processing the network response.
+    async fn retrieval_request(
+        &self,
+        peer_id: PeerId,
+        file_key: FileKey,
+        chunk_ids: std::collections::HashSet<ChunkId>,
+        retrieval_authorization: RetrievalAuthorization,
+    ) -> Result<schema::v1::provider::RemoteDownloadDataResponse, RequestError> {
+        send_download_request(
+            self,
             peer_id,
             file_key,
             chunk_ids,
-            bucket_id,
-            callback,
-        };
-        self.send(command).await;
-
-        // First we wait for the response from the FileTransferService.
-        // The response is another oneshot channel to wait for the response from the network.
-        let network_rx = file_transfer_rx.await.expect("Failed to receive response from FileTransferService. Probably means FileTransferService has crashed.");
-
-        // Now we wait on the actual response from the network.
-        let response = network_rx.await.expect(
-            "Failed to receive response from the NetworkService. Probably means the NetworkService has crashed.",
-        );
-
-        match response {
-            Ok((data, _protocol_name)) => {
-                let response = schema::v1::provider::Response::decode(&data[..]);
-                match response {
-                    Ok(response) => match response.response {
-                        Some(
-                            schema::v1::provider::response::Response::RemoteDownloadDataResponse(
-                                response,
-                            ),
-                        ) => Ok(response),
-                        _ => Err(RequestError::UnexpectedResponse),
-                    },
-                    Err(error) => Err(RequestError::DecodeError(error)),
-                }
-            }
-            Err(error) => Err(RequestError::RequestFailure(error)),
-        }
+            None,
+            Some(retrieval_authorization),
+        )
+        .await
     }
 
     /// Respond to a download request of a file chunk with a [`FileKeyProof`].
@@ -344,6 +384,22 @@ impl FileTransferServiceInterface for ActorHandle<FileTransferService> {
         }
     }
 
+    /// Respond to a download request with an error, so that the requester doesn't wait for the
+    /// request to time out when it won't be served.
+    /// This returns after the message has been processed by the service.
+    async fn reject_download_request(
+        &self,
+        request_id: DownloadRequestId,
+    ) -> Result<(), RequestError> {
+        let (callback, file_transfer_rx) = tokio::sync::oneshot::channel();
+        let command = FileTransferServiceCommand::RejectDownloadRequest {
+            request_id,
+            callback,
+        };
+        self.send(command).await;
+        file_transfer_rx.await.expect("Failed to received response from FileTransferService. Probably means FileTransferService has crashed.")
+    }
+
     /// Tell the FileTransferService to register a multiaddress as known for a specified [`PeerId`].
     /// This returns after the message has been processed by the service.
     async fn add_known_address(
@@ -361,6 +417,41 @@ impl FileTransferServiceInterface for ActorHandle<FileTransferService> {
         rx.await.expect("Failed to add known multiaddress to peer")
     }
 
+    /// Tell the FileTransferService to lower the reputation of [`peer_id`] for misbehaving,
+    /// e.g. for sending invalid data.
+    /// This returns after the message has been processed by the service.
+    async fn report_peer(&self, peer_id: PeerId, reason: &'static str) -> Result<(), RequestError> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let command = FileTransferServiceCommand::ReportPeer {
+            peer_id,
+            reason,
+            callback,
+        };
+        self.send(command).await;
+        rx.await.expect("Failed to report peer")
+    }
+
+    /// Tell the FileTransferService that [`peer_id`] sent a batch of chunks of [`file_key`] some
+    /// of which were already stored.
+    /// Once the batches with duplicate chunks exceed the threshold within a window, the
+    /// reputation of the peer is lowered and a
+    /// [`DuplicateChunksDetected`](crate::events::DuplicateChunksDetected) event is emitted.
+    /// This returns after the message has been processed by the service.
+    async fn report_duplicate_chunks(
+        &self,
+        peer_id: PeerId,
+        file_key: FileKey,
+    ) -> Result<(), RequestError> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let command = FileTransferServiceCommand::ReportDuplicateChunks {
+            peer_id,
+            file_key,
+            callback,
+        };
+        self.send(command).await;
+        rx.await.expect("Failed to report duplicate chunks")
+    }
+
     /// Tell the FileTransferService to start listening for new upload requests from [`peer_id`]
     /// on file [`file_key`].
     /// This returns after the message has been processed by the service.
@@ -389,6 +480,35 @@ impl FileTransferServiceInterface for ActorHandle<FileTransferService> {
         rx.await.expect("Failed to unregister file")
     }
 
+    /// Get the number of unique file keys currently registered for upload, i.e. the number of
+    /// uploads this node is currently accepting chunks for.
+    /// This returns after the message has been processed by the service.
+    async fn get_active_upload_count(&self) -> usize {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let command = FileTransferServiceCommand::GetActiveUploadCount { callback };
+        self.send(command).await;
+        rx.await.expect("Failed to get active upload count")
+    }
+
+    /// Get the file keys currently registered for upload, regardless of the peers allowed to
+    /// upload them.
+    /// This returns after the message has been processed by the service.
+    async fn get_registered_file_keys(&self) -> Vec<FileKey> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let command = FileTransferServiceCommand::GetRegisteredFileKeys { callback };
+        self.send(command).await;
+        rx.await.expect("Failed to get registered file keys")
+    }
+
+    /// Get the peer ID of this node, which is the one other peers see chunks coming from.
+    /// This returns after the message has been processed by the service.
+    async fn get_local_peer_id(&self) -> PeerId {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let command = FileTransferServiceCommand::GetLocalPeerId { callback };
+        self.send(command).await;
+        rx.await.expect("Failed to get local peer ID")
+    }
+
     /// Tell the FileTransferService to start listening for new upload requests from [`peer_id`]
     /// on Bucket [`bucket_id`].
     /// This returns after the message has been processed by the service.
@@ -448,3 +568,49 @@ impl FileTransferServiceInterface for ActorHandle<FileTransferService> {
         peer_ids
     }
 }
+
+/// Sends a download request to `peer_id` and waits for its response.
+async fn send_download_request(
+    file_transfer: &ActorHandle<FileTransferService>,
+    peer_id: PeerId,
+    file_key: FileKey,
+    chunk_ids: std::collections::HashSet<ChunkId>,
+    bucket_id: Option<BucketId>,
+    retrieval_authorization: Option<RetrievalAuthorization>,
+) -> Result<schema::v1::provider::RemoteDownloadDataResponse, RequestError> {
+    let (callback, file_transfer_rx) = tokio::sync::oneshot::channel();
+    let command = FileTransferServiceCommand::DownloadRequest {
+        peer_id,
+        file_key,
+        chunk_ids,
+        bucket_id,
+        retrieval_authorization,
+        callback,
+    };
+    file_transfer.send(command).await;
+
+    // First we wait for the response from the FileTransferService.
+    // The response is another oneshot channel to wait for the response from the network.
+    let network_rx = file_transfer_rx.await.expect("Failed to receive response from FileTransferService. Probably means FileTransferService has crashed.");
+
+    // Now we wait on the actual response from the network.
+    let response = network_rx.await.expect(
+        "Failed to receive response from the NetworkService. Probably means the NetworkService has crashed.",
+    );
+
+    match response {
+        Ok((data, _protocol_name)) => {
+            let response = schema::v1::provider::Response::decode(&data[..]);
+            match response {
+                Ok(response) => match response.response {
+                    Some(schema::v1::provider::response::Response::RemoteDownloadDataResponse(
+                        response,
+                    )) => Ok(response),
+                    _ => Err(RequestError::UnexpectedResponse),
+                },
+                Err(error) => Err(RequestError::DecodeError(error)),
+            }
+        }
+        Err(error) => Err(RequestError::RequestFailure(error)),
+    }
+}
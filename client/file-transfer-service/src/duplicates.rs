@@ -0,0 +1,257 @@
+//! Tracks the chunks uploaded to this node that it had already stored.
+//!
+//! Peers resume interrupted uploads from the chunks this node acknowledged, so a peer that keeps
+//! sending chunks that were already stored is wasting bandwidth: either the resumption of uploads
+//! is not working, or the peer is misbehaving. The batches holding duplicate chunks are counted
+//! per peer and file within a window, which is reported once the count exceeds a threshold.
+//!
+//! Batches are counted rather than chunks, since a single batch holds up to
+//! [`BATCH_CHUNK_FILE_TRANSFER_MAX_CHUNKS`](shc_common::types::BATCH_CHUNK_FILE_TRANSFER_MAX_CHUNKS)
+//! chunks, and peers honestly resend a whole batch whose acknowledgement was lost.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use sc_network::PeerId;
+
+use shc_common::types::FileKey;
+
+use crate::{
+    events::DuplicateChunksDetected,
+    upload::{MAX_BATCH_UPLOAD_RETRIES, MAX_UNACKNOWLEDGED_BATCHES},
+};
+
+/// Number of batches with duplicate chunks of a file that a peer can send within
+/// [`DUPLICATE_CHUNKS_WINDOW`] before being reported.
+///
+/// As many as a [`ChunkUploader`](crate::upload::ChunkUploader) resends when none of the batches
+/// in flight are acknowledged, each of them for the maximum number of retries.
+pub const DUPLICATE_CHUNKS_THRESHOLD: u64 =
+    MAX_BATCH_UPLOAD_RETRIES as u64 * MAX_UNACKNOWLEDGED_BATCHES as u64;
+
+/// Length of the window in which the batches with duplicate chunks of a file sent by a peer are
+/// counted.
+pub const DUPLICATE_CHUNKS_WINDOW: Duration = Duration::from_secs(60);
+
+/// Configuration of the [`DuplicateChunksTracker`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DuplicateChunksConfig {
+    /// Number of batches with duplicate chunks of a file that a peer can send within `window`
+    /// before being reported.
+    pub threshold: u64,
+    /// Length of the window in which the batches with duplicate chunks are counted.
+    pub window: Duration,
+}
+
+impl Default for DuplicateChunksConfig {
+    fn default() -> Self {
+        Self {
+            threshold: DUPLICATE_CHUNKS_THRESHOLD,
+            window: DUPLICATE_CHUNKS_WINDOW,
+        }
+    }
+}
+
+/// The batches with duplicate chunks of a file sent by a peer since the start of the current
+/// window.
+struct DuplicateChunksWindow {
+    started_at: Instant,
+    batches: u64,
+    /// Whether the threshold was already exceeded in this window, which is only reported once.
+    reported: bool,
+}
+
+impl DuplicateChunksWindow {
+    fn new(started_at: Instant) -> Self {
+        Self {
+            started_at,
+            batches: 0,
+            reported: false,
+        }
+    }
+}
+
+/// Counts the batches with duplicate chunks sent by each peer for each file, within fixed windows.
+#[derive(Default)]
+pub struct DuplicateChunksTracker {
+    config: DuplicateChunksConfig,
+    windows: HashMap<(PeerId, FileKey), DuplicateChunksWindow>,
+}
+
+impl DuplicateChunksTracker {
+    pub fn new(config: DuplicateChunksConfig) -> Self {
+        Self {
+            config,
+            windows: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> DuplicateChunksConfig {
+        self.config
+    }
+
+    /// Records a batch with duplicate chunks of the file with `file_key` sent by `peer_id` at
+    /// `now`.
+    ///
+    /// Returns the [`DuplicateChunksDetected`] event to emit when the batches sent in the current
+    /// window first exceed the threshold, and `None` otherwise.
+    pub fn record(
+        &mut self,
+        peer_id: PeerId,
+        file_key: FileKey,
+        now: Instant,
+    ) -> Option<DuplicateChunksDetected> {
+        let window = self
+            .windows
+            .entry((peer_id, file_key))
+            .or_insert_with(|| DuplicateChunksWindow::new(now));
+        if now.saturating_duration_since(window.started_at) >= self.config.window {
+            *window = DuplicateChunksWindow::new(now);
+        }
+
+        window.batches = window.batches.saturating_add(1);
+        if window.reported || window.batches <= self.config.threshold {
+            return None;
+        }
+
+        window.reported = true;
+        Some(DuplicateChunksDetected {
+            peer: peer_id,
+            file_key,
+            duplicate_batches: window.batches,
+            window: self.config.window,
+        })
+    }
+
+    /// Forgets the batches with duplicate chunks of the file with `file_key`, once it is no longer uploaded.
+    pub fn forget_file(&mut self, file_key: &FileKey) {
+        self.windows.retain(|(_, key), _| key != file_key);
+    }
+
+    /// Drops the windows that are over at `now`.
+    pub fn prune(&mut self, now: Instant) {
+        let window = self.config.window;
+        self.windows
+            .retain(|_, duplicates| now.saturating_duration_since(duplicates.started_at) < window);
+    }
+
+    /// Number of (peer, file key) pairs with batches with duplicate chunks in their current window.
+    pub fn len(&self) -> usize {
+        self.windows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sp_core::H256;
+
+    fn tracker(threshold: u64) -> DuplicateChunksTracker {
+        DuplicateChunksTracker::new(DuplicateChunksConfig {
+            threshold,
+            window: Duration::from_secs(60),
+        })
+    }
+
+    /// Records `batches` batches with duplicate chunks, returning the batches reported, if any.
+    fn record_batches(
+        tracker: &mut DuplicateChunksTracker,
+        peer_id: PeerId,
+        file_key: FileKey,
+        batches: u64,
+        now: Instant,
+    ) -> Option<u64> {
+        (0..batches)
+            .filter_map(|_| tracker.record(peer_id, file_key, now))
+            .map(|event| event.duplicate_batches)
+            .last()
+    }
+
+    #[test]
+    fn duplicates_past_the_threshold_are_reported_once_per_window() {
+        let mut tracker = tracker(10);
+        let peer_id = PeerId::random();
+        let file_key = FileKey::from(H256::repeat_byte(1));
+        let start = Instant::now();
+
+        assert_eq!(
+            record_batches(&mut tracker, peer_id, file_key, 10, start),
+            None
+        );
+        let event = tracker
+            .record(peer_id, file_key, start + Duration::from_secs(10))
+            .expect("The threshold is exceeded");
+        assert_eq!(event.peer, peer_id);
+        assert_eq!(event.file_key, file_key);
+        assert_eq!(event.duplicate_batches, 11);
+        assert_eq!(event.window, Duration::from_secs(60));
+        // The same window is not reported twice.
+        assert_eq!(
+            record_batches(
+                &mut tracker,
+                peer_id,
+                file_key,
+                20,
+                start + Duration::from_secs(20)
+            ),
+            None
+        );
+
+        // The count starts over in the next window.
+        let next_window = start + Duration::from_secs(60);
+        assert_eq!(
+            record_batches(&mut tracker, peer_id, file_key, 10, next_window),
+            None
+        );
+        assert_eq!(
+            record_batches(&mut tracker, peer_id, file_key, 1, next_window),
+            Some(11)
+        );
+    }
+
+    #[test]
+    fn duplicates_are_counted_per_peer_and_file() {
+        let mut tracker = tracker(2);
+        let (peer_a, peer_b) = (PeerId::random(), PeerId::random());
+        let (file_a, file_b) = (
+            FileKey::from(H256::repeat_byte(1)),
+            FileKey::from(H256::repeat_byte(2)),
+        );
+        let now = Instant::now();
+
+        assert_eq!(record_batches(&mut tracker, peer_a, file_a, 2, now), None);
+        assert_eq!(record_batches(&mut tracker, peer_b, file_a, 2, now), None);
+        assert_eq!(record_batches(&mut tracker, peer_a, file_b, 2, now), None);
+        assert_eq!(
+            record_batches(&mut tracker, peer_a, file_a, 1, now),
+            Some(3)
+        );
+        assert_eq!(tracker.len(), 3);
+
+        tracker.forget_file(&file_a);
+        assert_eq!(tracker.len(), 1);
+        tracker.prune(now + Duration::from_secs(60));
+        assert!(tracker.is_empty());
+    }
+
+    #[test]
+    fn resending_the_unacknowledged_batches_of_an_upload_is_not_reported() {
+        let mut tracker = DuplicateChunksTracker::default();
+        let peer_id = PeerId::random();
+        let file_key = FileKey::from(H256::repeat_byte(1));
+        let now = Instant::now();
+
+        let resent_batches = MAX_BATCH_UPLOAD_RETRIES as u64 * MAX_UNACKNOWLEDGED_BATCHES as u64;
+        assert_eq!(
+            record_batches(&mut tracker, peer_id, file_key, resent_batches, now),
+            None
+        );
+        assert!(tracker.record(peer_id, file_key, now).is_some());
+    }
+}
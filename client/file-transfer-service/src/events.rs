@@ -3,7 +3,8 @@ use shc_actors_framework::event_bus::{EventBus, EventBusMessage, ProvidesEventBu
 use shc_common::types::{
     BucketId, ChunkId, DownloadRequestId, FileKey, FileKeyProof, UploadRequestId,
 };
-use std::collections::HashSet;
+use sp_runtime::AccountId32;
+use std::{collections::HashSet, time::Duration};
 
 /// A request to upload file chunks to a remote peer with verifiable proof.
 ///
@@ -32,6 +33,8 @@ impl EventBusMessage for RemoteUploadRequest {}
 /// A request to download chunks from a remote peer
 #[derive(Clone)]
 pub struct RemoteDownloadRequest {
+    /// The peer ID of the requester node.
+    pub peer: PeerId,
     /// The key of the file to download chunks from
     pub file_key: FileKey,
     /// Set of unique chunk IDs to download. Using HashSet to enforce uniqueness
@@ -40,14 +43,38 @@ pub struct RemoteDownloadRequest {
     pub bucket_id: Option<BucketId>,
     /// Unique identifier for this download request
     pub request_id: DownloadRequestId,
+    /// The account on behalf of which the file is retrieved, if the request carried a valid
+    /// retrieval authorization instead of passing the allow list check.
+    ///
+    /// Tasks handling the request are responsible for checking that this account can read the file.
+    pub requester: Option<AccountId32>,
 }
 
 impl EventBusMessage for RemoteDownloadRequest {}
 
+/// A peer sent more batches with chunks of a file that were already stored than the threshold of
+/// the [`DuplicateChunksConfig`](crate::duplicates::DuplicateChunksConfig), within its window.
+///
+/// Emitted once per window. The reputation of the peer has already been lowered.
+#[derive(Clone)]
+pub struct DuplicateChunksDetected {
+    /// The peer ID of the sender of the duplicate chunks.
+    pub peer: PeerId,
+    /// File key of the file whose chunks were sent again.
+    pub file_key: FileKey,
+    /// Number of batches with duplicate chunks sent within the window so far.
+    pub duplicate_batches: u64,
+    /// Length of the window in which the batches with duplicate chunks were counted.
+    pub window: Duration,
+}
+
+impl EventBusMessage for DuplicateChunksDetected {}
+
 #[derive(Clone, Default)]
 pub struct FileTransferServiceEventBusProvider {
     remote_upload_request_event_bus: EventBus<RemoteUploadRequest>,
     remote_download_request_event_bus: EventBus<RemoteDownloadRequest>,
+    duplicate_chunks_detected_event_bus: EventBus<DuplicateChunksDetected>,
 }
 
 impl FileTransferServiceEventBusProvider {
@@ -55,6 +82,7 @@ impl FileTransferServiceEventBusProvider {
         Self {
             remote_upload_request_event_bus: EventBus::new(),
             remote_download_request_event_bus: EventBus::new(),
+            duplicate_chunks_detected_event_bus: EventBus::new(),
         }
     }
 }
@@ -70,3 +98,9 @@ impl ProvidesEventBus<RemoteDownloadRequest> for FileTransferServiceEventBusProv
         &self.remote_download_request_event_bus
     }
 }
+
+impl ProvidesEventBus<DuplicateChunksDetected> for FileTransferServiceEventBusProvider {
+    fn event_bus(&self) -> &EventBus<DuplicateChunksDetected> {
+        &self.duplicate_chunks_detected_event_bus
+    }
+}
@@ -28,18 +28,22 @@ use prost::Message;
 use std::{
     collections::{BTreeSet, HashMap, HashSet},
     sync::Arc,
+    time::Instant,
 };
 use tokio::time::{interval, Duration};
 
 use sc_network::{
     request_responses::{IncomingRequest, OutgoingResponse},
     service::traits::NetworkService,
-    IfDisconnected, NetworkPeers, NetworkRequest, ProtocolName, ReputationChange,
+    IfDisconnected, NetworkPeers, NetworkRequest, NetworkStateInfo, ProtocolName, ReputationChange,
 };
 use sc_network_types::PeerId;
 use sc_tracing::tracing::{debug, error, info, warn};
 
-use shc_actors_framework::actor::{Actor, ActorEventLoop};
+use shc_actors_framework::{
+    actor::{Actor, ActorEventLoop},
+    event_bus::ProvidesEventBus,
+};
 use shc_common::types::{
     BucketId, DownloadRequestId, FileKey, FileKeyProof, UploadRequestId,
     BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE, FILE_CHUNK_SIZE,
@@ -50,12 +54,57 @@ use crate::events::RemoteUploadRequest;
 
 use super::{
     commands::{FileTransferServiceCommand, RequestError},
-    events::{FileTransferServiceEventBusProvider, RemoteDownloadRequest},
+    duplicates::{DuplicateChunksConfig, DuplicateChunksTracker},
+    events::{DuplicateChunksDetected, FileTransferServiceEventBusProvider, RemoteDownloadRequest},
+    retrieval::{RetrievalAuthorization, UsedRetrievalAuthorizations},
     schema,
 };
 
 const LOG_TARGET: &str = "file-transfer-service";
 
+/// Reputation change applied to peers sending invalid requests or data.
+const BAD_REQUEST_REPUTATION_CHANGE: i32 = -(1 << 12);
+
+/// Reputation change applied to peers sending too many chunks that were already stored, which
+/// wastes bandwidth but is not necessarily malicious.
+const DUPLICATE_CHUNKS_REPUTATION_CHANGE: i32 = -(1 << 10);
+
+/// Records in `tracker` a batch with duplicate chunks of `file_key` sent by `peer_id` at `now`.
+///
+/// Once the batches sent by the peer exceed the threshold within a window, its reputation is
+/// lowered through `report_peer` and a [`DuplicateChunksDetected`] event is emitted.
+fn record_duplicate_chunks(
+    tracker: &mut DuplicateChunksTracker,
+    event_bus_provider: &FileTransferServiceEventBusProvider,
+    peer_id: sc_network::PeerId,
+    file_key: FileKey,
+    now: Instant,
+    report_peer: impl FnOnce(ReputationChange),
+) {
+    let Some(event) = tracker.record(peer_id, file_key, now) else {
+        return;
+    };
+
+    warn!(
+        target: LOG_TARGET,
+        "Peer {} sent {} batches with chunks of file {:?} that were already stored within {:?}. Lowering reputation.",
+        peer_id,
+        event.duplicate_batches,
+        file_key,
+        event.window
+    );
+    report_peer(ReputationChange::new(
+        DUPLICATE_CHUNKS_REPUTATION_CHANGE,
+        "Duplicate chunks",
+    ));
+    ProvidesEventBus::<DuplicateChunksDetected>::event_bus(event_bus_provider).emit(event);
+}
+
+/// The current UNIX timestamp, in seconds.
+fn unix_timestamp_secs() -> u64 {
+    chrono::Utc::now().timestamp().try_into().unwrap_or(0)
+}
+
 #[derive(Eq)]
 pub struct BucketIdWithExpiration {
     bucket_id: BucketId,
@@ -122,6 +171,10 @@ pub struct FileTransferService {
         HashMap<UploadRequestId, futures::channel::oneshot::Sender<OutgoingResponse>>,
     /// Counter for generating unique upload request IDs
     upload_pending_response_nonce: UploadRequestId,
+    /// Duplicate chunks sent by each peer for each file, within the current window.
+    duplicate_chunks: DuplicateChunksTracker,
+    /// Retrieval authorizations used so far, which are rejected if used again.
+    used_retrieval_authorizations: UsedRetrievalAuthorizations,
 }
 
 impl Actor for FileTransferService {
@@ -237,6 +290,7 @@ impl Actor for FileTransferService {
                     file_key,
                     chunk_ids,
                     bucket_id,
+                    retrieval_authorization,
                     callback,
                 } => {
                     // Calculate max chunks based on packet size and chunk size
@@ -260,6 +314,8 @@ impl Actor for FileTransferService {
                             file_key: file_key.encode(),
                             file_chunk_ids: chunk_ids_u64,
                             bucket_id: bucket_id.map(|id| id.encode()),
+                            retrieval_authorization: retrieval_authorization
+                                .map(|authorization| authorization.encode()),
                         },
                     );
 
@@ -342,6 +398,41 @@ impl Actor for FileTransferService {
                         ),
                     };
                 }
+                FileTransferServiceCommand::RejectDownloadRequest {
+                    request_id,
+                    callback,
+                } => {
+                    let outgoing_response = OutgoingResponse {
+                        result: Err(()),
+                        reputation_changes: Vec::new(),
+                        sent_feedback: None,
+                    };
+
+                    let request_callback_result =
+                        match self.download_pending_responses.remove(&request_id) {
+                            Some(pending_response_sender) => {
+                                match pending_response_sender.send(outgoing_response) {
+                                    Ok(()) => callback.send(Ok(())),
+                                    Err(e) => {
+                                        error!(
+                                            target: LOG_TARGET,
+                                            "Failed to reject Download Request {:?}", e
+                                        );
+                                        callback.send(Err(RequestError::DownloadResponseFailure(e)))
+                                    }
+                                }
+                            }
+                            None => callback.send(Err(RequestError::DownloadRequestIdNotFound)),
+                        };
+
+                    match request_callback_result {
+                        Ok(()) => {}
+                        Err(_) => error!(
+                            target: LOG_TARGET,
+                            "Failed to send the response back. Looks like the requester task is gone."
+                        ),
+                    };
+                }
                 FileTransferServiceCommand::AddKnownAddress {
                     peer_id,
                     multiaddress,
@@ -357,6 +448,47 @@ impl Actor for FileTransferService {
                         ),
                     }
                 }
+                FileTransferServiceCommand::ReportPeer {
+                    peer_id,
+                    reason,
+                    callback,
+                } => {
+                    self.network.report_peer(
+                        peer_id.into(),
+                        ReputationChange::new(BAD_REQUEST_REPUTATION_CHANGE, reason),
+                    );
+                    // `report_peer()` method doesn't return anything.
+                    match callback.send(Ok(())) {
+                        Ok(()) => {}
+                        Err(_) => error!(
+                            target: LOG_TARGET,
+                            "Failed to send the response back. Looks like the requester task is gone."
+                        ),
+                    }
+                }
+                FileTransferServiceCommand::ReportDuplicateChunks {
+                    peer_id,
+                    file_key,
+                    callback,
+                } => {
+                    let network = &self.network;
+                    record_duplicate_chunks(
+                        &mut self.duplicate_chunks,
+                        &self.event_bus_provider,
+                        peer_id,
+                        file_key,
+                        Instant::now(),
+                        |reputation_change| network.report_peer(peer_id.into(), reputation_change),
+                    );
+
+                    match callback.send(Ok(())) {
+                        Ok(()) => {}
+                        Err(_) => error!(
+                            target: LOG_TARGET,
+                            "Failed to send the response back. Looks like the requester task is gone."
+                        ),
+                    }
+                }
                 FileTransferServiceCommand::RegisterNewFile {
                     peer_id,
                     file_key,
@@ -387,6 +519,7 @@ impl Actor for FileTransferService {
                                 self.peer_file_allow_list.remove(&(*peer_id, file_key));
                             }
                             self.peers_by_file.remove(&file_key);
+                            self.duplicate_chunks.forget_file(&file_key);
                             Ok(())
                         }
                         None => Err(RequestError::FileNotRegistered),
@@ -399,6 +532,34 @@ impl Actor for FileTransferService {
                         ),
                     }
                 }
+                FileTransferServiceCommand::GetActiveUploadCount { callback } => {
+                    match callback.send(self.peers_by_file.len()) {
+                        Ok(()) => {}
+                        Err(_) => error!(
+                            target: LOG_TARGET,
+                            "Failed to send the response back. Looks like the requester task is gone."
+                        ),
+                    }
+                }
+                FileTransferServiceCommand::GetRegisteredFileKeys { callback } => {
+                    let file_keys = self.peers_by_file.keys().copied().collect();
+                    match callback.send(file_keys) {
+                        Ok(()) => {}
+                        Err(_) => error!(
+                            target: LOG_TARGET,
+                            "Failed to send the response back. Looks like the requester task is gone."
+                        ),
+                    }
+                }
+                FileTransferServiceCommand::GetLocalPeerId { callback } => {
+                    match callback.send(self.network.local_peer_id()) {
+                        Ok(()) => {}
+                        Err(_) => error!(
+                            target: LOG_TARGET,
+                            "Failed to send the response back. Looks like the requester task is gone."
+                        ),
+                    }
+                }
                 FileTransferServiceCommand::RegisterNewBucketPeer {
                     peer_id,
                     bucket_id,
@@ -517,6 +678,10 @@ impl ActorEventLoop<FileTransferService> for FileTransferServiceEventLoop {
                 Some(MergedEventLoopMessage::Tick) => {
                     // Handle expired buckets
                     self.actor.handle_expired_buckets();
+                    self.actor.duplicate_chunks.prune(Instant::now());
+                    self.actor
+                        .used_retrieval_authorizations
+                        .prune(unix_timestamp_secs());
                 }
                 None => {
                     warn!(target: LOG_TARGET, "FileTransferService event loop terminated.");
@@ -533,6 +698,7 @@ impl FileTransferService {
         protocol_name: ProtocolName,
         request_receiver: async_channel::Receiver<IncomingRequest>,
         network: Arc<dyn NetworkService>,
+        duplicate_chunks_config: DuplicateChunksConfig,
     ) -> Self {
         Self {
             protocol_name,
@@ -548,6 +714,8 @@ impl FileTransferService {
             download_pending_response_nonce: DownloadRequestId::new(0),
             upload_pending_responses: HashMap::new(),
             upload_pending_response_nonce: UploadRequestId::new(0),
+            duplicate_chunks: DuplicateChunksTracker::new(duplicate_chunks_config),
+            used_retrieval_authorizations: UsedRetrievalAuthorizations::default(),
         }
     }
 
@@ -676,7 +844,43 @@ impl FileTransferService {
                     None => None,
                 };
 
-                if !self.is_allowed(peer, file_key, bucket_id) {
+                // Requests made on behalf of an account are let through without being in the
+                // allow list, as long as the account signed them. The task handling them is then
+                // responsible for checking if the account can read the file.
+                let requester = match r.retrieval_authorization {
+                    Some(ref authorization) => {
+                        let result = RetrievalAuthorization::decode(&mut authorization.as_slice())
+                            .map_err(|e| anyhow::anyhow!("Failed to decode: {:?}", e))
+                            .and_then(|authorization| {
+                                self.used_retrieval_authorizations
+                                    .use_authorization(
+                                        &authorization,
+                                        &file_key,
+                                        &peer,
+                                        unix_timestamp_secs(),
+                                    )
+                                    .map_err(anyhow::Error::from)?;
+                                Ok(authorization)
+                            });
+                        match result {
+                            Ok(authorization) => Some(authorization.requester),
+                            Err(e) => {
+                                warn!(
+                                    target: LOG_TARGET,
+                                    "Received download request from {} for file key {:?} with an invalid retrieval authorization: {}",
+                                    peer, file_key, e
+                                );
+
+                                self.handle_bad_request(pending_response);
+
+                                return;
+                            }
+                        }
+                    }
+                    None => None,
+                };
+
+                if requester.is_none() && !self.is_allowed(peer, file_key, bucket_id) {
                     warn!(
                         target: LOG_TARGET,
                         "Received unexpected download request from {} for file key {:?} (bucket {:?})",
@@ -698,10 +902,12 @@ impl FileTransferService {
                     .insert(request_id.clone(), pending_response);
 
                 self.emit(RemoteDownloadRequest {
+                    peer,
                     file_key,
                     chunk_ids,
                     request_id,
                     bucket_id,
+                    requester,
                 });
             }
             None => {
@@ -734,7 +940,10 @@ impl FileTransferService {
         pending_response: futures::channel::oneshot::Sender<OutgoingResponse>,
     ) {
         debug!(target: LOG_TARGET, "Bad request received. Lowering reputation.");
-        let reputation_changes = vec![ReputationChange::new(-(1 << 12), "bad request")];
+        let reputation_changes = vec![ReputationChange::new(
+            BAD_REQUEST_REPUTATION_CHANGE,
+            "bad request",
+        )];
 
         let response = OutgoingResponse {
             result: Err(()),
@@ -789,3 +998,45 @@ impl FileTransferService {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sp_core::H256;
+
+    #[test]
+    fn duplicate_chunks_past_the_threshold_lower_the_reputation_and_emit_an_event() {
+        let window = Duration::from_secs(60);
+        let mut tracker = DuplicateChunksTracker::new(DuplicateChunksConfig {
+            threshold: 2,
+            window,
+        });
+        let event_bus_provider = FileTransferServiceEventBusProvider::new();
+        let mut events =
+            ProvidesEventBus::<DuplicateChunksDetected>::event_bus(&event_bus_provider).subscribe();
+        let peer_id = sc_network::PeerId::random();
+        let file_key = FileKey::from(H256::repeat_byte(1));
+        let now = Instant::now();
+
+        let mut reputation_changes = Vec::new();
+        for _ in 0..4 {
+            record_duplicate_chunks(
+                &mut tracker,
+                &event_bus_provider,
+                peer_id,
+                file_key,
+                now,
+                |reputation_change| reputation_changes.push(reputation_change.value),
+            );
+        }
+
+        // The peer is reported once, when its third batch exceeds the threshold.
+        assert_eq!(reputation_changes, vec![DUPLICATE_CHUNKS_REPUTATION_CHANGE]);
+        let event = events.try_recv().expect("An event is emitted");
+        assert_eq!(event.peer, peer_id);
+        assert_eq!(event.file_key, file_key);
+        assert_eq!(event.duplicate_batches, 3);
+        assert_eq!(event.window, window);
+        assert!(events.try_recv().is_err());
+    }
+}
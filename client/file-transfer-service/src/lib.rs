@@ -12,16 +12,24 @@ use shc_common::types::{
     BlockHash, OpaqueBlock, ParachainClient, BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE,
 };
 
+use self::duplicates::DuplicateChunksConfig;
+
 pub use self::handler::FileTransferService;
 
 /// For defining the commands processed by the file transfer service.
 pub mod commands;
+/// For tracking the chunks uploaded to this node that it had already stored.
+pub mod duplicates;
 /// For defining the events emitted by the file transfer service.
 pub mod events;
 /// For incoming provider requests.
 pub mod handler;
+/// For authorising the retrieval of files on behalf of an account.
+pub mod retrieval;
 /// For defining the provider requests protocol schema.
 pub mod schema;
+/// For sending the chunks of a file to a provider, retrying the batches it doesn't acknowledge.
+pub mod upload;
 
 /// Maximum memory usage target for queued requests (8GB)
 const MAX_QUEUED_REQUESTS_MEMORY_BYTES: u64 = 8 * 1024 * 1024 * 1024;
@@ -87,12 +95,18 @@ pub async fn spawn_file_transfer_service(
     request_receiver: async_channel::Receiver<IncomingRequest>,
     protocol_name: ProtocolName,
     network: Arc<dyn NetworkService>,
+    duplicate_chunks_config: DuplicateChunksConfig,
 ) -> ActorHandle<FileTransferService> {
     let task_spawner = task_spawner
         .with_name("file-transfer-service")
         .with_group("network");
 
-    let file_transfer_service = FileTransferService::new(protocol_name, request_receiver, network);
+    let file_transfer_service = FileTransferService::new(
+        protocol_name,
+        request_receiver,
+        network,
+        duplicate_chunks_config,
+    );
 
     let file_transfer_service_handle = task_spawner.spawn_actor(file_transfer_service);
 
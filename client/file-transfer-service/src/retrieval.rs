@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use codec::{Decode, Encode};
+use sc_network::PeerId;
+use shc_common::types::FileKey;
+use sp_runtime::{traits::Verify, AccountId32, MultiSignature};
+
+/// Prefix of the payload signed by a requester to authorise a retrieval.
+///
+/// Prevents a signature over a retrieval payload from being valid for any other purpose.
+const RETRIEVAL_PAYLOAD_PREFIX: &[u8] = b"storagehub-retrieval";
+
+/// Maximum number of seconds a [`RetrievalAuthorization`] can be valid for.
+///
+/// Bounds how long its nonce has to be remembered to reject it being used again.
+pub const MAX_RETRIEVAL_AUTHORIZATION_VALIDITY_SECS: u64 = 5 * 60;
+
+/// Proof that a download request is made on behalf of `requester`.
+///
+/// Download requests normally only succeed for peers in the allow list of the provider. Requests
+/// carrying a valid [`RetrievalAuthorization`] are instead let through, so that the task handling
+/// them can check whether `requester` is allowed to read the file (i.e. MSPs serving files to the
+/// users of their buckets).
+///
+/// Each authorization can only be used for a single request, before it expires.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct RetrievalAuthorization {
+    /// The account requesting the file.
+    pub requester: AccountId32,
+    /// UNIX timestamp, in seconds, from which the authorization is no longer valid.
+    pub expires_at: u64,
+    /// Number telling apart the authorizations of `requester`, which must not be repeated until
+    /// the authorization expires.
+    pub nonce: u64,
+    /// Signature of `requester` over the [`RetrievalAuthorization::payload`] of the request.
+    pub signature: MultiSignature,
+}
+
+impl RetrievalAuthorization {
+    /// The payload to sign to retrieve `file_key` from the peer `peer_id`, until `expires_at`.
+    ///
+    /// `peer_id` is the peer making the request, so that the authorization can't be replayed by
+    /// any other peer.
+    pub fn payload(file_key: &FileKey, peer_id: &PeerId, expires_at: u64, nonce: u64) -> Vec<u8> {
+        (
+            RETRIEVAL_PAYLOAD_PREFIX,
+            file_key,
+            peer_id.to_bytes(),
+            expires_at,
+            nonce,
+        )
+            .encode()
+    }
+
+    /// Whether the signature is valid for `requester` retrieving `file_key` as `peer_id`.
+    pub fn verify(&self, file_key: &FileKey, peer_id: &PeerId) -> bool {
+        self.signature.verify(
+            &Self::payload(file_key, peer_id, self.expires_at, self.nonce)[..],
+            &self.requester,
+        )
+    }
+}
+
+/// Reasons for which a [`RetrievalAuthorization`] is not valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum RetrievalAuthorizationError {
+    #[error("Invalid signature")]
+    InvalidSignature,
+    #[error("Authorization expired")]
+    Expired,
+    #[error("Authorization valid for longer than allowed")]
+    ValidForTooLong,
+    #[error("Authorization already used")]
+    AlreadyUsed,
+}
+
+/// The [`RetrievalAuthorization`]s used so far, so that each of them is only used once.
+///
+/// Authorizations are remembered until they expire, as they are rejected from then on anyway.
+#[derive(Debug, Default)]
+pub struct UsedRetrievalAuthorizations {
+    /// Expiration of the authorizations used, by requester and nonce.
+    expirations: HashMap<(AccountId32, u64), u64>,
+}
+
+impl UsedRetrievalAuthorizations {
+    /// Checks that `authorization` is valid for retrieving `file_key` as `peer_id` at `now`, a
+    /// UNIX timestamp in seconds, and marks it as used.
+    pub fn use_authorization(
+        &mut self,
+        authorization: &RetrievalAuthorization,
+        file_key: &FileKey,
+        peer_id: &PeerId,
+        now: u64,
+    ) -> Result<(), RetrievalAuthorizationError> {
+        if authorization.expires_at <= now {
+            return Err(RetrievalAuthorizationError::Expired);
+        }
+        if authorization.expires_at - now > MAX_RETRIEVAL_AUTHORIZATION_VALIDITY_SECS {
+            return Err(RetrievalAuthorizationError::ValidForTooLong);
+        }
+        if !authorization.verify(file_key, peer_id) {
+            return Err(RetrievalAuthorizationError::InvalidSignature);
+        }
+
+        let key = (authorization.requester.clone(), authorization.nonce);
+        match self.expirations.get(&key) {
+            Some(expires_at) if *expires_at > now => Err(RetrievalAuthorizationError::AlreadyUsed),
+            _ => {
+                self.expirations.insert(key, authorization.expires_at);
+                Ok(())
+            }
+        }
+    }
+
+    /// Forgets the authorizations that are expired at `now`.
+    pub fn prune(&mut self, now: u64) {
+        self.expirations.retain(|_, expires_at| *expires_at > now);
+    }
+
+    /// Number of authorizations used that haven't been pruned yet.
+    pub fn len(&self) -> usize {
+        self.expirations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.expirations.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sp_core::{sr25519, Pair};
+
+    const NOW: u64 = 1_000_000;
+
+    fn authorization(
+        pair: &sr25519::Pair,
+        file_key: &FileKey,
+        peer_id: &PeerId,
+        expires_at: u64,
+        nonce: u64,
+    ) -> RetrievalAuthorization {
+        RetrievalAuthorization {
+            requester: pair.public().into(),
+            expires_at,
+            nonce,
+            signature: pair
+                .sign(&RetrievalAuthorization::payload(
+                    file_key, peer_id, expires_at, nonce,
+                ))
+                .into(),
+        }
+    }
+
+    #[test]
+    fn authorization_is_only_valid_for_the_signed_file_and_peer() {
+        let pair = sr25519::Pair::from_string("//Alice", None).unwrap();
+        let file_key = FileKey::from([1u8; 32]);
+        let peer_id = PeerId::random();
+        let authorization = authorization(&pair, &file_key, &peer_id, NOW + 60, 0);
+
+        assert!(authorization.verify(&file_key, &peer_id));
+        assert!(!authorization.verify(&FileKey::from([2u8; 32]), &peer_id));
+        assert!(!authorization.verify(&file_key, &PeerId::random()));
+    }
+
+    #[test]
+    fn authorization_signed_by_another_account_is_rejected() {
+        let alice = sr25519::Pair::from_string("//Alice", None).unwrap();
+        let bob = sr25519::Pair::from_string("//Bob", None).unwrap();
+        let file_key = FileKey::from([1u8; 32]);
+        let peer_id = PeerId::random();
+
+        let mut authorization = authorization(&bob, &file_key, &peer_id, NOW + 60, 0);
+        authorization.requester = alice.public().into();
+
+        assert!(!authorization.verify(&file_key, &peer_id));
+    }
+
+    #[test]
+    fn authorization_with_a_tampered_expiration_is_rejected() {
+        let pair = sr25519::Pair::from_string("//Alice", None).unwrap();
+        let file_key = FileKey::from([1u8; 32]);
+        let peer_id = PeerId::random();
+        let mut used = UsedRetrievalAuthorizations::default();
+
+        let mut authorization = authorization(&pair, &file_key, &peer_id, NOW + 60, 0);
+        authorization.expires_at += 60;
+
+        assert_eq!(
+            used.use_authorization(&authorization, &file_key, &peer_id, NOW),
+            Err(RetrievalAuthorizationError::InvalidSignature)
+        );
+        assert!(used.is_empty());
+    }
+
+    #[test]
+    fn authorization_can_only_be_used_once() {
+        let pair = sr25519::Pair::from_string("//Alice", None).unwrap();
+        let file_key = FileKey::from([1u8; 32]);
+        let peer_id = PeerId::random();
+        let mut used = UsedRetrievalAuthorizations::default();
+
+        let first = authorization(&pair, &file_key, &peer_id, NOW + 60, 0);
+        assert_eq!(
+            used.use_authorization(&first, &file_key, &peer_id, NOW),
+            Ok(())
+        );
+        assert_eq!(
+            used.use_authorization(&first, &file_key, &peer_id, NOW + 1),
+            Err(RetrievalAuthorizationError::AlreadyUsed)
+        );
+
+        // Another nonce makes for another authorization.
+        let second = authorization(&pair, &file_key, &peer_id, NOW + 60, 1);
+        assert_eq!(
+            used.use_authorization(&second, &file_key, &peer_id, NOW + 1),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn expired_authorizations_are_rejected_and_forgotten() {
+        let pair = sr25519::Pair::from_string("//Alice", None).unwrap();
+        let file_key = FileKey::from([1u8; 32]);
+        let peer_id = PeerId::random();
+        let mut used = UsedRetrievalAuthorizations::default();
+
+        let authorization = authorization(&pair, &file_key, &peer_id, NOW + 60, 0);
+        assert_eq!(
+            used.use_authorization(&authorization, &file_key, &peer_id, NOW),
+            Ok(())
+        );
+        assert_eq!(
+            used.use_authorization(&authorization, &file_key, &peer_id, NOW + 60),
+            Err(RetrievalAuthorizationError::Expired)
+        );
+
+        used.prune(NOW + 59);
+        assert_eq!(used.len(), 1);
+        used.prune(NOW + 60);
+        assert!(used.is_empty());
+    }
+
+    #[test]
+    fn authorization_valid_for_too_long_is_rejected() {
+        let pair = sr25519::Pair::from_string("//Alice", None).unwrap();
+        let file_key = FileKey::from([1u8; 32]);
+        let peer_id = PeerId::random();
+        let mut used = UsedRetrievalAuthorizations::default();
+
+        let expires_at = NOW + MAX_RETRIEVAL_AUTHORIZATION_VALIDITY_SECS + 1;
+        let authorization = authorization(&pair, &file_key, &peer_id, expires_at, 0);
+
+        assert_eq!(
+            used.use_authorization(&authorization, &file_key, &peer_id, NOW),
+            Err(RetrievalAuthorizationError::ValidForTooLong)
+        );
+    }
+}
@@ -0,0 +1,627 @@
+//! Sends the chunks of a file to a provider, in batches that are resent until the provider
+//! acknowledges them.
+//!
+//! A provider usually has several peer IDs (one per registered multiaddress). Batches are sent to
+//! one of them at a time, and once it fails repeatedly the batches it did not acknowledge are
+//! sent to the next one.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::{stream::FuturesUnordered, StreamExt};
+use sc_network::{PeerId, RequestFailure};
+use sc_tracing::tracing::{debug, warn};
+use thiserror::Error;
+
+use shc_common::types::{
+    ChunkId, BATCH_CHUNK_FILE_TRANSFER_MAX_CHUNKS, BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE,
+    FILE_CHUNK_SIZE,
+};
+
+use super::commands::RequestError;
+
+const LOG_TARGET: &str = "file-transfer-service";
+
+/// Maximum number of times a batch of chunks is resent to a peer after a transient failure.
+pub const MAX_BATCH_UPLOAD_RETRIES: u32 = 5;
+
+/// Delay before the first retry of a batch upload, doubled on each subsequent retry.
+pub const BATCH_UPLOAD_RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Upper bound of the delay between retries of a batch upload.
+pub const MAX_BATCH_UPLOAD_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Time to wait for a peer to acknowledge a batch before resending it.
+///
+/// Longer than the timeout of the provider requests protocol, so that it only triggers if the
+/// network never reports back.
+pub const BATCH_UPLOAD_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Maximum number of batches sent to a peer that it has not acknowledged yet.
+pub const MAX_UNACKNOWLEDGED_BATCHES: usize = 4;
+
+/// Configuration of the retries and pacing of a [`ChunkUploader`].
+#[derive(Clone, Debug)]
+pub struct ChunkUploadConfig {
+    /// Maximum number of times a batch is resent to the same peer after a transient failure.
+    pub max_retries: u32,
+    /// Delay before the first retry of a batch, doubled on each subsequent retry.
+    pub retry_base_delay: Duration,
+    /// Upper bound of the delay between retries of a batch.
+    pub max_retry_delay: Duration,
+    /// Time to wait for a peer to acknowledge a batch before resending it.
+    pub request_timeout: Duration,
+    /// Maximum number of batches sent to a peer that it has not acknowledged yet.
+    pub max_unacknowledged_batches: usize,
+}
+
+impl Default for ChunkUploadConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: MAX_BATCH_UPLOAD_RETRIES,
+            retry_base_delay: BATCH_UPLOAD_RETRY_BASE_DELAY,
+            max_retry_delay: MAX_BATCH_UPLOAD_RETRY_DELAY,
+            request_timeout: BATCH_UPLOAD_TIMEOUT,
+            max_unacknowledged_batches: MAX_UNACKNOWLEDGED_BATCHES,
+        }
+    }
+}
+
+impl ChunkUploadConfig {
+    /// Delay before retrying a batch for the `retries + 1`-th time.
+    pub fn retry_delay(&self, retries: u32) -> Duration {
+        self.retry_base_delay
+            .saturating_mul(2u32.saturating_pow(retries))
+            .min(self.max_retry_delay)
+    }
+}
+
+/// Statistics of the batches of chunks of an upload sent to a peer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PeerSendStats {
+    /// Number of batches sent, including the retries.
+    pub batches_sent: u64,
+    /// Number of batches acknowledged by the peer.
+    pub batches_acknowledged: u64,
+    /// Number of chunks in the batches acknowledged by the peer.
+    pub chunks_acknowledged: u64,
+    /// Number of times a batch was resent after a transient failure.
+    pub retries: u64,
+    /// Number of batches that were not acknowledged in time.
+    pub timeouts: u64,
+    /// Number of uploads that were moved to another peer after this one failed repeatedly.
+    pub failovers: u64,
+}
+
+/// How the batches of chunks of a file are built and sent to the peers.
+#[async_trait]
+pub trait ChunkUploadTransport: Send + Sync {
+    /// What is sent to the peers for a batch of chunks.
+    type Batch: Clone + Send + Sync;
+
+    /// Builds the batch with the chunks `chunk_ids` of the file being uploaded.
+    async fn batch(&self, chunk_ids: &[ChunkId]) -> anyhow::Result<Self::Batch>;
+
+    /// Sends `batch` to `peer_id`, returning whether the peer now has the entire file.
+    async fn send(&self, peer_id: PeerId, batch: Self::Batch) -> Result<bool, RequestError>;
+}
+
+#[derive(Debug, Error)]
+pub enum ChunkUploadError {
+    /// A batch of chunks could not be built, so the file can't be sent to any peer.
+    #[error("Failed to build batch of chunks: {0:?}")]
+    Batch(anyhow::Error),
+    /// A peer did not acknowledge a batch after all the retries.
+    #[error("Peer {peer_id} did not acknowledge a batch of chunks: {reason}")]
+    PeerFailed { peer_id: PeerId, reason: String },
+    /// None of the peers acknowledged all the batches.
+    #[error("Failed to send {unacknowledged_chunks} chunks to any of the peers")]
+    AllPeersFailed { unacknowledged_chunks: u64 },
+}
+
+/// Reason a batch was not acknowledged.
+enum SendFailure {
+    TimedOut,
+    Request(RequestError),
+}
+
+impl SendFailure {
+    /// Whether the batch may be acknowledged if resent to the same peer.
+    fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            SendFailure::TimedOut
+                | SendFailure::Request(RequestError::RequestFailure(
+                    RequestFailure::Refused | RequestFailure::Network(_)
+                ))
+        )
+    }
+}
+
+/// Outcome of the upload of a file by a [`ChunkUploader`].
+#[derive(Debug)]
+pub struct ChunkUploadReport {
+    /// Whether all the batches were acknowledged by one of the peers.
+    pub result: Result<(), ChunkUploadError>,
+    /// Statistics of the batches sent to each of the peers the upload was sent to.
+    pub peer_stats: HashMap<PeerId, PeerSendStats>,
+}
+
+/// Statistics of the batches sent to each peer during a single upload, shared by the batches in
+/// flight.
+#[derive(Default)]
+struct UploadStats(Mutex<HashMap<PeerId, PeerSendStats>>);
+
+impl UploadStats {
+    fn record(&self, peer_id: PeerId, update: impl FnOnce(&mut PeerSendStats)) {
+        update(
+            self.0
+                .lock()
+                .expect("Chunk upload stats lock poisoned")
+                .entry(peer_id)
+                .or_default(),
+        );
+    }
+
+    fn into_inner(self) -> HashMap<PeerId, PeerSendStats> {
+        self.0
+            .into_inner()
+            .expect("Chunk upload stats lock poisoned")
+    }
+}
+
+/// Sends the chunks of files to providers, keeping track of which batches each peer acknowledged.
+///
+/// The statistics of an upload are returned in its [`ChunkUploadReport`], so they only cover the
+/// peers of that upload and nothing is kept once it is over.
+#[derive(Clone, Default)]
+pub struct ChunkUploader {
+    config: ChunkUploadConfig,
+}
+
+impl ChunkUploader {
+    pub fn new(config: ChunkUploadConfig) -> Self {
+        Self { config }
+    }
+
+    /// Sends `batches` of chunks through `transport` to the first of `peer_ids` that acknowledges
+    /// all of them.
+    ///
+    /// When a peer fails repeatedly, only the batches it did not acknowledge are sent to the next
+    /// one. The upload stops early if a peer signals that it has the entire file.
+    pub async fn upload<T: ChunkUploadTransport>(
+        &self,
+        transport: &T,
+        peer_ids: &[PeerId],
+        batches: Vec<Vec<ChunkId>>,
+    ) -> ChunkUploadReport {
+        let stats = UploadStats::default();
+        let result = self
+            .upload_to_peers(transport, peer_ids, batches, &stats)
+            .await;
+
+        ChunkUploadReport {
+            result,
+            peer_stats: stats.into_inner(),
+        }
+    }
+
+    async fn upload_to_peers<T: ChunkUploadTransport>(
+        &self,
+        transport: &T,
+        peer_ids: &[PeerId],
+        batches: Vec<Vec<ChunkId>>,
+        stats: &UploadStats,
+    ) -> Result<(), ChunkUploadError> {
+        let mut unacknowledged: BTreeMap<usize, Vec<ChunkId>> =
+            batches.into_iter().enumerate().collect();
+
+        for peer_id in peer_ids {
+            match self
+                .upload_to_peer(transport, *peer_id, &mut unacknowledged, stats)
+                .await
+            {
+                Ok(()) => return Ok(()),
+                Err(e @ ChunkUploadError::Batch(_)) => return Err(e),
+                Err(e) => {
+                    stats.record(*peer_id, |stats| stats.failovers += 1);
+                    warn!(
+                        target: LOG_TARGET,
+                        "{}, sending the {} unacknowledged batches to the next peer",
+                        e,
+                        unacknowledged.len()
+                    );
+                }
+            }
+        }
+
+        Err(ChunkUploadError::AllPeersFailed {
+            unacknowledged_chunks: unacknowledged.values().map(|b| b.len() as u64).sum(),
+        })
+    }
+
+    /// Sends the `unacknowledged` batches to `peer_id`, with at most
+    /// [`ChunkUploadConfig::max_unacknowledged_batches`] of them in flight at any time, and
+    /// removes them as they are acknowledged.
+    async fn upload_to_peer<T: ChunkUploadTransport>(
+        &self,
+        transport: &T,
+        peer_id: PeerId,
+        unacknowledged: &mut BTreeMap<usize, Vec<ChunkId>>,
+        stats: &UploadStats,
+    ) -> Result<(), ChunkUploadError> {
+        let mut to_send = unacknowledged.clone().into_iter();
+        let mut in_flight = FuturesUnordered::new();
+
+        loop {
+            while in_flight.len() < self.config.max_unacknowledged_batches.max(1) {
+                let Some((index, chunk_ids)) = to_send.next() else {
+                    break;
+                };
+                in_flight.push(async move {
+                    let result = self.send_batch(transport, peer_id, &chunk_ids, stats).await;
+                    (index, chunk_ids.len() as u64, result)
+                });
+            }
+
+            // Batches still in flight when returning early are dropped, and stay unacknowledged.
+            let Some((index, chunks, result)) = in_flight.next().await else {
+                return Ok(());
+            };
+            let file_complete = result?;
+
+            unacknowledged.remove(&index);
+            stats.record(peer_id, |stats| {
+                stats.batches_acknowledged += 1;
+                stats.chunks_acknowledged += chunks;
+            });
+
+            if file_complete {
+                debug!(target: LOG_TARGET, "Peer {:?} has the entire file", peer_id);
+                unacknowledged.clear();
+                return Ok(());
+            }
+        }
+    }
+
+    /// Builds the batch with `chunk_ids` and sends it to `peer_id` until it is acknowledged,
+    /// retrying transient failures with an exponential backoff.
+    async fn send_batch<T: ChunkUploadTransport>(
+        &self,
+        transport: &T,
+        peer_id: PeerId,
+        chunk_ids: &[ChunkId],
+        stats: &UploadStats,
+    ) -> Result<bool, ChunkUploadError> {
+        let batch = transport
+            .batch(chunk_ids)
+            .await
+            .map_err(ChunkUploadError::Batch)?;
+
+        let mut retries = 0;
+        loop {
+            stats.record(peer_id, |stats| stats.batches_sent += 1);
+
+            let failure = match tokio::time::timeout(
+                self.config.request_timeout,
+                transport.send(peer_id, batch.clone()),
+            )
+            .await
+            {
+                Ok(Ok(file_complete)) => return Ok(file_complete),
+                Ok(Err(e)) => SendFailure::Request(e),
+                Err(_) => {
+                    stats.record(peer_id, |stats| stats.timeouts += 1);
+                    SendFailure::TimedOut
+                }
+            };
+
+            let reason = match &failure {
+                SendFailure::TimedOut => {
+                    format!("not acknowledged within {:?}", self.config.request_timeout)
+                }
+                SendFailure::Request(e) => e.to_string(),
+            };
+
+            if !failure.is_transient() || retries >= self.config.max_retries {
+                return Err(ChunkUploadError::PeerFailed { peer_id, reason });
+            }
+
+            let delay = self.config.retry_delay(retries);
+            retries += 1;
+            stats.record(peer_id, |stats| stats.retries += 1);
+            warn!(
+                target: LOG_TARGET,
+                "Batch upload to peer {:?} failed ({}), retrying in {:?}... (attempt {})",
+                peer_id,
+                reason,
+                delay,
+                retries
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Splits the chunks of a file of `file_size` bytes into batches of at most
+/// [`BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE`] bytes.
+pub fn batch_chunk_ids(file_size: u64) -> Vec<Vec<ChunkId>> {
+    let chunks_count = file_size.div_ceil(FILE_CHUNK_SIZE);
+    let chunks_per_batch = (BATCH_CHUNK_FILE_TRANSFER_MAX_CHUNKS as u64).max(1);
+
+    (0..chunks_count)
+        .step_by(chunks_per_batch as usize)
+        .map(|first| {
+            (first..(first + chunks_per_batch).min(chunks_count))
+                .map(ChunkId::new)
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// How a mocked peer answers the batches sent to it.
+    #[derive(Clone, Copy)]
+    enum Peer {
+        /// Receives every batch, but the response to `drop_percentage` % of them is lost.
+        Flaky { drop_percentage: u64 },
+        /// Acknowledges the first `batches` batches, then refuses all the others.
+        AcknowledgesFirst { batches: usize },
+        /// Never answers.
+        Unresponsive,
+    }
+
+    #[derive(Default)]
+    struct MockState {
+        /// Chunks received by each peer, whether they acknowledged them or not.
+        received: HashMap<PeerId, Vec<ChunkId>>,
+        /// Number of batches acknowledged by each peer.
+        acknowledged: HashMap<PeerId, usize>,
+        sent: u64,
+        in_flight: usize,
+        max_in_flight: usize,
+    }
+
+    /// A transport to peers behaving like [`Peer`]s, which sends the chunk IDs as the batch.
+    struct MockTransport {
+        peers: HashMap<PeerId, Peer>,
+        state: Mutex<MockState>,
+    }
+
+    impl MockTransport {
+        fn new(peers: &[(PeerId, Peer)]) -> Self {
+            Self {
+                peers: peers.iter().cloned().collect(),
+                state: Default::default(),
+            }
+        }
+
+        fn received(&self, peer_id: &PeerId) -> HashSet<ChunkId> {
+            let state = self.state.lock().unwrap();
+            state
+                .received
+                .get(peer_id)
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect()
+        }
+    }
+
+    #[async_trait]
+    impl ChunkUploadTransport for MockTransport {
+        type Batch = Vec<ChunkId>;
+
+        async fn batch(&self, chunk_ids: &[ChunkId]) -> anyhow::Result<Vec<ChunkId>> {
+            Ok(chunk_ids.to_vec())
+        }
+
+        async fn send(&self, peer_id: PeerId, batch: Vec<ChunkId>) -> Result<bool, RequestError> {
+            let sent = {
+                let mut state = self.state.lock().unwrap();
+                state.sent += 1;
+                state.in_flight += 1;
+                state.max_in_flight = state.max_in_flight.max(state.in_flight);
+                state.sent
+            };
+
+            // Give other batches the chance to be in flight at the same time.
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            if matches!(self.peers[&peer_id], Peer::Unresponsive) {
+                return futures::future::pending().await;
+            }
+
+            let mut state = self.state.lock().unwrap();
+            state.in_flight -= 1;
+            let acknowledge = match self.peers[&peer_id] {
+                Peer::Flaky { drop_percentage } => {
+                    // The peer gets the chunks even if its response is lost.
+                    state.received.entry(peer_id).or_default().extend(batch);
+                    // Deterministically spread the lost responses over the requests.
+                    sent * 37 % 100 >= drop_percentage
+                }
+                Peer::AcknowledgesFirst { batches } => {
+                    let acknowledge =
+                        state.acknowledged.get(&peer_id).copied().unwrap_or(0) < batches;
+                    if acknowledge {
+                        state.received.entry(peer_id).or_default().extend(batch);
+                    }
+                    acknowledge
+                }
+                Peer::Unresponsive => unreachable!("Unresponsive peers never answer"),
+            };
+
+            if !acknowledge {
+                return Err(RequestError::RequestFailure(RequestFailure::Refused));
+            }
+            *state.acknowledged.entry(peer_id).or_default() += 1;
+            Ok(false)
+        }
+    }
+
+    fn config() -> ChunkUploadConfig {
+        ChunkUploadConfig {
+            max_retries: 3,
+            retry_base_delay: Duration::from_millis(1),
+            max_retry_delay: Duration::from_millis(4),
+            request_timeout: Duration::from_millis(50),
+            max_unacknowledged_batches: 3,
+        }
+    }
+
+    /// `count` batches of a single chunk each.
+    fn batches(count: u64) -> Vec<Vec<ChunkId>> {
+        (0..count).map(|i| vec![ChunkId::new(i)]).collect()
+    }
+
+    fn chunks(count: u64) -> HashSet<ChunkId> {
+        (0..count).map(ChunkId::new).collect()
+    }
+
+    #[test]
+    fn retries_back_off_exponentially_up_to_a_maximum() {
+        let config = ChunkUploadConfig::default();
+        let delays: Vec<_> = (0..MAX_BATCH_UPLOAD_RETRIES)
+            .map(|retries| config.retry_delay(retries))
+            .collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16].map(Duration::from_secs).to_vec());
+
+        assert_eq!(config.retry_delay(5), MAX_BATCH_UPLOAD_RETRY_DELAY);
+        assert_eq!(config.retry_delay(u32::MAX), MAX_BATCH_UPLOAD_RETRY_DELAY);
+    }
+
+    #[test]
+    fn chunks_are_batched_up_to_the_maximum_batch_size() {
+        let chunks_per_batch = BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE as u64 / FILE_CHUNK_SIZE;
+        let file_size = FILE_CHUNK_SIZE * (chunks_per_batch * 2) + 1;
+
+        let batches = batch_chunk_ids(file_size);
+
+        assert_eq!(
+            batches.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![chunks_per_batch as usize, chunks_per_batch as usize, 1]
+        );
+        assert_eq!(
+            batches.into_iter().flatten().collect::<HashSet<_>>(),
+            chunks(chunks_per_batch * 2 + 1)
+        );
+        assert_eq!(batch_chunk_ids(1), vec![vec![ChunkId::new(0)]]);
+    }
+
+    #[tokio::test]
+    async fn batches_are_resent_until_a_flaky_peer_acknowledges_them() {
+        let peer = PeerId::random();
+        let transport = MockTransport::new(&[(
+            peer,
+            Peer::Flaky {
+                drop_percentage: 30,
+            },
+        )]);
+        // Enough retries for every batch to eventually get through.
+        let uploader = ChunkUploader::new(ChunkUploadConfig {
+            max_retries: 10,
+            ..config()
+        });
+
+        let report = uploader.upload(&transport, &[peer], batches(20)).await;
+        report.result.unwrap();
+
+        assert_eq!(transport.received(&peer), chunks(20));
+        let stats = report.peer_stats[&peer];
+        assert_eq!(stats.batches_acknowledged, 20);
+        assert_eq!(stats.chunks_acknowledged, 20);
+        assert!(stats.retries > 0);
+        assert_eq!(
+            stats.batches_sent,
+            stats.batches_acknowledged + stats.retries
+        );
+        assert_eq!(stats.failovers, 0);
+
+        // Never more batches in flight than the window allows.
+        let max_in_flight = transport.state.lock().unwrap().max_in_flight;
+        assert!(max_in_flight > 1);
+        assert!(max_in_flight <= config().max_unacknowledged_batches);
+    }
+
+    #[tokio::test]
+    async fn unacknowledged_batches_fail_over_to_the_next_peer() {
+        let failing = PeerId::random();
+        let next = PeerId::random();
+        let transport = MockTransport::new(&[
+            (failing, Peer::AcknowledgesFirst { batches: 4 }),
+            (next, Peer::Flaky { drop_percentage: 0 }),
+        ]);
+        let uploader = ChunkUploader::new(config());
+
+        let report = uploader
+            .upload(&transport, &[failing, next], batches(10))
+            .await;
+        report.result.unwrap();
+
+        let stats = report.peer_stats;
+        assert_eq!(stats[&failing].failovers, 1);
+        assert_eq!(stats[&failing].batches_acknowledged, 4);
+        assert_eq!(stats[&next].batches_acknowledged, 6);
+
+        // The chunks acknowledged by the failing peer are not sent again.
+        let acknowledged_by_failing = transport.received(&failing);
+        let received_by_next = transport.received(&next);
+        assert!(acknowledged_by_failing.is_disjoint(&received_by_next));
+        assert_eq!(&acknowledged_by_failing | &received_by_next, chunks(10));
+    }
+
+    #[tokio::test]
+    async fn batches_time_out_when_no_peer_answers() {
+        let peer = PeerId::random();
+        let transport = MockTransport::new(&[(peer, Peer::Unresponsive)]);
+        let uploader = ChunkUploader::new(config());
+
+        let report = uploader.upload(&transport, &[peer], batches(5)).await;
+
+        assert!(matches!(
+            report.result,
+            Err(ChunkUploadError::AllPeersFailed {
+                unacknowledged_chunks: 5
+            })
+        ));
+        let stats = report.peer_stats[&peer];
+        assert!(stats.timeouts > config().max_retries as u64);
+        assert_eq!(stats.batches_acknowledged, 0);
+        assert_eq!(stats.failovers, 1);
+    }
+
+    #[tokio::test]
+    async fn stats_only_cover_the_peers_of_each_upload() {
+        let first = PeerId::random();
+        let second = PeerId::random();
+        let transport = MockTransport::new(&[
+            (first, Peer::Flaky { drop_percentage: 0 }),
+            (second, Peer::Flaky { drop_percentage: 0 }),
+        ]);
+        let uploader = ChunkUploader::new(config());
+
+        let first_report = uploader.upload(&transport, &[first], batches(2)).await;
+        let second_report = uploader.upload(&transport, &[second], batches(3)).await;
+
+        first_report.result.unwrap();
+        second_report.result.unwrap();
+        assert_eq!(
+            first_report.peer_stats.keys().collect::<Vec<_>>(),
+            vec![&first]
+        );
+        assert_eq!(first_report.peer_stats[&first].batches_acknowledged, 2);
+        assert_eq!(
+            second_report.peer_stats.keys().collect::<Vec<_>>(),
+            vec![&second]
+        );
+        assert_eq!(second_report.peer_stats[&second].batches_acknowledged, 3);
+    }
+}
@@ -0,0 +1,99 @@
+//! Compares generating repeated proofs against the same Forest root by copying the Forest
+//! Storage every time, against reusing the snapshot kept in a [`ForestStorageSnapshotCache`].
+//!
+//! Run with `cargo bench -p shc-forest-manager --bench snapshot_cache`.
+
+use std::sync::Arc;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use shc_common::types::{
+    FileMetadata, Fingerprint, HashT, HasherOutT, StorageProofsMerkleTrieLayout,
+};
+use shc_forest_manager::{
+    in_memory::InMemoryForestStorage, snapshot_cache::ForestStorageSnapshotCache,
+    traits::ForestStorage,
+};
+use tokio::sync::RwLock;
+
+type Layout = StorageProofsMerkleTrieLayout;
+
+const FILES_IN_FOREST: u32 = 10_000;
+const PROOFS_PER_ROOT: usize = 10;
+
+fn forest_storage() -> (InMemoryForestStorage<Layout>, Vec<HasherOutT<Layout>>) {
+    let mut forest_storage = InMemoryForestStorage::<Layout>::new();
+
+    let files_metadata = (0..FILES_IN_FOREST)
+        .map(|i| {
+            FileMetadata::new(
+                b"owner".to_vec(),
+                b"bucket".to_vec(),
+                format!("file_{}", i).into_bytes(),
+                1024,
+                Fingerprint::default(),
+            )
+            .expect("Valid file metadata")
+        })
+        .collect::<Vec<_>>();
+
+    forest_storage
+        .insert_files_metadata(&files_metadata)
+        .expect("Failed to insert files metadata");
+
+    let challenges = files_metadata
+        .iter()
+        .take(PROOFS_PER_ROOT)
+        .map(|metadata| metadata.file_key::<HashT<Layout>>())
+        .collect();
+
+    (forest_storage, challenges)
+}
+
+fn repeated_proofs_against_one_root(c: &mut Criterion) {
+    let (forest_storage, challenges) = forest_storage();
+    let root = forest_storage.root();
+
+    let mut group = c.benchmark_group("repeated_proofs_against_one_root");
+
+    group.bench_function("copy_forest_per_proof", |b| {
+        b.iter(|| {
+            for challenge in &challenges {
+                let snapshot = Arc::new(RwLock::new(forest_storage.clone()));
+                let proof = snapshot
+                    .blocking_read()
+                    .generate_proof(vec![*challenge])
+                    .expect("Failed to generate proof");
+                black_box(proof);
+            }
+        })
+    });
+
+    group.bench_function("snapshot_cache", |b| {
+        b.iter_batched(
+            || ForestStorageSnapshotCache::<HasherOutT<Layout>, _>::new(1),
+            |mut cache| {
+                for challenge in &challenges {
+                    let snapshot = match cache.get(&root) {
+                        Some(snapshot) => snapshot,
+                        None => {
+                            let snapshot = Arc::new(RwLock::new(forest_storage.clone()));
+                            cache.insert(root, snapshot.clone());
+                            snapshot
+                        }
+                    };
+                    let proof = snapshot
+                        .blocking_read()
+                        .generate_proof(vec![*challenge])
+                        .expect("Failed to generate proof");
+                    black_box(proof);
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, repeated_proofs_against_one_root);
+criterion_main!(benches);
@@ -1,5 +1,5 @@
 use codec::{Decode, Encode};
-use hash_db::Hasher;
+use hash_db::{HashDB, Hasher, EMPTY_PREFIX};
 use shc_common::types::{FileMetadata, HasherOutT};
 use sp_trie::{recorder::Recorder, MemoryDB, TrieDBBuilder, TrieLayout, TrieMut};
 use trie_db::{Trie, TrieDBMutBuilder};
@@ -23,6 +23,45 @@ impl<T: TrieLayout> InMemoryForestStorage<T> {
 
         Self { root, memdb }
     }
+
+    /// SCALE-encodes the root and the trie nodes of this forest storage, so that it can be
+    /// written to disk and later recovered with [`InMemoryForestStorage::decode_snapshot`].
+    pub fn encode_snapshot(&self) -> Vec<u8> {
+        let nodes: Vec<(Vec<u8>, i32)> = self
+            .memdb
+            .clone()
+            .drain()
+            .into_values()
+            .filter(|(_, rc)| *rc > 0)
+            .collect();
+
+        (self.root.as_ref().to_vec(), nodes).encode()
+    }
+
+    /// Rebuilds a forest storage from a snapshot created by
+    /// [`InMemoryForestStorage::encode_snapshot`].
+    pub fn decode_snapshot(snapshot: &[u8]) -> Result<Self, ErrorT<T>> {
+        let (encoded_root, nodes) = <(Vec<u8>, Vec<(Vec<u8>, i32)>)>::decode(&mut &snapshot[..])?;
+
+        let mut root = HasherOutT::<T>::default();
+        if encoded_root.len() != root.as_ref().len() {
+            return Err(ForestStorageError::FailedToDecodeValue.into());
+        }
+        root.as_mut().copy_from_slice(&encoded_root);
+
+        let mut memdb = MemoryDB::default();
+        for (node, rc) in nodes {
+            for _ in 0..rc {
+                memdb.insert(EMPTY_PREFIX, &node);
+            }
+        }
+
+        if !memdb.contains(&root, EMPTY_PREFIX) {
+            return Err(ForestStorageError::ExpectingRootToBeInStorage.into());
+        }
+
+        Ok(Self { root, memdb })
+    }
 }
 
 impl<T: TrieLayout> Clone for InMemoryForestStorage<T> {
@@ -138,28 +177,36 @@ where
         Ok(())
     }
 
-    fn get_files_by_user(
-        &self,
-        user: &sp_runtime::AccountId32,
-    ) -> Result<Vec<(HasherOutT<T>, FileMetadata)>, ErrorT<T>> {
+    fn get_all_files(&self) -> Result<Vec<(HasherOutT<T>, FileMetadata)>, ErrorT<T>> {
         let trie = TrieDBBuilder::<T>::new(&self.memdb, &self.root).build();
         let mut files = Vec::new();
         let mut trie_iter = trie
             .iter()
             .map_err(|_| ForestStorageError::FailedToCreateTrieIterator)?;
 
-        let encoded_user = user.encode();
-
         while let Some((_, value)) = trie_iter.next().transpose()? {
             let metadata = FileMetadata::decode(&mut &value[..])?;
             let file_key = metadata.file_key::<T::Hash>();
-            if metadata.owner() == &encoded_user {
-                files.push((file_key, metadata));
-            }
+            files.push((file_key, metadata));
         }
 
         Ok(files)
     }
+
+    fn get_files_by_user(
+        &self,
+        user: &sp_runtime::AccountId32,
+    ) -> Result<Vec<(HasherOutT<T>, FileMetadata)>, ErrorT<T>> {
+        let encoded_user = user.encode();
+
+        let files = self
+            .get_all_files()?
+            .into_iter()
+            .filter(|(_, metadata)| metadata.owner() == &encoded_user)
+            .collect();
+
+        Ok(files)
+    }
 }
 
 #[cfg(test)]
@@ -459,4 +506,50 @@ mod tests {
             assert!(!forest_storage.contains_file_key(&key).unwrap());
         }
     }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut forest_storage = InMemoryForestStorage::<StorageProofsMerkleTrieLayout>::new();
+
+        let mut keys = Vec::new();
+        for i in 1..=10 {
+            let file_metadata = FileMetadata::new(
+                "Alice".as_bytes().to_vec(),
+                "bucket".as_bytes().to_vec(),
+                "location".as_bytes().to_vec(),
+                i,
+                Fingerprint::default(),
+            )
+            .unwrap();
+
+            keys.extend(
+                forest_storage
+                    .insert_files_metadata(&[file_metadata])
+                    .unwrap(),
+            );
+        }
+        // Deleted file keys should not be in the restored forest storage.
+        let deleted = keys.pop().unwrap();
+        forest_storage.delete_file_key(&deleted).unwrap();
+
+        let restored = InMemoryForestStorage::<StorageProofsMerkleTrieLayout>::decode_snapshot(
+            &forest_storage.encode_snapshot(),
+        )
+        .unwrap();
+
+        assert_eq!(restored.root(), forest_storage.root());
+        assert_eq!(restored.get_all_files().unwrap().len(), keys.len());
+        for key in &keys {
+            assert!(restored.contains_file_key(key).unwrap());
+        }
+        assert!(!restored.contains_file_key(&deleted).unwrap());
+
+        let snapshot = forest_storage.encode_snapshot();
+        assert!(
+            InMemoryForestStorage::<StorageProofsMerkleTrieLayout>::decode_snapshot(
+                &snapshot[..snapshot.len() - 1]
+            )
+            .is_err()
+        );
+    }
 }
@@ -1,7 +1,10 @@
 mod error;
 pub mod in_memory;
+pub mod proof_cache;
 pub(crate) mod prove;
+pub mod recovery;
 pub mod rocksdb;
+pub mod snapshot_cache;
 pub mod traits;
 pub(crate) mod utils;
 
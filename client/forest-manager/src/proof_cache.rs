@@ -0,0 +1,152 @@
+use std::collections::VecDeque;
+
+use shc_common::types::{ForestProof, HasherOutT};
+use sp_trie::TrieLayout;
+
+/// Default number of forest proofs kept in a [`ForestProofCache`].
+pub const DEFAULT_FOREST_PROOF_CACHE_SIZE: usize = 8;
+
+/// Bounded cache of the forest proofs generated against a single forest root, keyed by the set of
+/// challenges they respond to.
+///
+/// Retrying the submission of a proof would otherwise walk the forest again to generate the very
+/// same proof. A proof is only valid for the root it was generated against, so the whole cache is
+/// cleared as soon as a proof of another root is looked up or inserted.
+pub struct ForestProofCache<T: TrieLayout> {
+    capacity: usize,
+    /// The root of the cached proofs.
+    root: Option<HasherOutT<T>>,
+    /// Cached proofs by their sorted challenges, ordered from most to least recently inserted.
+    entries: VecDeque<(Vec<HasherOutT<T>>, ForestProof<T>)>,
+}
+
+impl<T: TrieLayout> ForestProofCache<T> {
+    /// Creates a cache holding up to `capacity` proofs. A `capacity` of 0 is treated as 1.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            root: None,
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Get the proof of `challenges`, in any order, against the forest root `root`.
+    pub fn get(
+        &mut self,
+        root: &HasherOutT<T>,
+        challenges: &[HasherOutT<T>],
+    ) -> Option<ForestProof<T>> {
+        self.expire_unless_root(root);
+
+        let challenges = sorted(challenges);
+        self.entries
+            .iter()
+            .find(|(cached, _)| *cached == challenges)
+            .map(|(_, proof)| proof.clone())
+    }
+
+    /// Inserts `proof`, the proof of `challenges` against its root, evicting the oldest proof if
+    /// the cache is full.
+    pub fn insert(&mut self, challenges: &[HasherOutT<T>], proof: ForestProof<T>) {
+        self.expire_unless_root(&proof.root);
+
+        let challenges = sorted(challenges);
+        self.entries.retain(|(cached, _)| *cached != challenges);
+        self.entries.push_front((challenges, proof));
+        self.entries.truncate(self.capacity);
+    }
+
+    /// Drops the cached proofs if they are not of `root`, which becomes the root of the cache.
+    fn expire_unless_root(&mut self, root: &HasherOutT<T>) {
+        if self.root.as_ref() != Some(root) {
+            self.entries.clear();
+            self.root = Some(*root);
+        }
+    }
+}
+
+fn sorted<K: Ord + Clone>(challenges: &[K]) -> Vec<K> {
+    let mut challenges = challenges.to_vec();
+    challenges.sort();
+    challenges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codec::Encode;
+    use shc_common::types::{FileMetadata, Fingerprint, StorageProofsMerkleTrieLayout};
+    use sp_core::H256;
+
+    use crate::{in_memory::InMemoryForestStorage, traits::ForestStorage};
+
+    type Layout = StorageProofsMerkleTrieLayout;
+
+    fn forest_with_files(locations: &[&str]) -> InMemoryForestStorage<Layout> {
+        let metadata: Vec<_> = locations
+            .iter()
+            .map(|location| {
+                FileMetadata::new(
+                    b"owner".to_vec(),
+                    b"bucket".to_vec(),
+                    location.as_bytes().to_vec(),
+                    100,
+                    Fingerprint::default(),
+                )
+                .unwrap()
+            })
+            .collect();
+
+        let mut forest = InMemoryForestStorage::<Layout>::new();
+        forest.insert_files_metadata(&metadata).unwrap();
+        forest
+    }
+
+    #[test]
+    fn identical_challenges_hit_the_cache_until_the_root_changes() {
+        let forest = forest_with_files(&["a", "b", "c"]);
+        let challenges = vec![H256::repeat_byte(1), H256::repeat_byte(2)];
+        let mut cache = ForestProofCache::<Layout>::new(DEFAULT_FOREST_PROOF_CACHE_SIZE);
+
+        assert!(cache.get(&forest.root(), &challenges).is_none());
+        let proof = forest.generate_proof(challenges.clone()).unwrap();
+        cache.insert(&challenges, proof.clone());
+
+        // A retry responds to the same challenges, regardless of their order.
+        let reversed: Vec<_> = challenges.iter().rev().copied().collect();
+        let cached = cache
+            .get(&forest.root(), &reversed)
+            .expect("Proof of the same challenges is cached");
+        assert_eq!(cached.encode(), proof.encode());
+        assert!(cache.get(&forest.root(), &[H256::repeat_byte(3)]).is_none());
+
+        // Proofs of the previous root are dropped once it changes.
+        let changed = forest_with_files(&["a", "b", "c", "d"]);
+        assert!(cache.get(&changed.root(), &challenges).is_none());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn oldest_proof_is_evicted() {
+        let forest = forest_with_files(&["a", "b"]);
+        let mut cache = ForestProofCache::<Layout>::new(2);
+
+        for byte in 1..=3 {
+            let challenges = [H256::repeat_byte(byte)];
+            let proof = forest.generate_proof(challenges.to_vec()).unwrap();
+            cache.insert(&challenges, proof);
+        }
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&forest.root(), &[H256::repeat_byte(1)]).is_none());
+        assert!(cache.get(&forest.root(), &[H256::repeat_byte(3)]).is_some());
+    }
+}
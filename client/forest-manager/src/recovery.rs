@@ -0,0 +1,185 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use hash_db::Hasher;
+use shc_common::types::{FileMetadata, HasherOutT};
+use trie_db::TrieLayout;
+
+use crate::{error::ErrorT, in_memory::InMemoryForestStorage, traits::ForestStorage};
+
+/// The changes made to a Forest by [`reconcile_forest`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct ForestReconciliation<K> {
+    /// File keys that were in the Forest but not in the authoritative set of files.
+    pub removed: Vec<K>,
+    /// File keys that were in the authoritative set of files but missing from the Forest.
+    pub inserted: Vec<K>,
+}
+
+impl<K> ForestReconciliation<K> {
+    /// Whether the Forest already held exactly the authoritative set of files.
+    pub fn is_noop(&self) -> bool {
+        self.removed.is_empty() && self.inserted.is_empty()
+    }
+}
+
+/// Computes the root of a Forest holding exactly `files`, without touching any existing Forest.
+///
+/// Used to check that a set of files rebuilt from authoritative data matches the on-chain root
+/// before reconciling the local Forest with it.
+pub fn forest_root_of<T: TrieLayout>(files: &[FileMetadata]) -> Result<HasherOutT<T>, ErrorT<T>>
+where
+    <T::Hash as Hasher>::Out: TryFrom<[u8; 32]>,
+{
+    let mut forest = InMemoryForestStorage::<T>::new();
+    forest.insert_files_metadata(&dedup_by_file_key::<T>(files))?;
+    Ok(forest.root())
+}
+
+/// Makes `forest` hold exactly `files`, removing the file keys that are not in `files` and
+/// inserting the missing ones.
+///
+/// Only the differences are applied, so the files that are both in the Forest and in `files`
+/// are left untouched.
+pub fn reconcile_forest<T, FS>(
+    forest: &mut FS,
+    files: &[FileMetadata],
+) -> Result<ForestReconciliation<HasherOutT<T>>, ErrorT<T>>
+where
+    T: TrieLayout,
+    FS: ForestStorage<T>,
+{
+    let expected: BTreeMap<HasherOutT<T>, &FileMetadata> = files
+        .iter()
+        .map(|metadata| (metadata.file_key::<T::Hash>(), metadata))
+        .collect();
+    let current: BTreeSet<HasherOutT<T>> = forest
+        .get_all_files()?
+        .into_iter()
+        .map(|(file_key, _)| file_key)
+        .collect();
+
+    let removed: Vec<_> = current
+        .iter()
+        .filter(|file_key| !expected.contains_key(*file_key))
+        .copied()
+        .collect();
+    let (inserted, missing): (Vec<_>, Vec<_>) = expected
+        .iter()
+        .filter(|(file_key, _)| !current.contains(*file_key))
+        .map(|(file_key, metadata)| (*file_key, (*metadata).clone()))
+        .unzip();
+
+    for file_key in &removed {
+        forest.delete_file_key(file_key)?;
+    }
+    forest.insert_files_metadata(&missing)?;
+
+    Ok(ForestReconciliation { removed, inserted })
+}
+
+/// Removes the repeated files from `files`, keeping their first occurrence.
+fn dedup_by_file_key<T: TrieLayout>(files: &[FileMetadata]) -> Vec<FileMetadata> {
+    let mut seen = BTreeSet::new();
+    files
+        .iter()
+        .filter(|metadata| seen.insert(metadata.file_key::<T::Hash>()))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use kvdb_memorydb::InMemory;
+    use shc_common::types::{Fingerprint, StorageProofsMerkleTrieLayout};
+
+    use super::*;
+    use crate::rocksdb::{RocksDBForestStorage, StorageDb};
+
+    type Layout = StorageProofsMerkleTrieLayout;
+
+    fn file(index: u8) -> FileMetadata {
+        FileMetadata::new(
+            b"owner".to_vec(),
+            b"bucket".to_vec(),
+            format!("location_{}", index).into_bytes(),
+            100 + index as u64,
+            Fingerprint::from([index; 32]),
+        )
+        .unwrap()
+    }
+
+    /// Checks that reconciling a corrupted forest with the files stored on-chain brings it back
+    /// to the on-chain root.
+    fn assert_reconciliation_converges<FS: ForestStorage<Layout>>(mut forest: FS) {
+        let on_chain_files: Vec<_> = (0..10).map(file).collect();
+        let on_chain_root = forest_root_of::<Layout>(&on_chain_files).unwrap();
+
+        // Corrupt the forest: one file missing, one file that is not stored on-chain.
+        forest.insert_files_metadata(&on_chain_files[1..]).unwrap();
+        let unexpected_file = file(42);
+        forest
+            .insert_files_metadata(&[unexpected_file.clone()])
+            .unwrap();
+        assert_ne!(forest.root(), on_chain_root);
+
+        let reconciliation = reconcile_forest(&mut forest, &on_chain_files).unwrap();
+
+        assert_eq!(forest.root(), on_chain_root);
+        assert_eq!(
+            reconciliation,
+            ForestReconciliation {
+                removed: vec![unexpected_file.file_key::<<Layout as TrieLayout>::Hash>()],
+                inserted: vec![on_chain_files[0].file_key::<<Layout as TrieLayout>::Hash>()],
+            }
+        );
+
+        // Reconciling a forest that is already in sync changes nothing.
+        assert!(reconcile_forest(&mut forest, &on_chain_files)
+            .unwrap()
+            .is_noop());
+        assert_eq!(forest.root(), on_chain_root);
+    }
+
+    #[test]
+    fn reconciling_in_memory_forest_converges_to_on_chain_root() {
+        assert_reconciliation_converges(InMemoryForestStorage::<Layout>::new());
+    }
+
+    #[test]
+    fn reconciling_rocksdb_forest_converges_to_on_chain_root() {
+        let storage = StorageDb {
+            db: Arc::new(kvdb_memorydb::create(1)),
+            _phantom: Default::default(),
+        };
+        assert_reconciliation_converges(
+            RocksDBForestStorage::<Layout, InMemory>::new(storage).unwrap(),
+        );
+    }
+
+    #[test]
+    fn reconciling_with_no_files_empties_the_forest() {
+        let mut forest = InMemoryForestStorage::<Layout>::new();
+        forest
+            .insert_files_metadata(&(0..3).map(file).collect::<Vec<_>>())
+            .unwrap();
+
+        let reconciliation = reconcile_forest(&mut forest, &[]).unwrap();
+
+        assert_eq!(reconciliation.removed.len(), 3);
+        assert!(reconciliation.inserted.is_empty());
+        assert_eq!(forest.root(), InMemoryForestStorage::<Layout>::new().root());
+    }
+
+    #[test]
+    fn forest_root_of_ignores_repeated_files() {
+        let files: Vec<_> = (0..3).map(file).collect();
+        let repeated = [files.clone(), files.clone()].concat();
+
+        assert_eq!(
+            forest_root_of::<Layout>(&files).unwrap(),
+            forest_root_of::<Layout>(&repeated).unwrap()
+        );
+    }
+}
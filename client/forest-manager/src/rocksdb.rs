@@ -448,10 +448,7 @@ where
         Ok(())
     }
 
-    fn get_files_by_user(
-        &self,
-        user: &sp_runtime::AccountId32,
-    ) -> Result<Vec<(HasherOutT<T>, FileMetadata)>, ErrorT<T>> {
+    fn get_all_files(&self) -> Result<Vec<(HasherOutT<T>, FileMetadata)>, ErrorT<T>> {
         let db = self.as_hash_db();
         let trie = TrieDBBuilder::<T>::new(&db, &self.root).build();
         let mut files = Vec::new();
@@ -462,13 +459,26 @@ where
         while let Some((_, value)) = trie_iter.next().transpose()? {
             let metadata = FileMetadata::decode(&mut &value[..])?;
             let file_key = metadata.file_key::<T::Hash>();
-            if metadata.owner() == &user.encode() {
-                files.push((file_key, metadata));
-            }
+            files.push((file_key, metadata));
         }
 
         Ok(files)
     }
+
+    fn get_files_by_user(
+        &self,
+        user: &sp_runtime::AccountId32,
+    ) -> Result<Vec<(HasherOutT<T>, FileMetadata)>, ErrorT<T>> {
+        let encoded_user = user.encode();
+
+        let files = self
+            .get_all_files()?
+            .into_iter()
+            .filter(|(_, metadata)| metadata.owner() == &encoded_user)
+            .collect();
+
+        Ok(files)
+    }
 }
 
 #[cfg(test)]
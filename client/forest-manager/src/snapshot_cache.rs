@@ -0,0 +1,158 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use tokio::sync::RwLock;
+
+/// Default number of forest storage snapshots kept in a [`ForestStorageSnapshotCache`].
+pub const DEFAULT_FOREST_SNAPSHOT_CACHE_SIZE: usize = 8;
+
+/// Bounded least-recently-used cache of forest storage snapshots, keyed by their root.
+///
+/// Generating several proofs against the same root (common within a challenge window) would
+/// otherwise rebuild the same snapshot every time. Snapshots that are still referenced outside of
+/// the cache (i.e. by an in-flight task) are never evicted, so the cache can temporarily hold more
+/// than `capacity` snapshots until those references are dropped.
+#[derive(Debug)]
+pub struct ForestStorageSnapshotCache<R, FS> {
+    capacity: usize,
+    /// Cached snapshots, ordered from most to least recently used.
+    entries: VecDeque<(R, Arc<RwLock<FS>>)>,
+}
+
+impl<R, FS> ForestStorageSnapshotCache<R, FS>
+where
+    R: PartialEq,
+{
+    /// Creates a cache holding up to `capacity` snapshots. A `capacity` of 0 is treated as 1.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Get the snapshot with root `root`, marking it as the most recently used.
+    pub fn get(&mut self, root: &R) -> Option<Arc<RwLock<FS>>> {
+        let position = self.entries.iter().position(|(r, _)| r == root)?;
+        let entry = self.entries.remove(position)?;
+        let fs = entry.1.clone();
+        self.entries.push_front(entry);
+
+        Some(fs)
+    }
+
+    /// Inserts the snapshot `fs` with root `root` as the most recently used one, replacing any
+    /// snapshot previously cached for `root`.
+    ///
+    /// Returns the snapshots evicted to make room for it, so that the caller can release any
+    /// resource backing them.
+    pub fn insert(&mut self, root: R, fs: Arc<RwLock<FS>>) -> Vec<(R, Arc<RwLock<FS>>)> {
+        let mut evicted = Vec::new();
+        if let Some(position) = self.entries.iter().position(|(r, _)| *r == root) {
+            evicted.extend(self.entries.remove(position));
+        }
+        self.entries.push_front((root, fs));
+
+        evicted.extend(self.evict_unused());
+        evicted
+    }
+
+    /// Evicts the least recently used snapshots not referenced outside of the cache, until the
+    /// cache is back within its capacity.
+    pub fn evict_unused(&mut self) -> Vec<(R, Arc<RwLock<FS>>)> {
+        let mut evicted = Vec::new();
+        while self.entries.len() > self.capacity {
+            // The most recently used snapshot is never evicted, as it is the one just used.
+            let Some(position) = self
+                .entries
+                .iter()
+                .enumerate()
+                .skip(1)
+                .rev()
+                .find(|(_, (_, fs))| Arc::strong_count(fs) == 1)
+                .map(|(position, _)| position)
+            else {
+                // Every snapshot is in use, try again on the next insertion.
+                break;
+            };
+            evicted.extend(self.entries.remove(position));
+        }
+
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(value: u32) -> Arc<RwLock<u32>> {
+        Arc::new(RwLock::new(value))
+    }
+
+    #[test]
+    fn least_recently_used_snapshot_is_evicted() {
+        let mut cache = ForestStorageSnapshotCache::new(2);
+
+        assert!(cache.insert(1, snapshot(1)).is_empty());
+        assert!(cache.insert(2, snapshot(2)).is_empty());
+
+        // Using `1` makes `2` the least recently used snapshot.
+        assert!(cache.get(&1).is_some());
+
+        let evicted = cache.insert(3, snapshot(3));
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].0, 2);
+
+        assert!(cache.get(&1).is_some());
+        assert!(cache.get(&2).is_none());
+        assert!(cache.get(&3).is_some());
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn snapshots_in_use_are_not_evicted() {
+        let mut cache = ForestStorageSnapshotCache::new(1);
+
+        cache.insert(1, snapshot(1));
+        let in_use = cache.get(&1).unwrap();
+
+        // The only candidate for eviction is still in use, so the cache grows over capacity.
+        assert!(cache.insert(2, snapshot(2)).is_empty());
+        let evicted = cache.insert(3, snapshot(3));
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].0, 2);
+        assert_eq!(cache.len(), 2);
+
+        // Once the in-flight task is done with it, the snapshot can be evicted.
+        drop(in_use);
+        let evicted = cache.evict_unused();
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].0, 1);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get(&3).is_some());
+    }
+
+    #[test]
+    fn inserting_a_cached_root_replaces_the_snapshot() {
+        let mut cache = ForestStorageSnapshotCache::new(2);
+
+        cache.insert(1, snapshot(1));
+        let evicted = cache.insert(1, snapshot(10));
+
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(*cache.get(&1).unwrap().try_read().unwrap(), 10);
+    }
+}
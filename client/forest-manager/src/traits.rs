@@ -33,6 +33,8 @@ pub trait ForestStorage<T: TrieLayout>: 'static {
     ) -> Result<Vec<HasherOutT<T>>, ErrorT<T>>;
     /// Delete a file key.
     fn delete_file_key(&mut self, file_key: &HasherOutT<T>) -> Result<(), ErrorT<T>>;
+    /// Get all the files in the forest.
+    fn get_all_files(&self) -> Result<Vec<(HasherOutT<T>, FileMetadata)>, ErrorT<T>>;
     /// Get all the files that belong to a particular user.
     fn get_files_by_user(
         &self,
@@ -68,6 +70,18 @@ pub trait ForestStorageHandler {
         dest_key: &Self::Key,
     ) -> Option<Arc<RwLock<Self::FS>>>;
 
+    /// Get a snapshot of the forest storage instance for `key` at `forest_root`.
+    ///
+    /// Snapshots are cached by root, so that repeatedly generating proofs against the same root
+    /// reuses the same snapshot instead of copying the forest storage every time.
+    /// Returns `None` if no snapshot is cached for `forest_root` and the forest storage instance
+    /// for `key` doesn't exist or its root is not `forest_root`.
+    async fn snapshot_by_root(
+        &self,
+        key: &Self::Key,
+        forest_root: &HasherOutT<StorageProofsMerkleTrieLayout>,
+    ) -> Option<Arc<RwLock<Self::FS>>>;
+
     /// Get or create forest storage instance.
     async fn get_or_create(&mut self, key: &Self::Key) -> Arc<RwLock<Self::FS>> {
         if let Some(forest_storage) = self.get(key).await {
@@ -76,4 +90,17 @@ pub trait ForestStorageHandler {
             self.create(key).await
         }
     }
+    /// Write all the forest storage instances to disk, so that they survive a restart of the node.
+    ///
+    /// Does nothing by default, as forest storages backed by a database are already persisted.
+    async fn persist_all(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Load the forest storage instances written to disk by [`ForestStorageHandler::persist_all`].
+    ///
+    /// Does nothing by default, as forest storages backed by a database are already persisted.
+    async fn restore_all(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
@@ -0,0 +1,2 @@
+-- Remove deletion_queued_at_block column from file table
+ALTER TABLE file DROP COLUMN deletion_queued_at_block;
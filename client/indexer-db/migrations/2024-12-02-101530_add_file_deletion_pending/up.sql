@@ -0,0 +1,2 @@
+-- Track when a file was queued for deletion through a priority challenge
+ALTER TABLE file ADD COLUMN deletion_queued_at_block BIGINT;
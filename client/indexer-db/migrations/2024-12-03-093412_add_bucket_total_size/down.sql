@@ -0,0 +1,2 @@
+-- Remove total_size column from bucket table
+ALTER TABLE bucket DROP COLUMN total_size;
@@ -0,0 +1,2 @@
+-- Track the total size of the files stored in each bucket
+ALTER TABLE bucket ADD COLUMN total_size BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+-- Drop the bucket_move table
+DROP TABLE IF EXISTS bucket_move;
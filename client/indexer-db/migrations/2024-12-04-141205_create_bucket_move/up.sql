@@ -0,0 +1,18 @@
+-- Create BucketMove table
+CREATE TABLE bucket_move (
+    id BIGSERIAL PRIMARY KEY,
+    onchain_bucket_id BYTEA NOT NULL,
+    account VARCHAR NOT NULL,
+    old_msp_id BIGINT,
+    new_msp_id BIGINT NOT NULL,
+    status INTEGER NOT NULL,
+    requested_at_block BIGINT NOT NULL,
+    resolved_at_block BIGINT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (old_msp_id) REFERENCES msp(id) ON DELETE SET NULL,
+    FOREIGN KEY (new_msp_id) REFERENCES msp(id) ON DELETE CASCADE
+);
+
+-- Create index on onchain_bucket_id for faster lookups
+CREATE INDEX idx_bucket_move_onchain_bucket_id ON bucket_move(onchain_bucket_id);
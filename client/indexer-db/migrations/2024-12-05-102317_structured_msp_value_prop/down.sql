@@ -0,0 +1,3 @@
+ALTER TABLE msp DROP COLUMN value_prop_encoded;
+ALTER TABLE msp ALTER COLUMN value_prop TYPE VARCHAR USING COALESCE(value_prop::text, '');
+ALTER TABLE msp ALTER COLUMN value_prop SET NOT NULL;
@@ -0,0 +1,7 @@
+-- Value propositions were previously stored as their debug representation, which can't be decoded
+-- back to a value proposition. They are dropped instead of being kept as JSON strings, so the MSPs
+-- indexed before this migration have no value proposition (NULL), while new rows hold the
+-- structured value proposition.
+ALTER TABLE msp ALTER COLUMN value_prop DROP NOT NULL;
+ALTER TABLE msp ALTER COLUMN value_prop TYPE JSONB USING NULL;
+ALTER TABLE msp ADD COLUMN value_prop_encoded BYTEA;
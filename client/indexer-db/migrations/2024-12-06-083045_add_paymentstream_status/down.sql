@@ -0,0 +1,2 @@
+-- Remove status column from paymentstream table
+ALTER TABLE paymentstream DROP COLUMN status;
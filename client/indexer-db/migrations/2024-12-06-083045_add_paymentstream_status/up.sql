@@ -0,0 +1,2 @@
+-- Track whether the user of each payment stream is able to pay for it
+ALTER TABLE paymentstream ADD COLUMN status INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+-- Drop the checkpoint_challenge table
+DROP TABLE IF EXISTS checkpoint_challenge;
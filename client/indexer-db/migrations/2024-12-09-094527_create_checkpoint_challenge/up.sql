@@ -0,0 +1,13 @@
+-- Create CheckpointChallenge table
+CREATE TABLE checkpoint_challenge (
+    id BIGSERIAL PRIMARY KEY,
+    tick BIGINT NOT NULL,
+    challenged_file_key BYTEA NOT NULL,
+    trie_mutation_type INTEGER NOT NULL,
+    block_number BIGINT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create indexes on tick and challenged_file_key for faster lookups
+CREATE INDEX idx_checkpoint_challenge_tick ON checkpoint_challenge(tick);
+CREATE INDEX idx_checkpoint_challenge_challenged_file_key ON checkpoint_challenge(challenged_file_key);
@@ -0,0 +1,2 @@
+-- Drop the storage_request_response table
+DROP TABLE IF EXISTS storage_request_response;
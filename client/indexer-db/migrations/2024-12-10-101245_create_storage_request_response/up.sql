@@ -0,0 +1,13 @@
+-- Create StorageRequestResponse table
+CREATE TABLE storage_request_response (
+    id BIGSERIAL PRIMARY KEY,
+    file_key BYTEA NOT NULL,
+    accepted BOOLEAN NOT NULL,
+    rejection_reason INTEGER,
+    block_number BIGINT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create indexes on file_key and rejection_reason for faster lookups and aggregations
+CREATE INDEX idx_storage_request_response_file_key ON storage_request_response(file_key);
+CREATE INDEX idx_storage_request_response_rejection_reason ON storage_request_response(rejection_reason);
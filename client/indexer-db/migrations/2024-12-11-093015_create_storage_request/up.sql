@@ -0,0 +1,20 @@
+-- Create StorageRequest table
+CREATE TABLE storage_request (
+    id BIGSERIAL PRIMARY KEY,
+    file_key BYTEA NOT NULL,
+    onchain_bucket_id BYTEA NOT NULL,
+    requester BYTEA NOT NULL,
+    file_size BIGINT NOT NULL,
+    fingerprint BYTEA NOT NULL,
+    location BYTEA NOT NULL,
+    status INTEGER NOT NULL,
+    opened_at_block BIGINT NOT NULL,
+    expires_at_tick BIGINT NOT NULL,
+    closed_at_block BIGINT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create indexes on file_key and requester for faster lookups
+CREATE INDEX idx_storage_request_file_key ON storage_request(file_key);
+CREATE INDEX idx_storage_request_requester ON storage_request(requester);
@@ -0,0 +1,6 @@
+-- Track the account receiving the payments of each provider, which can differ from its owner account
+ALTER TABLE bsp ADD COLUMN payment_account VARCHAR NOT NULL DEFAULT '';
+ALTER TABLE msp ADD COLUMN payment_account VARCHAR NOT NULL DEFAULT '';
+
+CREATE INDEX idx_bsp_payment_account ON bsp(payment_account);
+CREATE INDEX idx_msp_payment_account ON msp(payment_account);
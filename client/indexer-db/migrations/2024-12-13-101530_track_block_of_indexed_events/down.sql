@@ -0,0 +1,28 @@
+-- Remove the block tracking columns from the event tables
+DROP INDEX IF EXISTS idx_storage_request_response_block;
+DROP INDEX IF EXISTS idx_storage_request_block;
+DROP INDEX IF EXISTS idx_checkpoint_challenge_block;
+DROP INDEX IF EXISTS idx_bucket_move_block;
+DROP INDEX IF EXISTS idx_paymentstream_block;
+DROP INDEX IF EXISTS idx_file_block;
+DROP INDEX IF EXISTS idx_bucket_block;
+DROP INDEX IF EXISTS idx_msp_block;
+DROP INDEX IF EXISTS idx_bsp_block;
+
+ALTER TABLE service_state DROP COLUMN last_processed_block_hash;
+
+ALTER TABLE storage_request_response DROP COLUMN block_hash;
+ALTER TABLE storage_request DROP COLUMN block_hash;
+ALTER TABLE checkpoint_challenge DROP COLUMN block_hash;
+ALTER TABLE bucket_move DROP COLUMN block_hash;
+ALTER TABLE paymentstream DROP COLUMN block_hash;
+ALTER TABLE file DROP COLUMN block_hash;
+ALTER TABLE bucket DROP COLUMN block_hash;
+ALTER TABLE msp DROP COLUMN block_hash;
+ALTER TABLE bsp DROP COLUMN block_hash;
+
+ALTER TABLE paymentstream DROP COLUMN block_number;
+ALTER TABLE file DROP COLUMN block_number;
+ALTER TABLE bucket DROP COLUMN block_number;
+ALTER TABLE msp DROP COLUMN block_number;
+ALTER TABLE bsp DROP COLUMN block_number;
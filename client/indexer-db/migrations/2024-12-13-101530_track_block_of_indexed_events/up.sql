@@ -0,0 +1,33 @@
+-- Track the block at which each row of the event tables was inserted, so that the rows inserted
+-- for the blocks of a fork can be reverted when it is orphaned by a reorg.
+-- Rows indexed before this migration come from finalized blocks, so they keep an empty hash and
+-- are never reverted.
+ALTER TABLE bsp ADD COLUMN block_number BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE msp ADD COLUMN block_number BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE bucket ADD COLUMN block_number BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE file ADD COLUMN block_number BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE paymentstream ADD COLUMN block_number BIGINT NOT NULL DEFAULT 0;
+-- bucket_move and storage_request already track it in requested_at_block and opened_at_block.
+
+ALTER TABLE bsp ADD COLUMN block_hash BYTEA NOT NULL DEFAULT ''::bytea;
+ALTER TABLE msp ADD COLUMN block_hash BYTEA NOT NULL DEFAULT ''::bytea;
+ALTER TABLE bucket ADD COLUMN block_hash BYTEA NOT NULL DEFAULT ''::bytea;
+ALTER TABLE file ADD COLUMN block_hash BYTEA NOT NULL DEFAULT ''::bytea;
+ALTER TABLE paymentstream ADD COLUMN block_hash BYTEA NOT NULL DEFAULT ''::bytea;
+ALTER TABLE bucket_move ADD COLUMN block_hash BYTEA NOT NULL DEFAULT ''::bytea;
+ALTER TABLE checkpoint_challenge ADD COLUMN block_hash BYTEA NOT NULL DEFAULT ''::bytea;
+ALTER TABLE storage_request ADD COLUMN block_hash BYTEA NOT NULL DEFAULT ''::bytea;
+ALTER TABLE storage_request_response ADD COLUMN block_hash BYTEA NOT NULL DEFAULT ''::bytea;
+
+-- Track the hash of the last processed block, to find out whether it was orphaned by a reorg.
+ALTER TABLE service_state ADD COLUMN last_processed_block_hash BYTEA NOT NULL DEFAULT ''::bytea;
+
+CREATE INDEX idx_bsp_block ON bsp(block_number, block_hash);
+CREATE INDEX idx_msp_block ON msp(block_number, block_hash);
+CREATE INDEX idx_bucket_block ON bucket(block_number, block_hash);
+CREATE INDEX idx_file_block ON file(block_number, block_hash);
+CREATE INDEX idx_paymentstream_block ON paymentstream(block_number, block_hash);
+CREATE INDEX idx_bucket_move_block ON bucket_move(requested_at_block, block_hash);
+CREATE INDEX idx_checkpoint_challenge_block ON checkpoint_challenge(block_number, block_hash);
+CREATE INDEX idx_storage_request_block ON storage_request(opened_at_block, block_hash);
+CREATE INDEX idx_storage_request_response_block ON storage_request_response(block_number, block_hash);
@@ -0,0 +1,15 @@
+DROP TABLE IF EXISTS storage_request_response;
+DROP TABLE IF EXISTS checkpoint_challenge;
+DROP TABLE IF EXISTS bucket_move;
+DROP TABLE IF EXISTS file_peer_id;
+DROP TABLE IF EXISTS peer_id;
+DROP TABLE IF EXISTS bsp_file;
+DROP TABLE IF EXISTS file;
+DROP TABLE IF EXISTS paymentstream;
+DROP TABLE IF EXISTS bucket;
+DROP TABLE IF EXISTS msp_multiaddress;
+DROP TABLE IF EXISTS msp;
+DROP TABLE IF EXISTS bsp_multiaddress;
+DROP TABLE IF EXISTS bsp;
+DROP TABLE IF EXISTS multiaddress;
+DROP TABLE IF EXISTS service_state;
@@ -0,0 +1,181 @@
+-- SQLite counterpart of the Postgres migrations in `migrations`, creating the tables as they
+-- stand after all of them. SQLite stores integers as 64-bit values, byte strings as BLOBs and JSON
+-- as TEXT. NUMERIC values are read back as 64-bit floats, so very large balances lose precision.
+
+CREATE TABLE service_state (
+    id INTEGER PRIMARY KEY CHECK (id = 1),                          -- Enforce only one row
+    last_processed_block BIGINT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO service_state (id, last_processed_block) VALUES (1, 0);
+
+CREATE TABLE multiaddress (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    address BLOB NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_multiaddress_address ON multiaddress(address);
+
+CREATE TABLE bsp (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    account TEXT NOT NULL,
+    capacity NUMERIC NOT NULL,
+    stake NUMERIC NOT NULL DEFAULT 0,
+    last_tick_proven BIGINT NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    onchain_bsp_id TEXT NOT NULL,
+    merkle_root BLOB NOT NULL
+);
+
+CREATE TABLE bsp_multiaddress (
+    bsp_id BIGINT NOT NULL,
+    multiaddress_id BIGINT NOT NULL,
+    PRIMARY KEY (bsp_id, multiaddress_id),
+    FOREIGN KEY (bsp_id) REFERENCES bsp(id) ON DELETE CASCADE,
+    FOREIGN KEY (multiaddress_id) REFERENCES multiaddress(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_bsp_multiaddress_bsp_id ON bsp_multiaddress(bsp_id);
+
+CREATE TABLE msp (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    account TEXT NOT NULL,
+    capacity NUMERIC NOT NULL,
+    value_prop TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    onchain_msp_id TEXT NOT NULL,
+    value_prop_encoded BLOB
+);
+
+CREATE TABLE msp_multiaddress (
+    msp_id BIGINT NOT NULL,
+    multiaddress_id BIGINT NOT NULL,
+    PRIMARY KEY (msp_id, multiaddress_id),
+    FOREIGN KEY (msp_id) REFERENCES msp(id) ON DELETE CASCADE,
+    FOREIGN KEY (multiaddress_id) REFERENCES multiaddress(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_msp_multiaddress_msp_id ON msp_multiaddress(msp_id);
+
+CREATE TABLE bucket (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    msp_id BIGINT,
+    account TEXT NOT NULL,
+    onchain_bucket_id BLOB NOT NULL,
+    name BLOB NOT NULL,
+    collection_id TEXT,
+    private BOOLEAN NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    merkle_root BLOB NOT NULL,
+    total_size BIGINT NOT NULL DEFAULT 0,
+    FOREIGN KEY (msp_id) REFERENCES msp(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_bucket_msp_id ON bucket(msp_id);
+CREATE INDEX idx_bucket_account ON bucket(account);
+CREATE INDEX idx_bucket_blockchain_id ON bucket(onchain_bucket_id);
+
+CREATE TABLE paymentstream (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    account TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    total_amount_paid NUMERIC NOT NULL DEFAULT 0,
+    last_tick_charged BIGINT NOT NULL DEFAULT 0,
+    charged_at_tick BIGINT NOT NULL DEFAULT 0,
+    status INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX idx_paymentstream_account ON paymentstream(account);
+CREATE INDEX idx_paymentstream_provider ON paymentstream(provider);
+
+CREATE TABLE file (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    account BLOB NOT NULL,
+    file_key BLOB NOT NULL,
+    bucket_id BIGINT NOT NULL,
+    location BLOB NOT NULL,
+    fingerprint BLOB NOT NULL,
+    size BIGINT NOT NULL,
+    step INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    deletion_queued_at_block BIGINT
+);
+
+CREATE INDEX idx_file_file_key ON file(file_key);
+CREATE INDEX idx_file_bucket_id ON file(bucket_id);
+
+CREATE TABLE bsp_file (
+    bsp_id BIGINT NOT NULL,
+    file_id BIGINT NOT NULL,
+    PRIMARY KEY (bsp_id, file_id),
+    FOREIGN KEY (file_id) REFERENCES file(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_bsp_file_bsp_id ON bsp_file(bsp_id);
+CREATE INDEX idx_bsp_file_file_id ON bsp_file(file_id);
+
+CREATE TABLE peer_id (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    peer BLOB NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE file_peer_id (
+    file_id BIGINT NOT NULL,
+    peer_id BIGINT NOT NULL,
+    PRIMARY KEY (file_id, peer_id),
+    FOREIGN KEY (file_id) REFERENCES file(id) ON DELETE CASCADE,
+    FOREIGN KEY (peer_id) REFERENCES peer_id(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_file_peer_id_file_id ON file_peer_id(file_id);
+
+CREATE TABLE bucket_move (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    onchain_bucket_id BLOB NOT NULL,
+    account TEXT NOT NULL,
+    old_msp_id BIGINT,
+    new_msp_id BIGINT NOT NULL,
+    status INTEGER NOT NULL,
+    requested_at_block BIGINT NOT NULL,
+    resolved_at_block BIGINT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (old_msp_id) REFERENCES msp(id) ON DELETE SET NULL,
+    FOREIGN KEY (new_msp_id) REFERENCES msp(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_bucket_move_onchain_bucket_id ON bucket_move(onchain_bucket_id);
+
+CREATE TABLE checkpoint_challenge (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    tick BIGINT NOT NULL,
+    challenged_file_key BLOB NOT NULL,
+    trie_mutation_type INTEGER NOT NULL,
+    block_number BIGINT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_checkpoint_challenge_tick ON checkpoint_challenge(tick);
+CREATE INDEX idx_checkpoint_challenge_challenged_file_key ON checkpoint_challenge(challenged_file_key);
+
+CREATE TABLE storage_request_response (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    file_key BLOB NOT NULL,
+    accepted BOOLEAN NOT NULL,
+    rejection_reason INTEGER,
+    block_number BIGINT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_storage_request_response_file_key ON storage_request_response(file_key);
+CREATE INDEX idx_storage_request_response_rejection_reason ON storage_request_response(rejection_reason);
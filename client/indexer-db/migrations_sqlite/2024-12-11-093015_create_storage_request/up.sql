@@ -0,0 +1,18 @@
+CREATE TABLE storage_request (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    file_key BLOB NOT NULL,
+    onchain_bucket_id BLOB NOT NULL,
+    requester BLOB NOT NULL,
+    file_size BIGINT NOT NULL,
+    fingerprint BLOB NOT NULL,
+    location BLOB NOT NULL,
+    status INTEGER NOT NULL,
+    opened_at_block BIGINT NOT NULL,
+    expires_at_tick BIGINT NOT NULL,
+    closed_at_block BIGINT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_storage_request_file_key ON storage_request(file_key);
+CREATE INDEX idx_storage_request_requester ON storage_request(requester);
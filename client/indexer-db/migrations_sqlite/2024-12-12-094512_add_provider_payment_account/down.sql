@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS idx_msp_payment_account;
+DROP INDEX IF EXISTS idx_bsp_payment_account;
+
+ALTER TABLE msp DROP COLUMN payment_account;
+ALTER TABLE bsp DROP COLUMN payment_account;
@@ -0,0 +1,5 @@
+ALTER TABLE bsp ADD COLUMN payment_account TEXT NOT NULL DEFAULT '';
+ALTER TABLE msp ADD COLUMN payment_account TEXT NOT NULL DEFAULT '';
+
+CREATE INDEX idx_bsp_payment_account ON bsp(payment_account);
+CREATE INDEX idx_msp_payment_account ON msp(payment_account);
@@ -0,0 +1,27 @@
+ALTER TABLE bsp ADD COLUMN block_number BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE msp ADD COLUMN block_number BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE bucket ADD COLUMN block_number BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE file ADD COLUMN block_number BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE paymentstream ADD COLUMN block_number BIGINT NOT NULL DEFAULT 0;
+
+ALTER TABLE bsp ADD COLUMN block_hash BLOB NOT NULL DEFAULT X'';
+ALTER TABLE msp ADD COLUMN block_hash BLOB NOT NULL DEFAULT X'';
+ALTER TABLE bucket ADD COLUMN block_hash BLOB NOT NULL DEFAULT X'';
+ALTER TABLE file ADD COLUMN block_hash BLOB NOT NULL DEFAULT X'';
+ALTER TABLE paymentstream ADD COLUMN block_hash BLOB NOT NULL DEFAULT X'';
+ALTER TABLE bucket_move ADD COLUMN block_hash BLOB NOT NULL DEFAULT X'';
+ALTER TABLE checkpoint_challenge ADD COLUMN block_hash BLOB NOT NULL DEFAULT X'';
+ALTER TABLE storage_request ADD COLUMN block_hash BLOB NOT NULL DEFAULT X'';
+ALTER TABLE storage_request_response ADD COLUMN block_hash BLOB NOT NULL DEFAULT X'';
+
+ALTER TABLE service_state ADD COLUMN last_processed_block_hash BLOB NOT NULL DEFAULT X'';
+
+CREATE INDEX idx_bsp_block ON bsp(block_number, block_hash);
+CREATE INDEX idx_msp_block ON msp(block_number, block_hash);
+CREATE INDEX idx_bucket_block ON bucket(block_number, block_hash);
+CREATE INDEX idx_file_block ON file(block_number, block_hash);
+CREATE INDEX idx_paymentstream_block ON paymentstream(block_number, block_hash);
+CREATE INDEX idx_bucket_move_block ON bucket_move(requested_at_block, block_hash);
+CREATE INDEX idx_checkpoint_challenge_block ON checkpoint_challenge(block_number, block_hash);
+CREATE INDEX idx_storage_request_block ON storage_request(opened_at_block, block_hash);
+CREATE INDEX idx_storage_request_response_block ON storage_request_response(block_number, block_hash);
@@ -24,6 +24,11 @@ pub struct Bsp {
     pub updated_at: NaiveDateTime,
     pub onchain_bsp_id: String,
     pub merkle_root: Vec<u8>,
+    /// The account receiving the payments of the BSP, which can differ from its `account`.
+    pub payment_account: String,
+    /// The block at which the BSP signed up.
+    pub block_number: i64,
+    pub block_hash: Vec<u8>,
 }
 
 /// Association table between BSP and MultiAddress
@@ -45,6 +50,9 @@ impl Bsp {
         multiaddresses: Vec<MultiAddress>,
         onchain_bsp_id: String,
         stake: BigDecimal,
+        payment_account: String,
+        block_number: i64,
+        block_hash: Vec<u8>,
     ) -> Result<Self, diesel::result::Error> {
         let bsp = diesel::insert_into(bsp::table)
             .values((
@@ -53,6 +61,9 @@ impl Bsp {
                 bsp::onchain_bsp_id.eq(onchain_bsp_id),
                 bsp::merkle_root.eq(merkle_root),
                 bsp::stake.eq(stake),
+                bsp::payment_account.eq(payment_account),
+                bsp::block_number.eq(block_number),
+                bsp::block_hash.eq(block_hash),
             ))
             .returning(Bsp::as_select())
             .get_result(conn)
@@ -76,6 +87,20 @@ impl Bsp {
         Ok(bsp)
     }
 
+    /// Deletes the BSPs indexed at the block `block_number` with hash `block_hash`.
+    pub async fn delete_inserted_at<'a>(
+        conn: &mut DbConnection<'a>,
+        block_number: i64,
+        block_hash: Vec<u8>,
+    ) -> Result<usize, diesel::result::Error> {
+        let deleted = diesel::delete(bsp::table)
+            .filter(bsp::block_number.eq(block_number))
+            .filter(bsp::block_hash.eq(block_hash))
+            .execute(conn)
+            .await?;
+        Ok(deleted)
+    }
+
     pub async fn delete<'a>(
         conn: &mut DbConnection<'a>,
         account: String,
@@ -122,6 +147,19 @@ impl Bsp {
         Ok(bsp)
     }
 
+    /// Get the BSPs paid to the given account, as several providers can share a payment account.
+    pub async fn get_by_payment_account<'a>(
+        conn: &mut DbConnection<'a>,
+        payment_account: String,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        let bsps = bsp::table
+            .filter(bsp::payment_account.eq(payment_account))
+            .order(bsp::id.asc())
+            .load(conn)
+            .await?;
+        Ok(bsps)
+    }
+
     pub async fn update_stake<'a>(
         conn: &mut DbConnection<'a>,
         onchain_bsp_id: String,
@@ -1,8 +1,13 @@
+use bigdecimal::{BigDecimal, ToPrimitive};
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
 
-use crate::{schema::bucket, DbConnection};
+use crate::{
+    models::FileStorageRequestStep,
+    schema::{bucket, file},
+    DbConnection,
+};
 
 /// Table that holds the Buckets.
 #[derive(Debug, Queryable, Insertable, Selectable)]
@@ -21,6 +26,12 @@ pub struct Bucket {
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
     pub merkle_root: Vec<u8>,
+    /// The total size of the files stored in the bucket. Denormalized from the `file` table,
+    /// see [`Bucket::get_total_stored_size`].
+    pub total_size: i64,
+    /// The block at which the bucket was created.
+    pub block_number: i64,
+    pub block_hash: Vec<u8>,
 }
 
 impl Bucket {
@@ -33,6 +44,8 @@ impl Bucket {
         collection_id: Option<String>,
         private: bool,
         merkle_root: Vec<u8>,
+        block_number: i64,
+        block_hash: Vec<u8>,
     ) -> Result<Self, diesel::result::Error> {
         let bucket = diesel::insert_into(bucket::table)
             .values((
@@ -43,6 +56,8 @@ impl Bucket {
                 bucket::collection_id.eq(collection_id),
                 bucket::private.eq(private),
                 bucket::merkle_root.eq(merkle_root),
+                bucket::block_number.eq(block_number),
+                bucket::block_hash.eq(block_hash),
             ))
             .returning(Bucket::as_select())
             .get_result(conn)
@@ -50,6 +65,20 @@ impl Bucket {
         Ok(bucket)
     }
 
+    /// Deletes the buckets indexed at the block `block_number` with hash `block_hash`.
+    pub async fn delete_inserted_at<'a>(
+        conn: &mut DbConnection<'a>,
+        block_number: i64,
+        block_hash: Vec<u8>,
+    ) -> Result<usize, diesel::result::Error> {
+        let deleted = diesel::delete(bucket::table)
+            .filter(bucket::block_number.eq(block_number))
+            .filter(bucket::block_hash.eq(block_hash))
+            .execute(conn)
+            .await?;
+        Ok(deleted)
+    }
+
     pub async fn update_privacy<'a>(
         conn: &mut DbConnection<'a>,
         account: String,
@@ -97,6 +126,64 @@ impl Bucket {
         Ok(())
     }
 
+    pub async fn update_total_size<'a>(
+        conn: &mut DbConnection<'a>,
+        onchain_bucket_id: Vec<u8>,
+        total_size: i64,
+    ) -> Result<(), diesel::result::Error> {
+        diesel::update(bucket::table)
+            .filter(bucket::onchain_bucket_id.eq(onchain_bucket_id))
+            .set(bucket::total_size.eq(total_size))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Computes the total size of the files stored in the bucket with the given hex-encoded
+    /// on-chain ID, aggregating the size of its files in the [`FileStorageRequestStep::Stored`]
+    /// step.
+    pub async fn get_total_stored_size<'a>(
+        conn: &mut DbConnection<'a>,
+        onchain_bucket_id: &str,
+    ) -> Result<i64, diesel::result::Error> {
+        let onchain_bucket_id = decode_onchain_bucket_id(onchain_bucket_id)?;
+        let total_size: Option<BigDecimal> = file::table
+            .filter(
+                file::bucket_id.eq_any(
+                    bucket::table
+                        .filter(bucket::onchain_bucket_id.eq(onchain_bucket_id))
+                        .select(bucket::id),
+                ),
+            )
+            .filter(file::step.eq(FileStorageRequestStep::Stored as i32))
+            .select(diesel::dsl::sum(file::size))
+            .first(conn)
+            .await?;
+        Ok(total_size.and_then(|size| size.to_i64()).unwrap_or(0))
+    }
+
+    /// Counts the files stored in the bucket with the given hex-encoded on-chain ID, that is, its
+    /// files in the [`FileStorageRequestStep::Stored`] step.
+    pub async fn get_files_count<'a>(
+        conn: &mut DbConnection<'a>,
+        onchain_bucket_id: &str,
+    ) -> Result<i64, diesel::result::Error> {
+        let onchain_bucket_id = decode_onchain_bucket_id(onchain_bucket_id)?;
+        let files_count = file::table
+            .filter(
+                file::bucket_id.eq_any(
+                    bucket::table
+                        .filter(bucket::onchain_bucket_id.eq(onchain_bucket_id))
+                        .select(bucket::id),
+                ),
+            )
+            .filter(file::step.eq(FileStorageRequestStep::Stored as i32))
+            .count()
+            .get_result(conn)
+            .await?;
+        Ok(files_count)
+    }
+
     pub async fn delete<'a>(
         conn: &mut DbConnection<'a>,
         onchain_bucket_id: Vec<u8>,
@@ -119,3 +206,12 @@ impl Bucket {
         Ok(bucket)
     }
 }
+
+/// Decodes a hex-encoded on-chain bucket ID, with or without the `0x` prefix.
+fn decode_onchain_bucket_id(onchain_bucket_id: &str) -> Result<Vec<u8>, diesel::result::Error> {
+    array_bytes::hex2bytes(onchain_bucket_id).map_err(|e| {
+        diesel::result::Error::SerializationError(
+            format!("Invalid on-chain bucket ID {}: {:?}", onchain_bucket_id, e).into(),
+        )
+    })
+}
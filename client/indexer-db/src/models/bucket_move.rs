@@ -0,0 +1,131 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+
+use crate::{schema::bucket_move, DbConnection};
+
+pub enum BucketMoveStatus {
+    Requested = 0,
+    Accepted = 1,
+    Rejected = 2,
+    Expired = 3,
+}
+
+/// Table that holds the requests to move a Bucket to a new MSP, and their outcome.
+#[derive(Debug, Queryable, Insertable, Selectable)]
+#[diesel(table_name = bucket_move)]
+pub struct BucketMove {
+    /// The ID of the bucket move as stored in the database.
+    pub id: i64,
+    pub onchain_bucket_id: Vec<u8>,
+    /// The account that requested the move.
+    pub account: String,
+    /// The ID of the MSP (column in the database) storing the bucket when the move was requested.
+    pub old_msp_id: Option<i64>,
+    /// The ID of the MSP (column in the database) the bucket is requested to be moved to.
+    pub new_msp_id: i64,
+    /// The status of the move. 0 = requested, 1 = accepted, 2 = rejected, 3 = expired.
+    pub status: i32,
+    pub requested_at_block: i64,
+    /// The block at which the move was accepted, rejected or expired, if it was.
+    pub resolved_at_block: Option<i64>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    /// The hash of the block at `requested_at_block`.
+    pub block_hash: Vec<u8>,
+}
+
+impl BucketMove {
+    pub async fn create<'a>(
+        conn: &mut DbConnection<'a>,
+        onchain_bucket_id: Vec<u8>,
+        account: String,
+        old_msp_id: Option<i64>,
+        new_msp_id: i64,
+        requested_at_block: i64,
+        block_hash: Vec<u8>,
+    ) -> Result<Self, diesel::result::Error> {
+        let bucket_move = diesel::insert_into(bucket_move::table)
+            .values((
+                bucket_move::onchain_bucket_id.eq(onchain_bucket_id),
+                bucket_move::account.eq(account),
+                bucket_move::old_msp_id.eq(old_msp_id),
+                bucket_move::new_msp_id.eq(new_msp_id),
+                bucket_move::status.eq(BucketMoveStatus::Requested as i32),
+                bucket_move::requested_at_block.eq(requested_at_block),
+                bucket_move::block_hash.eq(block_hash),
+            ))
+            .returning(BucketMove::as_select())
+            .get_result(conn)
+            .await?;
+        Ok(bucket_move)
+    }
+
+    /// Deletes the bucket moves indexed at the block `block_number` with hash `block_hash`.
+    pub async fn delete_inserted_at<'a>(
+        conn: &mut DbConnection<'a>,
+        block_number: i64,
+        block_hash: Vec<u8>,
+    ) -> Result<usize, diesel::result::Error> {
+        let deleted = diesel::delete(bucket_move::table)
+            .filter(bucket_move::requested_at_block.eq(block_number))
+            .filter(bucket_move::block_hash.eq(block_hash))
+            .execute(conn)
+            .await?;
+        Ok(deleted)
+    }
+
+    /// Sets the terminal `status` of the pending move of the bucket.
+    pub async fn resolve<'a>(
+        conn: &mut DbConnection<'a>,
+        onchain_bucket_id: Vec<u8>,
+        status: BucketMoveStatus,
+        resolved_at_block: i64,
+    ) -> Result<(), diesel::result::Error> {
+        diesel::update(bucket_move::table)
+            .filter(bucket_move::onchain_bucket_id.eq(onchain_bucket_id))
+            .filter(bucket_move::status.eq(BucketMoveStatus::Requested as i32))
+            .set((
+                bucket_move::status.eq(status as i32),
+                bucket_move::resolved_at_block.eq(resolved_at_block),
+            ))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_by_onchain_bucket_id<'a>(
+        conn: &mut DbConnection<'a>,
+        onchain_bucket_id: Vec<u8>,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        let bucket_moves = bucket_move::table
+            .filter(bucket_move::onchain_bucket_id.eq(onchain_bucket_id))
+            .order(bucket_move::id.asc())
+            .load(conn)
+            .await?;
+        Ok(bucket_moves)
+    }
+
+    /// Deletes up to `limit` moves that were resolved before `block_number`.
+    ///
+    /// Moves that are still pending are kept regardless of when they were requested.
+    /// Returns the number of deleted moves.
+    pub async fn delete_resolved_before<'a>(
+        conn: &mut DbConnection<'a>,
+        block_number: i64,
+        limit: i64,
+    ) -> Result<usize, diesel::result::Error> {
+        let ids_to_delete = bucket_move::table
+            .filter(bucket_move::status.ne(BucketMoveStatus::Requested as i32))
+            .filter(bucket_move::resolved_at_block.lt(block_number))
+            .select(bucket_move::id)
+            .order(bucket_move::id.asc())
+            .limit(limit);
+
+        let deleted = diesel::delete(bucket_move::table)
+            .filter(bucket_move::id.eq_any(ids_to_delete))
+            .execute(conn)
+            .await?;
+        Ok(deleted)
+    }
+}
@@ -0,0 +1,116 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+
+use crate::{schema::checkpoint_challenge, DbConnection};
+
+/// What happens to a key of the Forests once challenged in a checkpoint challenge.
+pub enum TrieMutationType {
+    /// The key is kept in the Forests.
+    Retain = 0,
+    /// The key is removed from the Forests (i.e. the file is being deleted).
+    Remove = 1,
+}
+
+/// Table that holds the file keys challenged in each checkpoint challenge, so that Providers can
+/// audit which checkpoint challenges they had to prove.
+#[derive(Debug, Queryable, Insertable, Selectable)]
+#[diesel(table_name = checkpoint_challenge)]
+pub struct CheckpointChallenge {
+    /// The ID of the checkpoint challenge as stored in the database.
+    pub id: i64,
+    /// The challenges ticker at which the checkpoint challenge was issued.
+    pub tick: i64,
+    pub challenged_file_key: Vec<u8>,
+    /// The mutation applied to the key once challenged. 0 = retain, 1 = remove.
+    pub trie_mutation_type: i32,
+    pub block_number: i64,
+    pub created_at: NaiveDateTime,
+    /// The hash of the block at `block_number`.
+    pub block_hash: Vec<u8>,
+}
+
+impl CheckpointChallenge {
+    pub async fn create<'a>(
+        conn: &mut DbConnection<'a>,
+        tick: i64,
+        challenged_file_key: Vec<u8>,
+        trie_mutation_type: TrieMutationType,
+        block_number: i64,
+        block_hash: Vec<u8>,
+    ) -> Result<Self, diesel::result::Error> {
+        let checkpoint_challenge = diesel::insert_into(checkpoint_challenge::table)
+            .values((
+                checkpoint_challenge::tick.eq(tick),
+                checkpoint_challenge::challenged_file_key.eq(challenged_file_key),
+                checkpoint_challenge::trie_mutation_type.eq(trie_mutation_type as i32),
+                checkpoint_challenge::block_number.eq(block_number),
+                checkpoint_challenge::block_hash.eq(block_hash),
+            ))
+            .returning(CheckpointChallenge::as_select())
+            .get_result(conn)
+            .await?;
+        Ok(checkpoint_challenge)
+    }
+
+    /// Deletes the checkpoint challenges indexed at the block `block_number` with hash `block_hash`.
+    pub async fn delete_inserted_at<'a>(
+        conn: &mut DbConnection<'a>,
+        block_number: i64,
+        block_hash: Vec<u8>,
+    ) -> Result<usize, diesel::result::Error> {
+        let deleted = diesel::delete(checkpoint_challenge::table)
+            .filter(checkpoint_challenge::block_number.eq(block_number))
+            .filter(checkpoint_challenge::block_hash.eq(block_hash))
+            .execute(conn)
+            .await?;
+        Ok(deleted)
+    }
+
+    /// Gets the file keys challenged in the checkpoint challenge issued at `tick`.
+    pub async fn get_by_tick<'a>(
+        conn: &mut DbConnection<'a>,
+        tick: i64,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        let checkpoint_challenges = checkpoint_challenge::table
+            .filter(checkpoint_challenge::tick.eq(tick))
+            .order(checkpoint_challenge::id.asc())
+            .load(conn)
+            .await?;
+        Ok(checkpoint_challenges)
+    }
+
+    /// Gets the checkpoint challenges in which `challenged_file_key` was challenged.
+    pub async fn get_by_challenged_file_key<'a>(
+        conn: &mut DbConnection<'a>,
+        challenged_file_key: Vec<u8>,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        let checkpoint_challenges = checkpoint_challenge::table
+            .filter(checkpoint_challenge::challenged_file_key.eq(challenged_file_key))
+            .order(checkpoint_challenge::id.asc())
+            .load(conn)
+            .await?;
+        Ok(checkpoint_challenges)
+    }
+
+    /// Deletes up to `limit` checkpoint challenges issued before `block_number`.
+    ///
+    /// Returns the number of deleted checkpoint challenges.
+    pub async fn delete_before<'a>(
+        conn: &mut DbConnection<'a>,
+        block_number: i64,
+        limit: i64,
+    ) -> Result<usize, diesel::result::Error> {
+        let ids_to_delete = checkpoint_challenge::table
+            .filter(checkpoint_challenge::block_number.lt(block_number))
+            .select(checkpoint_challenge::id)
+            .order(checkpoint_challenge::id.asc())
+            .limit(limit);
+
+        let deleted = diesel::delete(checkpoint_challenge::table)
+            .filter(checkpoint_challenge::id.eq_any(ids_to_delete))
+            .execute(conn)
+            .await?;
+        Ok(deleted)
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{
+    models::{
+        Bsp, Bucket, BucketMove, CheckpointChallenge, File, Msp, PaymentStream, ServiceState,
+        StorageRequest, StorageRequestResponse,
+    },
+    DbConnection,
+};
+
+/// Tables in which indexing an event inserts records, which have to be deleted if the block of
+/// the event is orphaned by a reorg.
+///
+/// Each record keeps the number and hash of the block it was indexed at, so that only the records
+/// of the orphaned fork are deleted. The records of the association tables between them are
+/// deleted along with them, through their `ON DELETE CASCADE` foreign keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventTable {
+    StorageRequestResponse,
+    StorageRequest,
+    CheckpointChallenge,
+    BucketMove,
+    PaymentStream,
+    File,
+    Bucket,
+    Bsp,
+    Msp,
+}
+
+impl EventTable {
+    /// All the event tables, in an order in which no record is deleted before the records
+    /// referencing it.
+    pub const ALL: &'static [EventTable] = &[
+        EventTable::StorageRequestResponse,
+        EventTable::StorageRequest,
+        EventTable::CheckpointChallenge,
+        EventTable::BucketMove,
+        EventTable::PaymentStream,
+        EventTable::File,
+        EventTable::Bucket,
+        EventTable::Bsp,
+        EventTable::Msp,
+    ];
+
+    /// The name of the table in the database.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventTable::StorageRequestResponse => "storage_request_response",
+            EventTable::StorageRequest => "storage_request",
+            EventTable::CheckpointChallenge => "checkpoint_challenge",
+            EventTable::BucketMove => "bucket_move",
+            EventTable::PaymentStream => "paymentstream",
+            EventTable::File => "file",
+            EventTable::Bucket => "bucket",
+            EventTable::Bsp => "bsp",
+            EventTable::Msp => "msp",
+        }
+    }
+
+    /// Deletes the records of the table indexed at the block `block_number` with hash
+    /// `block_hash`.
+    ///
+    /// Returns the number of deleted records.
+    pub async fn delete_inserted_at<'a>(
+        &self,
+        conn: &mut DbConnection<'a>,
+        block_number: i64,
+        block_hash: Vec<u8>,
+    ) -> Result<usize, diesel::result::Error> {
+        match self {
+            EventTable::StorageRequestResponse => {
+                StorageRequestResponse::delete_inserted_at(conn, block_number, block_hash).await
+            }
+            EventTable::StorageRequest => {
+                StorageRequest::delete_inserted_at(conn, block_number, block_hash).await
+            }
+            EventTable::CheckpointChallenge => {
+                CheckpointChallenge::delete_inserted_at(conn, block_number, block_hash).await
+            }
+            EventTable::BucketMove => {
+                BucketMove::delete_inserted_at(conn, block_number, block_hash).await
+            }
+            EventTable::PaymentStream => {
+                PaymentStream::delete_inserted_at(conn, block_number, block_hash).await
+            }
+            EventTable::File => File::delete_inserted_at(conn, block_number, block_hash).await,
+            EventTable::Bucket => Bucket::delete_inserted_at(conn, block_number, block_hash).await,
+            EventTable::Bsp => Bsp::delete_inserted_at(conn, block_number, block_hash).await,
+            EventTable::Msp => Msp::delete_inserted_at(conn, block_number, block_hash).await,
+        }
+    }
+}
+
+/// Reverts the indexing of the `orphaned_blocks` of a fork orphaned by a reorg, given as their
+/// number and hash, where `fork_point` is the number and hash of the last block the fork shares
+/// with the new chain.
+///
+/// The records inserted at the orphaned blocks are deleted from all the [`EventTable`]s. The
+/// events of those blocks may also have updated records indexed before the fork (e.g. the step of
+/// a file or the root of a bucket), which can't be restored from the records left. So if the last
+/// processed block is one of the orphaned blocks, it is moved back to the fork point, for the
+/// blocks of the new chain to be indexed from there and update those records again. Updates that
+/// the new chain does not make again are left as the orphaned fork made them.
+///
+/// Should be run in a transaction. Returns the number of deleted records.
+pub async fn revert_orphaned_blocks<'a>(
+    conn: &mut DbConnection<'a>,
+    orphaned_blocks: &[(i64, Vec<u8>)],
+    fork_point: (i64, Vec<u8>),
+) -> Result<usize, diesel::result::Error> {
+    let mut deleted = 0;
+    for table in EventTable::ALL {
+        for (block_number, block_hash) in orphaned_blocks {
+            deleted += table
+                .delete_inserted_at(conn, *block_number, block_hash.clone())
+                .await?;
+        }
+    }
+
+    let service_state = ServiceState::get(conn).await?;
+    if orphaned_blocks
+        .iter()
+        .any(|(_, block_hash)| *block_hash == service_state.last_processed_block_hash)
+    {
+        let (fork_point_number, fork_point_hash) = fork_point;
+        ServiceState::update(conn, fork_point_number, fork_point_hash).await?;
+    }
+
+    Ok(deleted)
+}
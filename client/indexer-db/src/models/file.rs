@@ -15,6 +15,7 @@ use crate::{
 pub enum FileStorageRequestStep {
     Requested = 0,
     Stored = 1,
+    DeletionPending = 2,
 }
 
 /// Table that holds the Files (both ongoing requests and completed).
@@ -29,10 +30,15 @@ pub struct File {
     pub location: Vec<u8>,
     pub fingerprint: Vec<u8>,
     pub size: i64,
-    /// The step this file is at. 0 = requested, 1 = fulfilled.
+    /// The step this file is at. 0 = requested, 1 = fulfilled, 2 = deletion pending.
     pub step: i32,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    /// The block at which a priority challenge for the deletion of this file was queued, if any.
+    pub deletion_queued_at_block: Option<i64>,
+    /// The block at which the storage request of the file was issued.
+    pub block_number: i64,
+    pub block_hash: Vec<u8>,
 }
 
 /// Association table between File and PeerId
@@ -56,6 +62,8 @@ impl File {
         size: i64,
         step: FileStorageRequestStep,
         peer_ids: Vec<crate::models::PeerId>,
+        block_number: i64,
+        block_hash: Vec<u8>,
     ) -> Result<Self, diesel::result::Error> {
         let file = diesel::insert_into(file::table)
             .values((
@@ -66,6 +74,8 @@ impl File {
                 file::fingerprint.eq(fingerprint.into()),
                 file::size.eq(size),
                 file::step.eq(step as i32),
+                file::block_number.eq(block_number),
+                file::block_hash.eq(block_hash),
             ))
             .returning(File::as_select())
             .get_result(conn)
@@ -89,6 +99,20 @@ impl File {
         Ok(file)
     }
 
+    /// Deletes the files indexed at the block `block_number` with hash `block_hash`.
+    pub async fn delete_inserted_at<'a>(
+        conn: &mut DbConnection<'a>,
+        block_number: i64,
+        block_hash: Vec<u8>,
+    ) -> Result<usize, diesel::result::Error> {
+        let deleted = diesel::delete(file::table)
+            .filter(file::block_number.eq(block_number))
+            .filter(file::block_hash.eq(block_hash))
+            .execute(conn)
+            .await?;
+        Ok(deleted)
+    }
+
     pub async fn get_by_file_key<'a>(
         conn: &mut DbConnection<'a>,
         file_key: impl AsRef<[u8]>,
@@ -115,6 +139,23 @@ impl File {
         Ok(())
     }
 
+    pub async fn mark_deletion_pending<'a>(
+        conn: &mut DbConnection<'a>,
+        file_key: impl AsRef<[u8]>,
+        deletion_queued_at_block: i64,
+    ) -> Result<(), diesel::result::Error> {
+        let file_key = file_key.as_ref().to_vec();
+        diesel::update(file::table)
+            .filter(file::file_key.eq(file_key))
+            .set((
+                file::step.eq(FileStorageRequestStep::DeletionPending as i32),
+                file::deletion_queued_at_block.eq(deletion_queued_at_block),
+            ))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
     pub async fn delete<'a>(
         conn: &mut DbConnection<'a>,
         file_key: impl AsRef<[u8]>,
@@ -151,6 +192,25 @@ impl File {
         Ok(files)
     }
 
+    /// Returns the files stored by the BSP with `onchain_bsp_id`, each with the on-chain ID of
+    /// its bucket, which is needed to build its [`FileMetadata`].
+    pub async fn get_by_onchain_bsp_id<'a>(
+        conn: &mut DbConnection<'a>,
+        onchain_bsp_id: String,
+    ) -> Result<Vec<(Self, Vec<u8>)>, diesel::result::Error> {
+        use crate::schema::{bsp, bsp_file};
+
+        let files = bsp_file::table
+            .inner_join(bsp::table.on(bsp_file::bsp_id.eq(bsp::id)))
+            .inner_join(file::table.on(bsp_file::file_id.eq(file::id)))
+            .inner_join(bucket::table.on(file::bucket_id.eq(bucket::id)))
+            .filter(bsp::onchain_bsp_id.eq(onchain_bsp_id))
+            .select((File::as_select(), bucket::onchain_bucket_id))
+            .load(conn)
+            .await?;
+        Ok(files)
+    }
+
     pub async fn get_bsp_peer_ids(
         &self,
         conn: &mut DbConnection<'_>,
@@ -0,0 +1,65 @@
+use crate::{
+    models::{BucketMove, CheckpointChallenge, StorageRequest, StorageRequestResponse},
+    DbConnection,
+};
+
+/// Tables holding records of past events, which can be purged once they are old enough.
+///
+/// Tables holding the live state of the network (i.e. `bsp`, `msp`, `bucket`, `file`...) are
+/// deliberately not part of this, so that purging old records can never touch them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryTable {
+    /// Resolved requests to move a bucket to a new MSP.
+    BucketMove,
+    /// File keys challenged in checkpoint challenges.
+    CheckpointChallenge,
+    /// Fulfilled, expired or revoked storage requests.
+    StorageRequest,
+    /// Responses to storage requests.
+    StorageRequestResponse,
+}
+
+impl HistoryTable {
+    /// All the history tables.
+    pub const ALL: &'static [HistoryTable] = &[
+        HistoryTable::BucketMove,
+        HistoryTable::CheckpointChallenge,
+        HistoryTable::StorageRequest,
+        HistoryTable::StorageRequestResponse,
+    ];
+
+    /// The name of the table in the database.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HistoryTable::BucketMove => "bucket_move",
+            HistoryTable::CheckpointChallenge => "checkpoint_challenge",
+            HistoryTable::StorageRequest => "storage_request",
+            HistoryTable::StorageRequestResponse => "storage_request_response",
+        }
+    }
+
+    /// Deletes up to `limit` records of the table that are older than `block_number`.
+    ///
+    /// Returns the number of deleted records.
+    pub async fn delete_older_than<'a>(
+        &self,
+        conn: &mut DbConnection<'a>,
+        block_number: i64,
+        limit: i64,
+    ) -> Result<usize, diesel::result::Error> {
+        match self {
+            HistoryTable::BucketMove => {
+                BucketMove::delete_resolved_before(conn, block_number, limit).await
+            }
+            HistoryTable::CheckpointChallenge => {
+                CheckpointChallenge::delete_before(conn, block_number, limit).await
+            }
+            HistoryTable::StorageRequest => {
+                StorageRequest::delete_closed_before(conn, block_number, limit).await
+            }
+            HistoryTable::StorageRequestResponse => {
+                StorageRequestResponse::delete_before(conn, block_number, limit).await
+            }
+        }
+    }
+}
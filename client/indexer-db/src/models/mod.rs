@@ -1,17 +1,29 @@
 pub mod bsp;
 pub mod bucket;
+pub mod bucket_move;
+pub mod checkpoint_challenge;
+pub mod event_table;
 pub mod file;
+pub mod history;
 pub mod msp;
 pub mod multiaddress;
 pub mod payment_stream;
 pub mod peer_id;
 pub mod service_state;
+pub mod storage_request;
+pub mod storage_request_response;
 
 pub use bsp::*;
 pub use bucket::*;
+pub use bucket_move::*;
+pub use checkpoint_challenge::*;
+pub use event_table::*;
 pub use file::*;
+pub use history::*;
 pub use msp::*;
 pub use multiaddress::*;
 pub use payment_stream::*;
 pub use peer_id::*;
 pub use service_state::*;
+pub use storage_request::*;
+pub use storage_request_response::*;
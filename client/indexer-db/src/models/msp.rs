@@ -2,10 +2,11 @@ use bigdecimal::BigDecimal;
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use diesel_async::RunQueryDsl;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    models::multiaddress::MultiAddress,
-    schema::{msp, msp_multiaddress},
+    models::{multiaddress::MultiAddress, Bucket},
+    schema::{bucket, msp, msp_multiaddress},
     DbConnection,
 };
 
@@ -17,10 +18,53 @@ pub struct Msp {
     pub id: i64,
     pub account: String,
     pub capacity: BigDecimal,
-    pub value_prop: String,
+    /// The value proposition of the MSP, as a JSON object (see [`MspValueProposition`]).
+    ///
+    /// `None` for the MSPs indexed while value propositions were stored as their debug
+    /// representation, which could not be migrated.
+    pub value_prop: Option<serde_json::Value>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
     pub onchain_msp_id: String,
+    /// The SCALE-encoded value proposition, only stored if the indexer is configured to do so.
+    pub value_prop_encoded: Option<Vec<u8>>,
+    /// The account receiving the payments of the MSP, which can differ from its `account`.
+    pub payment_account: String,
+    /// The block at which the MSP signed up.
+    pub block_number: i64,
+    pub block_hash: Vec<u8>,
+}
+
+/// JSON representation of an MSP value proposition, as stored in the `value_prop` column.
+///
+/// Hashes and byte strings are hex-encoded, and the price is stored as a decimal string since
+/// it does not fit in a JSON number.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MspValueProposition {
+    pub id: String,
+    pub price_per_giga_unit_of_data_per_block: String,
+    pub commitment: String,
+    pub bucket_data_limit: u64,
+    pub available: bool,
+}
+
+/// The stats of an MSP, as returned by [`Msp::get_provider_stats`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderStats {
+    pub onchain_msp_id: String,
+    pub capacity: BigDecimal,
+    /// A summary of each of the buckets stored by the MSP, ordered by creation.
+    pub buckets: Vec<BucketSummary>,
+}
+
+/// A summary of a bucket stored by an MSP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BucketSummary {
+    pub onchain_bucket_id: Vec<u8>,
+    /// The number of files stored in the bucket, see [`Bucket::get_files_count`].
+    pub files_count: i64,
+    /// The total size of the files stored in the bucket.
+    pub total_size: i64,
 }
 
 /// Association table between MSP and MultiAddress
@@ -38,16 +82,24 @@ impl Msp {
         conn: &mut DbConnection<'a>,
         account: String,
         capacity: BigDecimal,
-        value_prop: String,
+        value_prop: serde_json::Value,
+        value_prop_encoded: Option<Vec<u8>>,
         multiaddresses: Vec<MultiAddress>,
         onchain_msp_id: String,
+        payment_account: String,
+        block_number: i64,
+        block_hash: Vec<u8>,
     ) -> Result<Self, diesel::result::Error> {
         let msp = diesel::insert_into(msp::table)
             .values((
                 msp::account.eq(account),
                 msp::capacity.eq(capacity),
                 msp::value_prop.eq(value_prop),
+                msp::value_prop_encoded.eq(value_prop_encoded),
                 msp::onchain_msp_id.eq(onchain_msp_id),
+                msp::payment_account.eq(payment_account),
+                msp::block_number.eq(block_number),
+                msp::block_hash.eq(block_hash),
             ))
             .returning(Msp::as_select())
             .get_result(conn)
@@ -71,6 +123,20 @@ impl Msp {
         Ok(msp)
     }
 
+    /// Deletes the MSPs indexed at the block `block_number` with hash `block_hash`.
+    pub async fn delete_inserted_at<'a>(
+        conn: &mut DbConnection<'a>,
+        block_number: i64,
+        block_hash: Vec<u8>,
+    ) -> Result<usize, diesel::result::Error> {
+        let deleted = diesel::delete(msp::table)
+            .filter(msp::block_number.eq(block_number))
+            .filter(msp::block_hash.eq(block_hash))
+            .execute(conn)
+            .await?;
+        Ok(deleted)
+    }
+
     pub async fn delete<'a>(
         conn: &mut DbConnection<'a>,
         account: String,
@@ -92,4 +158,79 @@ impl Msp {
             .await?;
         Ok(msp)
     }
+
+    pub async fn get_by_account<'a>(
+        conn: &mut DbConnection<'a>,
+        account: String,
+    ) -> Result<Self, diesel::result::Error> {
+        let msp = msp::table
+            .filter(msp::account.eq(account))
+            .first(conn)
+            .await?;
+        Ok(msp)
+    }
+
+    /// Get the MSPs paid to the given account, as several providers can share a payment account.
+    pub async fn get_by_payment_account<'a>(
+        conn: &mut DbConnection<'a>,
+        payment_account: String,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        let msps = msp::table
+            .filter(msp::payment_account.eq(payment_account))
+            .order(msp::id.asc())
+            .load(conn)
+            .await?;
+        Ok(msps)
+    }
+
+    /// Get the stats of the MSP with the given on-chain ID, with a summary of each of its buckets.
+    pub async fn get_provider_stats<'a>(
+        conn: &mut DbConnection<'a>,
+        onchain_msp_id: String,
+    ) -> Result<ProviderStats, diesel::result::Error> {
+        let msp = Self::get_by_onchain_msp_id(conn, onchain_msp_id).await?;
+        let msp_buckets: Vec<Bucket> = bucket::table
+            .filter(bucket::msp_id.eq(msp.id))
+            .order(bucket::id.asc())
+            .select(Bucket::as_select())
+            .load(conn)
+            .await?;
+
+        let mut buckets = Vec::with_capacity(msp_buckets.len());
+        for bucket in msp_buckets {
+            let files_count = Bucket::get_files_count(
+                conn,
+                &array_bytes::bytes2hex("0x", &bucket.onchain_bucket_id),
+            )
+            .await?;
+            buckets.push(BucketSummary {
+                onchain_bucket_id: bucket.onchain_bucket_id,
+                files_count,
+                total_size: bucket.total_size,
+            });
+        }
+
+        Ok(ProviderStats {
+            onchain_msp_id: msp.onchain_msp_id,
+            capacity: msp.capacity,
+            buckets,
+        })
+    }
+
+    /// Get the MSP storing the bucket with the given on-chain ID.
+    ///
+    /// Fails with [`diesel::result::Error::NotFound`] if the bucket does not exist or is not
+    /// stored by any MSP.
+    pub async fn get_by_bucket_id<'a>(
+        conn: &mut DbConnection<'a>,
+        onchain_bucket_id: Vec<u8>,
+    ) -> Result<Self, diesel::result::Error> {
+        let msp = msp::table
+            .inner_join(bucket::table.on(bucket::msp_id.eq(msp::id.nullable())))
+            .filter(bucket::onchain_bucket_id.eq(onchain_bucket_id))
+            .select(Msp::as_select())
+            .first(conn)
+            .await?;
+        Ok(msp)
+    }
 }
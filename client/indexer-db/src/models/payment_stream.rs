@@ -4,6 +4,11 @@ use diesel_async::RunQueryDsl;
 
 use crate::{schema::paymentstream, DbConnection};
 
+pub enum PaymentStreamStatus {
+    Active = 0,
+    UserInsolvent = 1,
+}
+
 #[derive(Debug, Queryable, Insertable, Selectable)]
 #[diesel(table_name = paymentstream)]
 pub struct PaymentStream {
@@ -19,6 +24,11 @@ pub struct PaymentStream {
     pub last_tick_charged: i64,
     // The tick at which the payment actually happened
     pub charged_at_tick: i64,
+    // The status of the payment stream. 0 = active, 1 = user insolvent
+    pub status: i32,
+    // The block at which the payment stream was created
+    pub block_number: i64,
+    pub block_hash: Vec<u8>,
 }
 
 impl PaymentStream {
@@ -26,11 +36,15 @@ impl PaymentStream {
         conn: &mut DbConnection<'a>,
         account: String,
         provider: String,
+        block_number: i64,
+        block_hash: Vec<u8>,
     ) -> Result<Self, diesel::result::Error> {
         let ps = diesel::insert_into(paymentstream::table)
             .values((
                 paymentstream::account.eq(account),
                 paymentstream::provider.eq(provider),
+                paymentstream::block_number.eq(block_number),
+                paymentstream::block_hash.eq(block_hash),
             ))
             .returning(PaymentStream::as_select())
             .get_result(conn)
@@ -38,6 +52,20 @@ impl PaymentStream {
         Ok(ps)
     }
 
+    /// Deletes the payment streams indexed at the block `block_number` with hash `block_hash`.
+    pub async fn delete_inserted_at<'a>(
+        conn: &mut DbConnection<'a>,
+        block_number: i64,
+        block_hash: Vec<u8>,
+    ) -> Result<usize, diesel::result::Error> {
+        let deleted = diesel::delete(paymentstream::table)
+            .filter(paymentstream::block_number.eq(block_number))
+            .filter(paymentstream::block_hash.eq(block_hash))
+            .execute(conn)
+            .await?;
+        Ok(deleted)
+    }
+
     pub async fn get<'a>(
         conn: &mut DbConnection<'a>,
         account: String,
@@ -55,6 +83,21 @@ impl PaymentStream {
         Ok(ps)
     }
 
+    /// Returns all the payment streams of `account`, with any provider.
+    ///
+    /// The lookup is served by the `idx_paymentstream_account` index, so it doesn't scan the table.
+    pub async fn get_by_user<'a>(
+        conn: &mut DbConnection<'a>,
+        account: String,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        let payment_streams = paymentstream::table
+            .filter(paymentstream::account.eq(account))
+            .order(paymentstream::id.asc())
+            .load(conn)
+            .await?;
+        Ok(payment_streams)
+    }
+
     pub async fn update_total_amount<'a>(
         conn: &mut DbConnection<'a>,
         ps_id: i64,
@@ -73,4 +116,20 @@ impl PaymentStream {
             .await?;
         Ok(())
     }
+
+    /// Sets the status of all the payment streams of `account` currently in `from` status.
+    pub async fn update_status_for_account<'a>(
+        conn: &mut DbConnection<'a>,
+        account: String,
+        from: PaymentStreamStatus,
+        to: PaymentStreamStatus,
+    ) -> Result<(), diesel::result::Error> {
+        diesel::update(paymentstream::table)
+            .filter(paymentstream::account.eq(account))
+            .filter(paymentstream::status.eq(from as i32))
+            .set(paymentstream::status.eq(to as i32))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
 }
@@ -12,6 +12,8 @@ pub struct ServiceState {
     pub last_processed_block: i64,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    /// The hash of the last processed block, empty if it was processed before it was tracked.
+    pub last_processed_block_hash: Vec<u8>,
 }
 
 impl ServiceState {
@@ -22,10 +24,14 @@ impl ServiceState {
     pub async fn update<'a>(
         conn: &mut DbConnection<'a>,
         last_processed_block: i64,
+        last_processed_block_hash: Vec<u8>,
     ) -> Result<Self, diesel::result::Error> {
         diesel::update(service_state::table)
             .filter(service_state::id.eq(1))
-            .set(service_state::last_processed_block.eq(last_processed_block))
+            .set((
+                service_state::last_processed_block.eq(last_processed_block),
+                service_state::last_processed_block_hash.eq(last_processed_block_hash),
+            ))
             .get_result(conn)
             .await
     }
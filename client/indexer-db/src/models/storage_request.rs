@@ -0,0 +1,166 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+
+use crate::{schema::storage_request, DbConnection};
+
+/// The status of a storage request. The discriminants are what is stored in the `status` column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageRequestStatus {
+    /// The storage request is open, waiting for the MSP and BSPs to store the file.
+    Open = 0,
+    /// The file was stored by the MSP and enough BSPs.
+    Fulfilled = 1,
+    /// The storage request expired before being fulfilled.
+    Expired = 2,
+    /// The requester revoked the storage request.
+    Revoked = 3,
+    /// The MSP rejected the storage request, or it expired without the MSP accepting it.
+    Rejected = 4,
+}
+
+/// Table that holds the storage requests issued for each file key, so that users can monitor
+/// their pending requests without querying the chain.
+#[derive(Debug, Queryable, Insertable, Selectable)]
+#[diesel(table_name = storage_request)]
+pub struct StorageRequest {
+    /// The ID of the storage request as stored in the database.
+    pub id: i64,
+    pub file_key: Vec<u8>,
+    pub onchain_bucket_id: Vec<u8>,
+    /// The account that issued the storage request.
+    pub requester: Vec<u8>,
+    pub file_size: i64,
+    pub fingerprint: Vec<u8>,
+    pub location: Vec<u8>,
+    /// The status of the request. 0 = open, 1 = fulfilled, 2 = expired, 3 = revoked,
+    /// 4 = rejected.
+    pub status: i32,
+    pub opened_at_block: i64,
+    /// The tick of the challenges ticker at which the storage request expires, as emitted by
+    /// the runtime.
+    pub expires_at_tick: i64,
+    /// The block at which the request was fulfilled, expired, revoked or rejected, if it was.
+    pub closed_at_block: Option<i64>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    /// The hash of the block at `opened_at_block`.
+    pub block_hash: Vec<u8>,
+}
+
+impl StorageRequest {
+    pub async fn create<'a>(
+        conn: &mut DbConnection<'a>,
+        file_key: Vec<u8>,
+        onchain_bucket_id: Vec<u8>,
+        requester: Vec<u8>,
+        file_size: i64,
+        fingerprint: Vec<u8>,
+        location: Vec<u8>,
+        opened_at_block: i64,
+        expires_at_tick: i64,
+        block_hash: Vec<u8>,
+    ) -> Result<Self, diesel::result::Error> {
+        let storage_request = diesel::insert_into(storage_request::table)
+            .values((
+                storage_request::file_key.eq(file_key),
+                storage_request::onchain_bucket_id.eq(onchain_bucket_id),
+                storage_request::requester.eq(requester),
+                storage_request::file_size.eq(file_size),
+                storage_request::fingerprint.eq(fingerprint),
+                storage_request::location.eq(location),
+                storage_request::status.eq(StorageRequestStatus::Open as i32),
+                storage_request::opened_at_block.eq(opened_at_block),
+                storage_request::expires_at_tick.eq(expires_at_tick),
+                storage_request::block_hash.eq(block_hash),
+            ))
+            .returning(StorageRequest::as_select())
+            .get_result(conn)
+            .await?;
+        Ok(storage_request)
+    }
+
+    /// Deletes the storage requests indexed at the block `block_number` with hash `block_hash`.
+    pub async fn delete_inserted_at<'a>(
+        conn: &mut DbConnection<'a>,
+        block_number: i64,
+        block_hash: Vec<u8>,
+    ) -> Result<usize, diesel::result::Error> {
+        let deleted = diesel::delete(storage_request::table)
+            .filter(storage_request::opened_at_block.eq(block_number))
+            .filter(storage_request::block_hash.eq(block_hash))
+            .execute(conn)
+            .await?;
+        Ok(deleted)
+    }
+
+    /// Closes the open storage request of `file_key` with `status` at `closed_at_block`.
+    pub async fn close<'a>(
+        conn: &mut DbConnection<'a>,
+        file_key: Vec<u8>,
+        status: StorageRequestStatus,
+        closed_at_block: i64,
+    ) -> Result<(), diesel::result::Error> {
+        diesel::update(storage_request::table)
+            .filter(storage_request::file_key.eq(file_key))
+            .filter(storage_request::status.eq(StorageRequestStatus::Open as i32))
+            .set((
+                storage_request::status.eq(status as i32),
+                storage_request::closed_at_block.eq(closed_at_block),
+                storage_request::updated_at.eq(diesel::dsl::now),
+            ))
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Gets the storage requests issued for `file_key`, oldest first.
+    pub async fn get_by_file_key<'a>(
+        conn: &mut DbConnection<'a>,
+        file_key: Vec<u8>,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        let storage_requests = storage_request::table
+            .filter(storage_request::file_key.eq(file_key))
+            .order(storage_request::id.asc())
+            .load(conn)
+            .await?;
+        Ok(storage_requests)
+    }
+
+    /// Gets the storage requests of `requester` that are still open, oldest first.
+    pub async fn get_open_by_requester<'a>(
+        conn: &mut DbConnection<'a>,
+        requester: Vec<u8>,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        let storage_requests = storage_request::table
+            .filter(storage_request::requester.eq(requester))
+            .filter(storage_request::status.eq(StorageRequestStatus::Open as i32))
+            .order(storage_request::id.asc())
+            .load(conn)
+            .await?;
+        Ok(storage_requests)
+    }
+
+    /// Deletes up to `limit` storage requests that were closed before `block_number`.
+    ///
+    /// Open storage requests are kept regardless of when they were issued.
+    /// Returns the number of deleted storage requests.
+    pub async fn delete_closed_before<'a>(
+        conn: &mut DbConnection<'a>,
+        block_number: i64,
+        limit: i64,
+    ) -> Result<usize, diesel::result::Error> {
+        let ids_to_delete = storage_request::table
+            .filter(storage_request::status.ne(StorageRequestStatus::Open as i32))
+            .filter(storage_request::closed_at_block.lt(block_number))
+            .select(storage_request::id)
+            .order(storage_request::id.asc())
+            .limit(limit);
+
+        let deleted = diesel::delete(storage_request::table)
+            .filter(storage_request::id.eq_any(ids_to_delete))
+            .execute(conn)
+            .await?;
+        Ok(deleted)
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+
+use crate::{schema::storage_request_response, DbConnection};
+
+/// Why a storage request was rejected for a file key.
+///
+/// Mirrors the `RejectedStorageRequestReason` of the runtime. The discriminants are what is
+/// stored in the `rejection_reason` column, so they must never change once assigned; new
+/// reasons get new discriminants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StorageRequestRejectionReason {
+    ReachedMaximumCapacity = 0,
+    ReceivedInvalidProof = 1,
+    FileKeyAlreadyStored = 2,
+    RequestExpired = 3,
+    InternalError = 4,
+    ReachedBucketDataLimit = 5,
+    MaxConcurrentUploadsReached = 6,
+}
+
+impl StorageRequestRejectionReason {
+    /// All the rejection reasons.
+    pub const ALL: &'static [StorageRequestRejectionReason] = &[
+        StorageRequestRejectionReason::ReachedMaximumCapacity,
+        StorageRequestRejectionReason::ReceivedInvalidProof,
+        StorageRequestRejectionReason::FileKeyAlreadyStored,
+        StorageRequestRejectionReason::RequestExpired,
+        StorageRequestRejectionReason::InternalError,
+        StorageRequestRejectionReason::ReachedBucketDataLimit,
+        StorageRequestRejectionReason::MaxConcurrentUploadsReached,
+    ];
+
+    /// The value stored in the `rejection_reason` column.
+    pub fn code(&self) -> i32 {
+        *self as i32
+    }
+
+    /// The rejection reason stored as `code` in the `rejection_reason` column, if it is known.
+    pub fn from_code(code: i32) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|reason| reason.code() == code)
+            .copied()
+    }
+
+    /// Stable name of the rejection reason, to be shown in dashboards.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StorageRequestRejectionReason::ReachedMaximumCapacity => "reached_maximum_capacity",
+            StorageRequestRejectionReason::ReceivedInvalidProof => "received_invalid_proof",
+            StorageRequestRejectionReason::FileKeyAlreadyStored => "file_key_already_stored",
+            StorageRequestRejectionReason::RequestExpired => "request_expired",
+            StorageRequestRejectionReason::InternalError => "internal_error",
+            StorageRequestRejectionReason::ReachedBucketDataLimit => "reached_bucket_data_limit",
+            StorageRequestRejectionReason::MaxConcurrentUploadsReached => {
+                "max_concurrent_uploads_reached"
+            }
+        }
+    }
+}
+
+/// Table that holds the outcome of the storage requests of each file key, i.e. whether the MSP
+/// accepted it or why it was rejected.
+#[derive(Debug, Queryable, Insertable, Selectable)]
+#[diesel(table_name = storage_request_response)]
+pub struct StorageRequestResponse {
+    /// The ID of the response as stored in the database.
+    pub id: i64,
+    pub file_key: Vec<u8>,
+    pub accepted: bool,
+    /// The [`StorageRequestRejectionReason`] code if the storage request was rejected, `None` if
+    /// it was accepted.
+    pub rejection_reason: Option<i32>,
+    pub block_number: i64,
+    pub created_at: NaiveDateTime,
+    /// The hash of the block at `block_number`.
+    pub block_hash: Vec<u8>,
+}
+
+/// Number of accepted and rejected storage requests, as returned by
+/// [`StorageRequestResponse::count_by_rejection_reason`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StorageRequestResponseCounts {
+    pub accepted: i64,
+    /// Total number of rejected storage requests, including those rejected for reasons unknown
+    /// to this version of the indexer.
+    pub rejected: i64,
+    pub rejected_by_reason: BTreeMap<StorageRequestRejectionReason, i64>,
+}
+
+impl StorageRequestResponseCounts {
+    /// Builds the counts from `(rejection_reason, count)` rows, where a `None` rejection reason
+    /// counts accepted storage requests.
+    pub fn from_rows(rows: impl IntoIterator<Item = (Option<i32>, i64)>) -> Self {
+        let mut counts = Self::default();
+        for (rejection_reason, count) in rows {
+            match rejection_reason {
+                None => counts.accepted += count,
+                Some(code) => {
+                    counts.rejected += count;
+                    if let Some(reason) = StorageRequestRejectionReason::from_code(code) {
+                        *counts.rejected_by_reason.entry(reason).or_default() += count;
+                    }
+                }
+            }
+        }
+        counts
+    }
+
+    /// Total number of responded storage requests.
+    pub fn total(&self) -> i64 {
+        self.accepted + self.rejected
+    }
+
+    /// Percentage of all the responded storage requests that were rejected for `reason`.
+    pub fn rejected_percentage(&self, reason: StorageRequestRejectionReason) -> f64 {
+        if self.total() == 0 {
+            return 0.0;
+        }
+        let rejected = self
+            .rejected_by_reason
+            .get(&reason)
+            .copied()
+            .unwrap_or_default();
+        rejected as f64 * 100.0 / self.total() as f64
+    }
+}
+
+impl StorageRequestResponse {
+    /// Records the response to the storage request of `file_key`: accepted if `rejection_reason`
+    /// is `None`, rejected for `rejection_reason` otherwise.
+    pub async fn create<'a>(
+        conn: &mut DbConnection<'a>,
+        file_key: Vec<u8>,
+        rejection_reason: Option<StorageRequestRejectionReason>,
+        block_number: i64,
+        block_hash: Vec<u8>,
+    ) -> Result<Self, diesel::result::Error> {
+        let response = diesel::insert_into(storage_request_response::table)
+            .values((
+                storage_request_response::file_key.eq(file_key),
+                storage_request_response::accepted.eq(rejection_reason.is_none()),
+                storage_request_response::rejection_reason
+                    .eq(rejection_reason.map(|reason| reason.code())),
+                storage_request_response::block_number.eq(block_number),
+                storage_request_response::block_hash.eq(block_hash),
+            ))
+            .returning(StorageRequestResponse::as_select())
+            .get_result(conn)
+            .await?;
+        Ok(response)
+    }
+
+    /// Deletes the storage request responses indexed at the block `block_number` with hash `block_hash`.
+    pub async fn delete_inserted_at<'a>(
+        conn: &mut DbConnection<'a>,
+        block_number: i64,
+        block_hash: Vec<u8>,
+    ) -> Result<usize, diesel::result::Error> {
+        let deleted = diesel::delete(storage_request_response::table)
+            .filter(storage_request_response::block_number.eq(block_number))
+            .filter(storage_request_response::block_hash.eq(block_hash))
+            .execute(conn)
+            .await?;
+        Ok(deleted)
+    }
+
+    /// Gets the responses to the storage requests of `file_key`.
+    pub async fn get_by_file_key<'a>(
+        conn: &mut DbConnection<'a>,
+        file_key: Vec<u8>,
+    ) -> Result<Vec<Self>, diesel::result::Error> {
+        let responses = storage_request_response::table
+            .filter(storage_request_response::file_key.eq(file_key))
+            .order(storage_request_response::id.asc())
+            .load(conn)
+            .await?;
+        Ok(responses)
+    }
+
+    /// Counts the accepted storage requests, and the rejected ones by rejection reason.
+    pub async fn count_by_rejection_reason<'a>(
+        conn: &mut DbConnection<'a>,
+    ) -> Result<StorageRequestResponseCounts, diesel::result::Error> {
+        let rows: Vec<(Option<i32>, i64)> = storage_request_response::table
+            .group_by(storage_request_response::rejection_reason)
+            .select((
+                storage_request_response::rejection_reason,
+                diesel::dsl::count_star(),
+            ))
+            .load(conn)
+            .await?;
+        Ok(StorageRequestResponseCounts::from_rows(rows))
+    }
+
+    /// Deletes up to `limit` responses recorded before `block_number`.
+    ///
+    /// Returns the number of deleted responses.
+    pub async fn delete_before<'a>(
+        conn: &mut DbConnection<'a>,
+        block_number: i64,
+        limit: i64,
+    ) -> Result<usize, diesel::result::Error> {
+        let ids_to_delete = storage_request_response::table
+            .filter(storage_request_response::block_number.lt(block_number))
+            .select(storage_request_response::id)
+            .order(storage_request_response::id.asc())
+            .limit(limit);
+
+        let deleted = diesel::delete(storage_request_response::table)
+            .filter(storage_request_response::id.eq_any(ids_to_delete))
+            .execute(conn)
+            .await?;
+        Ok(deleted)
+    }
+}
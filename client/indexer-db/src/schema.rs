@@ -11,6 +11,9 @@ diesel::table! {
         updated_at -> Timestamp,
         onchain_bsp_id -> Varchar,
         merkle_root -> Bytea,
+        payment_account -> Varchar,
+        block_number -> Int8,
+        block_hash -> Bytea,
     }
 }
 
@@ -40,6 +43,37 @@ diesel::table! {
         created_at -> Timestamp,
         updated_at -> Timestamp,
         merkle_root -> Bytea,
+        total_size -> Int8,
+        block_number -> Int8,
+        block_hash -> Bytea,
+    }
+}
+
+diesel::table! {
+    bucket_move (id) {
+        id -> Int8,
+        onchain_bucket_id -> Bytea,
+        account -> Varchar,
+        old_msp_id -> Nullable<Int8>,
+        new_msp_id -> Int8,
+        status -> Int4,
+        requested_at_block -> Int8,
+        resolved_at_block -> Nullable<Int8>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        block_hash -> Bytea,
+    }
+}
+
+diesel::table! {
+    checkpoint_challenge (id) {
+        id -> Int8,
+        tick -> Int8,
+        challenged_file_key -> Bytea,
+        trie_mutation_type -> Int4,
+        block_number -> Int8,
+        created_at -> Timestamp,
+        block_hash -> Bytea,
     }
 }
 
@@ -55,6 +89,9 @@ diesel::table! {
         step -> Int4,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        deletion_queued_at_block -> Nullable<Int8>,
+        block_number -> Int8,
+        block_hash -> Bytea,
     }
 }
 
@@ -70,10 +107,14 @@ diesel::table! {
         id -> Int8,
         account -> Varchar,
         capacity -> Numeric,
-        value_prop -> Varchar,
+        value_prop -> Nullable<Jsonb>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
         onchain_msp_id -> Varchar,
+        value_prop_encoded -> Nullable<Bytea>,
+        payment_account -> Varchar,
+        block_number -> Int8,
+        block_hash -> Bytea,
     }
 }
 
@@ -101,6 +142,9 @@ diesel::table! {
         total_amount_paid -> Numeric,
         last_tick_charged -> Int8,
         charged_at_tick -> Int8,
+        status -> Int4,
+        block_number -> Int8,
+        block_hash -> Bytea,
     }
 }
 
@@ -119,6 +163,38 @@ diesel::table! {
         last_processed_block -> Int8,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
+        last_processed_block_hash -> Bytea,
+    }
+}
+
+diesel::table! {
+    storage_request (id) {
+        id -> Int8,
+        file_key -> Bytea,
+        onchain_bucket_id -> Bytea,
+        requester -> Bytea,
+        file_size -> Int8,
+        fingerprint -> Bytea,
+        location -> Bytea,
+        status -> Int4,
+        opened_at_block -> Int8,
+        expires_at_tick -> Int8,
+        closed_at_block -> Nullable<Int8>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        block_hash -> Bytea,
+    }
+}
+
+diesel::table! {
+    storage_request_response (id) {
+        id -> Int8,
+        file_key -> Bytea,
+        accepted -> Bool,
+        rejection_reason -> Nullable<Int4>,
+        block_number -> Int8,
+        created_at -> Timestamp,
+        block_hash -> Bytea,
     }
 }
 
@@ -136,6 +212,8 @@ diesel::allow_tables_to_appear_in_same_query!(
     bsp_file,
     bsp_multiaddress,
     bucket,
+    bucket_move,
+    checkpoint_challenge,
     file,
     file_peer_id,
     msp,
@@ -144,4 +222,6 @@ diesel::allow_tables_to_appear_in_same_query!(
     paymentstream,
     peer_id,
     service_state,
+    storage_request,
+    storage_request_response,
 );
@@ -0,0 +1,231 @@
+// SQLite schema of the indexer database, matching the migrations in `migrations_sqlite`.
+//
+// Kept in sync with `schema.rs` by hand: the Diesel CLI prints SQLite `INTEGER` columns as
+// `Integer`, but the models read ids, sizes and block numbers as `i64`, which SQLite integers
+// always fit.
+
+diesel::table! {
+    bsp (id) {
+        id -> BigInt,
+        account -> Text,
+        capacity -> Numeric,
+        stake -> Numeric,
+        last_tick_proven -> BigInt,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        onchain_bsp_id -> Text,
+        merkle_root -> Binary,
+        payment_account -> Text,
+        block_number -> BigInt,
+        block_hash -> Binary,
+    }
+}
+
+diesel::table! {
+    bsp_file (bsp_id, file_id) {
+        bsp_id -> BigInt,
+        file_id -> BigInt,
+    }
+}
+
+diesel::table! {
+    bsp_multiaddress (bsp_id, multiaddress_id) {
+        bsp_id -> BigInt,
+        multiaddress_id -> BigInt,
+    }
+}
+
+diesel::table! {
+    bucket (id) {
+        id -> BigInt,
+        msp_id -> Nullable<BigInt>,
+        account -> Text,
+        onchain_bucket_id -> Binary,
+        name -> Binary,
+        collection_id -> Nullable<Text>,
+        private -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        merkle_root -> Binary,
+        total_size -> BigInt,
+        block_number -> BigInt,
+        block_hash -> Binary,
+    }
+}
+
+diesel::table! {
+    bucket_move (id) {
+        id -> BigInt,
+        onchain_bucket_id -> Binary,
+        account -> Text,
+        old_msp_id -> Nullable<BigInt>,
+        new_msp_id -> BigInt,
+        status -> Integer,
+        requested_at_block -> BigInt,
+        resolved_at_block -> Nullable<BigInt>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        block_hash -> Binary,
+    }
+}
+
+diesel::table! {
+    checkpoint_challenge (id) {
+        id -> BigInt,
+        tick -> BigInt,
+        challenged_file_key -> Binary,
+        trie_mutation_type -> Integer,
+        block_number -> BigInt,
+        created_at -> Timestamp,
+        block_hash -> Binary,
+    }
+}
+
+diesel::table! {
+    file (id) {
+        id -> BigInt,
+        account -> Binary,
+        file_key -> Binary,
+        bucket_id -> BigInt,
+        location -> Binary,
+        fingerprint -> Binary,
+        size -> BigInt,
+        step -> Integer,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        deletion_queued_at_block -> Nullable<BigInt>,
+        block_number -> BigInt,
+        block_hash -> Binary,
+    }
+}
+
+diesel::table! {
+    file_peer_id (file_id, peer_id) {
+        file_id -> BigInt,
+        peer_id -> BigInt,
+    }
+}
+
+diesel::table! {
+    msp (id) {
+        id -> BigInt,
+        account -> Text,
+        capacity -> Numeric,
+        value_prop -> Nullable<Json>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        onchain_msp_id -> Text,
+        value_prop_encoded -> Nullable<Binary>,
+        payment_account -> Text,
+        block_number -> BigInt,
+        block_hash -> Binary,
+    }
+}
+
+diesel::table! {
+    msp_multiaddress (msp_id, multiaddress_id) {
+        msp_id -> BigInt,
+        multiaddress_id -> BigInt,
+    }
+}
+
+diesel::table! {
+    multiaddress (id) {
+        id -> BigInt,
+        address -> Binary,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    paymentstream (id) {
+        id -> BigInt,
+        account -> Text,
+        provider -> Text,
+        total_amount_paid -> Numeric,
+        last_tick_charged -> BigInt,
+        charged_at_tick -> BigInt,
+        status -> Integer,
+        block_number -> BigInt,
+        block_hash -> Binary,
+    }
+}
+
+diesel::table! {
+    peer_id (id) {
+        id -> BigInt,
+        peer -> Binary,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    service_state (id) {
+        id -> Integer,
+        last_processed_block -> BigInt,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        last_processed_block_hash -> Binary,
+    }
+}
+
+diesel::table! {
+    storage_request (id) {
+        id -> BigInt,
+        file_key -> Binary,
+        onchain_bucket_id -> Binary,
+        requester -> Binary,
+        file_size -> BigInt,
+        fingerprint -> Binary,
+        location -> Binary,
+        status -> Integer,
+        opened_at_block -> BigInt,
+        expires_at_tick -> BigInt,
+        closed_at_block -> Nullable<BigInt>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        block_hash -> Binary,
+    }
+}
+
+diesel::table! {
+    storage_request_response (id) {
+        id -> BigInt,
+        file_key -> Binary,
+        accepted -> Bool,
+        rejection_reason -> Nullable<Integer>,
+        block_number -> BigInt,
+        created_at -> Timestamp,
+        block_hash -> Binary,
+    }
+}
+
+diesel::joinable!(bsp_file -> file (file_id));
+diesel::joinable!(bsp_multiaddress -> bsp (bsp_id));
+diesel::joinable!(bsp_multiaddress -> multiaddress (multiaddress_id));
+diesel::joinable!(bucket -> msp (msp_id));
+diesel::joinable!(file_peer_id -> file (file_id));
+diesel::joinable!(file_peer_id -> peer_id (peer_id));
+diesel::joinable!(msp_multiaddress -> msp (msp_id));
+diesel::joinable!(msp_multiaddress -> multiaddress (multiaddress_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    bsp,
+    bsp_file,
+    bsp_multiaddress,
+    bucket,
+    bucket_move,
+    checkpoint_challenge,
+    file,
+    file_peer_id,
+    msp,
+    msp_multiaddress,
+    multiaddress,
+    paymentstream,
+    peer_id,
+    service_state,
+    storage_request,
+    storage_request_response,
+);
@@ -0,0 +1,69 @@
+use async_trait::async_trait;
+
+use shc_actors_framework::actor::ActorHandle;
+use shc_indexer_db::models::PaymentStream;
+use sp_runtime::AccountId32;
+
+use crate::handler::{GetUserPaymentStreamsError, IndexerService, PurgeOldRecordsError};
+
+/// Messages understood by the IndexerService actor.
+///
+/// The indexed data is meant to be read directly from the database, so commands are mostly used
+/// for maintaining the database itself, plus a few queries that need to go through the indexer.
+#[derive(Debug)]
+pub enum IndexerServiceCommand {
+    /// Delete the records of the history tables that are more than `max_age_blocks` blocks older
+    /// than the last block processed by the indexer.
+    PurgeOldRecords {
+        max_age_blocks: i64,
+        callback: tokio::sync::oneshot::Sender<Result<usize, PurgeOldRecordsError>>,
+    },
+    /// Get all the payment streams of `user_account`, with any provider.
+    GetUserPaymentStreams {
+        user_account: AccountId32,
+        callback:
+            tokio::sync::oneshot::Sender<Result<Vec<PaymentStream>, GetUserPaymentStreamsError>>,
+    },
+}
+
+/// Interface for interacting with the IndexerService actor.
+#[async_trait]
+pub trait IndexerServiceInterface {
+    /// Purge the records of the history tables older than `max_age_blocks` blocks.
+    ///
+    /// Returns the number of purged records. Live state tables are never purged.
+    async fn purge_old_records(&self, max_age_blocks: i64) -> Result<usize, PurgeOldRecordsError>;
+
+    /// Get all the payment streams of `user_account`, with any provider.
+    async fn get_user_payment_streams(
+        &self,
+        user_account: AccountId32,
+    ) -> Result<Vec<PaymentStream>, GetUserPaymentStreamsError>;
+}
+
+/// Implement the IndexerServiceInterface for the ActorHandle<IndexerService>.
+#[async_trait]
+impl IndexerServiceInterface for ActorHandle<IndexerService> {
+    async fn purge_old_records(&self, max_age_blocks: i64) -> Result<usize, PurgeOldRecordsError> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let message = IndexerServiceCommand::PurgeOldRecords {
+            max_age_blocks,
+            callback,
+        };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from IndexerService. Probably means IndexerService has crashed.")
+    }
+
+    async fn get_user_payment_streams(
+        &self,
+        user_account: AccountId32,
+    ) -> Result<Vec<PaymentStream>, GetUserPaymentStreamsError> {
+        let (callback, rx) = tokio::sync::oneshot::channel();
+        let message = IndexerServiceCommand::GetUserPaymentStreams {
+            user_account,
+            callback,
+        };
+        self.send(message).await;
+        rx.await.expect("Failed to receive response from IndexerService. Probably means IndexerService has crashed.")
+    }
+}
@@ -1,13 +1,13 @@
 use diesel_async::AsyncConnection;
 use futures::prelude::*;
-use log::{error, info};
+use log::{error, info, warn};
 use shc_common::types::StorageProviderId;
 use sp_runtime::AccountId32;
 use std::sync::Arc;
 use thiserror::Error;
 
 use pallet_storage_providers_runtime_api::StorageProvidersApi;
-use sc_client_api::{BlockBackend, BlockchainEvents};
+use sc_client_api::{BlockBackend, BlockchainEvents, HeaderBackend};
 use shc_actors_framework::actor::{Actor, ActorEventLoop};
 use shc_common::blockchain_utils::{convert_raw_multiaddress_to_multiaddr, EventsRetrievalError};
 use shc_common::{
@@ -20,17 +20,27 @@ use sp_core::H256;
 use sp_runtime::traits::Header;
 use storage_hub_runtime::RuntimeEvent;
 
+use crate::commands::IndexerServiceCommand;
+use crate::metrics::{IndexerMetrics, RowsWritten};
+use crate::storage_request_response::{closed_storage_request, storage_request_response};
+use crate::value_prop::{value_prop_to_encoded, value_prop_to_json};
+
 pub(crate) const LOG_TARGET: &str = "indexer-service";
 
-// Since the indexed data should be used directly from the database,
-// we don't need to implement commands.
-#[derive(Debug)]
-pub enum IndexerServiceCommand {}
+/// Number of records deleted at once when purging old records.
+///
+/// Deleting in batches avoids a single long-running statement locking the history tables.
+const PURGE_BATCH_SIZE: i64 = 1000;
 
 // The IndexerService actor
 pub struct IndexerService {
     client: Arc<ParachainClient>,
     db_pool: DbPool,
+    /// Whether to also store the SCALE-encoded value propositions of MSPs, next to their JSON
+    /// representation.
+    store_encoded_value_props: bool,
+    /// Metrics of the rows written while indexing, if exported.
+    metrics: Option<IndexerMetrics>,
 }
 
 // Implement the Actor trait for IndexerService
@@ -45,7 +55,30 @@ impl Actor for IndexerService {
     ) -> impl std::future::Future<Output = ()> + Send {
         async move {
             match message {
-                // No commands for now
+                IndexerServiceCommand::PurgeOldRecords {
+                    max_age_blocks,
+                    callback,
+                } => {
+                    let result = self.purge_old_records(max_age_blocks).await;
+                    match callback.send(result) {
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to send purged records count: {:?}", e);
+                        }
+                    }
+                }
+                IndexerServiceCommand::GetUserPaymentStreams {
+                    user_account,
+                    callback,
+                } => {
+                    let result = self.get_user_payment_streams(&user_account).await;
+                    match callback.send(result) {
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to send user payment streams: {:?}", e);
+                        }
+                    }
+                }
             }
         }
     }
@@ -57,8 +90,70 @@ impl Actor for IndexerService {
 
 // Implement methods for IndexerService
 impl IndexerService {
-    pub fn new(client: Arc<ParachainClient>, db_pool: DbPool) -> Self {
-        Self { client, db_pool }
+    pub fn new(
+        client: Arc<ParachainClient>,
+        db_pool: DbPool,
+        store_encoded_value_props: bool,
+        metrics: Option<IndexerMetrics>,
+    ) -> Self {
+        Self {
+            client,
+            db_pool,
+            store_encoded_value_props,
+            metrics,
+        }
+    }
+
+    /// Returns all the payment streams of `user_account`, with any provider.
+    async fn get_user_payment_streams(
+        &self,
+        user_account: &AccountId32,
+    ) -> Result<Vec<PaymentStream>, GetUserPaymentStreamsError> {
+        let mut db_conn = self.db_pool.get().await?;
+
+        // Payment streams are indexed by the string representation of the account.
+        let payment_streams =
+            PaymentStream::get_by_user(&mut db_conn, user_account.to_string()).await?;
+
+        Ok(payment_streams)
+    }
+
+    /// Deletes the records of the [`HistoryTable`]s that are older than `max_age_blocks` blocks,
+    /// relative to the last block processed by the indexer.
+    ///
+    /// Records are deleted in batches of [`PURGE_BATCH_SIZE`], each in its own statement, so that
+    /// other queries aren't blocked for the whole duration of the purge.
+    async fn purge_old_records(&self, max_age_blocks: i64) -> Result<usize, PurgeOldRecordsError> {
+        if max_age_blocks < 0 {
+            return Err(PurgeOldRecordsError::NegativeMaxAge(max_age_blocks));
+        }
+
+        let mut db_conn = self.db_pool.get().await?;
+
+        let service_state = ServiceState::get(&mut db_conn).await?;
+        let cutoff_block = service_state
+            .last_processed_block
+            .saturating_sub(max_age_blocks);
+
+        let mut total_purged = 0;
+        for table in HistoryTable::ALL {
+            let mut purged = 0;
+            loop {
+                let deleted = table
+                    .delete_older_than(&mut db_conn, cutoff_block, PURGE_BATCH_SIZE)
+                    .await?;
+                purged += deleted;
+
+                if deleted < PURGE_BATCH_SIZE as usize {
+                    break;
+                }
+            }
+
+            info!(target: LOG_TARGET, "Purged {} records older than block #{} from {}", purged, cutoff_block, table.name());
+            total_purged += purged;
+        }
+
+        Ok(total_purged)
     }
 
     async fn handle_finality_notification<Block>(
@@ -76,7 +171,19 @@ impl IndexerService {
 
         let mut db_conn = self.db_pool.get().await?;
 
-        let service_state = ServiceState::get(&mut db_conn).await?;
+        let mut service_state = ServiceState::get(&mut db_conn).await?;
+
+        // The last processed block is no longer in the chain, so what was indexed from the fork
+        // it belongs to is reverted.
+        if let Ok(indexed_block_hash) =
+            <[u8; 32]>::try_from(service_state.last_processed_block_hash.as_slice())
+        {
+            let orphaned_blocks = self.orphaned_indexed_blocks(H256::from(indexed_block_hash))?;
+            if !orphaned_blocks.is_empty() {
+                self.reorg_handler(&mut db_conn, orphaned_blocks).await?;
+                service_state = ServiceState::get(&mut db_conn).await?;
+            }
+        }
 
         for block_number in
             (service_state.last_processed_block as BlockNumber + 1)..=finalized_block_number
@@ -92,6 +199,72 @@ impl IndexerService {
         Ok(())
     }
 
+    /// The blocks from the indexed block with `block_hash` back to the last one still in the
+    /// chain, which were orphaned by a reorg. Empty if the block is still in the chain.
+    fn orphaned_indexed_blocks(&self, mut block_hash: H256) -> Result<Vec<H256>, HandleReorgError> {
+        let mut orphaned_blocks = Vec::new();
+        loop {
+            let header = self
+                .client
+                .header(block_hash)?
+                .ok_or(HandleReorgError::BlockNotFound(block_hash))?;
+            if self.client.block_hash(*header.number())? == Some(block_hash) {
+                return Ok(orphaned_blocks);
+            }
+            orphaned_blocks.push(block_hash);
+            block_hash = *header.parent_hash();
+        }
+    }
+
+    /// Reverts what was indexed for the blocks of a fork orphaned by a reorg.
+    ///
+    /// Only finalized blocks are indexed for now, so reorgs should not affect the indexer, but
+    /// they would if it ever followed the best chain instead. The records inserted for the
+    /// `orphaned_blocks` are deleted from all the [`EventTable`]s, and if the last processed
+    /// block is orphaned, it is moved back to the parent of the first orphaned block, so that
+    /// indexing resumes from there on the new chain (see [`revert_orphaned_blocks`]).
+    ///
+    /// Returns the number of deleted records.
+    pub async fn reorg_handler<'a>(
+        &self,
+        conn: &mut DbConnection<'a>,
+        orphaned_blocks: Vec<H256>,
+    ) -> Result<usize, HandleReorgError> {
+        let mut blocks = Vec::with_capacity(orphaned_blocks.len());
+        // The parent of the first orphaned block, which the fork shares with the new chain.
+        let mut fork_point: Option<(i64, Vec<u8>)> = None;
+        for block_hash in orphaned_blocks {
+            let header = self
+                .client
+                .header(block_hash)?
+                .ok_or(HandleReorgError::BlockNotFound(block_hash))?;
+            let block_number = *header.number() as i64;
+            if fork_point.as_ref().map_or(true, |(fork_point_number, _)| {
+                block_number <= *fork_point_number
+            }) {
+                fork_point = Some((block_number - 1, header.parent_hash().as_ref().to_vec()));
+            }
+            blocks.push((block_number, block_hash.as_ref().to_vec()));
+        }
+
+        let Some(fork_point) = fork_point else {
+            return Ok(0);
+        };
+        let fork_point_number = fork_point.0;
+
+        let deleted = conn
+            .transaction::<usize, HandleReorgError, _>(move |conn| {
+                Box::pin(
+                    async move { Ok(revert_orphaned_blocks(conn, &blocks, fork_point).await?) },
+                )
+            })
+            .await?;
+
+        warn!(target: LOG_TARGET, "Reverted {} records indexed for the blocks orphaned after #{}", deleted, fork_point_number);
+
+        Ok(deleted)
+    }
+
     async fn index_block<'a, 'b: 'a>(
         &'b self,
         conn: &mut DbConnection<'a>,
@@ -102,18 +275,33 @@ impl IndexerService {
 
         let block_events = get_events_at_block(&self.client, &block_hash)?;
 
-        conn.transaction::<(), IndexBlockError, _>(move |conn| {
-            Box::pin(async move {
-                ServiceState::update(conn, block_number as i64).await?;
+        let rows_written = conn
+            .transaction::<RowsWritten, IndexBlockError, _>(move |conn| {
+                Box::pin(async move {
+                    ServiceState::update(conn, block_number as i64, block_hash.as_ref().to_vec())
+                        .await?;
 
-                for ev in block_events {
-                    self.index_event(conn, &ev.event, block_hash).await?;
-                }
+                    let mut rows_written = RowsWritten::default();
+                    for ev in block_events {
+                        self.index_event(
+                            conn,
+                            &ev.event,
+                            block_number,
+                            block_hash,
+                            &mut rows_written,
+                        )
+                        .await?;
+                    }
 
-                Ok(())
+                    Ok(rows_written)
+                })
             })
-        })
-        .await?;
+            .await?;
+
+        // Only record the metrics once the block is committed.
+        if let Some(metrics) = &self.metrics {
+            metrics.record_block(&rows_written);
+        }
 
         Ok(())
     }
@@ -122,21 +310,39 @@ impl IndexerService {
         &'b self,
         conn: &mut DbConnection<'a>,
         event: &RuntimeEvent,
+        block_number: BlockNumber,
         block_hash: H256,
+        rows_written: &mut RowsWritten,
     ) -> Result<(), diesel::result::Error> {
         match event {
-            RuntimeEvent::BucketNfts(event) => self.index_bucket_nfts_event(conn, event).await?,
-            RuntimeEvent::FileSystem(event) => self.index_file_system_event(conn, event).await?,
-            RuntimeEvent::PaymentStreams(event) => {
-                self.index_payment_streams_event(conn, event).await?
-            }
-            RuntimeEvent::ProofsDealer(event) => {
-                self.index_proofs_dealer_event(conn, event).await?
-            }
-            RuntimeEvent::Providers(event) => {
-                self.index_providers_event(conn, event, block_hash).await?
-            }
-            RuntimeEvent::Randomness(event) => self.index_randomness_event(conn, event).await?,
+            RuntimeEvent::BucketNfts(event) => rows_written.add(
+                "bucket_nfts",
+                self.index_bucket_nfts_event(conn, event).await?,
+            ),
+            RuntimeEvent::FileSystem(event) => rows_written.add(
+                "file_system",
+                self.index_file_system_event(conn, event, block_number, block_hash)
+                    .await?,
+            ),
+            RuntimeEvent::PaymentStreams(event) => rows_written.add(
+                "payment_streams",
+                self.index_payment_streams_event(conn, event, block_number, block_hash)
+                    .await?,
+            ),
+            RuntimeEvent::ProofsDealer(event) => rows_written.add(
+                "proofs_dealer",
+                self.index_proofs_dealer_event(conn, event, block_number, block_hash)
+                    .await?,
+            ),
+            RuntimeEvent::Providers(event) => rows_written.add(
+                "providers",
+                self.index_providers_event(conn, event, block_number, block_hash)
+                    .await?,
+            ),
+            RuntimeEvent::Randomness(event) => rows_written.add(
+                "randomness",
+                self.index_randomness_event(conn, event).await?,
+            ),
             // TODO: We have to index the events from the CrRandomness pallet when we integrate it to the runtime,
             // since they contain the information about the commit-reveal deadlines for Providers.
             // RuntimeEvent::CrRandomness(event) => self.index_cr_randomness_event(conn, event).await?,
@@ -165,21 +371,25 @@ impl IndexerService {
         &'b self,
         _conn: &mut DbConnection<'a>,
         event: &pallet_bucket_nfts::Event<storage_hub_runtime::Runtime>,
-    ) -> Result<(), diesel::result::Error> {
+    ) -> Result<u64, diesel::result::Error> {
         match event {
             pallet_bucket_nfts::Event::AccessShared { .. } => {}
             pallet_bucket_nfts::Event::ItemReadAccessUpdated { .. } => {}
             pallet_bucket_nfts::Event::ItemBurned { .. } => {}
             pallet_bucket_nfts::Event::__Ignore(_, _) => {}
         }
-        Ok(())
+        Ok(0)
     }
 
     async fn index_file_system_event<'a, 'b: 'a>(
         &'b self,
         conn: &mut DbConnection<'a>,
         event: &pallet_file_system::Event<storage_hub_runtime::Runtime>,
-    ) -> Result<(), diesel::result::Error> {
+        block_number: BlockNumber,
+        block_hash: H256,
+    ) -> Result<u64, diesel::result::Error> {
+        let mut rows_written = 0;
+
         match event {
             pallet_file_system::Event::NewBucket {
                 who,
@@ -202,8 +412,11 @@ impl IndexerService {
                     collection_id.map(|id| id.to_string()),
                     *private,
                     root.as_ref().to_vec(),
+                    block_number as i64,
+                    block_hash.as_ref().to_vec(),
                 )
                 .await?;
+                rows_written += 1;
             }
             pallet_file_system::Event::MoveBucketAccepted {
                 old_msp_id: _,
@@ -213,6 +426,15 @@ impl IndexerService {
             } => {
                 let new_msp = Msp::get_by_onchain_msp_id(conn, new_msp_id.to_string()).await?;
                 Bucket::update_msp(conn, bucket_id.as_ref().to_vec(), new_msp.id).await?;
+                rows_written += 1;
+                BucketMove::resolve(
+                    conn,
+                    bucket_id.as_ref().to_vec(),
+                    BucketMoveStatus::Accepted,
+                    block_number as i64,
+                )
+                .await?;
+                rows_written += 1;
             }
             pallet_file_system::Event::BucketPrivacyUpdated {
                 who,
@@ -220,6 +442,7 @@ impl IndexerService {
                 collection_id,
                 private,
             } => {
+                // Fails if the bucket is not indexed, instead of skipping it.
                 Bucket::update_privacy(
                     conn,
                     who.to_string(),
@@ -228,6 +451,16 @@ impl IndexerService {
                     *private,
                 )
                 .await?;
+                rows_written += 1;
+
+                // Refresh the denormalized total size of the bucket.
+                let total_size = Bucket::get_total_stored_size(
+                    conn,
+                    &array_bytes::bytes2hex("0x", bucket_id.as_ref()),
+                )
+                .await?;
+                Bucket::update_total_size(conn, bucket_id.as_ref().to_vec(), total_size).await?;
+                rows_written += 1;
             }
             pallet_file_system::Event::BspConfirmStoppedStoring {
                 bsp_id,
@@ -236,6 +469,7 @@ impl IndexerService {
             } => {
                 Bsp::update_merkle_root(conn, bsp_id.to_string(), new_root.as_ref().to_vec())
                     .await?;
+                rows_written += 1;
             }
             pallet_file_system::Event::BspConfirmedStoring {
                 who: _,
@@ -246,11 +480,13 @@ impl IndexerService {
             } => {
                 Bsp::update_merkle_root(conn, bsp_id.to_string(), new_root.as_ref().to_vec())
                     .await?;
+                rows_written += 1;
 
                 let bsp = Bsp::get_by_onchain_bsp_id(conn, bsp_id.to_string()).await?;
                 for file_key in confirmed_file_keys {
                     let file = File::get_by_file_key(conn, file_key.as_ref().to_vec()).await?;
                     BspFile::create(conn, bsp.id, file.id).await?;
+                    rows_written += 1;
                 }
             }
             pallet_file_system::Event::NewStorageRequest {
@@ -261,7 +497,7 @@ impl IndexerService {
                 fingerprint,
                 size,
                 peer_ids,
-                expires_at: _,
+                expires_at,
             } => {
                 let bucket =
                     Bucket::get_by_onchain_bucket_id(conn, bucket_id.as_ref().to_vec()).await?;
@@ -269,6 +505,7 @@ impl IndexerService {
                 let mut sql_peer_ids = Vec::new();
                 for peer_id in peer_ids {
                     sql_peer_ids.push(PeerId::create(conn, peer_id.to_vec()).await?);
+                    rows_written += 1;
                 }
 
                 File::create(
@@ -281,10 +518,49 @@ impl IndexerService {
                     *size as i64,
                     FileStorageRequestStep::Requested,
                     sql_peer_ids,
+                    block_number as i64,
+                    block_hash.as_ref().to_vec(),
+                )
+                .await?;
+                // The file and one row associating it to each of its peer IDs.
+                rows_written += 1 + peer_ids.len() as u64;
+
+                StorageRequest::create(
+                    conn,
+                    file_key.as_ref().to_vec(),
+                    bucket_id.as_ref().to_vec(),
+                    <AccountId32 as AsRef<[u8]>>::as_ref(who).to_vec(),
+                    *size as i64,
+                    fingerprint.as_ref().to_vec(),
+                    location.to_vec(),
+                    block_number as i64,
+                    *expires_at as i64,
+                    block_hash.as_ref().to_vec(),
                 )
                 .await?;
+                rows_written += 1;
+            }
+            pallet_file_system::Event::MoveBucketRequested {
+                who,
+                bucket_id,
+                new_msp_id,
+                new_value_prop_id: _,
+            } => {
+                let bucket =
+                    Bucket::get_by_onchain_bucket_id(conn, bucket_id.as_ref().to_vec()).await?;
+                let new_msp = Msp::get_by_onchain_msp_id(conn, new_msp_id.to_string()).await?;
+                BucketMove::create(
+                    conn,
+                    bucket_id.as_ref().to_vec(),
+                    who.to_string(),
+                    bucket.msp_id,
+                    new_msp.id,
+                    block_number as i64,
+                    block_hash.as_ref().to_vec(),
+                )
+                .await?;
+                rows_written += 1;
             }
-            pallet_file_system::Event::MoveBucketRequested { .. } => {}
             pallet_file_system::Event::NewCollectionAndAssociation { .. } => {}
             pallet_file_system::Event::AcceptedBspVolunteer { .. } => {}
             pallet_file_system::Event::StorageRequestFulfilled { file_key } => {
@@ -294,6 +570,7 @@ impl IndexerService {
                     FileStorageRequestStep::Stored,
                 )
                 .await?;
+                rows_written += 1;
             }
             pallet_file_system::Event::StorageRequestExpired { file_key } => {
                 File::update_step(
@@ -302,14 +579,35 @@ impl IndexerService {
                     FileStorageRequestStep::Stored,
                 )
                 .await?;
+                rows_written += 1;
             }
             pallet_file_system::Event::StorageRequestRevoked { file_key } => {
                 File::delete(conn, file_key.as_ref().to_vec()).await?;
+                rows_written += 1;
+            }
+            pallet_file_system::Event::MspAcceptedStorageRequest { .. }
+            | pallet_file_system::Event::StorageRequestRejected { .. } => {
+                if let Some((file_key, rejection_reason)) = storage_request_response(event) {
+                    StorageRequestResponse::create(
+                        conn,
+                        file_key,
+                        rejection_reason,
+                        block_number as i64,
+                        block_hash.as_ref().to_vec(),
+                    )
+                    .await?;
+                    rows_written += 1;
+                }
             }
-            pallet_file_system::Event::MspAcceptedStorageRequest { .. } => {}
-            pallet_file_system::Event::StorageRequestRejected { .. } => {}
             pallet_file_system::Event::BspRequestedToStopStoring { .. } => {}
-            pallet_file_system::Event::PriorityChallengeForFileDeletionQueued { .. } => {}
+            pallet_file_system::Event::PriorityChallengeForFileDeletionQueued {
+                issuer: _,
+                file_key,
+            } => {
+                File::mark_deletion_pending(conn, file_key.as_ref().to_vec(), block_number as i64)
+                    .await?;
+                rows_written += 1;
+            }
             pallet_file_system::Event::MspStopStoringBucketInsolventUser { .. } => {
                 // TODO: Index this
             }
@@ -323,13 +621,36 @@ impl IndexerService {
                 // We are now only deleting for BSP as BSP are associating with files
                 // MSP will handle insolvent user at the level of buckets (an MSP will delete the full bucket for an insolvent user and it will produce a new kind of event)
                 BspFile::delete(conn, file_key, sp_id.to_string()).await?;
+                rows_written += 1;
             }
             pallet_file_system::Event::FailedToQueuePriorityChallenge { .. } => {}
             pallet_file_system::Event::FileDeletionRequest { .. } => {}
             pallet_file_system::Event::ProofSubmittedForPendingFileDeletionRequest { .. } => {}
             pallet_file_system::Event::BspChallengeCycleInitialised { .. } => {}
-            pallet_file_system::Event::MoveBucketRequestExpired { .. } => {}
-            pallet_file_system::Event::MoveBucketRejected { .. } => {}
+            pallet_file_system::Event::MoveBucketRequestExpired { bucket_id } => {
+                BucketMove::resolve(
+                    conn,
+                    bucket_id.as_ref().to_vec(),
+                    BucketMoveStatus::Expired,
+                    block_number as i64,
+                )
+                .await?;
+                rows_written += 1;
+            }
+            pallet_file_system::Event::MoveBucketRejected {
+                bucket_id,
+                old_msp_id: _,
+                new_msp_id: _,
+            } => {
+                BucketMove::resolve(
+                    conn,
+                    bucket_id.as_ref().to_vec(),
+                    BucketMoveStatus::Rejected,
+                    block_number as i64,
+                )
+                .await?;
+                rows_written += 1;
+            }
             pallet_file_system::Event::MspStoppedStoringBucket { .. } => {}
             pallet_file_system::Event::BucketDeleted {
                 who: _,
@@ -337,6 +658,7 @@ impl IndexerService {
                 maybe_collection_id: _,
             } => {
                 Bucket::delete(conn, bucket_id.as_ref().to_vec()).await?;
+                rows_written += 1;
             }
             pallet_file_system::Event::FailedToGetMspOfBucket { .. } => {}
             pallet_file_system::Event::FailedToDecreaseMspUsedCapacity { .. } => {}
@@ -351,22 +673,40 @@ impl IndexerService {
             }
             pallet_file_system::Event::__Ignore(_, _) => {}
         }
-        Ok(())
+
+        // Every event that ends a storage request closes it, whatever else it indexes.
+        if let Some((file_key, status)) = closed_storage_request(event) {
+            StorageRequest::close(conn, file_key, status, block_number as i64).await?;
+            rows_written += 1;
+        }
+
+        Ok(rows_written)
     }
 
     async fn index_payment_streams_event<'a, 'b: 'a>(
         &'b self,
         conn: &mut DbConnection<'a>,
         event: &pallet_payment_streams::Event<storage_hub_runtime::Runtime>,
-    ) -> Result<(), diesel::result::Error> {
+        block_number: BlockNumber,
+        block_hash: H256,
+    ) -> Result<u64, diesel::result::Error> {
+        let mut rows_written = 0;
+
         match event {
             pallet_payment_streams::Event::DynamicRatePaymentStreamCreated {
                 provider_id,
                 user_account,
                 amount_provided: _amount_provided,
             } => {
-                PaymentStream::create(conn, user_account.to_string(), provider_id.to_string())
-                    .await?;
+                PaymentStream::create(
+                    conn,
+                    user_account.to_string(),
+                    provider_id.to_string(),
+                    block_number as i64,
+                    block_hash.as_ref().to_vec(),
+                )
+                .await?;
+                rows_written += 1;
             }
             pallet_payment_streams::Event::DynamicRatePaymentStreamUpdated { .. } => {
                 // TODO: Currently we are not treating the info of dynamic rate update
@@ -377,8 +717,15 @@ impl IndexerService {
                 user_account,
                 rate: _rate,
             } => {
-                PaymentStream::create(conn, user_account.to_string(), provider_id.to_string())
-                    .await?;
+                PaymentStream::create(
+                    conn,
+                    user_account.to_string(),
+                    provider_id.to_string(),
+                    block_number as i64,
+                    block_hash.as_ref().to_vec(),
+                )
+                .await?;
+                rows_written += 1;
             }
             pallet_payment_streams::Event::FixedRatePaymentStreamUpdated { .. } => {
                 // TODO: Currently we are not treating the info of fixed rate update
@@ -406,24 +753,47 @@ impl IndexerService {
                     charged_at_tick,
                 )
                 .await?;
+                rows_written += 1;
             }
             pallet_payment_streams::Event::UsersCharged { .. } => {}
             pallet_payment_streams::Event::LastChargeableInfoUpdated { .. } => {}
-            pallet_payment_streams::Event::UserWithoutFunds { .. } => {}
+            pallet_payment_streams::Event::UserWithoutFunds { who } => {
+                PaymentStream::update_status_for_account(
+                    conn,
+                    who.to_string(),
+                    PaymentStreamStatus::Active,
+                    PaymentStreamStatus::UserInsolvent,
+                )
+                .await?;
+                rows_written += 1;
+            }
             pallet_payment_streams::Event::UserPaidAllDebts { .. } => {}
             pallet_payment_streams::Event::UserPaidSomeDebts { .. } => {}
-            pallet_payment_streams::Event::UserSolvent { .. } => {}
+            pallet_payment_streams::Event::UserSolvent { who } => {
+                PaymentStream::update_status_for_account(
+                    conn,
+                    who.to_string(),
+                    PaymentStreamStatus::UserInsolvent,
+                    PaymentStreamStatus::Active,
+                )
+                .await?;
+                rows_written += 1;
+            }
             pallet_payment_streams::Event::InconsistentTickProcessing { .. } => {}
             pallet_payment_streams::Event::__Ignore(_, _) => {}
         }
-        Ok(())
+        Ok(rows_written)
     }
 
     async fn index_proofs_dealer_event<'a, 'b: 'a>(
         &'b self,
         conn: &mut DbConnection<'a>,
         event: &pallet_proofs_dealer::Event<storage_hub_runtime::Runtime>,
-    ) -> Result<(), diesel::result::Error> {
+        block_number: BlockNumber,
+        block_hash: H256,
+    ) -> Result<u64, diesel::result::Error> {
+        let mut rows_written = 0;
+
         match event {
             pallet_proofs_dealer::Event::MutationsAppliedForProvider { .. } => {}
             pallet_proofs_dealer::Event::MutationsApplied { .. } => {}
@@ -439,24 +809,50 @@ impl IndexerService {
                     (*last_tick_proven).into(),
                 )
                 .await?;
+                rows_written += 1;
             }
             pallet_proofs_dealer::Event::NewChallengeSeed { .. } => {}
-            pallet_proofs_dealer::Event::NewCheckpointChallenge { .. } => {}
+            pallet_proofs_dealer::Event::NewCheckpointChallenge {
+                challenges_ticker,
+                challenges,
+            } => {
+                for challenge in challenges {
+                    let trie_mutation_type = if challenge.should_remove_key {
+                        TrieMutationType::Remove
+                    } else {
+                        TrieMutationType::Retain
+                    };
+
+                    CheckpointChallenge::create(
+                        conn,
+                        (*challenges_ticker).into(),
+                        challenge.key.as_ref().to_vec(),
+                        trie_mutation_type,
+                        block_number as i64,
+                        block_hash.as_ref().to_vec(),
+                    )
+                    .await?;
+                    rows_written += 1;
+                }
+            }
             pallet_proofs_dealer::Event::SlashableProvider { .. } => {}
             pallet_proofs_dealer::Event::NoRecordOfLastSubmittedProof { .. } => {}
             pallet_proofs_dealer::Event::NewChallengeCycleInitialised { .. } => {}
             pallet_proofs_dealer::Event::ChallengesTickerSet { .. } => {}
             pallet_proofs_dealer::Event::__Ignore(_, _) => {}
         }
-        Ok(())
+        Ok(rows_written)
     }
 
     async fn index_providers_event<'a, 'b: 'a>(
         &'b self,
         conn: &mut DbConnection<'a>,
         event: &pallet_storage_providers::Event<storage_hub_runtime::Runtime>,
+        block_number: BlockNumber,
         block_hash: H256,
-    ) -> Result<(), diesel::result::Error> {
+    ) -> Result<u64, diesel::result::Error> {
+        let mut rows_written = 0;
+
         match event {
             pallet_storage_providers::Event::BspRequestSignUpSuccess { .. } => {}
             pallet_storage_providers::Event::BspSignUpSuccess {
@@ -465,6 +861,7 @@ impl IndexerService {
                 root,
                 multiaddresses,
                 capacity,
+                payment_account,
             } => {
                 let stake = self
                     .client
@@ -479,11 +876,14 @@ impl IndexerService {
                     if let Some(multiaddr) = convert_raw_multiaddress_to_multiaddr(multiaddress) {
                         sql_multiaddresses
                             .push(MultiAddress::create(conn, multiaddr.to_vec()).await?);
+                        rows_written += 1;
                     } else {
                         error!(target: LOG_TARGET, "Failed to parse multiaddr");
                     }
                 }
 
+                // The provider and one row associating it to each of its multiaddresses.
+                let provider_rows = 1 + sql_multiaddresses.len() as u64;
                 Bsp::create(
                     conn,
                     who.to_string(),
@@ -492,14 +892,19 @@ impl IndexerService {
                     sql_multiaddresses,
                     bsp_id.to_string(),
                     stake,
+                    payment_account.to_string(),
+                    block_number as i64,
+                    block_hash.as_ref().to_vec(),
                 )
                 .await?;
+                rows_written += provider_rows;
             }
             pallet_storage_providers::Event::BspSignOffSuccess {
                 who,
                 bsp_id: _bsp_id,
             } => {
                 Bsp::delete(conn, who.to_string()).await?;
+                rows_written += 1;
             }
             pallet_storage_providers::Event::CapacityChanged {
                 who,
@@ -510,6 +915,7 @@ impl IndexerService {
             } => match provider_id {
                 StorageProviderId::BackupStorageProvider(bsp_id) => {
                     Bsp::update_capacity(conn, who.to_string(), new_capacity.into()).await?;
+                    rows_written += 1;
 
                     // update also the stake
                     let stake = self
@@ -521,9 +927,11 @@ impl IndexerService {
                         .into();
 
                     Bsp::update_stake(conn, bsp_id.to_string(), stake).await?;
+                    rows_written += 1;
                 }
                 StorageProviderId::MainStorageProvider(_) => {
                     Bsp::update_capacity(conn, who.to_string(), new_capacity.into()).await?;
+                    rows_written += 1;
                 }
             },
             pallet_storage_providers::Event::SignUpRequestCanceled { .. } => {}
@@ -534,35 +942,46 @@ impl IndexerService {
                 multiaddresses,
                 capacity,
                 value_prop,
+                payment_account,
             } => {
                 let mut sql_multiaddresses = Vec::new();
                 for multiaddress in multiaddresses {
                     if let Some(multiaddr) = convert_raw_multiaddress_to_multiaddr(multiaddress) {
                         sql_multiaddresses
                             .push(MultiAddress::create(conn, multiaddr.to_vec()).await?);
+                        rows_written += 1;
                     } else {
                         error!(target: LOG_TARGET, "Failed to parse multiaddr");
                     }
                 }
 
-                // TODO: update value prop after properly defined in runtime
-                let value_prop = format!("{value_prop:?}");
+                let value_prop_encoded = self
+                    .store_encoded_value_props
+                    .then(|| value_prop_to_encoded(value_prop));
 
+                // The provider and one row associating it to each of its multiaddresses.
+                let provider_rows = 1 + sql_multiaddresses.len() as u64;
                 Msp::create(
                     conn,
                     who.to_string(),
                     capacity.into(),
-                    value_prop,
+                    value_prop_to_json(value_prop),
+                    value_prop_encoded,
                     sql_multiaddresses,
                     msp_id.to_string(),
+                    payment_account.to_string(),
+                    block_number as i64,
+                    block_hash.as_ref().to_vec(),
                 )
                 .await?;
+                rows_written += provider_rows;
             }
             pallet_storage_providers::Event::MspSignOffSuccess {
                 who,
                 msp_id: _msp_id,
             } => {
                 Msp::delete(conn, who.to_string()).await?;
+                rows_written += 1;
             }
             pallet_storage_providers::Event::BucketRootChanged {
                 bucket_id,
@@ -575,6 +994,7 @@ impl IndexerService {
                     new_root.as_ref().to_vec(),
                 )
                 .await?;
+                rows_written += 1;
             }
             pallet_storage_providers::Event::Slashed { .. } => {}
             pallet_storage_providers::Event::AwaitingTopUp {
@@ -590,6 +1010,7 @@ impl IndexerService {
                     .into();
 
                 Bsp::update_stake(conn, provider_id.to_string(), stake).await?;
+                rows_written += 1;
             }
             pallet_storage_providers::Event::TopUpFulfilled { .. } => {}
             pallet_storage_providers::Event::ValuePropAdded { .. } => {}
@@ -602,9 +1023,11 @@ impl IndexerService {
             }
             pallet_storage_providers::Event::MspDeleted { provider_id } => {
                 Msp::delete(conn, provider_id.to_string()).await?;
+                rows_written += 1;
             }
             pallet_storage_providers::Event::BspDeleted { provider_id } => {
                 Bsp::delete(conn, provider_id.to_string()).await?;
+                rows_written += 1;
             }
             pallet_storage_providers::Event::FailedToGetOwnerAccountOfInsolventProvider {
                 ..
@@ -622,19 +1045,19 @@ impl IndexerService {
             }
             pallet_storage_providers::Event::__Ignore(_, _) => {}
         }
-        Ok(())
+        Ok(rows_written)
     }
 
     async fn index_randomness_event<'a, 'b: 'a>(
         &'b self,
         _conn: &mut DbConnection<'a>,
         event: &pallet_randomness::Event<storage_hub_runtime::Runtime>,
-    ) -> Result<(), diesel::result::Error> {
+    ) -> Result<u64, diesel::result::Error> {
         match event {
             pallet_randomness::Event::NewOneEpochAgoRandomnessAvailable { .. } => {}
             pallet_randomness::Event::__Ignore(_, _) => {}
         }
-        Ok(())
+        Ok(0)
     }
 }
 
@@ -707,8 +1130,38 @@ pub enum HandleFinalityNotificationError {
     BlockHashNotFound,
     #[error("Index block error: {0}")]
     IndexBlockError(#[from] IndexBlockError),
+    #[error("Reorg error: {0}")]
+    ReorgError(#[from] HandleReorgError),
     #[error("Client error: {0}")]
     ClientError(#[from] sp_blockchain::Error),
     #[error("Pool run error: {0}")]
     PoolRunError(#[from] diesel_async::pooled_connection::bb8::RunError),
 }
+
+#[derive(Error, Debug)]
+pub enum HandleReorgError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] diesel::result::Error),
+    #[error("Orphaned block not found: {0}")]
+    BlockNotFound(H256),
+    #[error("Client error: {0}")]
+    ClientError(#[from] sp_blockchain::Error),
+}
+
+#[derive(Error, Debug)]
+pub enum PurgeOldRecordsError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] diesel::result::Error),
+    #[error("Pool run error: {0}")]
+    PoolRunError(#[from] diesel_async::pooled_connection::bb8::RunError),
+    #[error("Maximum age of records to keep cannot be negative: {0}")]
+    NegativeMaxAge(i64),
+}
+
+#[derive(Error, Debug)]
+pub enum GetUserPaymentStreamsError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] diesel::result::Error),
+    #[error("Pool run error: {0}")]
+    PoolRunError(#[from] diesel_async::pooled_connection::bb8::RunError),
+}
@@ -1,10 +1,18 @@
+pub mod commands;
 pub mod handler;
+pub mod metrics;
+pub mod storage_request_response;
+pub mod value_prop;
 
+use log::error;
 use std::sync::Arc;
 
 use shc_actors_framework::actor::{ActorHandle, ActorSpawner, TaskSpawner};
 use shc_common::types::ParachainClient;
 use shc_indexer_db::DbPool;
+use substrate_prometheus_endpoint::Registry;
+
+use crate::{handler::LOG_TARGET, metrics::IndexerMetrics};
 
 pub use self::handler::IndexerService;
 
@@ -12,12 +20,21 @@ pub async fn spawn_indexer_service(
     task_spawner: &TaskSpawner,
     client: Arc<ParachainClient>,
     db_pool: DbPool,
+    store_encoded_value_props: bool,
+    prometheus_registry: Option<&Registry>,
 ) -> ActorHandle<IndexerService> {
     let task_spawner = task_spawner
         .with_name("indexer-service")
         .with_group("network");
 
-    let indexer_service = IndexerService::new(client, db_pool);
+    // Metrics are optional, so failing to register them shouldn't prevent indexing.
+    let metrics = prometheus_registry.and_then(|registry| {
+        IndexerMetrics::register(registry)
+            .map_err(|e| error!(target: LOG_TARGET, "Failed to register indexer metrics: {:?}", e))
+            .ok()
+    });
+
+    let indexer_service = IndexerService::new(client, db_pool, store_encoded_value_props, metrics);
 
     task_spawner.spawn_actor(indexer_service)
 }
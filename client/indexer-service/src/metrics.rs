@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+
+use substrate_prometheus_endpoint::{
+    register, Counter, CounterVec, Opts, PrometheusError, Registry, U64,
+};
+
+/// Prometheus metrics of the indexer, to keep an eye on its load.
+#[derive(Clone)]
+pub struct IndexerMetrics {
+    /// Number of blocks indexed.
+    blocks_indexed: Counter<U64>,
+    /// Number of database rows inserted, updated or deleted while indexing, by pallet.
+    rows_written: CounterVec<U64>,
+}
+
+impl IndexerMetrics {
+    /// Creates the indexer metrics and registers them in `registry`.
+    pub fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+        Ok(Self {
+            blocks_indexed: register(
+                Counter::new(
+                    "storagehub_indexer_blocks_indexed_total",
+                    "Number of blocks indexed",
+                )?,
+                registry,
+            )?,
+            rows_written: register(
+                CounterVec::new(
+                    Opts::new(
+                        "storagehub_indexer_rows_written_total",
+                        "Number of database rows inserted, updated or deleted while indexing events",
+                    ),
+                    &["pallet"],
+                )?,
+                registry,
+            )?,
+        })
+    }
+
+    /// Records an indexed block and the rows written for it.
+    ///
+    /// Should only be called once the block has been committed to the database, so that blocks
+    /// rolled back are not counted.
+    pub(crate) fn record_block(&self, rows_written: &RowsWritten) {
+        self.blocks_indexed.inc();
+        for (pallet, rows) in &rows_written.0 {
+            self.rows_written.with_label_values(&[pallet]).inc_by(*rows);
+        }
+    }
+}
+
+/// Rows written to the database while indexing a block, by pallet.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct RowsWritten(BTreeMap<&'static str, u64>);
+
+impl RowsWritten {
+    /// Adds `rows` rows written while indexing an event of `pallet`.
+    pub(crate) fn add(&mut self, pallet: &'static str, rows: u64) {
+        if rows > 0 {
+            *self.0.entry(pallet).or_default() += rows;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indexing_a_block_increments_the_counters_of_its_pallets() {
+        let registry = Registry::new();
+        let metrics = IndexerMetrics::register(&registry).expect("Metrics are registered once");
+
+        // A block with a new bucket and a storage request for a file with two peer IDs, and a
+        // payment stream being charged.
+        let mut rows_written = RowsWritten::default();
+        rows_written.add("file_system", 1);
+        rows_written.add("file_system", 3);
+        rows_written.add("payment_streams", 1);
+        // Events that are not indexed don't write anything.
+        rows_written.add("randomness", 0);
+        metrics.record_block(&rows_written);
+
+        assert_eq!(metrics.blocks_indexed.get(), 1);
+        assert_eq!(
+            metrics
+                .rows_written
+                .with_label_values(&["file_system"])
+                .get(),
+            4
+        );
+        assert_eq!(
+            metrics
+                .rows_written
+                .with_label_values(&["payment_streams"])
+                .get(),
+            1
+        );
+
+        // Pallets without rows written have no series at all.
+        let series = registry
+            .gather()
+            .into_iter()
+            .find(|family| family.get_name() == "storagehub_indexer_rows_written_total")
+            .expect("Rows written are exported")
+            .get_metric()
+            .len();
+        assert_eq!(series, 2);
+
+        metrics.record_block(&RowsWritten::default());
+        assert_eq!(metrics.blocks_indexed.get(), 2);
+        assert_eq!(
+            metrics
+                .rows_written
+                .with_label_values(&["file_system"])
+                .get(),
+            4
+        );
+    }
+}
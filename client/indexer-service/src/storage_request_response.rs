@@ -0,0 +1,220 @@
+use pallet_file_system::types::RejectedStorageRequestReason;
+use shc_indexer_db::models::{StorageRequestRejectionReason, StorageRequestStatus};
+use storage_hub_runtime::Runtime;
+
+/// Maps a rejection reason emitted by the runtime to the one stored in the
+/// `storage_request_response` table.
+pub fn rejection_reason_to_db(
+    reason: &RejectedStorageRequestReason,
+) -> StorageRequestRejectionReason {
+    match reason {
+        RejectedStorageRequestReason::ReachedMaximumCapacity => {
+            StorageRequestRejectionReason::ReachedMaximumCapacity
+        }
+        RejectedStorageRequestReason::ReceivedInvalidProof => {
+            StorageRequestRejectionReason::ReceivedInvalidProof
+        }
+        RejectedStorageRequestReason::FileKeyAlreadyStored => {
+            StorageRequestRejectionReason::FileKeyAlreadyStored
+        }
+        RejectedStorageRequestReason::RequestExpired => {
+            StorageRequestRejectionReason::RequestExpired
+        }
+        RejectedStorageRequestReason::InternalError => StorageRequestRejectionReason::InternalError,
+        RejectedStorageRequestReason::ReachedBucketDataLimit => {
+            StorageRequestRejectionReason::ReachedBucketDataLimit
+        }
+        RejectedStorageRequestReason::MaxConcurrentUploadsReached => {
+            StorageRequestRejectionReason::MaxConcurrentUploadsReached
+        }
+    }
+}
+
+/// The response to a storage request recorded for a file system event, as
+/// `(file_key, rejection_reason)`, with a `None` rejection reason if the storage request was
+/// accepted.
+///
+/// Returns `None` for events that are not a response to a storage request.
+pub fn storage_request_response(
+    event: &pallet_file_system::Event<Runtime>,
+) -> Option<(Vec<u8>, Option<StorageRequestRejectionReason>)> {
+    match event {
+        pallet_file_system::Event::MspAcceptedStorageRequest { file_key } => {
+            Some((file_key.as_ref().to_vec(), None))
+        }
+        pallet_file_system::Event::StorageRequestRejected { file_key, reason } => Some((
+            file_key.as_ref().to_vec(),
+            Some(rejection_reason_to_db(reason)),
+        )),
+        _ => None,
+    }
+}
+
+/// The storage request closed by a file system event, as `(file_key, status)`.
+///
+/// Returns `None` for events that don't end a storage request.
+pub fn closed_storage_request(
+    event: &pallet_file_system::Event<Runtime>,
+) -> Option<(Vec<u8>, StorageRequestStatus)> {
+    let (file_key, status) = match event {
+        pallet_file_system::Event::StorageRequestFulfilled { file_key } => {
+            (file_key, StorageRequestStatus::Fulfilled)
+        }
+        pallet_file_system::Event::StorageRequestExpired { file_key } => {
+            (file_key, StorageRequestStatus::Expired)
+        }
+        pallet_file_system::Event::StorageRequestRevoked { file_key } => {
+            (file_key, StorageRequestStatus::Revoked)
+        }
+        pallet_file_system::Event::StorageRequestRejected { file_key, .. } => {
+            (file_key, StorageRequestStatus::Rejected)
+        }
+        _ => return None,
+    };
+    Some((file_key.as_ref().to_vec(), status))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashSet};
+
+    use shc_indexer_db::models::StorageRequestResponseCounts;
+    use sp_core::H256;
+
+    use super::*;
+
+    const ALL_REASONS: [RejectedStorageRequestReason; 7] = [
+        RejectedStorageRequestReason::ReachedMaximumCapacity,
+        RejectedStorageRequestReason::ReceivedInvalidProof,
+        RejectedStorageRequestReason::FileKeyAlreadyStored,
+        RejectedStorageRequestReason::RequestExpired,
+        RejectedStorageRequestReason::InternalError,
+        RejectedStorageRequestReason::ReachedBucketDataLimit,
+        RejectedStorageRequestReason::MaxConcurrentUploadsReached,
+    ];
+
+    #[test]
+    fn rejection_reasons_map_to_distinct_stable_codes() {
+        let mapped: Vec<_> = ALL_REASONS.iter().map(rejection_reason_to_db).collect();
+
+        // The codes are stored in the database, so they must never change.
+        assert_eq!(
+            mapped
+                .iter()
+                .map(|reason| reason.code())
+                .collect::<Vec<_>>(),
+            (0..7).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            mapped
+                .iter()
+                .map(|reason| reason.as_str())
+                .collect::<HashSet<_>>()
+                .len(),
+            mapped.len()
+        );
+        for reason in mapped {
+            assert_eq!(
+                StorageRequestRejectionReason::from_code(reason.code()),
+                Some(reason)
+            );
+        }
+    }
+
+    #[test]
+    fn mixed_accept_and_reject_responses_are_counted_by_reason() {
+        let file_key = |i: u8| H256::repeat_byte(i);
+        let events: Vec<pallet_file_system::Event<Runtime>> = vec![
+            pallet_file_system::Event::MspAcceptedStorageRequest {
+                file_key: file_key(0),
+            },
+            pallet_file_system::Event::StorageRequestRejected {
+                file_key: file_key(1),
+                reason: RejectedStorageRequestReason::ReachedMaximumCapacity,
+            },
+            pallet_file_system::Event::MspAcceptedStorageRequest {
+                file_key: file_key(2),
+            },
+            pallet_file_system::Event::StorageRequestRejected {
+                file_key: file_key(3),
+                reason: RejectedStorageRequestReason::ReachedMaximumCapacity,
+            },
+            pallet_file_system::Event::StorageRequestRejected {
+                file_key: file_key(4),
+                reason: RejectedStorageRequestReason::RequestExpired,
+            },
+            // Not a response to a storage request.
+            pallet_file_system::Event::StorageRequestExpired {
+                file_key: file_key(4),
+            },
+        ];
+
+        let responses: Vec<_> = events.iter().filter_map(storage_request_response).collect();
+        assert_eq!(responses.len(), 5);
+        assert_eq!(responses[0], (file_key(0).as_ref().to_vec(), None));
+        assert_eq!(
+            responses[1],
+            (
+                file_key(1).as_ref().to_vec(),
+                Some(StorageRequestRejectionReason::ReachedMaximumCapacity)
+            )
+        );
+
+        // Group the stored `rejection_reason` column the way the counting query does.
+        let mut rows = BTreeMap::<Option<i32>, i64>::new();
+        for (_, reason) in &responses {
+            *rows.entry(reason.map(|reason| reason.code())).or_default() += 1;
+        }
+        let counts = StorageRequestResponseCounts::from_rows(rows);
+
+        assert_eq!(counts.accepted, 2);
+        assert_eq!(counts.rejected, 3);
+        assert_eq!(counts.total(), 5);
+        assert_eq!(
+            counts.rejected_by_reason,
+            BTreeMap::from([
+                (StorageRequestRejectionReason::ReachedMaximumCapacity, 2),
+                (StorageRequestRejectionReason::RequestExpired, 1),
+            ])
+        );
+        assert_eq!(
+            counts.rejected_percentage(StorageRequestRejectionReason::ReachedMaximumCapacity),
+            40.0
+        );
+        assert_eq!(
+            counts.rejected_percentage(StorageRequestRejectionReason::InternalError),
+            0.0
+        );
+    }
+
+    #[test]
+    fn every_terminal_event_closes_its_storage_request() {
+        let file_key = H256::repeat_byte(1);
+        let closed = |event: pallet_file_system::Event<Runtime>| closed_storage_request(&event);
+
+        assert_eq!(
+            closed(pallet_file_system::Event::StorageRequestFulfilled { file_key }),
+            Some((file_key.as_ref().to_vec(), StorageRequestStatus::Fulfilled))
+        );
+        assert_eq!(
+            closed(pallet_file_system::Event::StorageRequestExpired { file_key }),
+            Some((file_key.as_ref().to_vec(), StorageRequestStatus::Expired))
+        );
+        assert_eq!(
+            closed(pallet_file_system::Event::StorageRequestRevoked { file_key }),
+            Some((file_key.as_ref().to_vec(), StorageRequestStatus::Revoked))
+        );
+        for reason in ALL_REASONS {
+            assert_eq!(
+                closed(pallet_file_system::Event::StorageRequestRejected { file_key, reason }),
+                Some((file_key.as_ref().to_vec(), StorageRequestStatus::Rejected))
+            );
+        }
+
+        // Accepting a storage request doesn't end it, the BSPs may still have to store the file.
+        assert_eq!(
+            closed(pallet_file_system::Event::MspAcceptedStorageRequest { file_key }),
+            None
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::anyhow;
+use codec::{Decode, Encode};
+use pallet_storage_providers::types::{ValueProposition, ValuePropositionWithId};
+use shc_indexer_db::models::MspValueProposition;
+use sp_core::H256;
+use storage_hub_runtime::Runtime;
+
+/// Converts a value proposition emitted by the runtime into its JSON representation, as stored
+/// in the `value_prop` column of the `msp` table.
+pub fn value_prop_to_json(value_prop: &ValuePropositionWithId<Runtime>) -> serde_json::Value {
+    let json = MspValueProposition {
+        id: array_bytes::bytes2hex("0x", value_prop.id.as_bytes()),
+        price_per_giga_unit_of_data_per_block: value_prop
+            .value_prop
+            .price_per_giga_unit_of_data_per_block
+            .to_string(),
+        commitment: array_bytes::bytes2hex("0x", value_prop.value_prop.commitment.as_slice()),
+        bucket_data_limit: value_prop.value_prop.bucket_data_limit,
+        available: value_prop.value_prop.available,
+    };
+
+    serde_json::to_value(json).expect("MspValueProposition only holds JSON-compatible types; qed")
+}
+
+/// Reconstructs a value proposition from the JSON representation stored in the `value_prop`
+/// column of the `msp` table.
+pub fn value_prop_from_json(
+    json: serde_json::Value,
+) -> anyhow::Result<ValuePropositionWithId<Runtime>> {
+    let json: MspValueProposition = serde_json::from_value(json)?;
+
+    let id = array_bytes::hex2bytes(&json.id).map_err(|e| anyhow!("Invalid id: {:?}", e))?;
+    if id.len() != H256::len_bytes() {
+        return Err(anyhow!("Invalid id length: {}", id.len()));
+    }
+    let commitment = array_bytes::hex2bytes(&json.commitment)
+        .map_err(|e| anyhow!("Invalid commitment: {:?}", e))?
+        .try_into()
+        .map_err(|_| anyhow!("Commitment exceeds the maximum commitment size"))?;
+
+    Ok(ValuePropositionWithId::new(
+        H256::from_slice(&id),
+        ValueProposition {
+            price_per_giga_unit_of_data_per_block: json
+                .price_per_giga_unit_of_data_per_block
+                .parse()?,
+            commitment,
+            bucket_data_limit: json.bucket_data_limit,
+            available: json.available,
+        },
+    ))
+}
+
+/// SCALE-encodes a value proposition, as stored in the `value_prop_encoded` column of the `msp`
+/// table.
+pub fn value_prop_to_encoded(value_prop: &ValuePropositionWithId<Runtime>) -> Vec<u8> {
+    value_prop.encode()
+}
+
+/// Decodes a value proposition stored in the `value_prop_encoded` column of the `msp` table.
+pub fn value_prop_from_encoded(
+    mut encoded: &[u8],
+) -> Result<ValuePropositionWithId<Runtime>, codec::Error> {
+    ValuePropositionWithId::decode(&mut encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value_prop() -> ValuePropositionWithId<Runtime> {
+        let mut value_prop = ValueProposition::<Runtime>::new(
+            // Larger than `u64::MAX` and than what a JSON number can represent exactly.
+            u128::MAX - 1,
+            b"commitment".to_vec().try_into().unwrap(),
+            1024 * 1024 * 1024,
+        );
+        value_prop.available = false;
+
+        ValuePropositionWithId::new(value_prop.derive_id(), value_prop)
+    }
+
+    #[test]
+    fn json_and_encoded_value_prop_decode_to_the_same_value_prop() {
+        let value_prop = value_prop();
+
+        let json = value_prop_to_json(&value_prop);
+        let encoded = value_prop_to_encoded(&value_prop);
+
+        // The JSON columns are queryable without knowing about SCALE.
+        assert_eq!(json["bucket_data_limit"], 1024 * 1024 * 1024);
+        assert_eq!(json["available"], false);
+        assert_eq!(
+            json["price_per_giga_unit_of_data_per_block"],
+            (u128::MAX - 1).to_string()
+        );
+
+        let from_json = value_prop_from_json(json).unwrap();
+        let from_encoded = value_prop_from_encoded(&encoded).unwrap();
+
+        assert_eq!(from_json, value_prop);
+        assert_eq!(from_encoded, value_prop);
+        assert_eq!(from_json, from_encoded);
+    }
+
+    #[test]
+    fn value_prop_from_json_rejects_invalid_id() {
+        let mut json = value_prop_to_json(&value_prop());
+        json["id"] = serde_json::Value::String("0x1234".to_string());
+
+        assert!(value_prop_from_json(json).is_err());
+    }
+}
@@ -0,0 +1,367 @@
+//! Proofs that this node holds some chunks of a file, generated on request so that users and
+//! auditors can check them without waiting for the provider to be challenged on-chain.
+
+use std::{
+    collections::HashSet,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use sp_core::{Bytes, Decode, Encode, H256};
+use sp_runtime::{Deserialize, Serialize};
+
+use shc_common::types::{
+    ChunkId, FileKeyProof, HashT, ProvenFileKeyError, StorageProofsMerkleTrieLayout,
+};
+use shc_file_manager::traits::{FileStorage, FileStorageError};
+
+/// Maximum number of chunks proven by this node within [`FILE_PROOF_RATE_LIMIT_WINDOW`].
+pub const MAX_PROVEN_CHUNKS_PER_WINDOW: u64 = 1024;
+
+/// Window over which the chunks proven by this node are rate limited.
+pub const FILE_PROOF_RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Result of verifying a file proof through RPC.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VerifiedFileProof {
+    /// Key of the file, as derived from the metadata in the proof.
+    pub file_key: H256,
+    /// IDs of the chunks proven to be in the file, in ascending order.
+    pub chunk_ids: Vec<u64>,
+}
+
+#[derive(Debug)]
+pub enum FileProofError {
+    /// No chunk IDs were given to generate a proof for.
+    NoChunksRequested,
+    /// Proving the requested chunks would go over the limit of the current window.
+    RateLimited { requested: u64, max_per_window: u64 },
+    /// The file or some of the requested chunks are not in the File Storage.
+    FailedToGenerateProof(FileStorageError),
+    /// The proof is not a SCALE-encoded [`FileKeyProof`].
+    FailedToDecodeProof,
+    /// The proof is for a file with another fingerprint than the expected one.
+    FingerprintMismatch { expected: H256, found: H256 },
+    /// The proof does not match the fingerprint of its file.
+    InvalidProof(ProvenFileKeyError),
+}
+
+impl fmt::Display for FileProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileProofError::NoChunksRequested => write!(f, "No chunk IDs to prove"),
+            FileProofError::RateLimited {
+                requested,
+                max_per_window,
+            } => write!(
+                f,
+                "Proving {} chunks would exceed the limit of {} chunks every {} seconds, try again later",
+                requested,
+                max_per_window,
+                FILE_PROOF_RATE_LIMIT_WINDOW.as_secs()
+            ),
+            FileProofError::FailedToGenerateProof(e) => {
+                write!(f, "Failed to generate file proof: {:?}", e)
+            }
+            FileProofError::FailedToDecodeProof => write!(f, "Failed to decode file proof"),
+            FileProofError::FingerprintMismatch { expected, found } => write!(
+                f,
+                "File proof is for fingerprint {:?} instead of {:?}",
+                found, expected
+            ),
+            FileProofError::InvalidProof(e) => write!(f, "Invalid file proof: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for FileProofError {}
+
+/// Limits the chunks proven by this node within a fixed window, as building a proof means
+/// reading every proven chunk from the File Storage.
+///
+/// Shared between all the RPC connections of the node.
+#[derive(Debug, Clone)]
+pub struct FileProofRateLimiter {
+    max_chunks_per_window: u64,
+    window: Duration,
+    /// Start of the current window, and chunks proven in it.
+    proven: Arc<Mutex<Option<(Instant, u64)>>>,
+}
+
+impl Default for FileProofRateLimiter {
+    fn default() -> Self {
+        Self::new(MAX_PROVEN_CHUNKS_PER_WINDOW, FILE_PROOF_RATE_LIMIT_WINDOW)
+    }
+}
+
+impl FileProofRateLimiter {
+    pub fn new(max_chunks_per_window: u64, window: Duration) -> Self {
+        Self {
+            max_chunks_per_window,
+            window,
+            proven: Default::default(),
+        }
+    }
+
+    /// Records proving `chunks` at `now`, unless that would exceed the limit of the current
+    /// window. Returns the start of the window they were recorded in.
+    fn try_prove(&self, chunks: u64, now: Instant) -> Result<Instant, FileProofError> {
+        let mut proven = self
+            .proven
+            .lock()
+            .expect("File proof rate limiter lock poisoned");
+
+        let (start, in_window) = match *proven {
+            Some((start, in_window)) if now.saturating_duration_since(start) < self.window => {
+                (start, in_window)
+            }
+            _ => {
+                *proven = Some((now, 0));
+                (now, 0)
+            }
+        };
+
+        let in_window = in_window.saturating_add(chunks);
+        if in_window > self.max_chunks_per_window {
+            return Err(FileProofError::RateLimited {
+                requested: chunks,
+                max_per_window: self.max_chunks_per_window,
+            });
+        }
+
+        *proven = Some((start, in_window));
+        Ok(start)
+    }
+
+    /// Gives back `chunks` recorded by [`Self::try_prove`] in the window starting at
+    /// `window_start`, if it is still the current one, as they were not proven after all.
+    fn refund(&self, chunks: u64, window_start: Instant) {
+        let mut proven = self
+            .proven
+            .lock()
+            .expect("File proof rate limiter lock poisoned");
+
+        if let Some((start, proven)) = proven.as_mut() {
+            if *start == window_start {
+                *proven = proven.saturating_sub(chunks);
+            }
+        }
+    }
+}
+
+/// Generates a proof of the chunks `chunk_ids` of the file `file_key` in `file_storage`, and
+/// SCALE-encodes it.
+pub fn generate_file_proof<FL>(
+    file_storage: &FL,
+    rate_limiter: &FileProofRateLimiter,
+    file_key: &H256,
+    chunk_ids: &[u64],
+) -> Result<Bytes, FileProofError>
+where
+    FL: FileStorage<StorageProofsMerkleTrieLayout>,
+{
+    let chunk_ids: HashSet<ChunkId> = chunk_ids.iter().copied().map(ChunkId::new).collect();
+    if chunk_ids.is_empty() {
+        return Err(FileProofError::NoChunksRequested);
+    }
+
+    let chunks = chunk_ids.len() as u64;
+    let window_start = rate_limiter.try_prove(chunks, Instant::now())?;
+
+    let proof = file_storage
+        .generate_proof(file_key, &chunk_ids)
+        .map_err(|e| {
+            // Only the chunks actually proven count towards the limit.
+            rate_limiter.refund(chunks, window_start);
+            FileProofError::FailedToGenerateProof(e)
+        })?;
+
+    Ok(proof.encode().into())
+}
+
+/// Decodes a proof generated by [`generate_file_proof`], checks that it is for a file with
+/// `fingerprint` and returns the chunks it proves.
+pub fn verify_file_proof(
+    proof: &[u8],
+    fingerprint: H256,
+) -> Result<VerifiedFileProof, FileProofError> {
+    let proof =
+        FileKeyProof::decode(&mut &proof[..]).map_err(|_| FileProofError::FailedToDecodeProof)?;
+
+    let found = H256::from(proof.file_metadata.fingerprint().as_hash());
+    if found != fingerprint {
+        return Err(FileProofError::FingerprintMismatch {
+            expected: fingerprint,
+            found,
+        });
+    }
+
+    let mut chunk_ids: Vec<u64> = proof
+        .proven::<StorageProofsMerkleTrieLayout>()
+        .map_err(FileProofError::InvalidProof)?
+        .into_iter()
+        .map(|leaf| leaf.key.as_u64())
+        .collect();
+    chunk_ids.sort();
+
+    Ok(VerifiedFileProof {
+        file_key: proof
+            .file_metadata
+            .file_key::<HashT<StorageProofsMerkleTrieLayout>>(),
+        chunk_ids,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shc_common::types::{FileMetadata, FILE_CHUNK_SIZE};
+    use shc_file_manager::{
+        chunker::write_chunks_from_reader, in_memory::InMemoryFileStorage, traits::FileDataTrie,
+    };
+
+    type Layout = StorageProofsMerkleTrieLayout;
+
+    const CHUNKS: u64 = 4;
+
+    /// A File Storage holding a file of [`CHUNKS`] chunks, and the key and fingerprint of it.
+    fn storage_with_file() -> (InMemoryFileStorage<Layout>, H256, H256) {
+        let mut file_storage = InMemoryFileStorage::<Layout>::new();
+        let mut file_data = file_storage.new_file_data_trie();
+        let file: Vec<u8> = (0..FILE_CHUNK_SIZE * CHUNKS).map(|i| i as u8).collect();
+        let file_size = write_chunks_from_reader(&mut file_data, file.as_slice()).unwrap();
+
+        let fingerprint = H256::from_slice(file_data.get_root().as_ref());
+        let metadata = FileMetadata::new(
+            vec![1u8; 32],
+            vec![2u8; 32],
+            b"file".to_vec(),
+            file_size,
+            fingerprint.as_ref().into(),
+        )
+        .unwrap();
+        let file_key = metadata.file_key::<HashT<Layout>>();
+        file_storage
+            .insert_file_with_data(file_key, metadata, file_data)
+            .unwrap();
+
+        (file_storage, file_key, fingerprint)
+    }
+
+    #[test]
+    fn generated_proofs_verify_against_the_file_fingerprint() {
+        let (file_storage, file_key, fingerprint) = storage_with_file();
+
+        let proof = generate_file_proof(
+            &file_storage,
+            &FileProofRateLimiter::default(),
+            &file_key,
+            &[3, 0, 3],
+        )
+        .unwrap();
+
+        assert_eq!(
+            verify_file_proof(&proof, fingerprint).unwrap(),
+            VerifiedFileProof {
+                file_key,
+                chunk_ids: vec![0, 3],
+            }
+        );
+    }
+
+    #[test]
+    fn proofs_do_not_verify_against_another_fingerprint() {
+        let (file_storage, file_key, fingerprint) = storage_with_file();
+        let proof = generate_file_proof(
+            &file_storage,
+            &FileProofRateLimiter::default(),
+            &file_key,
+            &[1],
+        )
+        .unwrap();
+
+        let other = H256::repeat_byte(0xaa);
+        assert!(matches!(
+            verify_file_proof(&proof, other),
+            Err(FileProofError::FingerprintMismatch { expected, found })
+                if expected == other && found == fingerprint
+        ));
+        assert!(matches!(
+            verify_file_proof(&proof[1..], fingerprint),
+            Err(FileProofError::FailedToDecodeProof)
+        ));
+    }
+
+    #[test]
+    fn proofs_of_missing_chunks_are_not_generated() {
+        let (file_storage, file_key, _) = storage_with_file();
+        let rate_limiter = FileProofRateLimiter::default();
+
+        assert!(matches!(
+            generate_file_proof(&file_storage, &rate_limiter, &file_key, &[]),
+            Err(FileProofError::NoChunksRequested)
+        ));
+        assert!(matches!(
+            generate_file_proof(&file_storage, &rate_limiter, &file_key, &[CHUNKS]),
+            Err(FileProofError::FailedToGenerateProof(_))
+        ));
+        assert!(matches!(
+            generate_file_proof(&file_storage, &rate_limiter, &H256::zero(), &[0]),
+            Err(FileProofError::FailedToGenerateProof(_))
+        ));
+    }
+
+    #[test]
+    fn proven_chunks_are_rate_limited_within_a_window() {
+        let window = Duration::from_secs(60);
+        let rate_limiter = FileProofRateLimiter::new(10, window);
+        let start = Instant::now();
+
+        assert!(rate_limiter.try_prove(6, start).is_ok());
+        assert!(rate_limiter.try_prove(4, start + window / 2).is_ok());
+        assert!(matches!(
+            rate_limiter.try_prove(1, start + window / 2),
+            Err(FileProofError::RateLimited {
+                requested: 1,
+                max_per_window: 10
+            })
+        ));
+        // A rejected request does not count towards the limit, and a new window starts afresh.
+        assert!(rate_limiter.try_prove(10, start + window).is_ok());
+        assert!(rate_limiter.try_prove(11, start + window * 2).is_err());
+    }
+
+    #[test]
+    fn refunded_chunks_only_count_back_in_their_own_window() {
+        let window = Duration::from_secs(60);
+        let rate_limiter = FileProofRateLimiter::new(10, window);
+        let start = Instant::now();
+
+        let window_start = rate_limiter.try_prove(10, start).unwrap();
+        rate_limiter.refund(10, window_start);
+        let window_start = rate_limiter.try_prove(10, start + window / 2).unwrap();
+        assert_eq!(window_start, start);
+
+        // Refunds for a past window don't free up the current one.
+        rate_limiter.try_prove(10, start + window).unwrap();
+        rate_limiter.refund(10, window_start);
+        assert!(rate_limiter.try_prove(1, start + window).is_err());
+    }
+
+    #[test]
+    fn failed_generations_do_not_count_towards_the_limit() {
+        let (file_storage, file_key, _) = storage_with_file();
+        let rate_limiter = FileProofRateLimiter::new(CHUNKS, Duration::from_secs(60));
+
+        for _ in 0..3 {
+            assert!(matches!(
+                generate_file_proof(&file_storage, &rate_limiter, &H256::zero(), &[0, 1, 2, 3]),
+                Err(FileProofError::FailedToGenerateProof(_))
+            ));
+        }
+        assert!(
+            generate_file_proof(&file_storage, &rate_limiter, &file_key, &[0, 1, 2, 3]).is_ok()
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use std::sync::{Arc, RwLock as StdRwLock};
+
+use jsonrpsee::core::async_trait;
+use sp_core::H256;
+use sp_runtime::{Deserialize, Serialize};
+
+/// Result of recovering a Forest whose local root diverged from its on-chain root.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RecoverForestResult {
+    /// The local root already matched the on-chain root, so the Forest was left untouched.
+    InSync(H256),
+    /// The Forest was rebuilt and its local root matches the on-chain root again.
+    Recovered(RecoveredForest),
+}
+
+/// Details of a Forest rebuilt from authoritative data.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RecoveredForest {
+    /// The local root of the Forest before being rebuilt.
+    pub diverged_root: H256,
+    /// The on-chain root, which is the local root of the rebuilt Forest.
+    pub on_chain_root: H256,
+    /// The key under which the diverged Forest was snapshotted, if it could be.
+    pub diverged_snapshot_key: Option<Vec<u8>>,
+    /// File keys that were in the diverged Forest but are not stored on-chain.
+    pub removed_file_keys: Vec<H256>,
+    /// File keys stored on-chain that were missing from the diverged Forest.
+    pub inserted_file_keys: Vec<H256>,
+}
+
+/// Rebuilds the local Forests of the node that diverged from their on-chain roots.
+#[async_trait]
+pub trait ForestRecovery: Send + Sync {
+    /// Recovers the Forest with `forest_key`, or the BSP's Forest if `None`.
+    async fn recover_forest(&self, forest_key: Option<H256>)
+        -> anyhow::Result<RecoverForestResult>;
+}
+
+/// Shared slot for the [`ForestRecovery`] of the node.
+///
+/// Like the [`ProviderStatusHandle`](crate::provider_status::ProviderStatusHandle), it is given
+/// to the RPC upfront, and set once the StorageHub services are running. It is only set for
+/// nodes able to recover their Forests.
+#[derive(Clone, Default)]
+pub struct ForestRecoveryHandle {
+    recovery: Arc<StdRwLock<Option<Arc<dyn ForestRecovery>>>>,
+}
+
+impl ForestRecoveryHandle {
+    /// Sets the recovery of the Forests, replacing any previous one.
+    pub fn set(&self, recovery: Arc<dyn ForestRecovery>) {
+        *self
+            .recovery
+            .write()
+            .expect("Forest recovery handle lock poisoned") = Some(recovery);
+    }
+
+    /// The recovery of the Forests, or `None` if it is not available (yet).
+    pub fn get(&self) -> Option<Arc<dyn ForestRecovery>> {
+        self.recovery
+            .read()
+            .expect("Forest recovery handle lock poisoned")
+            .clone()
+    }
+}
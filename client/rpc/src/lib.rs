@@ -28,18 +28,44 @@ use shc_common::{
         BackupStorageProviderId, BlockNumber, BucketId, ChunkId, CustomChallenge, FileMetadata,
         ForestLeaf, HashT, KeyProof, KeyProofs, MainStorageProviderId, ProofsDealerProviderId,
         Proven, RandomnessOutput, StorageProof, StorageProofsMerkleTrieLayout, BCSV_KEY_TYPE,
-        FILE_CHUNK_SIZE,
     },
+    upload_progress::{UploadProgress, UploadProgressRegistry},
+    user_uploads::{UserUploadQueue, UserUploadRequest},
+};
+use shc_file_manager::{
+    chunker::{compute_file_metadata, write_chunks_from_reader, ChunkerError},
+    traits::{ExcludeType, FileDataTrie, FileStorage, FileStorageError},
 };
-use shc_file_manager::traits::{ExcludeType, FileDataTrie, FileStorage, FileStorageError};
 use shc_forest_manager::traits::{ForestStorage, ForestStorageHandler};
-use sp_core::{sr25519::Pair as Sr25519Pair, Encode, Pair, H256};
+use sp_core::{sr25519::Pair as Sr25519Pair, Bytes, Encode, Pair, H256};
 use sp_keystore::{Keystore, KeystorePtr};
 use sp_runtime::{traits::Block as BlockT, AccountId32, Deserialize, KeyTypeId, Serialize};
 use sp_runtime_interface::pass_by::PassByInner;
 
+pub mod file_proofs;
+pub mod forest_recovery;
+pub mod provider_status;
+pub mod storage_request;
+pub mod storage_verification;
+
+use file_proofs::{
+    generate_file_proof, verify_file_proof, FileProofRateLimiter, VerifiedFileProof,
+};
+use forest_recovery::{ForestRecoveryHandle, RecoverForestResult};
+use provider_status::{
+    collect_provider_status, ProviderStatus, ProviderStatusHandle, PROVIDER_STATUS_SOURCE_TIMEOUT,
+};
+use storage_request::{
+    validate_storage_request, FileSource, IssueStorageRequestResult, StorageRequestIssuerHandle,
+};
+use storage_verification::{StorageVerificationHandle, StorageVerificationReport};
+
 const LOG_TARGET: &str = "storage-hub-client-rpc";
 
+/// The maximum size of a file whose metadata is computed by `computeFileMetadata`, since the
+/// whole file is chunked and hashed to compute it.
+const MAX_COMPUTE_FILE_METADATA_SIZE: u64 = 1024 * 1024 * 1024;
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct CheckpointChallenge {
     pub file_key: H256,
@@ -52,10 +78,25 @@ pub struct LoadFileInStorageResult {
     pub file_metadata: FileMetadata,
 }
 
+/// Result of computing the metadata of a file through RPC.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ComputeFileMetadataResult {
+    pub file_key: H256,
+    pub file_size: u64,
+    pub chunks_count: u64,
+    pub fingerprint: H256,
+}
+
 pub struct StorageHubClientRpcConfig<FL, FSH> {
     pub file_storage: Arc<RwLock<FL>>,
     pub forest_storage_handler: FSH,
     pub keystore: KeystorePtr,
+    pub upload_progress: UploadProgressRegistry,
+    pub upload_queue: UserUploadQueue,
+    pub provider_status: ProviderStatusHandle,
+    pub forest_recovery: ForestRecoveryHandle,
+    pub storage_verification: StorageVerificationHandle,
+    pub storage_request_issuer: StorageRequestIssuerHandle,
 }
 
 impl<FL, FSH: Clone> Clone for StorageHubClientRpcConfig<FL, FSH> {
@@ -64,6 +105,12 @@ impl<FL, FSH: Clone> Clone for StorageHubClientRpcConfig<FL, FSH> {
             file_storage: self.file_storage.clone(),
             forest_storage_handler: self.forest_storage_handler.clone(),
             keystore: self.keystore.clone(),
+            upload_progress: self.upload_progress.clone(),
+            upload_queue: self.upload_queue.clone(),
+            provider_status: self.provider_status.clone(),
+            forest_recovery: self.forest_recovery.clone(),
+            storage_verification: self.storage_verification.clone(),
+            storage_request_issuer: self.storage_request_issuer.clone(),
         }
     }
 }
@@ -77,11 +124,23 @@ where
         file_storage: Arc<RwLock<FL>>,
         forest_storage_handler: FSH,
         keystore: KeystorePtr,
+        upload_progress: UploadProgressRegistry,
+        upload_queue: UserUploadQueue,
+        provider_status: ProviderStatusHandle,
+        forest_recovery: ForestRecoveryHandle,
+        storage_verification: StorageVerificationHandle,
+        storage_request_issuer: StorageRequestIssuerHandle,
     ) -> Self {
         Self {
             file_storage,
             forest_storage_handler,
             keystore,
+            upload_progress,
+            upload_queue,
+            provider_status,
+            forest_recovery,
+            storage_verification,
+            storage_request_issuer,
         }
     }
 }
@@ -139,6 +198,57 @@ pub trait StorageHubClientApi {
         bucket_id: H256,
     ) -> RpcResult<LoadFileInStorageResult>;
 
+    /// Load a file in the file storage and upload it to the network.
+    ///
+    /// The file is owned by the BCSV key of this node. Once loaded, a storage request is issued
+    /// for it to the MSP of `bucket_id`, and the user node sends the chunks to the MSP and to the
+    /// BSPs volunteering for it. The progress of the upload can be followed with `uploadProgress`.
+    ///
+    /// If `replication_target` is `None`, the standard replication target of the runtime is used.
+    #[method(name = "uploadFile", with_extensions)]
+    async fn upload_file(
+        &self,
+        file_path: String,
+        location: String,
+        bucket_id: H256,
+        replication_target: Option<u32>,
+    ) -> RpcResult<LoadFileInStorageResult>;
+
+    /// Issue a storage request for a file and return its file key.
+    ///
+    /// The file, given either as a path in the local file system of the node or as raw bytes, is
+    /// loaded in the file storage, owned by the BCSV key of this node. The `issue_storage_request`
+    /// extrinsic is then submitted for it, to be stored by `msp_id`, and the chunks are sent like
+    /// for `uploadFile`. The bucket must be owned by the BCSV key, and the file must fit in it
+    /// without going over the data limit of its value proposition.
+    ///
+    /// If `replication_target` is `None`, the standard replication target of the runtime is used.
+    /// Only supported by user nodes.
+    #[method(name = "issueStorageRequest", with_extensions)]
+    async fn issue_storage_request(
+        &self,
+        file: FileSource,
+        location: String,
+        bucket_id: H256,
+        msp_id: H256,
+        replication_target: Option<u32>,
+    ) -> RpcResult<IssueStorageRequestResult>;
+
+    /// Compute the metadata of a file and its file key, without storing or submitting anything.
+    ///
+    /// The file, given either as a path in the local file system of the node or as raw bytes, is
+    /// chunked like when it is loaded in the file storage, as owned by `owner` at `location` in
+    /// `bucket_id`. Clients can rely on this to derive file keys instead of reimplementing the
+    /// chunking. Files bigger than 1 GiB are rejected. This is an unsafe call.
+    #[method(name = "computeFileMetadata", with_extensions)]
+    async fn compute_file_metadata(
+        &self,
+        file: FileSource,
+        owner: AccountId32,
+        bucket_id: H256,
+        location: String,
+    ) -> RpcResult<ComputeFileMetadataResult>;
+
     /// Remove a list of files from the file storage.
     ///
     /// This is useful to allow BSPs and MSPs to manually adjust their file storage to match
@@ -190,6 +300,40 @@ pub trait StorageHubClientApi {
         file_keys: Vec<H256>,
     ) -> RpcResult<RemoveFilesFromForestStorageResult>;
 
+    /// Rebuild a forest whose root diverged from its on-chain root.
+    ///
+    /// The diverged forest is snapshotted for later inspection, and then reconciled with the
+    /// files stored on-chain, as tracked by the indexer, once checked that they make up the
+    /// on-chain root. This also runs automatically when applying the mutations of a block
+    /// leaves the local root out of sync with the on-chain one.
+    ///
+    /// Supported by BSP nodes, whose forest key is empty, and by MSP nodes, whose forest key is
+    /// the ID of the bucket to recover.
+    #[method(name = "recoverForest", with_extensions)]
+    async fn recover_forest(&self, forest_key: Option<H256>) -> RpcResult<RecoverForestResult>;
+
+    /// Verify every file in the file storage against its fingerprint, and the forest against the
+    /// on-chain root.
+    ///
+    /// Meant to be run after restoring the node from a backup. The chunk counters and current
+    /// roots of the files are rebuilt from their stored chunks, and the forest is rebuilt from the
+    /// metadata of the complete files if they make up the on-chain root. The same verification
+    /// runs on startup with `--verify-storage-on-startup`, before the proof tasks are started.
+    ///
+    /// Only supported by BSP nodes.
+    #[method(name = "verifyStorage", with_extensions)]
+    async fn verify_storage(&self) -> RpcResult<StorageVerificationReport>;
+
+    /// Request the repair of a single file stored by the node, whose local copy is corrupt.
+    ///
+    /// The repair runs in the background: the file is verified against its fingerprint, left
+    /// untouched if it is intact, and otherwise downloaded again from the other providers storing
+    /// it, as done for the files found missing by `verifyStorage`. Its outcome is logged.
+    ///
+    /// Only supported by BSP nodes, for files in their forest.
+    #[method(name = "repairFile", with_extensions)]
+    async fn repair_file(&self, file_key: H256) -> RpcResult<()>;
+
     /// Get the root hash of a forest.
     ///
     /// In the case of an BSP node, the forest key is empty since it only maintains a single forest.
@@ -206,6 +350,20 @@ pub trait StorageHubClientApi {
         file_key: H256,
     ) -> RpcResult<GetFileFromFileStorageResult>;
 
+    /// Get the progress of a file being uploaded to this node.
+    ///
+    /// Returns `None` if the file is not being uploaded, or its upload finished long enough ago
+    /// for its progress to have been discarded.
+    #[method(name = "uploadProgress")]
+    async fn upload_progress(&self, file_key: H256) -> RpcResult<Option<UploadProgress>>;
+
+    /// Get the health and state of the Storage Provider run by this node.
+    ///
+    /// Each source of the status is queried concurrently and with a timeout, so values from
+    /// sources that fail or are too slow to answer are left out and listed as unavailable.
+    #[method(name = "providerStatus")]
+    async fn provider_status(&self) -> RpcResult<ProviderStatus>;
+
     #[method(name = "getFileMetadata")]
     async fn get_file_metadata(
         &self,
@@ -251,6 +409,22 @@ pub trait StorageHubClientApi {
         file_key: H256,
     ) -> RpcResult<Vec<u8>>;
 
+    /// Generate a proof that this node holds the chunks `chunk_ids` of the file `file_key`.
+    ///
+    /// Returns the SCALE-encoded `FileKeyProof`, which can be checked with `verifyFileProof` on
+    /// any node. The number of chunks proven by this node is rate limited. This is an unsafe call.
+    #[method(name = "generateFileProof", with_extensions)]
+    async fn generate_file_proof(&self, file_key: H256, chunk_ids: Vec<u64>) -> RpcResult<Bytes>;
+
+    /// Verify a SCALE-encoded `FileKeyProof` against the `fingerprint` of its file, and list the
+    /// chunks it proves.
+    #[method(name = "verifyFileProof")]
+    async fn verify_file_proof(
+        &self,
+        proof: Bytes,
+        fingerprint: H256,
+    ) -> RpcResult<VerifiedFileProof>;
+
     #[method(name = "insertBcsvKeys", with_extensions)]
     async fn insert_bcsv_keys(&self, seed: Option<String>) -> RpcResult<String>;
 
@@ -277,6 +451,13 @@ pub struct StorageHubClientRpc<FL, FSH, C, Block> {
     file_storage: Arc<RwLock<FL>>,
     forest_storage_handler: FSH,
     keystore: KeystorePtr,
+    upload_progress: UploadProgressRegistry,
+    upload_queue: UserUploadQueue,
+    provider_status: ProviderStatusHandle,
+    forest_recovery: ForestRecoveryHandle,
+    storage_verification: StorageVerificationHandle,
+    storage_request_issuer: StorageRequestIssuerHandle,
+    file_proof_rate_limiter: FileProofRateLimiter,
     _block_marker: std::marker::PhantomData<Block>,
 }
 
@@ -294,9 +475,93 @@ where
             file_storage: storage_hub_client_rpc_config.file_storage,
             forest_storage_handler: storage_hub_client_rpc_config.forest_storage_handler,
             keystore: storage_hub_client_rpc_config.keystore,
+            upload_progress: storage_hub_client_rpc_config.upload_progress,
+            upload_queue: storage_hub_client_rpc_config.upload_queue,
+            provider_status: storage_hub_client_rpc_config.provider_status,
+            forest_recovery: storage_hub_client_rpc_config.forest_recovery,
+            storage_verification: storage_hub_client_rpc_config.storage_verification,
+            storage_request_issuer: storage_hub_client_rpc_config.storage_request_issuer,
+            file_proof_rate_limiter: FileProofRateLimiter::default(),
             _block_marker: Default::default(),
         }
     }
+
+    /// The account this node signs its extrinsics with, which owns the files it uploads.
+    fn bcsv_account(&self) -> RpcResult<AccountId32> {
+        self.keystore
+            .sr25519_public_keys(BCSV_KEY_TYPE)
+            .first()
+            .copied()
+            .map(Into::into)
+            .ok_or_else(|| into_rpc_error("No BCSV key found in the keystore"))
+    }
+
+    /// Chunks the file at `file_path` and inserts it in the file storage, owned by `owner` in
+    /// `bucket_id`.
+    async fn load_file(
+        &self,
+        file_path: String,
+        location: String,
+        owner: AccountId32,
+        bucket_id: H256,
+    ) -> RpcResult<LoadFileInStorageResult> {
+        // Open file in the local file system.
+        let file = File::open(PathBuf::from(file_path.clone())).map_err(into_rpc_error)?;
+
+        self.load_file_from_reader(file, location, owner, bucket_id)
+            .await
+    }
+
+    /// Chunks the file read from `file` and inserts it in the file storage, owned by `owner` in
+    /// `bucket_id`.
+    async fn load_file_from_reader(
+        &self,
+        file: impl Read,
+        location: String,
+        owner: AccountId32,
+        bucket_id: H256,
+    ) -> RpcResult<LoadFileInStorageResult> {
+        // Instantiate an "empty" [`FileDataTrie`] so we can write the file chunks into it.
+        let mut file_data_trie = self.file_storage.write().await.new_file_data_trie();
+        let file_size =
+            write_chunks_from_reader(&mut file_data_trie, file).map_err(into_rpc_error)?;
+
+        // Generate the necessary metadata so we can insert file into the File Storage.
+        let root = file_data_trie.get_root();
+
+        if file_size == 0 {
+            return Err(into_rpc_error(FileStorageError::FileIsEmpty));
+        }
+
+        // Build StorageHub's [`FileMetadata`]
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&owner).to_vec(),
+            bucket_id.as_ref().to_vec(),
+            location.clone().into(),
+            file_size,
+            root.as_ref().into(),
+        )
+        .map_err(into_rpc_error)?;
+
+        let file_key = file_metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+
+        // Acquire FileStorage write lock.
+        let mut file_storage_write_lock = self.file_storage.write().await;
+
+        // Finally store file in File Storage.
+        file_storage_write_lock
+            .insert_file_with_data(file_key, file_metadata.clone(), file_data_trie)
+            .map_err(into_rpc_error)?;
+
+        let result = LoadFileInStorageResult {
+            file_key,
+            file_metadata,
+        };
+
+        info!(target: LOG_TARGET, "File loaded successfully: {:?}", result);
+
+        Ok(result)
+    }
 }
 
 /// Interface generated by the `rpc` macro from our `StorageHubClientApi` trait.
@@ -338,82 +603,170 @@ where
         // Check if the execution is safe.
         check_if_safe(ext)?;
 
-        // Open file in the local file system.
-        let mut file = File::open(PathBuf::from(file_path.clone())).map_err(into_rpc_error)?;
+        self.load_file(file_path, location, owner, bucket_id).await
+    }
 
-        // Instantiate an "empty" [`FileDataTrie`] so we can write the file chunks into it.
-        let mut file_data_trie = self.file_storage.write().await.new_file_data_trie();
-        // A chunk id is simply an integer index.
-        let mut chunk_id: u64 = 0;
-
-        // Read file in chunks of [`FILE_CHUNK_SIZE`] into buffer then push buffer into a vector.
-        // Loops until EOF or until some error that is NOT `ErrorKind::Interrupted` is found.
-        // If `ErrorKind::Interrupted` is found, the operation is simply retried, as per
-        // https://doc.rust-lang.org/std/io/trait.Read.html#errors-1
-        loop {
-            let mut chunk = Vec::with_capacity(FILE_CHUNK_SIZE as usize);
-            let read_result = <File as Read>::by_ref(&mut file)
-                .take(FILE_CHUNK_SIZE)
-                .read_to_end(&mut chunk);
-            match read_result {
-                // Reached EOF, break loop.
-                Ok(0) => {
-                    debug!(target: LOG_TARGET, "Finished reading file");
-                    break;
-                }
-                // Haven't reached EOF yet, continue loop.
-                Ok(bytes_read) => {
-                    debug!(target: LOG_TARGET, "Read {} bytes from file", bytes_read);
-
-                    // Build the actual [`FileDataTrie`] by inserting each chunk into it.
-                    file_data_trie
-                        .write_chunk(&ChunkId::new(chunk_id), &chunk)
-                        .map_err(into_rpc_error)?;
-                    chunk_id += 1;
-                }
-                Err(e) => {
-                    error!(target: LOG_TARGET, "Error when trying to read file: {:?}", e);
-                    return Err(into_rpc_error(e));
-                }
-            }
+    async fn upload_file(
+        &self,
+        ext: &Extensions,
+        file_path: String,
+        location: String,
+        bucket_id: H256,
+        replication_target: Option<u32>,
+    ) -> RpcResult<LoadFileInStorageResult> {
+        // Check if the execution is safe.
+        check_if_safe(ext)?;
+
+        // The file is owned by the account this node signs its extrinsics with.
+        let owner = self.bcsv_account()?;
+
+        let result = self
+            .load_file(file_path, location, owner, bucket_id)
+            .await?;
+
+        let queued = self.upload_queue.push(UserUploadRequest {
+            file_key: result.file_key,
+            file_metadata: result.file_metadata.clone(),
+            replication_target,
+        });
+        if !queued {
+            return Err(into_rpc_error(
+                "This node is not running the user tasks, so it cannot upload files",
+            ));
         }
 
-        // Generate the necessary metadata so we can insert file into the File Storage.
-        let root = file_data_trie.get_root();
-        let fs_metadata = file.metadata().map_err(into_rpc_error)?;
+        info!(target: LOG_TARGET, "File {:?} queued for upload", result.file_key);
 
-        if fs_metadata.len() == 0 {
-            return Err(into_rpc_error(FileStorageError::FileIsEmpty));
-        }
+        Ok(result)
+    }
 
-        // Build StorageHub's [`FileMetadata`]
-        let file_metadata = FileMetadata::new(
-            <AccountId32 as AsRef<[u8]>>::as_ref(&owner).to_vec(),
-            bucket_id.as_ref().to_vec(),
-            location.clone().into(),
-            fs_metadata.len(),
-            root.as_ref().into(),
-        )
-        .map_err(into_rpc_error)?;
+    async fn issue_storage_request(
+        &self,
+        ext: &Extensions,
+        file: FileSource,
+        location: String,
+        bucket_id: H256,
+        msp_id: H256,
+        replication_target: Option<u32>,
+    ) -> RpcResult<IssueStorageRequestResult> {
+        // Check if the execution is safe.
+        check_if_safe(ext)?;
 
-        let file_key = file_metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+        let issuer = self.storage_request_issuer.get().ok_or_else(|| {
+            into_rpc_error(
+                "This node is not running the user tasks, so it cannot issue storage requests",
+            )
+        })?;
 
-        // Acquire FileStorage write lock.
-        let mut file_storage_write_lock = self.file_storage.write().await;
+        // The file is owned by the account this node signs its extrinsics with.
+        let owner = self.bcsv_account()?;
 
-        // Finally store file in File Storage.
-        file_storage_write_lock
-            .insert_file_with_data(file_key, file_metadata.clone(), file_data_trie)
+        // Check the storage request before chunking the file, which can take a while.
+        let file_size = match &file {
+            FileSource::Path(file_path) => {
+                std::fs::metadata(file_path).map_err(into_rpc_error)?.len()
+            }
+            FileSource::Bytes(bytes) => bytes.len() as u64,
+        };
+        validate_storage_request(issuer.as_ref(), bucket_id, &owner, file_size)
+            .await
             .map_err(into_rpc_error)?;
 
-        let result = LoadFileInStorageResult {
+        let LoadFileInStorageResult {
             file_key,
             file_metadata,
+        } = match file {
+            FileSource::Path(file_path) => {
+                self.load_file(file_path, location, owner, bucket_id)
+                    .await?
+            }
+            FileSource::Bytes(bytes) => {
+                self.load_file_from_reader(bytes.as_slice(), location, owner, bucket_id)
+                    .await?
+            }
         };
+        let fingerprint = H256(file_metadata.fingerprint().as_hash());
 
-        info!(target: LOG_TARGET, "File loaded successfully: {:?}", result);
+        let extrinsic_hash = match issuer
+            .submit_storage_request(file_key, file_metadata, msp_id, replication_target)
+            .await
+        {
+            Ok(extrinsic_hash) => extrinsic_hash,
+            Err(e) => {
+                // Nothing will be uploaded, so the file is removed to allow issuing it again.
+                if let Err(delete_error) = self.file_storage.write().await.delete_file(&file_key) {
+                    error!(target: LOG_TARGET, "Failed to remove file {:?} from file storage: {:?}", file_key, delete_error);
+                }
+                return Err(into_rpc_error(e));
+            }
+        };
 
-        Ok(result)
+        info!(
+            target: LOG_TARGET,
+            "Issued storage request for file {:?} in extrinsic {:?}", file_key, extrinsic_hash
+        );
+
+        Ok(IssueStorageRequestResult {
+            file_key,
+            fingerprint,
+            extrinsic_hash,
+        })
+    }
+
+    async fn compute_file_metadata(
+        &self,
+        ext: &Extensions,
+        file: FileSource,
+        owner: AccountId32,
+        bucket_id: H256,
+        location: String,
+    ) -> RpcResult<ComputeFileMetadataResult> {
+        // Check if the execution is safe.
+        check_if_safe(ext)?;
+
+        let file_size = match &file {
+            FileSource::Path(file_path) => {
+                std::fs::metadata(file_path).map_err(into_rpc_error)?.len()
+            }
+            FileSource::Bytes(bytes) => bytes.len() as u64,
+        };
+        if file_size > MAX_COMPUTE_FILE_METADATA_SIZE {
+            return Err(into_rpc_error(format!(
+                "File of {} bytes is bigger than the maximum of {} bytes",
+                file_size, MAX_COMPUTE_FILE_METADATA_SIZE
+            )));
+        }
+
+        let owner = <AccountId32 as AsRef<[u8]>>::as_ref(&owner).to_vec();
+        let bucket_id = bucket_id.as_ref().to_vec();
+        let location = location.into_bytes();
+
+        // Chunking and hashing the file is CPU bound, so it is kept off the async executor.
+        let file_metadata = tokio::task::spawn_blocking(move || match file {
+            FileSource::Path(file_path) => File::open(PathBuf::from(file_path))
+                .map_err(ChunkerError::from)
+                .and_then(|file| {
+                    compute_file_metadata::<StorageProofsMerkleTrieLayout>(
+                        file, owner, bucket_id, location,
+                    )
+                }),
+            FileSource::Bytes(bytes) => compute_file_metadata::<StorageProofsMerkleTrieLayout>(
+                bytes.as_slice(),
+                owner,
+                bucket_id,
+                location,
+            ),
+        })
+        .await
+        .map_err(into_rpc_error)?
+        .map_err(into_rpc_error)?;
+
+        Ok(ComputeFileMetadataResult {
+            file_key: file_metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>(),
+            file_size: file_metadata.file_size(),
+            chunks_count: file_metadata.chunks_count(),
+            fingerprint: H256(file_metadata.fingerprint().as_hash()),
+        })
     }
 
     async fn remove_files_from_file_storage(
@@ -577,6 +930,55 @@ where
         Ok(RemoveFilesFromForestStorageResult::Success)
     }
 
+    async fn recover_forest(
+        &self,
+        ext: &Extensions,
+        forest_key: Option<H256>,
+    ) -> RpcResult<RecoverForestResult> {
+        // Check if the execution is safe.
+        check_if_safe(ext)?;
+
+        let recovery = self.forest_recovery.get().ok_or_else(|| {
+            into_rpc_error(
+                "Forest recovery is not available. It is only supported by running BSP and MSP nodes.",
+            )
+        })?;
+
+        recovery
+            .recover_forest(forest_key)
+            .await
+            .map_err(into_rpc_error)
+    }
+
+    async fn verify_storage(&self, ext: &Extensions) -> RpcResult<StorageVerificationReport> {
+        // Check if the execution is safe.
+        check_if_safe(ext)?;
+
+        let verification = self.storage_verification.get().ok_or_else(|| {
+            into_rpc_error(
+                "Storage verification is not available. It is only supported by running BSP nodes.",
+            )
+        })?;
+
+        verification.verify_storage().await.map_err(into_rpc_error)
+    }
+
+    async fn repair_file(&self, ext: &Extensions, file_key: H256) -> RpcResult<()> {
+        // Check if the execution is safe.
+        check_if_safe(ext)?;
+
+        let verification = self.storage_verification.get().ok_or_else(|| {
+            into_rpc_error(
+                "File repair is not available. It is only supported by running BSP nodes.",
+            )
+        })?;
+
+        verification
+            .repair_file(file_key)
+            .await
+            .map_err(into_rpc_error)
+    }
+
     async fn get_forest_root(&self, forest_key: Option<H256>) -> RpcResult<Option<H256>> {
         let forest_key = match forest_key {
             Some(forest_key) => forest_key.as_ref().to_vec().into(),
@@ -651,6 +1053,24 @@ where
         }
     }
 
+    async fn upload_progress(&self, file_key: H256) -> RpcResult<Option<UploadProgress>> {
+        Ok(self.upload_progress.get(&file_key))
+    }
+
+    async fn provider_status(&self) -> RpcResult<ProviderStatus> {
+        let source = self.provider_status.get().ok_or_else(|| {
+            into_rpc_error("StorageHub services are not running yet. Try again later.")
+        })?;
+
+        Ok(collect_provider_status(
+            source.as_ref(),
+            &self.file_storage,
+            &self.forest_storage_handler,
+            PROVIDER_STATUS_SOURCE_TIMEOUT,
+        )
+        .await)
+    }
+
     // Note: this method could use either the file storage or the forest storage, but it's using the forest storage.
     // WARNING: Right now, forests don't have the file metadata saved to them, so don't expect to get the file
     // metadata from this method until that's fixed.
@@ -888,6 +1308,35 @@ where
         Ok(key_proof.proof.encode())
     }
 
+    async fn generate_file_proof(
+        &self,
+        ext: &Extensions,
+        file_key: H256,
+        chunk_ids: Vec<u64>,
+    ) -> RpcResult<Bytes> {
+        // Check if the execution is safe.
+        check_if_safe(ext)?;
+
+        // Acquire FileStorage read lock.
+        let read_file_storage = self.file_storage.read().await;
+
+        generate_file_proof(
+            &*read_file_storage,
+            &self.file_proof_rate_limiter,
+            &file_key,
+            &chunk_ids,
+        )
+        .map_err(|e| into_rpc_error(anyhow::Error::from(e)))
+    }
+
+    async fn verify_file_proof(
+        &self,
+        proof: Bytes,
+        fingerprint: H256,
+    ) -> RpcResult<VerifiedFileProof> {
+        verify_file_proof(&proof, fingerprint).map_err(|e| into_rpc_error(anyhow::Error::from(e)))
+    }
+
     // If a seed is provided, we manually generate and persist it into the file system.
     // In the case a seed is not provided, we delegate generation and insertion to `sr25519_generate_new`, which
     // internally uses the block number as a seed.
@@ -0,0 +1,555 @@
+use std::{
+    future::Future,
+    sync::{Arc, RwLock as StdRwLock},
+    time::Duration,
+};
+
+use jsonrpsee::core::async_trait;
+use sp_runtime::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use shc_common::{
+    consts::CURRENT_FOREST_KEY,
+    types::{BlockNumber, StorageProofsMerkleTrieLayout},
+};
+use shc_file_manager::traits::FileStorage;
+use shc_forest_manager::traits::{ForestStorage, ForestStorageHandler};
+use sp_core::H256;
+
+/// Maximum time to wait for each of the sources of a [`ProviderStatus`], so that a stuck
+/// subsystem only leaves its own values out of the status.
+pub const PROVIDER_STATUS_SOURCE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The kind of Storage Provider this node is running as.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ProviderKind {
+    Bsp,
+    Msp,
+}
+
+/// The on-chain identity of the Storage Provider managed by this node.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProviderIdentity {
+    pub id: H256,
+    pub kind: ProviderKind,
+}
+
+/// The on-chain storage capacity of a Storage Provider.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProviderCapacity {
+    pub capacity: u64,
+    pub capacity_used: u64,
+}
+
+/// The number of requests waiting in each of the queues of the Blockchain Service.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PendingRequests {
+    pub submit_proof: u64,
+    pub confirm_storing: u64,
+    pub msp_respond_storage: u64,
+    pub stop_storing_for_insolvent_user: u64,
+    pub file_deletion: u64,
+}
+
+/// The comparison of the root of a local Forest with its on-chain root.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ForestRootStatus {
+    /// The bucket ID of the Forest for an MSP, or `None` for the Forest of a BSP.
+    pub forest_key: Option<H256>,
+    pub on_chain_root: H256,
+    /// `None` if there is no local Forest for `forest_key`, which is only in sync with an empty
+    /// on-chain Forest.
+    pub local_root: Option<H256>,
+    pub in_sync: bool,
+}
+
+/// Health and state of the Storage Provider run by this node.
+///
+/// Each value is `None` if its source failed or didn't answer in time, in which case the source is
+/// listed in `unavailable_sources` along with the reason.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProviderStatus {
+    /// `None` as well if the node's account is not registered as a Storage Provider.
+    pub provider: Option<ProviderIdentity>,
+    pub forest_roots: Option<Vec<ForestRootStatus>>,
+    /// Whether all the local Forests match their on-chain roots.
+    pub forest_roots_in_sync: Option<bool>,
+    pub capacity: Option<ProviderCapacity>,
+    /// Number of files in the local file storage, complete or not.
+    pub files_stored: Option<u64>,
+    /// Sum of the sizes of the files in the local file storage.
+    pub files_stored_size: Option<u64>,
+    pub pending_requests: Option<PendingRequests>,
+    /// The last tick for which a proof was submitted. Always `None` for an MSP.
+    pub last_proof_tick: Option<BlockNumber>,
+    /// Number of files currently registered in the File Transfer Service to receive uploads.
+    pub file_transfer_registrations: Option<u64>,
+    pub unavailable_sources: Vec<String>,
+}
+
+/// The sources of a [`ProviderStatus`] that live in the StorageHub services, as opposed to the
+/// file and Forest storages the RPC already has access to.
+#[async_trait]
+pub trait ProviderStatusSource: Send + Sync {
+    /// The Storage Provider managed by this node, if registered.
+    async fn provider(&self) -> anyhow::Result<Option<ProviderIdentity>>;
+
+    /// The on-chain roots of the Forests of `provider`, by Forest key (see
+    /// [`ForestRootStatus::forest_key`]).
+    async fn on_chain_forest_roots(
+        &self,
+        provider: &ProviderIdentity,
+    ) -> anyhow::Result<Vec<(Option<H256>, H256)>>;
+
+    /// The on-chain storage capacity of `provider`.
+    async fn capacity(&self, provider: &ProviderIdentity) -> anyhow::Result<ProviderCapacity>;
+
+    /// The last tick for which `provider` submitted a proof, if any.
+    async fn last_proof_tick(
+        &self,
+        provider: &ProviderIdentity,
+    ) -> anyhow::Result<Option<BlockNumber>>;
+
+    /// The depths of the pending requests queues.
+    async fn pending_requests(&self) -> anyhow::Result<PendingRequests>;
+
+    /// The number of files registered to receive uploads.
+    async fn file_transfer_registrations(&self) -> anyhow::Result<u64>;
+}
+
+/// Shared slot for the [`ProviderStatusSource`] of the node.
+///
+/// The RPC is started before the StorageHub services it queries, so it is given this handle
+/// upfront, and the source is set once the services are running.
+#[derive(Clone, Default)]
+pub struct ProviderStatusHandle {
+    source: Arc<StdRwLock<Option<Arc<dyn ProviderStatusSource>>>>,
+}
+
+impl ProviderStatusHandle {
+    /// Sets the source of the provider status, replacing any previous one.
+    pub fn set(&self, source: Arc<dyn ProviderStatusSource>) {
+        *self
+            .source
+            .write()
+            .expect("Provider status handle lock poisoned") = Some(source);
+    }
+
+    /// The source of the provider status, or `None` if the services are not running yet.
+    pub fn get(&self) -> Option<Arc<dyn ProviderStatusSource>> {
+        self.source
+            .read()
+            .expect("Provider status handle lock poisoned")
+            .clone()
+    }
+}
+
+/// Awaits `source` for at most `timeout`, describing why it's unavailable otherwise.
+async fn within<T>(
+    timeout: Duration,
+    name: &str,
+    source: impl Future<Output = anyhow::Result<T>>,
+) -> Result<T, String> {
+    match tokio::time::timeout(timeout, source).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(format!("{}: {}", name, e)),
+        Err(_) => Err(format!("{}: timed out after {:?}", name, timeout)),
+    }
+}
+
+/// Records the reason why a source is unavailable, if it is.
+fn available<T>(unavailable_sources: &mut Vec<String>, result: Result<T, String>) -> Option<T> {
+    result
+        .map_err(|reason| unavailable_sources.push(reason))
+        .ok()
+}
+
+/// Compares the on-chain roots of the Forests of `provider` with the local ones.
+async fn forest_root_statuses<FSH>(
+    source: &dyn ProviderStatusSource,
+    forest_storage_handler: &FSH,
+    provider: &ProviderIdentity,
+) -> anyhow::Result<Vec<ForestRootStatus>>
+where
+    FSH: ForestStorageHandler + Send + Sync,
+{
+    let empty_forest_root = sp_trie::empty_trie_root::<StorageProofsMerkleTrieLayout>();
+
+    let mut statuses = Vec::new();
+    for (forest_key, on_chain_root) in source.on_chain_forest_roots(provider).await? {
+        let key: FSH::Key = match forest_key {
+            Some(bucket_id) => bucket_id.as_ref().to_vec().into(),
+            None => CURRENT_FOREST_KEY.to_vec().into(),
+        };
+        let local_root = match forest_storage_handler.get(&key).await {
+            Some(fs) => Some(fs.read().await.root()),
+            None => None,
+        };
+
+        statuses.push(ForestRootStatus {
+            forest_key,
+            on_chain_root,
+            local_root,
+            in_sync: local_root.unwrap_or(empty_forest_root) == on_chain_root,
+        });
+    }
+
+    Ok(statuses)
+}
+
+/// Gathers the [`ProviderStatus`] from all its sources, concurrently and waiting at most
+/// `timeout` for each of them.
+pub(crate) async fn collect_provider_status<FL, FSH>(
+    source: &dyn ProviderStatusSource,
+    file_storage: &RwLock<FL>,
+    forest_storage_handler: &FSH,
+    timeout: Duration,
+) -> ProviderStatus
+where
+    FL: FileStorage<StorageProofsMerkleTrieLayout> + Send + Sync,
+    FSH: ForestStorageHandler + Send + Sync,
+{
+    let mut unavailable_sources = Vec::new();
+
+    // The rest of the on-chain state is queried for this provider, so it goes first.
+    let provider = available(
+        &mut unavailable_sources,
+        within(timeout, "provider", source.provider()).await,
+    )
+    .flatten();
+
+    let provider_sources = async {
+        let Some(provider) = provider else {
+            return (None, None, None);
+        };
+        let (forest_roots, capacity, last_proof_tick) = tokio::join!(
+            within(
+                timeout,
+                "forest_roots",
+                forest_root_statuses(source, forest_storage_handler, &provider),
+            ),
+            within(timeout, "capacity", source.capacity(&provider)),
+            within(
+                timeout,
+                "last_proof_tick",
+                source.last_proof_tick(&provider)
+            ),
+        );
+        (Some(forest_roots), Some(capacity), Some(last_proof_tick))
+    };
+
+    let ((forest_roots, capacity, last_proof_tick), pending_requests, registrations, file_stats) = tokio::join!(
+        provider_sources,
+        within(timeout, "pending_requests", source.pending_requests()),
+        within(
+            timeout,
+            "file_transfer_registrations",
+            source.file_transfer_registrations(),
+        ),
+        within(timeout, "file_storage", async {
+            file_storage
+                .read()
+                .await
+                .stats()
+                .map_err(|e| anyhow::anyhow!("{:?}", e))
+        }),
+    );
+
+    let forest_roots = forest_roots.and_then(|result| available(&mut unavailable_sources, result));
+    let capacity = capacity.and_then(|result| available(&mut unavailable_sources, result));
+    let last_proof_tick = last_proof_tick
+        .and_then(|result| available(&mut unavailable_sources, result))
+        .flatten();
+    let pending_requests = available(&mut unavailable_sources, pending_requests);
+    let file_transfer_registrations = available(&mut unavailable_sources, registrations);
+    let file_stats = available(&mut unavailable_sources, file_stats);
+
+    ProviderStatus {
+        provider,
+        forest_roots_in_sync: forest_roots
+            .as_ref()
+            .map(|forest_roots| forest_roots.iter().all(|status| status.in_sync)),
+        forest_roots,
+        capacity,
+        files_stored: file_stats.map(|stats| stats.files),
+        files_stored_size: file_stats.map(|stats| stats.total_size),
+        pending_requests,
+        last_proof_tick,
+        file_transfer_registrations,
+        unavailable_sources,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use shc_file_manager::in_memory::InMemoryFileStorage;
+    use shc_forest_manager::in_memory::InMemoryForestStorage;
+
+    use super::*;
+
+    type ForestStorage = InMemoryForestStorage<StorageProofsMerkleTrieLayout>;
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("Failed to build runtime")
+            .block_on(f)
+    }
+
+    fn empty_forest_root() -> H256 {
+        sp_trie::empty_trie_root::<StorageProofsMerkleTrieLayout>()
+    }
+
+    #[derive(Clone, Default)]
+    struct MockForestStorageHandler {
+        forests: HashMap<Vec<u8>, Arc<RwLock<ForestStorage>>>,
+    }
+
+    #[async_trait]
+    impl ForestStorageHandler for MockForestStorageHandler {
+        type Key = Vec<u8>;
+        type FS = ForestStorage;
+
+        async fn get(&self, key: &Self::Key) -> Option<Arc<RwLock<Self::FS>>> {
+            self.forests.get(key).cloned()
+        }
+
+        async fn create(&mut self, key: &Self::Key) -> Arc<RwLock<Self::FS>> {
+            self.forests
+                .entry(key.clone())
+                .or_insert_with(|| Arc::new(RwLock::new(ForestStorage::new())))
+                .clone()
+        }
+
+        async fn remove_forest_storage(&mut self, key: &Self::Key) {
+            self.forests.remove(key);
+        }
+
+        async fn snapshot(
+            &self,
+            _src_key: &Self::Key,
+            _dest_key: &Self::Key,
+        ) -> Option<Arc<RwLock<Self::FS>>> {
+            unimplemented!()
+        }
+
+        async fn snapshot_by_root(
+            &self,
+            _key: &Self::Key,
+            _forest_root: &H256,
+        ) -> Option<Arc<RwLock<Self::FS>>> {
+            unimplemented!()
+        }
+    }
+
+    /// A source whose on-chain roots are `on_chain_roots`, and that never answers for the pending
+    /// requests if `stuck` is set.
+    struct MockSource {
+        provider: Option<ProviderIdentity>,
+        on_chain_roots: Vec<(Option<H256>, H256)>,
+        stuck: bool,
+    }
+
+    #[async_trait]
+    impl ProviderStatusSource for MockSource {
+        async fn provider(&self) -> anyhow::Result<Option<ProviderIdentity>> {
+            Ok(self.provider)
+        }
+
+        async fn on_chain_forest_roots(
+            &self,
+            _provider: &ProviderIdentity,
+        ) -> anyhow::Result<Vec<(Option<H256>, H256)>> {
+            Ok(self.on_chain_roots.clone())
+        }
+
+        async fn capacity(&self, _provider: &ProviderIdentity) -> anyhow::Result<ProviderCapacity> {
+            Ok(ProviderCapacity {
+                capacity: 1024,
+                capacity_used: 256,
+            })
+        }
+
+        async fn last_proof_tick(
+            &self,
+            provider: &ProviderIdentity,
+        ) -> anyhow::Result<Option<BlockNumber>> {
+            Ok(match provider.kind {
+                ProviderKind::Bsp => Some(42),
+                ProviderKind::Msp => None,
+            })
+        }
+
+        async fn pending_requests(&self) -> anyhow::Result<PendingRequests> {
+            if self.stuck {
+                std::future::pending::<()>().await;
+            }
+            Ok(PendingRequests {
+                submit_proof: 1,
+                confirm_storing: 2,
+                ..Default::default()
+            })
+        }
+
+        async fn file_transfer_registrations(&self) -> anyhow::Result<u64> {
+            Err(anyhow::anyhow!("File Transfer Service is not running"))
+        }
+    }
+
+    fn bsp_source(on_chain_root: H256) -> MockSource {
+        MockSource {
+            provider: Some(ProviderIdentity {
+                id: H256::repeat_byte(7),
+                kind: ProviderKind::Bsp,
+            }),
+            on_chain_roots: vec![(None, on_chain_root)],
+            stuck: false,
+        }
+    }
+
+    fn bsp_forest_storage_handler() -> MockForestStorageHandler {
+        let mut handler = MockForestStorageHandler::default();
+        block_on(handler.create(&CURRENT_FOREST_KEY.to_vec()));
+        handler
+    }
+
+    fn collect(
+        source: &MockSource,
+        forest_storage_handler: &MockForestStorageHandler,
+    ) -> ProviderStatus {
+        let file_storage = RwLock::new(InMemoryFileStorage::<StorageProofsMerkleTrieLayout>::new());
+        block_on(collect_provider_status(
+            source,
+            &file_storage,
+            forest_storage_handler,
+            PROVIDER_STATUS_SOURCE_TIMEOUT,
+        ))
+    }
+
+    #[test]
+    fn provider_status_is_serialized_with_all_its_sources() {
+        let status = collect(
+            &bsp_source(empty_forest_root()),
+            &bsp_forest_storage_handler(),
+        );
+
+        let json = serde_json::to_value(&status).expect("Status should serialize");
+        assert_eq!(json["provider"]["kind"], "Bsp");
+        assert_eq!(json["forest_roots_in_sync"], true);
+        assert_eq!(
+            json["forest_roots"][0]["forest_key"],
+            serde_json::Value::Null
+        );
+        assert_eq!(json["forest_roots"][0]["in_sync"], true);
+        assert_eq!(json["capacity"]["capacity"], 1024);
+        assert_eq!(json["capacity"]["capacity_used"], 256);
+        assert_eq!(json["files_stored"], 0);
+        assert_eq!(json["files_stored_size"], 0);
+        assert_eq!(json["pending_requests"]["submit_proof"], 1);
+        assert_eq!(json["pending_requests"]["confirm_storing"], 2);
+        assert_eq!(json["last_proof_tick"], 42);
+        assert_eq!(json["file_transfer_registrations"], serde_json::Value::Null);
+        assert_eq!(
+            json["unavailable_sources"],
+            serde_json::json!([
+                "file_transfer_registrations: File Transfer Service is not running"
+            ])
+        );
+    }
+
+    #[test]
+    fn forest_root_mismatch_is_flagged() {
+        let on_chain_root = H256::repeat_byte(1);
+        let status = collect(&bsp_source(on_chain_root), &bsp_forest_storage_handler());
+
+        assert_eq!(status.forest_roots_in_sync, Some(false));
+        assert_eq!(
+            status.forest_roots,
+            Some(vec![ForestRootStatus {
+                forest_key: None,
+                on_chain_root,
+                local_root: Some(empty_forest_root()),
+                in_sync: false,
+            }])
+        );
+    }
+
+    #[test]
+    fn missing_local_forest_is_only_in_sync_with_an_empty_on_chain_forest() {
+        let empty_bucket = H256::repeat_byte(2);
+        let non_empty_bucket = H256::repeat_byte(3);
+        let source = MockSource {
+            provider: Some(ProviderIdentity {
+                id: H256::repeat_byte(7),
+                kind: ProviderKind::Msp,
+            }),
+            on_chain_roots: vec![
+                (Some(empty_bucket), empty_forest_root()),
+                (Some(non_empty_bucket), H256::repeat_byte(1)),
+            ],
+            stuck: false,
+        };
+
+        let status = collect(&source, &MockForestStorageHandler::default());
+
+        let in_sync = status
+            .forest_roots
+            .expect("Forest roots should be available")
+            .iter()
+            .map(|status| (status.forest_key, status.local_root, status.in_sync))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            in_sync,
+            vec![
+                (Some(empty_bucket), None, true),
+                (Some(non_empty_bucket), None, false)
+            ]
+        );
+        assert_eq!(status.forest_roots_in_sync, Some(false));
+        assert_eq!(status.last_proof_tick, None);
+    }
+
+    #[test]
+    fn stuck_source_only_leaves_out_its_own_values() {
+        let source = MockSource {
+            stuck: true,
+            ..bsp_source(empty_forest_root())
+        };
+        let file_storage = RwLock::new(InMemoryFileStorage::<StorageProofsMerkleTrieLayout>::new());
+
+        let status = block_on(collect_provider_status(
+            &source,
+            &file_storage,
+            &bsp_forest_storage_handler(),
+            Duration::from_millis(50),
+        ));
+
+        assert_eq!(status.pending_requests, None);
+        assert!(status
+            .unavailable_sources
+            .iter()
+            .any(|reason| reason.starts_with("pending_requests: timed out")));
+        assert_eq!(status.forest_roots_in_sync, Some(true));
+        assert_eq!(status.files_stored, Some(0));
+        assert_eq!(status.last_proof_tick, Some(42));
+    }
+
+    #[test]
+    fn unregistered_node_has_no_provider_values() {
+        let source = MockSource {
+            provider: None,
+            on_chain_roots: Vec::new(),
+            stuck: false,
+        };
+
+        let status = collect(&source, &MockForestStorageHandler::default());
+
+        assert_eq!(status.provider, None);
+        assert_eq!(status.forest_roots, None);
+        assert_eq!(status.capacity, None);
+        assert_eq!(status.files_stored, Some(0));
+    }
+}
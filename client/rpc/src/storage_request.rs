@@ -0,0 +1,328 @@
+use std::{
+    fmt,
+    sync::{Arc, RwLock as StdRwLock},
+};
+
+use jsonrpsee::core::async_trait;
+use sp_core::H256;
+use sp_runtime::{AccountId32, Deserialize, Serialize};
+
+use shc_common::types::FileMetadata;
+
+/// The contents of a file to issue a storage request for.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FileSource {
+    /// A file in the local file system of the node.
+    Path(String),
+    /// The raw bytes of the file.
+    Bytes(Vec<u8>),
+}
+
+/// Result of issuing a storage request through RPC.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IssueStorageRequestResult {
+    pub file_key: H256,
+    pub fingerprint: H256,
+    /// Hash of the `issue_storage_request` extrinsic, which may not be included in a block yet.
+    pub extrinsic_hash: H256,
+}
+
+/// A bucket, as seen by the account issuing a storage request to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BucketInfo {
+    pub owner: AccountId32,
+    /// Total size of the files stored in the bucket.
+    pub size: u64,
+    /// Maximum total size of the files in the bucket, set by the value proposition of its MSP.
+    pub data_limit: u64,
+}
+
+/// Reasons for refusing to issue a storage request before submitting it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InvalidStorageRequest {
+    /// The bucket is owned by another account than the one the node signs its extrinsics with.
+    NotBucketOwner {
+        bucket_owner: AccountId32,
+        account: AccountId32,
+    },
+    /// The file does not fit in the bucket without going over its data limit.
+    ExceedsBucketDataLimit {
+        file_size: u64,
+        bucket_size: u64,
+        data_limit: u64,
+    },
+}
+
+impl fmt::Display for InvalidStorageRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidStorageRequest::NotBucketOwner {
+                bucket_owner,
+                account,
+            } => write!(
+                f,
+                "Bucket is owned by {}, not by the account of this node {}",
+                bucket_owner, account
+            ),
+            InvalidStorageRequest::ExceedsBucketDataLimit {
+                file_size,
+                bucket_size,
+                data_limit,
+            } => write!(
+                f,
+                "File of {} bytes does not fit in a bucket holding {} of its {} bytes",
+                file_size, bucket_size, data_limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InvalidStorageRequest {}
+
+/// Checks that `account` can issue a storage request for a file of `file_size` bytes to the
+/// bucket described by `bucket`.
+pub fn check_storage_request(
+    bucket: &BucketInfo,
+    account: &AccountId32,
+    file_size: u64,
+) -> Result<(), InvalidStorageRequest> {
+    if &bucket.owner != account {
+        return Err(InvalidStorageRequest::NotBucketOwner {
+            bucket_owner: bucket.owner.clone(),
+            account: account.clone(),
+        });
+    }
+
+    let fits = bucket
+        .size
+        .checked_add(file_size)
+        .is_some_and(|size| size <= bucket.data_limit);
+    if !fits {
+        return Err(InvalidStorageRequest::ExceedsBucketDataLimit {
+            file_size,
+            bucket_size: bucket.size,
+            data_limit: bucket.data_limit,
+        });
+    }
+
+    Ok(())
+}
+
+/// Fetches the bucket with `bucket_id` through `issuer` and checks that `account` can issue a
+/// storage request for a file of `file_size` bytes to it.
+///
+/// Refusals are returned as an [`InvalidStorageRequest`] wrapped in the error.
+pub async fn validate_storage_request(
+    issuer: &dyn StorageRequestIssuer,
+    bucket_id: H256,
+    account: &AccountId32,
+    file_size: u64,
+) -> anyhow::Result<()> {
+    let bucket = issuer.bucket_info(bucket_id, account.clone()).await?;
+    check_storage_request(&bucket, account, file_size)?;
+    Ok(())
+}
+
+/// Issues the storage requests of the files loaded through RPC by a user node.
+#[async_trait]
+pub trait StorageRequestIssuer: Send + Sync {
+    /// The bucket with `bucket_id`, as seen by `account`.
+    async fn bucket_info(
+        &self,
+        bucket_id: H256,
+        account: AccountId32,
+    ) -> anyhow::Result<BucketInfo>;
+
+    /// Submits the `issue_storage_request` extrinsic of the file with `file_key`, to be stored by
+    /// `msp_id`, and returns its hash.
+    ///
+    /// If `replication_target` is `None`, the standard replication target of the runtime is used.
+    /// The chunks of the file are sent to the providers once the storage request is included in
+    /// a block.
+    async fn submit_storage_request(
+        &self,
+        file_key: H256,
+        file_metadata: FileMetadata,
+        msp_id: H256,
+        replication_target: Option<u32>,
+    ) -> anyhow::Result<H256>;
+}
+
+/// Shared slot for the [`StorageRequestIssuer`] of the node.
+///
+/// Like the [`ProviderStatusHandle`](crate::provider_status::ProviderStatusHandle), it is given
+/// to the RPC upfront, and set once the StorageHub services are running. It is only set for
+/// user nodes.
+#[derive(Clone, Default)]
+pub struct StorageRequestIssuerHandle {
+    issuer: Arc<StdRwLock<Option<Arc<dyn StorageRequestIssuer>>>>,
+}
+
+impl StorageRequestIssuerHandle {
+    /// Sets the issuer of storage requests, replacing any previous one.
+    pub fn set(&self, issuer: Arc<dyn StorageRequestIssuer>) {
+        *self
+            .issuer
+            .write()
+            .expect("Storage request issuer handle lock poisoned") = Some(issuer);
+    }
+
+    /// The issuer of storage requests, or `None` if it is not available (yet).
+    pub fn get(&self) -> Option<Arc<dyn StorageRequestIssuer>> {
+        self.issuer
+            .read()
+            .expect("Storage request issuer handle lock poisoned")
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, future::Future};
+
+    use super::*;
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .expect("Failed to build runtime")
+            .block_on(f)
+    }
+
+    fn account(byte: u8) -> AccountId32 {
+        AccountId32::new([byte; 32])
+    }
+
+    /// Blockchain interface holding a fixed set of buckets.
+    struct MockStorageRequestIssuer {
+        buckets: HashMap<H256, BucketInfo>,
+    }
+
+    #[async_trait]
+    impl StorageRequestIssuer for MockStorageRequestIssuer {
+        async fn bucket_info(
+            &self,
+            bucket_id: H256,
+            _account: AccountId32,
+        ) -> anyhow::Result<BucketInfo> {
+            self.buckets
+                .get(&bucket_id)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("Bucket {:?} not found", bucket_id))
+        }
+
+        async fn submit_storage_request(
+            &self,
+            _file_key: H256,
+            _file_metadata: FileMetadata,
+            _msp_id: H256,
+            _replication_target: Option<u32>,
+        ) -> anyhow::Result<H256> {
+            unreachable!("Validating a storage request does not submit it")
+        }
+    }
+
+    fn issuer_with_bucket(
+        bucket_id: H256,
+        owner: AccountId32,
+        size: u64,
+    ) -> MockStorageRequestIssuer {
+        MockStorageRequestIssuer {
+            buckets: HashMap::from([(
+                bucket_id,
+                BucketInfo {
+                    owner,
+                    size,
+                    data_limit: 1000,
+                },
+            )]),
+        }
+    }
+
+    fn refusal(result: anyhow::Result<()>) -> InvalidStorageRequest {
+        result
+            .expect_err("Storage request should be refused")
+            .downcast::<InvalidStorageRequest>()
+            .expect("Storage request should be refused as invalid")
+    }
+
+    #[test]
+    fn files_fitting_in_an_owned_bucket_are_valid() {
+        let bucket_id = H256::repeat_byte(1);
+        let issuer = issuer_with_bucket(bucket_id, account(1), 400);
+
+        block_on(async {
+            validate_storage_request(&issuer, bucket_id, &account(1), 100)
+                .await
+                .unwrap();
+            // Filling the bucket up to its data limit is allowed.
+            validate_storage_request(&issuer, bucket_id, &account(1), 600)
+                .await
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn buckets_of_other_accounts_are_refused() {
+        let bucket_id = H256::repeat_byte(1);
+        let issuer = issuer_with_bucket(bucket_id, account(1), 0);
+
+        assert_eq!(
+            refusal(block_on(validate_storage_request(
+                &issuer,
+                bucket_id,
+                &account(2),
+                100
+            ))),
+            InvalidStorageRequest::NotBucketOwner {
+                bucket_owner: account(1),
+                account: account(2),
+            }
+        );
+    }
+
+    #[test]
+    fn files_over_the_bucket_data_limit_are_refused() {
+        let bucket_id = H256::repeat_byte(1);
+        let issuer = issuer_with_bucket(bucket_id, account(1), 400);
+
+        assert_eq!(
+            refusal(block_on(validate_storage_request(
+                &issuer,
+                bucket_id,
+                &account(1),
+                601
+            ))),
+            InvalidStorageRequest::ExceedsBucketDataLimit {
+                file_size: 601,
+                bucket_size: 400,
+                data_limit: 1000,
+            }
+        );
+        // Sizes overflowing the bucket size are refused rather than wrapping around.
+        assert!(matches!(
+            refusal(block_on(validate_storage_request(
+                &issuer,
+                bucket_id,
+                &account(1),
+                u64::MAX
+            ))),
+            InvalidStorageRequest::ExceedsBucketDataLimit { .. }
+        ));
+    }
+
+    #[test]
+    fn unknown_buckets_fail_validation() {
+        let issuer = issuer_with_bucket(H256::repeat_byte(1), account(1), 0);
+
+        let error = block_on(validate_storage_request(
+            &issuer,
+            H256::repeat_byte(2),
+            &account(1),
+            100,
+        ))
+        .unwrap_err();
+        assert!(error.downcast_ref::<InvalidStorageRequest>().is_none());
+    }
+}
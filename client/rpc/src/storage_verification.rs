@@ -0,0 +1,101 @@
+use std::sync::{Arc, RwLock as StdRwLock};
+
+use jsonrpsee::core::async_trait;
+use sp_core::H256;
+use sp_runtime::{Deserialize, Serialize};
+
+/// Result of verifying the integrity of the whole File Storage of the node, and reconciling its
+/// Forest with the files found complete.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StorageVerificationReport {
+    /// Number of files verified, including those verified before an interruption.
+    pub verified_files: u64,
+    /// File keys whose chunk counters or current root were out of date, and were rebuilt.
+    pub repaired_file_keys: Vec<H256>,
+    /// File keys whose chunks are not all stored, or do not make up their fingerprint.
+    pub incomplete_file_keys: Vec<H256>,
+    /// The outcome of checking the Forest against the on-chain root.
+    pub forest: ForestVerification,
+}
+
+impl StorageVerificationReport {
+    /// Whether the Forest matches the on-chain root, so proofs can be submitted from it.
+    pub fn forest_reconciled(&self) -> bool {
+        !matches!(self.forest, ForestVerification::Diverged(_))
+    }
+}
+
+/// Outcome of checking the Forest of the node against its on-chain root, after verifying the
+/// File Storage.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ForestVerification {
+    /// The local root already matched the on-chain root, so the Forest was left untouched.
+    InSync(H256),
+    /// The Forest was rebuilt from the metadata of the complete files in the File Storage, which
+    /// make up the on-chain root.
+    Rebuilt {
+        /// The local root of the Forest before being rebuilt.
+        previous_root: H256,
+        /// The on-chain root, which is the local root of the rebuilt Forest.
+        on_chain_root: H256,
+    },
+    /// Neither the Forest nor the complete files in the File Storage make up the on-chain root.
+    Diverged(ForestDiff),
+}
+
+/// The differences between a Forest that diverged from its on-chain root and the complete files
+/// in the File Storage.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ForestDiff {
+    /// The local root of the Forest.
+    pub local_root: H256,
+    /// The root of a Forest rebuilt from the complete files in the File Storage.
+    pub rebuilt_root: H256,
+    /// The on-chain root.
+    pub on_chain_root: H256,
+    /// File keys in the Forest that are not complete in the File Storage.
+    pub missing_from_file_storage: Vec<H256>,
+    /// File keys complete in the File Storage that are not in the Forest.
+    pub missing_from_forest: Vec<H256>,
+}
+
+/// Verifies the integrity of the whole File Storage of the node.
+#[async_trait]
+pub trait StorageVerification: Send + Sync {
+    /// Verifies every stored file against its fingerprint, rebuilding the data derived from its
+    /// chunks, and checks the Forest against the on-chain root.
+    async fn verify_storage(&self) -> anyhow::Result<StorageVerificationReport>;
+
+    /// Requests the repair of the file with `file_key`, which is verified against its
+    /// fingerprint and downloaded again from the other providers storing it if its local copy is
+    /// corrupt.
+    async fn repair_file(&self, file_key: H256) -> anyhow::Result<()>;
+}
+
+/// Shared slot for the [`StorageVerification`] of the node.
+///
+/// Like the [`ForestRecoveryHandle`](crate::forest_recovery::ForestRecoveryHandle), it is given
+/// to the RPC upfront, and set once the StorageHub services are running. It is only set for
+/// nodes able to verify their storage.
+#[derive(Clone, Default)]
+pub struct StorageVerificationHandle {
+    verification: Arc<StdRwLock<Option<Arc<dyn StorageVerification>>>>,
+}
+
+impl StorageVerificationHandle {
+    /// Sets the verification of the storage, replacing any previous one.
+    pub fn set(&self, verification: Arc<dyn StorageVerification>) {
+        *self
+            .verification
+            .write()
+            .expect("Storage verification handle lock poisoned") = Some(verification);
+    }
+
+    /// The verification of the storage, or `None` if it is not available (yet).
+    pub fn get(&self) -> Option<Arc<dyn StorageVerification>> {
+        self.verification
+            .read()
+            .expect("Storage verification handle lock poisoned")
+            .clone()
+    }
+}
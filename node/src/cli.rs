@@ -129,6 +129,10 @@ pub struct ProviderConfigurations {
     ]))]
     pub jump_capacity: Option<StorageDataUnit>,
 
+    /// Minimum number of blocks between two consecutive capacity changes submitted by the provider.
+    #[clap(long, default_value = "0")]
+    pub min_capacity_change_interval: u32,
+
     /// Type of StorageHub provider.
     /// Currently: `memory` and `rocks-db`.
     #[clap(
@@ -139,10 +143,19 @@ pub struct ProviderConfigurations {
     )]
     pub storage_layer: Option<StorageLayer>,
 
-    /// Storage location in the file system
+    /// Storage location in the file system.
+    ///
+    /// With the in-memory storage layer, the Forest Storage is persisted here on shutdown.
     #[clap(long, required_if_eq("storage_layer", "rocks-db"))]
     pub storage_path: Option<String>,
 
+    /// Additional directory (e.g. on another disk) to spread the files of the RocksDB File
+    /// Storage across, along with the storage path. Can be given several times.
+    /// Files are assigned to a directory by file key, so the directories, and their order,
+    /// can't be changed once files are stored.
+    #[clap(long)]
+    pub storage_data_path: Vec<String>,
+
     /// Extrinsic retry timeout in seconds.
     #[clap(long, default_value = "60")]
     pub extrinsic_retry_timeout: u64,
@@ -153,6 +166,119 @@ pub struct ProviderConfigurations {
         ("provider_type", "msp"),
     ]))]
     pub msp_charging_period: Option<u32>,
+
+    /// Maximum number of files the provider accepts to be uploaded to it simultaneously.
+    /// While this limit is reached, BSPs do not volunteer for new storage requests and MSPs
+    /// reject them.
+    /// Defaults to 100.
+    #[clap(long)]
+    pub max_active_uploads: Option<usize>,
+
+    /// Time in seconds to wait for a proof to be generated before giving up on it.
+    /// Defaults to 30.
+    #[clap(long)]
+    pub proof_generation_timeout: Option<u64>,
+
+    /// Time in seconds to wait for the non-inclusion Forest proof of a batch of files to be
+    /// confirmed by a BSP. On timeout, only the files proven so far are confirmed.
+    /// Defaults to 10.
+    #[clap(long)]
+    pub forest_proof_timeout: Option<u64>,
+
+    /// Time in seconds after which a BSP stops retrying to confirm storing a file that is still
+    /// queued, regardless of how many times it was tried. Setting it to 0 disables it.
+    /// Defaults to 3600.
+    #[clap(long)]
+    pub max_queue_age_secs: Option<u64>,
+
+    /// Time in seconds to wait for in-progress uploads and other tasks to finish when the node
+    /// is asked to terminate, after which they are abandoned.
+    /// Defaults to 30.
+    #[clap(long)]
+    pub shutdown_grace_period: Option<u64>,
+
+    /// Time in seconds between two checks of the local Forest roots against the on-chain ones,
+    /// alerting if they diverge. Setting it to 0 disables the check.
+    /// Defaults to 300.
+    #[clap(long)]
+    pub forest_root_check_interval: Option<u64>,
+
+    /// Stop submitting proofs while a local Forest root is diverged from its on-chain root.
+    #[clap(long)]
+    pub pause_proofs_on_forest_root_divergence: bool,
+
+    /// Verify every file in the File Storage against its fingerprint on startup, rebuilding
+    /// their chunk counters and current roots, and check the Forest against the on-chain root.
+    /// The proof tasks are not started unless the Forest can be reconciled with it.
+    /// Meant to be used after restoring a BSP from a backup.
+    #[clap(long)]
+    pub verify_storage_on_startup: bool,
+
+    /// Maximum number of Forest Storage snapshots kept by root, reused when generating
+    /// several proofs against the same root.
+    /// Defaults to 8.
+    #[clap(long)]
+    pub forest_snapshot_cache_size: Option<usize>,
+
+    /// Compress the chunks stored in the RocksDB File Storage with zstd at this level.
+    /// Chunks are stored uncompressed if not set. It can't be changed, nor set or unset,
+    /// once chunks have been stored.
+    #[clap(long)]
+    pub file_storage_compression_level: Option<i32>,
+
+    /// Number of file metadata kept in memory by the RocksDB File Storage, in each of its
+    /// directories, so that it is not read from disk for every chunk written. Setting it to 0
+    /// disables the cache.
+    /// Defaults to 1024.
+    #[clap(long)]
+    pub file_metadata_cache_capacity: Option<usize>,
+
+    /// Coalesce the chunks written to the RocksDB File Storage into a single write once this
+    /// many of them are pending, instead of writing every chunk on its own.
+    /// Chunks not written yet are lost if the node crashes, leaving their files incomplete.
+    /// Disabled if not set.
+    #[clap(long)]
+    pub file_storage_max_pending_chunks: Option<usize>,
+
+    /// Time in milliseconds after which the chunks coalesced by the RocksDB File Storage are
+    /// written, however few they are. Only used along with `--file-storage-max-pending-chunks`.
+    /// Defaults to 1000.
+    #[clap(long)]
+    pub file_storage_flush_interval_ms: Option<u64>,
+
+    /// Delete the trie nodes left orphaned in the RocksDB File Storage if the node was not shut
+    /// down cleanly. The repair reads the whole File Storage in the background once the node has
+    /// started, and nothing is written to the File Storage until it is done.
+    #[clap(long)]
+    pub repair_file_storage: bool,
+
+    /// Maximum number of files a BSP confirms storing in a single extrinsic.
+    /// Capped to the runtime's maximum batch size, which is also the default.
+    #[clap(long)]
+    pub confirm_storing_max_batch_size: Option<u32>,
+
+    /// Maximum number of ticks a BSP waits for a batch of files to confirm storing to fill up.
+    /// Defaults to 0, confirming files as soon as possible.
+    #[clap(long)]
+    pub confirm_storing_max_wait_ticks: Option<u32>,
+
+    /// Files whose storage request expires within this number of ticks are confirmed by a BSP
+    /// without waiting for their batch to fill up.
+    /// Defaults to 10.
+    #[clap(long)]
+    pub confirm_storing_expiry_margin_ticks: Option<u32>,
+
+    /// Number of batches with chunks already stored that a peer can send for a file within
+    /// `duplicate_chunks_window_secs` before its reputation is lowered.
+    /// Defaults to 20, as many as an uploader resends when none of its batches are acknowledged.
+    #[clap(long)]
+    pub duplicate_chunks_threshold: Option<u64>,
+
+    /// Length in seconds of the window in which the batches with chunks already stored sent by
+    /// a peer for a file are counted.
+    /// Defaults to 60.
+    #[clap(long)]
+    pub duplicate_chunks_window_secs: Option<u64>,
 }
 
 impl ProviderConfigurations {
@@ -167,12 +293,35 @@ impl ProviderConfigurations {
                 .clone()
                 .expect("Storage layer is required"),
             storage_path: self.storage_path.clone(),
+            storage_data_paths: Some(self.storage_data_path.clone()),
             // We can default since the clap would have errored out if it was not provided when required.
             // In any other case, max_storage_capacity is not required and can be set to default.
             max_storage_capacity: self.max_storage_capacity,
             jump_capacity: self.jump_capacity,
+            min_capacity_change_interval: Some(self.min_capacity_change_interval),
             extrinsic_retry_timeout: self.extrinsic_retry_timeout,
             msp_charging_period: self.msp_charging_period,
+            max_active_uploads: self.max_active_uploads,
+            proof_generation_timeout: self.proof_generation_timeout,
+            forest_proof_timeout: self.forest_proof_timeout,
+            max_queue_age_secs: self.max_queue_age_secs,
+            shutdown_grace_period: self.shutdown_grace_period,
+            forest_root_check_interval: self.forest_root_check_interval,
+            pause_proofs_on_forest_root_divergence: Some(
+                self.pause_proofs_on_forest_root_divergence,
+            ),
+            verify_storage_on_startup: Some(self.verify_storage_on_startup),
+            forest_snapshot_cache_size: self.forest_snapshot_cache_size,
+            file_storage_compression_level: self.file_storage_compression_level,
+            file_metadata_cache_capacity: self.file_metadata_cache_capacity,
+            file_storage_max_pending_chunks: self.file_storage_max_pending_chunks,
+            file_storage_flush_interval_ms: self.file_storage_flush_interval_ms,
+            repair_file_storage: Some(self.repair_file_storage),
+            confirm_storing_max_batch_size: self.confirm_storing_max_batch_size,
+            confirm_storing_max_wait_ticks: self.confirm_storing_max_wait_ticks,
+            confirm_storing_expiry_margin_ticks: self.confirm_storing_expiry_margin_ticks,
+            duplicate_chunks_threshold: self.duplicate_chunks_threshold,
+            duplicate_chunks_window_secs: self.duplicate_chunks_window_secs,
         }
     }
 }
@@ -182,16 +331,27 @@ pub struct IndexerConfigurations {
     /// Whether to enable the indexer.
     ///
     /// By default, the indexer is disabled.
-    /// If enabled, a Postgres database must be set up (see the indexer README for details).
+    /// If enabled, a Postgres database must be set up, or a SQLite one if the node is built with
+    /// the `indexer-sqlite` feature (see the indexer README for details).
     #[arg(long, default_value = "false")]
     pub indexer: bool,
 
-    /// Postgres database URL.
+    /// Database URL.
+    ///
+    /// `postgres://` URLs point to a Postgres database, while `sqlite://` URLs and plain paths
+    /// point to a SQLite database.
     ///
     /// If not provided, the indexer will use the `DATABASE_URL` environment variable. If the
     /// environment variable is not set, the node will abort.
     #[arg(long)]
     pub database_url: Option<String>,
+
+    /// Whether the indexer should also store the SCALE-encoded value propositions of MSPs.
+    ///
+    /// Value propositions are always stored as JSON, which can be queried directly. The encoded
+    /// value propositions allow reconstructing the exact runtime type.
+    #[arg(long, default_value = "false")]
+    pub indexer_store_encoded_value_props: bool,
 }
 
 /// Block authoring scheme to be used by the dev service.
@@ -266,7 +426,7 @@ pub struct Cli {
     pub provider_config: ProviderConfigurations,
 
     /// Provider configurations file path (allow to specify the provider configuration in a file instead of the cli)
-    #[clap(long, conflicts_with_all = ["provider", "provider_type", "max_storage_capacity", "jump_capacity", "storage_layer", "storage_path", "extrinsic_retry_timeout", "msp_charging_period"])]
+    #[clap(long, conflicts_with_all = ["provider", "provider_type", "max_storage_capacity", "jump_capacity", "min_capacity_change_interval", "storage_layer", "storage_path", "storage_data_path", "extrinsic_retry_timeout", "msp_charging_period", "max_active_uploads", "proof_generation_timeout", "forest_proof_timeout", "max_queue_age_secs", "shutdown_grace_period", "forest_root_check_interval", "pause_proofs_on_forest_root_divergence", "verify_storage_on_startup", "forest_snapshot_cache_size", "file_storage_compression_level", "file_metadata_cache_capacity", "file_storage_max_pending_chunks", "file_storage_flush_interval_ms", "repair_file_storage", "confirm_storing_max_batch_size", "confirm_storing_max_wait_ticks", "confirm_storing_expiry_margin_ticks", "duplicate_chunks_threshold", "duplicate_chunks_window_secs"])]
     pub provider_config_file: Option<String>,
 
     /// Indexer configurations
@@ -27,14 +27,59 @@ pub struct ProviderOptions {
     pub storage_layer: StorageLayer,
     /// RocksDB Path.
     pub storage_path: Option<String>,
+    /// Additional directories to spread the files of the RocksDB File Storage across.
+    pub storage_data_paths: Option<Vec<String>>,
     /// Maximum storage capacity of the Storage Provider (bytes).
     pub max_storage_capacity: Option<StorageDataUnit>,
     /// Jump capacity (bytes).
     pub jump_capacity: Option<StorageDataUnit>,
+    /// Minimum number of blocks between capacity changes.
+    pub min_capacity_change_interval: Option<u32>,
     /// Extrinsic retry timeout in seconds.
     pub extrinsic_retry_timeout: u64,
     /// MSP charging fees frequency.
     pub msp_charging_period: Option<u32>,
+    /// Maximum number of simultaneous uploads to the provider.
+    pub max_active_uploads: Option<usize>,
+    /// Proof generation timeout in seconds.
+    pub proof_generation_timeout: Option<u64>,
+    /// Forest proof generation timeout in seconds, when confirming files as a BSP.
+    pub forest_proof_timeout: Option<u64>,
+    /// Time in seconds after which a BSP drops a queued confirm storing request.
+    pub max_queue_age_secs: Option<u64>,
+    /// Time in seconds to wait for the tasks in flight to finish when shutting down.
+    pub shutdown_grace_period: Option<u64>,
+    /// Time in seconds between two checks of the local Forest roots against the on-chain ones.
+    pub forest_root_check_interval: Option<u64>,
+    /// Whether to stop submitting proofs while a local Forest root is diverged.
+    pub pause_proofs_on_forest_root_divergence: Option<bool>,
+    /// Whether to verify the File Storage and the Forest before starting the proof tasks.
+    pub verify_storage_on_startup: Option<bool>,
+    /// Maximum number of Forest Storage snapshots kept by root.
+    pub forest_snapshot_cache_size: Option<usize>,
+    /// Zstd level the chunks stored in the RocksDB File Storage are compressed at.
+    pub file_storage_compression_level: Option<i32>,
+    /// Number of file metadata kept in memory by the RocksDB File Storage, per directory.
+    pub file_metadata_cache_capacity: Option<usize>,
+    /// Number of chunks pending after which the chunks coalesced by the RocksDB File Storage are
+    /// written, if coalescing them.
+    pub file_storage_max_pending_chunks: Option<usize>,
+    /// Time in milliseconds after which the chunks coalesced by the RocksDB File Storage are
+    /// written.
+    pub file_storage_flush_interval_ms: Option<u64>,
+    /// Whether to delete the orphaned trie nodes of the RocksDB File Storage after a crash.
+    pub repair_file_storage: Option<bool>,
+    /// Maximum number of files confirmed in a single BSP confirm storing extrinsic.
+    pub confirm_storing_max_batch_size: Option<u32>,
+    /// Maximum number of ticks to wait for a BSP confirm storing batch to fill up.
+    pub confirm_storing_max_wait_ticks: Option<u32>,
+    /// Ticks before storage request expiry at which files are confirmed without waiting.
+    pub confirm_storing_expiry_margin_ticks: Option<u32>,
+    /// Number of batches with chunks already stored that a peer can send for a file within a
+    /// window before being reported.
+    pub duplicate_chunks_threshold: Option<u64>,
+    /// Length in seconds of the window in which the batches with duplicate chunks are counted.
+    pub duplicate_chunks_window_secs: Option<u64>,
 }
 
 fn load_spec(id: &str) -> std::result::Result<Box<dyn ChainSpec>, String> {
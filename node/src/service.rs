@@ -3,7 +3,7 @@
 // std
 use futures::{Stream, StreamExt};
 use log::info;
-use shc_blockchain_service::capacity_manager::CapacityConfig;
+use shc_blockchain_service::{capacity_manager::CapacityConfig, types::ConfirmStoringBatchConfig};
 use shc_indexer_db::DbPool;
 use shc_indexer_service::spawn_indexer_service;
 use std::{cell::RefCell, env, path::PathBuf, sync::Arc, time::Duration};
@@ -18,6 +18,7 @@ use polkadot_primitives::{BlakeTwo256, HashT, HeadData};
 use sc_consensus_manual_seal::consensus::aura::AuraConsensusDataProvider;
 use shc_actors_framework::actor::TaskSpawner;
 use shc_common::types::{BlockHash, OpaqueBlock, BCSV_KEY_TYPE};
+use shc_file_manager::rocksdb::{ChunkCompression, WriteCoalescing};
 use shc_rpc::StorageHubClientRpcConfig;
 use sp_consensus_aura::Slot;
 use sp_core::H256;
@@ -53,7 +54,9 @@ use sc_network::{
 use sc_service::{Configuration, PartialComponents, RpcHandlers, TFullBackend, TaskManager};
 use sc_telemetry::{Telemetry, TelemetryHandle, TelemetryWorker, TelemetryWorkerHandle};
 use sc_transaction_pool_api::OffchainTransactionPoolFactory;
-use shc_file_transfer_service::configure_file_transfer_network;
+use shc_file_transfer_service::{
+    configure_file_transfer_network, duplicates::DuplicateChunksConfig,
+};
 use sp_keystore::{Keystore, KeystorePtr};
 use substrate_prometheus_endpoint::Registry;
 
@@ -61,7 +64,10 @@ use crate::{
     cli::{self, IndexerConfigurations, ProviderType, StorageLayer},
     command::ProviderOptions,
     services::{
-        builder::{Buildable, StorageHubBuilder, StorageLayerBuilder},
+        builder::{
+            Buildable, StorageHubBuilder, StorageLayerBuilder,
+            DEFAULT_FILE_STORAGE_FLUSH_INTERVAL_MILLIS,
+        },
         handler::{RunnableTasks, StorageHubHandler},
         types::{
             BspProvider, InMemoryStorageLayer, MspProvider, NoStorageLayer, RocksDbStorageLayer,
@@ -194,11 +200,13 @@ pub fn new_partial(
 
 async fn init_sh_builder<R, S>(
     provider_options: &Option<ProviderOptions>,
-    task_manager: &TaskManager,
+    task_manager: &mut TaskManager,
     file_transfer_request_protocol: Option<(ProtocolName, Receiver<IncomingRequest>)>,
     network: Arc<dyn NetworkService>,
     keystore: KeystorePtr,
     maybe_db_pool: Option<DbPool>,
+    prometheus_registry: Option<&Registry>,
+    telemetry: Option<TelemetryHandle>,
 ) -> Option<(
     StorageHubBuilder<R, S>,
     StorageHubClientRpcConfig<<(R, S) as ShNodeType>::FL, <(R, S) as ShNodeType>::FSH>,
@@ -213,10 +221,31 @@ where
         Some(ProviderOptions {
             provider_type,
             storage_path,
+            storage_data_paths,
             max_storage_capacity,
             jump_capacity,
+            min_capacity_change_interval,
             extrinsic_retry_timeout,
             msp_charging_period,
+            max_active_uploads,
+            proof_generation_timeout,
+            forest_proof_timeout,
+            max_queue_age_secs,
+            forest_root_check_interval,
+            pause_proofs_on_forest_root_divergence,
+            verify_storage_on_startup,
+            shutdown_grace_period,
+            forest_snapshot_cache_size,
+            file_storage_compression_level,
+            file_metadata_cache_capacity,
+            file_storage_max_pending_chunks,
+            file_storage_flush_interval_ms,
+            repair_file_storage,
+            confirm_storing_max_batch_size,
+            confirm_storing_max_wait_ticks,
+            confirm_storing_expiry_margin_ticks,
+            duplicate_chunks_threshold,
+            duplicate_chunks_window_secs,
             ..
         }) => {
             info!(
@@ -224,8 +253,15 @@ where
                 storage_path, max_storage_capacity, jump_capacity, msp_charging_period,
             );
 
+            // The StorageHub tasks run in a child of the node's TaskManager, which is dropped after
+            // the node's own tasks are told to exit. This keeps the actors running while the
+            // StorageHub tasks are drained on shutdown (see `DrainOnShutdown`).
+            let sh_task_manager = TaskManager::new(tokio::runtime::Handle::current(), None)
+                .expect("TaskManager without a Prometheus registry should always be created.");
+            let task_spawner = TaskSpawner::new(sh_task_manager.spawn_handle(), "sh-builder");
+            task_manager.add_child(sh_task_manager);
+
             // Start building the StorageHubHandler, if running as a provider.
-            let task_spawner = TaskSpawner::new(task_manager.spawn_handle(), "sh-builder");
             let mut storage_hub_builder = StorageHubBuilder::<R, S>::new(task_spawner);
 
             // Setup and spawn the File Transfer Service.
@@ -233,6 +269,16 @@ where
                 file_transfer_request_protocol
                     .expect("FileTransfer request protocol should already be initialised.");
 
+            // The reports of duplicate chunks are configured when spawning the File Transfer Service.
+            let default_duplicate_chunks_config = DuplicateChunksConfig::default();
+            storage_hub_builder.with_duplicate_chunks_config(DuplicateChunksConfig {
+                threshold: duplicate_chunks_threshold
+                    .unwrap_or(default_duplicate_chunks_config.threshold),
+                window: duplicate_chunks_window_secs
+                    .map(Duration::from_secs)
+                    .unwrap_or(default_duplicate_chunks_config.window),
+            });
+
             storage_hub_builder
                 .with_file_transfer(
                     file_transfer_request_receiver,
@@ -241,15 +287,104 @@ where
                 )
                 .await;
 
+            // The Forest Storage snapshot cache is configured when setting up the storage layer.
+            if let Some(forest_snapshot_cache_size) = forest_snapshot_cache_size {
+                storage_hub_builder.with_forest_snapshot_cache_size(*forest_snapshot_cache_size);
+            }
+
+            // The File Storage compression is configured when setting up the storage layer.
+            if let Some(level) = file_storage_compression_level {
+                storage_hub_builder
+                    .with_file_storage_compression(ChunkCompression::Zstd { level: *level });
+            }
+
+            // The File Storage metadata cache is configured when setting up the storage layer.
+            if let Some(file_metadata_cache_capacity) = file_metadata_cache_capacity {
+                storage_hub_builder
+                    .with_file_metadata_cache_capacity(*file_metadata_cache_capacity);
+            }
+
+            // The File Storage write coalescing is configured when setting up the storage layer.
+            if let Some(max_pending_chunks) = file_storage_max_pending_chunks {
+                let flush_interval_ms = file_storage_flush_interval_ms
+                    .unwrap_or(DEFAULT_FILE_STORAGE_FLUSH_INTERVAL_MILLIS);
+                storage_hub_builder.with_file_storage_write_coalescing(WriteCoalescing {
+                    flush_interval: Duration::from_millis(flush_interval_ms),
+                    max_pending_chunks: *max_pending_chunks,
+                });
+            }
+
+            // The File Storage repair is started when setting up the storage layer.
+            if let Some(repair_file_storage) = repair_file_storage {
+                storage_hub_builder.with_repair_file_storage(*repair_file_storage);
+            }
+
+            // The File Storage data paths are used when setting up the storage layer.
+            if let Some(storage_data_paths) = storage_data_paths {
+                storage_hub_builder.with_file_storage_data_paths(storage_data_paths.clone());
+            }
+
             // Setup the `ShStorageLayer` and additional configuration parameters.
             storage_hub_builder
                 .setup_storage_layer(storage_path.clone())
                 .with_retry_timeout(*extrinsic_retry_timeout)
+                .with_prometheus_registry(prometheus_registry)
+                .with_telemetry(telemetry)
                 .with_capacity_config(Some(CapacityConfig::new(
                     max_storage_capacity.unwrap_or_default(),
                     jump_capacity.unwrap_or_default(),
+                    min_capacity_change_interval.unwrap_or_default(),
                 )));
 
+            if let Some(max_active_uploads) = max_active_uploads {
+                storage_hub_builder.with_max_active_uploads(*max_active_uploads);
+            }
+
+            if let Some(proof_generation_timeout) = proof_generation_timeout {
+                storage_hub_builder.with_proof_generation_timeout(*proof_generation_timeout);
+            }
+
+            if let Some(forest_proof_timeout) = forest_proof_timeout {
+                storage_hub_builder.with_forest_proof_timeout(*forest_proof_timeout);
+            }
+
+            if let Some(max_queue_age_secs) = max_queue_age_secs {
+                storage_hub_builder.with_max_queue_age_secs(*max_queue_age_secs);
+            }
+
+            if let Some(shutdown_grace_period) = shutdown_grace_period {
+                storage_hub_builder.with_shutdown_grace_period(*shutdown_grace_period);
+            }
+
+            if let Some(forest_root_check_interval) = forest_root_check_interval {
+                storage_hub_builder.with_forest_root_check_interval(*forest_root_check_interval);
+            }
+
+            if let Some(pause_proofs_on_forest_root_divergence) =
+                pause_proofs_on_forest_root_divergence
+            {
+                storage_hub_builder.with_pause_proofs_on_forest_root_divergence(
+                    *pause_proofs_on_forest_root_divergence,
+                );
+            }
+
+            if let Some(verify_storage_on_startup) = verify_storage_on_startup {
+                storage_hub_builder.with_verify_storage_on_startup(*verify_storage_on_startup);
+            }
+
+            // Setup specific configuration for the BSP node.
+            if *provider_type == ProviderType::Bsp {
+                let default_batch_config = ConfirmStoringBatchConfig::default();
+                storage_hub_builder.with_confirm_storing_batch_config(ConfirmStoringBatchConfig {
+                    max_batch_size: confirm_storing_max_batch_size
+                        .unwrap_or(default_batch_config.max_batch_size),
+                    max_wait_ticks: confirm_storing_max_wait_ticks
+                        .unwrap_or(default_batch_config.max_wait_ticks),
+                    expiry_margin_ticks: confirm_storing_expiry_margin_ticks
+                        .unwrap_or(default_batch_config.expiry_margin_ticks),
+                });
+            }
+
             // Setup specific configuration for the MSP node.
             if *provider_type == ProviderType::Msp {
                 storage_hub_builder
@@ -268,6 +403,7 @@ where
 
 async fn finish_sh_builder_and_run_tasks<R, S>(
     mut sh_builder: StorageHubBuilder<R, S>,
+    task_manager: &mut TaskManager,
     client: Arc<ParachainClient>,
     rpc_handlers: RpcHandlers,
     keystore: KeystorePtr,
@@ -296,9 +432,42 @@ where
     // Run StorageHub tasks according to the node role
     sh_handler.run_tasks().await;
 
+    // Drain the StorageHub tasks when the node shuts down.
+    task_manager.keep_alive(DrainOnShutdown {
+        sh_handler,
+        tokio_handle: tokio::runtime::Handle::current(),
+    });
+
     Ok(())
 }
 
+/// Gracefully shuts down the StorageHub tasks when dropped by the node's TaskManager.
+///
+/// The runner drops the TaskManager once the node receives a SIGINT or SIGTERM. The TaskManager
+/// signals its own tasks to exit, drops what it keeps alive and only then drops its children, so
+/// the child TaskManager running the StorageHub tasks is still up while this drains them.
+struct DrainOnShutdown<NT: ShNodeType + 'static> {
+    sh_handler: StorageHubHandler<NT>,
+    tokio_handle: tokio::runtime::Handle,
+}
+
+impl<NT: ShNodeType + 'static> Drop for DrainOnShutdown<NT> {
+    fn drop(&mut self) {
+        // Blocking on the drain is only possible outside of the runtime, which is where the runner
+        // drops the TaskManager. Anywhere else the node failed to start, so there is nothing to
+        // drain.
+        if tokio::runtime::Handle::try_current().is_ok() {
+            log::warn!("TaskManager dropped within the runtime, StorageHub tasks won't be drained");
+            return;
+        }
+
+        let grace_period =
+            Duration::from_secs(self.sh_handler.provider_config.shutdown_grace_period);
+        self.tokio_handle
+            .block_on(self.sh_handler.shutdown(grace_period));
+    }
+}
+
 /// Start a development node with the given solo chain `Configuration`.
 async fn start_dev_impl<R, S, Network>(
     config: Configuration,
@@ -353,6 +522,8 @@ where
             maybe_db_pool.clone().expect(
                 "Indexer is enabled but no database URL is provided (via CLI using --database-url or setting DATABASE_URL environment variable)",
             ),
+            indexer_config.indexer_store_encoded_value_props,
+            config.prometheus_registry(),
         )
         .await;
     }
@@ -477,11 +648,13 @@ where
     // If node is running as a Storage Provider, start building the StorageHubHandler using the StorageHubBuilder.
     let (sh_builder, maybe_storage_hub_client_rpc_config) = match init_sh_builder::<R, S>(
         &provider_options,
-        &task_manager,
+        &mut task_manager,
         file_transfer_request_protocol,
         network.clone(),
         keystore.clone(),
         maybe_db_pool,
+        prometheus_registry.as_ref(),
+        telemetry.as_ref().map(|telemetry| telemetry.handle()),
     )
     .await
     {
@@ -526,6 +699,7 @@ where
     if let Some(_) = provider_options {
         finish_sh_builder_and_run_tasks(
             sh_builder.expect("StorageHubBuilder should already be initialised."),
+            &mut task_manager,
             client.clone(),
             rpc_handlers,
             keystore.clone(),
@@ -756,6 +930,8 @@ where
             maybe_db_pool.clone().expect(
                 "Indexer is enabled but no database URL is provided (via CLI using --database-url or setting DATABASE_URL environment variable)",
             ),
+            indexer_config.indexer_store_encoded_value_props,
+            parachain_config.prometheus_registry(),
         )
         .await;
     }
@@ -826,11 +1002,13 @@ where
     // If node is running as a Storage Provider, start building the StorageHubHandler using the StorageHubBuilder.
     let (sh_builder, maybe_storage_hub_client_rpc_config) = match init_sh_builder::<R, S>(
         &provider_options,
-        &task_manager,
+        &mut task_manager,
         file_transfer_request_protocol,
         network.clone(),
         keystore.clone(),
         maybe_db_pool,
+        prometheus_registry.as_ref(),
+        telemetry.as_ref().map(|telemetry| telemetry.handle()),
     )
     .await
     {
@@ -875,6 +1053,7 @@ where
     if let Some(_) = provider_options {
         finish_sh_builder_and_run_tasks(
             sh_builder.expect("StorageHubBuilder should already be initialised."),
+            &mut task_manager,
             client.clone(),
             rpc_handlers,
             keystore.clone(),
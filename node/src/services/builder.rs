@@ -1,25 +1,64 @@
 use async_channel::Receiver;
+use log::{error, info, warn};
 use sc_network::{config::IncomingRequest, service::traits::NetworkService, ProtocolName};
 use sc_service::RpcHandlers;
+use sc_telemetry::TelemetryHandle;
 use shc_indexer_db::DbPool;
 use sp_keystore::KeystorePtr;
-use std::{path::PathBuf, sync::Arc};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 use tokio::sync::RwLock;
 
 use shc_actors_framework::actor::{ActorHandle, TaskSpawner};
 use shc_blockchain_service::{
-    capacity_manager::CapacityConfig, spawn_blockchain_service, BlockchainService,
+    capacity_manager::CapacityConfig, spawn_blockchain_service, types::ConfirmStoringBatchConfig,
+    BlockchainService,
 };
-use shc_common::types::ParachainClient;
-use shc_file_manager::{in_memory::InMemoryFileStorage, rocksdb::RocksDbFileStorage};
-use shc_file_transfer_service::{spawn_file_transfer_service, FileTransferService};
-use shc_forest_manager::traits::ForestStorageHandler;
-use shc_rpc::StorageHubClientRpcConfig;
+use shc_common::{
+    types::{ParachainClient, StorageProofsMerkleTrieLayout},
+    upload_progress::UploadProgressRegistry,
+    user_uploads::UserUploadQueue,
+};
+use shc_file_manager::{
+    in_memory::InMemoryFileStorage,
+    metadata_cache::DEFAULT_METADATA_CACHE_CAPACITY,
+    rocksdb::{ChunkCompression, RocksDbFileStorage, WriteCoalescing},
+    sharded::ShardedFileStorage,
+};
+use shc_file_transfer_service::{
+    duplicates::DuplicateChunksConfig, spawn_file_transfer_service, FileTransferService,
+};
+use shc_forest_manager::{
+    snapshot_cache::DEFAULT_FOREST_SNAPSHOT_CACHE_SIZE, traits::ForestStorageHandler,
+};
+use shc_rpc::{
+    forest_recovery::ForestRecoveryHandle, provider_status::ProviderStatusHandle,
+    storage_request::StorageRequestIssuerHandle, storage_verification::StorageVerificationHandle,
+    StorageHubClientRpcConfig,
+};
+use substrate_prometheus_endpoint::Registry;
+
+const LOG_TARGET: &str = "storage-hub-builder";
 
 const DEFAULT_EXTRINSIC_RETRY_TIMEOUT_SECONDS: u64 = 60;
+const DEFAULT_MAX_ACTIVE_UPLOADS: usize = 100;
+const DEFAULT_PROOF_GENERATION_TIMEOUT_SECONDS: u64 = 30;
+const DEFAULT_FOREST_PROOF_TIMEOUT_SECONDS: u64 = 10;
+const DEFAULT_MAX_QUEUE_AGE_SECONDS: u64 = 3600;
+const DEFAULT_SHUTDOWN_GRACE_PERIOD_SECONDS: u64 = 30;
+const DEFAULT_FOREST_ROOT_CHECK_INTERVAL_SECONDS: u64 = 300;
+pub const DEFAULT_FILE_STORAGE_FLUSH_INTERVAL_MILLIS: u64 = 1000;
+
+use crate::tasks::{
+    bsp_recover_forest::BspRecoverForestTask, bsp_verify_storage::BspVerifyStorageTask,
+    msp_recover_forest::MspRecoverForestTask, user_sends_file::UserSendsFileTask,
+};
 
 use super::{
+    forest_storage::FOREST_SNAPSHOTS_DIR,
     handler::{ProviderConfig, StorageHubHandler},
+    metrics::ProviderMetrics,
+    provider_status::ServicesProviderStatusSource,
+    telemetry::BspTelemetry,
     types::{
         BspForestStorageHandlerT, BspProvider, InMemoryStorageLayer, MspForestStorageHandlerT,
         MspProvider, NoStorageLayer, RocksDbStorageLayer, ShNodeType, ShRole, ShStorageLayer,
@@ -41,12 +80,36 @@ where
     file_transfer: Option<ActorHandle<FileTransferService>>,
     blockchain: Option<ActorHandle<BlockchainService<<(R, S) as ShNodeType>::FSH>>>,
     storage_path: Option<String>,
+    file_storage_data_paths: Vec<String>,
     file_storage: Option<Arc<RwLock<<(R, S) as ShNodeType>::FL>>>,
     forest_storage_handler: Option<<(R, S) as ShNodeType>::FSH>,
     capacity_config: Option<CapacityConfig>,
     extrinsic_retry_timeout: u64,
+    max_active_uploads: usize,
+    proof_generation_timeout: u64,
+    forest_proof_timeout: u64,
+    max_queue_age_secs: u64,
+    shutdown_grace_period: u64,
+    forest_root_check_interval: u64,
+    pause_proofs_on_forest_root_divergence: bool,
+    verify_storage_on_startup: bool,
+    forest_snapshot_cache_size: usize,
+    file_storage_compression: ChunkCompression,
+    file_metadata_cache_capacity: usize,
+    file_storage_write_coalescing: Option<WriteCoalescing>,
+    repair_file_storage: bool,
     indexer_db_pool: Option<DbPool>,
     notify_period: Option<u32>,
+    confirm_storing_batch_config: ConfirmStoringBatchConfig,
+    duplicate_chunks_config: DuplicateChunksConfig,
+    upload_progress: UploadProgressRegistry,
+    upload_queue: UserUploadQueue,
+    provider_status: ProviderStatusHandle,
+    forest_recovery: ForestRecoveryHandle,
+    storage_verification: StorageVerificationHandle,
+    storage_request_issuer: StorageRequestIssuerHandle,
+    metrics: Option<ProviderMetrics>,
+    telemetry: Option<BspTelemetry>,
 }
 
 /// Common components to build for any given configuration of [`ShRole`] and [`ShStorageLayer`].
@@ -60,12 +123,36 @@ where
             file_transfer: None,
             blockchain: None,
             storage_path: None,
+            file_storage_data_paths: Vec::new(),
             file_storage: None,
             forest_storage_handler: None,
             capacity_config: None,
             extrinsic_retry_timeout: DEFAULT_EXTRINSIC_RETRY_TIMEOUT_SECONDS,
+            max_active_uploads: DEFAULT_MAX_ACTIVE_UPLOADS,
+            proof_generation_timeout: DEFAULT_PROOF_GENERATION_TIMEOUT_SECONDS,
+            forest_proof_timeout: DEFAULT_FOREST_PROOF_TIMEOUT_SECONDS,
+            max_queue_age_secs: DEFAULT_MAX_QUEUE_AGE_SECONDS,
+            shutdown_grace_period: DEFAULT_SHUTDOWN_GRACE_PERIOD_SECONDS,
+            forest_root_check_interval: DEFAULT_FOREST_ROOT_CHECK_INTERVAL_SECONDS,
+            pause_proofs_on_forest_root_divergence: false,
+            verify_storage_on_startup: false,
+            forest_snapshot_cache_size: DEFAULT_FOREST_SNAPSHOT_CACHE_SIZE,
+            file_storage_compression: ChunkCompression::None,
+            file_metadata_cache_capacity: DEFAULT_METADATA_CACHE_CAPACITY,
+            file_storage_write_coalescing: None,
+            repair_file_storage: false,
             indexer_db_pool: None,
             notify_period: None,
+            confirm_storing_batch_config: ConfirmStoringBatchConfig::default(),
+            duplicate_chunks_config: DuplicateChunksConfig::default(),
+            upload_progress: UploadProgressRegistry::default(),
+            upload_queue: UserUploadQueue::default(),
+            provider_status: ProviderStatusHandle::default(),
+            forest_recovery: ForestRecoveryHandle::default(),
+            storage_verification: StorageVerificationHandle::default(),
+            storage_request_issuer: StorageRequestIssuerHandle::default(),
+            metrics: None,
+            telemetry: None,
         }
     }
 
@@ -83,6 +170,7 @@ where
             file_transfer_request_receiver,
             file_transfer_request_protocol_name,
             network,
+            self.duplicate_chunks_config,
         )
         .await;
 
@@ -90,6 +178,44 @@ where
         self
     }
 
+    /// Set when the File Transfer Service reports the peers sending chunks that were already
+    /// stored.
+    ///
+    /// Cannot be set if the File Transfer Service has already been spawned.
+    pub fn with_duplicate_chunks_config(
+        &mut self,
+        duplicate_chunks_config: DuplicateChunksConfig,
+    ) -> &mut Self {
+        if self.file_transfer.is_some() {
+            panic!("`with_duplicate_chunks_config` should be called before starting the File Transfer Service. Use `with_file_transfer` after calling `with_duplicate_chunks_config`.");
+        }
+        self.duplicate_chunks_config = duplicate_chunks_config;
+        self
+    }
+
+    /// Register the Prometheus metrics of the tasks in `prometheus_registry`, if any.
+    ///
+    /// Metrics are optional, so failing to register them is only logged.
+    pub fn with_prometheus_registry(
+        &mut self,
+        prometheus_registry: Option<&Registry>,
+    ) -> &mut Self {
+        self.metrics = prometheus_registry.and_then(|registry| {
+            ProviderMetrics::register(registry)
+                .map_err(
+                    |e| warn!(target: LOG_TARGET, "Failed to register provider metrics: {:?}", e),
+                )
+                .ok()
+        });
+        self
+    }
+
+    /// Report the uploads of the BSP tasks to the telemetry endpoints of `telemetry`, if any.
+    pub fn with_telemetry(&mut self, telemetry: Option<TelemetryHandle>) -> &mut Self {
+        self.telemetry = telemetry.map(BspTelemetry::new);
+        self
+    }
+
     /// Set the maximum storage capacity.
     ///
     /// The node will not increase its on-chain capacity above this value.
@@ -107,6 +233,154 @@ where
         self
     }
 
+    /// Set the maximum number of files that can be uploaded to the provider simultaneously.
+    ///
+    /// The default value is `100`.
+    pub fn with_max_active_uploads(&mut self, max_active_uploads: usize) -> &mut Self {
+        self.max_active_uploads = max_active_uploads;
+        self
+    }
+
+    /// Set the timeout for generating a proof, after which the proof is given up on.
+    ///
+    /// The default value is `30` seconds.
+    pub fn with_proof_generation_timeout(&mut self, proof_generation_timeout: u64) -> &mut Self {
+        self.proof_generation_timeout = proof_generation_timeout;
+        self
+    }
+
+    /// Set the timeout for generating the non-inclusion Forest proof when confirming files as a
+    /// BSP, after which only the files proven so far are confirmed.
+    ///
+    /// The default value is `10` seconds.
+    pub fn with_forest_proof_timeout(&mut self, forest_proof_timeout: u64) -> &mut Self {
+        self.forest_proof_timeout = forest_proof_timeout;
+        self
+    }
+
+    /// Set the time after which a BSP drops a confirm storing request still queued, regardless
+    /// of how many times it was tried. `0` disables it.
+    ///
+    /// The default value is `3600` seconds.
+    pub fn with_max_queue_age_secs(&mut self, max_queue_age_secs: u64) -> &mut Self {
+        self.max_queue_age_secs = max_queue_age_secs;
+        self
+    }
+
+    /// Set the time to wait for the tasks in flight (i.e. uploads being written) to finish when
+    /// the node is asked to terminate.
+    ///
+    /// The default value is `30` seconds.
+    pub fn with_shutdown_grace_period(&mut self, shutdown_grace_period: u64) -> &mut Self {
+        self.shutdown_grace_period = shutdown_grace_period;
+        self
+    }
+
+    /// Set the time between two checks of the local Forest roots against the on-chain ones.
+    /// Setting it to `0` disables the check.
+    ///
+    /// The default value is `300` seconds.
+    pub fn with_forest_root_check_interval(
+        &mut self,
+        forest_root_check_interval: u64,
+    ) -> &mut Self {
+        self.forest_root_check_interval = forest_root_check_interval;
+        self
+    }
+
+    /// Set whether to stop submitting proofs while a local Forest root is diverged from its
+    /// on-chain root.
+    ///
+    /// The default value is `false`.
+    pub fn with_pause_proofs_on_forest_root_divergence(
+        &mut self,
+        pause_proofs_on_forest_root_divergence: bool,
+    ) -> &mut Self {
+        self.pause_proofs_on_forest_root_divergence = pause_proofs_on_forest_root_divergence;
+        self
+    }
+
+    /// Set whether to verify the whole File Storage and the Forest against the on-chain root
+    /// before starting the proof tasks. Only used by BSPs.
+    ///
+    /// The default value is `false`.
+    pub fn with_verify_storage_on_startup(&mut self, verify_storage_on_startup: bool) -> &mut Self {
+        self.verify_storage_on_startup = verify_storage_on_startup;
+        self
+    }
+
+    /// Set the maximum number of Forest Storage snapshots kept in memory by root, to reuse them
+    /// when generating proofs.
+    ///
+    /// Must be set before setting up the storage layer. The default value is `8`.
+    pub fn with_forest_snapshot_cache_size(
+        &mut self,
+        forest_snapshot_cache_size: usize,
+    ) -> &mut Self {
+        self.forest_snapshot_cache_size = forest_snapshot_cache_size;
+        self
+    }
+
+    /// Set the compression of the chunks stored in a RocksDB File Storage.
+    ///
+    /// It can't be changed once chunks are stored (see [`ChunkCompression`]). Must be set before
+    /// setting up the storage layer. Chunks are stored uncompressed by default.
+    pub fn with_file_storage_compression(&mut self, compression: ChunkCompression) -> &mut Self {
+        self.file_storage_compression = compression;
+        self
+    }
+
+    /// Set the number of file metadata kept in memory by a RocksDB File Storage, in each of its
+    /// directories, so that it is not read from the database for every chunk written. A capacity
+    /// of 0 disables the cache.
+    ///
+    /// Must be set before setting up the storage layer. The default value is `1024`.
+    pub fn with_file_metadata_cache_capacity(
+        &mut self,
+        file_metadata_cache_capacity: usize,
+    ) -> &mut Self {
+        self.file_metadata_cache_capacity = file_metadata_cache_capacity;
+        self
+    }
+
+    /// Coalesce the chunks written to a RocksDB File Storage into fewer transactions, flushed
+    /// once enough of them are pending and every flush interval (see
+    /// [`RocksDbFileStorage::with_write_coalescing`]).
+    ///
+    /// The chunks not flushed yet are lost if the node crashes, leaving their files incomplete.
+    /// Must be set before setting up the storage layer. Disabled by default.
+    pub fn with_file_storage_write_coalescing(
+        &mut self,
+        file_storage_write_coalescing: WriteCoalescing,
+    ) -> &mut Self {
+        self.file_storage_write_coalescing = Some(file_storage_write_coalescing);
+        self
+    }
+
+    /// Set whether to delete the trie nodes left orphaned in a RocksDB File Storage that was not
+    /// shut down cleanly.
+    ///
+    /// The repair runs in a task once the node has started, holding the write lock of the File
+    /// Storage until it is done. Must be set before setting up the storage layer. Disabled by
+    /// default.
+    pub fn with_repair_file_storage(&mut self, repair_file_storage: bool) -> &mut Self {
+        self.repair_file_storage = repair_file_storage;
+        self
+    }
+
+    /// Set additional directories (e.g. on other disks) to spread the files of a RocksDB File
+    /// Storage across, along with the storage path.
+    ///
+    /// Files are assigned to a directory by file key, so the directories can't be changed once
+    /// files are stored. Must be set before setting up the storage layer. None by default.
+    pub fn with_file_storage_data_paths(
+        &mut self,
+        file_storage_data_paths: Vec<String>,
+    ) -> &mut Self {
+        self.file_storage_data_paths = file_storage_data_paths;
+        self
+    }
+
     /// Add an alert notification for every X blocks to the Blockchain Service.
     ///
     /// Cannot be added if the Blockchain Service has already been spawned.
@@ -118,10 +392,27 @@ where
         self
     }
 
+    /// Set how pending BSP confirm storing requests are batched into a single extrinsic by the
+    /// Blockchain Service.
+    ///
+    /// Cannot be set if the Blockchain Service has already been spawned.
+    pub fn with_confirm_storing_batch_config(
+        &mut self,
+        confirm_storing_batch_config: ConfirmStoringBatchConfig,
+    ) -> &mut Self {
+        if self.blockchain.is_some() {
+            panic!("`with_confirm_storing_batch_config` should be called before starting the Blockchain Service. Use `with_blockchain` after calling `with_confirm_storing_batch_config`.");
+        }
+        self.confirm_storing_batch_config = confirm_storing_batch_config;
+        self
+    }
+
     /// Spawn the Blockchain Service.
     ///
     /// Cannot be called before setting the Forest Storage Handler.
     /// Call [`setup_storage_layer`](StorageHubBuilder::setup_storage_layer) before calling this method.
+    /// If the File Transfer Service is already spawned, the `providerStatus` RPC starts being served
+    /// from here on.
     pub async fn with_blockchain(
         &mut self,
         client: Arc<ParachainClient>,
@@ -135,6 +426,13 @@ where
             );
         }
 
+        // Reload the Forest Storage persisted on the last shutdown before anything uses it.
+        if let Some(forest_storage_handler) = self.forest_storage_handler.as_mut() {
+            if let Err(e) = forest_storage_handler.restore_all().await {
+                warn!(target: LOG_TARGET, "Failed to restore the persisted Forest Storage: {:?}", e);
+            }
+        }
+
         let forest_storage_handler = self
             .forest_storage_handler
             .clone()
@@ -153,9 +451,18 @@ where
             rocksdb_root_path,
             self.notify_period,
             capacity_config,
+            self.confirm_storing_batch_config,
         )
         .await;
 
+        if let Some(file_transfer) = self.file_transfer.clone() {
+            self.provider_status
+                .set(Arc::new(ServicesProviderStatusSource::new(
+                    blockchain_service_handle.clone(),
+                    file_transfer,
+                )));
+        }
+
         self.blockchain = Some(blockchain_service_handle);
         self
     }
@@ -185,10 +492,115 @@ where
                 .clone()
                 .expect("Forest Storage Handler not initialized. Use `setup_storage_layer` before calling `create_rpc_config`."),
             keystore,
+            self.upload_progress.clone(),
+            self.upload_queue.clone(),
+            self.provider_status.clone(),
+            self.forest_recovery.clone(),
+            self.storage_verification.clone(),
+            self.storage_request_issuer.clone(),
         )
     }
 }
 
+/// Opens the RocksDB File Storage at `storage_path`, sharded across `data_paths` as well, with
+/// its chunks stored with `compression`.
+///
+/// With `write_coalescing`, the chunk writes are flushed periodically by a task spawned with
+/// `task_spawner`.
+fn rocksdb_file_storage(
+    task_spawner: &TaskSpawner,
+    storage_path: &str,
+    data_paths: &[String],
+    compression: ChunkCompression,
+    metadata_cache_capacity: usize,
+    write_coalescing: Option<WriteCoalescing>,
+    repair: bool,
+) -> Arc<RwLock<ShardedFileStorage<StorageProofsMerkleTrieLayout, kvdb_rocksdb::Database>>> {
+    let shards = std::iter::once(storage_path)
+        .chain(data_paths.iter().map(String::as_str))
+        .map(|path| {
+            let storage =
+                RocksDbFileStorage::<_, kvdb_rocksdb::Database>::rocksdb_storage(path.to_string())
+                    .expect("Failed to create RocksDB")
+                    .with_compression(compression);
+            let file_storage = RocksDbFileStorage::new(storage)
+                .with_metadata_cache_capacity(metadata_cache_capacity)
+                .with_crash_recovery_flag();
+            match write_coalescing {
+                Some(write_coalescing) => file_storage.with_write_coalescing(write_coalescing),
+                None => file_storage,
+            }
+        })
+        .collect();
+
+    // Opening the data paths in another order would look files up in the wrong shard.
+    let file_storage = ShardedFileStorage::new(shards).expect(
+        "The File Storage data paths were reordered, added or removed since they were first used",
+    );
+    let shut_down_cleanly = file_storage.was_shut_down_cleanly();
+    let file_storage = Arc::new(RwLock::new(file_storage));
+
+    if !shut_down_cleanly {
+        if repair {
+            task_spawner.spawn(repair_file_storage(file_storage.clone()));
+        } else {
+            warn!(target: LOG_TARGET, "File Storage was not shut down cleanly and may hold orphaned trie nodes. Start the node with `--repair-file-storage` to delete them.");
+        }
+    }
+
+    if let Some(write_coalescing) = write_coalescing {
+        task_spawner.spawn(flush_file_storage_periodically(
+            task_spawner.clone(),
+            file_storage.clone(),
+            write_coalescing.flush_interval,
+        ));
+    }
+
+    file_storage
+}
+
+/// Deletes the trie nodes left orphaned in `file_storage` as it was not shut down cleanly.
+///
+/// The repair reads the whole File Storage, so it runs on a blocking thread, holding the write
+/// lock of the File Storage until it is done.
+async fn repair_file_storage(
+    file_storage: Arc<
+        RwLock<ShardedFileStorage<StorageProofsMerkleTrieLayout, kvdb_rocksdb::Database>>,
+    >,
+) {
+    warn!(target: LOG_TARGET, "File Storage was not shut down cleanly, deleting its orphaned trie nodes. Nothing is written to it until the repair is done.");
+
+    let repair =
+        tokio::task::spawn_blocking(move || file_storage.blocking_write().check_and_repair());
+    match repair.await {
+        Ok(Ok(report)) => info!(target: LOG_TARGET, "File Storage repaired: {:?}", report),
+        Ok(Err(e)) => error!(target: LOG_TARGET, "Failed to repair the File Storage: {:?}", e),
+        Err(e) => error!(target: LOG_TARGET, "File Storage repair panicked: {:?}", e),
+    }
+}
+
+/// Flushes the chunk writes coalesced by `file_storage` every `flush_interval`, until the node
+/// shuts down. The File Storage flushes the remaining ones itself when it is dropped.
+async fn flush_file_storage_periodically(
+    task_spawner: TaskSpawner,
+    file_storage: Arc<
+        RwLock<ShardedFileStorage<StorageProofsMerkleTrieLayout, kvdb_rocksdb::Database>>,
+    >,
+    flush_interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(flush_interval);
+    loop {
+        ticker.tick().await;
+        if task_spawner.graceful_shutdown().is_shutting_down() {
+            return;
+        }
+
+        if let Err(e) = file_storage.read().await.flush() {
+            warn!(target: LOG_TARGET, "Failed to flush the chunks written to the File Storage: {:?}", e);
+        }
+    }
+}
+
 /// Abstraction trait to build the Storage Layer of a [`ShNodeType`].
 ///
 /// Each [`ShNodeType`] depends on a specific combination of [`ShRole`] and [`ShStorageLayer`],
@@ -200,10 +612,15 @@ pub trait StorageLayerBuilder {
 }
 
 impl StorageLayerBuilder for StorageHubBuilder<BspProvider, InMemoryStorageLayer> {
-    fn setup_storage_layer(&mut self, _storage_path: Option<String>) -> &mut Self {
+    fn setup_storage_layer(&mut self, storage_path: Option<String>) -> &mut Self {
         self.file_storage = Some(Arc::new(RwLock::new(InMemoryFileStorage::new())));
-        self.forest_storage_handler =
-            Some(<(BspProvider, InMemoryStorageLayer) as ShNodeType>::FSH::new());
+        self.forest_storage_handler = Some(
+            <(BspProvider, InMemoryStorageLayer) as ShNodeType>::FSH::new()
+                .with_snapshot_cache_size(self.forest_snapshot_cache_size)
+                .with_snapshots_path(
+                    storage_path.map(|path| PathBuf::from(path).join(FOREST_SNAPSHOTS_DIR)),
+                ),
+        );
 
         self
     }
@@ -215,23 +632,35 @@ impl StorageLayerBuilder for StorageHubBuilder<BspProvider, RocksDbStorageLayer>
 
         let storage_path = storage_path.expect("Storage path not set");
 
-        let file_storage =
-            RocksDbFileStorage::<_, kvdb_rocksdb::Database>::rocksdb_storage(storage_path.clone())
-                .expect("Failed to create RocksDB");
-        self.file_storage = Some(Arc::new(RwLock::new(RocksDbFileStorage::new(file_storage))));
-
-        self.forest_storage_handler =
-            Some(<(BspProvider, RocksDbStorageLayer) as ShNodeType>::FSH::new(storage_path));
+        self.file_storage = Some(rocksdb_file_storage(
+            self.task_spawner.as_ref().expect("Task Spawner not set"),
+            &storage_path,
+            &self.file_storage_data_paths,
+            self.file_storage_compression,
+            self.file_metadata_cache_capacity,
+            self.file_storage_write_coalescing,
+            self.repair_file_storage,
+        ));
+
+        self.forest_storage_handler = Some(
+            <(BspProvider, RocksDbStorageLayer) as ShNodeType>::FSH::new(storage_path)
+                .with_snapshot_cache_size(self.forest_snapshot_cache_size),
+        );
 
         self
     }
 }
 
 impl StorageLayerBuilder for StorageHubBuilder<MspProvider, InMemoryStorageLayer> {
-    fn setup_storage_layer(&mut self, _storage_path: Option<String>) -> &mut Self {
+    fn setup_storage_layer(&mut self, storage_path: Option<String>) -> &mut Self {
         self.file_storage = Some(Arc::new(RwLock::new(InMemoryFileStorage::new())));
-        self.forest_storage_handler =
-            Some(<(MspProvider, InMemoryStorageLayer) as ShNodeType>::FSH::new());
+        self.forest_storage_handler = Some(
+            <(MspProvider, InMemoryStorageLayer) as ShNodeType>::FSH::new()
+                .with_snapshot_cache_size(self.forest_snapshot_cache_size)
+                .with_snapshots_path(
+                    storage_path.map(|path| PathBuf::from(path).join(FOREST_SNAPSHOTS_DIR)),
+                ),
+        );
 
         self
     }
@@ -242,13 +671,20 @@ impl StorageLayerBuilder for StorageHubBuilder<MspProvider, RocksDbStorageLayer>
         let storage_path = storage_path.expect("Storage path not set");
         self.storage_path = Some(storage_path.clone());
 
-        let file_storage =
-            RocksDbFileStorage::<_, kvdb_rocksdb::Database>::rocksdb_storage(storage_path.clone())
-                .expect("Failed to create RocksDB");
-        self.file_storage = Some(Arc::new(RwLock::new(RocksDbFileStorage::new(file_storage))));
-
-        self.forest_storage_handler =
-            Some(<(MspProvider, RocksDbStorageLayer) as ShNodeType>::FSH::new(storage_path));
+        self.file_storage = Some(rocksdb_file_storage(
+            self.task_spawner.as_ref().expect("Task Spawner not set"),
+            &storage_path,
+            &self.file_storage_data_paths,
+            self.file_storage_compression,
+            self.file_metadata_cache_capacity,
+            self.file_storage_write_coalescing,
+            self.repair_file_storage,
+        ));
+
+        self.forest_storage_handler = Some(
+            <(MspProvider, RocksDbStorageLayer) as ShNodeType>::FSH::new(storage_path)
+                .with_snapshot_cache_size(self.forest_snapshot_cache_size),
+        );
 
         self
     }
@@ -274,11 +710,11 @@ pub trait Buildable<NT: ShNodeType> {
 
 impl<S: ShStorageLayer> Buildable<(BspProvider, S)> for StorageHubBuilder<BspProvider, S>
 where
-    (BspProvider, S): ShNodeType,
+    (BspProvider, S): ShNodeType + 'static,
     <(BspProvider, S) as ShNodeType>::FSH: BspForestStorageHandlerT,
 {
     fn build(self) -> StorageHubHandler<(BspProvider, S)> {
-        StorageHubHandler::new(
+        let storage_hub_handler = StorageHubHandler::new(
             self.task_spawner
                 .as_ref()
                 .expect("Task Spawner not set")
@@ -302,9 +738,33 @@ where
             ProviderConfig {
                 capacity_config: self.capacity_config.expect("Capacity Config not set"),
                 extrinsic_retry_timeout: self.extrinsic_retry_timeout,
+                max_active_uploads: self.max_active_uploads,
+                proof_generation_timeout: self.proof_generation_timeout,
+                forest_proof_timeout: self.forest_proof_timeout,
+                max_queue_age_secs: self.max_queue_age_secs,
+                shutdown_grace_period: self.shutdown_grace_period,
+                forest_root_check_interval: self.forest_root_check_interval,
+                pause_proofs_on_forest_root_divergence: self.pause_proofs_on_forest_root_divergence,
+                verify_storage_on_startup: self.verify_storage_on_startup,
             },
             self.indexer_db_pool.clone(),
-        )
+            self.upload_progress.clone(),
+            self.upload_queue.clone(),
+            self.metrics.clone(),
+            self.telemetry.clone(),
+        );
+
+        // Now that the tasks can be run, the Forest can be recovered and the storage verified
+        // through RPC too.
+        self.forest_recovery.set(Arc::new(BspRecoverForestTask::new(
+            storage_hub_handler.clone(),
+        )));
+        self.storage_verification
+            .set(Arc::new(BspVerifyStorageTask::new(
+                storage_hub_handler.clone(),
+            )));
+
+        storage_hub_handler
     }
 }
 
@@ -314,7 +774,7 @@ where
     <(MspProvider, S) as ShNodeType>::FSH: MspForestStorageHandlerT,
 {
     fn build(self) -> StorageHubHandler<(MspProvider, S)> {
-        StorageHubHandler::new(
+        let storage_hub_handler = StorageHubHandler::new(
             self.task_spawner
                 .as_ref()
                 .expect("Task Spawner not set")
@@ -338,9 +798,29 @@ where
             ProviderConfig {
                 capacity_config: self.capacity_config.expect("Capacity Config not set"),
                 extrinsic_retry_timeout: self.extrinsic_retry_timeout,
+                max_active_uploads: self.max_active_uploads,
+                proof_generation_timeout: self.proof_generation_timeout,
+                forest_proof_timeout: self.forest_proof_timeout,
+                max_queue_age_secs: self.max_queue_age_secs,
+                shutdown_grace_period: self.shutdown_grace_period,
+                forest_root_check_interval: self.forest_root_check_interval,
+                pause_proofs_on_forest_root_divergence: self.pause_proofs_on_forest_root_divergence,
+                verify_storage_on_startup: self.verify_storage_on_startup,
             },
             self.indexer_db_pool.clone(),
-        )
+            self.upload_progress.clone(),
+            self.upload_queue.clone(),
+            self.metrics.clone(),
+            self.telemetry.clone(),
+        );
+
+        // Now that the tasks can be run, the Forests of the buckets can be recovered through RPC
+        // too.
+        self.forest_recovery.set(Arc::new(MspRecoverForestTask::new(
+            storage_hub_handler.clone(),
+        )));
+
+        storage_hub_handler
     }
 }
 
@@ -351,7 +831,7 @@ where
         ForestStorageHandler + Clone + Send + Sync + 'static,
 {
     fn build(self) -> StorageHubHandler<(UserRole, NoStorageLayer)> {
-        StorageHubHandler::new(
+        let storage_hub_handler = StorageHubHandler::new(
             self.task_spawner
                 .as_ref()
                 .expect("Task Spawner not set")
@@ -372,10 +852,30 @@ where
             <(UserRole, NoStorageLayer) as ShNodeType>::FSH::new(),
             // Not used by the user role
             ProviderConfig {
-                capacity_config: CapacityConfig::new(0, 0),
+                capacity_config: CapacityConfig::new(0, 0, 0),
                 extrinsic_retry_timeout: self.extrinsic_retry_timeout,
+                max_active_uploads: self.max_active_uploads,
+                proof_generation_timeout: self.proof_generation_timeout,
+                forest_proof_timeout: self.forest_proof_timeout,
+                max_queue_age_secs: self.max_queue_age_secs,
+                shutdown_grace_period: self.shutdown_grace_period,
+                forest_root_check_interval: self.forest_root_check_interval,
+                pause_proofs_on_forest_root_divergence: self.pause_proofs_on_forest_root_divergence,
+                verify_storage_on_startup: self.verify_storage_on_startup,
             },
             self.indexer_db_pool.clone(),
-        )
+            self.upload_progress.clone(),
+            self.upload_queue.clone(),
+            self.metrics.clone(),
+            self.telemetry.clone(),
+        );
+
+        // Now that the tasks can be run, storage requests can be issued through RPC too.
+        self.storage_request_issuer
+            .set(Arc::new(UserSendsFileTask::new(
+                storage_hub_handler.clone(),
+            )));
+
+        storage_hub_handler
     }
 }
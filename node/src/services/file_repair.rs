@@ -0,0 +1,176 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use sp_core::H256;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Maximum number of files that are repaired at the same time.
+///
+/// Repairing a file downloads all of its chunks from other providers, so repairs are kept few to
+/// not starve the uploads and proofs of the node of bandwidth and disk.
+pub const MAX_CONCURRENT_FILE_REPAIRS: usize = 2;
+
+/// The progress of the repair of a file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileRepairProgress {
+    /// Number of chunks downloaded and verified so far.
+    pub chunks_repaired: u64,
+    /// Number of chunks of the file.
+    pub chunks_count: u64,
+}
+
+impl FileRepairProgress {
+    /// Percentage of the chunks of the file that were repaired so far.
+    pub fn percentage(&self) -> f64 {
+        // The number of chunks is unknown until the repair gets a slot.
+        if self.chunks_count == 0 {
+            return 0.0;
+        }
+        self.chunks_repaired as f64 * 100.0 / self.chunks_count as f64
+    }
+}
+
+/// The files being repaired by this provider, i.e. re-downloaded from other providers because
+/// their local copy is missing or corrupt.
+///
+/// It is shared between all the repair tasks, so that at most [`MAX_CONCURRENT_FILE_REPAIRS`]
+/// files are repaired at the same time and each file is repaired by a single task.
+#[derive(Clone)]
+pub struct FileRepairs {
+    permits: Arc<Semaphore>,
+    in_progress: Arc<RwLock<HashMap<H256, FileRepairProgress>>>,
+}
+
+impl Default for FileRepairs {
+    fn default() -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(MAX_CONCURRENT_FILE_REPAIRS)),
+            in_progress: Default::default(),
+        }
+    }
+}
+
+impl FileRepairs {
+    /// Starts the repair of the file with `file_key`, waiting until fewer than
+    /// [`MAX_CONCURRENT_FILE_REPAIRS`] files are being repaired.
+    ///
+    /// Returns `None` if the file is already being repaired. The repair is over when the returned
+    /// guard is dropped.
+    pub async fn start(&self, file_key: H256) -> Option<FileRepairGuard> {
+        {
+            let mut in_progress = self
+                .in_progress
+                .write()
+                .expect("File repairs lock poisoned");
+            if in_progress.contains_key(&file_key) {
+                return None;
+            }
+            in_progress.insert(file_key, FileRepairProgress::default());
+        }
+
+        // The file is tracked while waiting for a slot, so that it is not queued twice. Dropping
+        // the guard untracks it, even if the repair is cancelled before getting a slot.
+        let mut guard = FileRepairGuard {
+            file_key,
+            repairs: self.clone(),
+            _permit: None,
+        };
+        guard._permit = Some(
+            self.permits
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("File repairs semaphore is never closed"),
+        );
+
+        Some(guard)
+    }
+
+    /// The progress of the repair of the file with `file_key`, if it is being repaired.
+    pub fn progress(&self, file_key: &H256) -> Option<FileRepairProgress> {
+        self.in_progress
+            .read()
+            .expect("File repairs lock poisoned")
+            .get(file_key)
+            .copied()
+    }
+}
+
+/// A file being repaired, which stops being tracked by [`FileRepairs`] when dropped.
+pub struct FileRepairGuard {
+    file_key: H256,
+    repairs: FileRepairs,
+    /// The slot of the repair, once it got one.
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl FileRepairGuard {
+    /// Records the progress of the repair.
+    pub fn set_progress(&self, progress: FileRepairProgress) {
+        self.repairs
+            .in_progress
+            .write()
+            .expect("File repairs lock poisoned")
+            .insert(self.file_key, progress);
+    }
+}
+
+impl Drop for FileRepairGuard {
+    fn drop(&mut self) {
+        self.repairs
+            .in_progress
+            .write()
+            .expect("File repairs lock poisoned")
+            .remove(&self.file_key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn a_file_is_repaired_once_at_a_time() {
+        let repairs = FileRepairs::default();
+        let file_key = H256::repeat_byte(1);
+
+        let guard = repairs
+            .start(file_key)
+            .await
+            .expect("File is not being repaired");
+        assert!(repairs.start(file_key).await.is_none());
+
+        guard.set_progress(FileRepairProgress {
+            chunks_repaired: 1,
+            chunks_count: 4,
+        });
+        assert_eq!(repairs.progress(&file_key).unwrap().percentage(), 25.0);
+
+        drop(guard);
+        assert!(repairs.progress(&file_key).is_none());
+        assert!(repairs.start(file_key).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn repairs_wait_for_a_free_slot() {
+        let repairs = FileRepairs::default();
+        let guards: Vec<_> = futures::future::join_all(
+            (0..MAX_CONCURRENT_FILE_REPAIRS as u8).map(|i| repairs.start(H256::repeat_byte(i))),
+        )
+        .await;
+
+        let waiting = tokio::spawn({
+            let repairs = repairs.clone();
+            async move { repairs.start(H256::repeat_byte(u8::MAX)).await.is_some() }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+        // The waiting repair is tracked, so it won't be queued twice.
+        assert!(repairs.progress(&H256::repeat_byte(u8::MAX)).is_some());
+
+        drop(guards);
+        assert!(waiting.await.unwrap());
+    }
+}
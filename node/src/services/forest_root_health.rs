@@ -0,0 +1,166 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use sp_core::H256;
+
+/// Number of consecutive checks in which a local Forest root has to differ from its on-chain
+/// root before the Forest is considered diverged.
+///
+/// A single mismatch is expected every now and then, when a check races with the import of a
+/// block that mutates the Forest.
+pub const MISMATCHES_TO_DIVERGE: u32 = 2;
+
+/// The outcome of comparing a local Forest root with its on-chain root.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ForestRootStatus {
+    /// The local root matches the on-chain root.
+    InSync,
+    /// The local root matches the on-chain root again, after having diverged.
+    Recovered,
+    /// The local root doesn't match the on-chain root, but not for long enough to be considered
+    /// diverged yet.
+    Mismatch,
+    /// The local root hasn't matched the on-chain root in `consecutive_checks` checks.
+    Diverged { consecutive_checks: u32 },
+}
+
+/// Health of the local Forests of this provider, as seen by the periodic self-check comparing
+/// their roots with the ones on-chain.
+///
+/// It is shared between the self-check task, which records the result of each check, and the
+/// proof submission tasks, which can stop submitting proofs while a Forest is diverged.
+#[derive(Clone, Default)]
+pub struct ForestRootHealth {
+    /// Consecutive mismatching checks by Forest key. Forests in sync are not tracked.
+    mismatches: Arc<RwLock<HashMap<Vec<u8>, u32>>>,
+}
+
+impl ForestRootHealth {
+    /// Records a check of the Forest with `forest_key`, whose local root is `local_root` and
+    /// on-chain root is `on_chain_root`.
+    pub fn record_check(
+        &self,
+        forest_key: &[u8],
+        local_root: H256,
+        on_chain_root: H256,
+    ) -> ForestRootStatus {
+        let mut mismatches = self
+            .mismatches
+            .write()
+            .expect("Forest root health lock poisoned");
+
+        if local_root == on_chain_root {
+            return match mismatches.remove(forest_key) {
+                Some(consecutive_checks) if consecutive_checks >= MISMATCHES_TO_DIVERGE => {
+                    ForestRootStatus::Recovered
+                }
+                _ => ForestRootStatus::InSync,
+            };
+        }
+
+        let consecutive_checks = mismatches.entry(forest_key.to_vec()).or_default();
+        *consecutive_checks = consecutive_checks.saturating_add(1);
+
+        if *consecutive_checks >= MISMATCHES_TO_DIVERGE {
+            ForestRootStatus::Diverged {
+                consecutive_checks: *consecutive_checks,
+            }
+        } else {
+            ForestRootStatus::Mismatch
+        }
+    }
+
+    /// Stops tracking the Forests for which `keep` returns `false`, i.e. because they are no
+    /// longer stored by this provider.
+    pub fn retain(&self, keep: impl Fn(&[u8]) -> bool) {
+        self.mismatches
+            .write()
+            .expect("Forest root health lock poisoned")
+            .retain(|forest_key, _| keep(forest_key));
+    }
+
+    /// Whether any local Forest is currently diverged from its on-chain root.
+    pub fn is_diverged(&self) -> bool {
+        self.mismatches
+            .read()
+            .expect("Forest root health lock poisoned")
+            .values()
+            .any(|consecutive_checks| *consecutive_checks >= MISMATCHES_TO_DIVERGE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FOREST_KEY: &[u8] = b"forest";
+
+    #[test]
+    fn deliberate_mismatch_is_reported_as_divergence() {
+        let health = ForestRootHealth::default();
+        let on_chain_root = H256::repeat_byte(1);
+        let local_root = H256::repeat_byte(2);
+
+        assert_eq!(
+            health.record_check(FOREST_KEY, on_chain_root, on_chain_root),
+            ForestRootStatus::InSync
+        );
+        assert!(!health.is_diverged());
+
+        // A first mismatch could be a race with block import, so it is not alerted yet.
+        assert_eq!(
+            health.record_check(FOREST_KEY, local_root, on_chain_root),
+            ForestRootStatus::Mismatch
+        );
+        assert!(!health.is_diverged());
+
+        assert_eq!(
+            health.record_check(FOREST_KEY, local_root, on_chain_root),
+            ForestRootStatus::Diverged {
+                consecutive_checks: 2
+            }
+        );
+        assert!(health.is_diverged());
+
+        assert_eq!(
+            health.record_check(FOREST_KEY, on_chain_root, on_chain_root),
+            ForestRootStatus::Recovered
+        );
+        assert!(!health.is_diverged());
+    }
+
+    #[test]
+    fn single_mismatch_followed_by_a_match_is_not_a_recovery() {
+        let health = ForestRootHealth::default();
+        let on_chain_root = H256::repeat_byte(1);
+
+        assert_eq!(
+            health.record_check(FOREST_KEY, H256::repeat_byte(2), on_chain_root),
+            ForestRootStatus::Mismatch
+        );
+        assert_eq!(
+            health.record_check(FOREST_KEY, on_chain_root, on_chain_root),
+            ForestRootStatus::InSync
+        );
+    }
+
+    #[test]
+    fn forests_are_tracked_independently() {
+        let health = ForestRootHealth::default();
+        let on_chain_root = H256::repeat_byte(1);
+        let local_root = H256::repeat_byte(2);
+
+        health.record_check(b"bucket_a", local_root, on_chain_root);
+        health.record_check(b"bucket_a", local_root, on_chain_root);
+        assert_eq!(
+            health.record_check(b"bucket_b", local_root, on_chain_root),
+            ForestRootStatus::Mismatch
+        );
+        assert!(health.is_diverged());
+
+        health.retain(|forest_key| forest_key != b"bucket_a");
+        assert!(!health.is_diverged());
+    }
+}
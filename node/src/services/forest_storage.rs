@@ -1,17 +1,38 @@
-use std::{collections::HashMap, fmt::Debug, hash::Hash, sync::Arc};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    fs,
+    hash::Hash,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use async_trait::async_trait;
-use log::error;
-use shc_common::types::StorageProofsMerkleTrieLayout;
+use log::{error, info, warn};
+use shc_common::types::{HasherOutT, StorageProofsMerkleTrieLayout};
 use shc_forest_manager::{
     in_memory::InMemoryForestStorage,
     rocksdb::{self, RocksDBForestStorage},
+    snapshot_cache::{ForestStorageSnapshotCache, DEFAULT_FOREST_SNAPSHOT_CACHE_SIZE},
     traits::{ForestStorage, ForestStorageHandler},
 };
 use tokio::sync::RwLock;
 
 const LOG_TARGET: &str = "forest-storage-handler";
 
+/// Directory, within the storage path of the node, where in-memory forest storages are persisted on
+/// shutdown.
+pub const FOREST_SNAPSHOTS_DIR: &str = "forest_snapshots";
+
+/// Writes `snapshot` to `path` through a temporary file, so that an interrupted write never
+/// leaves a truncated snapshot behind.
+fn write_snapshot(path: &Path, snapshot: &[u8]) -> io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, snapshot)?;
+    fs::rename(tmp_path, path)
+}
+
 /// Forest storage handler that manages a single forest storage instance.
 #[derive(Debug)]
 pub struct ForestStorageSingle<FS>
@@ -98,6 +119,17 @@ impl ForestStorageHandler
     ) -> Option<Arc<RwLock<Self::FS>>> {
         None
     }
+
+    async fn snapshot_by_root(
+        &self,
+        _key: &Self::Key,
+        forest_root: &HasherOutT<StorageProofsMerkleTrieLayout>,
+    ) -> Option<Arc<RwLock<Self::FS>>> {
+        // There are no snapshots of the single instance, so it is returned as long as it is still
+        // at `forest_root`.
+        let is_at_root = self.fs_instance.read().await.root() == *forest_root;
+        is_at_root.then(|| self.fs_instance.clone())
+    }
 }
 
 #[async_trait]
@@ -137,11 +169,24 @@ impl ForestStorageHandler
     ) -> Option<Arc<RwLock<Self::FS>>> {
         None
     }
+
+    async fn snapshot_by_root(
+        &self,
+        _key: &Self::Key,
+        forest_root: &HasherOutT<StorageProofsMerkleTrieLayout>,
+    ) -> Option<Arc<RwLock<Self::FS>>> {
+        // There are no snapshots of the single instance, so it is returned as long as it is still
+        // at `forest_root`.
+        let is_at_root = self.fs_instance.read().await.root() == *forest_root;
+        is_at_root.then(|| self.fs_instance.clone())
+    }
 }
 
 /// Forest storage handler that manages multiple forest storage instances.
 ///
 /// The name caching comes from the fact that it maintains a list of existing forest storage instances.
+/// It also keeps a bounded cache of recently used snapshots keyed by their root, used by
+/// [`ForestStorageHandler::snapshot_by_root`].
 #[derive(Debug)]
 pub struct ForestStorageCaching<K, FS>
 where
@@ -149,7 +194,11 @@ where
     FS: ForestStorage<StorageProofsMerkleTrieLayout> + Send + Sync,
 {
     storage_path: Option<String>,
+    /// Directory where in-memory forest storages are persisted on shutdown, if any.
+    snapshots_path: Option<PathBuf>,
     fs_instances: Arc<RwLock<HashMap<K, Arc<RwLock<FS>>>>>,
+    root_snapshots:
+        Arc<RwLock<ForestStorageSnapshotCache<HasherOutT<StorageProofsMerkleTrieLayout>, FS>>>,
 }
 
 impl<K, FS> Clone for ForestStorageCaching<K, FS>
@@ -160,7 +209,9 @@ where
     fn clone(&self) -> Self {
         Self {
             storage_path: self.storage_path.clone(),
+            snapshots_path: self.snapshots_path.clone(),
             fs_instances: self.fs_instances.clone(),
+            root_snapshots: self.root_snapshots.clone(),
         }
     }
 }
@@ -172,7 +223,22 @@ where
     pub fn new() -> Self {
         Self {
             storage_path: None,
+            snapshots_path: None,
             fs_instances: Arc::new(RwLock::new(HashMap::new())),
+            root_snapshots: Arc::new(RwLock::new(ForestStorageSnapshotCache::new(
+                DEFAULT_FOREST_SNAPSHOT_CACHE_SIZE,
+            ))),
+        }
+    }
+
+    /// Set the directory where the forest storages are persisted on shutdown, and restored from
+    /// on startup.
+    ///
+    /// If `None`, the forest storages are lost when the node stops.
+    pub fn with_snapshots_path(self, snapshots_path: Option<PathBuf>) -> Self {
+        Self {
+            snapshots_path,
+            ..self
         }
     }
 }
@@ -188,16 +254,58 @@ where
     pub fn new(storage_path: String) -> Self {
         Self {
             storage_path: Some(storage_path),
+            snapshots_path: None,
             fs_instances: Arc::new(RwLock::new(HashMap::new())),
+            root_snapshots: Arc::new(RwLock::new(ForestStorageSnapshotCache::new(
+                DEFAULT_FOREST_SNAPSHOT_CACHE_SIZE,
+            ))),
+        }
+    }
+}
+
+impl<K, FS> ForestStorageCaching<K, FS>
+where
+    K: Eq + Hash + Send + Sync,
+    FS: ForestStorage<StorageProofsMerkleTrieLayout> + Send + Sync,
+{
+    /// Set the maximum number of forest storage snapshots kept by root.
+    pub fn with_snapshot_cache_size(self, snapshot_cache_size: usize) -> Self {
+        Self {
+            root_snapshots: Arc::new(RwLock::new(ForestStorageSnapshotCache::new(
+                snapshot_cache_size,
+            ))),
+            ..self
         }
     }
 }
 
+impl<K>
+    ForestStorageCaching<
+        K,
+        RocksDBForestStorage<StorageProofsMerkleTrieLayout, kvdb_rocksdb::Database>,
+    >
+where
+    K: Eq + Hash + Send + Sync,
+{
+    fn root_snapshot_path(
+        &self,
+        forest_root: &HasherOutT<StorageProofsMerkleTrieLayout>,
+    ) -> String {
+        format!(
+            "{}_snapshot_{:?}",
+            self.storage_path
+                .clone()
+                .expect("Storage path should be set for RocksDB implementation"),
+            forest_root
+        )
+    }
+}
+
 #[async_trait]
 impl<K> ForestStorageHandler
     for ForestStorageCaching<K, InMemoryForestStorage<StorageProofsMerkleTrieLayout>>
 where
-    K: Eq + Hash + From<Vec<u8>> + Clone + Debug + Send + Sync + 'static,
+    K: Eq + Hash + From<Vec<u8>> + AsRef<[u8]> + Clone + Debug + Send + Sync + 'static,
 {
     type Key = K;
     type FS = InMemoryForestStorage<StorageProofsMerkleTrieLayout>;
@@ -251,6 +359,120 @@ where
 
         Some(forest_storage_dest)
     }
+
+    async fn snapshot_by_root(
+        &self,
+        key: &Self::Key,
+        forest_root: &HasherOutT<StorageProofsMerkleTrieLayout>,
+    ) -> Option<Arc<RwLock<Self::FS>>> {
+        // Hold the lock while creating the snapshot, so that concurrent callers with the same
+        // `forest_root` wait for it instead of creating their own.
+        let mut root_snapshots = self.root_snapshots.write().await;
+
+        if let Some(fs) = root_snapshots.get(forest_root) {
+            return Some(fs);
+        }
+
+        let forest_storage_src = self.fs_instances.read().await.get(key)?.clone();
+        let forest_storage_src = forest_storage_src.read().await;
+        if forest_storage_src.root() != *forest_root {
+            return None;
+        }
+
+        // Create a copy of the Forest Storage
+        let forest_storage_dest = Arc::new(RwLock::new(forest_storage_src.clone()));
+        drop(forest_storage_src);
+
+        // Evicted in-memory snapshots are freed when dropped.
+        root_snapshots.insert(*forest_root, forest_storage_dest.clone());
+
+        Some(forest_storage_dest)
+    }
+
+    async fn persist_all(&self) -> anyhow::Result<()> {
+        let Some(snapshots_path) = &self.snapshots_path else {
+            return Ok(());
+        };
+        fs::create_dir_all(snapshots_path)?;
+
+        // Each forest storage is written to a file named after its hex-encoded key.
+        let fs_instances = self.fs_instances.read().await;
+        let mut persisted = Vec::with_capacity(fs_instances.len());
+        for (key, forest_storage) in fs_instances.iter() {
+            let file_name = hex::encode(key);
+            let snapshot = forest_storage.read().await.encode_snapshot();
+            write_snapshot(&snapshots_path.join(&file_name), &snapshot)?;
+            persisted.push(file_name);
+        }
+
+        // Remove the snapshots of forest storages removed since the last time they were persisted.
+        for entry in fs::read_dir(snapshots_path)? {
+            let entry = entry?;
+            if !persisted
+                .iter()
+                .any(|file_name| entry.file_name() == **file_name)
+            {
+                fs::remove_file(entry.path())?;
+            }
+        }
+
+        info!(
+            target: LOG_TARGET,
+            "Persisted {} forest storages to {:?}",
+            persisted.len(),
+            snapshots_path
+        );
+
+        Ok(())
+    }
+
+    async fn restore_all(&mut self) -> anyhow::Result<()> {
+        let Some(snapshots_path) = &self.snapshots_path else {
+            return Ok(());
+        };
+        if !snapshots_path.exists() {
+            return Ok(());
+        }
+
+        let mut fs_instances = self.fs_instances.write().await;
+        for entry in fs::read_dir(snapshots_path)? {
+            let path = entry?.path();
+            let Some(key) = path
+                .file_name()
+                .and_then(|file_name| file_name.to_str())
+                .and_then(|file_name| hex::decode(file_name).ok())
+            else {
+                warn!(target: LOG_TARGET, "Skipping unexpected file in forest storage snapshots: {:?}", path);
+                continue;
+            };
+
+            // A corrupt snapshot only loses its own forest storage, the others are still restored.
+            let snapshot = match fs::read(&path) {
+                Ok(snapshot) => snapshot,
+                Err(e) => {
+                    error!(target: LOG_TARGET, "Skipping unreadable forest storage snapshot {:?}: {:?}", path, e);
+                    continue;
+                }
+            };
+            let forest_storage = match InMemoryForestStorage::decode_snapshot(&snapshot) {
+                Ok(forest_storage) => forest_storage,
+                Err(e) => {
+                    error!(target: LOG_TARGET, "Skipping corrupt forest storage snapshot {:?}: {:?}", path, e);
+                    continue;
+                }
+            };
+            fs_instances.insert(key.into(), Arc::new(RwLock::new(forest_storage)));
+        }
+
+        info!(
+            target: LOG_TARGET,
+            "Restored {} forest storages from {:?}",
+            fs_instances.len(),
+            snapshots_path
+        );
+
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -347,4 +569,152 @@ where
 
         Some(forest_storage)
     }
+
+    async fn snapshot_by_root(
+        &self,
+        key: &Self::Key,
+        forest_root: &HasherOutT<StorageProofsMerkleTrieLayout>,
+    ) -> Option<Arc<RwLock<Self::FS>>> {
+        // Hold the lock while creating the snapshot, so that concurrent callers with the same
+        // `forest_root` wait for it instead of creating their own.
+        let mut root_snapshots = self.root_snapshots.write().await;
+
+        if let Some(fs) = root_snapshots.get(forest_root) {
+            return Some(fs);
+        }
+
+        let storage_path = self
+            .storage_path
+            .clone()
+            .expect("Storage path should be set");
+        let src = format!("{}_{:?}", storage_path, key);
+        let dest = self.root_snapshot_path(forest_root);
+
+        // Read-lock the source Forest Storage.
+        let src_fs = self.fs_instances.read().await.get(key)?.clone();
+        let src_fs = src_fs.read().await;
+        if src_fs.root() != *forest_root {
+            return None;
+        }
+
+        // Remove any leftover of a snapshot of this root from a previous run before copying.
+        let _ = std::fs::remove_dir_all(&dest);
+
+        // Copy the full source Forest Storage files to the snapshot directory.
+        let underlying_db = match rocksdb::copy_db(src, dest) {
+            Ok(db) => db,
+            Err(e) => {
+                error!(target: LOG_TARGET, "Failed to copy RocksDB: {}", e);
+                return None;
+            }
+        };
+
+        // Release the lock on the source Forest Storage.
+        drop(src_fs);
+
+        let forest_storage =
+            RocksDBForestStorage::new(underlying_db).expect("Failed to create Forest Storage");
+        let forest_storage = Arc::new(RwLock::new(forest_storage));
+
+        for (evicted_root, evicted_fs) in
+            root_snapshots.insert(*forest_root, forest_storage.clone())
+        {
+            // Close the evicted snapshot's database before removing its files.
+            drop(evicted_fs);
+            let evicted_path = self.root_snapshot_path(&evicted_root);
+            if let Err(e) = std::fs::remove_dir_all(&evicted_path) {
+                warn!(target: LOG_TARGET, "Failed to remove evicted Forest Storage snapshot {}: {}", evicted_path, e);
+            }
+        }
+
+        Some(forest_storage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use shc_common::types::{FileMetadata, Fingerprint};
+
+    use super::*;
+
+    type InMemoryForestStorageHandler =
+        ForestStorageCaching<Vec<u8>, InMemoryForestStorage<StorageProofsMerkleTrieLayout>>;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .expect("Failed to build runtime")
+            .block_on(future)
+    }
+
+    /// Inserts a file of `size` bytes in the forest storage of `handler` with `key`, creating it
+    /// if needed, and returns the root of the forest storage.
+    async fn insert_file(
+        handler: &mut InMemoryForestStorageHandler,
+        key: &Vec<u8>,
+        size: u64,
+    ) -> HasherOutT<StorageProofsMerkleTrieLayout> {
+        let file_metadata = FileMetadata::new(
+            b"owner".to_vec(),
+            key.clone(),
+            b"location".to_vec(),
+            size,
+            Fingerprint::default(),
+        )
+        .unwrap();
+
+        let forest_storage = handler.get_or_create(key).await;
+        let mut forest_storage = forest_storage.write().await;
+        forest_storage
+            .insert_files_metadata(&[file_metadata])
+            .unwrap();
+        forest_storage.root()
+    }
+
+    #[test]
+    fn persisted_forest_storages_are_restored_skipping_corrupt_snapshots() {
+        let snapshots_path = std::env::temp_dir().join(format!(
+            "sh-forest-storage-snapshots-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&snapshots_path);
+
+        let first_key = b"first".to_vec();
+        let second_key = b"second".to_vec();
+        let removed_key = b"removed".to_vec();
+
+        let mut handler =
+            InMemoryForestStorageHandler::new().with_snapshots_path(Some(snapshots_path.clone()));
+        let (first_root, second_root) = block_on(async {
+            let first_root = insert_file(&mut handler, &first_key, 1).await;
+            let second_root = insert_file(&mut handler, &second_key, 2).await;
+            insert_file(&mut handler, &removed_key, 3).await;
+            (first_root, second_root)
+        });
+
+        block_on(handler.persist_all()).unwrap();
+        // The snapshot of a forest storage removed since is removed on the next persist.
+        block_on(handler.remove_forest_storage(&removed_key));
+        block_on(handler.persist_all()).unwrap();
+        assert!(!snapshots_path.join(hex::encode(&removed_key)).exists());
+
+        // Corrupt the snapshot of the second forest storage.
+        let second_snapshot_path = snapshots_path.join(hex::encode(&second_key));
+        let snapshot = fs::read(&second_snapshot_path).unwrap();
+        fs::write(&second_snapshot_path, &snapshot[..snapshot.len() - 1]).unwrap();
+        // Files not named after a hex-encoded key are not snapshots.
+        fs::write(snapshots_path.join("not-a-snapshot"), b"").unwrap();
+
+        let mut restored =
+            InMemoryForestStorageHandler::new().with_snapshots_path(Some(snapshots_path.clone()));
+        block_on(restored.restore_all()).unwrap();
+
+        let first = block_on(restored.get(&first_key)).expect("First forest storage restored");
+        assert_eq!(block_on(first.read()).root(), first_root);
+        assert!(block_on(restored.get(&second_key)).is_none());
+        assert_ne!(first_root, second_root);
+        assert!(block_on(restored.get(&removed_key)).is_none());
+
+        fs::remove_dir_all(&snapshots_path).unwrap();
+    }
 }
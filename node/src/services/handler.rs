@@ -1,4 +1,6 @@
-use std::sync::Arc;
+use sc_tracing::tracing::{info, warn, Span};
+use sp_core::H256;
+use std::{sync::Arc, time::Duration};
 use tokio::sync::RwLock;
 
 use shc_actors_framework::{
@@ -7,44 +9,68 @@ use shc_actors_framework::{
 };
 use shc_blockchain_service::{
     capacity_manager::CapacityConfig,
+    commands::BlockchainServiceInterface,
     events::{
-        AcceptedBspVolunteer, FileDeletionRequest, FinalisedBspConfirmStoppedStoring,
-        FinalisedBucketMovedAway, FinalisedMspStopStoringBucketInsolventUser,
-        FinalisedMspStoppedStoringBucket, FinalisedProofSubmittedForPendingFileDeletionRequest,
-        LastChargeableInfoUpdated, MoveBucketAccepted, MoveBucketExpired, MoveBucketRejected,
-        MoveBucketRequested, MoveBucketRequestedForMsp, MultipleNewChallengeSeeds,
-        NewStorageRequest, NotifyPeriod, ProcessConfirmStoringRequest, ProcessFileDeletionRequest,
-        ProcessMspRespondStoringRequest, ProcessStopStoringForInsolventUserRequest,
-        ProcessSubmitProofRequest, SlashableProvider, SpStopStoringInsolventUser,
-        StartMovedBucketDownload, UserWithoutFunds,
+        AcceptedBspVolunteer, BspCapacityChanged, FileDeletionRequest,
+        FinalisedBspConfirmStoppedStoring, FinalisedBucketMovedAway,
+        FinalisedMspStopStoringBucketInsolventUser, FinalisedMspStoppedStoringBucket,
+        FinalisedProofSubmittedForPendingFileDeletionRequest, FinalisedTrieRemoveMutationsApplied,
+        ForestRootMismatch, LastChargeableInfoUpdated, MoveBucketAccepted, MoveBucketExpired,
+        MoveBucketRejected, MoveBucketRequested, MoveBucketRequestedForMsp,
+        MultipleNewChallengeSeeds, NewStorageRequest, NotifyPeriod,
+        PriorityChallengeForFileDeletionQueued, ProcessConfirmStoringRequest,
+        ProcessFileDeletionRequest, ProcessMspRespondStoringRequest,
+        ProcessStopStoringForInsolventUserRequest, ProcessSubmitProofRequest, Reorg,
+        RepairFileRequested, SlashableProvider, SpStopStoringInsolventUser,
+        StartMovedBucketDownload, StorageRequestExpired, StorageRequestFulfilled,
+        StorageRequestRevoked, UserWithoutFunds,
     },
     BlockchainService,
 };
-use shc_common::consts::CURRENT_FOREST_KEY;
+use shc_common::{
+    consts::CURRENT_FOREST_KEY,
+    upload_progress::{UploadProgressRegistry, UploadState},
+    user_uploads::UserUploadQueue,
+};
+use shc_file_manager::traits::FileStorage;
 use shc_file_transfer_service::{
-    events::{RemoteDownloadRequest, RemoteUploadRequest},
+    commands::FileTransferServiceInterface,
+    events::{DuplicateChunksDetected, RemoteDownloadRequest, RemoteUploadRequest},
     FileTransferService,
 };
 use shc_forest_manager::traits::ForestStorageHandler;
 use shc_indexer_db::DbPool;
 
 use crate::{
-    services::types::{
-        BspForestStorageHandlerT, BspProvider, MspForestStorageHandlerT, MspProvider, ShNodeType,
-        ShStorageLayer, UserRole,
+    services::{
+        file_repair::FileRepairs,
+        forest_root_health::ForestRootHealth,
+        metrics::ProviderMetrics,
+        telemetry::BspTelemetry,
+        types::{
+            BspForestStorageHandlerT, BspProvider, MspForestStorageHandlerT, MspProvider,
+            ShNodeType, ShStorageLayer, UserRole,
+        },
+        upload_span::UploadSpans,
     },
     tasks::{
         bsp_charge_fees::BspChargeFeesTask, bsp_delete_file::BspDeleteFileTask,
         bsp_download_file::BspDownloadFileTask, bsp_move_bucket::BspMoveBucketTask,
+        bsp_recover_forest::BspRecoverForestTask, bsp_repair_file::BspRepairFileTask,
         bsp_submit_proof::BspSubmitProofTask, bsp_upload_file::BspUploadFileTask,
-        msp_charge_fees::MspChargeFeesTask, msp_delete_bucket::MspDeleteBucketTask,
-        msp_delete_file::MspDeleteFileTask, msp_move_bucket::MspRespondMoveBucketTask,
+        bsp_verify_storage::BspVerifyStorageTask, msp_charge_fees::MspChargeFeesTask,
+        msp_delete_bucket::MspDeleteBucketTask, msp_delete_file::MspDeleteFileTask,
+        msp_move_bucket::MspRespondMoveBucketTask, msp_recover_forest::MspRecoverForestTask,
+        msp_retrieve_file::MspRetrieveFileTask,
         msp_stop_storing_insolvent_user::MspStopStoringInsolventUserTask,
-        msp_upload_file::MspUploadFileTask, sp_slash_provider::SlashProviderTask,
-        user_sends_file::UserSendsFileTask,
+        msp_upload_file::MspUploadFileTask,
+        sp_forest_root_health_check::SpForestRootHealthCheckTask,
+        sp_slash_provider::SlashProviderTask, user_sends_file::UserSendsFileTask,
     },
 };
 
+const LOG_TARGET: &str = "storage-hub-handler";
+
 /// Configuration parameters for Storage Providers.
 #[derive(Clone)]
 pub struct ProviderConfig {
@@ -54,6 +80,27 @@ pub struct ProviderConfig {
     pub capacity_config: CapacityConfig,
     /// The time in seconds to wait before retrying an extrinsic.
     pub extrinsic_retry_timeout: u64,
+    /// The maximum number of files that can be uploaded to the provider simultaneously.
+    pub max_active_uploads: usize,
+    /// The time in seconds to wait for a proof to be generated before giving up on it.
+    pub proof_generation_timeout: u64,
+    /// The time in seconds to wait for the non-inclusion Forest proof of a batch of files being
+    /// confirmed by a BSP, after which only the files proven so far are confirmed.
+    pub forest_proof_timeout: u64,
+    /// The time in seconds after which a BSP stops retrying to confirm storing a file, however
+    /// many times it was tried. `0` keeps retrying until the maximum number of tries is reached.
+    pub max_queue_age_secs: u64,
+    /// The time in seconds to wait for the tasks in flight to finish when shutting down.
+    pub shutdown_grace_period: u64,
+    /// The time in seconds between two checks of the local Forest roots against the on-chain
+    /// ones. `0` disables the check.
+    pub forest_root_check_interval: u64,
+    /// Whether to stop submitting proofs while a local Forest root is diverged from its on-chain
+    /// root.
+    pub pause_proofs_on_forest_root_divergence: bool,
+    /// Whether to verify the whole File Storage and the Forest against the on-chain root before
+    /// starting the proof tasks.
+    pub verify_storage_on_startup: bool,
 }
 
 /// Represents the handler for the Storage Hub service.
@@ -68,6 +115,11 @@ where
     /// The actor handle for the blockchain service.
     pub blockchain: ActorHandle<BlockchainService<NT::FSH>>,
     /// The file storage layer which stores all files in chunks.
+    ///
+    /// The File Storage locks each file internally while writing its chunks, so uploads only
+    /// take this lock for reading and run concurrently for distinct files. It is taken for writing
+    /// only by operations spanning several files (e.g. deleting all the files of a bucket) or
+    /// that insert and remove whole files.
     pub file_storage: Arc<RwLock<NT::FL>>,
     /// The forest storage layer which tracks all complete files stored in the file storage layer.
     pub forest_storage_handler: NT::FSH,
@@ -75,6 +127,20 @@ where
     pub provider_config: ProviderConfig,
     /// The indexer database pool.
     pub indexer_db_pool: Option<DbPool>,
+    /// The progress of the files being uploaded to this node, also exposed through RPC.
+    pub upload_progress: UploadProgressRegistry,
+    /// The files queued through RPC to be uploaded by this node, when running as a user.
+    pub upload_queue: UserUploadQueue,
+    /// The Prometheus metrics of the tasks, if exported.
+    pub metrics: Option<ProviderMetrics>,
+    /// The reporting of the BSP uploads to the telemetry endpoints, if any.
+    pub telemetry: Option<BspTelemetry>,
+    /// The health of the local Forests, as seen by the periodic check of their roots.
+    pub forest_root_health: ForestRootHealth,
+    /// The files being repaired by this node, which limits how many are repaired at once.
+    pub file_repairs: FileRepairs,
+    /// The tracing spans of the files being uploaded to this node.
+    pub upload_spans: UploadSpans,
 }
 
 impl<NT> Clone for StorageHubHandler<NT>
@@ -90,6 +156,13 @@ where
             forest_storage_handler: self.forest_storage_handler.clone(),
             provider_config: self.provider_config.clone(),
             indexer_db_pool: self.indexer_db_pool.clone(),
+            upload_progress: self.upload_progress.clone(),
+            upload_queue: self.upload_queue.clone(),
+            metrics: self.metrics.clone(),
+            telemetry: self.telemetry.clone(),
+            forest_root_health: self.forest_root_health.clone(),
+            file_repairs: self.file_repairs.clone(),
+            upload_spans: self.upload_spans.clone(),
         }
     }
 }
@@ -106,6 +179,10 @@ where
         forest_storage_handler: NT::FSH,
         provider_config: ProviderConfig,
         indexer_db_pool: Option<DbPool>,
+        upload_progress: UploadProgressRegistry,
+        upload_queue: UserUploadQueue,
+        metrics: Option<ProviderMetrics>,
+        telemetry: Option<BspTelemetry>,
     ) -> Self {
         Self {
             task_spawner,
@@ -115,7 +192,143 @@ where
             forest_storage_handler,
             provider_config,
             indexer_db_pool,
+            upload_spans: UploadSpans::new(upload_progress.clone()),
+            upload_progress,
+            upload_queue,
+            metrics,
+            telemetry,
+            forest_root_health: ForestRootHealth::default(),
+            file_repairs: FileRepairs::default(),
+        }
+    }
+
+    /// Starts tracking the upload of the `chunks_count` chunks of `file_key` to this node, and
+    /// `span` as its span.
+    pub fn start_upload(&self, file_key: H256, chunks_count: u64, span: Span) {
+        self.upload_progress.register(file_key, chunks_count);
+        self.upload_spans.track(file_key, span);
+        self.report_uploads_in_progress();
+    }
+
+    /// Ends the upload of `file_key` in the terminal `state`, finishing its span.
+    ///
+    /// To be called on every path ending an upload, so that neither its progress nor its span
+    /// are left behind.
+    pub fn end_upload(&self, file_key: &H256, state: UploadState) {
+        self.upload_progress.set_state(file_key, state);
+        self.upload_spans.finish(file_key);
+        self.report_uploads_in_progress();
+    }
+
+    /// Records that a peer kept sending chunks of a file that this node already stored, within
+    /// the span of its upload.
+    pub fn record_duplicate_chunks_detected(&self, event: &DuplicateChunksDetected) {
+        let file_key: H256 = event.file_key.into();
+        self.upload_spans.get(&file_key).in_scope(|| {
+            warn!(
+                target: LOG_TARGET,
+                "Peer {:?} sent {} batches with chunks of file {:x} that were already stored within {:?}",
+                event.peer,
+                event.duplicate_batches,
+                file_key,
+                event.window
+            )
+        });
+
+        if let Some(metrics) = &self.metrics {
+            metrics.duplicate_chunks_detected.inc();
+        }
+    }
+
+    /// Exports the number of uploads in progress. To be called whenever the progress of an
+    /// upload changes.
+    pub fn report_uploads_in_progress(&self) {
+        if let Some(metrics) = &self.metrics {
+            metrics
+                .uploads_in_progress
+                .set(self.upload_progress.in_progress_count() as u64);
+        }
+    }
+
+    /// Gracefully shuts down the tasks of this node.
+    ///
+    /// Stops accepting new events and waits, for at most `timeout`, for the events being handled
+    /// to finish, including the extrinsics they are watching and the chunks being written. Since
+    /// the File Storage commits each write as it happens, no file data is left uncommitted once
+    /// the handlers are done. The files still registered in the FileTransferService are then
+    /// unregistered, as their uploads won't be completed by this node, and those left incomplete
+    /// are deleted, and the File Storage is marked as shut down cleanly. Finally, the persisted
+    /// state of the BlockchainService (i.e. the pending requests queues) is flushed.
+    pub async fn shutdown(&self, timeout: Duration) {
+        info!(target: LOG_TARGET, "Shutting down, waiting for in-flight tasks to finish...");
+
+        let graceful_shutdown = self.task_spawner.graceful_shutdown();
+        if graceful_shutdown.shutdown(timeout).await {
+            info!(target: LOG_TARGET, "All in-flight tasks finished");
+        } else {
+            warn!(
+                target: LOG_TARGET,
+                "Timed out after {:?} waiting for {} in-flight tasks to finish",
+                timeout,
+                graceful_shutdown.in_flight()
+            );
+        }
+
+        // Peers are no longer allowed to send chunks for the uploads that were not completed.
+        let registered_file_keys = self.file_transfer.get_registered_file_keys().await;
+        if !registered_file_keys.is_empty() {
+            info!(
+                target: LOG_TARGET,
+                "Unregistering {} files with uploads in progress",
+                registered_file_keys.len()
+            );
         }
+        for file_key in &registered_file_keys {
+            if let Err(e) = self.file_transfer.unregister_file(*file_key).await {
+                warn!(target: LOG_TARGET, "Failed to unregister file {:?}: {:?}", file_key, e);
+            }
+        }
+
+        // Once restarted, this node no longer accepts chunks for these uploads, so their
+        // incomplete files would be left orphaned in the File Storage.
+        let mut write_file_storage = self.file_storage.write().await;
+        for file_key in registered_file_keys {
+            let file_key: H256 = file_key.into();
+            match write_file_storage.is_file_complete(&file_key) {
+                Ok(false) => {
+                    if let Err(e) = write_file_storage.delete_file(&file_key) {
+                        warn!(
+                            target: LOG_TARGET,
+                            "Failed to delete incomplete file {:?}: {:?}", file_key, e
+                        );
+                    }
+                }
+                Ok(true) => {}
+                Err(e) => {
+                    warn!(
+                        target: LOG_TARGET,
+                        "Failed to check if file {:?} is complete: {:?}", file_key, e
+                    );
+                }
+            }
+        }
+
+        // Nothing is written to the File Storage anymore, so it won't need to be repaired when
+        // the node starts again.
+        if let Err(e) = write_file_storage.shut_down() {
+            warn!(target: LOG_TARGET, "Failed to shut down the File Storage: {:?}", e);
+        }
+        drop(write_file_storage);
+
+        if let Err(e) = self.blockchain.flush_persistent_state().await {
+            warn!(target: LOG_TARGET, "Failed to flush the BlockchainService state: {:?}", e);
+        }
+
+        if let Err(e) = self.forest_storage_handler.persist_all().await {
+            warn!(target: LOG_TARGET, "Failed to persist the Forest Storage: {:?}", e);
+        }
+
+        info!(target: LOG_TARGET, "Shutdown complete");
     }
 }
 
@@ -178,6 +391,31 @@ where
                 .clone()
                 .subscribe_to(&self.task_spawner, &self.blockchain, true);
         accepted_bsp_volunteer_event_bus_listener.start();
+
+        // Subscribing to StorageRequestFulfilled event from the BlockchainService.
+        let storage_request_fulfilled_event_bus_listener: EventBusListener<
+            StorageRequestFulfilled,
+            _,
+        > = user_sends_file_task
+            .clone()
+            .subscribe_to(&self.task_spawner, &self.blockchain, true);
+        storage_request_fulfilled_event_bus_listener.start();
+
+        // Issue the storage requests of the files queued through the `uploadFile` RPC. Their
+        // chunks are then sent when handling the events above.
+        match self.upload_queue.take_receiver() {
+            Some(upload_requests) => {
+                self.task_spawner
+                    .with_name("user-upload-queue")
+                    .spawn(user_sends_file_task.process_upload_queue(upload_requests));
+            }
+            None => {
+                log::error!(
+                    target: LOG_TARGET,
+                    "The upload queue is already being processed, files uploaded through RPC won't be handled by these tasks"
+                );
+            }
+        }
     }
 }
 
@@ -208,6 +446,16 @@ where
                 false,
             );
         remote_upload_request_event_bus_listener.start();
+        // Subscribing to DuplicateChunksDetected event from the FileTransferService.
+        let duplicate_chunks_detected_event_bus_listener: EventBusListener<
+            DuplicateChunksDetected,
+            _,
+        > = msp_upload_file_task.clone().subscribe_to(
+            &self.task_spawner,
+            &self.file_transfer,
+            false,
+        );
+        duplicate_chunks_detected_event_bus_listener.start();
         // Subscribing to ProcessMspRespondStoringRequest event from the BlockchainService.
         let process_confirm_storing_request_event_bus_listener: EventBusListener<
             ProcessMspRespondStoringRequest,
@@ -216,6 +464,31 @@ where
             .clone()
             .subscribe_to(&self.task_spawner, &self.blockchain, true);
         process_confirm_storing_request_event_bus_listener.start();
+        // Subscribing to StorageRequestRevoked event from the BlockchainService.
+        let storage_request_revoked_event_bus_listener: EventBusListener<StorageRequestRevoked, _> =
+            msp_upload_file_task
+                .clone()
+                .subscribe_to(&self.task_spawner, &self.blockchain, true);
+        storage_request_revoked_event_bus_listener.start();
+        // Subscribing to StorageRequestExpired event from the BlockchainService.
+        let storage_request_expired_event_bus_listener: EventBusListener<StorageRequestExpired, _> =
+            msp_upload_file_task
+                .clone()
+                .subscribe_to(&self.task_spawner, &self.blockchain, true);
+        storage_request_expired_event_bus_listener.start();
+        // Subscribing to UserWithoutFunds event from the BlockchainService.
+        let upload_user_without_funds_event_bus_listener: EventBusListener<UserWithoutFunds, _> =
+            msp_upload_file_task
+                .clone()
+                .subscribe_to(&self.task_spawner, &self.blockchain, true);
+        upload_user_without_funds_event_bus_listener.start();
+
+        // MspRetrieveFileTask serves the files stored by this MSP to the users allowed to read them.
+        let msp_retrieve_file_task = MspRetrieveFileTask::new(self.clone());
+        // Subscribing to RemoteDownloadRequest event from the FileTransferService.
+        let remote_download_request_event_bus_listener: EventBusListener<RemoteDownloadRequest, _> =
+            msp_retrieve_file_task.subscribe_to(&self.task_spawner, &self.file_transfer, false);
+        remote_download_request_event_bus_listener.start();
 
         // Task that handles bucket deletion (both move and stop storing)
         let msp_delete_bucket_task = MspDeleteBucketTask::new(self.clone());
@@ -253,6 +526,12 @@ where
             .clone()
             .subscribe_to(&self.task_spawner, &self.blockchain, true);
         process_file_deletion_request_event_bus_listener.start();
+
+        // Task that listens for `ForestRootMismatch` to recover the Forests of the MSP's buckets.
+        let msp_recover_forest_task = MspRecoverForestTask::new(self.clone());
+        let forest_root_mismatch_event_bus_listener: EventBusListener<ForestRootMismatch, _> =
+            msp_recover_forest_task.subscribe_to(&self.task_spawner, &self.blockchain, true);
+        forest_root_mismatch_event_bus_listener.start();
         // Subscribing to FinalisedProofSubmittedForPendingFileDeletionRequest event from the BlockchainService.
         let finalised_file_deletion_request_event_bus_listener: EventBusListener<
             FinalisedProofSubmittedForPendingFileDeletionRequest,
@@ -318,6 +597,14 @@ where
                 .clone()
                 .subscribe_to(&self.task_spawner, &self.blockchain, true);
         notify_period_event_bus_listener.start();
+
+        // SpForestRootHealthCheckTask periodically checks the local bucket roots against the
+        // on-chain ones, alerting if they diverge.
+        let sp_forest_root_health_check_task = SpForestRootHealthCheckTask::new(self.clone());
+        if let Some(interval) = sp_forest_root_health_check_task.check_interval() {
+            self.task_spawner
+                .spawn(sp_forest_root_health_check_task.run_msp(interval));
+        }
     }
 }
 
@@ -361,6 +648,16 @@ where
                 false,
             );
         remote_upload_request_event_bus_listener.start();
+        // Subscribing to DuplicateChunksDetected event from the FileTransferService.
+        let duplicate_chunks_detected_event_bus_listener: EventBusListener<
+            DuplicateChunksDetected,
+            _,
+        > = bsp_upload_file_task.clone().subscribe_to(
+            &self.task_spawner,
+            &self.file_transfer,
+            false,
+        );
+        duplicate_chunks_detected_event_bus_listener.start();
         // Subscribing to ProcessConfirmStoringRequest event from the BlockchainService.
         let process_confirm_storing_request_event_bus_listener: EventBusListener<
             ProcessConfirmStoringRequest,
@@ -369,6 +666,42 @@ where
             .clone()
             .subscribe_to(&self.task_spawner, &self.blockchain, true);
         process_confirm_storing_request_event_bus_listener.start();
+        // Subscribing to StorageRequestRevoked event from the BlockchainService.
+        let storage_request_revoked_event_bus_listener: EventBusListener<StorageRequestRevoked, _> =
+            bsp_upload_file_task
+                .clone()
+                .subscribe_to(&self.task_spawner, &self.blockchain, true);
+        storage_request_revoked_event_bus_listener.start();
+        // Subscribing to StorageRequestExpired event from the BlockchainService.
+        let storage_request_expired_event_bus_listener: EventBusListener<StorageRequestExpired, _> =
+            bsp_upload_file_task
+                .clone()
+                .subscribe_to(&self.task_spawner, &self.blockchain, true);
+        storage_request_expired_event_bus_listener.start();
+        // Subscribing to UserWithoutFunds event from the BlockchainService.
+        let upload_user_without_funds_event_bus_listener: EventBusListener<UserWithoutFunds, _> =
+            bsp_upload_file_task
+                .clone()
+                .subscribe_to(&self.task_spawner, &self.blockchain, true);
+        upload_user_without_funds_event_bus_listener.start();
+        // Subscribing to Reorg event from the BlockchainService.
+        let reorg_event_bus_listener: EventBusListener<Reorg, _> = bsp_upload_file_task
+            .clone()
+            .subscribe_to(&self.task_spawner, &self.blockchain, true);
+        reorg_event_bus_listener.start();
+        // Subscribing to BspCapacityChanged event from the BlockchainService.
+        let bsp_capacity_changed_event_bus_listener: EventBusListener<BspCapacityChanged, _> =
+            bsp_upload_file_task
+                .clone()
+                .subscribe_to(&self.task_spawner, &self.blockchain, true);
+        bsp_capacity_changed_event_bus_listener.start();
+
+        // The BspRepairFileTask
+        let bsp_repair_file_task = BspRepairFileTask::new(self.clone());
+        // Subscribing to RepairFileRequested event from the BlockchainService.
+        let repair_file_requested_event_bus_listener: EventBusListener<RepairFileRequested, _> =
+            bsp_repair_file_task.subscribe_to(&self.task_spawner, &self.blockchain, true);
+        repair_file_requested_event_bus_listener.start();
 
         // The BspDownloadFileTask
         let bsp_download_file_task = BspDownloadFileTask::new(self.clone());
@@ -377,29 +710,19 @@ where
             bsp_download_file_task.subscribe_to(&self.task_spawner, &self.file_transfer, false);
         remote_download_request_event_bus_listener.start();
 
-        // BspSubmitProofTask is triggered by a MultipleNewChallengeSeeds event emitted by the BlockchainService.
-        // It responds by computing challenges derived from the seeds, taking also into account
-        // the custom challenges in checkpoint challenge rounds and enqueuing them in BlockchainService.
-        // BspSubmitProofTask also listens to ProcessSubmitProofRequest events, which are emitted by the
-        // BlockchainService when it is time to actually submit the proof of storage.
-        // Additionally, it handles file deletions as a consequence of inclusion proofs in custom challenges.
-        let bsp_submit_proof_task = BspSubmitProofTask::new(self.clone());
-        // Subscribing to MultipleNewChallengeSeeds event from the BlockchainService.
-        let multiple_new_challenge_seeds_event_bus_listener: EventBusListener<
-            MultipleNewChallengeSeeds,
-            _,
-        > = bsp_submit_proof_task
-            .clone()
-            .subscribe_to(&self.task_spawner, &self.blockchain, true);
-        multiple_new_challenge_seeds_event_bus_listener.start();
-        // Subscribing to ProcessSubmitProofRequest event from the BlockchainService.
-        let process_submit_proof_request_event_bus_listener: EventBusListener<
-            ProcessSubmitProofRequest,
-            _,
-        > = bsp_submit_proof_task
-            .clone()
-            .subscribe_to(&self.task_spawner, &self.blockchain, true);
-        process_submit_proof_request_event_bus_listener.start();
+        // The proof tasks are only started once the storage is verified, if requested, so that no
+        // proof is submitted from a Forest that does not match the on-chain root.
+        if self.provider_config.verify_storage_on_startup {
+            let bsp_verify_storage_task = BspVerifyStorageTask::new(self.clone());
+            let storage_hub_handler = self.clone();
+            self.task_spawner.spawn(async move {
+                if bsp_verify_storage_task.verify_on_startup().await {
+                    storage_hub_handler.start_bsp_proof_tasks();
+                }
+            });
+        } else {
+            self.start_bsp_proof_tasks();
+        }
 
         // Slash your own kin or potentially commit seppuku on your own stake.
         // Running this is as a BSP is very honourable and shows a great sense of justice.
@@ -489,5 +812,68 @@ where
             .clone()
             .subscribe_to(&self.task_spawner, &self.blockchain, true);
         finalised_bsp_confirm_stopped_storing_event_bus_listener.start();
+        // Subscribing to PriorityChallengeForFileDeletionQueued event from the BlockchainService.
+        let priority_challenge_for_file_deletion_queued_event_bus_listener: EventBusListener<
+            PriorityChallengeForFileDeletionQueued,
+            _,
+        > = bsp_delete_file_task
+            .clone()
+            .subscribe_to(&self.task_spawner, &self.blockchain, true);
+        priority_challenge_for_file_deletion_queued_event_bus_listener.start();
+
+        // Task that listens for `ForestRootMismatch` to recover the BSP's Forest.
+        let bsp_recover_forest_task = BspRecoverForestTask::new(self.clone());
+        let forest_root_mismatch_event_bus_listener: EventBusListener<ForestRootMismatch, _> =
+            bsp_recover_forest_task.subscribe_to(&self.task_spawner, &self.blockchain, true);
+        forest_root_mismatch_event_bus_listener.start();
+
+        // SpForestRootHealthCheckTask periodically checks the local Forest root against the
+        // on-chain one, alerting if they diverge.
+        let sp_forest_root_health_check_task = SpForestRootHealthCheckTask::new(self.clone());
+        if let Some(interval) = sp_forest_root_health_check_task.check_interval() {
+            self.task_spawner
+                .spawn(sp_forest_root_health_check_task.run_bsp(interval));
+        }
+    }
+
+    /// Starts the tasks proving the files stored by the BSP, and removing them from its Forest
+    /// when challenged to.
+    fn start_bsp_proof_tasks(&self) {
+        log::info!(target: LOG_TARGET, "Starting BSP proof tasks");
+
+        // BspSubmitProofTask is triggered by a MultipleNewChallengeSeeds event emitted by the BlockchainService.
+        // It responds by computing challenges derived from the seeds, taking also into account
+        // the custom challenges in checkpoint challenge rounds and enqueuing them in BlockchainService.
+        // BspSubmitProofTask also listens to ProcessSubmitProofRequest events, which are emitted by the
+        // BlockchainService when it is time to actually submit the proof of storage.
+        // Additionally, it handles file deletions as a consequence of inclusion proofs in custom challenges.
+        let bsp_submit_proof_task = BspSubmitProofTask::new(self.clone());
+        // Subscribing to MultipleNewChallengeSeeds event from the BlockchainService.
+        let multiple_new_challenge_seeds_event_bus_listener: EventBusListener<
+            MultipleNewChallengeSeeds,
+            _,
+        > = bsp_submit_proof_task
+            .clone()
+            .subscribe_to(&self.task_spawner, &self.blockchain, true);
+        multiple_new_challenge_seeds_event_bus_listener.start();
+        // Subscribing to ProcessSubmitProofRequest event from the BlockchainService.
+        let process_submit_proof_request_event_bus_listener: EventBusListener<
+            ProcessSubmitProofRequest,
+            _,
+        > = bsp_submit_proof_task
+            .clone()
+            .subscribe_to(&self.task_spawner, &self.blockchain, true);
+        process_submit_proof_request_event_bus_listener.start();
+        // Subscribing to FinalisedTrieRemoveMutationsApplied event from the BlockchainService.
+        let finalised_trie_remove_mutations_applied_event_bus_listener: EventBusListener<
+            FinalisedTrieRemoveMutationsApplied,
+            _,
+        > = bsp_submit_proof_task
+            .clone()
+            .subscribe_to(&self.task_spawner, &self.blockchain, true);
+        finalised_trie_remove_mutations_applied_event_bus_listener.start();
+        // Complete the removals of checkpoint challenged file keys interrupted by a restart.
+        self.task_spawner
+            .spawn(bsp_submit_proof_task.recover_pending_checkpoint_removals());
     }
 }
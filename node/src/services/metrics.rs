@@ -0,0 +1,407 @@
+use std::{collections::HashSet, time::Instant};
+
+use shc_common::types::{Chunk, ChunkId, FileKeyProof, HasherOutT, StorageProofsMerkleTrieLayout};
+use shc_file_manager::traits::{FileStorage, FileStorageError, FileStorageWriteOutcome};
+use shc_file_transfer_service::upload::PeerSendStats;
+use substrate_prometheus_endpoint::{
+    exponential_buckets, register, Counter, CounterVec, Gauge, Histogram, HistogramOpts, Opts,
+    PrometheusError, Registry, U64,
+};
+
+/// Why an uploaded chunk was not written to the File Storage, as exported in the `reason` label
+/// of `storagehub_chunks_rejected_total`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkRejection {
+    /// The proof of the batch of chunks failed to verify, or a chunk has an invalid size.
+    InvalidProof,
+    /// The chunk is beyond the last chunk of the file.
+    OutOfRange,
+    /// The chunk was already stored.
+    AlreadyStored,
+    /// The file is complete and sealed, so no chunk can be written anymore.
+    FileSealed,
+    /// Writing the chunk to the File Storage failed.
+    WriteFailed,
+}
+
+impl ChunkRejection {
+    /// Classifies the error of a failed chunk write.
+    pub fn from_write_error(error: &FileStorageError) -> Self {
+        match error {
+            FileStorageError::FileChunkAlreadyExists => ChunkRejection::AlreadyStored,
+            FileStorageError::FileSealed => ChunkRejection::FileSealed,
+            FileStorageError::ChunkIdOutOfRange => ChunkRejection::OutOfRange,
+            _ => ChunkRejection::WriteFailed,
+        }
+    }
+
+    /// The value of the `reason` label.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChunkRejection::InvalidProof => "invalid_proof",
+            ChunkRejection::OutOfRange => "out_of_range",
+            ChunkRejection::AlreadyStored => "already_stored",
+            ChunkRejection::FileSealed => "file_sealed",
+            ChunkRejection::WriteFailed => "write_failed",
+        }
+    }
+}
+
+/// Prometheus metrics of the tasks run by a Storage Provider.
+#[derive(Clone)]
+pub struct ProviderMetrics {
+    /// Number of files a BSP tried to confirm storing, but were skipped by the runtime.
+    pub bsp_confirm_storing_skipped: Counter<U64>,
+    /// Number of uploaded chunks written to the File Storage.
+    pub chunks_written: Counter<U64>,
+    /// Number of uploaded chunks that were not written to the File Storage, by
+    /// [`ChunkRejection`].
+    pub chunks_rejected: CounterVec<U64>,
+    /// Time taken to write a chunk to the File Storage, in seconds.
+    pub write_chunk_duration: Histogram,
+    /// Time taken to write a batch of uploaded chunks to the File Storage, including the wait for
+    /// its lock, in seconds.
+    pub upload_batch_write_duration: Histogram,
+    /// Time spent waiting for the File Storage lock before writing a batch of uploaded chunks, in
+    /// seconds. Tells lock contention apart from the time spent writing.
+    pub file_storage_lock_wait_duration: Histogram,
+    /// Time taken to generate a proof of the chunks of a file, in seconds.
+    pub generate_proof_duration: Histogram,
+    /// Number of registered uploads that haven't received all their chunks yet.
+    pub uploads_in_progress: Gauge<U64>,
+    /// Number of files whose chunks were all received.
+    pub files_completed: Counter<U64>,
+    /// Number of files whose storage was confirmed on-chain.
+    pub files_confirmed: Counter<U64>,
+    /// Number of times a peer sent more batches with chunks of a file that were already stored
+    /// than allowed within a window. The duplicate chunks themselves are counted in
+    /// `chunks_rejected`, as [`ChunkRejection::AlreadyStored`] or [`ChunkRejection::FileSealed`].
+    pub duplicate_chunks_detected: Counter<U64>,
+    /// Number of batches of chunks sent by the user to the providers, by outcome. Aggregates the
+    /// [`PeerSendStats`] of every upload, which are not kept once the upload is over.
+    pub upload_batches: CounterVec<U64>,
+    /// Number of chunks sent by the user that the providers acknowledged.
+    pub upload_chunks_acknowledged: Counter<U64>,
+}
+
+impl ProviderMetrics {
+    /// Creates the provider metrics and registers them in `registry`.
+    pub fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+        Ok(Self {
+            bsp_confirm_storing_skipped: register(
+                Counter::new(
+                    "storagehub_bsp_confirm_storing_skipped_total",
+                    "Number of files a BSP tried to confirm storing, but were skipped by the runtime",
+                )?,
+                registry,
+            )?,
+            chunks_written: register(
+                Counter::new(
+                    "storagehub_chunks_written_total",
+                    "Number of uploaded chunks written to the file storage",
+                )?,
+                registry,
+            )?,
+            chunks_rejected: register(
+                CounterVec::new(
+                    Opts::new(
+                        "storagehub_chunks_rejected_total",
+                        "Number of uploaded chunks that were not written to the file storage",
+                    ),
+                    &["reason"],
+                )?,
+                registry,
+            )?,
+            // From 100µs to ~3.3s.
+            write_chunk_duration: register(
+                Histogram::with_opts(
+                    HistogramOpts::new(
+                        "storagehub_write_chunk_duration_seconds",
+                        "Time taken to write a chunk to the file storage",
+                    )
+                    .buckets(exponential_buckets(0.0001, 2.0, 16)?),
+                )?,
+                registry,
+            )?,
+            // From 100µs to ~3.3s.
+            upload_batch_write_duration: register(
+                Histogram::with_opts(
+                    HistogramOpts::new(
+                        "storagehub_upload_batch_write_duration_seconds",
+                        "Time taken to write a batch of uploaded chunks to the file storage, including the wait for its lock",
+                    )
+                    .buckets(exponential_buckets(0.0001, 2.0, 16)?),
+                )?,
+                registry,
+            )?,
+            // From 10µs to ~0.3s.
+            file_storage_lock_wait_duration: register(
+                Histogram::with_opts(
+                    HistogramOpts::new(
+                        "storagehub_file_storage_lock_wait_duration_seconds",
+                        "Time spent waiting for the file storage lock before writing a batch of uploaded chunks",
+                    )
+                    .buckets(exponential_buckets(0.00001, 2.0, 16)?),
+                )?,
+                registry,
+            )?,
+            // From 1ms to ~33s.
+            generate_proof_duration: register(
+                Histogram::with_opts(
+                    HistogramOpts::new(
+                        "storagehub_generate_proof_duration_seconds",
+                        "Time taken to generate a proof of the chunks of a file",
+                    )
+                    .buckets(exponential_buckets(0.001, 2.0, 16)?),
+                )?,
+                registry,
+            )?,
+            uploads_in_progress: register(
+                Gauge::new(
+                    "storagehub_uploads_in_progress",
+                    "Number of registered uploads that haven't received all their chunks yet",
+                )?,
+                registry,
+            )?,
+            files_completed: register(
+                Counter::new(
+                    "storagehub_files_completed_total",
+                    "Number of files whose chunks were all received",
+                )?,
+                registry,
+            )?,
+            files_confirmed: register(
+                Counter::new(
+                    "storagehub_files_confirmed_total",
+                    "Number of files whose storage was confirmed on-chain",
+                )?,
+                registry,
+            )?,
+            duplicate_chunks_detected: register(
+                Counter::new(
+                    "storagehub_duplicate_chunks_detected_total",
+                    "Number of times a peer sent too many chunks of a file that were already stored within a window",
+                )?,
+                registry,
+            )?,
+            upload_batches: register(
+                CounterVec::new(
+                    Opts::new(
+                        "storagehub_upload_batches_total",
+                        "Number of batches of chunks sent by the user to the providers, by outcome",
+                    ),
+                    &["outcome"],
+                )?,
+                registry,
+            )?,
+            upload_chunks_acknowledged: register(
+                Counter::new(
+                    "storagehub_upload_chunks_acknowledged_total",
+                    "Number of chunks sent by the user that the providers acknowledged",
+                )?,
+                registry,
+            )?,
+        })
+    }
+
+    /// Records `count` uploaded chunks that were not written to the File Storage.
+    pub fn reject_chunks(&self, reason: ChunkRejection, count: usize) {
+        self.chunks_rejected
+            .with_label_values(&[reason.as_str()])
+            .inc_by(count as u64);
+    }
+
+    /// Records the batches of chunks sent to a peer during an upload of the user.
+    pub fn record_chunk_upload(&self, stats: &PeerSendStats) {
+        for (outcome, count) in [
+            ("sent", stats.batches_sent),
+            ("acknowledged", stats.batches_acknowledged),
+            ("retried", stats.retries),
+            ("timed_out", stats.timeouts),
+            ("failed_over", stats.failovers),
+        ] {
+            self.upload_batches
+                .with_label_values(&[outcome])
+                .inc_by(count);
+        }
+        self.upload_chunks_acknowledged
+            .inc_by(stats.chunks_acknowledged);
+    }
+}
+
+/// Writes an uploaded chunk to `file_storage`, recording how long it took, whether it was
+/// written and whether it completed the file in `metrics`.
+pub fn write_chunk_metered<FS: FileStorage<StorageProofsMerkleTrieLayout>>(
+    metrics: Option<&ProviderMetrics>,
+    file_storage: &FS,
+    file_key: &HasherOutT<StorageProofsMerkleTrieLayout>,
+    chunk_id: &ChunkId,
+    data: &Chunk,
+) -> Result<FileStorageWriteOutcome, FileStorageError> {
+    let start = Instant::now();
+    let result = file_storage.write_chunk(file_key, chunk_id, data);
+
+    if let Some(metrics) = metrics {
+        metrics
+            .write_chunk_duration
+            .observe(start.elapsed().as_secs_f64());
+        match &result {
+            Ok(outcome) => {
+                metrics.chunks_written.inc();
+                if matches!(outcome, FileStorageWriteOutcome::FileComplete) {
+                    metrics.files_completed.inc();
+                }
+            }
+            Err(error) => metrics.reject_chunks(ChunkRejection::from_write_error(error), 1),
+        }
+    }
+
+    result
+}
+
+/// Generates a proof of the chunks with `chunk_ids` of a file in `file_storage`, recording how
+/// long it took in `metrics`.
+pub fn generate_proof_metered<FS: FileStorage<StorageProofsMerkleTrieLayout>>(
+    metrics: Option<&ProviderMetrics>,
+    file_storage: &FS,
+    file_key: &HasherOutT<StorageProofsMerkleTrieLayout>,
+    chunk_ids: &HashSet<ChunkId>,
+) -> Result<FileKeyProof, FileStorageError> {
+    let start = Instant::now();
+    let result = file_storage.generate_proof(file_key, chunk_ids);
+
+    if let Some(metrics) = metrics {
+        metrics
+            .generate_proof_duration
+            .observe(start.elapsed().as_secs_f64());
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use shc_common::types::{FileMetadata, HashT, FILE_CHUNK_SIZE};
+    use shc_file_manager::{in_memory::InMemoryFileStorage, traits::FileDataTrie};
+    use sp_runtime::AccountId32;
+
+    use super::*;
+
+    #[test]
+    fn simulated_upload_moves_the_counters() {
+        let registry = Registry::new();
+        let metrics = ProviderMetrics::register(&registry).expect("Metrics are registered once");
+
+        // A file of two chunks, which the user uploads with one of them sent twice.
+        let chunks = [
+            Chunk::from([1u8; FILE_CHUNK_SIZE as usize]),
+            Chunk::from([2u8; FILE_CHUNK_SIZE as usize]),
+        ];
+        let mut file_storage = InMemoryFileStorage::<StorageProofsMerkleTrieLayout>::new();
+        let mut file_trie = file_storage.new_file_data_trie();
+        for (chunk_id, chunk) in chunks.iter().enumerate() {
+            file_trie
+                .write_chunk(&ChunkId::new(chunk_id as u64), chunk)
+                .unwrap();
+        }
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            b"location".to_vec(),
+            2 * FILE_CHUNK_SIZE,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+        let file_key = file_metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+        file_storage.insert_file(file_key, file_metadata).unwrap();
+
+        let write = |chunk_id: u64| {
+            write_chunk_metered(
+                Some(&metrics),
+                &file_storage,
+                &file_key,
+                &ChunkId::new(chunk_id),
+                &chunks[chunk_id as usize],
+            )
+        };
+        assert!(matches!(
+            write(0),
+            Ok(FileStorageWriteOutcome::FileIncomplete {
+                stored: 1,
+                total: 2
+            })
+        ));
+        assert!(write(0).is_err());
+        assert!(matches!(
+            write(1),
+            Ok(FileStorageWriteOutcome::FileComplete)
+        ));
+
+        // A batch of chunks that failed to verify.
+        metrics.reject_chunks(ChunkRejection::InvalidProof, 3);
+
+        generate_proof_metered(
+            Some(&metrics),
+            &file_storage,
+            &file_key,
+            &HashSet::from([ChunkId::new(0)]),
+        )
+        .unwrap();
+
+        assert_eq!(metrics.chunks_written.get(), 2);
+        assert_eq!(
+            metrics
+                .chunks_rejected
+                .with_label_values(&[ChunkRejection::AlreadyStored.as_str()])
+                .get(),
+            1
+        );
+        assert_eq!(
+            metrics
+                .chunks_rejected
+                .with_label_values(&[ChunkRejection::InvalidProof.as_str()])
+                .get(),
+            3
+        );
+        assert_eq!(metrics.write_chunk_duration.get_sample_count(), 3);
+        assert_eq!(metrics.generate_proof_duration.get_sample_count(), 1);
+        assert_eq!(metrics.files_completed.get(), 1);
+
+        // All the metrics are exported through the registry.
+        let exported: HashSet<_> = registry
+            .gather()
+            .iter()
+            .map(|family| family.get_name().to_string())
+            .collect();
+        assert!(exported.contains("storagehub_chunks_written_total"));
+        assert!(exported.contains("storagehub_write_chunk_duration_seconds"));
+        assert!(exported.contains("storagehub_upload_batch_write_duration_seconds"));
+        assert!(exported.contains("storagehub_file_storage_lock_wait_duration_seconds"));
+        assert!(exported.contains("storagehub_generate_proof_duration_seconds"));
+    }
+
+    #[test]
+    fn chunk_uploads_of_every_peer_add_up() {
+        let registry = Registry::new();
+        let metrics = ProviderMetrics::register(&registry).expect("Metrics are registered once");
+
+        let stats = PeerSendStats {
+            batches_sent: 4,
+            batches_acknowledged: 3,
+            chunks_acknowledged: 30,
+            retries: 1,
+            timeouts: 1,
+            failovers: 0,
+        };
+        metrics.record_chunk_upload(&stats);
+        metrics.record_chunk_upload(&stats);
+
+        let batches = |outcome: &str| metrics.upload_batches.with_label_values(&[outcome]).get();
+        assert_eq!(batches("sent"), 8);
+        assert_eq!(batches("acknowledged"), 6);
+        assert_eq!(batches("retried"), 2);
+        assert_eq!(batches("timed_out"), 2);
+        assert_eq!(batches("failed_over"), 0);
+        assert_eq!(metrics.upload_chunks_acknowledged.get(), 60);
+    }
+}
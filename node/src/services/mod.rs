@@ -1,4 +1,10 @@
 pub mod builder;
+pub mod file_repair;
+pub mod forest_root_health;
 pub mod forest_storage;
 pub mod handler;
+pub mod metrics;
+pub mod provider_status;
+pub mod telemetry;
 pub mod types;
+pub mod upload_span;
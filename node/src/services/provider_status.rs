@@ -0,0 +1,159 @@
+use anyhow::anyhow;
+use async_trait::async_trait;
+use sp_core::H256;
+
+use pallet_proofs_dealer_runtime_api::GetProofSubmissionRecordError;
+use shc_actors_framework::actor::ActorHandle;
+use shc_blockchain_service::{commands::BlockchainServiceInterface, BlockchainService};
+use shc_common::types::{BlockNumber, StorageProviderId};
+use shc_file_transfer_service::{commands::FileTransferServiceInterface, FileTransferService};
+use shc_forest_manager::traits::ForestStorageHandler;
+use shc_rpc::provider_status::{
+    PendingRequests, ProviderCapacity, ProviderIdentity, ProviderKind, ProviderStatusSource,
+};
+
+/// The [`ProviderStatusSource`] of a running node, backed by its Blockchain Service and File
+/// Transfer Service.
+pub struct ServicesProviderStatusSource<FSH>
+where
+    FSH: ForestStorageHandler + Clone + Send + Sync + 'static,
+{
+    blockchain: ActorHandle<BlockchainService<FSH>>,
+    file_transfer: ActorHandle<FileTransferService>,
+}
+
+impl<FSH> ServicesProviderStatusSource<FSH>
+where
+    FSH: ForestStorageHandler + Clone + Send + Sync + 'static,
+{
+    pub fn new(
+        blockchain: ActorHandle<BlockchainService<FSH>>,
+        file_transfer: ActorHandle<FileTransferService>,
+    ) -> Self {
+        Self {
+            blockchain,
+            file_transfer,
+        }
+    }
+}
+
+#[async_trait]
+impl<FSH> ProviderStatusSource for ServicesProviderStatusSource<FSH>
+where
+    FSH: ForestStorageHandler + Clone + Send + Sync + 'static,
+{
+    async fn provider(&self) -> anyhow::Result<Option<ProviderIdentity>> {
+        let provider = match self.blockchain.query_storage_provider_id(None).await? {
+            Some(StorageProviderId::BackupStorageProvider(id)) => Some(ProviderIdentity {
+                id,
+                kind: ProviderKind::Bsp,
+            }),
+            Some(StorageProviderId::MainStorageProvider(id)) => Some(ProviderIdentity {
+                id,
+                kind: ProviderKind::Msp,
+            }),
+            None => None,
+        };
+        Ok(provider)
+    }
+
+    async fn on_chain_forest_roots(
+        &self,
+        provider: &ProviderIdentity,
+    ) -> anyhow::Result<Vec<(Option<H256>, H256)>> {
+        match provider.kind {
+            ProviderKind::Bsp => {
+                let root = self
+                    .blockchain
+                    .query_provider_forest_root(provider.id)
+                    .await
+                    .map_err(|e| {
+                        anyhow!("Failed to query the on-chain root of the BSP: {:?}", e)
+                    })?;
+                Ok(vec![(None, root)])
+            }
+            ProviderKind::Msp => {
+                let buckets = self
+                    .blockchain
+                    .query_buckets_for_msp(provider.id)
+                    .await
+                    .map_err(|e| anyhow!("Failed to query the buckets of the MSP: {:?}", e))?;
+
+                let mut roots = Vec::with_capacity(buckets.len());
+                for bucket_id in buckets {
+                    let root = self
+                        .blockchain
+                        .query_bucket_root(bucket_id)
+                        .await
+                        .map_err(|e| {
+                            anyhow!(
+                                "Failed to query the on-chain root of bucket [{:?}]: {:?}",
+                                bucket_id,
+                                e
+                            )
+                        })?;
+                    roots.push((Some(bucket_id), root));
+                }
+                Ok(roots)
+            }
+        }
+    }
+
+    async fn capacity(&self, provider: &ProviderIdentity) -> anyhow::Result<ProviderCapacity> {
+        let capacity = self
+            .blockchain
+            .query_storage_provider_capacity(provider.id)
+            .await
+            .map_err(|e| anyhow!("Failed to query the storage capacity: {:?}", e))?;
+        let available_capacity = self
+            .blockchain
+            .query_available_storage_capacity(provider.id)
+            .await
+            .map_err(|e| anyhow!("Failed to query the available storage capacity: {:?}", e))?;
+
+        Ok(ProviderCapacity {
+            capacity,
+            capacity_used: capacity.saturating_sub(available_capacity),
+        })
+    }
+
+    async fn last_proof_tick(
+        &self,
+        provider: &ProviderIdentity,
+    ) -> anyhow::Result<Option<BlockNumber>> {
+        // Only BSPs submit proofs.
+        if provider.kind == ProviderKind::Msp {
+            return Ok(None);
+        }
+
+        match self
+            .blockchain
+            .query_last_tick_provider_submitted_proof(provider.id)
+            .await
+        {
+            Ok(tick) => Ok(Some(tick)),
+            Err(GetProofSubmissionRecordError::ProviderNeverSubmittedProof) => Ok(None),
+            Err(e) => Err(anyhow!(
+                "Failed to query the last tick a proof was submitted: {:?}",
+                e
+            )),
+        }
+    }
+
+    async fn pending_requests(&self) -> anyhow::Result<PendingRequests> {
+        let depths = self.blockchain.query_pending_requests_queue_depths().await;
+
+        Ok(PendingRequests {
+            submit_proof: depths.submit_proof,
+            confirm_storing: depths.confirm_storing,
+            msp_respond_storage: depths.msp_respond_storage,
+            stop_storing_for_insolvent_user: depths.stop_storing_for_insolvent_user,
+            file_deletion: depths.file_deletion,
+        })
+    }
+
+    async fn file_transfer_registrations(&self) -> anyhow::Result<u64> {
+        let registered_file_keys = self.file_transfer.get_registered_file_keys().await;
+        Ok(registered_file_keys.len() as u64)
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use sc_telemetry::{telemetry, TelemetryHandle, SUBSTRATE_INFO};
+
+/// Name of the message reporting the uploads of a BSP to the telemetry endpoints.
+const BSP_UPLOAD_MESSAGE: &str = "bsp.upload";
+
+/// Reports the uploads of a BSP to the telemetry endpoints of the node.
+///
+/// Like the `system.interval` message of Substrate, every `bsp.upload` message carries all the
+/// values, so that dashboards only need the last message of each node:
+/// - `bsp_files_stored_total`: files fully received since the node started.
+/// - `bsp_upload_in_progress`: files being received.
+/// - `bsp_proofs_submitted_total`: storage proofs submitted for the challenges of the BSP and
+///   included on-chain since the node started.
+/// - `bsp_capacity_used_pct`: percentage of the on-chain capacity in use, as of the last change
+///   of either, e.g. when files are confirmed or deleted.
+#[derive(Clone)]
+pub struct BspTelemetry {
+    handle: TelemetryHandle,
+    files_stored: Arc<AtomicU64>,
+    proofs_submitted: Arc<AtomicU64>,
+    capacity: Arc<AtomicU64>,
+    capacity_used: Arc<AtomicU64>,
+}
+
+impl BspTelemetry {
+    pub fn new(handle: TelemetryHandle) -> Self {
+        Self {
+            handle,
+            files_stored: Default::default(),
+            proofs_submitted: Default::default(),
+            capacity: Default::default(),
+            capacity_used: Default::default(),
+        }
+    }
+
+    /// Reports a file fully received, with `uploads_in_progress` files still being received.
+    pub fn file_stored(&self, uploads_in_progress: usize) {
+        self.files_stored.fetch_add(1, Ordering::Relaxed);
+        self.report(uploads_in_progress);
+    }
+
+    /// Reports a storage proof submitted for the challenges of the BSP and included on-chain.
+    pub fn proof_submitted(&self, uploads_in_progress: usize) {
+        self.proofs_submitted.fetch_add(1, Ordering::Relaxed);
+        self.report(uploads_in_progress);
+    }
+
+    /// Reports a change of the on-chain capacity, of which `available` is not used yet.
+    pub fn capacity_changed(&self, capacity: u64, available: u64, uploads_in_progress: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+        self.capacity_used
+            .store(capacity.saturating_sub(available), Ordering::Relaxed);
+        self.report(uploads_in_progress);
+    }
+
+    fn report(&self, uploads_in_progress: usize) {
+        telemetry!(
+            Some(self.handle.clone());
+            SUBSTRATE_INFO;
+            BSP_UPLOAD_MESSAGE;
+            "bsp_files_stored_total" => self.files_stored.load(Ordering::Relaxed),
+            "bsp_upload_in_progress" => uploads_in_progress,
+            "bsp_proofs_submitted_total" => self.proofs_submitted.load(Ordering::Relaxed),
+            "bsp_capacity_used_pct" => capacity_used_pct(
+                self.capacity_used.load(Ordering::Relaxed),
+                self.capacity.load(Ordering::Relaxed),
+            )
+        );
+    }
+}
+
+/// The percentage of `capacity` that `used` is, or 0 without any capacity.
+fn capacity_used_pct(used: u64, capacity: u64) -> f64 {
+    if capacity == 0 {
+        return 0.0;
+    }
+
+    used as f64 * 100.0 / capacity as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capacity_used_pct_handles_no_capacity() {
+        assert_eq!(capacity_used_pct(0, 0), 0.0);
+        assert_eq!(capacity_used_pct(25, 100), 25.0);
+        assert_eq!(capacity_used_pct(100, 100), 100.0);
+    }
+}
@@ -1,7 +1,7 @@
 use kvdb::KeyValueDB;
 use shc_common::types::StorageProofsMerkleTrieLayout;
 use shc_file_manager::{
-    in_memory::InMemoryFileStorage, rocksdb::RocksDbFileStorage, traits::FileStorage,
+    in_memory::InMemoryFileStorage, sharded::ShardedFileStorage, traits::FileStorage,
 };
 use shc_forest_manager::{
     in_memory::InMemoryForestStorage, rocksdb::RocksDBForestStorage, traits::ForestStorageHandler,
@@ -25,7 +25,7 @@ impl ShNodeType for (BspProvider, InMemoryStorageLayer) {
 }
 
 impl ShNodeType for (BspProvider, RocksDbStorageLayer) {
-    type FL = RocksDbFileStorage<StorageProofsMerkleTrieLayout, kvdb_rocksdb::Database>;
+    type FL = ShardedFileStorage<StorageProofsMerkleTrieLayout, kvdb_rocksdb::Database>;
     type FSH = ForestStorageCaching<
         Vec<u8>,
         RocksDBForestStorage<StorageProofsMerkleTrieLayout, kvdb_rocksdb::Database>,
@@ -38,7 +38,7 @@ impl ShNodeType for (MspProvider, InMemoryStorageLayer) {
 }
 
 impl ShNodeType for (MspProvider, RocksDbStorageLayer) {
-    type FL = RocksDbFileStorage<StorageProofsMerkleTrieLayout, kvdb_rocksdb::Database>;
+    type FL = ShardedFileStorage<StorageProofsMerkleTrieLayout, kvdb_rocksdb::Database>;
     type FSH = ForestStorageCaching<
         Vec<u8>,
         RocksDBForestStorage<StorageProofsMerkleTrieLayout, kvdb_rocksdb::Database>,
@@ -102,9 +102,9 @@ impl ShStorageLayer for NoStorageLayer {}
 ///
 /// This trait makes the [`FileStorage`] trait's generic type parameter concrete, and sets
 /// it to the [`StorageProofsMerkleTrieLayout`] used in StorageHub.
-pub trait FileStorageT: FileStorage<StorageProofsMerkleTrieLayout> + Send + Sync {}
+pub trait FileStorageT: FileStorage<StorageProofsMerkleTrieLayout> + Send + Sync + 'static {}
 impl FileStorageT for InMemoryFileStorage<StorageProofsMerkleTrieLayout> {}
-impl<DB> FileStorageT for RocksDbFileStorage<StorageProofsMerkleTrieLayout, DB> where
+impl<DB> FileStorageT for ShardedFileStorage<StorageProofsMerkleTrieLayout, DB> where
     DB: KeyValueDB + 'static
 {
 }
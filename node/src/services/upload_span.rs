@@ -0,0 +1,275 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use sc_tracing::tracing::{info_span, Span};
+use shc_common::upload_progress::UploadProgressRegistry;
+use sp_core::H256;
+
+const LOG_TARGET: &str = "upload";
+
+/// The `role` of the span of uploads to a BSP.
+pub const BSP_ROLE: &str = "bsp";
+/// The `role` of the span of uploads to an MSP.
+pub const MSP_ROLE: &str = "msp";
+
+/// Creates the span of the upload of the file with `file_key` to a provider of `role`, entered
+/// while handling its storage request.
+pub fn upload_span(file_key: H256, bucket_id: H256, size: u64, role: &'static str) -> Span {
+    info_span!(
+        target: LOG_TARGET,
+        "upload",
+        file_key = %format_args!("{:x}", file_key),
+        bucket_id = %format_args!("{:x}", bucket_id),
+        size,
+        role,
+    )
+}
+
+/// The tracing spans of the files being uploaded to this provider, keyed by file key.
+///
+/// Once a file is accepted for upload, its [`upload_span`] is tracked here to be entered by every
+/// task handling the next steps of the upload (receiving its chunks, writing them, confirming it
+/// on-chain), so that the logs of uploads happening at the same time can be told apart.
+///
+/// A span lives as long as its upload in the [`UploadProgressRegistry`]: it is finished when the
+/// upload reaches a terminal state, and the spans of the uploads that ended or are no longer
+/// tracked are dropped whenever a new upload starts.
+#[derive(Clone)]
+pub struct UploadSpans {
+    spans: Arc<Mutex<HashMap<H256, Span>>>,
+    upload_progress: UploadProgressRegistry,
+}
+
+impl UploadSpans {
+    pub fn new(upload_progress: UploadProgressRegistry) -> Self {
+        Self {
+            spans: Arc::default(),
+            upload_progress,
+        }
+    }
+
+    /// Tracks `span` as the span of the upload of `file_key`, replacing any previous one.
+    ///
+    /// The spans of the uploads that are no longer in progress are dropped.
+    pub fn track(&self, file_key: H256, span: Span) {
+        let mut spans = self.spans.lock().unwrap_or_else(PoisonError::into_inner);
+        spans.retain(|tracked_file_key, _| {
+            self.upload_progress
+                .get(tracked_file_key)
+                .is_some_and(|progress| !progress.state.is_terminal())
+        });
+        spans.insert(file_key, span);
+    }
+
+    /// The span of the upload of `file_key`, or a disabled span if it is not being uploaded.
+    pub fn get(&self, file_key: &H256) -> Span {
+        self.spans
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(file_key)
+            .cloned()
+            .unwrap_or_else(Span::none)
+    }
+
+    /// Makes `span` follow the spans of the uploads of `file_keys`, for the steps handling
+    /// several uploads at once (e.g. confirming them in a single extrinsic).
+    pub fn follow_uploads<'a>(
+        &self,
+        span: Span,
+        file_keys: impl IntoIterator<Item = &'a H256>,
+    ) -> Span {
+        for file_key in file_keys {
+            span.follows_from(&self.get(file_key));
+        }
+        span
+    }
+
+    /// Drops the span of the upload of `file_key`, once it reached a terminal state.
+    pub fn finish(&self, file_key: &H256) {
+        self.spans
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(file_key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sc_tracing::tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        subscriber::{set_default, with_default},
+        Event, Instrument, Metadata, Subscriber,
+    };
+    use shc_common::upload_progress::UploadState;
+
+    /// Subscriber recording the fields of the spans in which events are logged.
+    #[derive(Default)]
+    struct SpanFieldsRecorder {
+        spans: Mutex<Vec<HashMap<String, String>>>,
+        current: Mutex<Vec<u64>>,
+        /// The fields of the innermost span of each event.
+        events: Mutex<Vec<HashMap<String, String>>>,
+        /// The fields of the spans followed by each span, keyed by the name of the span.
+        follows_from: Mutex<HashMap<String, Vec<HashMap<String, String>>>>,
+    }
+
+    struct FieldsVisitor<'a>(&'a mut HashMap<String, String>);
+
+    impl Visit for FieldsVisitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    impl Subscriber for SpanFieldsRecorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+            let mut fields = HashMap::new();
+            fields.insert("name".to_string(), attributes.metadata().name().to_string());
+            attributes.record(&mut FieldsVisitor(&mut fields));
+            let mut spans = self.spans.lock().unwrap();
+            spans.push(fields);
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, span: &Id, follows: &Id) {
+            let spans = self.spans.lock().unwrap();
+            self.follows_from
+                .lock()
+                .unwrap()
+                .entry(spans[span.into_u64() as usize - 1]["name"].clone())
+                .or_default()
+                .push(spans[follows.into_u64() as usize - 1].clone());
+        }
+
+        fn event(&self, _: &Event<'_>) {
+            let fields = self
+                .current
+                .lock()
+                .unwrap()
+                .last()
+                .map(|id| self.spans.lock().unwrap()[*id as usize - 1].clone())
+                .unwrap_or_default();
+            self.events.lock().unwrap().push(fields);
+        }
+
+        fn enter(&self, span: &Id) {
+            self.current.lock().unwrap().push(span.into_u64());
+        }
+
+        fn exit(&self, _: &Id) {
+            self.current.lock().unwrap().pop();
+        }
+    }
+
+    #[test]
+    fn logs_of_an_upload_are_within_its_span() {
+        let recorder = Arc::new(SpanFieldsRecorder::default());
+        let file_key = H256::repeat_byte(1);
+        let bucket_id = H256::repeat_byte(2);
+
+        with_default(recorder.clone(), || {
+            let upload_progress = UploadProgressRegistry::default();
+            let upload_spans = UploadSpans::new(upload_progress.clone());
+            upload_progress.register(file_key, 1);
+            upload_spans.track(file_key, upload_span(file_key, bucket_id, 1024, BSP_ROLE));
+
+            // The span is found by the task handling a later step of the upload.
+            upload_spans
+                .get(&file_key)
+                .in_scope(|| sc_tracing::tracing::debug!("Writing chunk"));
+
+            upload_spans.finish(&file_key);
+            upload_spans
+                .get(&file_key)
+                .in_scope(|| sc_tracing::tracing::debug!("Upload is over"));
+        });
+
+        let events = recorder.events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["file_key"], format!("{:x}", file_key));
+        assert_eq!(events[0]["bucket_id"], format!("{:x}", bucket_id));
+        assert_eq!(events[0]["size"], "1024");
+        assert_eq!(events[0]["role"], "\"bsp\"");
+        assert!(events[1].is_empty());
+    }
+
+    #[test]
+    fn spans_of_ended_uploads_are_dropped_when_an_upload_starts() {
+        let recorder = Arc::new(SpanFieldsRecorder::default());
+        let bucket_id = H256::repeat_byte(9);
+        let [ended, in_progress, untracked, started] = [1, 2, 3, 4].map(H256::repeat_byte);
+
+        with_default(recorder.clone(), || {
+            let upload_progress = UploadProgressRegistry::default();
+            let upload_spans = UploadSpans::new(upload_progress.clone());
+            for file_key in [ended, in_progress, started] {
+                upload_progress.register(file_key, 1);
+            }
+            for file_key in [ended, in_progress, untracked] {
+                upload_spans.track(file_key, upload_span(file_key, bucket_id, 1024, MSP_ROLE));
+            }
+
+            // An upload ends without its span being finished, and another one is not tracked by
+            // the registry (e.g. its entry was garbage collected).
+            upload_progress.set_state(&ended, UploadState::Rejected);
+
+            upload_spans.track(started, upload_span(started, bucket_id, 1024, MSP_ROLE));
+
+            assert!(upload_spans.get(&ended).is_none());
+            assert!(upload_spans.get(&untracked).is_none());
+            assert!(!upload_spans.get(&in_progress).is_none());
+            assert!(!upload_spans.get(&started).is_none());
+        });
+    }
+
+    #[tokio::test]
+    async fn extrinsic_of_several_uploads_is_submitted_within_a_span_following_them() {
+        let recorder = Arc::new(SpanFieldsRecorder::default());
+        let _guard = set_default(recorder.clone());
+        let bucket_id = H256::repeat_byte(9);
+        let [confirmed, other, ended] = [1, 2, 3].map(H256::repeat_byte);
+
+        let upload_progress = UploadProgressRegistry::default();
+        let upload_spans = UploadSpans::new(upload_progress.clone());
+        for file_key in [confirmed, other, ended] {
+            upload_progress.register(file_key, 1);
+            upload_spans.track(file_key, upload_span(file_key, bucket_id, 1024, BSP_ROLE));
+        }
+        upload_spans.finish(&ended);
+
+        // As the extrinsic confirming the uploads is submitted and watched.
+        let confirm_span = upload_spans.follow_uploads(
+            info_span!(target: LOG_TARGET, "confirm_storing"),
+            &[confirmed, other, ended],
+        );
+        async { sc_tracing::tracing::debug!("Extrinsic included") }
+            .instrument(confirm_span)
+            .await;
+
+        let events = recorder.events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["name"], "confirm_storing");
+
+        // The span of the upload that already ended is not followed.
+        let follows_from = recorder.follows_from.lock().unwrap();
+        let followed_file_keys = follows_from["confirm_storing"]
+            .iter()
+            .map(|fields| fields["file_key"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            followed_file_keys,
+            vec![format!("{:x}", confirmed), format!("{:x}", other)]
+        );
+    }
+}
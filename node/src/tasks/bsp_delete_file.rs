@@ -1,11 +1,20 @@
+use std::time::Duration;
+
 use anyhow::anyhow;
 use sc_tracing::tracing::*;
 use shc_actors_framework::event_bus::EventHandler;
-use shc_blockchain_service::events::FinalisedBspConfirmStoppedStoring;
-use shc_common::consts::CURRENT_FOREST_KEY;
+use shc_blockchain_service::{
+    commands::BlockchainServiceInterface,
+    events::{FinalisedBspConfirmStoppedStoring, PriorityChallengeForFileDeletionQueued},
+};
+use shc_common::{
+    consts::CURRENT_FOREST_KEY,
+    types::{Block, BlockNumber, ChallengeTicksTolerance, StorageProviderId},
+};
 use shc_file_manager::traits::FileStorage;
 use shc_forest_manager::traits::{ForestStorage, ForestStorageHandler};
-use sp_core::H256;
+use sp_core::{Get, H256};
+use sp_runtime::AccountId32;
 
 use crate::services::{
     handler::StorageHubHandler,
@@ -105,3 +114,179 @@ where
         Ok(())
     }
 }
+
+/// Handles the [`PriorityChallengeForFileDeletionQueued`] event.
+///
+/// This event is triggered when a priority challenge for the deletion of a file has been queued.
+/// If this BSP is storing the file, it waits for as long as it has to answer the priority challenge
+/// (see [`BspDeleteFileTask::priority_challenge_deletion_timeout`]) and, if by then the file has not been removed from its Forest (either by responding to the
+/// priority challenge or by a `BspConfirmStoppedStoring`), it submits a `bsp_request_stop_storing`
+/// extrinsic for it.
+impl<NT> EventHandler<PriorityChallengeForFileDeletionQueued> for BspDeleteFileTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    async fn handle_event(
+        &mut self,
+        event: PriorityChallengeForFileDeletionQueued,
+    ) -> anyhow::Result<()> {
+        let file_key: H256 = event.file_key.into();
+
+        if !self.is_file_in_forest(&file_key).await? {
+            trace!(
+                target: LOG_TARGET,
+                "Priority challenge for deletion queued for file key {:x}, which is not stored by this BSP",
+                file_key
+            );
+            return Ok(());
+        }
+
+        let current_block = Block(
+            self.storage_hub_handler
+                .blockchain
+                .get_best_block_info()
+                .await
+                .number,
+        );
+        let deadline =
+            current_block.saturating_add(self.priority_challenge_deletion_timeout().await?);
+
+        info!(
+            target: LOG_TARGET,
+            "Priority challenge for deletion queued for file key {:x}. Waiting until block {} for it to be removed from the Forest",
+            file_key,
+            deadline
+        );
+
+        self.storage_hub_handler
+            .blockchain
+            .wait_for_block(deadline)
+            .await?;
+
+        if !self.is_file_in_forest(&file_key).await? {
+            debug!(
+                target: LOG_TARGET,
+                "File key {:x} was removed from the Forest before block {}",
+                file_key,
+                deadline
+            );
+            return Ok(());
+        }
+
+        warn!(
+            target: LOG_TARGET,
+            "File key {:x} is still in the Forest at block {}, requesting to stop storing it",
+            file_key,
+            deadline
+        );
+
+        self.request_stop_storing(&file_key).await
+    }
+}
+
+impl<NT> BspDeleteFileTask<NT>
+where
+    NT: ShNodeType,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    async fn is_file_in_forest(&self, file_key: &H256) -> anyhow::Result<bool> {
+        let current_forest_key = CURRENT_FOREST_KEY.to_vec();
+        let fs = self
+            .storage_hub_handler
+            .forest_storage_handler
+            .get(&current_forest_key)
+            .await
+            .ok_or_else(|| anyhow!("Failed to get forest storage."))?;
+        let is_in_forest = fs.read().await.contains_file_key(file_key)?;
+        Ok(is_in_forest)
+    }
+
+    /// Submits a `bsp_request_stop_storing` extrinsic for `file_key`, which has to be in this BSP's Forest.
+    /// The number of blocks to wait, after a priority challenge for the deletion of a file is
+    /// queued, before requesting to stop storing the file if it is still in the Forest.
+    ///
+    /// That is the longest this BSP can take to answer the challenge: a whole challenge period
+    /// plus the runtime's [`ChallengeTicksTolerance`].
+    async fn priority_challenge_deletion_timeout(&self) -> anyhow::Result<BlockNumber> {
+        let bsp_id = match self
+            .storage_hub_handler
+            .blockchain
+            .query_storage_provider_id(None)
+            .await?
+        {
+            Some(StorageProviderId::BackupStorageProvider(id)) => id,
+            _ => return Err(anyhow!("This node is not managing a BSP")),
+        };
+        let challenge_period = self
+            .storage_hub_handler
+            .blockchain
+            .query_challenge_period(bsp_id)
+            .await
+            .map_err(|e| anyhow!("Failed to query challenge period: {:?}", e))?;
+
+        Ok(challenge_period.saturating_add(ChallengeTicksTolerance::get()))
+    }
+
+    async fn request_stop_storing(&self, file_key: &H256) -> anyhow::Result<()> {
+        let current_forest_key = CURRENT_FOREST_KEY.to_vec();
+        let fs = self
+            .storage_hub_handler
+            .forest_storage_handler
+            .get(&current_forest_key)
+            .await
+            .ok_or_else(|| anyhow!("Failed to get forest storage."))?;
+
+        let read_fs = fs.read().await;
+        let metadata = read_fs
+            .get_file_metadata(file_key)
+            .map_err(|e| anyhow!("Failed to get file metadata from Forest: {:?}", e))?
+            .ok_or_else(|| {
+                anyhow!(
+                    "File metadata not found in Forest for file key {:x}",
+                    file_key
+                )
+            })?;
+        let inclusion_forest_proof = read_fs
+            .generate_proof(vec![*file_key])
+            .map_err(|e| anyhow!("Failed to generate proof from Forest: {:?}", e))?
+            .proof;
+        drop(read_fs);
+
+        let owner = AccountId32::try_from(metadata.owner().as_slice())
+            .map_err(|_| anyhow!("Invalid owner in file metadata for file key {:x}", file_key))?;
+
+        let call = storage_hub_runtime::RuntimeCall::FileSystem(
+            pallet_file_system::Call::bsp_request_stop_storing {
+                file_key: *file_key,
+                bucket_id: H256::from_slice(metadata.bucket_id().as_ref()),
+                location: sp_runtime::BoundedVec::truncate_from(metadata.location().clone()),
+                owner,
+                fingerprint: metadata.fingerprint().as_hash().into(),
+                size: metadata.file_size(),
+                can_serve: false,
+                inclusion_forest_proof,
+            },
+        );
+
+        self.storage_hub_handler
+            .blockchain
+            .send_extrinsic(call, Default::default())
+            .await?
+            .with_timeout(Duration::from_secs(
+                self.storage_hub_handler
+                    .provider_config
+                    .extrinsic_retry_timeout,
+            ))
+            .watch_for_success(&self.storage_hub_handler.blockchain)
+            .await?;
+
+        info!(
+            target: LOG_TARGET,
+            "Requested to stop storing file key {:x}",
+            file_key
+        );
+
+        Ok(())
+    }
+}
@@ -60,9 +60,20 @@ where
             chunk_ids,
             request_id,
             bucket_id,
+            requester,
             ..
         } = event;
 
+        // BSPs only serve peers in their allow list. Retrievals on behalf of users are served by MSPs.
+        if let Some(requester) = requester {
+            error!(target: LOG_TARGET, "Rejecting retrieval request for file {:?} on behalf of {:?}: BSPs don't serve retrievals", event.file_key, requester);
+            self.storage_hub_handler
+                .file_transfer
+                .reject_download_request(request_id)
+                .await?;
+            return Err(anyhow::anyhow!("BSPs don't serve retrievals"));
+        }
+
         // Get the file metadata from the file storage.
         let file_storage_read_lock = self.storage_hub_handler.file_storage.read().await;
         let file_metadata = file_storage_read_lock
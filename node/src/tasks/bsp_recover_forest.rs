@@ -0,0 +1,292 @@
+use anyhow::anyhow;
+use async_trait::async_trait;
+use sc_tracing::tracing::*;
+use sp_core::H256;
+
+use shc_actors_framework::event_bus::EventHandler;
+use shc_blockchain_service::{commands::BlockchainServiceInterface, events::ForestRootMismatch};
+use shc_common::{
+    consts::CURRENT_FOREST_KEY,
+    types::{FileMetadata, StorageProofsMerkleTrieLayout, StorageProviderId},
+};
+use shc_file_manager::traits::FileStorage;
+use shc_forest_manager::{
+    recovery::{forest_root_of, reconcile_forest},
+    traits::{ForestStorage, ForestStorageHandler},
+};
+use shc_rpc::forest_recovery::{ForestRecovery, RecoverForestResult, RecoveredForest};
+
+use crate::{
+    services::{
+        handler::StorageHubHandler,
+        types::{BspForestStorageHandlerT, ShNodeType},
+    },
+    tasks::bsp_repair_file::BspRepairFileTask,
+};
+
+const LOG_TARGET: &str = "bsp-recover-forest-task";
+
+/// BSP Recover Forest Task: Rebuilds the BSP's Forest when it diverges from its on-chain root.
+///
+/// The task has one handler:
+/// - [`ForestRootMismatch`]: Reacts to the event emitted by the BlockchainService when the local
+///   root of the Forest doesn't match the on-chain root after applying the mutations of a block.
+///
+/// The same recovery can be triggered manually through the `recoverForest` RPC method. It goes
+/// as follows:
+/// - Compares the local root of the Forest with the on-chain root of the BSP. If they match,
+///   there is nothing to recover.
+/// - Fetches the files stored by the BSP from the indexer, and checks that a Forest holding
+///   exactly those files has the on-chain root. Otherwise, the indexer is not in sync with the
+///   chain yet, and the Forest is left untouched.
+/// - Snapshots the diverged Forest, so that it can be inspected later.
+/// - Reconciles the Forest with the files stored on-chain under its write lock, so that no proof
+///   is generated from a partially rebuilt Forest.
+/// - Repairs the files inserted in the Forest that are not complete in the File Storage, from the
+///   other providers storing them (see [`BspRepairFileTask`]).
+pub struct BspRecoverForestTask<NT>
+where
+    NT: ShNodeType,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    storage_hub_handler: StorageHubHandler<NT>,
+}
+
+impl<NT> Clone for BspRecoverForestTask<NT>
+where
+    NT: ShNodeType,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    fn clone(&self) -> BspRecoverForestTask<NT> {
+        Self {
+            storage_hub_handler: self.storage_hub_handler.clone(),
+        }
+    }
+}
+
+impl<NT> BspRecoverForestTask<NT>
+where
+    NT: ShNodeType,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    pub fn new(storage_hub_handler: StorageHubHandler<NT>) -> Self {
+        Self {
+            storage_hub_handler,
+        }
+    }
+}
+
+impl<NT> EventHandler<ForestRootMismatch> for BspRecoverForestTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    async fn handle_event(&mut self, event: ForestRootMismatch) -> anyhow::Result<()> {
+        // Bucket Forests are only managed by MSPs.
+        if event.bucket_id.is_some() {
+            return Ok(());
+        }
+
+        warn!(target: LOG_TARGET, "Local Forest of BSP [{:?}] diverged from its on-chain root. Recovering it.", event.provider_id);
+
+        match self.recover_bsp_forest().await {
+            Ok(RecoverForestResult::InSync(root)) => {
+                info!(target: LOG_TARGET, "Local Forest is in sync with its on-chain root [{:?}]. Nothing to recover.", root);
+                Ok(())
+            }
+            Ok(RecoverForestResult::Recovered(_)) => Ok(()),
+            Err(e) => {
+                error!(target: LOG_TARGET, "CRITICAL ❗️❗️ Failed to recover the local Forest of BSP [{:?}]. It can be retried with the `recoverForest` RPC method. \nError: {:?}", event.provider_id, e);
+                Err(e)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<NT> ForestRecovery for BspRecoverForestTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    async fn recover_forest(
+        &self,
+        forest_key: Option<H256>,
+    ) -> anyhow::Result<RecoverForestResult> {
+        if forest_key.is_some() {
+            return Err(anyhow!(
+                "BSPs only maintain a single Forest, so no forest key should be given."
+            ));
+        }
+
+        self.recover_bsp_forest().await
+    }
+}
+
+impl<NT> BspRecoverForestTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    /// Rebuilds the BSP's Forest from the files stored on-chain, if its local root diverged from
+    /// the on-chain one.
+    async fn recover_bsp_forest(&self) -> anyhow::Result<RecoverForestResult> {
+        let bsp_id = match self
+            .storage_hub_handler
+            .blockchain
+            .query_storage_provider_id(None)
+            .await?
+        {
+            Some(StorageProviderId::BackupStorageProvider(id)) => id,
+            Some(StorageProviderId::MainStorageProvider(_)) => {
+                return Err(anyhow!(
+                    "Current node account is a Main Storage Provider. Expected a Backup Storage Provider ID."
+                ));
+            }
+            None => {
+                return Err(anyhow!("Failed to get own BSP ID."));
+            }
+        };
+
+        let on_chain_root = self
+            .storage_hub_handler
+            .blockchain
+            .query_provider_forest_root(bsp_id)
+            .await
+            .map_err(|e| anyhow!("Failed to query the on-chain root of the BSP: {:?}", e))?;
+
+        let current_forest_key = CURRENT_FOREST_KEY.to_vec();
+        let fs = self
+            .storage_hub_handler
+            .forest_storage_handler
+            .get(&current_forest_key)
+            .await
+            .ok_or_else(|| anyhow!("Failed to get the BSP Forest Storage."))?;
+
+        let diverged_root = fs.read().await.root();
+        if diverged_root == on_chain_root {
+            return Ok(RecoverForestResult::InSync(on_chain_root));
+        }
+
+        // Rebuild the set of files stored by this BSP, and check it against the on-chain root
+        // before touching the Forest.
+        let files = self.files_stored_on_chain(bsp_id).await?;
+        let rebuilt_root = forest_root_of::<StorageProofsMerkleTrieLayout>(&files)
+            .map_err(|e| anyhow!("Failed to compute the root of the rebuilt Forest: {:?}", e))?;
+        if rebuilt_root != on_chain_root {
+            return Err(anyhow!(
+                "The {} files stored by the BSP according to the indexer make up root [{:?}] instead of the on-chain root [{:?}]. The indexer may not be in sync with the chain yet.",
+                files.len(),
+                rebuilt_root,
+                on_chain_root
+            ));
+        }
+
+        // Keep a copy of the diverged Forest, to find out later how it diverged.
+        let diverged_snapshot_key = format!("diverged_forest_{:?}", diverged_root).into_bytes();
+        let diverged_snapshot_key = match self
+            .storage_hub_handler
+            .forest_storage_handler
+            .snapshot(&current_forest_key, &diverged_snapshot_key)
+            .await
+        {
+            Some(_) => Some(diverged_snapshot_key),
+            None => {
+                warn!(target: LOG_TARGET, "Failed to snapshot the diverged Forest with root [{:?}]. Recovering it anyway.", diverged_root);
+                None
+            }
+        };
+
+        let mut write_fs = fs.write().await;
+
+        // The Forest could have been recovered while waiting for the lock.
+        if write_fs.root() == on_chain_root {
+            return Ok(RecoverForestResult::InSync(on_chain_root));
+        }
+
+        let reconciliation =
+            reconcile_forest::<StorageProofsMerkleTrieLayout, _>(&mut *write_fs, &files).map_err(
+                |e| {
+                    anyhow!(
+                        "Failed to reconcile the Forest with the on-chain files: {:?}",
+                        e
+                    )
+                },
+            )?;
+
+        let recovered_root = write_fs.root();
+        if recovered_root != on_chain_root {
+            return Err(anyhow!(
+                "The recovered Forest has root [{:?}] instead of the on-chain root [{:?}]. Its mutations were likely applied while it was being recovered.",
+                recovered_root,
+                on_chain_root
+            ));
+        }
+        drop(write_fs);
+
+        info!(target: LOG_TARGET, "🌳 Recovered the local Forest from root [{:?}] to the on-chain root [{:?}], removing {} file(s) and inserting {} file(s).", diverged_root, on_chain_root, reconciliation.removed.len(), reconciliation.inserted.len());
+        debug!(target: LOG_TARGET, "Removed file keys: {:?}", reconciliation.removed);
+        debug!(target: LOG_TARGET, "Inserted file keys: {:?}", reconciliation.inserted);
+
+        self.repair_incomplete_files(&reconciliation.inserted).await;
+
+        Ok(RecoverForestResult::Recovered(RecoveredForest {
+            diverged_root,
+            on_chain_root,
+            diverged_snapshot_key,
+            removed_file_keys: reconciliation.removed,
+            inserted_file_keys: reconciliation.inserted,
+        }))
+    }
+
+    /// Spawns the repair of the files with `file_keys` that are not complete in the File Storage.
+    async fn repair_incomplete_files(&self, file_keys: &[H256]) {
+        let read_file_storage = self.storage_hub_handler.file_storage.read().await;
+        let incomplete_file_keys: Vec<H256> = file_keys
+            .iter()
+            .filter(|file_key| !matches!(read_file_storage.is_file_complete(file_key), Ok(true)))
+            .copied()
+            .collect();
+        drop(read_file_storage);
+
+        for file_key in incomplete_file_keys {
+            warn!(target: LOG_TARGET, "File [{:?}] is in the recovered Forest but not complete in the File Storage. Repairing it.", file_key);
+
+            let repair_task = BspRepairFileTask::new(self.storage_hub_handler.clone());
+            self.storage_hub_handler.task_spawner.spawn(async move {
+                if let Err(e) = repair_task.repair_file(file_key).await {
+                    error!(target: LOG_TARGET, "Failed to repair file [{:?}]: {:?}", file_key, e);
+                }
+            });
+        }
+    }
+
+    /// The metadata of the files stored on-chain by the BSP with `bsp_id`, as tracked by the
+    /// indexer.
+    async fn files_stored_on_chain(&self, bsp_id: H256) -> anyhow::Result<Vec<FileMetadata>> {
+        let indexer_db_pool = self.storage_hub_handler.indexer_db_pool.as_ref().ok_or_else(|| {
+            anyhow!("Indexer is disabled, but it is needed to recover the Forest. Please provide a database URL (and enable indexer) for it to use this feature.")
+        })?;
+
+        let mut indexer_connection = indexer_db_pool.get().await.map_err(|error| {
+            anyhow!(
+                "Failed to get indexer connection after timeout: {:?}",
+                error
+            )
+        })?;
+
+        let files = shc_indexer_db::models::File::get_by_onchain_bsp_id(
+            &mut indexer_connection,
+            bsp_id.to_string(),
+        )
+        .await?;
+
+        files
+            .into_iter()
+            .map(|(file, onchain_bucket_id)| {
+                file.to_file_metadata(onchain_bucket_id)
+                    .map_err(|e| anyhow!("Failed to convert file to file metadata: {:?}", e))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,867 @@
+use std::{collections::HashSet, fmt, future::Future, sync::Arc};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use codec::Decode;
+use sc_network::PeerId;
+use sc_tracing::tracing::*;
+use sp_core::H256;
+use tokio::sync::RwLock;
+
+use shc_actors_framework::{actor::ActorHandle, event_bus::EventHandler};
+use shc_blockchain_service::{commands::BlockchainServiceInterface, events::RepairFileRequested};
+use shc_common::{
+    consts::CURRENT_FOREST_KEY,
+    types::{
+        BucketId, Chunk, ChunkId, FileKeyProof, FileMetadata, HashT, StorageProofsMerkleTrieLayout,
+    },
+};
+use shc_file_manager::traits::{FileDataTrie, FileStorage, FileStorageError};
+use shc_file_transfer_service::{commands::FileTransferServiceInterface, FileTransferService};
+use shc_forest_manager::traits::{ForestStorage, ForestStorageHandler};
+
+use crate::services::{
+    file_repair::{FileRepairGuard, FileRepairProgress},
+    handler::StorageHubHandler,
+    types::{BspForestStorageHandlerT, ShNodeType},
+};
+
+const LOG_TARGET: &str = "bsp-repair-file-task";
+
+/// Maximum number of chunks to request from a provider in a single download request.
+const MAX_CHUNKS_PER_REQUEST: usize = 10;
+/// Number of retries per provider for a single batch of chunks.
+const DOWNLOAD_RETRY_ATTEMPTS: usize = 2;
+
+/// BSP Repair File Task: Downloads again a file stored by the BSP whose local copy is missing or
+/// corrupt, from the other providers storing it.
+///
+/// Repairs are triggered by [`BspRecoverForestTask`](super::bsp_recover_forest::BspRecoverForestTask)
+/// for the files it inserted in the Forest that are not complete in the File Storage. The task
+/// also has one handler:
+/// - [`RepairFileRequested`]: Reacts to the event emitted by the BlockchainService when the
+///   repair of a file is requested through the `RepairFile` command, e.g. by the operator with the
+///   `repairFile` RPC method. The file is verified against its fingerprint first, and only
+///   repaired if its local copy is corrupt or missing (see [`repair_corrupt_file`]).
+///
+/// A repair goes as follows:
+/// - Takes one of the few repair slots of [`FileRepairs`](crate::services::file_repair::FileRepairs),
+///   so that repairs don't starve the node of bandwidth and disk. A file already being repaired
+///   is not repaired twice.
+/// - Looks up the providers storing the file: the MSP of its bucket on-chain, and the other BSPs
+///   storing it according to the indexer.
+/// - Downloads the chunks of the file in batches of [`MAX_CHUNKS_PER_REQUEST`], verifying each
+///   batch against the fingerprint in the file metadata, and writes them to a new trie. A
+///   provider sending chunks that don't verify is skipped for the next one.
+/// - Replaces the local copy of the file with the new trie, once its root matches the fingerprint.
+///
+/// The providers asked for the chunks only serve them if they allow this node to download the
+/// file or its bucket.
+pub struct BspRepairFileTask<NT>
+where
+    NT: ShNodeType,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    storage_hub_handler: StorageHubHandler<NT>,
+}
+
+impl<NT> Clone for BspRepairFileTask<NT>
+where
+    NT: ShNodeType,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    fn clone(&self) -> BspRepairFileTask<NT> {
+        Self {
+            storage_hub_handler: self.storage_hub_handler.clone(),
+        }
+    }
+}
+
+impl<NT> BspRepairFileTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    pub fn new(storage_hub_handler: StorageHubHandler<NT>) -> Self {
+        Self {
+            storage_hub_handler,
+        }
+    }
+
+    /// Repairs the file with `file_key` from the other providers storing it.
+    ///
+    /// The file must be in the BSP's Forest, which holds its metadata.
+    pub async fn repair_file(&self, file_key: H256) -> anyhow::Result<()> {
+        let file_repairs = &self.storage_hub_handler.file_repairs;
+        let Some(repair) = file_repairs.start(file_key).await else {
+            let progress = file_repairs.progress(&file_key).unwrap_or_default();
+            debug!(target: LOG_TARGET, "File [{:?}] is already being repaired ({:.1}% done)", file_key, progress.percentage());
+            return Ok(());
+        };
+
+        let file_metadata = self.file_metadata_in_forest(&file_key).await?;
+        let sources = self.chunk_sources(&file_key, &file_metadata).await?;
+        repair_from_sources(
+            &self.storage_hub_handler.file_storage,
+            &file_metadata,
+            &sources,
+            &repair,
+        )
+        .await
+    }
+
+    /// The metadata of the file with `file_key` in the BSP's Forest.
+    async fn file_metadata_in_forest(&self, file_key: &H256) -> anyhow::Result<FileMetadata> {
+        let fs = self
+            .storage_hub_handler
+            .forest_storage_handler
+            .get(&CURRENT_FOREST_KEY.to_vec())
+            .await
+            .ok_or_else(|| anyhow!("Failed to get the BSP Forest Storage."))?;
+
+        let file_metadata = fs.read().await.get_file_metadata(file_key).map_err(|e| {
+            anyhow!(
+                "Failed to get the metadata of file [{:?}]: {:?}",
+                file_key,
+                e
+            )
+        })?;
+
+        file_metadata.ok_or_else(|| {
+            anyhow!(
+                "File [{:?}] is not in the BSP's Forest, so it is not stored by the BSP.",
+                file_key
+            )
+        })
+    }
+
+    /// The peers of the other providers storing the file with `file_key`: the MSP of its bucket,
+    /// and the BSPs storing it according to the indexer, if enabled.
+    async fn chunk_sources(
+        &self,
+        file_key: &H256,
+        file_metadata: &FileMetadata,
+    ) -> anyhow::Result<Vec<PeerChunkSource>> {
+        let bucket_id = H256::from_slice(file_metadata.bucket_id().as_ref());
+        let mut peer_ids = Vec::new();
+
+        match self
+            .storage_hub_handler
+            .blockchain
+            .query_msp_id_of_bucket_id(bucket_id)
+            .await
+        {
+            Ok(Some(msp_id)) => match self
+                .storage_hub_handler
+                .blockchain
+                .query_provider_multiaddresses(msp_id)
+                .await
+            {
+                Ok(multiaddresses) => peer_ids.extend(
+                    self.storage_hub_handler
+                        .file_transfer
+                        .extract_peer_ids_and_register_known_addresses(multiaddresses)
+                        .await,
+                ),
+                Err(e) => {
+                    warn!(target: LOG_TARGET, "Failed to get the multiaddresses of MSP [{:?}]: {:?}", msp_id, e)
+                }
+            },
+            Ok(None) => {}
+            Err(e) => {
+                warn!(target: LOG_TARGET, "Failed to get the MSP of bucket [{:?}]: {:?}", bucket_id, e)
+            }
+        }
+
+        if let Some(indexer_db_pool) = &self.storage_hub_handler.indexer_db_pool {
+            let mut indexer_connection = indexer_db_pool.get().await.map_err(|error| {
+                anyhow!(
+                    "Failed to get indexer connection after timeout: {:?}",
+                    error
+                )
+            })?;
+            let file =
+                shc_indexer_db::models::File::get_by_file_key(&mut indexer_connection, file_key)
+                    .await?;
+            peer_ids.extend(file.get_bsp_peer_ids(&mut indexer_connection).await?);
+        } else {
+            warn!(target: LOG_TARGET, "Indexer is disabled, so file [{:?}] can only be repaired from the MSP of its bucket.", file_key);
+        }
+
+        let mut seen = HashSet::new();
+        Ok(peer_ids
+            .into_iter()
+            .filter(|peer_id| seen.insert(*peer_id))
+            .map(|peer_id| PeerChunkSource {
+                file_transfer: self.storage_hub_handler.file_transfer.clone(),
+                peer_id,
+                bucket_id,
+            })
+            .collect())
+    }
+}
+
+impl<NT> EventHandler<RepairFileRequested> for BspRepairFileTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    async fn handle_event(&mut self, event: RepairFileRequested) -> anyhow::Result<()> {
+        let file_key = event.file_key;
+        info!(target: LOG_TARGET, "🩹 Repair of file [{:?}] requested", file_key);
+
+        let file_metadata = self.file_metadata_in_forest(&file_key).await?;
+
+        // The file is tracked as being repaired until its local copy is replaced, so that it is
+        // not repaired twice at the same time.
+        let Some(repair) = self.storage_hub_handler.file_repairs.start(file_key).await else {
+            let progress = self
+                .storage_hub_handler
+                .file_repairs
+                .progress(&file_key)
+                .unwrap_or_default();
+            warn!(target: LOG_TARGET, "File [{:?}] is already being repaired ({:.1}% done)", file_key, progress.percentage());
+            return Ok(());
+        };
+
+        let outcome = repair_corrupt_file(
+            &self.storage_hub_handler.file_storage,
+            &file_metadata,
+            self.chunk_sources(&file_key, &file_metadata),
+            &repair,
+        )
+        .await
+        .map_err(|e| {
+            error!(target: LOG_TARGET, "Failed to repair file [{:?}]: {:?}", file_key, e);
+            e
+        })?;
+
+        if outcome == FileRepairOutcome::Intact {
+            info!(target: LOG_TARGET, "File [{:?}] is intact, there is nothing to repair", file_key);
+        }
+
+        Ok(())
+    }
+}
+
+/// Outcome of [`repair_corrupt_file`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FileRepairOutcome {
+    /// The local copy of the file makes up its fingerprint, so it was left untouched.
+    Intact,
+    /// The local copy of the file was corrupt or missing, and was replaced by the one downloaded
+    /// from the other providers storing it.
+    Repaired,
+}
+
+/// Repairs the file with `file_metadata` if its local copy in `file_storage` is corrupt or
+/// missing, downloading it from the `sources` fetched only then.
+///
+/// The local copy is verified against the fingerprint of the file in a blocking task, under a
+/// read lock so that the uploads and proofs of the node go on meanwhile. A corrupt local copy is
+/// kept until it is replaced by the verified download, as done by [`repair_from_sources`].
+pub(crate) async fn repair_corrupt_file<FL, S>(
+    file_storage: &Arc<RwLock<FL>>,
+    file_metadata: &FileMetadata,
+    sources: impl Future<Output = anyhow::Result<Vec<S>>>,
+    repair: &FileRepairGuard,
+) -> anyhow::Result<FileRepairOutcome>
+where
+    FL: FileStorage<StorageProofsMerkleTrieLayout> + Send + Sync + 'static,
+    S: ChunkSource,
+{
+    let file_key = file_metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+    if is_intact(file_storage, file_key).await? {
+        return Ok(FileRepairOutcome::Intact);
+    }
+    warn!(target: LOG_TARGET, "🩹 The local copy of file [{:?}] is corrupt or missing", file_key);
+
+    repair_from_sources(file_storage, file_metadata, &sources.await?, repair).await?;
+
+    Ok(FileRepairOutcome::Repaired)
+}
+
+/// Whether the local copy in `file_storage` of the file with `file_key` makes up its
+/// fingerprint.
+///
+/// The file is hashed in a blocking task, under a read lock.
+async fn is_intact<FL>(file_storage: &Arc<RwLock<FL>>, file_key: H256) -> anyhow::Result<bool>
+where
+    FL: FileStorage<StorageProofsMerkleTrieLayout> + Send + Sync + 'static,
+{
+    let file_storage = file_storage.clone();
+    let verification = tokio::task::spawn_blocking(move || {
+        file_storage
+            .blocking_read()
+            .verify_and_repair_file(&file_key)
+    })
+    .await
+    .map_err(|e| anyhow!("File verification task failed: {:?}", e))?;
+
+    match verification {
+        Ok(verification) => Ok(verification.complete),
+        Err(FileStorageError::FileDoesNotExist) => Ok(false),
+        Err(e) => Err(anyhow!("Failed to verify file [{:?}]: {:?}", file_key, e)),
+    }
+}
+
+/// Downloads the file with `file_metadata` from `sources`, and replaces its local copy in
+/// `file_storage` with it.
+async fn repair_from_sources<FL, S>(
+    file_storage: &RwLock<FL>,
+    file_metadata: &FileMetadata,
+    sources: &[S],
+    repair: &FileRepairGuard,
+) -> anyhow::Result<()>
+where
+    FL: FileStorage<StorageProofsMerkleTrieLayout>,
+    S: ChunkSource,
+{
+    let file_key = file_metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+    if sources.is_empty() {
+        return Err(anyhow!(
+            "No other provider storing file [{:?}] was found to repair it from.",
+            file_key
+        ));
+    }
+
+    info!(target: LOG_TARGET, "🩹 Repairing file [{:?}] of {} chunks from {} provider(s)", file_key, file_metadata.chunks_count(), sources.len());
+
+    let file_data = download_verified_file(file_storage, file_metadata, sources, repair).await?;
+
+    let mut write_file_storage = file_storage.write().await;
+    replace_local_copy(
+        &mut *write_file_storage,
+        file_key,
+        file_metadata.clone(),
+        file_data,
+    )?;
+    drop(write_file_storage);
+
+    info!(target: LOG_TARGET, "🩹 Repaired file [{:?}]", file_key);
+
+    Ok(())
+}
+
+/// A provider storing a file, from which its chunks can be downloaded along with a proof.
+#[async_trait]
+pub(crate) trait ChunkSource: fmt::Display + Send + Sync {
+    /// Fetches the chunks with `chunk_ids` of the file with `file_key`, along with their proof.
+    async fn fetch_chunks(
+        &self,
+        file_key: H256,
+        chunk_ids: HashSet<ChunkId>,
+    ) -> anyhow::Result<FileKeyProof>;
+}
+
+/// A peer of a provider, whose chunks are downloaded through the file transfer protocol.
+pub(crate) struct PeerChunkSource {
+    file_transfer: ActorHandle<FileTransferService>,
+    peer_id: PeerId,
+    bucket_id: BucketId,
+}
+
+impl fmt::Display for PeerChunkSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "peer {}", self.peer_id)
+    }
+}
+
+#[async_trait]
+impl ChunkSource for PeerChunkSource {
+    async fn fetch_chunks(
+        &self,
+        file_key: H256,
+        chunk_ids: HashSet<ChunkId>,
+    ) -> anyhow::Result<FileKeyProof> {
+        let response = self
+            .file_transfer
+            .download_request(
+                self.peer_id,
+                file_key.into(),
+                chunk_ids,
+                Some(self.bucket_id),
+            )
+            .await
+            .map_err(|e| anyhow!("Download request failed: {:?}", e))?;
+
+        FileKeyProof::decode(&mut response.file_key_proof.as_ref())
+            .map_err(|e| anyhow!("Failed to decode file key proof: {:?}", e))
+    }
+}
+
+/// Downloads all the chunks of the file with `file_metadata` from `sources` into a new trie,
+/// recording the progress in `repair`.
+///
+/// Every batch of chunks is verified against the fingerprint of the file before being written,
+/// and the root of the returned trie is checked to be the fingerprint. If the download fails, the
+/// chunks written so far are left in the File Storage, as they could share nodes with the local
+/// copy of the file.
+pub(crate) async fn download_verified_file<FL, S>(
+    file_storage: &RwLock<FL>,
+    file_metadata: &FileMetadata,
+    sources: &[S],
+    repair: &FileRepairGuard,
+) -> anyhow::Result<FL::FileDataTrie>
+where
+    FL: FileStorage<StorageProofsMerkleTrieLayout>,
+    S: ChunkSource,
+{
+    let file_key = file_metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+    let chunks_count = file_metadata.chunks_count();
+    let mut progress = FileRepairProgress {
+        chunks_repaired: 0,
+        chunks_count,
+    };
+    repair.set_progress(progress);
+
+    let mut file_data = file_storage.read().await.new_file_data_trie_for(&file_key);
+    for batch_start in (0..chunks_count).step_by(MAX_CHUNKS_PER_REQUEST) {
+        let batch_end = std::cmp::min(batch_start + MAX_CHUNKS_PER_REQUEST as u64, chunks_count);
+        let chunk_ids: HashSet<ChunkId> = (batch_start..batch_end).map(ChunkId::new).collect();
+
+        for (chunk_id, chunk) in fetch_verified_chunks(sources, file_metadata, &chunk_ids).await? {
+            file_data.write_chunk(&chunk_id, &chunk).map_err(|e| {
+                anyhow!(
+                    "Failed to write chunk {} of file [{:?}]: {:?}",
+                    chunk_id.as_u64(),
+                    file_key,
+                    e
+                )
+            })?;
+        }
+
+        progress.chunks_repaired = batch_end;
+        repair.set_progress(progress);
+        debug!(target: LOG_TARGET, "Repairing file [{:?}]: {}/{} chunks ({:.1}%)", file_key, progress.chunks_repaired, chunks_count, progress.percentage());
+    }
+
+    if file_data.get_root().as_ref() != file_metadata.fingerprint().as_ref() {
+        return Err(anyhow!(
+            "The downloaded chunks of file [{:?}] make up root [{:?}] instead of its fingerprint [{:?}].",
+            file_key,
+            file_data.get_root(),
+            file_metadata.fingerprint()
+        ));
+    }
+
+    Ok(file_data)
+}
+
+/// Fetches the chunks with `chunk_ids` of the file with `file_metadata` from the first of
+/// `sources` that sends them with a valid proof.
+async fn fetch_verified_chunks<S: ChunkSource>(
+    sources: &[S],
+    file_metadata: &FileMetadata,
+    chunk_ids: &HashSet<ChunkId>,
+) -> anyhow::Result<Vec<(ChunkId, Chunk)>> {
+    let file_key = file_metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+
+    for source in sources {
+        for attempt in 0..=DOWNLOAD_RETRY_ATTEMPTS {
+            let file_key_proof = match source.fetch_chunks(file_key, chunk_ids.clone()).await {
+                Ok(file_key_proof) => file_key_proof,
+                Err(e) => {
+                    warn!(target: LOG_TARGET, "Download attempt {}/{} of chunks of file [{:?}] from {} failed: {:?}", attempt + 1, DOWNLOAD_RETRY_ATTEMPTS + 1, file_key, source, e);
+                    continue;
+                }
+            };
+
+            match verify_chunks(file_metadata, chunk_ids, file_key_proof) {
+                Ok(chunks) => return Ok(chunks),
+                Err(e) => {
+                    // Retrying won't make a provider send valid chunks.
+                    warn!(target: LOG_TARGET, "Chunks of file [{:?}] sent by {} are invalid: {:?}", file_key, source, e);
+                    break;
+                }
+            }
+        }
+    }
+
+    Err(anyhow!(
+        "Failed to download chunks {:?} of file [{:?}] from any of the {} provider(s)",
+        chunk_ids.iter().map(|id| id.as_u64()).collect::<Vec<_>>(),
+        file_key,
+        sources.len()
+    ))
+}
+
+/// Verifies that `file_key_proof` proves exactly the chunks with `chunk_ids` of the file with
+/// `file_metadata`, with the expected sizes, returning them.
+fn verify_chunks(
+    file_metadata: &FileMetadata,
+    chunk_ids: &HashSet<ChunkId>,
+    file_key_proof: FileKeyProof,
+) -> anyhow::Result<Vec<(ChunkId, Chunk)>> {
+    if &file_key_proof.file_metadata != file_metadata {
+        return Err(anyhow!(
+            "Proof is for another file, with fingerprint {:?}",
+            file_key_proof.file_metadata.fingerprint()
+        ));
+    }
+
+    let proven = file_key_proof
+        .proven::<StorageProofsMerkleTrieLayout>()
+        .map_err(|e| anyhow!("Failed to get proven data: {:?}", e))?;
+
+    let proven_ids: HashSet<ChunkId> = proven.iter().map(|leaf| leaf.key).collect();
+    if &proven_ids != chunk_ids {
+        return Err(anyhow!(
+            "Expected {} proven chunks but got {}",
+            chunk_ids.len(),
+            proven_ids.len()
+        ));
+    }
+
+    proven
+        .into_iter()
+        .map(|leaf| {
+            if !file_metadata.is_valid_chunk_size(leaf.key.as_u64(), leaf.data.len()) {
+                return Err(anyhow!(
+                    "Invalid size {} for chunk {}",
+                    leaf.data.len(),
+                    leaf.key.as_u64()
+                ));
+            }
+            Ok((leaf.key, leaf.data))
+        })
+        .collect()
+}
+
+/// Replaces the local copy of the file with `file_key`, if any, with the verified `file_data`,
+/// and seals it.
+fn replace_local_copy<FL>(
+    file_storage: &mut FL,
+    file_key: H256,
+    file_metadata: FileMetadata,
+    file_data: FL::FileDataTrie,
+) -> anyhow::Result<()>
+where
+    FL: FileStorage<StorageProofsMerkleTrieLayout>,
+{
+    let has_local_copy = file_storage
+        .get_metadata(&file_key)
+        .map_err(|e| {
+            anyhow!(
+                "Failed to get the local metadata of file [{:?}]: {:?}",
+                file_key,
+                e
+            )
+        })?
+        .is_some();
+
+    if has_local_copy {
+        file_storage.atomic_delete_and_insert(file_key, file_key, file_metadata, file_data)
+    } else {
+        file_storage.insert_file_with_data(file_key, file_metadata, file_data)
+    }
+    .map_err(|e| {
+        anyhow!(
+            "Failed to replace the local copy of file [{:?}]: {:?}",
+            file_key,
+            e
+        )
+    })?;
+
+    file_storage
+        .seal_file(&file_key)
+        .map_err(|e| anyhow!("Failed to seal repaired file [{:?}]: {:?}", file_key, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use shc_common::types::FILE_CHUNK_SIZE;
+    use shc_file_manager::in_memory::InMemoryFileStorage;
+    use sp_runtime::AccountId32;
+
+    use super::*;
+    use crate::services::file_repair::FileRepairs;
+
+    type FileStorageT = InMemoryFileStorage<StorageProofsMerkleTrieLayout>;
+
+    /// Number of chunks of the test files, spanning several download batches.
+    const CHUNKS_COUNT: u64 = 2 * MAX_CHUNKS_PER_REQUEST as u64 + 3;
+
+    /// Another provider, which sends the chunks of the file with `served_file_key` whatever the
+    /// file requested.
+    struct OtherProvider {
+        name: &'static str,
+        file_storage: Arc<RwLock<FileStorageT>>,
+        served_file_key: Option<H256>,
+    }
+
+    impl fmt::Display for OtherProvider {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.name)
+        }
+    }
+
+    #[async_trait]
+    impl ChunkSource for OtherProvider {
+        async fn fetch_chunks(
+            &self,
+            file_key: H256,
+            chunk_ids: HashSet<ChunkId>,
+        ) -> anyhow::Result<FileKeyProof> {
+            let file_key = self.served_file_key.unwrap_or(file_key);
+            self.file_storage
+                .read()
+                .await
+                .generate_proof(&file_key, &chunk_ids)
+                .map_err(|e| anyhow!("Failed to generate proof: {:?}", e))
+        }
+    }
+
+    fn chunk(seed: u8, chunk_id: u64) -> Chunk {
+        let size = if chunk_id == CHUNKS_COUNT - 1 {
+            FILE_CHUNK_SIZE / 2
+        } else {
+            FILE_CHUNK_SIZE
+        };
+        vec![seed.wrapping_add(chunk_id as u8); size as usize]
+    }
+
+    /// Inserts a complete file in `file_storage`, whose chunks are derived from `seed`.
+    fn insert_file(file_storage: &mut FileStorageT, seed: u8) -> FileMetadata {
+        let mut file_trie = file_storage.new_file_data_trie();
+        for chunk_id in 0..CHUNKS_COUNT {
+            file_trie
+                .write_chunk(&ChunkId::new(chunk_id), &chunk(seed, chunk_id))
+                .unwrap();
+        }
+
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            vec![seed],
+            (CHUNKS_COUNT - 1) * FILE_CHUNK_SIZE + FILE_CHUNK_SIZE / 2,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+
+        let file_key = file_metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+        file_storage
+            .insert_file_with_data(file_key, file_metadata.clone(), file_trie)
+            .unwrap();
+        file_metadata
+    }
+
+    /// A provider storing the files with `seeds`, returning it along with their metadata.
+    fn other_provider(name: &'static str, seeds: &[u8]) -> (OtherProvider, Vec<FileMetadata>) {
+        let mut file_storage = FileStorageT::new();
+        let files = seeds
+            .iter()
+            .map(|seed| insert_file(&mut file_storage, *seed))
+            .collect();
+        let provider = OtherProvider {
+            name,
+            file_storage: Arc::new(RwLock::new(file_storage)),
+            served_file_key: None,
+        };
+        (provider, files)
+    }
+
+    /// Repairs the file with `file_metadata` in `file_storage` from `sources`.
+    async fn repair<S: ChunkSource>(
+        file_storage: &RwLock<FileStorageT>,
+        file_metadata: &FileMetadata,
+        sources: &[S],
+    ) -> anyhow::Result<FileRepairProgress> {
+        let file_key = file_metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+        let repairs = FileRepairs::default();
+        let repair = repairs.start(file_key).await.unwrap();
+
+        let file_data =
+            download_verified_file(file_storage, file_metadata, sources, &repair).await?;
+        replace_local_copy(
+            &mut *file_storage.write().await,
+            file_key,
+            file_metadata.clone(),
+            file_data,
+        )?;
+
+        Ok(repairs.progress(&file_key).unwrap())
+    }
+
+    fn assert_file_is_intact(file_storage: &FileStorageT, file_metadata: &FileMetadata, seed: u8) {
+        let file_key = file_metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+        assert!(file_storage.is_file_complete(&file_key).unwrap());
+        for chunk_id in 0..CHUNKS_COUNT {
+            assert_eq!(
+                file_storage
+                    .get_chunk(&file_key, &ChunkId::new(chunk_id))
+                    .unwrap(),
+                chunk(seed, chunk_id)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_file_is_repaired_from_another_provider() {
+        let (source, files) = other_provider("msp", &[1]);
+        let file_storage = RwLock::new(FileStorageT::new());
+
+        let progress = repair(&file_storage, &files[0], &[source]).await.unwrap();
+
+        assert_eq!(progress.chunks_repaired, CHUNKS_COUNT);
+        assert_eq!(progress.percentage(), 100.0);
+        assert_file_is_intact(&*file_storage.read().await, &files[0], 1);
+    }
+
+    #[tokio::test]
+    async fn corrupt_local_copy_is_replaced() {
+        let (source, files) = other_provider("msp", &[1]);
+        let file_key = files[0].file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+
+        // The local copy lost most of its chunks.
+        let mut local_file_storage = FileStorageT::new();
+        local_file_storage
+            .insert_file(file_key, files[0].clone())
+            .unwrap();
+        local_file_storage
+            .write_chunk(&file_key, &ChunkId::new(0), &chunk(1, 0))
+            .unwrap();
+        let file_storage = RwLock::new(local_file_storage);
+
+        repair(&file_storage, &files[0], &[source]).await.unwrap();
+
+        assert_file_is_intact(&*file_storage.read().await, &files[0], 1);
+    }
+
+    /// Inserts in `file_storage` the file with `file_metadata` whose chunks are derived from
+    /// `seed`, with a byte of the chunk with `corrupt_chunk_id` flipped.
+    fn insert_corrupt_file(
+        file_storage: &mut FileStorageT,
+        file_metadata: &FileMetadata,
+        seed: u8,
+        corrupt_chunk_id: u64,
+    ) {
+        let mut file_trie = file_storage.new_file_data_trie();
+        for chunk_id in 0..CHUNKS_COUNT {
+            let mut chunk = chunk(seed, chunk_id);
+            if chunk_id == corrupt_chunk_id {
+                chunk[7] ^= 1;
+            }
+            file_trie
+                .write_chunk(&ChunkId::new(chunk_id), &chunk)
+                .unwrap();
+        }
+
+        let file_key = file_metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+        file_storage
+            .insert_file_with_data(file_key, file_metadata.clone(), file_trie)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn file_with_a_flipped_byte_is_detected_and_repaired() {
+        let (source, files) = other_provider("msp", &[1]);
+        let file_key = files[0].file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+
+        // All the chunks of the local copy are stored, but one of them has a flipped byte.
+        let mut local_file_storage = FileStorageT::new();
+        insert_corrupt_file(
+            &mut local_file_storage,
+            &files[0],
+            1,
+            MAX_CHUNKS_PER_REQUEST as u64,
+        );
+        let file_storage = Arc::new(RwLock::new(local_file_storage));
+        assert!(!is_intact(&file_storage, file_key).await.unwrap());
+
+        let repairs = FileRepairs::default();
+        let repair = repairs.start(file_key).await.unwrap();
+        let outcome = repair_corrupt_file(
+            &file_storage,
+            &files[0],
+            async { Ok(vec![source]) },
+            &repair,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(outcome, FileRepairOutcome::Repaired);
+        assert_eq!(
+            repairs.progress(&file_key).unwrap().chunks_repaired,
+            CHUNKS_COUNT
+        );
+        assert_file_is_intact(&*file_storage.read().await, &files[0], 1);
+        assert!(is_intact(&file_storage, file_key).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn intact_file_is_left_untouched() {
+        let mut local_file_storage = FileStorageT::new();
+        let file_metadata = insert_file(&mut local_file_storage, 1);
+        let file_key = file_metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+        let file_storage = Arc::new(RwLock::new(local_file_storage));
+
+        let repairs = FileRepairs::default();
+        let repair = repairs.start(file_key).await.unwrap();
+        let outcome = repair_corrupt_file::<_, OtherProvider>(
+            &file_storage,
+            &file_metadata,
+            async { panic!("The providers storing an intact file are not looked up") },
+            &repair,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(outcome, FileRepairOutcome::Intact);
+        assert_file_is_intact(&*file_storage.read().await, &file_metadata, 1);
+    }
+
+    #[tokio::test]
+    async fn corrupt_file_is_kept_if_it_cannot_be_repaired() {
+        let (_, files) = other_provider("msp", &[1]);
+        let file_key = files[0].file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+
+        let mut local_file_storage = FileStorageT::new();
+        insert_corrupt_file(&mut local_file_storage, &files[0], 1, 0);
+        let file_storage = Arc::new(RwLock::new(local_file_storage));
+
+        let repairs = FileRepairs::default();
+        let repair = repairs.start(file_key).await.unwrap();
+        assert!(repair_corrupt_file::<_, OtherProvider>(
+            &file_storage,
+            &files[0],
+            async { Ok(Vec::new()) },
+            &repair,
+        )
+        .await
+        .is_err());
+
+        // The corrupt local copy is only dropped once replaced by a verified one.
+        assert!(file_storage.read().await.file_exists(&file_key).unwrap());
+    }
+
+    #[tokio::test]
+    async fn provider_sending_invalid_chunks_is_skipped() {
+        // The first provider answers with the chunks of another file.
+        let (mut bad_source, files) = other_provider("bad bsp", &[1, 2]);
+        bad_source.served_file_key =
+            Some(files[1].file_key::<HashT<StorageProofsMerkleTrieLayout>>());
+        let (good_source, _) = other_provider("good bsp", &[1]);
+
+        let file_storage = RwLock::new(FileStorageT::new());
+        assert!(repair(&file_storage, &files[0], &[bad_source])
+            .await
+            .is_err());
+        let file_key = files[0].file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+        assert!(file_storage
+            .read()
+            .await
+            .get_metadata(&file_key)
+            .unwrap()
+            .is_none());
+
+        let (mut bad_source, _) = other_provider("bad bsp", &[1, 2]);
+        bad_source.served_file_key =
+            Some(files[1].file_key::<HashT<StorageProofsMerkleTrieLayout>>());
+        repair(&file_storage, &files[0], &[bad_source, good_source])
+            .await
+            .unwrap();
+
+        assert_file_is_intact(&*file_storage.read().await, &files[0], 1);
+    }
+}
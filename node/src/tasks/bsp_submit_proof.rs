@@ -1,6 +1,14 @@
-use std::{collections::HashSet, future::Future, pin::Pin, sync::Arc, time::Duration};
+use std::{
+    collections::HashSet,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
 
 use anyhow::anyhow;
+use async_trait::async_trait;
+use futures::future::join_all;
 use sc_tracing::tracing::*;
 use shc_file_manager::traits::FileStorage;
 use shp_file_metadata::ChunkId;
@@ -18,15 +26,23 @@ use shc_blockchain_service::{
 use shc_common::{
     consts::CURRENT_FOREST_KEY,
     types::{
-        BlockNumber, CustomChallenge, FileKey, ForestRoot, KeyProof, KeyProofs,
+        BlockNumber, CustomChallenge, FileKey, FileMetadata, ForestRoot, KeyProof, KeyProofs,
         ProofsDealerProviderId, Proven, RandomnessOutput, StorageProof,
+        StorageProofsMerkleTrieLayout, StorageProviderId,
     },
 };
-use shc_forest_manager::traits::{ForestStorage, ForestStorageHandler};
+use shc_forest_manager::{
+    proof_cache::{ForestProofCache, DEFAULT_FOREST_PROOF_CACHE_SIZE},
+    traits::{ForestStorage, ForestStorageHandler},
+};
 
-use crate::services::{
-    handler::StorageHubHandler,
-    types::{BspForestStorageHandlerT, ShNodeType},
+use crate::{
+    services::{
+        handler::StorageHubHandler,
+        metrics::generate_proof_metered,
+        types::{BspForestStorageHandlerT, ShNodeType},
+    },
+    tasks::proof_generation::generate_proof_with_timeout,
 };
 
 const LOG_TARGET: &str = "bsp-submit-proof-task";
@@ -46,8 +62,9 @@ const MAX_PROOF_SUBMISSION_ATTEMPTS: u32 = 3;
 ///   - Triggered when the Blockchain Service detects that the Forest write lock has been released.
 ///   - Generates proofs for the queued challenges derived from the seed in the [`MultipleNewChallengeSeeds`] event.
 ///   - Constructs key proofs for each file key involved in the challenges.
+///   - Persists the checkpoint challenges with a remove mutation among the proven keys, before submitting.
 ///   - Submits the proofs to the runtime, with up to [`MAX_PROOF_SUBMISSION_ATTEMPTS`] retries on failure.
-///   - Applies any necessary mutations to the Forest Storage (but not the File Storage).
+///   - Once the proof is accepted, removes those keys from the Forest Storage (but not the File Storage).
 ///   - Verifies that the new Forest root matches the one recorded on-chain to ensure consistency.
 ///
 /// - **[`FinalisedTrieRemoveMutationsApplied`] Event:**
@@ -57,12 +74,18 @@ const MAX_PROOF_SUBMISSION_ATTEMPTS: u32 = 3;
 ///     - If the key is still present, logs a warning, as this may indicate that the key was re-added after deletion.
 ///     - If the key is absent from the Forest Storage, safely removes the corresponding file from the File Storage.
 ///   - Ensures that no residual file keys remain in the File Storage when they should have been deleted.
+///
+/// Checkpoint removals recorded before a crash are completed on startup by
+/// [`BspSubmitProofTask::recover_pending_checkpoint_removals`].
 pub struct BspSubmitProofTask<NT>
 where
     NT: ShNodeType,
     NT::FSH: BspForestStorageHandlerT,
 {
     storage_hub_handler: StorageHubHandler<NT>,
+    /// The Forest proofs generated by the clones of this task, reused when retrying to respond
+    /// to the same challenges against the same Forest root.
+    forest_proof_cache: Arc<Mutex<ForestProofCache<StorageProofsMerkleTrieLayout>>>,
 }
 
 impl<NT> Clone for BspSubmitProofTask<NT>
@@ -73,6 +96,7 @@ where
     fn clone(&self) -> BspSubmitProofTask<NT> {
         Self {
             storage_hub_handler: self.storage_hub_handler.clone(),
+            forest_proof_cache: self.forest_proof_cache.clone(),
         }
     }
 }
@@ -85,6 +109,9 @@ where
     pub fn new(storage_hub_handler: StorageHubHandler<NT>) -> Self {
         Self {
             storage_hub_handler,
+            forest_proof_cache: Arc::new(Mutex::new(ForestProofCache::new(
+                DEFAULT_FOREST_PROOF_CACHE_SIZE,
+            ))),
         }
     }
 }
@@ -129,9 +156,11 @@ where
 ///
 /// This task performs the following actions:
 /// - Generates proofs for the challenges.
+/// - Records the proven keys challenged with a remove mutation (see [`checkpoint_removals`]), so
+///   that their removal can be completed if the node crashes after the proof is accepted.
 /// - Constructs key proofs and submits the proof to the runtime.
 ///   - Retries up to [`MAX_PROOF_SUBMISSION_ATTEMPTS`] times if the submission fails.
-/// - Applies any necessary mutations to the Forest Storage (not the File Storage).
+/// - Removes the recorded keys from the Forest Storage in a single batch (not the File Storage).
 /// - Ensures the new Forest root matches the one on-chain.
 impl<NT> EventHandler<ProcessSubmitProofRequest> for BspSubmitProofTask<NT>
 where
@@ -150,6 +179,17 @@ where
             return Ok(());
         }
 
+        // A proof generated from a Forest diverged from its on-chain root would be rejected.
+        if self
+            .storage_hub_handler
+            .provider_config
+            .pause_proofs_on_forest_root_divergence
+            && self.storage_hub_handler.forest_root_health.is_diverged()
+        {
+            warn!(target: LOG_TARGET, "Local Forest root diverged from the on-chain root. Skipping proof submission until it is back in sync.");
+            return Ok(());
+        }
+
         // Acquire Forest root write lock. This prevents other Forest-root-writing tasks from starting while we are processing this task.
         // That is until we release the lock gracefully with the `release_forest_root_write_lock` method, or `forest_root_write_lock` is dropped.
         let forest_root_write_tx = match event.forest_root_write_tx.lock().await.take() {
@@ -179,13 +219,53 @@ where
                 .await
                 .ok_or_else(|| anyhow!("CRITICAL❗️❗️ Failed to get forest storage."))?;
 
-            let p = fs
-                .read()
+            // Generate the proof from the snapshot of the current Forest root, which is reused
+            // by the other proofs generated against this same root.
+            // Fall back to the current Forest if its root changed in the meantime.
+            let current_forest_root = fs.read().await.root();
+            let fs = self
+                .storage_hub_handler
+                .forest_storage_handler
+                .snapshot_by_root(&current_forest_key, &current_forest_root)
                 .await
-                .generate_proof(event.data.forest_challenges.clone())
-                .map_err(|e| anyhow!("Failed to generate forest proof: {:?}", e))?;
+                .unwrap_or(fs);
 
-            p
+            // A retry of this same request reuses the proof generated by the previous attempt.
+            let cached_proof = self
+                .forest_proof_cache
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(&current_forest_root, &event.data.forest_challenges);
+            match cached_proof {
+                Some(proof) => {
+                    debug!(target: LOG_TARGET, "Reusing the cached Forest proof for tick [{:?}]", event.data.tick);
+                    proof
+                }
+                None => {
+                    let forest_challenges = event.data.forest_challenges.clone();
+                    let proof =
+                        generate_proof_with_timeout(
+                            self.proof_generation_timeout(),
+                            move |cancellation| {
+                                cancellation
+                                    .blocking_read(&fs)?
+                                    .generate_proof(forest_challenges)
+                                    .map_err(|e| anyhow!("Failed to generate forest proof: {:?}", e))
+                            },
+                        )
+                        .await
+                        .map_err(|e| {
+                            error!(target: LOG_TARGET, "Giving up on submitting proof for tick [{:?}]: {:?}", event.data.tick, e);
+                            e
+                        })?;
+
+                    self.forest_proof_cache
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .insert(&event.data.forest_challenges, proof.clone());
+                    proof
+                }
+            }
         };
 
         // Get the keys that were proven.
@@ -210,25 +290,26 @@ where
             }
         }
 
-        // Construct key challenges and generate key proofs for them.
-        let mut key_proofs = KeyProofs::new();
-        for file_key in &proven_keys {
-            // If the file key is a checkpoint challenge for a file deletion, we should NOT generate a key proof for it.
-            let should_generate_key_proof =
+        // If the file key is a checkpoint challenge for a file deletion, we should NOT generate a key proof for it.
+        let file_keys_to_prove = proven_keys
+            .iter()
+            .filter(|file_key| {
                 !event.data.checkpoint_challenges.contains(&CustomChallenge {
-                    key: *file_key,
+                    key: **file_key,
                     should_remove_key: true,
-                });
+                })
+            })
+            .copied()
+            .collect::<Vec<_>>();
 
-            if should_generate_key_proof {
-                // Generate the key proof for each file key.
-                let key_proof = self
-                    .generate_key_proof(*file_key, event.data.seed, event.data.provider_id)
-                    .await?;
+        // The file keys that will be removed from the Forest once this proof is accepted.
+        let checkpoint_removals =
+            checkpoint_removals(&event.data.checkpoint_challenges, &proven_keys);
 
-                key_proofs.insert(*file_key, key_proof);
-            };
-        }
+        // Construct key challenges and generate key proofs for them.
+        let key_proofs = self
+            .generate_key_proofs(file_keys_to_prove, event.data.seed, event.data.provider_id)
+            .await?;
 
         // Construct full proof.
         let proof = StorageProof {
@@ -287,6 +368,15 @@ where
             )) as Pin<Box<dyn Future<Output = bool> + Send>>
         };
 
+        // Record the removals before submitting, so that they can be completed on startup if the
+        // node crashes after the proof is accepted but before the keys are removed.
+        if !checkpoint_removals.is_empty() {
+            self.storage_hub_handler
+                .blockchain
+                .record_pending_checkpoint_removals(checkpoint_removals.clone(), event.data.tick)
+                .await?;
+        }
+
         // Attempt to submit the extrinsic with retries and tip increase.
         self.storage_hub_handler
             .blockchain
@@ -310,6 +400,20 @@ where
             })?;
 
         trace!(target: LOG_TARGET, "Proof submitted successfully");
+        if let Some(telemetry) = &self.storage_hub_handler.telemetry {
+            telemetry.proof_submitted(self.storage_hub_handler.upload_progress.in_progress_count());
+        }
+
+        // The proof was accepted, so the keys challenged with a remove mutation are out of the
+        // on-chain Forest. Their file data is deleted once the removal is finalised.
+        if !checkpoint_removals.is_empty() {
+            if let Err(e) = self
+                .apply_checkpoint_removals(event.data.provider_id, &checkpoint_removals)
+                .await
+            {
+                error!(target: LOG_TARGET, "Failed to remove checkpoint challenged file keys from the Forest: {:?}", e);
+            }
+        }
 
         // Release the forest root write "lock" and finish the task.
         self.storage_hub_handler
@@ -349,6 +453,7 @@ where
         );
 
         // For each mutation...
+        let mut removed_file_keys = Vec::new();
         for mutation in event.mutations {
             let file_key = FileKey::from(mutation.0);
 
@@ -370,16 +475,25 @@ where
             } else {
                 // If file key is not in Forest, we can now safely remove it from the File Storage.
                 self.remove_file_from_file_storage(&file_key.into()).await?;
+                removed_file_keys.push(file_key.into());
             }
         }
 
+        // The removals of checkpoint challenged keys among them are complete.
+        if !removed_file_keys.is_empty() {
+            self.storage_hub_handler
+                .blockchain
+                .clear_pending_checkpoint_removals(removed_file_keys)
+                .await?;
+        }
+
         Ok(())
     }
 }
 
 impl<NT> BspSubmitProofTask<NT>
 where
-    NT: ShNodeType,
+    NT: ShNodeType + 'static,
     NT::FSH: BspForestStorageHandlerT,
 {
     async fn queue_submit_proof_request(
@@ -390,19 +504,20 @@ where
     ) -> anyhow::Result<()> {
         trace!(target: LOG_TARGET, "Queueing submit proof request for provider [{:?}] with tick [{:?}] and seed [{:?}]", provider_id, tick, seed);
 
-        // Derive forest challenges from seed.
-        let mut forest_challenges = self
-            .derive_forest_challenges_from_seed(seed, provider_id)
-            .await?;
-        trace!(target: LOG_TARGET, "Forest challenges to respond to: {:?}", forest_challenges);
-
         // Check if there are checkpoint challenges since last tick this provider submitted a proof for.
-        // If so, this will add them to the forest challenges.
-        let checkpoint_challenges = self
-            .add_checkpoint_challenges_to_forest_challenges(provider_id, &mut forest_challenges)
-            .await?;
+        let checkpoint_challenges = self.query_checkpoint_challenges(provider_id).await?;
         trace!(target: LOG_TARGET, "Checkpoint challenges to respond to: {:?}", checkpoint_challenges);
 
+        // Derive forest challenges from seed, adding the checkpoint challenges to them.
+        let forest_challenges = derive_forest_challenges(
+            &self.storage_hub_handler.blockchain,
+            seed,
+            provider_id,
+            &checkpoint_challenges,
+        )
+        .await?;
+        trace!(target: LOG_TARGET, "Forest challenges to respond to: {:?}", forest_challenges);
+
         self.storage_hub_handler
             .blockchain
             .queue_submit_proof_request(SubmitProofRequest::new(
@@ -417,22 +532,11 @@ where
         Ok(())
     }
 
-    async fn derive_forest_challenges_from_seed(
+    /// Queries the checkpoint challenges issued since the last tick this provider submitted a
+    /// proof for, if any.
+    async fn query_checkpoint_challenges(
         &self,
-        seed: RandomnessOutput,
         provider_id: ProofsDealerProviderId,
-    ) -> anyhow::Result<Vec<H256>> {
-        Ok(self
-            .storage_hub_handler
-            .blockchain
-            .query_forest_challenges_from_seed(seed, provider_id)
-            .await?)
-    }
-
-    async fn add_checkpoint_challenges_to_forest_challenges(
-        &self,
-        provider_id: ProofsDealerProviderId,
-        forest_challenges: &mut Vec<H256>,
     ) -> anyhow::Result<Vec<CustomChallenge>> {
         let last_tick_provider_submitted_proof_for = self
             .storage_hub_handler
@@ -471,13 +575,6 @@ where
                 .await
                 .map_err(|e| anyhow!("Failed to query last checkpoint challenges: {:?}", e))?;
 
-            // Add the checkpoint challenges to the forest challenges.
-            forest_challenges.extend(
-                checkpoint_challenges
-                    .iter()
-                    .map(|custom_challenge| custom_challenge.key),
-            );
-
             // Return the checkpoint challenges.
             Ok(checkpoint_challenges)
         } else {
@@ -507,12 +604,89 @@ where
         Ok(())
     }
 
-    async fn generate_key_proof(
+    /// Generates the key proofs for all `file_keys`.
+    ///
+    /// The chunks to prove for each file are derived from the challenges first. Then the proof for
+    /// each file is generated in its own blocking task, since generating a proof traverses the
+    /// file's trie in the File Storage, and the traversals for different files are independent.
+    async fn generate_key_proofs(
+        &self,
+        file_keys: Vec<H256>,
+        seed: RandomnessOutput,
+        provider_id: ProofsDealerProviderId,
+    ) -> anyhow::Result<KeyProofs> {
+        let proof_generation_timeout = self.proof_generation_timeout();
+        let mut proof_tasks = Vec::with_capacity(file_keys.len());
+        for file_key in file_keys {
+            let (chunks_to_prove, challenge_count) = self
+                .get_chunks_to_prove(file_key, seed, provider_id)
+                .await?;
+
+            let file_storage = self.storage_hub_handler.file_storage.clone();
+            let metrics = self.storage_hub_handler.metrics.clone();
+            proof_tasks.push(generate_proof_with_timeout(
+                proof_generation_timeout,
+                move |cancellation| {
+                    // Construct file key proofs for the challenges.
+                    let file_key_proof = generate_proof_metered(
+                        metrics.as_ref(),
+                        &*cancellation.blocking_read(&file_storage)?,
+                        &file_key,
+                        &chunks_to_prove,
+                    )
+                    .map_err(|e| {
+                        anyhow!("File is not in storage, or proof does not exist: {:?}", e)
+                    })?;
+
+                    Ok((
+                        file_key,
+                        KeyProof {
+                            proof: file_key_proof,
+                            challenge_count,
+                        },
+                    ))
+                },
+            ));
+        }
+
+        // Wait for all proofs to be generated.
+        let results = join_all(proof_tasks).await;
+
+        let mut key_proofs = KeyProofs::new();
+        let mut failed_proofs = 0;
+        for result in results {
+            match result {
+                Ok((file_key, key_proof)) => {
+                    key_proofs.insert(file_key, key_proof);
+                }
+                Err(e) => {
+                    error!(target: LOG_TARGET, "Failed to generate key proof: {:?}", e);
+                    failed_proofs += 1;
+                }
+            }
+        }
+
+        // A proof missing any of the key proofs would be rejected by the runtime.
+        if failed_proofs > 0 {
+            return Err(anyhow!(
+                "Failed to generate {} out of {} key proofs",
+                failed_proofs,
+                failed_proofs + key_proofs.len()
+            ));
+        }
+
+        Ok(key_proofs)
+    }
+
+    /// Derives the chunks to prove for `file_key` from the challenges generated with `seed`.
+    ///
+    /// Returns the chunk IDs to prove along with the number of challenges for the file.
+    async fn get_chunks_to_prove(
         &self,
         file_key: H256,
         seed: RandomnessOutput,
         provider_id: ProofsDealerProviderId,
-    ) -> anyhow::Result<KeyProof> {
+    ) -> anyhow::Result<(HashSet<ChunkId>, u32)> {
         // Get the metadata for the file.
         let read_file_storage = self.storage_hub_handler.file_storage.read().await;
         let metadata = read_file_storage
@@ -522,36 +696,149 @@ where
         // Release the file storage read lock as soon as possible.
         drop(read_file_storage);
 
-        // Calculate the number of challenges for this file.
-        let challenge_count = metadata.chunks_to_check();
-
-        // Generate the challenges for this file.
-        let file_key_challenges = self
+        // The number of challenges for this file is calculated using the file size to challenges
+        // ratio of the runtime, so that changes to it are picked up without recompiling the node.
+        let file_size_to_challenges = self
             .storage_hub_handler
             .blockchain
-            .query_challenges_from_seed(seed, provider_id, challenge_count)
+            .query_file_size_to_challenges()
             .await?;
 
-        // Convert the challenges to chunk IDs.
-        let chunks_count = metadata.chunks_count();
-        let chunks_to_prove = file_key_challenges
-            .iter()
-            .map(|challenge| ChunkId::from_challenge(challenge.as_ref(), chunks_count))
+        derive_chunks_to_prove(
+            &self.storage_hub_handler.blockchain,
+            &metadata,
+            file_size_to_challenges,
+            seed,
+            provider_id,
+        )
+        .await
+    }
+
+    /// The time to wait for a proof to be generated before giving up on it.
+    fn proof_generation_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.storage_hub_handler
+                .provider_config
+                .proof_generation_timeout,
+        )
+    }
+
+    /// Removes `file_keys` from the Forest in a single batch, after the proof answering the
+    /// checkpoint challenges that removed them was accepted, and checks the new local Forest root
+    /// against the on-chain one.
+    ///
+    /// Keys no longer in the Forest (i.e. whose mutation was already applied by the Blockchain
+    /// Service) are skipped.
+    async fn apply_checkpoint_removals(
+        &self,
+        provider_id: ProofsDealerProviderId,
+        file_keys: &[H256],
+    ) -> anyhow::Result<()> {
+        let current_forest_key = CURRENT_FOREST_KEY.to_vec();
+        let fs = self
+            .storage_hub_handler
+            .forest_storage_handler
+            .get(&current_forest_key)
+            .await
+            .ok_or_else(|| anyhow!("CRITICAL❗️❗️ Failed to get forest storage."))?;
+
+        let removed = remove_file_keys_from_forest(&mut *fs.write().await, file_keys)?;
+        debug!(target: LOG_TARGET, "Removed checkpoint challenged file keys from the Forest: {:?}", removed);
+
+        let local_root = fs.read().await.root();
+        let on_chain_root = self
+            .storage_hub_handler
+            .blockchain
+            .query_provider_forest_root(provider_id)
+            .await
+            .map_err(|e| anyhow!("Failed to query on-chain Forest root: {:?}", e))?;
+        if local_root != on_chain_root {
+            error!(target: LOG_TARGET, "Local Forest root [{:?}] does not match the on-chain root [{:?}] after removing checkpoint challenged file keys", local_root, on_chain_root);
+            return Err(anyhow!(
+                "Forest root mismatch after removing checkpoint challenged file keys"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Completes the checkpoint removals recorded before the node stopped.
+    ///
+    /// A removal whose proof was accepted on-chain (i.e. for a tick up to the last one this BSP
+    /// submitted a proof for) is removed from the Forest, and its file data deleted. The record
+    /// of any other removal is dropped, since its proof never made it on-chain and the checkpoint
+    /// challenge will be answered again.
+    pub async fn recover_pending_checkpoint_removals(self) {
+        if let Err(e) = self.try_recover_pending_checkpoint_removals().await {
+            error!(target: LOG_TARGET, "Failed to recover pending checkpoint removals: {:?}", e);
+        }
+    }
+
+    async fn try_recover_pending_checkpoint_removals(&self) -> anyhow::Result<()> {
+        let pending = self
+            .storage_hub_handler
+            .blockchain
+            .query_pending_checkpoint_removals()
+            .await;
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let provider_id = match self
+            .storage_hub_handler
+            .blockchain
+            .query_storage_provider_id(None)
+            .await?
+        {
+            Some(StorageProviderId::BackupStorageProvider(id)) => id,
+            _ => return Err(anyhow!("This node is not managing a BSP")),
+        };
+        let last_tick_proven = self
+            .storage_hub_handler
+            .blockchain
+            .query_last_tick_provider_submitted_proof(provider_id)
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to query last tick provider submitted proof: {:?}",
+                    e
+                )
+            })?;
+
+        let (accepted, not_accepted): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .partition(|(_, tick)| *tick <= last_tick_proven);
+        let accepted = accepted
+            .into_iter()
+            .map(|(file_key, _)| file_key)
+            .collect::<Vec<_>>();
+        let not_accepted = not_accepted
+            .into_iter()
+            .map(|(file_key, _)| file_key)
             .collect::<Vec<_>>();
+        info!(target: LOG_TARGET, "Recovering {} pending checkpoint removals, dropping {} whose proof was not accepted", accepted.len(), not_accepted.len());
 
-        // Construct file key proofs for the challenges.
-        let read_file_storage = self.storage_hub_handler.file_storage.read().await;
-        let file_key_proof = read_file_storage
-            .generate_proof(&file_key, &HashSet::from_iter(chunks_to_prove))
-            .map_err(|e| anyhow!("File is not in storage, or proof does not exist: {:?}", e))?;
-        // Release the file storage read lock as soon as possible.
-        drop(read_file_storage);
+        if !not_accepted.is_empty() {
+            self.storage_hub_handler
+                .blockchain
+                .clear_pending_checkpoint_removals(not_accepted)
+                .await?;
+        }
+        if accepted.is_empty() {
+            return Ok(());
+        }
 
-        // Return the key proof.
-        Ok(KeyProof {
-            proof: file_key_proof,
-            challenge_count,
-        })
+        self.apply_checkpoint_removals(provider_id, &accepted)
+            .await?;
+        for file_key in &accepted {
+            self.remove_file_from_file_storage(file_key).await?;
+        }
+        self.storage_hub_handler
+            .blockchain
+            .clear_pending_checkpoint_removals(accepted)
+            .await?;
+
+        Ok(())
     }
 
     async fn remove_file_from_file_storage(&self, file_key: &H256) -> anyhow::Result<()> {
@@ -623,3 +910,337 @@ where
         true
     }
 }
+
+/// The source of the challenges that a Provider has to answer, derived from an on-chain
+/// [`RandomnessOutput`] seed.
+///
+/// Implemented by the Blockchain Service, which derives them with runtime API calls, and by
+/// fixed challenge sets in tests.
+#[async_trait]
+pub(crate) trait ChallengeSource: Send + Sync {
+    /// Derives the challenges to the Forest of `provider_id` from `seed`.
+    async fn forest_challenges(
+        &self,
+        seed: RandomnessOutput,
+        provider_id: ProofsDealerProviderId,
+    ) -> anyhow::Result<Vec<H256>>;
+
+    /// Derives `count` challenges to the chunks of a file of `provider_id` from `seed`.
+    async fn file_key_challenges(
+        &self,
+        seed: RandomnessOutput,
+        provider_id: ProofsDealerProviderId,
+        count: u32,
+    ) -> anyhow::Result<Vec<H256>>;
+}
+
+#[async_trait]
+impl<FSH> ChallengeSource for ActorHandle<BlockchainService<FSH>>
+where
+    FSH: ForestStorageHandler + Clone + Send + Sync + 'static,
+{
+    async fn forest_challenges(
+        &self,
+        seed: RandomnessOutput,
+        provider_id: ProofsDealerProviderId,
+    ) -> anyhow::Result<Vec<H256>> {
+        self.query_forest_challenges_from_seed(seed, provider_id)
+            .await
+            .map_err(|e| anyhow!("Failed to query forest challenges from seed: {:?}", e))
+    }
+
+    async fn file_key_challenges(
+        &self,
+        seed: RandomnessOutput,
+        provider_id: ProofsDealerProviderId,
+        count: u32,
+    ) -> anyhow::Result<Vec<H256>> {
+        self.query_challenges_from_seed(seed, provider_id, count)
+            .await
+            .map_err(|e| anyhow!("Failed to query challenges from seed: {:?}", e))
+    }
+}
+
+/// Derives the Forest challenges to answer for `seed`, followed by the keys of the
+/// `checkpoint_challenges`.
+async fn derive_forest_challenges(
+    challenges: &impl ChallengeSource,
+    seed: RandomnessOutput,
+    provider_id: ProofsDealerProviderId,
+    checkpoint_challenges: &[CustomChallenge],
+) -> anyhow::Result<Vec<H256>> {
+    let mut forest_challenges = challenges.forest_challenges(seed, provider_id).await?;
+    forest_challenges.extend(
+        checkpoint_challenges
+            .iter()
+            .map(|custom_challenge| custom_challenge.key),
+    );
+
+    Ok(forest_challenges)
+}
+
+/// Derives the chunks to prove of the file with `metadata` from the challenges generated with
+/// `seed`, with one challenge for every `file_size_to_challenges` bytes of the file.
+///
+/// Returns the chunk IDs to prove along with the number of challenges for the file.
+async fn derive_chunks_to_prove(
+    challenges: &impl ChallengeSource,
+    metadata: &FileMetadata,
+    file_size_to_challenges: u64,
+    seed: RandomnessOutput,
+    provider_id: ProofsDealerProviderId,
+) -> anyhow::Result<(HashSet<ChunkId>, u32)> {
+    let challenge_count = metadata.chunks_to_check_with(file_size_to_challenges);
+
+    // Generate the challenges for this file.
+    let file_key_challenges = challenges
+        .file_key_challenges(seed, provider_id, challenge_count)
+        .await?;
+
+    // Convert the challenges to chunk IDs, only considering the challenges for this file.
+    let chunks_count = metadata.chunks_count();
+    let chunks_to_prove = file_key_challenges
+        .iter()
+        .take(challenge_count as usize)
+        .map(|challenge| ChunkId::from_challenge(challenge.as_ref(), chunks_count))
+        .collect::<HashSet<_>>();
+
+    Ok((chunks_to_prove, challenge_count))
+}
+
+/// The proven file keys that are challenged with a remove mutation in `checkpoint_challenges`.
+///
+/// These are the keys that the runtime removes from the Forest once the proof is accepted, so
+/// they have to be removed from the local Forest as well.
+fn checkpoint_removals(
+    checkpoint_challenges: &[CustomChallenge],
+    proven_keys: &[H256],
+) -> Vec<H256> {
+    checkpoint_challenges
+        .iter()
+        .filter(|challenge| challenge.should_remove_key && proven_keys.contains(&challenge.key))
+        .map(|challenge| challenge.key)
+        .collect()
+}
+
+/// Removes the `file_keys` that are in the Forest from it.
+///
+/// Returns the file keys that were removed.
+fn remove_file_keys_from_forest<FS>(
+    forest_storage: &mut FS,
+    file_keys: &[H256],
+) -> anyhow::Result<Vec<H256>>
+where
+    FS: ForestStorage<StorageProofsMerkleTrieLayout>,
+{
+    let mut removed = Vec::new();
+    for file_key in file_keys {
+        let in_forest = forest_storage.contains_file_key(file_key).map_err(|e| {
+            anyhow!(
+                "Failed to check if file key {:?} is in Forest: {:?}",
+                file_key,
+                e
+            )
+        })?;
+        if !in_forest {
+            continue;
+        }
+
+        forest_storage.delete_file_key(file_key).map_err(|e| {
+            anyhow!(
+                "Failed to delete file_key {:?} from Forest: {:?}",
+                file_key,
+                e
+            )
+        })?;
+        removed.push(*file_key);
+    }
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shc_common::types::{Fingerprint, HashT, FILE_CHUNK_SIZE};
+    use shc_forest_manager::{in_memory::InMemoryForestStorage, recovery::forest_root_of};
+
+    /// A randomness source with a fixed set of challenges for a single seed.
+    struct FixedChallenges {
+        seed: RandomnessOutput,
+        forest_challenges: Vec<H256>,
+        file_key_challenges: Vec<H256>,
+    }
+
+    #[async_trait]
+    impl ChallengeSource for FixedChallenges {
+        async fn forest_challenges(
+            &self,
+            seed: RandomnessOutput,
+            _provider_id: ProofsDealerProviderId,
+        ) -> anyhow::Result<Vec<H256>> {
+            if seed != self.seed {
+                return Err(anyhow!("Unknown seed {:?}", seed));
+            }
+            Ok(self.forest_challenges.clone())
+        }
+
+        async fn file_key_challenges(
+            &self,
+            seed: RandomnessOutput,
+            _provider_id: ProofsDealerProviderId,
+            count: u32,
+        ) -> anyhow::Result<Vec<H256>> {
+            if seed != self.seed {
+                return Err(anyhow!("Unknown seed {:?}", seed));
+            }
+            Ok(self
+                .file_key_challenges
+                .iter()
+                .copied()
+                .cycle()
+                .take(count as usize)
+                .collect())
+        }
+    }
+
+    fn file_metadata(location: &str) -> FileMetadata {
+        FileMetadata::new(
+            [1u8; 32].to_vec(),
+            [2u8; 32].to_vec(),
+            location.as_bytes().to_vec(),
+            1024,
+            Fingerprint::default(),
+        )
+        .unwrap()
+    }
+
+    fn file_key(metadata: &FileMetadata) -> H256 {
+        metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>()
+    }
+
+    #[test]
+    fn checkpoint_challenge_with_a_remove_mutation_removes_only_its_key() {
+        let files = [
+            file_metadata("first"),
+            file_metadata("removed"),
+            file_metadata("last"),
+        ];
+        let mut forest_storage = InMemoryForestStorage::<StorageProofsMerkleTrieLayout>::new();
+        forest_storage.insert_files_metadata(&files).unwrap();
+
+        let checkpoint_challenges = [
+            CustomChallenge {
+                key: file_key(&files[0]),
+                should_remove_key: false,
+            },
+            CustomChallenge {
+                key: file_key(&files[1]),
+                should_remove_key: true,
+            },
+            CustomChallenge {
+                key: file_key(&files[2]),
+                should_remove_key: false,
+            },
+        ];
+        let proof = forest_storage
+            .generate_proof(checkpoint_challenges.iter().map(|c| c.key).collect())
+            .unwrap();
+        let proven_keys = proof
+            .proven
+            .iter()
+            .filter_map(|proven| match proven {
+                Proven::ExactKey(leaf) => Some(leaf.key),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let removals = checkpoint_removals(&checkpoint_challenges, &proven_keys);
+        assert_eq!(removals, vec![file_key(&files[1])]);
+
+        assert_eq!(
+            remove_file_keys_from_forest(&mut forest_storage, &removals).unwrap(),
+            removals
+        );
+        // Removing the keys again, e.g. when recovering after a crash, is a no-op.
+        assert!(remove_file_keys_from_forest(&mut forest_storage, &removals)
+            .unwrap()
+            .is_empty());
+
+        assert!(!forest_storage.contains_file_key(&removals[0]).unwrap());
+        assert_eq!(
+            forest_storage.root(),
+            forest_root_of::<StorageProofsMerkleTrieLayout>(&[files[0].clone(), files[2].clone()])
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn unproven_keys_are_not_removed() {
+        let challenge = CustomChallenge {
+            key: H256::repeat_byte(1),
+            should_remove_key: true,
+        };
+
+        assert!(checkpoint_removals(&[challenge], &[H256::repeat_byte(2)]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn challenges_are_derived_from_the_randomness_source() {
+        let seed = H256::repeat_byte(7);
+        let provider_id = H256::repeat_byte(9);
+        let checkpoint_key = H256::repeat_byte(3);
+        let challenges = FixedChallenges {
+            seed,
+            forest_challenges: vec![H256::repeat_byte(1), H256::repeat_byte(2)],
+            file_key_challenges: vec![H256::from_low_u64_be(5), H256::from_low_u64_be(6)],
+        };
+
+        let forest_challenges = derive_forest_challenges(
+            &challenges,
+            seed,
+            provider_id,
+            &[CustomChallenge {
+                key: checkpoint_key,
+                should_remove_key: false,
+            }],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            forest_challenges,
+            vec![H256::repeat_byte(1), H256::repeat_byte(2), checkpoint_key]
+        );
+
+        // A file of four chunks, with one challenge every two chunks.
+        let metadata = FileMetadata::new(
+            [1u8; 32].to_vec(),
+            [2u8; 32].to_vec(),
+            b"location".to_vec(),
+            4 * FILE_CHUNK_SIZE,
+            Fingerprint::default(),
+        )
+        .unwrap();
+        let (chunks_to_prove, challenge_count) = derive_chunks_to_prove(
+            &challenges,
+            &metadata,
+            2 * FILE_CHUNK_SIZE,
+            seed,
+            provider_id,
+        )
+        .await
+        .unwrap();
+        assert_eq!(challenge_count, 2);
+        assert_eq!(
+            chunks_to_prove,
+            HashSet::from([ChunkId::new(1), ChunkId::new(2)])
+        );
+
+        // Challenges are only derived for the seed of the round.
+        assert!(
+            derive_forest_challenges(&challenges, H256::repeat_byte(8), provider_id, &[])
+                .await
+                .is_err()
+        );
+    }
+}
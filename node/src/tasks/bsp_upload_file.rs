@@ -1,7 +1,11 @@
 use std::{
     collections::{HashMap, HashSet},
     str::FromStr,
-    time::Duration,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
 };
 
 use anyhow::anyhow;
@@ -15,26 +19,43 @@ use shc_actors_framework::event_bus::EventHandler;
 use shc_blockchain_service::{
     capacity_manager::CapacityRequestData,
     commands::BlockchainServiceInterface,
-    events::{NewStorageRequest, ProcessConfirmStoringRequest},
-    types::{ConfirmStoringRequest, RetryStrategy},
+    events::{
+        BspCapacityChanged, NewStorageRequest, ProcessConfirmStoringRequest, Reorg,
+        StorageRequestExpired, StorageRequestRevoked, UserWithoutFunds,
+    },
+    transaction::TransactionWatchCancellation,
+    types::{unix_timestamp_secs, ConfirmStoringRequest, RetryStrategy, WatchTransactionError},
 };
 use shc_common::{
     consts::CURRENT_FOREST_KEY,
     types::{
-        Balance, FileKey, FileKeyWithProof, FileMetadata, HashT, StorageProofsMerkleTrieLayout,
-        StorageProviderId, BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE,
+        Balance, Block, FileKey, FileKeyWithProof, FileMetadata, HashT, ProvenFileKeyError,
+        RejectedStorageRequestReason, StorageHubEventsVec, StorageProofsMerkleTrieLayout,
+        StorageProviderId, BATCH_CHUNK_FILE_TRANSFER_MAX_CHUNKS,
+        BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE,
     },
+    upload_progress::UploadState,
 };
-use shc_file_manager::traits::{FileStorage, FileStorageWriteError, FileStorageWriteOutcome};
+use shc_file_manager::traits::{FileStorage, FileStorageError, FileStorageWriteOutcome};
 use shc_file_transfer_service::{
-    commands::FileTransferServiceInterface, events::RemoteUploadRequest,
+    commands::FileTransferServiceInterface,
+    events::{DuplicateChunksDetected, RemoteUploadRequest},
 };
 use shc_forest_manager::traits::{ForestStorage, ForestStorageHandler};
-use storage_hub_runtime::MILLIUNIT;
-
-use crate::services::{
-    handler::StorageHubHandler,
-    types::{BspForestStorageHandlerT, ShNodeType},
+use storage_hub_runtime::{RuntimeEvent, MILLIUNIT};
+
+use crate::{
+    services::{
+        handler::StorageHubHandler,
+        metrics::{generate_proof_metered, write_chunk_metered, ChunkRejection},
+        types::{BspForestStorageHandlerT, ShNodeType},
+        upload_span::{upload_span, BSP_ROLE},
+    },
+    tasks::{
+        file_key_cleanup::FileKeyCleanup,
+        proof_generation::generate_partial_proof_with_timeout,
+        upload_failure::{UploadFailure, UploadFailureAction},
+    },
 };
 
 const LOG_TARGET: &str = "bsp-upload-file-task";
@@ -57,13 +78,25 @@ const MAX_CONFIRM_STORING_REQUEST_TIP: Balance = 500 * MILLIUNIT;
 /// - [`ProcessConfirmStoringRequest`] event: The third part of the flow. It is triggered by the
 ///   runtime when the BSP should construct a proof for the new file(s) and submit a confirm storing
 ///   extrinsic, waiting for it to be successfully included in a block.
+///
+/// Additionally, it listens to [`StorageRequestRevoked`] and [`StorageRequestExpired`] events to
+/// stop waiting for the volunteer for a file whose storage request is no longer open on-chain and
+/// discard the file if it was partially uploaded, and to
+/// [`UserWithoutFunds`] events to stop receiving files from users that can no longer pay for them.
+/// On a [`Reorg`], it volunteers again for the files whose volunteering might have been retracted.
+/// On a [`BspCapacityChanged`] event, it reports the new capacity to the telemetry endpoints.
 pub struct BspUploadFileTask<NT>
 where
     NT: ShNodeType,
     NT::FSH: BspForestStorageHandlerT,
 {
     storage_hub_handler: StorageHubHandler<NT>,
-    file_key_cleanup: Option<H256>,
+    /// Whether a `change_capacity` extrinsic submitted by one of the clones of this task is in
+    /// flight. See [`PendingCapacityChangeGuard`].
+    pending_capacity_change: Arc<AtomicBool>,
+    /// The watches of the volunteers for files that are in flight in any of the clones of this
+    /// task, cancelled when the storage request of the file is closed. See [`VolunteerWatchGuard`].
+    volunteer_watches: VolunteerWatches,
 }
 
 impl<NT> Clone for BspUploadFileTask<NT>
@@ -74,7 +107,8 @@ where
     fn clone(&self) -> BspUploadFileTask<NT> {
         Self {
             storage_hub_handler: self.storage_hub_handler.clone(),
-            file_key_cleanup: self.file_key_cleanup,
+            pending_capacity_change: self.pending_capacity_change.clone(),
+            volunteer_watches: self.volunteer_watches.clone(),
         }
     }
 }
@@ -87,7 +121,8 @@ where
     pub fn new(storage_hub_handler: StorageHubHandler<NT>) -> Self {
         Self {
             storage_hub_handler,
-            file_key_cleanup: None,
+            pending_capacity_change: Arc::new(AtomicBool::new(false)),
+            volunteer_watches: Default::default(),
         }
     }
 }
@@ -105,21 +140,30 @@ where
     NT::FSH: BspForestStorageHandlerT,
 {
     async fn handle_event(&mut self, event: NewStorageRequest) -> anyhow::Result<()> {
-        info!(
-            target: LOG_TARGET,
-            "Initiating BSP volunteer for file_key {:x}, location 0x{}, fingerprint {:x}",
-            event.file_key,
-            hex::encode(event.location.as_slice()),
-            event.fingerprint
-        );
+        let span = upload_span(event.file_key.into(), event.bucket_id, event.size, BSP_ROLE);
 
-        let result = self.handle_new_storage_request_event(event).await;
-        if result.is_err() {
-            if let Some(file_key) = &self.file_key_cleanup {
-                self.unvolunteer_file(*file_key).await;
-            }
+        async {
+            info!(
+                target: LOG_TARGET,
+                "Initiating BSP volunteer for file_key {:x}, location 0x{}, fingerprint {:x}",
+                event.file_key,
+                hex::encode(event.location.as_slice()),
+                event.fingerprint
+            );
+
+            let mut file_key_cleanup = FileKeyCleanup::default();
+            let result = self
+                .handle_new_storage_request_event(event, &mut file_key_cleanup)
+                .await;
+            file_key_cleanup
+                .clean_up_on_failure(result, |file_key| async move {
+                    self.unvolunteer_file(file_key).await;
+                    Ok(())
+                })
+                .await
         }
-        result
+        .instrument(span)
+        .await
     }
 }
 
@@ -133,59 +177,85 @@ where
     NT::FSH: BspForestStorageHandlerT,
 {
     async fn handle_event(&mut self, event: RemoteUploadRequest) -> anyhow::Result<()> {
-        trace!(target: LOG_TARGET, "Received remote upload request for file {:?} and peer {:?}", event.file_key, event.peer);
+        let span = self
+            .storage_hub_handler
+            .upload_spans
+            .get(&event.file_key.into());
 
-        let file_complete = match self.handle_remote_upload_request_event(event.clone()).await {
-            Ok(complete) => complete,
-            Err(e) => {
-                // Send error response through FileTransferService
+        async {
+            trace!(target: LOG_TARGET, "Received remote upload request for file {:?} and peer {:?}", event.file_key, event.peer);
+
+            let file_complete = match self.handle_remote_upload_request_event(event.clone()).await {
+                Ok(complete) => complete,
+                Err(e) => {
+                    // Send error response through FileTransferService
+                    if let Err(e) = self
+                        .storage_hub_handler
+                        .file_transfer
+                        .upload_response(false, event.request_id)
+                        .await
+                    {
+                        error!(target: LOG_TARGET, "Failed to send error response: {:?}", e);
+                    }
+                    return Err(e);
+                }
+            };
+
+            // Send completion status through FileTransferService
+            if let Err(e) = self
+                .storage_hub_handler
+                .file_transfer
+                .upload_response(file_complete, event.request_id)
+                .await
+            {
+                error!(target: LOG_TARGET, "Failed to send response: {:?}", e);
+            }
+
+            // Handle file completion if the entire file is uploaded
+            if file_complete {
                 if let Err(e) = self
                     .storage_hub_handler
                     .file_transfer
-                    .upload_response(false, event.request_id)
+                    .unregister_file(event.file_key)
                     .await
                 {
-                    error!(target: LOG_TARGET, "Failed to send error response: {:?}", e);
+                    error!(
+                        target: LOG_TARGET,
+                        "Failed to unregister file {:?} from file transfer service: {:?}",
+                        event.file_key,
+                        e
+                    );
                 }
-                return Err(e);
-            }
-        };
 
-        // Send completion status through FileTransferService
-        if let Err(e) = self
-            .storage_hub_handler
-            .file_transfer
-            .upload_response(file_complete, event.request_id)
-            .await
-        {
-            error!(target: LOG_TARGET, "Failed to send response: {:?}", e);
-        }
+                if let Some(telemetry) = &self.storage_hub_handler.telemetry {
+                    telemetry.file_stored(self.storage_hub_handler.upload_progress.in_progress_count());
+                }
 
-        // Handle file completion if the entire file is uploaded
-        if file_complete {
-            if let Err(e) = self
-                .storage_hub_handler
-                .file_transfer
-                .unregister_file(event.file_key)
-                .await
-            {
-                error!(
-                    target: LOG_TARGET,
-                    "Failed to unregister file {:?} from file transfer service: {:?}",
-                    event.file_key,
-                    e
-                );
+                self.storage_hub_handler
+                    .blockchain
+                    .queue_confirm_bsp_request(ConfirmStoringRequest::new(event.file_key.into()))
+                    .await?;
             }
 
-            self.storage_hub_handler
-                .blockchain
-                .queue_confirm_bsp_request(ConfirmStoringRequest {
-                    file_key: event.file_key.into(),
-                    try_count: 0,
-                })
-                .await?;
+            Ok(())
         }
+        .instrument(span)
+        .await
+    }
+}
 
+/// Handles the [`DuplicateChunksDetected`] event.
+///
+/// This event is triggered by the FileTransferService when a peer keeps sending chunks of a file
+/// that this BSP already stored, wasting bandwidth.
+impl<NT> EventHandler<DuplicateChunksDetected> for BspUploadFileTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    async fn handle_event(&mut self, event: DuplicateChunksDetected) -> anyhow::Result<()> {
+        self.storage_hub_handler
+            .record_duplicate_chunks_detected(&event);
         Ok(())
     }
 }
@@ -237,20 +307,47 @@ where
                 return Err(anyhow!("Failed to get own BSP ID."));
             }
         };
-        let current_forest_key = CURRENT_FOREST_KEY.to_vec();
-
-        // Query runtime for the chunks to prove for the file.
-        let mut confirm_storing_requests_with_chunks_to_prove = Vec::new();
-        for confirm_storing_request in event.data.confirm_storing_requests.iter() {
-            match self
+        // Requests that failed and are retried, queued together once all the requests are processed.
+        let mut confirm_storing_requests_to_retry = Vec::new();
+
+        // Drop the requests that have been queued for too long, however many times they were tried.
+        let (too_old_confirm_storing_requests, confirm_storing_requests) =
+            drop_too_old_confirm_storing_requests(
+                event.data.confirm_storing_requests,
+                self.storage_hub_handler.provider_config.max_queue_age_secs,
+                unix_timestamp_secs(),
+            );
+        for request in &too_old_confirm_storing_requests {
+            self.mark_upload_rejected(request.file_key);
+        }
+        if confirm_storing_requests.is_empty() {
+            return self
                 .storage_hub_handler
                 .blockchain
-                .query_bsp_confirm_chunks_to_prove_for_file(
-                    own_bsp_id,
-                    confirm_storing_request.file_key,
-                )
-                .await
-            {
+                .release_forest_root_write_lock(forest_root_write_tx)
+                .await;
+        }
+
+        // Query runtime for the chunks to prove for all the files at once.
+        let file_keys: Vec<H256> = confirm_storing_requests
+            .iter()
+            .map(|confirm_storing_request| confirm_storing_request.file_key)
+            .collect();
+        let chunks_to_prove_per_file = self
+            .storage_hub_handler
+            .blockchain
+            .query_confirm_chunks_to_prove_for_files(
+                StorageProviderId::BackupStorageProvider(own_bsp_id),
+                &file_keys,
+            )
+            .await;
+
+        let mut confirm_storing_requests_with_chunks_to_prove = Vec::new();
+        for (confirm_storing_request, (_, chunks_to_prove)) in confirm_storing_requests
+            .iter()
+            .zip(chunks_to_prove_per_file)
+        {
+            match chunks_to_prove {
                 Ok(chunks_to_prove) => {
                     confirm_storing_requests_with_chunks_to_prove
                         .push((confirm_storing_request, chunks_to_prove));
@@ -260,12 +357,10 @@ where
                     confirm_storing_request.increment_try_count();
                     if confirm_storing_request.try_count > MAX_CONFIRM_STORING_REQUEST_TRY_COUNT {
                         error!(target: LOG_TARGET, "Failed to query chunks to prove for file {:?}: {:?}\nMax try count exceeded! Dropping request!", confirm_storing_request.file_key, e);
+                        self.mark_upload_rejected(confirm_storing_request.file_key);
                     } else {
                         error!(target: LOG_TARGET, "Failed to query chunks to prove for file {:?}: {:?}\nEnqueuing file key again! (retry {}/{})", confirm_storing_request.file_key, e, confirm_storing_request.try_count, MAX_CONFIRM_STORING_REQUEST_TRY_COUNT);
-                        self.storage_hub_handler
-                            .blockchain
-                            .queue_confirm_bsp_request(confirm_storing_request)
-                            .await?;
+                        confirm_storing_requests_to_retry.push(confirm_storing_request);
                     }
                 }
             }
@@ -275,11 +370,14 @@ where
         let read_file_storage = self.storage_hub_handler.file_storage.read().await;
         let mut file_keys_and_proofs = Vec::new();
         let mut file_metadatas = HashMap::new();
+        let mut proven_confirm_storing_requests = HashMap::new();
         for (confirm_storing_request, chunks_to_prove) in
             confirm_storing_requests_with_chunks_to_prove.into_iter()
         {
             match (
-                read_file_storage.generate_proof(
+                generate_proof_metered(
+                    self.storage_hub_handler.metrics.as_ref(),
+                    &*read_file_storage,
                     &confirm_storing_request.file_key,
                     &HashSet::from_iter(chunks_to_prove),
                 ),
@@ -291,18 +389,20 @@ where
                         proof,
                     });
                     file_metadatas.insert(confirm_storing_request.file_key, metadata);
+                    proven_confirm_storing_requests.insert(
+                        confirm_storing_request.file_key,
+                        confirm_storing_request.clone(),
+                    );
                 }
                 _ => {
                     let mut confirm_storing_request = confirm_storing_request.clone();
                     confirm_storing_request.increment_try_count();
                     if confirm_storing_request.try_count > MAX_CONFIRM_STORING_REQUEST_TRY_COUNT {
                         error!(target: LOG_TARGET, "Failed to generate proof or get metadatas for file {:?}.\nMax try count exceeded! Dropping request!", confirm_storing_request.file_key);
+                        self.mark_upload_rejected(confirm_storing_request.file_key);
                     } else {
                         error!(target: LOG_TARGET, "Failed to generate proof or get metadatas for file {:?}.\nEnqueuing file key again! (retry {}/{})", confirm_storing_request.file_key, confirm_storing_request.try_count, MAX_CONFIRM_STORING_REQUEST_TRY_COUNT);
-                        self.storage_hub_handler
-                            .blockchain
-                            .queue_confirm_bsp_request(confirm_storing_request)
-                            .await?;
+                        confirm_storing_requests_to_retry.push(confirm_storing_request);
                     }
                 }
             }
@@ -310,6 +410,75 @@ where
         // Release the file storage read lock as soon as possible.
         drop(read_file_storage);
 
+        // The requests to retry are queued once the proven files are confirmed, since they only
+        // get a fresh start if the confirmation succeeds.
+        let proven_file_keys = proven_confirm_storing_requests
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        let confirmation = self
+            .confirm_proven_files(
+                own_bsp_id,
+                file_keys_and_proofs,
+                file_metadatas,
+                proven_confirm_storing_requests,
+                &mut confirm_storing_requests_to_retry,
+            )
+            .await;
+
+        let confirm_storing_requests_to_retry = confirm_storing_requests_to_requeue(
+            confirm_storing_requests_to_retry,
+            confirmation.is_ok(),
+        );
+        if confirmation.is_err() {
+            // The requests of the files that failed to be confirmed are dropped, which ends their
+            // uploads.
+            let requeued_file_keys = confirm_storing_requests_to_retry
+                .iter()
+                .map(|request| request.file_key)
+                .collect::<HashSet<_>>();
+            for file_key in proven_file_keys {
+                if !requeued_file_keys.contains(&file_key) {
+                    self.mark_upload_rejected(file_key);
+                }
+            }
+        }
+        if !confirm_storing_requests_to_retry.is_empty() {
+            self.storage_hub_handler
+                .blockchain
+                .queue_confirm_bsp_request_batch(confirm_storing_requests_to_retry)
+                .await?;
+        }
+        confirmation?;
+
+        // Release the forest root write "lock" and finish the task.
+        self.storage_hub_handler
+            .blockchain
+            .release_forest_root_write_lock(forest_root_write_tx)
+            .await
+    }
+}
+
+impl<NT> BspUploadFileTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    /// Confirms storing the files proven in `file_keys_and_proofs`, in a single
+    /// `bsp_confirm_storing` extrinsic.
+    ///
+    /// The requests of the files left out of the non-inclusion Forest proof, because it timed out,
+    /// are added to `confirm_storing_requests_to_retry`.
+    async fn confirm_proven_files(
+        &self,
+        own_bsp_id: H256,
+        mut file_keys_and_proofs: Vec<FileKeyWithProof>,
+        mut file_metadatas: HashMap<H256, FileMetadata>,
+        mut proven_confirm_storing_requests: HashMap<H256, ConfirmStoringRequest>,
+        confirm_storing_requests_to_retry: &mut Vec<ConfirmStoringRequest>,
+    ) -> anyhow::Result<()> {
+        let current_forest_key = CURRENT_FOREST_KEY.to_vec();
+
         if file_keys_and_proofs.is_empty() {
             error!(target: LOG_TARGET, "Failed to generate proofs for ALL the requested files.\n");
             return Err(anyhow!(
@@ -329,8 +498,42 @@ where
             .await
             .ok_or_else(|| anyhow!("Failed to get forest storage."))?;
 
-        // Generate a proof of non-inclusion (executed in closure to drop the read lock on the forest storage).
-        let non_inclusion_forest_proof = { fs.read().await.generate_proof(file_keys)? };
+        // Generate a proof of non-inclusion in a blocking task, as it can take a while for large
+        // Forests. If it does not finish in time, only the files proven so far are confirmed.
+        let (proven_file_keys, non_inclusion_forest_proof) = generate_partial_proof_with_timeout(
+            self.forest_proof_timeout(),
+            file_keys.clone(),
+            move |file_keys, cancellation| {
+                cancellation
+                    .blocking_read(&fs)?
+                    .generate_proof(file_keys.to_vec())
+                    .map_err(|e| anyhow!("Failed to generate forest proof: {:?}", e))
+            },
+        )
+        .await?;
+
+        // Confirm only the files proven, and queue the rest again for the next batch.
+        if proven_file_keys.len() < file_keys.len() {
+            let proven_file_keys = proven_file_keys.iter().collect::<HashSet<_>>();
+            confirm_storing_requests_to_retry.extend(
+                file_keys
+                    .iter()
+                    .filter(|file_key| !proven_file_keys.contains(file_key))
+                    .filter_map(|file_key| proven_confirm_storing_requests.remove(file_key)),
+            );
+
+            warn!(
+                target: LOG_TARGET,
+                "Timed out generating the non-inclusion forest proof. Confirming {} out of {} files, the rest are queued again.",
+                proven_file_keys.len(),
+                file_keys.len()
+            );
+
+            file_keys_and_proofs.retain(|file_key_with_proof| {
+                proven_file_keys.contains(&file_key_with_proof.file_key)
+            });
+            file_metadatas.retain(|file_key, _| proven_file_keys.contains(file_key));
+        }
 
         // Build extrinsic.
         let call = storage_hub_runtime::RuntimeCall::FileSystem(
@@ -344,9 +547,21 @@ where
             },
         );
 
+        // The confirmation is sent within a span following the uploads of the files it confirms,
+        // so that its inclusion can be told apart from the other uploads.
+        let confirm_span = self.storage_hub_handler.upload_spans.follow_uploads(
+            info_span!(
+                target: LOG_TARGET,
+                "confirm_storing",
+                file_keys = ?file_metadatas.keys().collect::<Vec<_>>()
+            ),
+            file_metadatas.keys(),
+        );
+
         // Send the confirmation transaction and wait for it to be included in the block and
         // continue only if it is successful.
-        self.storage_hub_handler
+        let maybe_events = self
+            .storage_hub_handler
             .blockchain
             .submit_extrinsic_with_retry(
                 call,
@@ -361,6 +576,7 @@ where
                     .retry_only_if_timeout(),
                 true,
             )
+            .instrument(confirm_span)
             .await
             .map_err(|e| {
                 anyhow!(
@@ -370,70 +586,501 @@ where
                 )
             })?;
 
-        // Release the forest root write "lock" and finish the task.
-        self.storage_hub_handler
-            .blockchain
-            .release_forest_root_write_lock(forest_root_write_tx)
+        // The runtime skips the files it cannot confirm, which are then not added to this BSP's
+        // Forest, so their local data is dropped instead of being kept forever.
+        // The event does not tell why a file was skipped, and most reasons (i.e. the storage
+        // request being already fulfilled, or the user being insolvent) are permanent, so
+        // skipped files are not queued to be confirmed again.
+        let skipped_file_keys = match &maybe_events {
+            Some(events) => {
+                let mut write_file_storage = self.storage_hub_handler.file_storage.write().await;
+                delete_skipped_files(&mut *write_file_storage, events, own_bsp_id)
+            }
+            None => HashSet::new(),
+        };
+        if !skipped_file_keys.is_empty() {
+            warn!(
+                target: LOG_TARGET,
+                "Runtime skipped confirming {} out of {} files: {:?}",
+                skipped_file_keys.len(),
+                file_metadatas.len(),
+                skipped_file_keys
+            );
+
+            if let Some(metrics) = &self.storage_hub_handler.metrics {
+                metrics
+                    .bsp_confirm_storing_skipped
+                    .inc_by(skipped_file_keys.len() as u64);
+            }
+        }
+
+        for file_key in file_metadatas.keys() {
+            if skipped_file_keys.contains(file_key) {
+                // Its data was already deleted along with the other skipped files.
+                self.unregister_file(*file_key).await;
+                self.mark_upload_rejected(*file_key);
+            } else {
+                self.storage_hub_handler
+                    .end_upload(file_key, UploadState::Confirmed);
+                if let Some(metrics) = &self.storage_hub_handler.metrics {
+                    metrics.files_confirmed.inc();
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Handles the [`StorageRequestRevoked`] event.
+///
+/// This event is triggered when a user revokes a storage request on-chain. If this BSP was in the
+/// middle of receiving the file, it stops accepting chunks for it and deletes the partial file.
+impl<NT> EventHandler<StorageRequestRevoked> for BspUploadFileTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    async fn handle_event(&mut self, event: StorageRequestRevoked) -> anyhow::Result<()> {
+        info!(
+            target: LOG_TARGET,
+            "Storage request for file_key {:x} was revoked",
+            event.file_key
+        );
+
+        self.handle_closed_storage_request(event.file_key.into())
             .await
     }
 }
 
-impl<NT> BspUploadFileTask<NT>
+/// Handles the [`StorageRequestExpired`] event.
+///
+/// This event is triggered when a storage request expires on-chain. If this BSP was in the
+/// middle of receiving the file, it stops accepting chunks for it and deletes the partial file.
+impl<NT> EventHandler<StorageRequestExpired> for BspUploadFileTask<NT>
 where
-    NT: ShNodeType,
+    NT: ShNodeType + 'static,
     NT::FSH: BspForestStorageHandlerT,
 {
-    async fn handle_new_storage_request_event(
-        &mut self,
-        event: NewStorageRequest,
-    ) -> anyhow::Result<()> {
-        if event.size == 0 {
-            let err_msg = "File size cannot be 0";
-            error!(target: LOG_TARGET, err_msg);
-            return Err(anyhow!(err_msg));
-        }
+    async fn handle_event(&mut self, event: StorageRequestExpired) -> anyhow::Result<()> {
+        info!(
+            target: LOG_TARGET,
+            "Storage request for file_key {:x} expired",
+            event.file_key
+        );
 
-        // First check if the file is not on our exclude list
-        let is_allowed = self.is_allowed(&event).await?;
+        self.handle_closed_storage_request(event.file_key.into())
+            .await
+    }
+}
 
-        if !is_allowed {
-            return Ok(());
-        }
+/// Handles the [`UserWithoutFunds`] event.
+///
+/// This event is triggered when a user runs out of funds to pay for its payment streams. This BSP
+/// stops receiving any file of the user it is in the middle of receiving, and deletes the partial
+/// files. Files already stored are handled by the [`BspChargeFeesTask`](super::bsp_charge_fees::BspChargeFeesTask).
+impl<NT> EventHandler<UserWithoutFunds> for BspUploadFileTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    async fn handle_event(&mut self, event: UserWithoutFunds) -> anyhow::Result<()> {
+        info!(
+            target: LOG_TARGET,
+            "User {:?} is without funds, stopping their uploads",
+            event.who
+        );
 
-        // Get the current Forest key of the Provider running this node.
-        let current_forest_key = CURRENT_FOREST_KEY.to_vec();
+        let owner = <AccountId32 as AsRef<[u8]>>::as_ref(&event.who).to_vec();
 
-        // Verify if file not already stored
-        let fs = self
+        let registered_file_keys = self
             .storage_hub_handler
-            .forest_storage_handler
-            .get(&current_forest_key)
-            .await
-            .ok_or_else(|| anyhow!("Failed to get forest storage."))?;
-        if fs.read().await.contains_file_key(&event.file_key.into())? {
-            info!(
-                target: LOG_TARGET,
-                "Skipping file key {:x} NewStorageRequest because we are already storing it.",
-                event.file_key
-            );
-            return Ok(());
+            .file_transfer
+            .get_registered_file_keys()
+            .await;
+
+        let read_file_storage = self.storage_hub_handler.file_storage.read().await;
+        let mut user_file_keys = Vec::new();
+        for file_key in registered_file_keys {
+            let file_key: H256 = file_key.into();
+            match read_file_storage.get_metadata(&file_key) {
+                Ok(Some(metadata)) if metadata.owner() == &owner => user_file_keys.push(file_key),
+                Ok(_) => {}
+                Err(e) => {
+                    error!(
+                        target: LOG_TARGET,
+                        "Failed to get metadata of file {:x}: {:?}",
+                        file_key,
+                        e
+                    );
+                }
+            }
         }
+        drop(read_file_storage);
 
-        // Construct file metadata.
-        let metadata = FileMetadata::new(
-            <AccountId32 as AsRef<[u8]>>::as_ref(&event.who).to_vec(),
-            event.bucket_id.as_ref().to_vec(),
-            event.location.to_vec(),
-            event.size as u64,
-            event.fingerprint,
-        )
-        .map_err(|_| anyhow::anyhow!("Invalid file metadata"))?;
+        for file_key in user_file_keys {
+            self.unvolunteer_file(file_key).await;
+        }
 
-        let own_provider_id = self
+        Ok(())
+    }
+}
+
+/// Handles the [`Reorg`] event.
+///
+/// This event is triggered when a new best block causes a reorg. The `bsp_volunteer` extrinsics
+/// included only in the retracted fork are not part of the new best fork, so this BSP volunteers
+/// again for:
+/// - The files it is still receiving, i.e. the incomplete files in the File Storage.
+/// - The files confirmed only in the retracted fork, i.e. in the Forest snapshot as of the old best
+///   block but not in the current Forest. Their confirmation is queued again as well.
+///
+/// Files whose storage request is no longer open to volunteers in the new best fork (e.g. because
+/// it was fulfilled or closed) are skipped, and so are the volunteers for the files this BSP is
+/// still a volunteer for in it.
+impl<NT> EventHandler<Reorg> for BspUploadFileTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    async fn handle_event(&mut self, event: Reorg) -> anyhow::Result<()> {
+        info!(
+            target: LOG_TARGET,
+            "Reorg from block {:?} to block {:?}, volunteering again for the files in progress",
+            event.old_best_block,
+            event.new_best_block
+        );
+
+        let incomplete_files = self
+            .storage_hub_handler
+            .file_storage
+            .read()
+            .await
+            .incomplete_files()
+            .map_err(|e| anyhow!("Failed to get incomplete files: {:?}", e))?;
+
+        let own_bsp_id = match self
             .storage_hub_handler
             .blockchain
             .query_storage_provider_id(None)
-            .await?;
+            .await?
+        {
+            Some(StorageProviderId::BackupStorageProvider(id)) => id,
+            _ => return Err(anyhow!("Failed to get own BSP ID.")),
+        };
+
+        // Failing to find the files confirmed in the retracted fork does not prevent volunteering
+        // again for the files being received.
+        let retracted_confirmed_files = self
+            .files_confirmed_in_retracted_fork(&event, own_bsp_id)
+            .await
+            .unwrap_or_else(|e| {
+                warn!(target: LOG_TARGET, "Failed to get the files confirmed in the retracted fork: {:?}", e);
+                Vec::new()
+            });
+
+        // Each file waits for this BSP to be eligible to volunteer for it, so they are handled
+        // concurrently.
+        let incomplete_files = incomplete_files
+            .into_iter()
+            .map(|incomplete_file| (incomplete_file.file_key, false));
+        let retracted_confirmed_files = retracted_confirmed_files
+            .into_iter()
+            .map(|file_key| (file_key, true));
+        futures::future::join_all(
+            incomplete_files
+                .chain(retracted_confirmed_files)
+                .map(|(file_key, complete)| self.volunteer_again(own_bsp_id, file_key, complete)),
+        )
+        .await;
+
+        Ok(())
+    }
+}
+
+/// Handles the [`BspCapacityChanged`] event.
+///
+/// This event is triggered when the on-chain capacity of this BSP changes, or the part of it in
+/// use does, e.g. when files are confirmed or this BSP stops storing them. The new capacity is
+/// reported to the telemetry endpoints, if any.
+impl<NT> EventHandler<BspCapacityChanged> for BspUploadFileTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    async fn handle_event(&mut self, event: BspCapacityChanged) -> anyhow::Result<()> {
+        self.report_capacity_change(event.bsp_id).await;
+        Ok(())
+    }
+}
+
+impl<NT> BspUploadFileTask<NT>
+where
+    NT: ShNodeType,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    /// The files in this BSP's Forest as of the old best block of `reorg`, that are no longer in
+    /// its current Forest.
+    ///
+    /// The Forest follows the new best fork once the reorg is notified, so these are the files whose
+    /// confirmation was retracted. Returns no files if the snapshot of the Forest as of the old best
+    /// block is gone.
+    async fn files_confirmed_in_retracted_fork(
+        &self,
+        reorg: &Reorg,
+        own_bsp_id: H256,
+    ) -> anyhow::Result<Vec<H256>> {
+        let retracted_root = self
+            .storage_hub_handler
+            .blockchain
+            .query_forest_root_at_block(own_bsp_id, reorg.old_best_block.hash)
+            .await?;
+
+        let current_forest_key = CURRENT_FOREST_KEY.to_vec();
+        let Some(retracted_fs) = self
+            .storage_hub_handler
+            .forest_storage_handler
+            .snapshot_by_root(&current_forest_key, &retracted_root)
+            .await
+        else {
+            debug!(
+                target: LOG_TARGET,
+                "No Forest snapshot with root {:?} as of the old best block",
+                retracted_root
+            );
+            return Ok(Vec::new());
+        };
+        let current_fs = self
+            .storage_hub_handler
+            .forest_storage_handler
+            .get(&current_forest_key)
+            .await
+            .ok_or_else(|| anyhow!("Failed to get forest storage."))?;
+
+        let retracted_fs = retracted_fs.read().await;
+        let current_fs = current_fs.read().await;
+        files_missing_from_forest(&*retracted_fs, &*current_fs)
+    }
+
+    /// Volunteers again for `file_key` after a reorg, once this BSP is eligible to volunteer for
+    /// it. If the file is `complete`, its confirmation is queued again as well.
+    ///
+    /// Nothing is submitted for files whose storage request is no longer open to volunteers in the
+    /// new best fork, nor for those this BSP is still a volunteer for in it, as those volunteers
+    /// would only fail and pay fees. Failures are only logged.
+    async fn volunteer_again(&self, own_bsp_id: H256, file_key: H256, complete: bool) {
+        // Fails if the storage request is not in the new best fork.
+        let earliest_volunteer_tick = match self
+            .storage_hub_handler
+            .blockchain
+            .query_file_earliest_volunteer_tick(own_bsp_id, file_key)
+            .await
+        {
+            Ok(tick) => tick,
+            Err(e) => {
+                warn!(
+                    target: LOG_TARGET,
+                    "Failed to query the earliest tick to volunteer again for file key {:x} after the reorg. Skipping: {:?}",
+                    file_key,
+                    e
+                );
+                return;
+            }
+        };
+
+        // As when first volunteering, the extrinsic is sent a tick before the BSP is eligible.
+        if let Err(e) = self
+            .storage_hub_handler
+            .blockchain
+            .wait_for_tick(earliest_volunteer_tick.saturating_sub(1))
+            .await
+        {
+            error!(
+                target: LOG_TARGET,
+                "Failed to wait for tick {} to volunteer again for file key {:x}: {:?}",
+                earliest_volunteer_tick,
+                file_key,
+                e
+            );
+            return;
+        }
+
+        match self
+            .storage_hub_handler
+            .blockchain
+            .is_storage_request_open_to_volunteers(file_key)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                debug!(
+                    target: LOG_TARGET,
+                    "Storage request for file key {:x} is no longer open to volunteers after the reorg. Skipping.",
+                    file_key
+                );
+                return;
+            }
+            Err(e) => {
+                error!(
+                    target: LOG_TARGET,
+                    "Failed to query if storage request for file key {:x} is open to volunteers: {:?}",
+                    file_key,
+                    e
+                );
+                return;
+            }
+        }
+
+        match self
+            .storage_hub_handler
+            .blockchain
+            .query_is_bsp_volunteer(own_bsp_id, file_key)
+            .await
+        {
+            Ok(true) => debug!(
+                target: LOG_TARGET,
+                "Still a volunteer for file key {:x} after the reorg. Not volunteering again.",
+                file_key
+            ),
+            Ok(false) => self.submit_volunteer_again(file_key).await,
+            Err(e) => {
+                error!(
+                    target: LOG_TARGET,
+                    "Failed to query if this BSP is a volunteer for file key {:x}: {:?}",
+                    file_key,
+                    e
+                );
+                return;
+            }
+        }
+
+        if complete {
+            if let Err(e) = self
+                .storage_hub_handler
+                .blockchain
+                .queue_confirm_bsp_request(ConfirmStoringRequest::new(file_key))
+                .await
+            {
+                error!(
+                    target: LOG_TARGET,
+                    "Failed to queue confirm storing request for file key {:x}: {:?}",
+                    file_key,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Submits the `bsp_volunteer` extrinsic for `file_key` again, logging its outcome.
+    async fn submit_volunteer_again(&self, file_key: H256) {
+        let call =
+            storage_hub_runtime::RuntimeCall::FileSystem(pallet_file_system::Call::bsp_volunteer {
+                file_key,
+            });
+        let result = match self
+            .storage_hub_handler
+            .blockchain
+            .send_extrinsic(call, Default::default())
+            .await
+        {
+            Ok(submitted) => submitted
+                .with_timeout(Duration::from_secs(
+                    self.storage_hub_handler
+                        .provider_config
+                        .extrinsic_retry_timeout,
+                ))
+                .watch_for_success(&self.storage_hub_handler.blockchain)
+                .await
+                .map_err(|e| anyhow!("Failed to watch transaction: {:?}", e)),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => info!(
+                target: LOG_TARGET,
+                "Volunteered again for file key {:x} after the reorg",
+                file_key
+            ),
+            Err(e) => warn!(
+                target: LOG_TARGET,
+                "Failed to volunteer again for file key {:x} after the reorg: {:?}",
+                file_key,
+                e
+            ),
+        }
+    }
+
+    async fn handle_new_storage_request_event(
+        &mut self,
+        event: NewStorageRequest,
+        file_key_cleanup: &mut FileKeyCleanup,
+    ) -> anyhow::Result<()> {
+        if event.size == 0 {
+            let err_msg = "File size cannot be 0";
+            error!(target: LOG_TARGET, err_msg);
+            return Err(anyhow!(err_msg));
+        }
+
+        // First check if the file is not on our exclude list
+        let is_allowed = self.is_allowed(&event).await?;
+
+        if !is_allowed {
+            return Ok(());
+        }
+
+        // Get the current Forest key of the Provider running this node.
+        let current_forest_key = CURRENT_FOREST_KEY.to_vec();
+
+        // Verify if file not already stored
+        let fs = self
+            .storage_hub_handler
+            .forest_storage_handler
+            .get(&current_forest_key)
+            .await
+            .ok_or_else(|| anyhow!("Failed to get forest storage."))?;
+        if fs.read().await.contains_file_key(&event.file_key.into())? {
+            info!(
+                target: LOG_TARGET,
+                "Skipping file key {:x} NewStorageRequest because we are already storing it.",
+                event.file_key
+            );
+            return Ok(());
+        }
+
+        // Refuse new uploads while the node is already receiving as many files as it is
+        // configured to handle simultaneously, to avoid contention on the file storage.
+        let active_uploads = self
+            .storage_hub_handler
+            .file_transfer
+            .get_active_upload_count()
+            .await;
+        let max_active_uploads = self.storage_hub_handler.provider_config.max_active_uploads;
+        if active_uploads >= max_active_uploads {
+            let err_msg = format!(
+                "Refusing to volunteer for file key {:x} ({:?}): {} active uploads, maximum is {}",
+                event.file_key,
+                RejectedStorageRequestReason::MaxConcurrentUploadsReached,
+                active_uploads,
+                max_active_uploads
+            );
+            warn!(target: LOG_TARGET, "{}", err_msg);
+            return Err(anyhow!(err_msg));
+        }
+
+        // Construct file metadata.
+        let metadata = FileMetadata::validated(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&event.who).to_vec(),
+            event.bucket_id.as_ref().to_vec(),
+            event.location.to_vec(),
+            event.size as u64,
+            event.fingerprint,
+        )
+        .map_err(|e| anyhow::anyhow!("Invalid file metadata: {:?}", e))?;
+
+        let own_provider_id = self
+            .storage_hub_handler
+            .blockchain
+            .query_storage_provider_id(None)
+            .await?;
 
         let own_bsp_id = match own_provider_id {
             Some(id) => match id {
@@ -502,10 +1149,24 @@ where
                 return Err(anyhow::anyhow!(err_msg));
             }
 
-            self.storage_hub_handler
-                .blockchain
-                .increase_capacity(CapacityRequestData::new(event.size))
-                .await?;
+            // Only one capacity change is submitted at a time, as concurrent ones would be
+            // computed from the same stale capacity and fail. If another request is already
+            // changing the capacity, wait for it to be applied and re-check the available capacity.
+            match PendingCapacityChangeGuard::try_acquire(&self.pending_capacity_change) {
+                Some(_pending_capacity_change) => {
+                    self.storage_hub_handler
+                        .blockchain
+                        .increase_capacity(CapacityRequestData::new(event.size))
+                        .await?;
+                }
+                None => {
+                    debug!(
+                        target: LOG_TARGET,
+                        "A capacity change is already in flight, waiting for it to be applied before re-checking the available capacity"
+                    );
+                    self.wait_for_pending_capacity_change(own_bsp_id).await?;
+                }
+            }
 
             let available_capacity = self
                 .storage_hub_handler
@@ -537,7 +1198,7 @@ where
             .as_ref()
             .try_into()?;
 
-        self.file_key_cleanup = Some(file_key.into());
+        file_key_cleanup.set(file_key.into());
 
         // Query runtime for the earliest block where the BSP can volunteer for the file.
         let earliest_volunteer_tick = self
@@ -554,7 +1215,7 @@ where
 
         info!(
             target: LOG_TARGET,
-            "Waiting for tick {:?} to volunteer for file {:x}",
+            "Waiting for {} to volunteer for file {:x}",
             earliest_volunteer_tick,
             file_key
         );
@@ -587,14 +1248,26 @@ where
         }
 
         // Optimistically create file in file storage so we can write uploaded chunks as soon as possible.
+        // If it is already there, e.g. from a previous storage request for it, its chunks are kept.
+        let chunks_count = metadata.chunks_count();
         let mut write_file_storage = self.storage_hub_handler.file_storage.write().await;
-        write_file_storage
-            .insert_file(
+        let inserted = write_file_storage
+            .insert_file_if_absent(
                 metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>(),
                 metadata,
             )
             .map_err(|e| anyhow!("Failed to insert file in file storage: {:?}", e))?;
         drop(write_file_storage);
+        if !inserted {
+            debug!(
+                target: LOG_TARGET,
+                "File key {:x} is already in file storage, resuming its upload.",
+                file_key
+            );
+        }
+
+        self.storage_hub_handler
+            .start_upload(file_key.into(), chunks_count, Span::current());
 
         // Optimistically register the file for upload in the file transfer service.
         // This solves the race condition between the user and the BSP, where the user could react faster
@@ -620,6 +1293,10 @@ where
                 file_key: H256(file_key.into()),
             });
 
+        // Stop waiting for the volunteer if the storage request is closed in the meantime.
+        let volunteer_watch =
+            VolunteerWatchGuard::register(&self.volunteer_watches, file_key.into());
+
         // Send extrinsic and wait for it to be included in the block.
         let result = self
             .storage_hub_handler
@@ -631,9 +1308,19 @@ where
                     .provider_config
                     .extrinsic_retry_timeout,
             ))
+            .with_cancellation(volunteer_watch.cancellation())
             .watch_for_success(&self.storage_hub_handler.blockchain)
             .await;
 
+        if let Err(WatchTransactionError::Cancelled) = result {
+            info!(
+                target: LOG_TARGET,
+                "Stopped waiting for the volunteer for file {:?}, since its storage request was closed",
+                file_key
+            );
+            return Ok(());
+        }
+
         if let Err(e) = result {
             error!(
                 target: LOG_TARGET,
@@ -649,6 +1336,11 @@ where
                 .wait_for_tick(earliest_volunteer_tick)
                 .await?;
 
+            // The storage request may have been closed while waiting for the tick.
+            if volunteer_watch.cancellation().is_cancelled() {
+                return Ok(());
+            }
+
             // Send extrinsic and wait for it to be included in the block.
             let result = self
                 .storage_hub_handler
@@ -660,9 +1352,19 @@ where
                         .provider_config
                         .extrinsic_retry_timeout,
                 ))
+                .with_cancellation(volunteer_watch.cancellation())
                 .watch_for_success(&self.storage_hub_handler.blockchain)
                 .await;
 
+            if let Err(WatchTransactionError::Cancelled) = result {
+                info!(
+                    target: LOG_TARGET,
+                    "Stopped waiting for the volunteer for file {:?}, since its storage request was closed",
+                    file_key
+                );
+                return Ok(());
+            }
+
             if let Err(e) = result {
                 error!(
                     target: LOG_TARGET,
@@ -686,10 +1388,18 @@ where
         event: RemoteUploadRequest,
     ) -> anyhow::Result<bool> {
         let file_key = event.file_key.into();
-        let mut write_file_storage = self.storage_hub_handler.file_storage.write().await;
+        // The File Storage serialises the writes to each file internally, so shared access is enough
+        // and uploads of other files and proof generation are not blocked by this one.
+        let write_start = Instant::now();
+        let read_file_storage = self.storage_hub_handler.file_storage.read().await;
+        if let Some(metrics) = &self.storage_hub_handler.metrics {
+            metrics
+                .file_storage_lock_wait_duration
+                .observe(write_start.elapsed().as_secs_f64());
+        }
 
         // Get the file metadata to verify the fingerprint
-        let file_metadata = write_file_storage
+        let file_metadata = read_file_storage
             .get_metadata(&file_key)
             .map_err(|e| anyhow!("Failed to get file metadata: {:?}", e))?
             .ok_or_else(|| anyhow!("File metadata not found"))?;
@@ -706,30 +1416,40 @@ where
         }
 
         // Verify and extract chunks from proof
-        let proven = match event
-            .file_key_proof
-            .proven::<StorageProofsMerkleTrieLayout>()
-        {
+        let proven = debug_span!(target: LOG_TARGET, "verify_proof").in_scope(|| {
+            event
+                .file_key_proof
+                .proven_expecting::<StorageProofsMerkleTrieLayout>(
+                    1..=BATCH_CHUNK_FILE_TRANSFER_MAX_CHUNKS,
+                )
+        });
+        let proven = match proven {
             Ok(proven) => {
-                if proven.is_empty() {
+                // Calculate total batch size
+                let total_batch_size: usize = proven.iter().map(|chunk| chunk.data.len()).sum();
+
+                if total_batch_size > BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE {
                     Err(anyhow::anyhow!(
-                        "Expected at least one proven chunk but got none."
+                        "Total batch size {} bytes exceeds maximum allowed size of {} bytes",
+                        total_batch_size,
+                        BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE
                     ))
                 } else {
-                    // Calculate total batch size
-                    let total_batch_size: usize = proven.iter().map(|chunk| chunk.data.len()).sum();
-
-                    if total_batch_size > BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE {
-                        Err(anyhow::anyhow!(
-                            "Total batch size {} bytes exceeds maximum allowed size of {} bytes",
-                            total_batch_size,
-                            BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE
-                        ))
-                    } else {
-                        Ok(proven)
-                    }
+                    Ok(proven)
                 }
             }
+            Err(ProvenFileKeyError::UnexpectedChunkCount { expected, got }) => {
+                if let Some(metrics) = &self.storage_hub_handler.metrics {
+                    metrics.reject_chunks(ChunkRejection::InvalidProof, got);
+                }
+                drop(read_file_storage);
+                return Err(self
+                    .handle_upload_failure(
+                        file_key,
+                        UploadFailure::UnexpectedChunkCount { expected, got },
+                    )
+                    .await);
+            }
             Err(e) => Err(anyhow::anyhow!(
                 "Failed to verify and get proven file key chunks: {:?}",
                 e
@@ -740,11 +1460,50 @@ where
             Ok(proven) => proven,
             Err(e) => {
                 error!(target: LOG_TARGET, "Failed to verify and get proven file key chunks: {}", e);
+                // The chunks of a batch whose proof failed to verify are unknown, so it counts
+                // as a single rejected chunk.
+                if let Some(metrics) = &self.storage_hub_handler.metrics {
+                    metrics.reject_chunks(ChunkRejection::InvalidProof, 1);
+                }
                 return Err(e);
             }
         };
 
+        // Reject the whole batch if any chunk is outside of the file's range. Storing it would
+        // inflate the stored chunks count and prevent the file from ever being complete.
+        let chunks_count = file_metadata.chunks_count();
+        if let Some(chunk) = proven
+            .iter()
+            .find(|chunk| chunk.key.as_u64() >= chunks_count)
+        {
+            error!(
+                target: LOG_TARGET,
+                "Chunk {:?} is out of range for file {:?} with {} chunks. Reporting peer {:?}",
+                chunk.key, file_key, chunks_count, event.peer
+            );
+            if let Err(e) = self
+                .storage_hub_handler
+                .file_transfer
+                .report_peer(event.peer, "chunk out of range")
+                .await
+            {
+                error!(target: LOG_TARGET, "Failed to report peer {:?}: {:?}", event.peer, e);
+            }
+            if let Some(metrics) = &self.storage_hub_handler.metrics {
+                metrics.reject_chunks(ChunkRejection::OutOfRange, proven.len());
+            }
+            return Err(anyhow!(
+                "Chunk {:?} is out of range for file with {} chunks",
+                chunk.key,
+                chunks_count
+            ));
+        }
+
         let mut file_complete = false;
+        // Chunks of the file stored after the last successful write of this batch, if any.
+        let mut stored_chunks = None;
+        // Whether some chunks of the batch were already stored.
+        let mut has_duplicate_chunks = false;
 
         // Process each proven chunk in the batch
         for chunk in proven {
@@ -763,11 +1522,13 @@ where
                                 actual_chunk_size,
                             chunk.data.len()
                         );
-                        return Err(anyhow!(
-                            "Invalid chunk size. Expected {}, got {}",
-                            actual_chunk_size,
-                            chunk.data.len()
-                        ));
+                        if let Some(metrics) = &self.storage_hub_handler.metrics {
+                            metrics.reject_chunks(ChunkRejection::InvalidProof, 1);
+                        }
+                        drop(read_file_storage);
+                        return Err(self
+                            .handle_upload_failure(file_key, UploadFailure::InvalidProof)
+                            .await);
                     }
                     Err(e) => {
                         let err_msg = format!(
@@ -784,63 +1545,102 @@ where
                 }
             }
 
-            let write_result = write_file_storage.write_chunk(&file_key, &chunk.key, &chunk.data);
+            let write_result = debug_span!(target: LOG_TARGET, "write_chunk", chunk = chunk_idx)
+                .in_scope(|| {
+                    write_chunk_metered(
+                        self.storage_hub_handler.metrics.as_ref(),
+                        &*read_file_storage,
+                        &file_key,
+                        &chunk.key,
+                        &chunk.data,
+                    )
+                });
 
             match write_result {
                 Ok(outcome) => match outcome {
                     FileStorageWriteOutcome::FileComplete => {
                         file_complete = true;
+                        stored_chunks = Some(chunks_count);
                         break; // We can stop processing chunks if the file is complete
                     }
-                    FileStorageWriteOutcome::FileIncomplete => continue,
+                    FileStorageWriteOutcome::FileIncomplete { stored, .. } => {
+                        stored_chunks = Some(stored);
+                        continue;
+                    }
                 },
-                Err(error) => match error {
-                    FileStorageWriteError::FileChunkAlreadyExists => {
+                Err(error) => {
+                    // Writes to a sealed file fail for the chunks it already stores as well.
+                    if matches!(
+                        error,
+                        FileStorageError::FileChunkAlreadyExists | FileStorageError::FileSealed
+                    ) {
+                        has_duplicate_chunks = true;
+                    }
+                    let failure = UploadFailure::Write(error);
+                    if failure.action() == UploadFailureAction::Ignore {
                         trace!(
                             target: LOG_TARGET,
-                            "Received duplicate chunk with key: {:?}",
-                            chunk.key
+                            "Skipping chunk with key {:?}: {}",
+                            chunk.key,
+                            failure
                         );
                         // Continue processing other chunks
                         continue;
                     }
-                    FileStorageWriteError::FileDoesNotExist => {
-                        return Err(anyhow::anyhow!(format!(
-                            "File does not exist for key {:?}. Maybe we forgot to unregister before deleting?",
-                            event.file_key
-                        )));
-                    }
-                    FileStorageWriteError::FailedToGetFileChunk
-                    | FileStorageWriteError::FailedToInsertFileChunk
-                    | FileStorageWriteError::FailedToDeleteChunk
-                    | FileStorageWriteError::FailedToDeleteRoot
-                    | FileStorageWriteError::FailedToPersistChanges
-                    | FileStorageWriteError::FailedToParseFileMetadata
-                    | FileStorageWriteError::FailedToParseFingerprint
-                    | FileStorageWriteError::FailedToReadStorage
-                    | FileStorageWriteError::FailedToUpdatePartialRoot
-                    | FileStorageWriteError::FailedToParsePartialRoot
-                    | FileStorageWriteError::FailedToGetStoredChunksCount
-                    | FileStorageWriteError::ChunkCountOverflow => {
-                        return Err(anyhow::anyhow!(format!(
-                            "Internal trie read/write error {:?}:{:?}",
-                            event.file_key, chunk.key
-                        )));
-                    }
-                    FileStorageWriteError::FingerprintAndStoredFileMismatch => {
-                        return Err(anyhow::anyhow!(format!(
-                            "Invariant broken! This is a bug! Fingerprint and stored file mismatch for key {:?}.",
-                            event.file_key
-                        )));
-                    }
-                    FileStorageWriteError::FailedToConstructTrieIter
-                    | FileStorageWriteError::FailedToContructFileTrie => {
-                        return Err(anyhow::anyhow!(format!(
-                            "This is a bug! Failed to construct trie iter for key {:?}.",
-                            event.file_key
-                        )));
-                    }
-                },
+
+                    drop(read_file_storage);
+                    return Err(self.handle_upload_failure(file_key, failure).await);
+                }
+            }
+        }
+
+        // Completed files must not change anymore.
+        if file_complete {
+            if let Err(e) = read_file_storage.seal_file(&file_key) {
+                error!(
+                    target: LOG_TARGET,
+                    "Failed to seal complete file {:?}: {:?}",
+                    file_key,
+                    e
+                );
+            }
+        }
+
+        // The writes report the stored chunks, so the File Storage is only queried if none of
+        // the chunks of the batch were written.
+        let stored_chunks = match stored_chunks {
+            Some(stored_chunks) => Ok(stored_chunks),
+            None => read_file_storage.stored_chunks_count(&file_key),
+        };
+        match stored_chunks {
+            Ok(stored_chunks) => self
+                .storage_hub_handler
+                .upload_progress
+                .record_chunks(&file_key, stored_chunks),
+            Err(e) => warn!(
+                target: LOG_TARGET,
+                "Failed to get stored chunks count for file {:?}: {:?}",
+                file_key,
+                e
+            ),
+        }
+        drop(read_file_storage);
+        if let Some(metrics) = &self.storage_hub_handler.metrics {
+            metrics
+                .upload_batch_write_duration
+                .observe(write_start.elapsed().as_secs_f64());
+        }
+        self.storage_hub_handler.report_uploads_in_progress();
+
+        // Peers resuming an upload should only send the chunks that were not stored yet.
+        if has_duplicate_chunks {
+            if let Err(e) = self
+                .storage_hub_handler
+                .file_transfer
+                .report_duplicate_chunks(event.peer, event.file_key)
+                .await
+            {
+                error!(target: LOG_TARGET, "Failed to report duplicate chunks sent by peer {:?}: {:?}", event.peer, e);
             }
         }
 
@@ -932,10 +1732,182 @@ where
         return Ok(true);
     }
 
+    /// Discards the local state of a file whose storage request is no longer open on-chain.
+    ///
+    /// Files that are already in this BSP's Forest are left untouched, as they were confirmed and
+    /// their removal is driven by the runtime (i.e. through a priority challenge).
+    /// Any pending confirm storing request for the file is dropped by the BlockchainService.
+    async fn handle_closed_storage_request(&self, file_key: H256) -> anyhow::Result<()> {
+        // Volunteering for the file is pointless now, so stop waiting for it.
+        VolunteerWatchGuard::cancel(&self.volunteer_watches, &file_key);
+
+        let current_forest_key = CURRENT_FOREST_KEY.to_vec();
+        let fs = self
+            .storage_hub_handler
+            .forest_storage_handler
+            .get(&current_forest_key)
+            .await
+            .ok_or_else(|| anyhow!("Failed to get forest storage."))?;
+        if fs.read().await.contains_file_key(&file_key)? {
+            debug!(
+                target: LOG_TARGET,
+                "File key {:x} is already in the Forest, skipping cleanup.",
+                file_key
+            );
+            return Ok(());
+        }
+
+        let is_in_file_storage = self
+            .storage_hub_handler
+            .file_storage
+            .read()
+            .await
+            .file_exists(&file_key)
+            .map_err(|e| anyhow!("Failed to check file existence: {:?}", e))?;
+        if !is_in_file_storage {
+            trace!(
+                target: LOG_TARGET,
+                "File key {:x} is not in file storage, nothing to clean up.",
+                file_key
+            );
+            self.mark_upload_rejected(file_key);
+            return Ok(());
+        }
+
+        self.unvolunteer_file(file_key).await;
+
+        Ok(())
+    }
+
+    /// Deals with a `failure` while handling the chunks uploaded for `file_key`, according to the
+    /// [`UploadFailureAction`] it is classified into, and returns the error to bubble up.
+    ///
+    /// BSPs cannot reject storage requests, so failures attributable to the user only fail the
+    /// current batch, as do transient failures, leaving the user to resend the chunks. A broken
+    /// local state cannot be recovered from by resending chunks though, so the BSP unvolunteers
+    /// the file instead.
+    ///
+    /// The caller must not hold a lock on the file storage.
+    async fn handle_upload_failure(&self, file_key: H256, failure: UploadFailure) -> anyhow::Error {
+        match failure.action() {
+            UploadFailureAction::Ignore => {}
+            UploadFailureAction::RetryLocally => warn!(
+                target: LOG_TARGET,
+                "{} for file key {:x}. Keeping the upload open for the chunks to be resent.",
+                failure,
+                file_key
+            ),
+            UploadFailureAction::Reject(reason) => warn!(
+                target: LOG_TARGET,
+                "{} for file key {:x} ({:?}). Discarding the batch of chunks.",
+                failure,
+                file_key,
+                reason
+            ),
+            UploadFailureAction::AbortAndAlert => {
+                error!(
+                    target: LOG_TARGET,
+                    "ALERT: {} for file key {:x}. The local state of the file is broken, unvolunteering it.",
+                    failure,
+                    file_key
+                );
+                self.unvolunteer_file(file_key).await;
+            }
+        }
+
+        anyhow!("{} for file key {:x}", failure, file_key)
+    }
+
+    /// Waits until the capacity change in flight completes and the earliest block to change the
+    /// capacity again has passed, by when the new capacity is applied on-chain.
+    async fn wait_for_pending_capacity_change(&self, own_bsp_id: H256) -> anyhow::Result<()> {
+        while self.pending_capacity_change.load(Ordering::SeqCst) {
+            let earliest_block = self
+                .storage_hub_handler
+                .blockchain
+                .query_earliest_change_capacity_block(own_bsp_id)
+                .await
+                .map_err(|e| {
+                    anyhow!("Failed to query earliest block to change capacity: {:?}", e)
+                })?;
+            let next_block = Block(
+                self.storage_hub_handler
+                    .blockchain
+                    .get_best_block_info()
+                    .await
+                    .number,
+            )
+            .saturating_add(1);
+
+            self.storage_hub_handler
+                .blockchain
+                .wait_for_block(earliest_block.max(next_block))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Reports the new capacity of the BSP to the telemetry endpoints, if any.
+    ///
+    /// Telemetry is best effort, so failing to query the capacity is only logged.
+    async fn report_capacity_change(&self, own_bsp_id: H256) {
+        let Some(telemetry) = &self.storage_hub_handler.telemetry else {
+            return;
+        };
+
+        let blockchain = &self.storage_hub_handler.blockchain;
+        match (
+            blockchain.query_storage_provider_capacity(own_bsp_id).await,
+            blockchain
+                .query_available_storage_capacity(own_bsp_id)
+                .await,
+        ) {
+            (Ok(capacity), Ok(available)) => telemetry.capacity_changed(
+                capacity,
+                available,
+                self.storage_hub_handler.upload_progress.in_progress_count(),
+            ),
+            (capacity, available) => warn!(
+                target: LOG_TARGET,
+                "Failed to query the capacity to report to telemetry: {:?}, {:?}",
+                capacity.err(),
+                available.err()
+            ),
+        }
+    }
+
+    /// The time to wait for the non-inclusion Forest proof of the files being confirmed.
+    fn forest_proof_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.storage_hub_handler
+                .provider_config
+                .forest_proof_timeout,
+        )
+    }
+
     async fn unvolunteer_file(&self, file_key: H256) {
         warn!(target: LOG_TARGET, "Unvolunteering file {:?}", file_key);
 
-        // Unregister the file from the file transfer service.
+        self.unregister_file(file_key).await;
+
+        let mut write_file_storage = self.storage_hub_handler.file_storage.write().await;
+        if let Err(e) = write_file_storage.delete_file(&file_key) {
+            error!(
+                target: LOG_TARGET,
+                "[unvolunteer_file] Failed to delete file {:?} from file storage: {:?}",
+                file_key,
+                e
+            );
+        }
+        drop(write_file_storage);
+
+        self.mark_upload_rejected(file_key);
+    }
+
+    /// Unregisters the file from the file transfer service, so that no more chunks are accepted
+    /// for it.
+    async fn unregister_file(&self, file_key: H256) {
         // The error is ignored, as the file might already be unregistered.
         if let Err(e) = self
             .storage_hub_handler
@@ -945,21 +1917,397 @@ where
         {
             error!(
                 target: LOG_TARGET,
-                "[unvolunteer_file] Failed to unregister file {:?} from file transfer service: {:?}",
+                "[unregister_file] Failed to unregister file {:?} from file transfer service: {:?}",
                 file_key,
                 e
             );
         }
+    }
 
-        let mut write_file_storage = self.storage_hub_handler.file_storage.write().await;
-        if let Err(e) = write_file_storage.delete_file(&file_key) {
+    /// Marks the upload of the file as rejected, finishing its span.
+    fn mark_upload_rejected(&self, file_key: H256) {
+        self.storage_hub_handler
+            .end_upload(&file_key, UploadState::Rejected);
+    }
+}
+
+/// Drops the `confirm_storing_requests` that have been queued for more than `max_queue_age_secs`
+/// at `now`, returning the dropped requests and the rest.
+fn drop_too_old_confirm_storing_requests(
+    confirm_storing_requests: Vec<ConfirmStoringRequest>,
+    max_queue_age_secs: u64,
+    now: u64,
+) -> (Vec<ConfirmStoringRequest>, Vec<ConfirmStoringRequest>) {
+    let (too_old_confirm_storing_requests, confirm_storing_requests): (Vec<_>, Vec<_>) =
+        confirm_storing_requests
+            .into_iter()
+            .partition(|request| request.is_too_old(max_queue_age_secs, now));
+    for request in &too_old_confirm_storing_requests {
+        error!(target: LOG_TARGET, "Confirm storing request for file {:?} queued for more than {} seconds! Dropping request!", request.file_key, max_queue_age_secs);
+    }
+
+    (too_old_confirm_storing_requests, confirm_storing_requests)
+}
+
+/// The `confirm_storing_requests_to_retry` to queue again after submitting a batch.
+///
+/// If the batch was `confirmed`, the failures of previous attempts do not count against them,
+/// so their `try_count` is reset. Requests failing over and over are still dropped once they
+/// are too old.
+fn confirm_storing_requests_to_requeue(
+    confirm_storing_requests_to_retry: Vec<ConfirmStoringRequest>,
+    confirmed: bool,
+) -> Vec<ConfirmStoringRequest> {
+    if !confirmed {
+        return confirm_storing_requests_to_retry;
+    }
+
+    confirm_storing_requests_to_retry
+        .into_iter()
+        .map(|mut confirm_storing_request| {
+            confirm_storing_request.reset_try_count();
+            confirm_storing_request
+        })
+        .collect()
+}
+
+/// Deletes from `file_storage` the files skipped by the runtime in the [`BspConfirmedStoring`]
+/// event of `bsp_id` found in `events`, returning their file keys.
+///
+/// Files that fail to be deleted are logged and still returned, as they are not stored either way.
+///
+/// [`BspConfirmedStoring`]: pallet_file_system::Event::BspConfirmedStoring
+fn delete_skipped_files<FL>(
+    file_storage: &mut FL,
+    events: &StorageHubEventsVec,
+    bsp_id: H256,
+) -> HashSet<H256>
+where
+    FL: FileStorage<StorageProofsMerkleTrieLayout>,
+{
+    let skipped_file_keys = bsp_confirmed_storing_skipped_file_keys(events, bsp_id);
+    for file_key in &skipped_file_keys {
+        if let Err(e) = file_storage.delete_file(file_key) {
             error!(
                 target: LOG_TARGET,
-                "[unvolunteer_file] Failed to delete file {:?} from file storage: {:?}",
+                "Failed to delete skipped file {:?} from file storage: {:?}",
                 file_key,
                 e
             );
         }
-        drop(write_file_storage);
+    }
+
+    skipped_file_keys
+}
+
+/// Returns the file keys skipped by the runtime in the [`BspConfirmedStoring`] event of `bsp_id`
+/// found in `events`, if any.
+///
+/// [`BspConfirmedStoring`]: pallet_file_system::Event::BspConfirmedStoring
+fn bsp_confirmed_storing_skipped_file_keys(
+    events: &StorageHubEventsVec,
+    bsp_id: H256,
+) -> HashSet<H256> {
+    events
+        .iter()
+        .filter_map(|event_record| match &event_record.event {
+            RuntimeEvent::FileSystem(pallet_file_system::Event::BspConfirmedStoring {
+                bsp_id: confirming_bsp_id,
+                skipped_file_keys,
+                ..
+            }) if *confirming_bsp_id == bsp_id => Some(skipped_file_keys.iter().copied()),
+            _ => None,
+        })
+        .flatten()
+        .collect()
+}
+
+/// The file keys in `forest` that are not in `other_forest`.
+fn files_missing_from_forest<FS, OtherFS>(
+    forest: &FS,
+    other_forest: &OtherFS,
+) -> anyhow::Result<Vec<H256>>
+where
+    FS: ForestStorage<StorageProofsMerkleTrieLayout>,
+    OtherFS: ForestStorage<StorageProofsMerkleTrieLayout>,
+{
+    let mut missing = Vec::new();
+    for (file_key, _) in forest
+        .get_all_files()
+        .map_err(|e| anyhow!("Failed to get all files from Forest: {:?}", e))?
+    {
+        let in_other_forest = other_forest
+            .contains_file_key(&file_key)
+            .map_err(|e| anyhow!("Failed to check if file key is in Forest: {:?}", e))?;
+        if !in_other_forest {
+            missing.push(file_key);
+        }
+    }
+
+    Ok(missing)
+}
+
+/// Marks a capacity change as in flight, in the flag shared by all the clones of a
+/// [`BspUploadFileTask`], until dropped.
+///
+/// Dropping it clears the flag whether the capacity change succeeded or not.
+struct PendingCapacityChangeGuard(Arc<AtomicBool>);
+
+impl PendingCapacityChangeGuard {
+    /// Marks a capacity change as in flight, or returns `None` if one already is.
+    fn try_acquire(pending_capacity_change: &Arc<AtomicBool>) -> Option<Self> {
+        pending_capacity_change
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .ok()
+            .map(|_| Self(pending_capacity_change.clone()))
+    }
+}
+
+impl Drop for PendingCapacityChangeGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// The cancellations of the watches of the volunteers in flight, by file key, with the number of
+/// volunteers in flight for each file.
+type VolunteerWatches = Arc<Mutex<HashMap<H256, (TransactionWatchCancellation, usize)>>>;
+
+/// Registers a volunteer for a file as in flight, in the map shared by all the clones of a
+/// [`BspUploadFileTask`], until dropped.
+///
+/// The volunteers in flight for the same file share the same cancellation, so that closing the
+/// storage request of the file cancels the watches of all of them.
+struct VolunteerWatchGuard {
+    volunteer_watches: VolunteerWatches,
+    file_key: H256,
+    cancellation: TransactionWatchCancellation,
+}
+
+impl VolunteerWatchGuard {
+    fn register(volunteer_watches: &VolunteerWatches, file_key: H256) -> Self {
+        let mut watches = volunteer_watches
+            .lock()
+            .expect("Volunteer watches lock poisoned");
+        let (cancellation, in_flight) = watches.entry(file_key).or_default();
+        *in_flight += 1;
+        Self {
+            volunteer_watches: volunteer_watches.clone(),
+            file_key,
+            cancellation: cancellation.clone(),
+        }
+    }
+
+    /// Cancels the watches of the volunteers in flight for `file_key`, if any.
+    fn cancel(volunteer_watches: &VolunteerWatches, file_key: &H256) {
+        let watches = volunteer_watches
+            .lock()
+            .expect("Volunteer watches lock poisoned");
+        if let Some((cancellation, _)) = watches.get(file_key) {
+            cancellation.cancel();
+        }
+    }
+
+    fn cancellation(&self) -> TransactionWatchCancellation {
+        self.cancellation.clone()
+    }
+}
+
+impl Drop for VolunteerWatchGuard {
+    fn drop(&mut self) {
+        let mut watches = self
+            .volunteer_watches
+            .lock()
+            .expect("Volunteer watches lock poisoned");
+        if let Some((_, in_flight)) = watches.get_mut(&self.file_key) {
+            *in_flight -= 1;
+            if *in_flight == 0 {
+                watches.remove(&self.file_key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use frame_system::{EventRecord, Phase};
+    use shc_common::types::{Chunk, ChunkId, FILE_CHUNK_SIZE};
+    use shc_file_manager::{in_memory::InMemoryFileStorage, traits::FileDataTrie};
+    use shc_forest_manager::in_memory::InMemoryForestStorage;
+
+    use super::*;
+
+    /// Inserts a complete single-chunk file in `file_storage`, returning its file key.
+    fn insert_file(
+        file_storage: &mut InMemoryFileStorage<StorageProofsMerkleTrieLayout>,
+        seed: u8,
+    ) -> H256 {
+        let chunk = Chunk::from([seed; FILE_CHUNK_SIZE as usize]);
+        let mut file_trie = file_storage.new_file_data_trie();
+        file_trie.write_chunk(&ChunkId::new(0), &chunk).unwrap();
+
+        let file_metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&AccountId32::new([0u8; 32])).to_vec(),
+            [1u8; 32].to_vec(),
+            vec![seed],
+            FILE_CHUNK_SIZE,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+
+        let file_key = file_metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+        file_storage
+            .insert_file_with_data(file_key, file_metadata, file_trie)
+            .unwrap();
+        file_key
+    }
+
+    #[test]
+    fn stale_confirm_storing_requests_are_dropped() {
+        let request = |byte, enqueued_at| ConfirmStoringRequest {
+            file_key: H256::repeat_byte(byte),
+            try_count: 0,
+            enqueued_at,
+        };
+        let requests = vec![request(1, 100), request(2, 50), request(3, 139)];
+
+        let file_keys = |requests: Vec<ConfirmStoringRequest>| {
+            requests.iter().map(|r| r.file_key).collect::<Vec<_>>()
+        };
+        let (too_old_requests, requests_left) =
+            drop_too_old_confirm_storing_requests(requests.clone(), 40, 140);
+        assert_eq!(file_keys(too_old_requests), vec![H256::repeat_byte(2)]);
+        assert_eq!(
+            file_keys(requests_left),
+            vec![H256::repeat_byte(1), H256::repeat_byte(3)]
+        );
+        // A maximum age of 0 keeps every request.
+        let (too_old_requests, requests_left) =
+            drop_too_old_confirm_storing_requests(requests, 0, 140);
+        assert!(too_old_requests.is_empty());
+        assert_eq!(requests_left.len(), 3);
+    }
+
+    #[test]
+    fn retried_requests_start_afresh_only_after_a_confirmation() {
+        let mut request = ConfirmStoringRequest::new(H256::repeat_byte(1));
+        request.increment_try_count();
+        request.increment_try_count();
+
+        let requeued = confirm_storing_requests_to_requeue(vec![request.clone()], false);
+        assert_eq!(requeued[0].try_count, 2);
+
+        let requeued = confirm_storing_requests_to_requeue(vec![request.clone()], true);
+        assert_eq!(requeued[0].try_count, 0);
+        assert_eq!(requeued[0].enqueued_at, request.enqueued_at);
+    }
+
+    #[test]
+    fn skipped_files_are_removed_from_file_storage() {
+        let bsp_id = H256::repeat_byte(1);
+        let mut file_storage = InMemoryFileStorage::<StorageProofsMerkleTrieLayout>::new();
+        let file_keys = (0..3)
+            .map(|seed| insert_file(&mut file_storage, seed))
+            .collect::<Vec<_>>();
+
+        // The runtime skips the second file for this BSP, and the first one for another BSP.
+        let confirmed_storing = |bsp_id: H256, confirmed: Vec<H256>, skipped: Vec<H256>| {
+            Box::new(EventRecord {
+                phase: Phase::ApplyExtrinsic(1),
+                event: RuntimeEvent::FileSystem(pallet_file_system::Event::BspConfirmedStoring {
+                    who: AccountId32::new([0u8; 32]),
+                    bsp_id,
+                    confirmed_file_keys: BoundedVec::truncate_from(confirmed),
+                    skipped_file_keys: BoundedVec::truncate_from(skipped),
+                    new_root: H256::zero(),
+                }),
+                topics: Vec::new(),
+            })
+        };
+        let events: StorageHubEventsVec = vec![
+            confirmed_storing(bsp_id, vec![file_keys[0], file_keys[2]], vec![file_keys[1]]),
+            confirmed_storing(H256::repeat_byte(2), Vec::new(), vec![file_keys[0]]),
+        ];
+
+        let skipped_file_keys = delete_skipped_files(&mut file_storage, &events, bsp_id);
+        assert_eq!(skipped_file_keys, HashSet::from([file_keys[1]]));
+
+        assert!(file_storage.get_metadata(&file_keys[1]).unwrap().is_none());
+        for file_key in [file_keys[0], file_keys[2]] {
+            assert!(file_storage.get_metadata(&file_key).unwrap().is_some());
+            assert!(file_storage.is_file_complete(&file_key).unwrap());
+        }
+    }
+
+    #[test]
+    fn files_confirmed_only_in_the_retracted_fork_are_found() {
+        let mut file_storage = InMemoryFileStorage::<StorageProofsMerkleTrieLayout>::new();
+        let metadata = (0..3)
+            .map(|seed| {
+                let file_key = insert_file(&mut file_storage, seed);
+                file_storage.get_metadata(&file_key).unwrap().unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        // The third file was confirmed in the retracted fork only.
+        let mut retracted_forest = InMemoryForestStorage::<StorageProofsMerkleTrieLayout>::new();
+        retracted_forest.insert_files_metadata(&metadata).unwrap();
+        let mut current_forest = InMemoryForestStorage::<StorageProofsMerkleTrieLayout>::new();
+        current_forest
+            .insert_files_metadata(&metadata[..2])
+            .unwrap();
+
+        assert_eq!(
+            files_missing_from_forest(&retracted_forest, &current_forest).unwrap(),
+            vec![metadata[2].file_key::<HashT<StorageProofsMerkleTrieLayout>>()]
+        );
+        assert!(
+            files_missing_from_forest(&current_forest, &retracted_forest)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn only_one_capacity_change_is_in_flight_at_a_time() {
+        let pending_capacity_change = Arc::new(AtomicBool::new(false));
+
+        let guard = PendingCapacityChangeGuard::try_acquire(&pending_capacity_change)
+            .expect("No capacity change in flight yet");
+        assert!(pending_capacity_change.load(Ordering::SeqCst));
+        assert!(PendingCapacityChangeGuard::try_acquire(&pending_capacity_change).is_none());
+
+        drop(guard);
+        assert!(!pending_capacity_change.load(Ordering::SeqCst));
+        assert!(PendingCapacityChangeGuard::try_acquire(&pending_capacity_change).is_some());
+    }
+
+    #[test]
+    fn closing_a_storage_request_cancels_the_volunteers_in_flight_for_its_file() {
+        let volunteer_watches = VolunteerWatches::default();
+        let file_key = H256::repeat_byte(1);
+        let other_file_key = H256::repeat_byte(2);
+
+        let first = VolunteerWatchGuard::register(&volunteer_watches, file_key);
+        let second = VolunteerWatchGuard::register(&volunteer_watches, file_key);
+        let other = VolunteerWatchGuard::register(&volunteer_watches, other_file_key);
+
+        VolunteerWatchGuard::cancel(&volunteer_watches, &file_key);
+        assert!(first.cancellation().is_cancelled());
+        assert!(second.cancellation().is_cancelled());
+        assert!(!other.cancellation().is_cancelled());
+
+        // The file stays registered until all its volunteers are done.
+        drop(first);
+        assert!(volunteer_watches.lock().unwrap().contains_key(&file_key));
+        drop(second);
+        assert!(!volunteer_watches.lock().unwrap().contains_key(&file_key));
+
+        // A later volunteer for the file is not cancelled by the previous closure.
+        let later = VolunteerWatchGuard::register(&volunteer_watches, file_key);
+        assert!(!later.cancellation().is_cancelled());
+        drop(other);
+        drop(later);
+        assert!(volunteer_watches.lock().unwrap().is_empty());
     }
 }
@@ -0,0 +1,397 @@
+use std::collections::BTreeSet;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use sc_tracing::tracing::*;
+use sp_core::H256;
+
+use shc_blockchain_service::commands::BlockchainServiceInterface;
+use shc_common::{
+    consts::CURRENT_FOREST_KEY,
+    types::{FileMetadata, HashT, StorageProofsMerkleTrieLayout, StorageProviderId},
+};
+use shc_file_manager::traits::{FileStorage, FileStorageError};
+use shc_forest_manager::{
+    recovery::{forest_root_of, reconcile_forest},
+    traits::{ForestStorage, ForestStorageHandler},
+};
+use shc_rpc::storage_verification::{
+    ForestDiff, ForestVerification, StorageVerification, StorageVerificationReport,
+};
+
+use crate::{
+    services::{
+        handler::StorageHubHandler,
+        types::{BspForestStorageHandlerT, ShNodeType},
+    },
+    tasks::bsp_repair_file::BspRepairFileTask,
+};
+
+const LOG_TARGET: &str = "bsp-verify-storage-task";
+
+/// Number of files verified between two saves of the checkpoint of the verification, which are
+/// also when its progress is logged.
+const FILES_PER_CHECKPOINT: usize = 100;
+
+/// BSP Verify Storage Task: Verifies the integrity of the whole File Storage of the BSP, and
+/// reconciles its Forest with the on-chain root.
+///
+/// It is meant to be run after restoring the node from a backup, in which the chunks of the
+/// files, the data derived from them and the Forest may have been saved at different times. It
+/// runs on startup if the node is started with `--verify-storage-on-startup`, in which case the
+/// proof tasks are only started once it reconciled the Forest, and it can be triggered through
+/// the `verifyStorage` RPC method. It goes as follows:
+/// - Verifies every file in the File Storage against its fingerprint, rebuilding its stored
+///   chunks count and current root from its chunks (see [`FileStorage::verify_and_repair_file`]).
+///   Files are verified in order of their keys, and the last verified key is regularly persisted,
+///   so that a verification interrupted by a restart resumes where it stopped.
+/// - Compares the local root of the Forest with the on-chain root of the BSP. If they don't
+///   match, the Forest is rebuilt from the metadata of the complete files in the File Storage,
+///   as long as they make up the on-chain root. Otherwise, the differences between the Forest and
+///   the File Storage are logged, and the Forest is left untouched.
+/// - Repairs the files in the Forest that are not complete in the File Storage, from the other
+///   providers storing them (see [`BspRepairFileTask`]).
+///
+/// A single file can also be verified and repaired through the `repairFile` RPC method, which
+/// sends the `RepairFile` command handled by the [`BspRepairFileTask`].
+pub struct BspVerifyStorageTask<NT>
+where
+    NT: ShNodeType,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    storage_hub_handler: StorageHubHandler<NT>,
+}
+
+impl<NT> Clone for BspVerifyStorageTask<NT>
+where
+    NT: ShNodeType,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    fn clone(&self) -> BspVerifyStorageTask<NT> {
+        Self {
+            storage_hub_handler: self.storage_hub_handler.clone(),
+        }
+    }
+}
+
+impl<NT> BspVerifyStorageTask<NT>
+where
+    NT: ShNodeType,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    pub fn new(storage_hub_handler: StorageHubHandler<NT>) -> Self {
+        Self {
+            storage_hub_handler,
+        }
+    }
+}
+
+#[async_trait]
+impl<NT> StorageVerification for BspVerifyStorageTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    async fn verify_storage(&self) -> anyhow::Result<StorageVerificationReport> {
+        self.verify_bsp_storage().await
+    }
+
+    async fn repair_file(&self, file_key: H256) -> anyhow::Result<()> {
+        self.storage_hub_handler
+            .blockchain
+            .repair_file(file_key)
+            .await
+    }
+}
+
+impl<NT> BspVerifyStorageTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    /// Verifies the storage of the BSP before it starts submitting proofs.
+    ///
+    /// Returns whether the Forest matches the on-chain root, so that the proof tasks can be
+    /// started.
+    pub async fn verify_on_startup(self) -> bool {
+        info!(target: LOG_TARGET, "🔍 Verifying the File Storage and Forest of the BSP before starting the proof tasks");
+
+        match self.verify_bsp_storage().await {
+            Ok(report) if report.forest_reconciled() => true,
+            Ok(_) => {
+                error!(target: LOG_TARGET, "CRITICAL ❗️❗️ The Forest of the BSP could not be reconciled with its on-chain root. Refusing to start the proof tasks. Once the storage is fixed, restart the node.");
+                false
+            }
+            Err(e) => {
+                error!(target: LOG_TARGET, "CRITICAL ❗️❗️ Failed to verify the storage of the BSP. Refusing to start the proof tasks. \nError: {:?}", e);
+                false
+            }
+        }
+    }
+
+    /// Verifies every file in the File Storage, resuming an interrupted verification if any, and
+    /// then checks the Forest against the on-chain root.
+    async fn verify_bsp_storage(&self) -> anyhow::Result<StorageVerificationReport> {
+        let blockchain = &self.storage_hub_handler.blockchain;
+        let file_storage = &self.storage_hub_handler.file_storage;
+
+        let file_keys = file_storage
+            .read()
+            .await
+            .file_keys()
+            .map_err(|e| anyhow!("Failed to list the files in the File Storage: {:?}", e))?;
+        let total_files = file_keys.len();
+
+        let checkpoint = blockchain.query_storage_verification_checkpoint().await;
+        let already_verified = files_verified_before(&file_keys, checkpoint);
+        if already_verified > 0 {
+            info!(target: LOG_TARGET, "Resuming the verification of the File Storage after file [{:?}], with {} of {} files already verified", checkpoint, already_verified, total_files);
+        }
+
+        let mut repaired_file_keys = Vec::new();
+        for (index, file_key) in file_keys.iter().enumerate().skip(already_verified) {
+            let verification = file_storage.read().await.verify_and_repair_file(file_key);
+            match verification {
+                Ok(verification) => {
+                    if verification.repaired {
+                        repaired_file_keys.push(*file_key);
+                    }
+                }
+                // The file was deleted since the files were listed.
+                Err(FileStorageError::FileDoesNotExist) => {}
+                Err(e) => {
+                    return Err(anyhow!("Failed to verify file [{:?}]: {:?}", file_key, e));
+                }
+            }
+
+            let verified_files = index + 1;
+            if verified_files % FILES_PER_CHECKPOINT == 0 || verified_files == total_files {
+                blockchain
+                    .set_storage_verification_checkpoint(Some(*file_key))
+                    .await?;
+                info!(target: LOG_TARGET, "🔍 Verified {} of {} files ({:.1}%)", verified_files, total_files, verified_files as f64 * 100.0 / total_files as f64);
+            }
+        }
+        blockchain.set_storage_verification_checkpoint(None).await?;
+
+        let (complete_files, incomplete_file_keys) = self.complete_files(&file_keys).await?;
+        info!(target: LOG_TARGET, "🔍 Verified the File Storage: {} complete file(s), {} incomplete file(s), {} file(s) repaired", complete_files.len(), incomplete_file_keys.len(), repaired_file_keys.len());
+
+        let forest = self.verify_forest(&complete_files).await?;
+
+        Ok(StorageVerificationReport {
+            verified_files: total_files as u64,
+            repaired_file_keys,
+            incomplete_file_keys,
+            forest,
+        })
+    }
+
+    /// Splits `file_keys` into the metadata of the complete files in the File Storage, and the
+    /// keys of the incomplete ones.
+    async fn complete_files(
+        &self,
+        file_keys: &[H256],
+    ) -> anyhow::Result<(Vec<FileMetadata>, Vec<H256>)> {
+        let read_file_storage = self.storage_hub_handler.file_storage.read().await;
+
+        let mut complete_files = Vec::new();
+        let mut incomplete_file_keys = Vec::new();
+        for file_key in file_keys {
+            let complete = match read_file_storage.is_file_complete(file_key) {
+                Ok(complete) => complete,
+                Err(FileStorageError::FileDoesNotExist) => continue,
+                Err(e) => {
+                    return Err(anyhow!(
+                        "Failed to check if file [{:?}] is complete: {:?}",
+                        file_key,
+                        e
+                    ))
+                }
+            };
+            let metadata = read_file_storage
+                .get_metadata(file_key)
+                .map_err(|e| anyhow!("Failed to get metadata of file [{:?}]: {:?}", file_key, e))?;
+
+            match metadata {
+                Some(metadata) if complete => complete_files.push(metadata),
+                Some(_) => incomplete_file_keys.push(*file_key),
+                None => {}
+            }
+        }
+
+        Ok((complete_files, incomplete_file_keys))
+    }
+
+    /// Checks the Forest against the on-chain root, rebuilding it from `complete_files` if they
+    /// make up the on-chain root.
+    async fn verify_forest(
+        &self,
+        complete_files: &[FileMetadata],
+    ) -> anyhow::Result<ForestVerification> {
+        let bsp_id = match self
+            .storage_hub_handler
+            .blockchain
+            .query_storage_provider_id(None)
+            .await?
+        {
+            Some(StorageProviderId::BackupStorageProvider(id)) => id,
+            Some(StorageProviderId::MainStorageProvider(_)) => {
+                return Err(anyhow!(
+                    "Current node account is a Main Storage Provider. Expected a Backup Storage Provider ID."
+                ));
+            }
+            None => {
+                return Err(anyhow!("Failed to get own BSP ID."));
+            }
+        };
+
+        let on_chain_root = self
+            .storage_hub_handler
+            .blockchain
+            .query_provider_forest_root(bsp_id)
+            .await
+            .map_err(|e| anyhow!("Failed to query the on-chain root of the BSP: {:?}", e))?;
+
+        let fs = self
+            .storage_hub_handler
+            .forest_storage_handler
+            .get(&CURRENT_FOREST_KEY.to_vec())
+            .await
+            .ok_or_else(|| anyhow!("Failed to get the BSP Forest Storage."))?;
+
+        let rebuilt_root = forest_root_of::<StorageProofsMerkleTrieLayout>(complete_files)
+            .map_err(|e| anyhow!("Failed to compute the root of the rebuilt Forest: {:?}", e))?;
+        let complete_file_keys: BTreeSet<H256> = complete_files
+            .iter()
+            .map(|metadata| metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>())
+            .collect();
+
+        let mut write_fs = fs.write().await;
+        let local_root = write_fs.root();
+        let forest_file_keys: BTreeSet<H256> = write_fs
+            .get_all_files()
+            .map_err(|e| anyhow!("Failed to read the files in the Forest: {:?}", e))?
+            .into_iter()
+            .map(|(file_key, _)| file_key)
+            .collect();
+        let (missing_from_file_storage, missing_from_forest) =
+            forest_diff(&forest_file_keys, &complete_file_keys);
+
+        if local_root == on_chain_root {
+            drop(write_fs);
+            info!(target: LOG_TARGET, "🌳 Local Forest is in sync with its on-chain root [{:?}]", on_chain_root);
+            self.repair_files(&missing_from_file_storage);
+            return Ok(ForestVerification::InSync(on_chain_root));
+        }
+
+        if rebuilt_root == on_chain_root {
+            reconcile_forest::<StorageProofsMerkleTrieLayout, _>(&mut *write_fs, complete_files)
+                .map_err(|e| {
+                    anyhow!(
+                        "Failed to rebuild the Forest from the complete files: {:?}",
+                        e
+                    )
+                })?;
+            drop(write_fs);
+            info!(target: LOG_TARGET, "🌳 Rebuilt the local Forest from root [{:?}] to the on-chain root [{:?}], removing {} file(s) and inserting {} file(s)", local_root, on_chain_root, missing_from_file_storage.len(), missing_from_forest.len());
+            return Ok(ForestVerification::Rebuilt {
+                previous_root: local_root,
+                on_chain_root,
+            });
+        }
+        drop(write_fs);
+
+        error!(target: LOG_TARGET, "Local Forest root [{:?}] and the root [{:?}] of the complete files in the File Storage both differ from the on-chain root [{:?}]", local_root, rebuilt_root, on_chain_root);
+        error!(target: LOG_TARGET, "File keys in the Forest but not complete in the File Storage: {:?}", missing_from_file_storage);
+        error!(target: LOG_TARGET, "File keys complete in the File Storage but not in the Forest: {:?}", missing_from_forest);
+
+        Ok(ForestVerification::Diverged(ForestDiff {
+            local_root,
+            rebuilt_root,
+            on_chain_root,
+            missing_from_file_storage,
+            missing_from_forest,
+        }))
+    }
+
+    /// Spawns the repair of the files with `file_keys` from the other providers storing them.
+    fn repair_files(&self, file_keys: &[H256]) {
+        for file_key in file_keys.iter().copied() {
+            warn!(target: LOG_TARGET, "File [{:?}] is in the Forest but not complete in the File Storage. Repairing it.", file_key);
+
+            let repair_task = BspRepairFileTask::new(self.storage_hub_handler.clone());
+            self.storage_hub_handler.task_spawner.spawn(async move {
+                if let Err(e) = repair_task.repair_file(file_key).await {
+                    error!(target: LOG_TARGET, "Failed to repair file [{:?}]: {:?}", file_key, e);
+                }
+            });
+        }
+    }
+}
+
+/// Number of `file_keys`, sorted in ascending order, already verified by a verification
+/// interrupted after verifying `checkpoint`.
+fn files_verified_before(file_keys: &[H256], checkpoint: Option<H256>) -> usize {
+    match checkpoint {
+        Some(checkpoint) => file_keys.partition_point(|file_key| *file_key <= checkpoint),
+        None => 0,
+    }
+}
+
+/// The file keys only in `forest_file_keys`, and the ones only in `complete_file_keys`.
+fn forest_diff(
+    forest_file_keys: &BTreeSet<H256>,
+    complete_file_keys: &BTreeSet<H256>,
+) -> (Vec<H256>, Vec<H256>) {
+    (
+        forest_file_keys
+            .difference(complete_file_keys)
+            .copied()
+            .collect(),
+        complete_file_keys
+            .difference(forest_file_keys)
+            .copied()
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verification_resumes_after_the_checkpoint() {
+        let file_keys: Vec<H256> = (1..=4).map(H256::repeat_byte).collect();
+
+        assert_eq!(files_verified_before(&file_keys, None), 0);
+        assert_eq!(
+            files_verified_before(&file_keys, Some(H256::repeat_byte(2))),
+            2
+        );
+        // The file at the checkpoint may have been deleted since.
+        assert_eq!(
+            files_verified_before(&file_keys[1..], Some(H256::repeat_byte(1))),
+            0
+        );
+        assert_eq!(
+            files_verified_before(&file_keys, Some(H256::repeat_byte(4))),
+            4
+        );
+    }
+
+    #[test]
+    fn forest_diff_lists_the_keys_missing_on_each_side() {
+        let forest_file_keys: BTreeSet<H256> = (1..=3).map(H256::repeat_byte).collect();
+        let complete_file_keys: BTreeSet<H256> = (2..=4).map(H256::repeat_byte).collect();
+
+        assert_eq!(
+            forest_diff(&forest_file_keys, &complete_file_keys),
+            (vec![H256::repeat_byte(1)], vec![H256::repeat_byte(4)])
+        );
+        assert_eq!(
+            forest_diff(&forest_file_keys, &forest_file_keys),
+            (vec![], vec![])
+        );
+    }
+}
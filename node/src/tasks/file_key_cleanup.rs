@@ -0,0 +1,157 @@
+use std::future::Future;
+
+use sp_core::H256;
+
+/// The file key to clean up if handling a storage request fails.
+///
+/// Upload tasks are cloned and handle many storage requests concurrently, so the file to clean
+/// up is scoped to a single invocation of the handler instead of being kept in the task itself.
+/// Otherwise, a failing storage request could clean up the file of another one in flight.
+#[derive(Debug, Default)]
+pub(crate) struct FileKeyCleanup {
+    file_key: Option<H256>,
+}
+
+impl FileKeyCleanup {
+    /// Marks `file_key` to be cleaned up if handling the storage request fails from now on.
+    pub fn set(&mut self, file_key: H256) {
+        self.file_key = Some(file_key);
+    }
+
+    /// Cleans up the file set to be cleaned up with `clean_up`, if `result` of handling the
+    /// storage request is an error.
+    ///
+    /// Nothing is cleaned up if the storage request was handled successfully, or if it failed
+    /// before any file was set to be cleaned up. Returns `result`, unless cleaning up fails.
+    pub async fn clean_up_on_failure<T, F, Fut>(
+        self,
+        result: anyhow::Result<T>,
+        clean_up: F,
+    ) -> anyhow::Result<T>
+    where
+        F: FnOnce(H256) -> Fut,
+        Fut: Future<Output = anyhow::Result<()>>,
+    {
+        if let (Err(_), Some(file_key)) = (&result, self.file_key) {
+            clean_up(file_key).await?;
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    use shc_common::types::{
+        FileMetadata, Fingerprint, HashT, HasherOutT, StorageProofsMerkleTrieLayout,
+    };
+    use shc_file_manager::{in_memory::InMemoryFileStorage, traits::FileStorage};
+
+    type TestFileStorage = Arc<RwLock<InMemoryFileStorage<StorageProofsMerkleTrieLayout>>>;
+
+    fn file_metadata(location: &str) -> FileMetadata {
+        FileMetadata::new(
+            [1u8; 32].to_vec(),
+            [2u8; 32].to_vec(),
+            location.as_bytes().to_vec(),
+            1024,
+            Fingerprint::from([3u8; 32]),
+        )
+        .unwrap()
+    }
+
+    fn file_key(metadata: &FileMetadata) -> HasherOutT<StorageProofsMerkleTrieLayout> {
+        metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>()
+    }
+
+    /// Handles a storage request the way the upload tasks do: the file is stored and set to be
+    /// cleaned up, and the request then fails if `fail` is set.
+    async fn handle_storage_request(
+        file_storage: TestFileStorage,
+        metadata: FileMetadata,
+        fail: bool,
+    ) -> anyhow::Result<()> {
+        let mut file_key_cleanup = FileKeyCleanup::default();
+        let result = async {
+            file_storage
+                .write()
+                .await
+                .insert_file(file_key(&metadata), metadata.clone())
+                .map_err(|e| anyhow!("Failed to insert file: {:?}", e))?;
+            file_key_cleanup.set(file_key(&metadata));
+
+            // Let the other storage requests in flight make progress.
+            tokio::task::yield_now().await;
+
+            if fail {
+                Err(anyhow!("Failed to volunteer"))
+            } else {
+                Ok(())
+            }
+        }
+        .await;
+
+        file_key_cleanup
+            .clean_up_on_failure(result, |file_key| async move {
+                file_storage
+                    .write()
+                    .await
+                    .delete_file(&file_key)
+                    .map_err(|e| anyhow!("Failed to delete file: {:?}", e))
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn only_the_failed_storage_request_is_cleaned_up() {
+        let file_storage = Arc::new(RwLock::new(InMemoryFileStorage::new()));
+        let failing = file_metadata("failing");
+        let succeeding = file_metadata("succeeding");
+
+        let (failing_result, succeeding_result) = tokio::join!(
+            handle_storage_request(file_storage.clone(), failing.clone(), true),
+            handle_storage_request(file_storage.clone(), succeeding.clone(), false),
+        );
+        assert!(failing_result.is_err());
+        assert!(succeeding_result.is_ok());
+
+        let file_storage = file_storage.read().await;
+        assert!(file_storage
+            .get_metadata(&file_key(&failing))
+            .unwrap()
+            .is_none());
+        assert!(file_storage
+            .get_metadata(&file_key(&succeeding))
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn nothing_is_cleaned_up_if_failing_before_setting_a_file_key() {
+        let result: anyhow::Result<()> = Err(anyhow!("File size cannot be 0"));
+
+        let result = FileKeyCleanup::default()
+            .clean_up_on_failure(result, |_| async { Err(anyhow!("No file to clean up")) })
+            .await;
+
+        assert_eq!(result.unwrap_err().to_string(), "File size cannot be 0");
+    }
+
+    #[tokio::test]
+    async fn failing_to_clean_up_is_returned() {
+        let mut file_key_cleanup = FileKeyCleanup::default();
+        file_key_cleanup.set(H256::repeat_byte(1));
+        let result: anyhow::Result<()> = Err(anyhow!("Failed to volunteer"));
+
+        let result = file_key_cleanup
+            .clean_up_on_failure(result, |_| async { Err(anyhow!("Failed to delete file")) })
+            .await;
+
+        assert_eq!(result.unwrap_err().to_string(), "Failed to delete file");
+    }
+}
@@ -2,15 +2,24 @@ pub mod bsp_charge_fees;
 pub mod bsp_delete_file;
 pub mod bsp_download_file;
 pub mod bsp_move_bucket;
+pub mod bsp_recover_forest;
+pub mod bsp_repair_file;
 pub mod bsp_submit_proof;
 pub mod bsp_upload_file;
+pub mod bsp_verify_storage;
+mod file_key_cleanup;
 pub mod mock_bsp_volunteer;
 pub mod mock_sp_react_to_event;
 pub mod msp_charge_fees;
 pub mod msp_delete_bucket;
 pub mod msp_delete_file;
 pub mod msp_move_bucket;
+pub mod msp_recover_forest;
+pub mod msp_retrieve_file;
 pub mod msp_stop_storing_insolvent_user;
 pub mod msp_upload_file;
+mod proof_generation;
+pub mod sp_forest_root_health_check;
 pub mod sp_slash_provider;
+mod upload_failure;
 pub mod user_sends_file;
@@ -11,8 +11,10 @@ use shc_blockchain_service::{
     },
     types::{self, RetryStrategy},
 };
+use shc_common::types::{ForestProof, StorageProofsMerkleTrieLayout};
 use shc_file_manager::traits::FileStorage;
 use shc_forest_manager::traits::{ForestStorage, ForestStorageHandler};
+use sp_core::H256;
 
 use crate::services::{
     handler::StorageHubHandler,
@@ -154,81 +156,26 @@ where
             delete_file_request.file_key
         );
 
-        // Get the forest storage for the bucket
-        let forest_storage = self
-            .storage_hub_handler
-            .forest_storage_handler
-            .get(&delete_file_request.bucket_id.as_ref().to_vec())
-            .await
-            .ok_or_else(|| anyhow!("Failed to get forest storage."))?;
-
-        // Acquire write lock once for the entire operation
-        let forest_storage_read = forest_storage.read().await;
-
-        // TODO: Pass multiple file keys to generate_proof once batching is supported by the runtime.
-        let forest_proof =
-            forest_storage_read.generate_proof(vec![delete_file_request.file_key.into()])?;
-
-        drop(forest_storage_read);
-
-        // Build and submit extrinsic
-        let call = storage_hub_runtime::RuntimeCall::FileSystem(
-            pallet_file_system::Call::pending_file_deletion_request_submit_proof {
-                user: delete_file_request.user.clone(),
-                file_key: delete_file_request.file_key.into(),
-                file_size: delete_file_request.file_size,
-                bucket_id: delete_file_request.bucket_id,
-                forest_proof: forest_proof.proof.clone(),
-            },
-        );
-
-        // Submit extrinsic with retry and wait for it to be included in a block
-        self.storage_hub_handler
-            .blockchain
-            .submit_extrinsic_with_retry(
-                call,
-                RetryStrategy::default()
-                    .with_max_retries(MAX_DELETE_FILE_REQUEST_TRY_COUNT)
-                    .with_max_tip(MAX_DELETE_FILE_REQUEST_TIP as f64)
-                    .with_timeout(Duration::from_secs(
-                        self.storage_hub_handler
-                            .provider_config
-                            .extrinsic_retry_timeout,
-                    ))
-                    .retry_only_if_timeout(),
-                false,
-            )
+        if let Err(e) = self
+            .process_file_deletion_request(delete_file_request)
             .await
-            .map_err(|e| {
-                anyhow!(
-                    "Failed to submit file deletion proof after {} retries: {:?}",
-                    MAX_DELETE_FILE_REQUEST_TRY_COUNT,
-                    e
-                )
-            })?;
-
-        if forest_proof.contains_file_key(&delete_file_request.file_key.into()) {
-            let mut forest_storage_write = forest_storage.write().await;
-            // Delete the file key from forest storage
-            forest_storage_write
-                .delete_file_key(&delete_file_request.file_key.into())
-                .map_err(|e| {
-                    let err_msg = format!(
-                        "CRITICAL❗️❗️ Failed to remove file key from Forest storage after remove delta was applied on chain for file_key {:?}, error: {:?}",
-                        delete_file_request.file_key,
-                        e
-                    );
-                    error!(target: LOG_TARGET, err_msg);
-                    anyhow!(err_msg)
-                })?;
+        {
+            // The request was popped from the persistent queue, so it is queued again to retry
+            // it, unless it already failed too many times.
+            match file_deletion_request_to_retry(delete_file_request) {
+                Some(request) => {
+                    error!(target: LOG_TARGET, "Failed to process file deletion request for file_key {:?}: {:?}\nEnqueuing it again! (retry {}/{})", request.file_key, e, request.try_count, MAX_DELETE_FILE_REQUEST_TRY_COUNT);
+                    self.storage_hub_handler
+                        .blockchain
+                        .queue_file_deletion_request(request)
+                        .await?;
+                }
+                None => {
+                    error!(target: LOG_TARGET, "Failed to process file deletion request for file_key {:?}: {:?}\nMax try count exceeded! Dropping request!", delete_file_request.file_key, e);
+                }
+            }
         }
 
-        info!(
-            target: LOG_TARGET,
-            "Successfully processed file deletion request for file_key {:x}",
-            delete_file_request.file_key
-        );
-
         // Release the forest root write lock
         self.storage_hub_handler
             .blockchain
@@ -302,3 +249,232 @@ where
         Ok(())
     }
 }
+
+impl<NT> MspDeleteFileTask<NT>
+where
+    NT: ShNodeType,
+    NT::FSH: MspForestStorageHandlerT,
+{
+    /// Submits the Forest proof for `delete_file_request` and, if the file key was in the
+    /// bucket's Forest, removes it from there.
+    ///
+    /// The file data is only deleted from the File Storage once the proof submission is
+    /// finalised, in the [`FinalisedProofSubmittedForPendingFileDeletionRequest`] handler.
+    async fn process_file_deletion_request(
+        &self,
+        delete_file_request: &types::FileDeletionRequest,
+    ) -> anyhow::Result<()> {
+        // Get the forest storage for the bucket
+        let forest_storage = self
+            .storage_hub_handler
+            .forest_storage_handler
+            .get(&delete_file_request.bucket_id.as_ref().to_vec())
+            .await
+            .ok_or_else(|| {
+                anyhow!(
+                    "Failed to get forest storage for bucket {:?}.",
+                    delete_file_request.bucket_id
+                )
+            })?;
+
+        // Generate the proof from the snapshot of the bucket's current Forest root, which is shared
+        // with the other proofs generated against this same root. Fall back to the bucket's
+        // Forest if its root changed in the meantime.
+        let forest_root = forest_storage.read().await.root();
+        let proof_forest_storage = self
+            .storage_hub_handler
+            .forest_storage_handler
+            .snapshot_by_root(
+                &delete_file_request.bucket_id.as_ref().to_vec(),
+                &forest_root,
+            )
+            .await
+            .unwrap_or_else(|| forest_storage.clone());
+
+        // TODO: Pass multiple file keys to generate_proof once batching is supported by the runtime.
+        let forest_proof = generate_file_deletion_proof(
+            &*proof_forest_storage.read().await,
+            &delete_file_request.file_key,
+        )?;
+
+        // Build and submit extrinsic
+        let call = storage_hub_runtime::RuntimeCall::FileSystem(
+            pallet_file_system::Call::pending_file_deletion_request_submit_proof {
+                user: delete_file_request.user.clone(),
+                file_key: delete_file_request.file_key.into(),
+                file_size: delete_file_request.file_size,
+                bucket_id: delete_file_request.bucket_id,
+                forest_proof: forest_proof.proof.clone(),
+            },
+        );
+
+        // Submit extrinsic with retry and wait for it to be included in a block
+        self.storage_hub_handler
+            .blockchain
+            .submit_extrinsic_with_retry(
+                call,
+                RetryStrategy::default()
+                    .with_max_retries(MAX_DELETE_FILE_REQUEST_TRY_COUNT)
+                    .with_max_tip(MAX_DELETE_FILE_REQUEST_TIP as f64)
+                    .with_timeout(Duration::from_secs(
+                        self.storage_hub_handler
+                            .provider_config
+                            .extrinsic_retry_timeout,
+                    ))
+                    .retry_only_if_timeout(),
+                false,
+            )
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to submit file deletion proof after {} retries: {:?}",
+                    MAX_DELETE_FILE_REQUEST_TRY_COUNT,
+                    e
+                )
+            })?;
+
+        // The remove mutation is applied on-chain at this point, so failing to apply it locally
+        // can't be fixed by retrying the request.
+        let removed = remove_proven_file_key(
+            &mut *forest_storage.write().await,
+            &forest_proof,
+            &delete_file_request.file_key,
+        )
+        .unwrap_or_else(|e| {
+            error!(target: LOG_TARGET, "CRITICAL❗️❗️ Failed to remove file key from Forest storage after remove delta was applied on chain for file_key {:?}, error: {:?}", delete_file_request.file_key, e);
+            false
+        });
+
+        if removed {
+            info!(
+                target: LOG_TARGET,
+                "Successfully processed file deletion request for file_key {:x}",
+                delete_file_request.file_key
+            );
+        } else {
+            warn!(
+                target: LOG_TARGET,
+                "File key {:x} requested to be deleted is not in the Forest of bucket {:?}. Submitted a non-inclusion proof.",
+                delete_file_request.file_key,
+                delete_file_request.bucket_id
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Generates the Forest proof for deleting `file_key` from a bucket's Forest.
+///
+/// If the Forest doesn't have the file key, this is a non-inclusion proof, with which the runtime
+/// drops the deletion request without removing anything.
+fn generate_file_deletion_proof<FS>(
+    forest_storage: &FS,
+    file_key: &H256,
+) -> anyhow::Result<ForestProof<StorageProofsMerkleTrieLayout>>
+where
+    FS: ForestStorage<StorageProofsMerkleTrieLayout>,
+{
+    forest_storage.generate_proof(vec![*file_key]).map_err(|e| {
+        anyhow!(
+            "Failed to generate Forest proof for file_key {:?}: {:?}",
+            file_key,
+            e
+        )
+    })
+}
+
+/// Removes `file_key` from the Forest if `forest_proof` proved it to be in it.
+///
+/// Returns whether the file key was removed.
+fn remove_proven_file_key<FS>(
+    forest_storage: &mut FS,
+    forest_proof: &ForestProof<StorageProofsMerkleTrieLayout>,
+    file_key: &H256,
+) -> anyhow::Result<bool>
+where
+    FS: ForestStorage<StorageProofsMerkleTrieLayout>,
+{
+    if !forest_proof.contains_file_key(file_key) {
+        return Ok(false);
+    }
+
+    forest_storage.delete_file_key(file_key).map_err(|e| {
+        anyhow!(
+            "Failed to delete file_key {:?} from Forest: {:?}",
+            file_key,
+            e
+        )
+    })?;
+
+    Ok(true)
+}
+
+/// The request to queue again after failing to process `request`, or `None` if it already
+/// failed [`MAX_DELETE_FILE_REQUEST_TRY_COUNT`] times.
+fn file_deletion_request_to_retry(
+    request: &types::FileDeletionRequest,
+) -> Option<types::FileDeletionRequest> {
+    let mut request = request.clone();
+    request.increment_try_count();
+    (request.try_count <= MAX_DELETE_FILE_REQUEST_TRY_COUNT).then_some(request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shc_common::types::{FileMetadata, Fingerprint, HashT};
+    use shc_forest_manager::in_memory::InMemoryForestStorage;
+    use sp_runtime::AccountId32;
+
+    fn file_metadata(location: &str) -> FileMetadata {
+        FileMetadata::new(
+            [1u8; 32].to_vec(),
+            [2u8; 32].to_vec(),
+            location.as_bytes().to_vec(),
+            1024,
+            Fingerprint::from([3u8; 32]),
+        )
+        .unwrap()
+    }
+
+    fn file_key(metadata: &FileMetadata) -> H256 {
+        metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>()
+    }
+
+    #[test]
+    fn deleting_a_file_not_in_the_forest_leaves_it_untouched() {
+        let mut forest_storage = InMemoryForestStorage::<StorageProofsMerkleTrieLayout>::new();
+        forest_storage
+            .insert_files_metadata(&[file_metadata("kept")])
+            .unwrap();
+        let root = forest_storage.root();
+        let missing = file_key(&file_metadata("missing"));
+
+        // A non-inclusion proof is submitted, which doesn't allow removing anything.
+        let forest_proof = generate_file_deletion_proof(&forest_storage, &missing).unwrap();
+        assert!(!forest_proof.contains_file_key(&missing));
+
+        assert!(!remove_proven_file_key(&mut forest_storage, &forest_proof, &missing).unwrap());
+        assert_eq!(forest_storage.root(), root);
+    }
+
+    #[test]
+    fn failed_requests_are_retried_up_to_max_try_count() {
+        let mut request = types::FileDeletionRequest::new(
+            AccountId32::new([1u8; 32]),
+            H256::repeat_byte(2),
+            1024,
+            H256::repeat_byte(3),
+            H256::repeat_byte(4),
+            true,
+        );
+
+        for try_count in 1..=MAX_DELETE_FILE_REQUEST_TRY_COUNT {
+            request = file_deletion_request_to_retry(&request).expect("Retries left");
+            assert_eq!(request.try_count, try_count);
+        }
+
+        assert!(file_deletion_request_to_retry(&request).is_none());
+    }
+}
@@ -691,7 +691,7 @@ where
 
         self.storage_hub_handler
             .file_storage
-            .write()
+            .read()
             .await
             .write_chunk(&file_key, &chunk_id, &chunk_data)
             .map_err(|error| anyhow!("Failed to write chunk {}: {:?}", chunk_idx, error))?;
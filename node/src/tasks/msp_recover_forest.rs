@@ -0,0 +1,266 @@
+use anyhow::anyhow;
+use async_trait::async_trait;
+use sc_tracing::tracing::*;
+use sp_core::H256;
+
+use shc_actors_framework::event_bus::EventHandler;
+use shc_blockchain_service::{commands::BlockchainServiceInterface, events::ForestRootMismatch};
+use shc_common::types::{BucketId, FileMetadata, StorageProofsMerkleTrieLayout};
+use shc_file_manager::traits::FileStorage;
+use shc_forest_manager::{
+    recovery::{forest_root_of, reconcile_forest},
+    traits::{ForestStorage, ForestStorageHandler},
+};
+use shc_rpc::forest_recovery::{ForestRecovery, RecoverForestResult, RecoveredForest};
+
+use crate::services::{
+    handler::StorageHubHandler,
+    types::{MspForestStorageHandlerT, ShNodeType},
+};
+
+const LOG_TARGET: &str = "msp-recover-forest-task";
+
+/// MSP Recover Forest Task: Rebuilds the Forest of a bucket when it diverges from its on-chain
+/// root.
+///
+/// The task has one handler:
+/// - [`ForestRootMismatch`]: Reacts to the event emitted by the BlockchainService when the local
+///   root of a bucket's Forest doesn't match the on-chain root after applying the mutations of a
+///   block.
+///
+/// The same recovery can be triggered manually through the `recoverForest` RPC method, with the
+/// bucket ID as forest key. It goes as follows:
+/// - Compares the local root of the bucket's Forest with its on-chain root. If they match, there
+///   is nothing to recover.
+/// - Fetches the files of the bucket from the indexer, and checks that a Forest holding exactly
+///   those files has the on-chain root. Otherwise, the indexer is not in sync with the chain yet,
+///   or it tracks files the MSP hasn't accepted yet, and the Forest is left untouched.
+/// - Snapshots the diverged Forest, so that it can be inspected later.
+/// - Reconciles the Forest with the files of the bucket under its write lock, so that no proof
+///   is generated from a partially rebuilt Forest.
+/// - Warns about the files inserted in the Forest that are not complete in the File Storage,
+///   since MSPs can't fetch them back from other providers.
+pub struct MspRecoverForestTask<NT>
+where
+    NT: ShNodeType,
+    NT::FSH: MspForestStorageHandlerT,
+{
+    storage_hub_handler: StorageHubHandler<NT>,
+}
+
+impl<NT> Clone for MspRecoverForestTask<NT>
+where
+    NT: ShNodeType,
+    NT::FSH: MspForestStorageHandlerT,
+{
+    fn clone(&self) -> MspRecoverForestTask<NT> {
+        Self {
+            storage_hub_handler: self.storage_hub_handler.clone(),
+        }
+    }
+}
+
+impl<NT> MspRecoverForestTask<NT>
+where
+    NT: ShNodeType,
+    NT::FSH: MspForestStorageHandlerT,
+{
+    pub fn new(storage_hub_handler: StorageHubHandler<NT>) -> Self {
+        Self {
+            storage_hub_handler,
+        }
+    }
+}
+
+impl<NT> EventHandler<ForestRootMismatch> for MspRecoverForestTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: MspForestStorageHandlerT,
+{
+    async fn handle_event(&mut self, event: ForestRootMismatch) -> anyhow::Result<()> {
+        // The BSP's Forest is recovered by the BSP Recover Forest Task.
+        let Some(bucket_id) = event.bucket_id else {
+            return Ok(());
+        };
+
+        warn!(target: LOG_TARGET, "Local Forest of bucket [{:?}] diverged from its on-chain root. Recovering it.", bucket_id);
+
+        match self.recover_bucket_forest(bucket_id).await {
+            Ok(RecoverForestResult::InSync(root)) => {
+                info!(target: LOG_TARGET, "Local Forest of bucket [{:?}] is in sync with its on-chain root [{:?}]. Nothing to recover.", bucket_id, root);
+                Ok(())
+            }
+            Ok(RecoverForestResult::Recovered(_)) => Ok(()),
+            Err(e) => {
+                error!(target: LOG_TARGET, "CRITICAL ❗️❗️ Failed to recover the local Forest of bucket [{:?}]. It can be retried with the `recoverForest` RPC method. \nError: {:?}", bucket_id, e);
+                Err(e)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<NT> ForestRecovery for MspRecoverForestTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: MspForestStorageHandlerT,
+{
+    async fn recover_forest(
+        &self,
+        forest_key: Option<H256>,
+    ) -> anyhow::Result<RecoverForestResult> {
+        let bucket_id = forest_key.ok_or_else(|| {
+            anyhow!("MSPs maintain a Forest per bucket, so the bucket ID should be given as forest key.")
+        })?;
+
+        self.recover_bucket_forest(bucket_id).await
+    }
+}
+
+impl<NT> MspRecoverForestTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: MspForestStorageHandlerT,
+{
+    /// Rebuilds the Forest of the bucket with `bucket_id` from its files, if its local root
+    /// diverged from the on-chain one.
+    async fn recover_bucket_forest(
+        &self,
+        bucket_id: BucketId,
+    ) -> anyhow::Result<RecoverForestResult> {
+        let on_chain_root = self
+            .storage_hub_handler
+            .blockchain
+            .query_bucket_root(bucket_id)
+            .await
+            .map_err(|e| anyhow!("Failed to query the on-chain root of the bucket: {:?}", e))?;
+
+        let forest_key = bucket_id.as_ref().to_vec();
+        let fs = self
+            .storage_hub_handler
+            .forest_storage_handler
+            .get(&forest_key)
+            .await
+            .ok_or_else(|| {
+                anyhow!(
+                    "Failed to get the Forest Storage of bucket [{:?}]. The bucket may not be stored by this MSP.",
+                    bucket_id
+                )
+            })?;
+
+        let diverged_root = fs.read().await.root();
+        if diverged_root == on_chain_root {
+            return Ok(RecoverForestResult::InSync(on_chain_root));
+        }
+
+        // Rebuild the set of files of the bucket, and check it against the on-chain root before
+        // touching the Forest.
+        let files = self.files_of_bucket(bucket_id).await?;
+        let rebuilt_root = forest_root_of::<StorageProofsMerkleTrieLayout>(&files)
+            .map_err(|e| anyhow!("Failed to compute the root of the rebuilt Forest: {:?}", e))?;
+        if rebuilt_root != on_chain_root {
+            return Err(anyhow!(
+                "The {} files of the bucket according to the indexer make up root [{:?}] instead of the on-chain root [{:?}]. The indexer may not be in sync with the chain yet, or some storage requests may still be pending.",
+                files.len(),
+                rebuilt_root,
+                on_chain_root
+            ));
+        }
+
+        // Keep a copy of the diverged Forest, to find out later how it diverged.
+        let diverged_snapshot_key =
+            format!("diverged_forest_{:?}_{:?}", bucket_id, diverged_root).into_bytes();
+        let diverged_snapshot_key = match self
+            .storage_hub_handler
+            .forest_storage_handler
+            .snapshot(&forest_key, &diverged_snapshot_key)
+            .await
+        {
+            Some(_) => Some(diverged_snapshot_key),
+            None => {
+                warn!(target: LOG_TARGET, "Failed to snapshot the diverged Forest of bucket [{:?}] with root [{:?}]. Recovering it anyway.", bucket_id, diverged_root);
+                None
+            }
+        };
+
+        let mut write_fs = fs.write().await;
+
+        // The Forest could have been recovered while waiting for the lock.
+        if write_fs.root() == on_chain_root {
+            return Ok(RecoverForestResult::InSync(on_chain_root));
+        }
+
+        let reconciliation =
+            reconcile_forest::<StorageProofsMerkleTrieLayout, _>(&mut *write_fs, &files).map_err(
+                |e| {
+                    anyhow!(
+                        "Failed to reconcile the Forest with the files of the bucket: {:?}",
+                        e
+                    )
+                },
+            )?;
+
+        let recovered_root = write_fs.root();
+        if recovered_root != on_chain_root {
+            return Err(anyhow!(
+                "The recovered Forest has root [{:?}] instead of the on-chain root [{:?}]. Its mutations were likely applied while it was being recovered.",
+                recovered_root,
+                on_chain_root
+            ));
+        }
+        drop(write_fs);
+
+        info!(target: LOG_TARGET, "🌳 Recovered the local Forest of bucket [{:?}] from root [{:?}] to the on-chain root [{:?}], removing {} file(s) and inserting {} file(s).", bucket_id, diverged_root, on_chain_root, reconciliation.removed.len(), reconciliation.inserted.len());
+        debug!(target: LOG_TARGET, "Removed file keys: {:?}", reconciliation.removed);
+        debug!(target: LOG_TARGET, "Inserted file keys: {:?}", reconciliation.inserted);
+
+        self.warn_incomplete_files(&reconciliation.inserted).await;
+
+        Ok(RecoverForestResult::Recovered(RecoveredForest {
+            diverged_root,
+            on_chain_root,
+            diverged_snapshot_key,
+            removed_file_keys: reconciliation.removed,
+            inserted_file_keys: reconciliation.inserted,
+        }))
+    }
+
+    /// Warns about the files with `file_keys` that are not complete in the File Storage.
+    async fn warn_incomplete_files(&self, file_keys: &[H256]) {
+        let read_file_storage = self.storage_hub_handler.file_storage.read().await;
+        for file_key in file_keys {
+            if !matches!(read_file_storage.is_file_complete(file_key), Ok(true)) {
+                warn!(target: LOG_TARGET, "File [{:?}] is in the recovered Forest but not complete in the File Storage. It can't be served until it is uploaded again.", file_key);
+            }
+        }
+    }
+
+    /// The metadata of the files of the bucket with `bucket_id`, as tracked by the indexer.
+    async fn files_of_bucket(&self, bucket_id: BucketId) -> anyhow::Result<Vec<FileMetadata>> {
+        let indexer_db_pool = self.storage_hub_handler.indexer_db_pool.as_ref().ok_or_else(|| {
+            anyhow!("Indexer is disabled, but it is needed to recover the Forest. Please provide a database URL (and enable indexer) for it to use this feature.")
+        })?;
+
+        let mut indexer_connection = indexer_db_pool.get().await.map_err(|error| {
+            anyhow!(
+                "Failed to get indexer connection after timeout: {:?}",
+                error
+            )
+        })?;
+
+        let onchain_bucket_id = bucket_id.as_ref().to_vec();
+        let files = shc_indexer_db::models::File::get_by_onchain_bucket_id(
+            &mut indexer_connection,
+            onchain_bucket_id.clone(),
+        )
+        .await?;
+
+        files
+            .into_iter()
+            .map(|file| {
+                file.to_file_metadata(onchain_bucket_id.clone())
+                    .map_err(|e| anyhow!("Failed to convert file to file metadata: {:?}", e))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,529 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use codec::Decode;
+use pallet_storage_providers_runtime_api::BucketReadAccess;
+use sc_tracing::tracing::*;
+use shc_actors_framework::event_bus::EventHandler;
+use shc_blockchain_service::commands::BlockchainServiceInterface;
+use shc_common::types::{
+    BucketId, ChunkId, FileKeyProof, FileMetadata, HashT, StorageProofsMerkleTrieLayout,
+};
+use shc_file_manager::traits::{FileStorage, FileStorageError};
+use shc_file_transfer_service::{
+    commands::FileTransferServiceInterface, events::RemoteDownloadRequest,
+};
+use sp_core::H256;
+use sp_runtime::AccountId32;
+
+use crate::services::{
+    handler::StorageHubHandler,
+    metrics::{generate_proof_metered, ProviderMetrics},
+    types::{MspForestStorageHandlerT, ShNodeType},
+};
+
+const LOG_TARGET: &str = "msp-retrieve-file-task";
+
+/// Maximum number of bytes served to a single user within [`RETRIEVAL_RATE_LIMIT_WINDOW`].
+const MAX_BYTES_SERVED_PER_USER_PER_WINDOW: u64 = 1024 * 1024 * 1024;
+/// Window over which the bytes served to each user are rate limited.
+const RETRIEVAL_RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// MSP Retrieve File Task: Handles serving the files stored by the MSP to the users of its buckets.
+///
+/// The flow includes the following steps:
+/// - **[`RemoteDownloadRequest`] Event:**
+///   - Only handles requests made on behalf of a user, i.e. with a requester that signed the
+///     retrieval, which the FileTransferService lets through without an allow list check.
+///   - Checks that the requester can read the file: it is the owner of the file's bucket, the
+///     bucket is public, or the requester holds an item of the bucket's read access group.
+///   - Rate limits the bytes served to each requester, and meters the total bytes served to them.
+///   - Generates a proof for the requested chunks (which also contains the chunks themselves)
+///     from the File Storage and sends it back to the requester.
+pub struct MspRetrieveFileTask<NT>
+where
+    NT: ShNodeType,
+    NT::FSH: MspForestStorageHandlerT,
+{
+    storage_hub_handler: StorageHubHandler<NT>,
+    rate_limiter: RetrievalRateLimiter,
+}
+
+impl<NT> Clone for MspRetrieveFileTask<NT>
+where
+    NT: ShNodeType,
+    NT::FSH: MspForestStorageHandlerT,
+{
+    fn clone(&self) -> MspRetrieveFileTask<NT> {
+        Self {
+            storage_hub_handler: self.storage_hub_handler.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+        }
+    }
+}
+
+impl<NT> MspRetrieveFileTask<NT>
+where
+    NT: ShNodeType,
+    NT::FSH: MspForestStorageHandlerT,
+{
+    pub fn new(storage_hub_handler: StorageHubHandler<NT>) -> Self {
+        Self {
+            storage_hub_handler,
+            rate_limiter: RetrievalRateLimiter::new(
+                MAX_BYTES_SERVED_PER_USER_PER_WINDOW,
+                RETRIEVAL_RATE_LIMIT_WINDOW,
+            ),
+        }
+    }
+}
+
+/// Handles a remote download request made on behalf of a user.
+///
+/// This will check that the requester can read the file, generate a proof for the chunks and
+/// send it back to the requester. Requests that are not served are rejected right away, so that
+/// the requester doesn't wait for them to time out.
+impl<NT> EventHandler<RemoteDownloadRequest> for MspRetrieveFileTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: MspForestStorageHandlerT,
+{
+    async fn handle_event(&mut self, event: RemoteDownloadRequest) -> anyhow::Result<()> {
+        trace!(target: LOG_TARGET, "Received remote download request with id {:?} for file {:?}", event.request_id, event.file_key);
+
+        let request_id = event.request_id.clone();
+        let result = self.handle_remote_download_request(event).await;
+        if result.is_err() {
+            if let Err(e) = self
+                .storage_hub_handler
+                .file_transfer
+                .reject_download_request(request_id)
+                .await
+            {
+                debug!(target: LOG_TARGET, "Failed to reject download request: {:?}", e);
+            }
+        }
+
+        result
+    }
+}
+
+impl<NT> MspRetrieveFileTask<NT>
+where
+    NT: ShNodeType,
+    NT::FSH: MspForestStorageHandlerT,
+{
+    async fn handle_remote_download_request(
+        &self,
+        event: RemoteDownloadRequest,
+    ) -> anyhow::Result<()> {
+        let RemoteDownloadRequest {
+            peer,
+            file_key,
+            chunk_ids,
+            bucket_id,
+            request_id,
+            requester,
+        } = event;
+
+        let Some(requester) = requester else {
+            warn!(target: LOG_TARGET, "Ignoring download request for file {:?} from peer {:?} without a requester", file_key, peer);
+            return Err(anyhow!("Download request without a requester"));
+        };
+
+        let file_key: H256 = file_key.into();
+
+        // Get the file metadata from the file storage.
+        let file_metadata = self
+            .storage_hub_handler
+            .file_storage
+            .read()
+            .await
+            .get_metadata(&file_key)
+            .map_err(|e| anyhow!("Failed to get file metadata: {:?}", e))?
+            .ok_or_else(|| {
+                error!(target: LOG_TARGET, "File {:?} not found in file storage", file_key);
+                anyhow!("File not found in file storage")
+            })?;
+
+        let file_bucket_id = BucketId::decode(&mut file_metadata.bucket_id().as_slice())
+            .map_err(|e| anyhow!("Failed to decode bucket ID of file {:?}: {:?}", file_key, e))?;
+        if let Some(bucket_id) = bucket_id {
+            if bucket_id != file_bucket_id {
+                error!(
+                    target: LOG_TARGET,
+                    "File bucket mismatch for file {:?}: expected {:?}, got {:?}",
+                    file_key, file_bucket_id, bucket_id
+                );
+                return Err(anyhow!("File bucket mismatch"));
+            }
+        }
+
+        // Get what the requester can read from the bucket.
+        let read_access = self
+            .storage_hub_handler
+            .blockchain
+            .query_bucket_read_access(file_bucket_id, requester.clone())
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to query read access of bucket {:?}: {:?}",
+                    file_bucket_id,
+                    e
+                )
+            })?;
+
+        let retrieval = Retrieval {
+            requester: &requester,
+            file_metadata: &file_metadata,
+            chunk_ids: &chunk_ids,
+        };
+        let served = serve_retrieval(
+            &*self.storage_hub_handler.file_storage.read().await,
+            self.storage_hub_handler.metrics.as_ref(),
+            &self.rate_limiter,
+            &read_access,
+            &retrieval,
+            Instant::now(),
+        );
+        let (file_key_proof, served_bytes) = match served {
+            Ok(served) => served,
+            Err(RetrievalRefusal::Unauthorised) => {
+                warn!(target: LOG_TARGET, "Requester {:?} (peer {:?}) is not allowed to read file {:?} of bucket {:?}", requester, peer, file_key, file_bucket_id);
+                self.storage_hub_handler
+                    .file_transfer
+                    .report_peer(peer, "unauthorised retrieval")
+                    .await?;
+                return Err(RetrievalRefusal::Unauthorised.into());
+            }
+            Err(refusal) => {
+                warn!(target: LOG_TARGET, "Not serving chunks {:?} of file {:?} to {:?}: {}", chunk_ids, file_key, requester, refusal);
+                return Err(refusal.into());
+            }
+        };
+
+        // Send the chunk data and proof back to the requester.
+        self.storage_hub_handler
+            .file_transfer
+            .download_response(file_key_proof, request_id)
+            .await?;
+
+        debug!(
+            target: LOG_TARGET,
+            "Served {} bytes of file {:?} to {:?} ({} bytes in total)",
+            served_bytes,
+            file_key,
+            requester,
+            self.rate_limiter.total_bytes_served(&requester)
+        );
+
+        Ok(())
+    }
+}
+
+/// A retrieval of chunks of a file on behalf of a requester.
+struct Retrieval<'a> {
+    requester: &'a AccountId32,
+    file_metadata: &'a FileMetadata,
+    chunk_ids: &'a HashSet<ChunkId>,
+}
+
+/// Reasons for which a [`Retrieval`] is not served.
+#[derive(Debug, thiserror::Error)]
+enum RetrievalRefusal {
+    #[error("Requester is not allowed to read the file")]
+    Unauthorised,
+    #[error("Retrieval rate limit exceeded")]
+    RateLimited,
+    #[error("Invalid chunk {0:?} requested")]
+    InvalidChunk(ChunkId),
+    #[error("Failed to generate proof: {0:?}")]
+    ProofGeneration(FileStorageError),
+}
+
+/// Serves `retrieval` from `file_storage` at `now`, given the `read_access` of the requester to
+/// the file's bucket.
+///
+/// The bytes served are rate limited and metered by `rate_limiter`. Returns the proof of the
+/// chunks retrieved, which also contains the chunk data itself, along with the bytes served.
+fn serve_retrieval<FS>(
+    file_storage: &FS,
+    metrics: Option<&ProviderMetrics>,
+    rate_limiter: &RetrievalRateLimiter,
+    read_access: &BucketReadAccess<AccountId32>,
+    retrieval: &Retrieval,
+    now: Instant,
+) -> Result<(FileKeyProof, u64), RetrievalRefusal>
+where
+    FS: FileStorage<StorageProofsMerkleTrieLayout>,
+{
+    if !can_retrieve(read_access, retrieval.requester) {
+        return Err(RetrievalRefusal::Unauthorised);
+    }
+
+    let mut requested_bytes = 0u64;
+    for chunk_id in retrieval.chunk_ids {
+        let chunk_size = retrieval
+            .file_metadata
+            .chunk_size_at(chunk_id.as_u64())
+            .map_err(|_| RetrievalRefusal::InvalidChunk(*chunk_id))?;
+        requested_bytes = requested_bytes.saturating_add(chunk_size as u64);
+    }
+
+    if !rate_limiter.try_serve(retrieval.requester, requested_bytes, now) {
+        return Err(RetrievalRefusal::RateLimited);
+    }
+
+    let file_key = retrieval
+        .file_metadata
+        .file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+    let file_key_proof =
+        generate_proof_metered(metrics, file_storage, &file_key, retrieval.chunk_ids)
+            .map_err(RetrievalRefusal::ProofGeneration)?;
+
+    Ok((file_key_proof, requested_bytes))
+}
+
+/// Whether `requester` can retrieve the files of a bucket with `read_access`.
+fn can_retrieve(read_access: &BucketReadAccess<AccountId32>, requester: &AccountId32) -> bool {
+    read_access.owner == *requester || !read_access.private || read_access.holds_read_access_item
+}
+
+/// Bytes served to a single requester.
+#[derive(Debug, Default)]
+struct ServedBytes {
+    /// Start of the current rate limiting window, if any bytes were served yet.
+    window_start: Option<Instant>,
+    /// Bytes served within the current rate limiting window.
+    in_window: u64,
+    /// Bytes served since the node started.
+    total: u64,
+}
+
+/// Limits the bytes served to each requester within a fixed window, and meters the total bytes
+/// served to them.
+///
+/// Shared between all the clones of the task handling the retrievals.
+#[derive(Debug, Clone)]
+struct RetrievalRateLimiter {
+    max_bytes_per_window: u64,
+    window: Duration,
+    served: Arc<Mutex<HashMap<AccountId32, ServedBytes>>>,
+}
+
+impl RetrievalRateLimiter {
+    fn new(max_bytes_per_window: u64, window: Duration) -> Self {
+        Self {
+            max_bytes_per_window,
+            window,
+            served: Default::default(),
+        }
+    }
+
+    /// Records serving `bytes` to `requester` at `now`, unless that would exceed the limit of the
+    /// current window.
+    ///
+    /// Returns whether the bytes can be served.
+    fn try_serve(&self, requester: &AccountId32, bytes: u64, now: Instant) -> bool {
+        let mut served = self.served.lock().expect("Rate limiter lock poisoned");
+        let served = served.entry(requester.clone()).or_default();
+
+        let window_expired = served.window_start.map_or(true, |start| {
+            now.saturating_duration_since(start) >= self.window
+        });
+        if window_expired {
+            served.window_start = Some(now);
+            served.in_window = 0;
+        }
+
+        let in_window = served.in_window.saturating_add(bytes);
+        if in_window > self.max_bytes_per_window {
+            return false;
+        }
+
+        served.in_window = in_window;
+        served.total = served.total.saturating_add(bytes);
+        true
+    }
+
+    /// Total bytes served to `requester`.
+    fn total_bytes_served(&self, requester: &AccountId32) -> u64 {
+        self.served
+            .lock()
+            .expect("Rate limiter lock poisoned")
+            .get(requester)
+            .map_or(0, |served| served.total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shc_common::types::FILE_CHUNK_SIZE;
+    use shc_file_manager::{in_memory::InMemoryFileStorage, traits::FileDataTrie};
+
+    const OWNER: AccountId32 = AccountId32::new([1u8; 32]);
+    const OTHER: AccountId32 = AccountId32::new([2u8; 32]);
+
+    /// File Storage with a file of `OWNER` made of two chunks, the first one full.
+    fn file_storage_with_file() -> (
+        InMemoryFileStorage<StorageProofsMerkleTrieLayout>,
+        FileMetadata,
+    ) {
+        let chunks = [vec![1u8; FILE_CHUNK_SIZE as usize], vec![2u8; 10]];
+        let mut file_storage = InMemoryFileStorage::<StorageProofsMerkleTrieLayout>::new();
+        let mut file_trie = file_storage.new_file_data_trie();
+        for (chunk_id, chunk) in chunks.iter().enumerate() {
+            file_trie
+                .write_chunk(&ChunkId::new(chunk_id as u64), chunk)
+                .unwrap();
+        }
+        let metadata = FileMetadata::new(
+            <AccountId32 as AsRef<[u8]>>::as_ref(&OWNER).to_vec(),
+            [3u8; 32].to_vec(),
+            b"location".to_vec(),
+            FILE_CHUNK_SIZE + 10,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+        let file_key = metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+        file_storage
+            .insert_file_with_data(file_key, metadata.clone(), file_trie)
+            .unwrap();
+
+        (file_storage, metadata)
+    }
+
+    fn read_access(private: bool, holds_read_access_item: bool) -> BucketReadAccess<AccountId32> {
+        BucketReadAccess {
+            owner: OWNER,
+            private,
+            holds_read_access_item,
+        }
+    }
+
+    #[test]
+    fn owner_can_retrieve_from_private_bucket() {
+        assert!(can_retrieve(&read_access(true, false), &OWNER));
+    }
+
+    #[test]
+    fn unauthorised_requester_cannot_retrieve_from_private_bucket() {
+        assert!(!can_retrieve(&read_access(true, false), &OTHER));
+    }
+
+    #[test]
+    fn read_access_group_holder_can_retrieve_from_private_bucket() {
+        assert!(can_retrieve(&read_access(true, true), &OTHER));
+    }
+
+    #[test]
+    fn anyone_can_retrieve_from_public_bucket() {
+        assert!(can_retrieve(&read_access(false, false), &OTHER));
+    }
+
+    #[test]
+    fn authorised_requester_is_served_the_proven_chunks() {
+        let (file_storage, file_metadata) = file_storage_with_file();
+        let rate_limiter = RetrievalRateLimiter::new(u64::MAX, Duration::from_secs(60));
+        let chunk_ids = HashSet::from([ChunkId::new(1)]);
+        let retrieval = Retrieval {
+            requester: &OTHER,
+            file_metadata: &file_metadata,
+            chunk_ids: &chunk_ids,
+        };
+
+        let (file_key_proof, served_bytes) = serve_retrieval(
+            &file_storage,
+            None,
+            &rate_limiter,
+            &read_access(true, true),
+            &retrieval,
+            Instant::now(),
+        )
+        .unwrap();
+
+        let proven = file_key_proof
+            .proven::<StorageProofsMerkleTrieLayout>()
+            .unwrap();
+        assert_eq!(proven.len(), 1);
+        assert_eq!(proven[0].key, ChunkId::new(1));
+        assert_eq!(proven[0].data, vec![2u8; 10]);
+        assert_eq!(served_bytes, 10);
+        assert_eq!(rate_limiter.total_bytes_served(&OTHER), 10);
+    }
+
+    #[test]
+    fn unauthorised_requester_is_not_served_nor_metered() {
+        let (file_storage, file_metadata) = file_storage_with_file();
+        let rate_limiter = RetrievalRateLimiter::new(u64::MAX, Duration::from_secs(60));
+        let chunk_ids = HashSet::from([ChunkId::new(0)]);
+        let retrieval = Retrieval {
+            requester: &OTHER,
+            file_metadata: &file_metadata,
+            chunk_ids: &chunk_ids,
+        };
+
+        let result = serve_retrieval(
+            &file_storage,
+            None,
+            &rate_limiter,
+            &read_access(true, false),
+            &retrieval,
+            Instant::now(),
+        );
+
+        assert!(matches!(result, Err(RetrievalRefusal::Unauthorised)));
+        assert_eq!(rate_limiter.total_bytes_served(&OTHER), 0);
+    }
+
+    #[test]
+    fn retrievals_over_the_rate_limit_are_not_served() {
+        let (file_storage, file_metadata) = file_storage_with_file();
+        // Enough to serve the first chunk, but not both.
+        let rate_limiter = RetrievalRateLimiter::new(FILE_CHUNK_SIZE + 5, Duration::from_secs(60));
+        let now = Instant::now();
+        let serve = |chunk_id: u64| {
+            let chunk_ids = HashSet::from([ChunkId::new(chunk_id)]);
+            let retrieval = Retrieval {
+                requester: &OWNER,
+                file_metadata: &file_metadata,
+                chunk_ids: &chunk_ids,
+            };
+            serve_retrieval(
+                &file_storage,
+                None,
+                &rate_limiter,
+                &read_access(true, false),
+                &retrieval,
+                now,
+            )
+        };
+
+        assert!(serve(0).is_ok());
+        assert!(matches!(serve(1), Err(RetrievalRefusal::RateLimited)));
+        assert!(matches!(serve(2), Err(RetrievalRefusal::InvalidChunk(_))));
+        assert_eq!(rate_limiter.total_bytes_served(&OWNER), FILE_CHUNK_SIZE);
+    }
+
+    #[test]
+    fn rate_limiter_limits_bytes_per_window_and_meters_total() {
+        let rate_limiter = RetrievalRateLimiter::new(100, Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert!(rate_limiter.try_serve(&OWNER, 60, start));
+        assert!(!rate_limiter.try_serve(&OWNER, 60, start + Duration::from_secs(1)));
+        // Other requesters have their own limit.
+        assert!(rate_limiter.try_serve(&OTHER, 100, start));
+
+        // The limit is reset once the window is over.
+        assert!(rate_limiter.try_serve(&OWNER, 60, start + Duration::from_secs(60)));
+
+        assert_eq!(rate_limiter.total_bytes_served(&OWNER), 120);
+        assert_eq!(rate_limiter.total_bytes_served(&OTHER), 100);
+    }
+}
@@ -2,8 +2,10 @@ use anyhow::anyhow;
 use std::{
     collections::{HashMap, HashSet},
     str::FromStr,
+    sync::Arc,
     time::Duration,
 };
+use tokio::sync::RwLock;
 
 use sc_network::PeerId;
 use sc_tracing::tracing::*;
@@ -13,25 +15,39 @@ use sp_core::H256;
 use sp_runtime::AccountId32;
 
 use pallet_file_system::types::RejectedStorageRequest;
+use pallet_file_system_runtime_api::IsStorageRequestOpenToVolunteersError;
 use shc_actors_framework::event_bus::EventHandler;
-use shc_blockchain_service::events::ProcessMspRespondStoringRequest;
+use shc_blockchain_service::events::{
+    ProcessMspRespondStoringRequest, StorageRequestExpired, StorageRequestRevoked, UserWithoutFunds,
+};
 use shc_blockchain_service::{commands::BlockchainServiceInterface, events::NewStorageRequest};
 use shc_common::types::{
-    FileKey, FileKeyWithProof, FileMetadata, HashT, RejectedStorageRequestReason,
-    StorageProofsMerkleTrieLayout, StorageProviderId, StorageRequestMspAcceptedFileKeys,
-    StorageRequestMspBucketResponse, BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE,
+    FileKey, FileKeyWithProof, FileMetadata, HashT, ProvenFileKeyError,
+    RejectedStorageRequestReason, StorageData, StorageProofsMerkleTrieLayout, StorageProviderId,
+    StorageRequestMspAcceptedFileKeys, StorageRequestMspBucketResponse,
+    BATCH_CHUNK_FILE_TRANSFER_MAX_CHUNKS, BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE,
 };
-use shc_file_manager::traits::{FileStorage, FileStorageWriteError, FileStorageWriteOutcome};
+use shc_common::upload_progress::UploadState;
+use shc_file_manager::traits::{FileStorage, FileStorageError, FileStorageWriteOutcome};
 use shc_file_transfer_service::{
-    commands::FileTransferServiceInterface, events::RemoteUploadRequest,
+    commands::FileTransferServiceInterface,
+    events::{DuplicateChunksDetected, RemoteUploadRequest},
 };
 use shc_forest_manager::traits::{ForestStorage, ForestStorageHandler};
 
+use crate::services::metrics::{generate_proof_metered, write_chunk_metered, ChunkRejection};
 use crate::services::types::ShNodeType;
+use crate::services::upload_span::{upload_span, MSP_ROLE};
 use crate::services::{handler::StorageHubHandler, types::MspForestStorageHandlerT};
+use crate::tasks::file_key_cleanup::FileKeyCleanup;
+use crate::tasks::upload_failure::{UploadFailure, UploadFailureAction};
 
 const LOG_TARGET: &str = "msp-upload-file-task";
 
+/// Maximum number of times a response to a storage request is queued again after failing to be
+/// sent, e.g. because its proof could not be generated or the extrinsic was not included in time.
+const MAX_RESPOND_STORAGE_REQUEST_TRY_COUNT: u32 = 3;
+
 /// MSP Upload File Task: Handles the whole flow of a file being uploaded to a MSP, from
 /// the MSP's perspective.
 ///
@@ -52,15 +68,33 @@ const LOG_TARGET: &str = "msp-upload-file-task";
 ///   when there are new storage request(s) to respond to. The batch of storage requests
 ///   will be responded to in a single call to the FileSystem pallet `msp_respond_storage_requests_multiple_buckets` extrinsic
 ///   which will emit an event that describes the final result of the batch response (i.e. all accepted,
-///   rejected and/or failed file keys). The MSP will then apply the necessary deltas to each one of the bucket's
-///   forest storage to reflect the result.
+///   rejected and/or failed file keys). The accepted file keys are added to each one of the bucket's forest
+///   storage only once the runtime applies them, through the `MutationsApplied` event processed by the BlockchainService.
+///
+/// Additionally, it listens to [`StorageRequestRevoked`] and [`StorageRequestExpired`] events to
+/// discard any partially uploaded file whose storage request is no longer open on-chain, and to
+/// [`UserWithoutFunds`] events to stop receiving files from users that can no longer pay for them.
 pub struct MspUploadFileTask<NT>
 where
     NT: ShNodeType,
     NT::FSH: MspForestStorageHandlerT,
 {
     storage_hub_handler: StorageHubHandler<NT>,
-    file_key_cleanup: Option<H256>,
+    /// Cached size and data limit of the buckets this MSP received storage requests for.
+    /// See [`BucketSizeAndLimit`].
+    bucket_sizes: Arc<RwLock<HashMap<H256, BucketSizeAndLimit>>>,
+}
+
+/// The size of a bucket and the data limit of the value proposition it is associated with.
+///
+/// The size starts as the on-chain size of the bucket, and the size of every file this MSP
+/// commits to store in the bucket is added to it, so that concurrent storage requests cannot
+/// go over the limit together. Entries are invalidated once this MSP responds to storage requests
+/// in the bucket, so that the next storage request queries the on-chain size again.
+#[derive(Debug, Clone, Copy)]
+struct BucketSizeAndLimit {
+    size: StorageData,
+    data_limit: StorageData,
 }
 
 impl<NT> Clone for MspUploadFileTask<NT>
@@ -71,7 +105,7 @@ where
     fn clone(&self) -> MspUploadFileTask<NT> {
         Self {
             storage_hub_handler: self.storage_hub_handler.clone(),
-            file_key_cleanup: self.file_key_cleanup,
+            bucket_sizes: self.bucket_sizes.clone(),
         }
     }
 }
@@ -84,7 +118,7 @@ where
     pub fn new(storage_hub_handler: StorageHubHandler<NT>) -> Self {
         Self {
             storage_hub_handler,
-            file_key_cleanup: None,
+            bucket_sizes: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 }
@@ -94,6 +128,7 @@ where
 /// This event is triggered by an on-chain event of a user submitting a storage request to StorageHub.
 ///
 /// This task will:
+/// - Check that the file fits in the bucket without exceeding the data limit of its value proposition.
 /// - Check if the MSP has enough storage capacity to store the file and increase it if necessary (up to a maximum).
 /// - Register the user and file key in the registry of the File Transfer Service, which handles incoming p2p
 /// upload requests.
@@ -103,21 +138,27 @@ where
     NT::FSH: MspForestStorageHandlerT,
 {
     async fn handle_event(&mut self, event: NewStorageRequest) -> anyhow::Result<()> {
-        info!(
-            target: LOG_TARGET,
-            "Registering user peer for file_key {:x}, location 0x{}, fingerprint {:x}",
-            event.file_key,
-            hex::encode(event.location.as_slice()),
-            event.fingerprint
-        );
+        let span = upload_span(event.file_key.into(), event.bucket_id, event.size, MSP_ROLE);
 
-        let result = self.handle_new_storage_request_event(event).await;
-        if result.is_err() {
-            if let Some(file_key) = &self.file_key_cleanup {
-                self.unregister_file(*file_key).await?;
-            }
+        async {
+            info!(
+                target: LOG_TARGET,
+                "Registering user peer for file_key {:x}, location 0x{}, fingerprint {:x}",
+                event.file_key,
+                hex::encode(event.location.as_slice()),
+                event.fingerprint
+            );
+
+            let mut file_key_cleanup = FileKeyCleanup::default();
+            let result = self
+                .handle_new_storage_request_event(event, &mut file_key_cleanup)
+                .await;
+            file_key_cleanup
+                .clean_up_on_failure(result, |file_key| self.unregister_file(file_key))
+                .await
         }
-        result
+        .instrument(span)
+        .await
     }
 }
 
@@ -131,51 +172,64 @@ where
     NT::FSH: MspForestStorageHandlerT,
 {
     async fn handle_event(&mut self, event: RemoteUploadRequest) -> anyhow::Result<()> {
-        trace!(target: LOG_TARGET, "Received remote upload request for file {:?} and peer {:?}", event.file_key, event.peer);
+        let span = self
+            .storage_hub_handler
+            .upload_spans
+            .get(&event.file_key.into());
 
-        let file_complete = match self.handle_remote_upload_request_event(event.clone()).await {
-            Ok(complete) => complete,
-            Err(e) => {
-                // Send error response through FileTransferService
-                if let Err(e) = self
-                    .storage_hub_handler
-                    .file_transfer
-                    .upload_response(false, event.request_id)
-                    .await
-                {
-                    error!(target: LOG_TARGET, "Failed to send error response: {:?}", e);
+        async {
+            trace!(target: LOG_TARGET, "Received remote upload request for file {:?} and peer {:?}", event.file_key, event.peer);
+
+            let file_complete = match self.handle_remote_upload_request_event(event.clone()).await {
+                Ok(complete) => complete,
+                Err(e) => {
+                    // Send error response through FileTransferService
+                    if let Err(e) = self
+                        .storage_hub_handler
+                        .file_transfer
+                        .upload_response(false, event.request_id)
+                        .await
+                    {
+                        error!(target: LOG_TARGET, "Failed to send error response: {:?}", e);
+                    }
+                    return Err(e);
                 }
-                return Err(e);
+            };
+
+            // Send completion status through FileTransferService
+            if let Err(e) = self
+                .storage_hub_handler
+                .file_transfer
+                .upload_response(file_complete, event.request_id)
+                .await
+            {
+                error!(target: LOG_TARGET, "Failed to send response: {:?}", e);
             }
-        };
 
-        // Send completion status through FileTransferService
-        if let Err(e) = self
-            .storage_hub_handler
-            .file_transfer
-            .upload_response(file_complete, event.request_id)
-            .await
-        {
-            error!(target: LOG_TARGET, "Failed to send response: {:?}", e);
-        }
+            // Handle file completion if the entire file is uploaded or is already being stored.
+            if file_complete {
+                self.on_file_complete(&event.file_key.into()).await?;
+            }
 
-        // Handle file completion if the entire file is uploaded or is already being stored.
-        if file_complete {
-            self.on_file_complete(&event.file_key.into()).await?;
+            Ok(())
         }
-
-        Ok(())
+        .instrument(span)
+        .await
     }
 }
 
 /// Handles the [`ProcessMspRespondStoringRequest`] event.
 ///
-/// Triggered when there are new storage request(s) to respond to. Normally, storage requests are
-/// immediately rejected if the MSP cannot store the file (e.g. not enough capacity). However, this event
-/// is able to respond to storage requests that are either being accepted or rejected either way.
+/// Triggered when there are new storage request(s) to respond to. Both acceptances and rejections
+/// (e.g. not enough capacity or an invalid proof received from the user) are queued and responded
+/// to here, batching as many responses as possible, across buckets, in a single extrinsic.
 ///
 /// The MSP will call the `msp_respond_storage_requests_multiple_buckets` extrinsic on the FileSystem pallet to respond to the
 /// storage requests.
+///
+/// Responses that fail for a reason that may be transient (querying the chunks to prove,
+/// generating a proof or submitting the extrinsic) are queued again, up to
+/// [`MAX_RESPOND_STORAGE_REQUEST_TRY_COUNT`] times.
 impl<NT> EventHandler<ProcessMspRespondStoringRequest> for MspUploadFileTask<NT>
 where
     NT: ShNodeType + 'static,
@@ -197,156 +251,137 @@ where
             }
         };
 
-        let own_provider_id = self
-            .storage_hub_handler
+        // The responses that failed are queued again, so the lock is released whatever the
+        // outcome, for the next responses to be processed.
+        let result = self
+            .respond_storing_requests(&event.data.respond_storing_requests)
+            .await;
+
+        // Release the forest root write "lock" and finish the task.
+        self.storage_hub_handler
             .blockchain
-            .query_storage_provider_id(None)
+            .release_forest_root_write_lock(forest_root_write_tx)
             .await?;
 
-        let own_msp_id = match own_provider_id {
-            Some(StorageProviderId::MainStorageProvider(id)) => id,
-            Some(StorageProviderId::BackupStorageProvider(_)) => {
-                return Err(anyhow!("Current node account is a Backup Storage Provider. Expected a Main Storage Provider ID."));
-            }
-            None => {
-                return Err(anyhow!("Failed to get own MSP ID."));
-            }
-        };
+        result
+    }
+}
 
-        let mut file_key_responses = HashMap::new();
+/// Handles the [`DuplicateChunksDetected`] event.
+///
+/// This event is triggered by the FileTransferService when a peer keeps sending chunks of a file
+/// that this MSP already stored, wasting bandwidth.
+impl<NT> EventHandler<DuplicateChunksDetected> for MspUploadFileTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: MspForestStorageHandlerT,
+{
+    async fn handle_event(&mut self, event: DuplicateChunksDetected) -> anyhow::Result<()> {
+        self.storage_hub_handler
+            .record_duplicate_chunks_detected(&event);
+        Ok(())
+    }
+}
 
-        let read_file_storage = self.storage_hub_handler.file_storage.read().await;
+/// Handles the [`StorageRequestRevoked`] event.
+///
+/// This event is triggered when a user revokes a storage request on-chain. If this MSP was in the
+/// middle of receiving the file, it stops accepting chunks for it and deletes the partial file.
+impl<NT> EventHandler<StorageRequestRevoked> for MspUploadFileTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: MspForestStorageHandlerT,
+{
+    async fn handle_event(&mut self, event: StorageRequestRevoked) -> anyhow::Result<()> {
+        info!(
+            target: LOG_TARGET,
+            "Storage request for file_key {:x} was revoked",
+            event.file_key
+        );
 
-        for respond in &event.data.respond_storing_requests {
-            info!(target: LOG_TARGET, "Processing respond storing request.");
-            let bucket_id = match read_file_storage.get_metadata(&respond.file_key) {
-                Ok(Some(metadata)) => H256::from_slice(metadata.bucket_id().as_ref()),
-                Ok(None) => {
-                    error!(target: LOG_TARGET, "File does not exist for key {:?}. Maybe we forgot to unregister before deleting?", respond.file_key);
-                    continue;
-                }
-                Err(e) => {
-                    error!(target: LOG_TARGET, "Failed to get file metadata: {:?}", e);
-                    continue;
-                }
-            };
+        self.handle_closed_storage_request(event.file_key.into())
+            .await
+    }
+}
 
-            let entry = file_key_responses
-                .entry(bucket_id)
-                .or_insert_with(|| (Vec::new(), Vec::new()));
+/// Handles the [`StorageRequestExpired`] event.
+///
+/// This event is triggered when a storage request expires on-chain. If this MSP was in the
+/// middle of receiving the file, it stops accepting chunks for it and deletes the partial file.
+impl<NT> EventHandler<StorageRequestExpired> for MspUploadFileTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: MspForestStorageHandlerT,
+{
+    async fn handle_event(&mut self, event: StorageRequestExpired) -> anyhow::Result<()> {
+        info!(
+            target: LOG_TARGET,
+            "Storage request for file_key {:x} expired",
+            event.file_key
+        );
 
-            match &respond.response {
-                MspRespondStorageRequest::Accept => {
-                    let chunks_to_prove = match self
-                        .storage_hub_handler
-                        .blockchain
-                        .query_msp_confirm_chunks_to_prove_for_file(own_msp_id, respond.file_key)
-                        .await
-                    {
-                        Ok(chunks) => chunks,
-                        Err(e) => {
-                            error!(target: LOG_TARGET, "Failed to get chunks to prove: {:?}", e);
-                            continue;
-                        }
-                    };
+        self.handle_closed_storage_request(event.file_key.into())
+            .await
+    }
+}
 
-                    let proof = match read_file_storage
-                        .generate_proof(&respond.file_key, &HashSet::from_iter(chunks_to_prove))
-                    {
-                        Ok(p) => p,
-                        Err(e) => {
-                            error!(target: LOG_TARGET, "Failed to generate proof: {:?}", e);
-                            continue;
-                        }
-                    };
+/// Handles the [`UserWithoutFunds`] event.
+///
+/// This event is triggered when a user runs out of funds to pay for its payment streams. This MSP
+/// stops receiving any file of the user it is in the middle of receiving, and deletes the partial
+/// files. Buckets already stored are handled by the [`MspStopStoringInsolventUserTask`](super::msp_stop_storing_insolvent_user::MspStopStoringInsolventUserTask).
+impl<NT> EventHandler<UserWithoutFunds> for MspUploadFileTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: MspForestStorageHandlerT,
+{
+    async fn handle_event(&mut self, event: UserWithoutFunds) -> anyhow::Result<()> {
+        info!(
+            target: LOG_TARGET,
+            "User {:?} is without funds, stopping their uploads",
+            event.who
+        );
 
-                    entry.0.push(FileKeyWithProof {
-                        file_key: respond.file_key,
-                        proof,
-                    });
-                }
-                MspRespondStorageRequest::Reject(reason) => {
-                    entry.1.push(RejectedStorageRequest {
-                        file_key: respond.file_key,
-                        reason: reason.clone(),
-                    });
+        let owner = <AccountId32 as AsRef<[u8]>>::as_ref(&event.who).to_vec();
+
+        let registered_file_keys = self
+            .storage_hub_handler
+            .file_transfer
+            .get_registered_file_keys()
+            .await;
+
+        let read_file_storage = self.storage_hub_handler.file_storage.read().await;
+        let mut user_file_keys = Vec::new();
+        for file_key in registered_file_keys {
+            let file_key: H256 = file_key.into();
+            match read_file_storage.get_metadata(&file_key) {
+                Ok(Some(metadata)) if metadata.owner() == &owner => user_file_keys.push(file_key),
+                Ok(_) => {}
+                Err(e) => {
+                    error!(
+                        target: LOG_TARGET,
+                        "Failed to get metadata of file {:x}: {:?}",
+                        file_key,
+                        e
+                    );
                 }
             }
         }
-
         drop(read_file_storage);
 
-        let mut storage_request_msp_response = Vec::new();
-
-        for (bucket_id, (accept, reject)) in file_key_responses.iter_mut() {
-            let fs = self
-                .storage_hub_handler
-                .forest_storage_handler
-                .get_or_create(&bucket_id.as_ref().to_vec())
-                .await;
-
-            let accept = if !accept.is_empty() {
-                let file_keys: Vec<_> = accept
-                    .iter()
-                    .map(|file_key_with_proof| file_key_with_proof.file_key)
-                    .collect();
-
-                let forest_proof = match fs.read().await.generate_proof(file_keys) {
-                    Ok(proof) => proof,
-                    Err(e) => {
-                        error!(target: LOG_TARGET, "Failed to generate non-inclusion forest proof: {:?}", e);
-                        continue;
-                    }
-                };
-
-                Some(StorageRequestMspAcceptedFileKeys {
-                    file_keys_and_proofs: accept.clone(),
-                    forest_proof: forest_proof.proof,
-                })
-            } else {
-                None
-            };
-
-            storage_request_msp_response.push(StorageRequestMspBucketResponse {
-                bucket_id: *bucket_id,
-                accept,
-                reject: reject.clone(),
-            });
-        }
-
-        let call = storage_hub_runtime::RuntimeCall::FileSystem(
-            pallet_file_system::Call::msp_respond_storage_requests_multiple_buckets {
-                storage_request_msp_response: storage_request_msp_response.clone(),
-            },
-        );
-
-        self.storage_hub_handler
-            .blockchain
-            .send_extrinsic(call, Default::default())
-            .await?
-            .with_timeout(Duration::from_secs(60))
-            .watch_for_success(&self.storage_hub_handler.blockchain)
-            .await?;
-
-        // Remove the files that were rejected from the File Storage.
-        // Accepted files will be added to the Bucket's Forest Storage by the BlockchainService.
-        for storage_request_msp_bucket_response in storage_request_msp_response {
-            let mut fs = self.storage_hub_handler.file_storage.write().await;
-
-            for RejectedStorageRequest { file_key, .. } in
-                &storage_request_msp_bucket_response.reject
-            {
-                if let Err(e) = fs.delete_file(&file_key) {
-                    error!(target: LOG_TARGET, "Failed to delete file {:?}: {:?}", file_key, e);
-                }
+        // Files already accepted into the bucket's Forest are left to the stop storing flow.
+        for file_key in user_file_keys {
+            if let Err(e) = self.handle_closed_storage_request(file_key).await {
+                error!(
+                    target: LOG_TARGET,
+                    "Failed to stop the upload of file {:x}: {:?}",
+                    file_key,
+                    e
+                );
             }
         }
 
-        // Release the forest root write "lock" and finish the task.
-        self.storage_hub_handler
-            .blockchain
-            .release_forest_root_write_lock(forest_root_write_tx)
-            .await
+        Ok(())
     }
 }
 
@@ -358,6 +393,7 @@ where
     async fn handle_new_storage_request_event(
         &mut self,
         event: NewStorageRequest,
+        file_key_cleanup: &mut FileKeyCleanup,
     ) -> anyhow::Result<()> {
         if event.size == 0 {
             let err_msg = "File size cannot be 0";
@@ -412,14 +448,14 @@ where
         }
 
         // Construct file metadata.
-        let metadata = FileMetadata::new(
+        let metadata = FileMetadata::validated(
             <AccountId32 as AsRef<[u8]>>::as_ref(&event.who).to_vec(),
             event.bucket_id.as_ref().to_vec(),
             event.location.to_vec(),
             event.size as u64,
             event.fingerprint,
         )
-        .map_err(|_| anyhow::anyhow!("Invalid file metadata"))?;
+        .map_err(|e| anyhow::anyhow!("Invalid file metadata: {:?}", e))?;
 
         // Get the file key.
         let file_key: FileKey = metadata
@@ -435,8 +471,42 @@ where
         let read_fs = fs.read().await;
 
         // If we do not have the file already in forest storage, we must take into account the
-        // available storage capacity.
+        // bucket data limit and the available storage capacity.
         if !read_fs.contains_file_key(&file_key.into())? {
+            // Refuse new uploads while the node is already receiving as many files as it is
+            // configured to handle simultaneously, to avoid contention on the file storage.
+            let active_uploads = self
+                .storage_hub_handler
+                .file_transfer
+                .get_active_upload_count()
+                .await;
+            let max_active_uploads = self.storage_hub_handler.provider_config.max_active_uploads;
+            if active_uploads >= max_active_uploads {
+                return Err(self
+                    .handle_upload_failure(
+                        &file_key.into(),
+                        event.bucket_id,
+                        UploadFailure::MaxConcurrentUploadsReached {
+                            active_uploads,
+                            max_active_uploads,
+                        },
+                    )
+                    .await);
+            }
+
+            if !self
+                .reserve_bucket_size(event.bucket_id, event.size)
+                .await?
+            {
+                return Err(self
+                    .handle_upload_failure(
+                        &file_key.into(),
+                        event.bucket_id,
+                        UploadFailure::BucketDataLimitReached,
+                    )
+                    .await);
+            }
+
             let available_capacity = self
                 .storage_hub_handler
                 .blockchain
@@ -509,55 +579,34 @@ where
 
                 // Reject storage request if the new available capacity is still less than the file size.
                 if available_capacity < event.size {
-                    let err_msg = "Increased storage capacity is still insufficient to volunteer for file. Rejecting storage request.";
-                    warn!(
-                        target: LOG_TARGET, "{}", err_msg
-                    );
-
-                    // Build extrinsic.
-                    let call = storage_hub_runtime::RuntimeCall::FileSystem(
-                        pallet_file_system::Call::msp_respond_storage_requests_multiple_buckets {
-                            storage_request_msp_response: vec![StorageRequestMspBucketResponse {
-                                bucket_id: event.bucket_id,
-                                accept: None,
-                                reject: vec![RejectedStorageRequest {
-                                    file_key: H256(event.file_key.into()),
-                                    reason: RejectedStorageRequestReason::ReachedMaximumCapacity,
-                                }],
-                            }],
-                        },
-                    );
-
-                    self.storage_hub_handler
-                        .blockchain
-                        .send_extrinsic(call, Default::default())
-                        .await?
-                        .with_timeout(Duration::from_secs(60))
-                        .watch_for_success(&self.storage_hub_handler.blockchain)
-                        .await?;
-
-                    return Err(anyhow::anyhow!(err_msg));
+                    return Err(self
+                        .handle_upload_failure(
+                            &file_key.into(),
+                            event.bucket_id,
+                            UploadFailure::CapacityReached,
+                        )
+                        .await);
                 }
             }
         }
 
-        self.file_key_cleanup = Some(file_key.into());
+        file_key_cleanup.set(file_key.into());
+
+        self.storage_hub_handler.start_upload(
+            file_key.into(),
+            metadata.chunks_count(),
+            Span::current(),
+        );
 
         let mut write_file_storage = self.storage_hub_handler.file_storage.write().await;
 
         // Create file in file storage if it is not present so we can write uploaded chunks as soon as possible.
-        if write_file_storage
-            .get_metadata(&file_key.into())
-            .map_err(|e| anyhow!("Failed to get metadata from file storage: {:?}", e))?
-            .is_none()
-        {
-            write_file_storage
-                .insert_file(
-                    metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>(),
-                    metadata,
-                )
-                .map_err(|e| anyhow!("Failed to insert file in file storage: {:?}", e))?;
-        }
+        write_file_storage
+            .insert_file_if_absent(
+                metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>(),
+                metadata,
+            )
+            .map_err(|e| anyhow!("Failed to insert file in file storage: {:?}", e))?;
 
         drop(write_file_storage);
 
@@ -584,21 +633,67 @@ where
         Ok(())
     }
 
-    async fn handle_remote_upload_request_event(
-        &mut self,
-        event: RemoteUploadRequest,
+    /// Checks that storing `file_size` more in the bucket does not exceed the data limit of its
+    /// value proposition, reserving it in the cached bucket size if so.
+    ///
+    /// Returns `false` if the file does not fit in the bucket.
+    async fn reserve_bucket_size(
+        &self,
+        bucket_id: H256,
+        file_size: StorageData,
     ) -> anyhow::Result<bool> {
-        let file_key = event.file_key.into();
-        let bucket_id = match self
-            .storage_hub_handler
-            .file_storage
-            .read()
-            .await
-            .get_metadata(&file_key)
-        {
-            Ok(metadata) => match metadata {
-                Some(metadata) => H256::from_slice(metadata.bucket_id().as_ref()),
-                None => {
+        let mut bucket_sizes = self.bucket_sizes.write().await;
+
+        if !bucket_sizes.contains_key(&bucket_id) {
+            let (size, data_limit) = self
+                .storage_hub_handler
+                .blockchain
+                .query_bucket_size_and_data_limit(bucket_id)
+                .await
+                .map_err(|e| {
+                    let err_msg = format!(
+                        "Failed to query size and data limit of bucket {:?}: {:?}",
+                        bucket_id, e
+                    );
+                    error!(target: LOG_TARGET, err_msg);
+                    anyhow!(err_msg)
+                })?;
+
+            bucket_sizes.insert(bucket_id, BucketSizeAndLimit { size, data_limit });
+        }
+
+        let bucket_size_and_limit = bucket_sizes
+            .get_mut(&bucket_id)
+            .expect("Bucket size was just inserted if missing; qed");
+
+        if !fits_in_bucket(
+            bucket_size_and_limit.size,
+            file_size,
+            bucket_size_and_limit.data_limit,
+        ) {
+            return Ok(false);
+        }
+
+        bucket_size_and_limit.size = bucket_size_and_limit.size.saturating_add(file_size);
+
+        Ok(true)
+    }
+
+    async fn handle_remote_upload_request_event(
+        &mut self,
+        event: RemoteUploadRequest,
+    ) -> anyhow::Result<bool> {
+        let file_key = event.file_key.into();
+        let bucket_id = match self
+            .storage_hub_handler
+            .file_storage
+            .read()
+            .await
+            .get_metadata(&file_key)
+        {
+            Ok(metadata) => match metadata {
+                Some(metadata) => H256::from_slice(metadata.bucket_id().as_ref()),
+                None => {
                     let err_msg = format!("File does not exist for key {:?}. Maybe we forgot to unregister before deleting?", event.file_key);
                     error!(target: LOG_TARGET, err_msg);
                     return Err(anyhow!(err_msg));
@@ -632,30 +727,40 @@ where
         }
 
         // Verify and extract chunks from proof
-        let proven = match event
-            .file_key_proof
-            .proven::<StorageProofsMerkleTrieLayout>()
-        {
+        let proven = debug_span!(target: LOG_TARGET, "verify_proof").in_scope(|| {
+            event
+                .file_key_proof
+                .proven_expecting::<StorageProofsMerkleTrieLayout>(
+                    1..=BATCH_CHUNK_FILE_TRANSFER_MAX_CHUNKS,
+                )
+        });
+        let proven = match proven {
             Ok(proven) => {
-                if proven.is_empty() {
+                // Calculate total batch size
+                let total_batch_size: usize = proven.iter().map(|chunk| chunk.data.len()).sum();
+
+                if total_batch_size > BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE {
                     Err(anyhow::anyhow!(
-                        "Expected at least one proven chunk but got none."
+                        "Total batch size {} bytes exceeds maximum allowed size of {} bytes",
+                        total_batch_size,
+                        BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE
                     ))
                 } else {
-                    // Calculate total batch size
-                    let total_batch_size: usize = proven.iter().map(|chunk| chunk.data.len()).sum();
-
-                    if total_batch_size > BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE {
-                        Err(anyhow::anyhow!(
-                            "Total batch size {} bytes exceeds maximum allowed size of {} bytes",
-                            total_batch_size,
-                            BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE
-                        ))
-                    } else {
-                        Ok(proven)
-                    }
+                    Ok(proven)
                 }
             }
+            Err(ProvenFileKeyError::UnexpectedChunkCount { expected, got }) => {
+                if let Some(metrics) = &self.storage_hub_handler.metrics {
+                    metrics.reject_chunks(ChunkRejection::InvalidProof, got);
+                }
+                return Err(self
+                    .handle_upload_failure(
+                        &file_key,
+                        bucket_id,
+                        UploadFailure::UnexpectedChunkCount { expected, got },
+                    )
+                    .await);
+            }
             Err(e) => Err(anyhow::anyhow!(
                 "Failed to verify and get proven file key chunks: {:?}",
                 e
@@ -671,18 +776,55 @@ where
                     "Failed to verify proof for file {:?}: {:?}",
                     file_key, error
                 );
-                self.handle_rejected_storage_request(
-                    &file_key,
-                    bucket_id,
-                    RejectedStorageRequestReason::ReceivedInvalidProof,
-                )
-                .await?;
-                return Err(anyhow!("Failed to verify proof"));
+                // The chunks of a batch whose proof failed to verify are unknown, so it counts
+                // as a single rejected chunk.
+                if let Some(metrics) = &self.storage_hub_handler.metrics {
+                    metrics.reject_chunks(ChunkRejection::InvalidProof, 1);
+                }
+                return Err(self
+                    .handle_upload_failure(&file_key, bucket_id, UploadFailure::InvalidProof)
+                    .await);
             }
         };
 
-        let mut write_file_storage = self.storage_hub_handler.file_storage.write().await;
+        // Reject the whole batch if any chunk is outside of the file's range. Storing it would
+        // inflate the stored chunks count and prevent the file from ever being complete.
+        let chunks_count = file_metadata.chunks_count();
+        if let Some(chunk) = proven
+            .iter()
+            .find(|chunk| chunk.key.as_u64() >= chunks_count)
+        {
+            error!(
+                target: LOG_TARGET,
+                "Chunk {:?} is out of range for file {:?} with {} chunks. Reporting peer {:?}",
+                chunk.key, file_key, chunks_count, event.peer
+            );
+            if let Err(e) = self
+                .storage_hub_handler
+                .file_transfer
+                .report_peer(event.peer, "chunk out of range")
+                .await
+            {
+                error!(target: LOG_TARGET, "Failed to report peer {:?}: {:?}", event.peer, e);
+            }
+            if let Some(metrics) = &self.storage_hub_handler.metrics {
+                metrics.reject_chunks(ChunkRejection::OutOfRange, proven.len());
+            }
+            return Err(anyhow!(
+                "Chunk {:?} is out of range for file with {} chunks",
+                chunk.key,
+                chunks_count
+            ));
+        }
+
+        // The File Storage serialises the writes to each file internally, so shared access is enough
+        // and uploads of other files and proof generation are not blocked by this one.
+        let read_file_storage = self.storage_hub_handler.file_storage.read().await;
         let mut file_complete = false;
+        // Chunks of the file stored after the last successful write of this batch, if any.
+        let mut stored_chunks = None;
+        // Whether some chunks of the batch were already stored.
+        let mut has_duplicate_chunks = false;
 
         // Process each proven chunk in the batch
         for chunk in proven {
@@ -699,155 +841,224 @@ where
                     expected_chunk_size,
                     chunk.data.len()
                 );
-                self.handle_rejected_storage_request(
-                    &file_key,
-                    bucket_id,
-                    RejectedStorageRequestReason::ReceivedInvalidProof,
-                )
-                .await?;
-                return Err(anyhow!(
-                    "Invalid chunk size for chunk {}: Expected: {}, got: {}",
-                    chunk_idx,
-                    expected_chunk_size,
-                    chunk.data.len()
-                ));
+                if let Some(metrics) = &self.storage_hub_handler.metrics {
+                    metrics.reject_chunks(ChunkRejection::InvalidProof, 1);
+                }
+                drop(read_file_storage);
+                return Err(self
+                    .handle_upload_failure(&file_key, bucket_id, UploadFailure::InvalidProof)
+                    .await);
             }
 
-            let write_result = write_file_storage.write_chunk(&file_key, &chunk.key, &chunk.data);
+            let write_result = debug_span!(target: LOG_TARGET, "write_chunk", chunk = chunk_idx)
+                .in_scope(|| {
+                    write_chunk_metered(
+                        self.storage_hub_handler.metrics.as_ref(),
+                        &*read_file_storage,
+                        &file_key,
+                        &chunk.key,
+                        &chunk.data,
+                    )
+                });
 
             match write_result {
                 Ok(outcome) => match outcome {
                     FileStorageWriteOutcome::FileComplete => {
                         file_complete = true;
+                        stored_chunks = Some(chunks_count);
                         break; // We can stop processing chunks if the file is complete
                     }
-                    FileStorageWriteOutcome::FileIncomplete => continue,
+                    FileStorageWriteOutcome::FileIncomplete { stored, .. } => {
+                        stored_chunks = Some(stored);
+                        continue;
+                    }
                 },
-                Err(error) => match error {
-                    FileStorageWriteError::FileChunkAlreadyExists => {
+                Err(error) => {
+                    // Writes to a sealed file fail for the chunks it already stores as well.
+                    if matches!(
+                        error,
+                        FileStorageError::FileChunkAlreadyExists | FileStorageError::FileSealed
+                    ) {
+                        has_duplicate_chunks = true;
+                    }
+                    let failure = UploadFailure::Write(error);
+                    if failure.action() == UploadFailureAction::Ignore {
                         trace!(
                             target: LOG_TARGET,
-                            "Received duplicate chunk with key: {:?}",
-                            chunk.key
+                            "Skipping chunk with key {:?}: {}",
+                            chunk.key,
+                            failure
                         );
                         // Continue processing other chunks
                         continue;
                     }
-                    FileStorageWriteError::FileDoesNotExist => {
-                        self.handle_rejected_storage_request(
-                            &file_key,
-                            bucket_id,
-                            RejectedStorageRequestReason::InternalError,
-                        )
-                        .await?;
-                        return Err(anyhow::anyhow!(format!(
-                            "File does not exist for key {:?}. Maybe we forgot to unregister before deleting?",
-                            file_key
-                        )));
-                    }
-                    FileStorageWriteError::FailedToGetFileChunk
-                    | FileStorageWriteError::FailedToInsertFileChunk
-                    | FileStorageWriteError::FailedToDeleteChunk
-                    | FileStorageWriteError::FailedToDeleteRoot
-                    | FileStorageWriteError::FailedToPersistChanges
-                    | FileStorageWriteError::FailedToParseFileMetadata
-                    | FileStorageWriteError::FailedToParseFingerprint
-                    | FileStorageWriteError::FailedToReadStorage
-                    | FileStorageWriteError::FailedToUpdatePartialRoot
-                    | FileStorageWriteError::FailedToParsePartialRoot
-                    | FileStorageWriteError::FailedToGetStoredChunksCount
-                    | FileStorageWriteError::ChunkCountOverflow => {
-                        self.handle_rejected_storage_request(
-                            &file_key,
-                            bucket_id,
-                            RejectedStorageRequestReason::InternalError,
-                        )
-                        .await?;
-                        return Err(anyhow::anyhow!(format!(
-                            "Internal trie read/write error {:?}:{:?}",
-                            file_key, chunk.key
-                        )));
-                    }
-                    FileStorageWriteError::FingerprintAndStoredFileMismatch => {
-                        self.handle_rejected_storage_request(
-                            &file_key,
-                            bucket_id,
-                            RejectedStorageRequestReason::InternalError,
-                        )
-                        .await?;
-                        return Err(anyhow::anyhow!(format!(
-                            "Invariant broken! This is a bug! Fingerprint and stored file mismatch for key {:?}.",
-                            file_key
-                        )));
-                    }
-                    FileStorageWriteError::FailedToConstructTrieIter
-                    | FileStorageWriteError::FailedToContructFileTrie => {
-                        self.handle_rejected_storage_request(
-                            &file_key,
-                            bucket_id,
-                            RejectedStorageRequestReason::InternalError,
-                        )
-                        .await?;
-                        return Err(anyhow::anyhow!(format!(
-                            "This is a bug! Failed to construct trie iter for key {:?}.",
-                            file_key
-                        )));
-                    }
-                },
+
+                    drop(read_file_storage);
+                    return Err(self
+                        .handle_upload_failure(&file_key, bucket_id, failure)
+                        .await);
+                }
             }
         }
 
         // If we haven't found the file to be complete during chunk processing,
         // check if it's complete now (in case this was the last batch)
         if !file_complete {
-            match write_file_storage.is_file_complete(&file_key) {
+            match read_file_storage.is_file_complete(&file_key) {
                 Ok(is_complete) => file_complete = is_complete,
                 Err(e) => {
-                    self.handle_rejected_storage_request(
-                        &file_key,
-                        bucket_id,
-                        RejectedStorageRequestReason::InternalError,
-                    )
-                    .await?;
-                    let err_msg = format!(
-                        "Failed to check if file is complete. The file key {:?} is in a bad state with error: {:?}",
+                    drop(read_file_storage);
+                    error!(
+                        target: LOG_TARGET,
+                        "Failed to check if file {:?} is complete: {:?}",
                         file_key, e
                     );
-                    error!(target: LOG_TARGET, "{}", err_msg);
-                    return Err(anyhow::anyhow!(err_msg));
+                    return Err(self
+                        .handle_upload_failure(
+                            &file_key,
+                            bucket_id,
+                            UploadFailure::CompletenessCheckFailed,
+                        )
+                        .await);
                 }
             }
         }
 
+        // Completed files must not change anymore.
+        if file_complete {
+            if let Err(e) = read_file_storage.seal_file(&file_key) {
+                error!(
+                    target: LOG_TARGET,
+                    "Failed to seal complete file {:?}: {:?}",
+                    file_key,
+                    e
+                );
+            }
+        }
+
+        // The writes report the stored chunks, so the File Storage is only queried if none of
+        // the chunks of the batch were written.
+        let stored_chunks = match stored_chunks {
+            Some(stored_chunks) => Ok(stored_chunks),
+            None => read_file_storage.stored_chunks_count(&file_key),
+        };
+        match stored_chunks {
+            Ok(stored_chunks) => self
+                .storage_hub_handler
+                .upload_progress
+                .record_chunks(&file_key, stored_chunks),
+            Err(e) => warn!(
+                target: LOG_TARGET,
+                "Failed to get stored chunks count for file {:?}: {:?}",
+                file_key,
+                e
+            ),
+        }
+        drop(read_file_storage);
+        self.storage_hub_handler.report_uploads_in_progress();
+
+        // Peers resuming an upload should only send the chunks that were not stored yet.
+        if has_duplicate_chunks {
+            if let Err(e) = self
+                .storage_hub_handler
+                .file_transfer
+                .report_duplicate_chunks(event.peer, event.file_key)
+                .await
+            {
+                error!(target: LOG_TARGET, "Failed to report duplicate chunks sent by peer {:?}: {:?}", event.peer, e);
+            }
+        }
+
         Ok(file_complete)
     }
 
+    /// Deals with a `failure` while handling the storage request for `file_key`, according to
+    /// the [`UploadFailureAction`] it is classified into, and returns the error to bubble up.
+    ///
+    /// Only failures attributable to the user (or to this MSP's policy) and broken local state
+    /// result in the storage request being rejected. Transient failures keep the upload open, so
+    /// that the user can resend the chunks that were not acknowledged.
+    ///
+    /// The caller must not hold a lock on the file storage.
+    async fn handle_upload_failure(
+        &self,
+        file_key: &H256,
+        bucket_id: H256,
+        failure: UploadFailure,
+    ) -> anyhow::Error {
+        let action = failure.action();
+        match action {
+            UploadFailureAction::Ignore => {}
+            UploadFailureAction::RetryLocally => warn!(
+                target: LOG_TARGET,
+                "{} for file key {:x}. Keeping the upload open for the chunks to be resent.",
+                failure,
+                file_key
+            ),
+            UploadFailureAction::Reject(ref reason) => warn!(
+                target: LOG_TARGET,
+                "{} for file key {:x}. Rejecting storage request with reason {:?}.",
+                failure,
+                file_key,
+                reason
+            ),
+            UploadFailureAction::AbortAndAlert => error!(
+                target: LOG_TARGET,
+                "ALERT: {} for file key {:x}. The local state of the file is broken, rejecting storage request as an internal error.",
+                failure,
+                file_key
+            ),
+        }
+
+        if let Some(reason) = action.rejection_reason() {
+            if let Err(e) = self
+                .handle_rejected_storage_request(file_key, bucket_id, reason)
+                .await
+            {
+                return e.context(format!(
+                    "Failed to reject storage request for file key {:x} after: {}",
+                    file_key, failure
+                ));
+            }
+        }
+
+        anyhow!("{} for file key {:x}", failure, file_key)
+    }
+
+    /// Rejects the storage request for `file_key`.
+    ///
+    /// The rejection is queued to be sent in a batch by the [`ProcessMspRespondStoringRequest`]
+    /// handler, while the file is immediately unregistered and deleted from the file storage.
+    ///
+    /// The caller must not hold a lock on the file storage.
     async fn handle_rejected_storage_request(
         &self,
         file_key: &H256,
         bucket_id: H256,
         reason: RejectedStorageRequestReason,
     ) -> anyhow::Result<()> {
-        let call = storage_hub_runtime::RuntimeCall::FileSystem(
-            pallet_file_system::Call::msp_respond_storage_requests_multiple_buckets {
-                storage_request_msp_response: vec![StorageRequestMspBucketResponse {
-                    bucket_id,
-                    accept: None,
-                    reject: vec![RejectedStorageRequest {
-                        file_key: *file_key,
-                        reason,
-                    }],
-                }],
-            },
-        );
-
-        self.storage_hub_handler
+        // If the storage request no longer exists on-chain (i.e. it was revoked or it expired),
+        // there is nothing to reject.
+        if let Err(IsStorageRequestOpenToVolunteersError::StorageRequestNotFound) = self
+            .storage_hub_handler
             .blockchain
-            .send_extrinsic(call, Default::default())
-            .await?
-            .with_timeout(Duration::from_secs(60))
-            .watch_for_success(&self.storage_hub_handler.blockchain)
-            .await?;
+            .is_storage_request_open_to_volunteers(*file_key)
+            .await
+        {
+            warn!(
+                target: LOG_TARGET,
+                "Storage request for file key {:x} no longer exists, skipping rejection",
+                file_key
+            );
+        } else {
+            self.storage_hub_handler
+                .blockchain
+                .queue_msp_respond_storage_request(
+                    RespondStorageRequest::new(*file_key, MspRespondStorageRequest::Reject(reason))
+                        .with_bucket_id(bucket_id),
+                )
+                .await?;
+        }
 
         // Unregister the file
         self.unregister_file(*file_key).await?;
@@ -855,6 +1066,53 @@ where
         Ok(())
     }
 
+    /// Discards the local state of a file whose storage request is no longer open on-chain.
+    ///
+    /// Files that are already in the bucket's Forest are left untouched, as they were accepted
+    /// by this MSP. Any pending respond storage request for the file is dropped by the
+    /// BlockchainService.
+    async fn handle_closed_storage_request(&self, file_key: H256) -> anyhow::Result<()> {
+        let metadata = match self
+            .storage_hub_handler
+            .file_storage
+            .read()
+            .await
+            .get_metadata(&file_key)
+            .map_err(|e| anyhow!("Failed to get file metadata: {:?}", e))?
+        {
+            Some(metadata) => metadata,
+            None => {
+                trace!(
+                    target: LOG_TARGET,
+                    "File key {:x} is not in file storage, nothing to clean up.",
+                    file_key
+                );
+                self.storage_hub_handler
+                    .end_upload(&file_key, UploadState::Rejected);
+                return Ok(());
+            }
+        };
+
+        let bucket_id = H256::from_slice(metadata.bucket_id().as_ref());
+        if let Some(fs) = self
+            .storage_hub_handler
+            .forest_storage_handler
+            .get(&bucket_id.as_ref().to_vec())
+            .await
+        {
+            if fs.read().await.contains_file_key(&file_key)? {
+                debug!(
+                    target: LOG_TARGET,
+                    "File key {:x} is already in the bucket's Forest, skipping cleanup.",
+                    file_key
+                );
+                return Ok(());
+            }
+        }
+
+        self.unregister_file(file_key).await
+    }
+
     async fn unregister_file(&self, file_key: H256) -> anyhow::Result<()> {
         warn!(target: LOG_TARGET, "Unregistering file {:?}", file_key);
 
@@ -871,6 +1129,10 @@ where
 
         // TODO: Handle error
         let _ = write_file_storage.delete_file(&file_key);
+        drop(write_file_storage);
+
+        self.storage_hub_handler
+            .end_upload(&file_key, UploadState::Rejected);
 
         Ok(())
     }
@@ -896,4 +1158,504 @@ where
 
         Ok(())
     }
+
+    /// Responds to the storage requests in `respond_storing_requests` in a single extrinsic,
+    /// queueing again those whose response failed to be sent.
+    async fn respond_storing_requests(
+        &self,
+        respond_storing_requests: &[RespondStorageRequest],
+    ) -> anyhow::Result<()> {
+        let own_msp_id = match self
+            .storage_hub_handler
+            .blockchain
+            .query_storage_provider_id(None)
+            .await
+        {
+            Ok(Some(StorageProviderId::MainStorageProvider(id))) => Ok(id),
+            Ok(Some(StorageProviderId::BackupStorageProvider(_))) => Err(anyhow!("Current node account is a Backup Storage Provider. Expected a Main Storage Provider ID.")),
+            Ok(None) => Err(anyhow!("Failed to get own MSP ID.")),
+            Err(e) => Err(anyhow!("Failed to query own MSP ID: {:?}", e)),
+        };
+        let own_msp_id = match own_msp_id {
+            Ok(id) => id,
+            Err(e) => {
+                // None of the responses was sent, so they are all queued again.
+                error!(target: LOG_TARGET, "Failed to respond to {} storage request(s): {:?}", respond_storing_requests.len(), e);
+                self.retry_respond_storage_requests(respond_storing_requests.to_vec())
+                    .await?;
+                return Err(e);
+            }
+        };
+
+        let mut file_key_responses = HashMap::new();
+        let mut respond_requests_to_retry = Vec::new();
+
+        // Query runtime for the chunks to prove for all the accepted files at once.
+        let accepted_file_keys: Vec<H256> = respond_storing_requests
+            .iter()
+            .filter(|respond| matches!(respond.response, MspRespondStorageRequest::Accept))
+            .map(|respond| respond.file_key)
+            .collect();
+        let chunks_to_prove_per_file: HashMap<_, _> = self
+            .storage_hub_handler
+            .blockchain
+            .query_confirm_chunks_to_prove_for_files(
+                StorageProviderId::MainStorageProvider(own_msp_id),
+                &accepted_file_keys,
+            )
+            .await
+            .into_iter()
+            .collect();
+
+        let read_file_storage = self.storage_hub_handler.file_storage.read().await;
+
+        for respond in respond_storing_requests {
+            info!(target: LOG_TARGET, "Processing respond storing request.");
+            let bucket_id = match respond.bucket_id.map_or_else(
+                || {
+                    read_file_storage
+                        .get_metadata(&respond.file_key)
+                        .map(|maybe_metadata| {
+                            maybe_metadata
+                                .map(|metadata| H256::from_slice(metadata.bucket_id().as_ref()))
+                        })
+                },
+                |bucket_id| Ok(Some(bucket_id)),
+            ) {
+                Ok(Some(bucket_id)) => bucket_id,
+                Ok(None) => {
+                    error!(target: LOG_TARGET, "File does not exist for key {:?}. Maybe we forgot to unregister before deleting?", respond.file_key);
+                    continue;
+                }
+                Err(e) => {
+                    error!(target: LOG_TARGET, "Failed to get file metadata: {:?}", e);
+                    continue;
+                }
+            };
+
+            let entry = file_key_responses
+                .entry(bucket_id)
+                .or_insert_with(|| (Vec::new(), Vec::new(), Vec::new()));
+
+            match &respond.response {
+                MspRespondStorageRequest::Accept => {
+                    let chunks_to_prove = match chunks_to_prove_per_file.get(&respond.file_key) {
+                        Some(Ok(chunks)) => chunks.clone(),
+                        e => {
+                            error!(target: LOG_TARGET, "Failed to get chunks to prove for file {:?}: {:?}", respond.file_key, e);
+                            respond_requests_to_retry.push(respond.clone());
+                            continue;
+                        }
+                    };
+
+                    let proof = match generate_proof_metered(
+                        self.storage_hub_handler.metrics.as_ref(),
+                        &*read_file_storage,
+                        &respond.file_key,
+                        &HashSet::from_iter(chunks_to_prove),
+                    ) {
+                        Ok(p) => p,
+                        Err(e) => {
+                            error!(target: LOG_TARGET, "Failed to generate proof for file {:?}: {:?}", respond.file_key, e);
+                            respond_requests_to_retry.push(respond.clone());
+                            continue;
+                        }
+                    };
+
+                    entry.0.push(FileKeyWithProof {
+                        file_key: respond.file_key,
+                        proof,
+                    });
+                }
+                MspRespondStorageRequest::Reject(reason) => {
+                    entry.1.push(RejectedStorageRequest {
+                        file_key: respond.file_key,
+                        reason: reason.clone(),
+                    });
+                }
+            }
+            entry.2.push(respond.clone());
+        }
+
+        drop(read_file_storage);
+
+        let mut storage_request_msp_response = Vec::new();
+        let mut responded_requests = Vec::new();
+
+        for (bucket_id, (accept, reject, requests)) in file_key_responses.iter_mut() {
+            let fs = self
+                .storage_hub_handler
+                .forest_storage_handler
+                .get_or_create(&bucket_id.as_ref().to_vec())
+                .await;
+
+            let accept = if !accept.is_empty() {
+                let file_keys: Vec<_> = accept
+                    .iter()
+                    .map(|file_key_with_proof| file_key_with_proof.file_key)
+                    .collect();
+
+                // Generate the proof from the snapshot of the bucket's current Forest root, which
+                // is shared with the other proofs generated against this same root, e.g. those of
+                // every empty bucket. Fall back to the bucket's Forest if its root changed in the
+                // meantime.
+                let forest_root = fs.read().await.root();
+                let fs = self
+                    .storage_hub_handler
+                    .forest_storage_handler
+                    .snapshot_by_root(&bucket_id.as_ref().to_vec(), &forest_root)
+                    .await
+                    .unwrap_or(fs);
+
+                let forest_proof = match fs.read().await.generate_proof(file_keys) {
+                    Ok(proof) => proof,
+                    Err(e) => {
+                        error!(target: LOG_TARGET, "Failed to generate non-inclusion forest proof for bucket {:?}: {:?}", bucket_id, e);
+                        respond_requests_to_retry.append(requests);
+                        continue;
+                    }
+                };
+
+                Some(StorageRequestMspAcceptedFileKeys {
+                    file_keys_and_proofs: accept.clone(),
+                    forest_proof: forest_proof.proof,
+                })
+            } else {
+                None
+            };
+
+            storage_request_msp_response.push(StorageRequestMspBucketResponse {
+                bucket_id: *bucket_id,
+                accept,
+                reject: reject.clone(),
+            });
+            responded_requests.append(requests);
+        }
+
+        let call = storage_hub_runtime::RuntimeCall::FileSystem(
+            pallet_file_system::Call::msp_respond_storage_requests_multiple_buckets {
+                storage_request_msp_response: storage_request_msp_response.clone(),
+            },
+        );
+
+        // The response is sent within a span following the uploads of the files it responds to,
+        // so that its inclusion can be told apart from the other uploads.
+        let respond_span = self.storage_hub_handler.upload_spans.follow_uploads(
+            info_span!(
+                target: LOG_TARGET,
+                "respond_storing",
+                file_keys = ?responded_requests.iter().map(|r| r.file_key).collect::<Vec<_>>()
+            ),
+            responded_requests.iter().map(|request| &request.file_key),
+        );
+
+        let events = async {
+            match self
+                .storage_hub_handler
+                .blockchain
+                .send_extrinsic(call, Default::default())
+                .await
+            {
+                Ok(submitted) => submitted
+                    .with_timeout(Duration::from_secs(60))
+                    .watch_for_success_with_events(&self.storage_hub_handler.blockchain)
+                    .await
+                    .map_err(|e| anyhow!("Failed to watch transaction: {:?}", e)),
+                Err(e) => Err(e),
+            }
+        }
+        .instrument(respond_span)
+        .await;
+        let events = match events {
+            Ok(events) => {
+                self.retry_respond_storage_requests(respond_requests_to_retry)
+                    .await?;
+                events
+            }
+            Err(e) => {
+                error!(target: LOG_TARGET, "Failed to respond to {} storage request(s): {:?}", responded_requests.len(), e);
+                respond_requests_to_retry.append(&mut responded_requests);
+                self.retry_respond_storage_requests(respond_requests_to_retry)
+                    .await?;
+                return Err(e);
+            }
+        };
+
+        // Reconcile the accepted file keys against the outcome reported by the runtime.
+        // Accepted files are never inserted into the Bucket's Forest Storage by this task. They are
+        // added by the BlockchainService when processing the `MutationsApplied` event of the bucket,
+        // which only contains the file keys the runtime actually added and is followed by a check of
+        // the local bucket root against the on-chain one.
+        let accepted_on_chain: HashSet<H256> = events
+            .iter()
+            .filter_map(|event_record| match &event_record.event {
+                storage_hub_runtime::RuntimeEvent::FileSystem(
+                    pallet_file_system::Event::MspAcceptedStorageRequest { file_key },
+                ) => Some(*file_key),
+                _ => None,
+            })
+            .collect();
+
+        for storage_request_msp_bucket_response in &storage_request_msp_response {
+            let Some(accept) = &storage_request_msp_bucket_response.accept else {
+                continue;
+            };
+
+            for FileKeyWithProof { file_key, .. } in &accept.file_keys_and_proofs {
+                if accepted_on_chain.contains(file_key) {
+                    self.storage_hub_handler
+                        .end_upload(file_key, UploadState::Confirmed);
+                    if let Some(metrics) = &self.storage_hub_handler.metrics {
+                        metrics.files_confirmed.inc();
+                    }
+                } else {
+                    warn!(
+                        target: LOG_TARGET,
+                        "File key {:x} of bucket {:x} was not reported as accepted by the runtime. It will not be added to the bucket's forest.",
+                        file_key,
+                        storage_request_msp_bucket_response.bucket_id
+                    );
+                }
+            }
+        }
+
+        // The size of the buckets responded to changed on-chain, so their cached size is invalidated.
+        let mut bucket_sizes = self.bucket_sizes.write().await;
+        for storage_request_msp_bucket_response in &storage_request_msp_response {
+            bucket_sizes.remove(&storage_request_msp_bucket_response.bucket_id);
+        }
+        drop(bucket_sizes);
+
+        // Remove the files that were rejected from the File Storage.
+        // Files rejected while being uploaded have already been deleted when the rejection was queued.
+        for storage_request_msp_bucket_response in storage_request_msp_response {
+            let mut fs = self.storage_hub_handler.file_storage.write().await;
+
+            for RejectedStorageRequest { file_key, .. } in
+                &storage_request_msp_bucket_response.reject
+            {
+                self.storage_hub_handler
+                    .end_upload(file_key, UploadState::Rejected);
+
+                match fs.delete_file(&file_key) {
+                    Ok(()) | Err(FileStorageError::FileDoesNotExist) => {}
+                    Err(e) => {
+                        error!(target: LOG_TARGET, "Failed to delete file {:?}: {:?}", file_key, e);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Queues again the responses to storage requests in `requests` that failed to be sent,
+    /// dropping those that already failed [`MAX_RESPOND_STORAGE_REQUEST_TRY_COUNT`] times, which
+    /// ends their uploads.
+    async fn retry_respond_storage_requests(
+        &self,
+        requests: Vec<RespondStorageRequest>,
+    ) -> anyhow::Result<()> {
+        let dropped_file_keys = requeue_failed_responses(requests, |request| {
+            self.storage_hub_handler
+                .blockchain
+                .queue_msp_respond_storage_request(request)
+        })
+        .await;
+        for file_key in &dropped_file_keys {
+            self.storage_hub_handler
+                .end_upload(file_key, UploadState::Rejected);
+        }
+
+        Ok(())
+    }
+}
+
+/// Queues again with `queue` the responses to storage requests in `requests` that failed to be
+/// sent, dropping those that already failed [`MAX_RESPOND_STORAGE_REQUEST_TRY_COUNT`] times.
+///
+/// A response that cannot be queued again is dropped as well, without keeping the others from
+/// being queued. Returns the file keys of the dropped responses.
+async fn requeue_failed_responses<F, Fut>(
+    requests: Vec<RespondStorageRequest>,
+    mut queue: F,
+) -> Vec<H256>
+where
+    F: FnMut(RespondStorageRequest) -> Fut,
+    Fut: std::future::Future<Output = anyhow::Result<()>>,
+{
+    let mut dropped_file_keys = Vec::new();
+    for request in requests {
+        match respond_storage_request_to_retry(&request) {
+            Some(request) => {
+                warn!(target: LOG_TARGET, "Enqueuing response to storage request for file key {:?} again! (retry {}/{})", request.file_key, request.try_count, MAX_RESPOND_STORAGE_REQUEST_TRY_COUNT);
+                let file_key = request.file_key;
+                if let Err(e) = queue(request).await {
+                    error!(target: LOG_TARGET, "CRITICAL❗️❗️ Failed to enqueue response to storage request for file key {:?} again: {:?}. Dropping response!", file_key, e);
+                    dropped_file_keys.push(file_key);
+                }
+            }
+            None => {
+                error!(target: LOG_TARGET, "CRITICAL❗️❗️ Failed to respond to storage request for file key {:?} {} times. Max try count exceeded! Dropping response {:?}!", request.file_key, request.try_count + 1, request.response);
+                dropped_file_keys.push(request.file_key);
+            }
+        }
+    }
+
+    dropped_file_keys
+}
+
+/// The response to queue again after failing to send `request`, or `None` if it already failed
+/// [`MAX_RESPOND_STORAGE_REQUEST_TRY_COUNT`] times.
+fn respond_storage_request_to_retry(
+    request: &RespondStorageRequest,
+) -> Option<RespondStorageRequest> {
+    let mut request = request.clone();
+    request.increment_try_count();
+    (request.try_count <= MAX_RESPOND_STORAGE_REQUEST_TRY_COUNT).then_some(request)
+}
+
+/// Whether a file of `file_size` fits in a bucket of `bucket_size` given its `data_limit`.
+///
+/// Mirrors the check done by the runtime when increasing the size of a bucket, in which the new
+/// size of the bucket can be at most equal to the data limit.
+fn fits_in_bucket(
+    bucket_size: StorageData,
+    file_size: StorageData,
+    data_limit: StorageData,
+) -> bool {
+    bucket_size
+        .checked_add(file_size)
+        .is_some_and(|new_bucket_size| new_bucket_size <= data_limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_in_bucket_allows_reaching_the_exact_limit() {
+        assert!(fits_in_bucket(0, 100, 100));
+        assert!(fits_in_bucket(60, 40, 100));
+        assert!(fits_in_bucket(100, 0, 100));
+    }
+
+    #[test]
+    fn fits_in_bucket_rejects_going_over_the_limit() {
+        assert!(!fits_in_bucket(0, 101, 100));
+        assert!(!fits_in_bucket(60, 41, 100));
+        assert!(!fits_in_bucket(100, 1, 100));
+        assert!(!fits_in_bucket(StorageData::MAX, 1, StorageData::MAX));
+    }
+
+    #[test]
+    fn failed_responses_are_retried_up_to_max_try_count() {
+        let mut request = RespondStorageRequest::new(
+            H256::repeat_byte(1),
+            MspRespondStorageRequest::Reject(RejectedStorageRequestReason::InternalError),
+        )
+        .with_bucket_id(H256::repeat_byte(2));
+
+        for try_count in 1..=MAX_RESPOND_STORAGE_REQUEST_TRY_COUNT {
+            request = respond_storage_request_to_retry(&request).expect("Retries left");
+            assert_eq!(request.try_count, try_count);
+            // The retried response is the same, for the same bucket.
+            assert_eq!(request.file_key, H256::repeat_byte(1));
+            assert_eq!(request.bucket_id, Some(H256::repeat_byte(2)));
+        }
+
+        assert!(respond_storage_request_to_retry(&request).is_none());
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .expect("Failed to build runtime")
+            .block_on(future)
+    }
+
+    /// Runs [`requeue_failed_responses`] with a queue that collects the requeued responses.
+    fn requeue(requests: Vec<RespondStorageRequest>) -> Vec<RespondStorageRequest> {
+        requeue_and_drop(requests).0
+    }
+
+    /// Runs [`requeue_failed_responses`], returning the requeued responses and the file keys of
+    /// the dropped ones.
+    fn requeue_and_drop(
+        requests: Vec<RespondStorageRequest>,
+    ) -> (Vec<RespondStorageRequest>, Vec<H256>) {
+        let mut queued = Vec::new();
+        let dropped_file_keys = block_on(requeue_failed_responses(requests, |request| {
+            queued.push(request);
+            std::future::ready(Ok(()))
+        }));
+        (queued, dropped_file_keys)
+    }
+
+    #[test]
+    fn transiently_failed_response_is_requeued_once() {
+        let request =
+            RespondStorageRequest::new(H256::repeat_byte(1), MspRespondStorageRequest::Accept);
+
+        // The first response is not included in time, e.g. because of a congested block.
+        let queued = requeue(vec![request]);
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].file_key, H256::repeat_byte(1));
+        assert_eq!(queued[0].try_count, 1);
+        assert!(matches!(
+            queued[0].response,
+            MspRespondStorageRequest::Accept
+        ));
+    }
+
+    #[test]
+    fn failing_responses_are_requeued_until_max_try_count() {
+        let mut queued = vec![
+            RespondStorageRequest::new(H256::repeat_byte(1), MspRespondStorageRequest::Accept),
+            RespondStorageRequest::new(
+                H256::repeat_byte(2),
+                MspRespondStorageRequest::Reject(RejectedStorageRequestReason::InternalError),
+            ),
+        ];
+
+        // Every time the responses fail, all of them are queued again...
+        for try_count in 1..=MAX_RESPOND_STORAGE_REQUEST_TRY_COUNT {
+            queued = requeue(queued);
+            assert_eq!(queued.len(), 2);
+            assert!(queued.iter().all(|request| request.try_count == try_count));
+        }
+
+        // ...until they failed too many times, and are dropped, ending their uploads.
+        let (queued, dropped_file_keys) = requeue_and_drop(queued);
+        assert!(queued.is_empty());
+        assert_eq!(
+            dropped_file_keys,
+            vec![H256::repeat_byte(1), H256::repeat_byte(2)]
+        );
+    }
+
+    #[test]
+    fn requeue_continues_when_queueing_a_response_fails() {
+        let requests = vec![
+            RespondStorageRequest::new(H256::repeat_byte(1), MspRespondStorageRequest::Accept),
+            RespondStorageRequest::new(H256::repeat_byte(2), MspRespondStorageRequest::Accept),
+        ];
+
+        // Queueing the first response fails, which does not keep the second one from being queued.
+        let mut queued = Vec::new();
+        let dropped_file_keys = block_on(requeue_failed_responses(requests, |request| {
+            let result = if request.file_key == H256::repeat_byte(1) {
+                Err(anyhow!("Blockchain service unavailable"))
+            } else {
+                queued.push(request);
+                Ok(())
+            };
+            std::future::ready(result)
+        }));
+
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].file_key, H256::repeat_byte(2));
+        // The response that could not be queued is dropped, ending its upload.
+        assert_eq!(dropped_file_keys, vec![H256::repeat_byte(1)]);
+    }
 }
@@ -0,0 +1,314 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::sync::{RwLock, RwLockReadGuard};
+
+/// Interval at which a proof generation waiting for a lock checks whether it was cancelled.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Error returned when generating a proof takes longer than the configured timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ProofGenerationTimedOut(pub Duration);
+
+impl fmt::Display for ProofGenerationTimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Proof generation timed out after {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ProofGenerationTimedOut {}
+
+/// Cancellation of a proof generation running in a blocking task, set once it times out.
+///
+/// The blocking task takes the locks of the storage it generates the proof from through
+/// [`ProofGenerationCancellation::blocking_read`], so that it does not take them anymore once
+/// cancelled, and doesn't stall the writers of the storage after its result is discarded.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ProofGenerationCancellation(Arc<AtomicBool>);
+
+impl ProofGenerationCancellation {
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Read-locks `lock` from a blocking task, unless the proof generation is cancelled first.
+    ///
+    /// Unlike [`RwLock::blocking_read`], this doesn't queue for the lock, so a proof generation
+    /// that times out while a writer holds the lock never takes it afterwards.
+    pub fn blocking_read<'a, S>(
+        &self,
+        lock: &'a RwLock<S>,
+    ) -> anyhow::Result<RwLockReadGuard<'a, S>> {
+        loop {
+            if self.is_cancelled() {
+                return Err(anyhow::anyhow!("Proof generation was cancelled"));
+            }
+            if let Ok(guard) = lock.try_read() {
+                return Ok(guard);
+            }
+            std::thread::sleep(LOCK_POLL_INTERVAL);
+        }
+    }
+}
+
+/// Runs `generate` in a blocking task, giving up if it doesn't finish within `timeout`.
+///
+/// Generating a proof traverses a trie in storage, which would block the async runtime and could
+/// take arbitrarily long with a pathological trie or a stalled disk. On timeout, a
+/// [`ProofGenerationTimedOut`] error is returned right away so that the caller can release its
+/// locks and give up on the proof. The proof being generated cannot be interrupted, so the
+/// blocking task finishes it in the background and its result is discarded, but `generate` is
+/// given a [`ProofGenerationCancellation`] through which it must take its locks, so that it
+/// does not take them after the timeout.
+pub(crate) async fn generate_proof_with_timeout<T, F>(
+    timeout: Duration,
+    generate: F,
+) -> anyhow::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&ProofGenerationCancellation) -> anyhow::Result<T> + Send + 'static,
+{
+    let cancellation = ProofGenerationCancellation::default();
+    let task = tokio::task::spawn_blocking({
+        let cancellation = cancellation.clone();
+        move || generate(&cancellation)
+    });
+
+    match tokio::time::timeout(timeout, task).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => Err(anyhow::anyhow!("Proof generation task failed: {:?}", e)),
+        Err(_) => {
+            cancellation.cancel();
+            Err(ProofGenerationTimedOut(timeout).into())
+        }
+    }
+}
+
+/// Generates a proof for as many of `keys` as possible within `timeout`, returning the keys
+/// proven along with their proof.
+///
+/// Unlike [`generate_proof_with_timeout`], a timeout does not discard all the work done: the
+/// proof is generated in a blocking task for growing prefixes of `keys`, doubling in size each
+/// time until all of them are proven, which at most doubles the time it takes to prove them all.
+/// On timeout, the proof for the largest prefix proven so far is returned, and the blocking task
+/// stops after the proof it is generating. A [`ProofGenerationTimedOut`] error is only returned
+/// if not even the first key could be proven in time.
+///
+/// As with [`generate_proof_with_timeout`], `generate` must take its locks through the
+/// [`ProofGenerationCancellation`] it is given.
+pub(crate) async fn generate_partial_proof_with_timeout<K, P, F>(
+    timeout: Duration,
+    keys: Vec<K>,
+    generate: F,
+) -> anyhow::Result<(Vec<K>, P)>
+where
+    K: Clone + Send + 'static,
+    P: Send + 'static,
+    F: Fn(&[K], &ProofGenerationCancellation) -> anyhow::Result<P> + Send + 'static,
+{
+    // The number of keys proven so far and their proof.
+    let latest_proof = Arc::new(Mutex::new(None::<(usize, P)>));
+    let cancellation = ProofGenerationCancellation::default();
+
+    let task = tokio::task::spawn_blocking({
+        let keys = keys.clone();
+        let latest_proof = latest_proof.clone();
+        let cancellation = cancellation.clone();
+        move || -> anyhow::Result<()> {
+            let mut proven = 0;
+            while proven < keys.len() && !cancellation.is_cancelled() {
+                let next = (proven * 2).clamp(1, keys.len());
+                let proof = generate(&keys[..next], &cancellation)?;
+                *latest_proof.lock().expect("Proof lock poisoned") = Some((next, proof));
+                proven = next;
+            }
+            Ok(())
+        }
+    });
+
+    match tokio::time::timeout(timeout, task).await {
+        Ok(Ok(Ok(()))) => {}
+        Ok(Ok(Err(e))) => return Err(e),
+        Ok(Err(e)) => return Err(anyhow::anyhow!("Proof generation task failed: {:?}", e)),
+        Err(_) => cancellation.cancel(),
+    }
+
+    let latest_proof = latest_proof.lock().expect("Proof lock poisoned").take();
+    match latest_proof {
+        Some((proven, proof)) => Ok((keys[..proven].to_vec(), proof)),
+        None if cancellation.is_cancelled() => Err(ProofGenerationTimedOut(timeout).into()),
+        None => Err(anyhow::anyhow!("No keys to generate a proof for")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use shc_common::types::{
+        ChunkId, FileMetadata, Fingerprint, HashT, StorageProofsMerkleTrieLayout,
+    };
+    use shc_file_manager::{in_memory::InMemoryFileStorage, traits::FileStorage};
+    use std::collections::HashSet;
+
+    /// File Storage that takes `delay` to generate a proof, as with a stalled disk.
+    struct SlowFileStorage {
+        inner: InMemoryFileStorage<StorageProofsMerkleTrieLayout>,
+        delay: Duration,
+    }
+
+    impl SlowFileStorage {
+        fn generate_proof(
+            &self,
+            file_key: &sp_core::H256,
+            chunk_ids: &HashSet<ChunkId>,
+        ) -> anyhow::Result<()> {
+            std::thread::sleep(self.delay);
+            self.inner
+                .generate_proof(file_key, chunk_ids)
+                .map(|_| ())
+                .map_err(|e| anyhow!("Failed to generate proof: {:?}", e))
+        }
+    }
+
+    fn file_storage_with_file(delay: Duration) -> (Arc<RwLock<SlowFileStorage>>, sp_core::H256) {
+        let mut inner = InMemoryFileStorage::<StorageProofsMerkleTrieLayout>::new();
+        let metadata = FileMetadata::new(
+            [1u8; 32].to_vec(),
+            [2u8; 32].to_vec(),
+            b"location".to_vec(),
+            1024,
+            Fingerprint::from([3u8; 32]),
+        )
+        .unwrap();
+        let file_key = metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+        inner.insert_file(file_key, metadata).unwrap();
+
+        (
+            Arc::new(RwLock::new(SlowFileStorage { inner, delay })),
+            file_key,
+        )
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("Failed to build runtime")
+            .block_on(future)
+    }
+
+    #[test]
+    fn slow_proof_generation_times_out() {
+        let (file_storage, file_key) = file_storage_with_file(Duration::from_secs(1));
+
+        let result = block_on(generate_proof_with_timeout(
+            Duration::from_millis(50),
+            move |cancellation| {
+                cancellation
+                    .blocking_read(&file_storage)?
+                    .generate_proof(&file_key, &HashSet::from([ChunkId::new(0)]))
+            },
+        ));
+
+        let error = result.expect_err("Proof generation should have timed out");
+        assert_eq!(
+            error.downcast_ref::<ProofGenerationTimedOut>(),
+            Some(&ProofGenerationTimedOut(Duration::from_millis(50)))
+        );
+    }
+
+    #[test]
+    fn timed_out_proof_generation_does_not_take_the_lock_afterwards() {
+        let (file_storage, file_key) = file_storage_with_file(Duration::ZERO);
+        let generated = Arc::new(AtomicBool::new(false));
+
+        // A writer holds the File Storage lock past the timeout.
+        let write_guard = file_storage.clone().try_write_owned().unwrap();
+        let result = block_on(generate_proof_with_timeout(Duration::from_millis(50), {
+            let file_storage = file_storage.clone();
+            let generated = generated.clone();
+            move |cancellation| {
+                let read_guard = cancellation.blocking_read(&file_storage)?;
+                generated.store(true, Ordering::SeqCst);
+                read_guard.generate_proof(&file_key, &HashSet::from([ChunkId::new(0)]))
+            }
+        }));
+        assert!(result.is_err());
+        drop(write_guard);
+
+        // Give the blocking task time to see the lock released.
+        std::thread::sleep(LOCK_POLL_INTERVAL * 5);
+        assert!(file_storage.try_write().is_ok());
+        assert!(!generated.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn proof_generation_within_the_timeout_returns_its_result() {
+        let result = block_on(generate_proof_with_timeout(Duration::from_secs(5), |_| {
+            Ok(42)
+        }));
+
+        assert_eq!(result.unwrap(), 42);
+    }
+
+    #[test]
+    fn partial_proof_within_the_timeout_proves_all_keys() {
+        let (keys, proof) = block_on(generate_partial_proof_with_timeout(
+            Duration::from_secs(5),
+            vec![1, 2, 3, 4, 5],
+            |keys: &[u32], _| Ok(keys.iter().sum::<u32>()),
+        ))
+        .unwrap();
+
+        assert_eq!(keys, vec![1, 2, 3, 4, 5]);
+        assert_eq!(proof, 15);
+    }
+
+    #[test]
+    fn partial_proof_returns_the_keys_proven_before_the_timeout() {
+        // Proving more than 2 keys stalls, so only the first 2 keys are proven in time.
+        let (keys, proof) = block_on(generate_partial_proof_with_timeout(
+            Duration::from_millis(200),
+            vec![1, 2, 3, 4, 5],
+            |keys: &[u32], _| {
+                if keys.len() > 2 {
+                    std::thread::sleep(Duration::from_secs(1));
+                }
+                Ok(keys.iter().sum::<u32>())
+            },
+        ))
+        .unwrap();
+
+        assert_eq!(keys, vec![1, 2]);
+        assert_eq!(proof, 3);
+    }
+
+    #[test]
+    fn partial_proof_times_out_if_no_key_is_proven() {
+        let result = block_on(generate_partial_proof_with_timeout(
+            Duration::from_millis(50),
+            vec![1, 2],
+            |keys: &[u32], _| {
+                std::thread::sleep(Duration::from_secs(1));
+                Ok(keys.len())
+            },
+        ));
+
+        let error = result.expect_err("Proof generation should have timed out");
+        assert_eq!(
+            error.downcast_ref::<ProofGenerationTimedOut>(),
+            Some(&ProofGenerationTimedOut(Duration::from_millis(50)))
+        );
+    }
+}
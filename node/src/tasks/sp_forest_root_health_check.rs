@@ -0,0 +1,276 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use sc_tracing::tracing::*;
+use sp_core::H256;
+
+use shc_blockchain_service::commands::BlockchainServiceInterface;
+use shc_common::{consts::CURRENT_FOREST_KEY, types::StorageProviderId};
+use shc_forest_manager::traits::{ForestStorage, ForestStorageHandler};
+
+use crate::services::{
+    forest_root_health::ForestRootStatus,
+    handler::StorageHubHandler,
+    types::{BspForestStorageHandlerT, MspForestStorageHandlerT, ShNodeType},
+};
+
+const LOG_TARGET: &str = "sp-forest-root-health-check-task";
+
+/// Forest root health check task.
+///
+/// Periodically compares the roots of the local Forests of this provider with the ones on-chain:
+/// the BSP's root for a BSP, or the root of each bucket stored for an MSP. A Forest that keeps
+/// diverging from its on-chain root means this provider can no longer generate valid proofs for
+/// it, so the node operator is alerted and, if configured, proof submission is paused until the
+/// Forest is back in sync.
+pub struct SpForestRootHealthCheckTask<NT>
+where
+    NT: ShNodeType,
+{
+    storage_hub_handler: StorageHubHandler<NT>,
+}
+
+impl<NT> Clone for SpForestRootHealthCheckTask<NT>
+where
+    NT: ShNodeType,
+{
+    fn clone(&self) -> SpForestRootHealthCheckTask<NT> {
+        Self {
+            storage_hub_handler: self.storage_hub_handler.clone(),
+        }
+    }
+}
+
+impl<NT> SpForestRootHealthCheckTask<NT>
+where
+    NT: ShNodeType,
+{
+    pub fn new(storage_hub_handler: StorageHubHandler<NT>) -> Self {
+        Self {
+            storage_hub_handler,
+        }
+    }
+
+    /// The time to wait between two checks, or `None` if the check is disabled.
+    pub fn check_interval(&self) -> Option<Duration> {
+        match self
+            .storage_hub_handler
+            .provider_config
+            .forest_root_check_interval
+        {
+            0 => None,
+            seconds => Some(Duration::from_secs(seconds)),
+        }
+    }
+
+    /// Records the check of the Forest with `forest_key` and alerts about its outcome.
+    fn record_check(
+        &self,
+        forest_key: &[u8],
+        forest_name: &str,
+        local_root: H256,
+        on_chain_root: H256,
+    ) {
+        let status = self.storage_hub_handler.forest_root_health.record_check(
+            forest_key,
+            local_root,
+            on_chain_root,
+        );
+
+        match status {
+            ForestRootStatus::InSync => {
+                trace!(target: LOG_TARGET, "Local root of {} is in sync with its on-chain root [{:?}]", forest_name, on_chain_root);
+            }
+            ForestRootStatus::Recovered => {
+                info!(target: LOG_TARGET, "Local root of {} is back in sync with its on-chain root [{:?}]", forest_name, on_chain_root);
+            }
+            ForestRootStatus::Mismatch => {
+                // Most likely a block mutating the Forest is being imported, so wait for the
+                // next check before alerting.
+                debug!(target: LOG_TARGET, "Local root [{:?}] of {} differs from its on-chain root [{:?}]. Checking again later.", local_root, forest_name, on_chain_root);
+            }
+            ForestRootStatus::Diverged { consecutive_checks } => {
+                error!(target: LOG_TARGET, "CRITICAL❗️❗️ Local root [{:?}] of {} diverged from its on-chain root [{:?}] for {} consecutive checks. Proofs generated for it will be rejected.", local_root, forest_name, on_chain_root, consecutive_checks);
+                if self
+                    .storage_hub_handler
+                    .provider_config
+                    .pause_proofs_on_forest_root_divergence
+                {
+                    error!(target: LOG_TARGET, "CRITICAL❗️❗️ Proof submission is paused until all local Forest roots are back in sync.");
+                }
+            }
+        }
+    }
+}
+
+impl<NT> SpForestRootHealthCheckTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: BspForestStorageHandlerT,
+{
+    /// Runs [`Self::check_bsp_forest_root`] periodically, until the node shuts down.
+    pub async fn run_bsp(self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if self
+                .storage_hub_handler
+                .task_spawner
+                .graceful_shutdown()
+                .is_shutting_down()
+            {
+                return;
+            }
+
+            if let Err(e) = self.check_bsp_forest_root().await {
+                warn!(target: LOG_TARGET, "Failed to check the BSP Forest root: {:?}", e);
+            }
+        }
+    }
+
+    /// Compares the root of the BSP's local Forest with its on-chain root.
+    pub async fn check_bsp_forest_root(&self) -> anyhow::Result<()> {
+        let bsp_id = match self
+            .storage_hub_handler
+            .blockchain
+            .query_storage_provider_id(None)
+            .await?
+        {
+            Some(StorageProviderId::BackupStorageProvider(id)) => id,
+            Some(StorageProviderId::MainStorageProvider(_)) => {
+                return Err(anyhow!(
+                    "Current node account is a Main Storage Provider. Expected a Backup Storage Provider ID."
+                ));
+            }
+            // Not registered yet, so there is no on-chain root to compare with.
+            None => return Ok(()),
+        };
+
+        let on_chain_root = self
+            .storage_hub_handler
+            .blockchain
+            .query_provider_forest_root(bsp_id)
+            .await
+            .map_err(|e| anyhow!("Failed to query the on-chain root of the BSP: {:?}", e))?;
+
+        let current_forest_key = CURRENT_FOREST_KEY.to_vec();
+        let fs = self
+            .storage_hub_handler
+            .forest_storage_handler
+            .get(&current_forest_key)
+            .await
+            .ok_or_else(|| anyhow!("Failed to get the BSP Forest Storage."))?;
+        let local_root = fs.read().await.root();
+
+        self.record_check(
+            &current_forest_key,
+            "the BSP Forest",
+            local_root,
+            on_chain_root,
+        );
+
+        Ok(())
+    }
+}
+
+impl<NT> SpForestRootHealthCheckTask<NT>
+where
+    NT: ShNodeType + 'static,
+    NT::FSH: MspForestStorageHandlerT,
+{
+    /// Runs [`Self::check_msp_bucket_roots`] periodically, until the node shuts down.
+    pub async fn run_msp(self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if self
+                .storage_hub_handler
+                .task_spawner
+                .graceful_shutdown()
+                .is_shutting_down()
+            {
+                return;
+            }
+
+            if let Err(e) = self.check_msp_bucket_roots().await {
+                warn!(target: LOG_TARGET, "Failed to check the MSP bucket roots: {:?}", e);
+            }
+        }
+    }
+
+    /// Compares the root of the local Forest of each bucket stored by the MSP with its on-chain
+    /// root.
+    ///
+    /// Buckets without a local Forest are skipped, since it is only created once the first file
+    /// of the bucket is accepted.
+    pub async fn check_msp_bucket_roots(&self) -> anyhow::Result<()> {
+        let msp_id = match self
+            .storage_hub_handler
+            .blockchain
+            .query_storage_provider_id(None)
+            .await?
+        {
+            Some(StorageProviderId::MainStorageProvider(id)) => id,
+            Some(StorageProviderId::BackupStorageProvider(_)) => {
+                return Err(anyhow!(
+                    "Current node account is a Backup Storage Provider. Expected a Main Storage Provider ID."
+                ));
+            }
+            // Not registered yet, so there are no buckets to check.
+            None => return Ok(()),
+        };
+
+        let buckets = self
+            .storage_hub_handler
+            .blockchain
+            .query_buckets_for_msp(msp_id)
+            .await
+            .map_err(|e| anyhow!("Failed to query the buckets of the MSP: {:?}", e))?;
+
+        // Buckets no longer stored by this MSP can't hold back proof submission.
+        self.storage_hub_handler
+            .forest_root_health
+            .retain(|forest_key| {
+                buckets
+                    .iter()
+                    .any(|bucket_id| bucket_id.as_ref() == forest_key)
+            });
+
+        for bucket_id in buckets {
+            let forest_key = bucket_id.as_ref().to_vec();
+            let Some(fs) = self
+                .storage_hub_handler
+                .forest_storage_handler
+                .get(&forest_key)
+                .await
+            else {
+                trace!(target: LOG_TARGET, "No local Forest for bucket [{:?}]. Skipping.", bucket_id);
+                continue;
+            };
+            let local_root = fs.read().await.root();
+
+            let on_chain_root = match self
+                .storage_hub_handler
+                .blockchain
+                .query_bucket_root(bucket_id)
+                .await
+            {
+                Ok(root) => root,
+                Err(e) => {
+                    // The bucket could have been deleted or moved since the buckets were queried.
+                    warn!(target: LOG_TARGET, "Failed to query the on-chain root of bucket [{:?}]: {:?}", bucket_id, e);
+                    continue;
+                }
+            };
+
+            self.record_check(
+                &forest_key,
+                &format!("bucket [{:?}]", bucket_id),
+                local_root,
+                on_chain_root,
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+use std::{fmt, ops::RangeInclusive};
+
+use shc_common::types::RejectedStorageRequestReason;
+use shc_file_manager::traits::{FileStorageError, FileStorageErrorKind};
+
+/// A failure while handling a storage request or the chunks uploaded for it.
+#[derive(Debug)]
+pub(crate) enum UploadFailure {
+    /// Writing an uploaded chunk to the file storage failed.
+    Write(FileStorageError),
+    /// The proof of the uploaded chunks failed to verify, or a proven chunk does not have the
+    /// size expected for its position in the file.
+    InvalidProof,
+    /// The proof of the uploaded chunks is valid, but it proves fewer or more chunks than a batch
+    /// can hold.
+    UnexpectedChunkCount {
+        expected: RangeInclusive<usize>,
+        got: usize,
+    },
+    /// Storing the file would exceed the data limit of its bucket.
+    BucketDataLimitReached,
+    /// There is not enough storage capacity left to store the file, even after increasing it.
+    CapacityReached,
+    /// This provider is already receiving as many files as it is configured to handle
+    /// simultaneously.
+    MaxConcurrentUploadsReached {
+        active_uploads: usize,
+        max_active_uploads: usize,
+    },
+    /// Checking whether the file is complete failed, so its local state cannot be trusted.
+    CompletenessCheckFailed,
+}
+
+/// How an [`UploadFailure`] should be dealt with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum UploadFailureAction {
+    /// Not a failure at all (i.e. a duplicated chunk, or a chunk for a sealed file), so
+    /// processing continues.
+    Ignore,
+    /// A transient local failure. The upload is kept open so that the user can resend the
+    /// chunks, which is what happens when a batch is not acknowledged.
+    RetryLocally,
+    /// A failure attributable to the user or to this provider's policy. The storage request is
+    /// rejected with a reason telling the user what went wrong.
+    Reject(RejectedStorageRequestReason),
+    /// The local state of the file is broken, which should never happen. The storage request is
+    /// rejected as an internal error and the node operator is alerted.
+    AbortAndAlert,
+}
+
+impl UploadFailure {
+    /// Classifies this failure into the [`UploadFailureAction`] to take.
+    pub(crate) fn action(&self) -> UploadFailureAction {
+        match self {
+            // The uploaded chunks are invalid, e.g. beyond the last chunk of the file.
+            UploadFailure::Write(error) if error.should_reject_request() => {
+                UploadFailureAction::Reject(RejectedStorageRequestReason::ReceivedInvalidProof)
+            }
+            UploadFailure::Write(error) => match error.kind() {
+                // Nothing left to write for the file, as it is already complete.
+                FileStorageErrorKind::AlreadyExists => UploadFailureAction::Ignore,
+                FileStorageErrorKind::Backend => UploadFailureAction::RetryLocally,
+                _ => UploadFailureAction::AbortAndAlert,
+            },
+            UploadFailure::InvalidProof | UploadFailure::UnexpectedChunkCount { .. } => {
+                UploadFailureAction::Reject(RejectedStorageRequestReason::ReceivedInvalidProof)
+            }
+            UploadFailure::BucketDataLimitReached => {
+                UploadFailureAction::Reject(RejectedStorageRequestReason::ReachedBucketDataLimit)
+            }
+            UploadFailure::CapacityReached => {
+                UploadFailureAction::Reject(RejectedStorageRequestReason::ReachedMaximumCapacity)
+            }
+            UploadFailure::MaxConcurrentUploadsReached { .. } => UploadFailureAction::Reject(
+                RejectedStorageRequestReason::MaxConcurrentUploadsReached,
+            ),
+            UploadFailure::CompletenessCheckFailed => UploadFailureAction::AbortAndAlert,
+        }
+    }
+}
+
+impl fmt::Display for UploadFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadFailure::Write(FileStorageError::FileDoesNotExist) => write!(
+                f,
+                "File does not exist. Maybe we forgot to unregister before deleting?"
+            ),
+            UploadFailure::Write(FileStorageError::FingerprintAndStoredFileMismatch) => {
+                write!(
+                    f,
+                    "Invariant broken! This is a bug! Fingerprint and stored file mismatch"
+                )
+            }
+            UploadFailure::Write(
+                FileStorageError::FailedToConstructTrieIter
+                | FileStorageError::FailedToContructFileTrie,
+            ) => write!(f, "This is a bug! Failed to construct file trie"),
+            UploadFailure::Write(error) => write!(f, "File storage write error: {:?}", error),
+            UploadFailure::InvalidProof => write!(f, "Invalid proof of uploaded chunks"),
+            UploadFailure::UnexpectedChunkCount { expected, got } => write!(
+                f,
+                "Expected between {} and {} proven chunks but got {}",
+                expected.start(),
+                expected.end(),
+                got
+            ),
+            UploadFailure::BucketDataLimitReached => {
+                write!(
+                    f,
+                    "Storing the file would exceed the data limit of the bucket"
+                )
+            }
+            UploadFailure::CapacityReached => {
+                write!(f, "Not enough storage capacity to store the file")
+            }
+            UploadFailure::MaxConcurrentUploadsReached {
+                active_uploads,
+                max_active_uploads,
+            } => write!(
+                f,
+                "{} active uploads, maximum is {}",
+                active_uploads, max_active_uploads
+            ),
+            UploadFailure::CompletenessCheckFailed => {
+                write!(f, "Failed to check if the file is complete")
+            }
+        }
+    }
+}
+
+impl UploadFailureAction {
+    /// The reason to reject the storage request with, if it should be rejected at all.
+    pub(crate) fn rejection_reason(&self) -> Option<RejectedStorageRequestReason> {
+        match self {
+            UploadFailureAction::Ignore | UploadFailureAction::RetryLocally => None,
+            UploadFailureAction::Reject(reason) => Some(reason.clone()),
+            UploadFailureAction::AbortAndAlert => Some(RejectedStorageRequestReason::InternalError),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    use shc_common::types::{
+        ChunkId, FileMetadata, HashT, ProvenFileKeyError, StorageProofsMerkleTrieLayout,
+        FILE_CHUNK_SIZE,
+    };
+    use shc_file_manager::{
+        in_memory::InMemoryFileStorage,
+        traits::{FileDataTrie, FileStorage},
+    };
+
+    #[test]
+    fn duplicated_chunks_are_ignored() {
+        for error in [
+            FileStorageError::FileChunkAlreadyExists,
+            FileStorageError::FileSealed,
+        ] {
+            let failure = UploadFailure::Write(error);
+            assert_eq!(failure.action(), UploadFailureAction::Ignore);
+            assert_eq!(failure.action().rejection_reason(), None);
+        }
+    }
+
+    #[test]
+    fn transient_storage_errors_are_retried_locally() {
+        for error in [
+            FileStorageError::FailedToGetFileChunk,
+            FileStorageError::FailedToInsertFileChunk,
+            FileStorageError::FailedToDeleteFileChunk,
+            FileStorageError::FailedToDeleteRoot,
+            FileStorageError::FailedToWriteToStorage,
+            FileStorageError::FailedToReadStorage,
+            FileStorageError::FailedToUpdatePartialRoot,
+            FileStorageError::FailedToGetStoredChunksCount,
+        ] {
+            let failure = UploadFailure::Write(error);
+            assert_eq!(failure.action(), UploadFailureAction::RetryLocally);
+            assert_eq!(failure.action().rejection_reason(), None);
+        }
+    }
+
+    #[test]
+    fn user_attributable_failures_are_rejected_with_their_reason() {
+        let cases = [
+            (
+                UploadFailure::Write(FileStorageError::ChunkIdOutOfRange),
+                RejectedStorageRequestReason::ReceivedInvalidProof,
+            ),
+            (
+                UploadFailure::InvalidProof,
+                RejectedStorageRequestReason::ReceivedInvalidProof,
+            ),
+            (
+                UploadFailure::UnexpectedChunkCount {
+                    expected: 1..=1,
+                    got: 2,
+                },
+                RejectedStorageRequestReason::ReceivedInvalidProof,
+            ),
+            (
+                UploadFailure::BucketDataLimitReached,
+                RejectedStorageRequestReason::ReachedBucketDataLimit,
+            ),
+            (
+                UploadFailure::CapacityReached,
+                RejectedStorageRequestReason::ReachedMaximumCapacity,
+            ),
+            (
+                UploadFailure::MaxConcurrentUploadsReached {
+                    active_uploads: 100,
+                    max_active_uploads: 100,
+                },
+                RejectedStorageRequestReason::MaxConcurrentUploadsReached,
+            ),
+        ];
+
+        for (failure, reason) in cases {
+            assert_eq!(
+                failure.action(),
+                UploadFailureAction::Reject(reason.clone())
+            );
+            assert_eq!(failure.action().rejection_reason(), Some(reason));
+        }
+    }
+
+    #[test]
+    fn proofs_of_an_unexpected_number_of_chunks_are_rejected_as_invalid_proofs() {
+        let chunks = vec![vec![1u8; FILE_CHUNK_SIZE as usize], vec![2u8; 1]];
+        let mut file_storage = InMemoryFileStorage::<StorageProofsMerkleTrieLayout>::new();
+        let mut file_trie = file_storage.new_file_data_trie();
+        for (chunk_id, chunk) in chunks.iter().enumerate() {
+            file_trie
+                .write_chunk(&ChunkId::new(chunk_id as u64), chunk)
+                .unwrap();
+        }
+        let metadata = FileMetadata::new(
+            vec![0u8; 32],
+            vec![1u8; 32],
+            b"location".to_vec(),
+            FILE_CHUNK_SIZE + 1,
+            file_trie.get_root().as_ref().into(),
+        )
+        .unwrap();
+        let file_key = metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>();
+        file_storage
+            .insert_file_with_data(file_key, metadata, file_trie)
+            .unwrap();
+
+        let file_key_proof = file_storage
+            .generate_proof(
+                &file_key,
+                &HashSet::from([ChunkId::new(0), ChunkId::new(1)]),
+            )
+            .unwrap();
+        let failure = match file_key_proof.proven_expecting::<StorageProofsMerkleTrieLayout>(1..=1)
+        {
+            Err(ProvenFileKeyError::UnexpectedChunkCount { expected, got }) => {
+                assert_eq!((expected.clone(), got), (1..=1, 2));
+                UploadFailure::UnexpectedChunkCount { expected, got }
+            }
+            other => panic!("Expected an unexpected chunk count, got {:?}", other),
+        };
+
+        assert_eq!(
+            failure.action().rejection_reason(),
+            Some(RejectedStorageRequestReason::ReceivedInvalidProof)
+        );
+    }
+
+    #[test]
+    fn broken_local_state_aborts_as_internal_error() {
+        let failures = [
+            UploadFailure::Write(FileStorageError::FileDoesNotExist),
+            UploadFailure::Write(FileStorageError::FailedToParseFileMetadata),
+            UploadFailure::Write(FileStorageError::FailedToParseFingerprint),
+            UploadFailure::Write(FileStorageError::FailedToParsePartialRoot),
+            UploadFailure::Write(FileStorageError::ChunkCountOverflow),
+            UploadFailure::Write(FileStorageError::FingerprintAndStoredFileMismatch),
+            UploadFailure::Write(FileStorageError::FailedToConstructTrieIter),
+            UploadFailure::Write(FileStorageError::FailedToContructFileTrie),
+            UploadFailure::CompletenessCheckFailed,
+        ];
+
+        for failure in failures {
+            assert_eq!(failure.action(), UploadFailureAction::AbortAndAlert);
+            assert_eq!(
+                failure.action().rejection_reason(),
+                Some(RejectedStorageRequestReason::InternalError)
+            );
+        }
+    }
+}
@@ -1,22 +1,37 @@
-use log::{debug, info, warn};
-use sc_network::{PeerId, RequestFailure};
+use async_trait::async_trait;
+use frame_support::BoundedVec;
+use log::{debug, error, info, warn};
+use pallet_file_system::types::ReplicationTarget;
+use sc_network::PeerId;
 use sp_core::H256;
 use sp_runtime::AccountId32;
-use std::collections::HashSet;
+use std::{collections::HashSet, sync::Arc, time::Duration};
+use tokio::sync::{mpsc::UnboundedReceiver, RwLock};
 
-use shc_actors_framework::event_bus::EventHandler;
+use shc_actors_framework::{actor::ActorHandle, event_bus::EventHandler};
 use shc_blockchain_service::{
     commands::BlockchainServiceInterface,
-    events::{AcceptedBspVolunteer, NewStorageRequest},
+    events::{AcceptedBspVolunteer, NewStorageRequest, StorageRequestFulfilled},
+    types::RetryStrategy,
 };
-use shc_common::types::{
-    FileMetadata, HashT, StorageProofsMerkleTrieLayout, BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE,
+use shc_common::{
+    types::{FileKeyProof, FileMetadata, HashT, StorageProofsMerkleTrieLayout},
+    upload_progress::UploadState,
+    user_uploads::UserUploadRequest,
 };
 use shc_file_manager::traits::FileStorage;
-use shc_file_transfer_service::commands::{FileTransferServiceInterface, RequestError};
+use shc_file_transfer_service::{
+    commands::{FileTransferServiceInterface, RequestError},
+    upload::{batch_chunk_ids, ChunkUploadTransport, ChunkUploader},
+    FileTransferService,
+};
+use shc_rpc::storage_request::{BucketInfo, StorageRequestIssuer};
 use shp_file_metadata::ChunkId;
 
-use crate::services::{handler::StorageHubHandler, types::ShNodeType};
+use crate::services::{
+    handler::StorageHubHandler,
+    types::{FileStorageT, ShNodeType},
+};
 
 const LOG_TARGET: &str = "user-sends-file-task";
 
@@ -24,11 +39,17 @@ const LOG_TARGET: &str = "user-sends-file-task";
 /// volunteering for that file.
 /// It can serve multiple BSPs volunteering to store each file, since
 /// it reacts to every [`AcceptedBspVolunteer`] from the runtime.
+///
+/// It also issues the storage requests of the files queued through the `uploadFile` RPC (see
+/// [`UserSendsFileTask::issue_storage_request`]), and marks their upload as confirmed once the
+/// [`StorageRequestFulfilled`] event is received.
 pub struct UserSendsFileTask<NT>
 where
     NT: ShNodeType,
 {
     storage_hub_handler: StorageHubHandler<NT>,
+    /// Sends the chunks of the files to the providers.
+    chunk_uploader: ChunkUploader,
 }
 
 impl<NT> Clone for UserSendsFileTask<NT>
@@ -38,6 +59,7 @@ where
     fn clone(&self) -> Self {
         Self {
             storage_hub_handler: self.storage_hub_handler.clone(),
+            chunk_uploader: self.chunk_uploader.clone(),
         }
     }
 }
@@ -49,6 +71,223 @@ where
     pub fn new(storage_hub_handler: StorageHubHandler<NT>) -> Self {
         Self {
             storage_hub_handler,
+            chunk_uploader: ChunkUploader::default(),
+        }
+    }
+
+    /// Issues the storage requests of the files queued through the `uploadFile` RPC as they are
+    /// received from `upload_requests`, until the queue is closed.
+    ///
+    /// Each storage request is issued in its own task, so that a request waiting to be included
+    /// in a block does not hold back the ones queued after it.
+    pub async fn process_upload_queue(
+        self,
+        mut upload_requests: UnboundedReceiver<UserUploadRequest>,
+    ) where
+        NT: 'static,
+    {
+        while let Some(request) = upload_requests.recv().await {
+            let task = self.clone();
+            self.storage_hub_handler
+                .task_spawner
+                .with_name("user-upload-file")
+                .spawn(async move {
+                    let file_key = request.file_key;
+                    if let Err(e) = task.issue_storage_request(request).await {
+                        error!(
+                            target: LOG_TARGET,
+                            "Failed to upload file key {:?}: {:?}", file_key, e
+                        );
+                    }
+                });
+        }
+    }
+
+    /// Issues the storage request of a file loaded in the file storage through the `uploadFile`
+    /// RPC, to the MSP of its bucket.
+    ///
+    /// The chunks are sent once the storage request is included in a block, when handling the
+    /// [`NewStorageRequest`] and [`AcceptedBspVolunteer`] events.
+    pub async fn issue_storage_request(&self, request: UserUploadRequest) -> anyhow::Result<()> {
+        let UserUploadRequest {
+            file_key,
+            file_metadata,
+            replication_target,
+        } = request;
+
+        info!(
+            target: LOG_TARGET,
+            "Issuing storage request for file key {:?} with location [{:?}]",
+            file_key,
+            file_metadata.location(),
+        );
+
+        let bucket_id = H256::from_slice(file_metadata.bucket_id().as_ref());
+        let msp_id = self
+            .storage_hub_handler
+            .blockchain
+            .query_msp_id_of_bucket_id(bucket_id)
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "Failed to query MSP ID of bucket ID {:?}\n Error: {:?}",
+                    bucket_id,
+                    e
+                )
+            })?
+            .ok_or_else(|| anyhow::anyhow!("No MSP ID found for bucket ID {:?}", bucket_id))?;
+
+        let call = self
+            .storage_request_call(file_key, &file_metadata, msp_id, replication_target)
+            .await?;
+
+        // Track the upload before the storage request is included, so that the chunks sent as
+        // soon as it is are accounted for.
+        self.storage_hub_handler
+            .upload_progress
+            .register(file_key, file_metadata.chunks_count());
+
+        if let Err(e) = self
+            .storage_hub_handler
+            .blockchain
+            .submit_extrinsic_with_retry(
+                call,
+                RetryStrategy::default().with_timeout(Duration::from_secs(
+                    self.storage_hub_handler
+                        .provider_config
+                        .extrinsic_retry_timeout,
+                )),
+                false,
+            )
+            .await
+        {
+            self.storage_hub_handler
+                .upload_progress
+                .set_state(&file_key, UploadState::Rejected);
+            return Err(anyhow::anyhow!(
+                "Failed to issue storage request for file key {:?}: {:?}",
+                file_key,
+                e
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Builds the `issue_storage_request` call of the file with `file_key`, to be stored by
+    /// `msp_id`, with this node as the only peer allowed to send its chunks.
+    async fn storage_request_call(
+        &self,
+        file_key: H256,
+        file_metadata: &FileMetadata,
+        msp_id: H256,
+        replication_target: Option<u32>,
+    ) -> anyhow::Result<storage_hub_runtime::RuntimeCall> {
+        let bucket_id = H256::from_slice(file_metadata.bucket_id().as_ref());
+
+        // Providers only accept chunks of the file from the peer IDs in the storage request.
+        let local_peer_id = self
+            .storage_hub_handler
+            .file_transfer
+            .get_local_peer_id()
+            .await;
+        let peer_id = BoundedVec::try_from(local_peer_id.to_string().into_bytes())
+            .map_err(|_| anyhow::anyhow!("Local peer ID {:?} is too long", local_peer_id))?;
+        let peer_ids = BoundedVec::try_from(vec![peer_id])
+            .map_err(|_| anyhow::anyhow!("Failed to convert peer IDs to BoundedVec"))?;
+
+        let location = BoundedVec::try_from(file_metadata.location().clone())
+            .map_err(|_| anyhow::anyhow!("Location of file key {:?} is too long", file_key))?;
+
+        let replication_target = match replication_target {
+            Some(target) => ReplicationTarget::Custom(target),
+            None => ReplicationTarget::Standard,
+        };
+
+        Ok(storage_hub_runtime::RuntimeCall::FileSystem(
+            pallet_file_system::Call::issue_storage_request {
+                bucket_id,
+                location,
+                fingerprint: H256(file_metadata.fingerprint().as_hash()),
+                size: file_metadata.file_size(),
+                msp_id,
+                peer_ids,
+                replication_target,
+            },
+        ))
+    }
+}
+
+#[async_trait]
+impl<NT> StorageRequestIssuer for UserSendsFileTask<NT>
+where
+    NT: ShNodeType + 'static,
+{
+    async fn bucket_info(
+        &self,
+        bucket_id: H256,
+        account: AccountId32,
+    ) -> anyhow::Result<BucketInfo> {
+        let read_access = self
+            .storage_hub_handler
+            .blockchain
+            .query_bucket_read_access(bucket_id, account)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to query bucket {:?}: {:?}", bucket_id, e))?;
+        let (size, data_limit) = self
+            .storage_hub_handler
+            .blockchain
+            .query_bucket_size_and_data_limit(bucket_id)
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "Failed to query size and data limit of bucket {:?}: {:?}",
+                    bucket_id,
+                    e
+                )
+            })?;
+
+        Ok(BucketInfo {
+            owner: read_access.owner,
+            size,
+            data_limit,
+        })
+    }
+
+    /// Unlike [`UserSendsFileTask::issue_storage_request`], it returns as soon as the extrinsic
+    /// is submitted, without waiting for its inclusion nor retrying it.
+    async fn submit_storage_request(
+        &self,
+        file_key: H256,
+        file_metadata: FileMetadata,
+        msp_id: H256,
+        replication_target: Option<u32>,
+    ) -> anyhow::Result<H256> {
+        let call = self
+            .storage_request_call(file_key, &file_metadata, msp_id, replication_target)
+            .await?;
+
+        self.storage_hub_handler
+            .upload_progress
+            .register(file_key, file_metadata.chunks_count());
+
+        match self
+            .storage_hub_handler
+            .blockchain
+            .send_extrinsic(call, Default::default())
+            .await
+        {
+            Ok(submitted_transaction) => Ok(submitted_transaction.hash()),
+            Err(e) => {
+                self.storage_hub_handler
+                    .upload_progress
+                    .set_state(&file_key, UploadState::Rejected);
+                Err(anyhow::anyhow!(
+                    "Failed to submit storage request for file key {:?}: {:?}",
+                    file_key,
+                    e
+                ))
+            }
         }
     }
 }
@@ -120,14 +359,14 @@ where
             .extract_peer_ids_and_register_known_addresses(multiaddress_vec)
             .await;
 
-        let file_metadata = FileMetadata::new(
+        let file_metadata = FileMetadata::validated(
             <AccountId32 as AsRef<[u8]>>::as_ref(&event.who).to_vec(),
             event.bucket_id.as_ref().to_vec(),
             event.location.into_inner(),
             event.size.into(),
             event.fingerprint,
         )
-        .map_err(|_| anyhow::anyhow!("Invalid file metadata"))?;
+        .map_err(|e| anyhow::anyhow!("Invalid file metadata: {:?}", e))?;
 
         let file_key = file_metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>();
 
@@ -159,14 +398,14 @@ where
             event.location,
         );
 
-        let file_metadata = FileMetadata::new(
+        let file_metadata = FileMetadata::validated(
             <AccountId32 as AsRef<[u8]>>::as_ref(&event.owner).to_vec(),
             event.bucket_id.as_ref().to_vec(),
             event.location.into_inner(),
             event.size.into(),
             event.fingerprint,
         )
-        .map_err(|_| anyhow::anyhow!("Invalid file metadata"))?;
+        .map_err(|e| anyhow::anyhow!("Invalid file metadata: {:?}", e))?;
 
         // Adds the multiaddresses of the BSP volunteering to store the file to the known addresses of the file transfer service.
         // This is required to establish a connection to the BSP.
@@ -190,254 +429,116 @@ where
     }
 }
 
+impl<NT> EventHandler<StorageRequestFulfilled> for UserSendsFileTask<NT>
+where
+    NT: ShNodeType + 'static,
+{
+    /// Reacts to a storage request being fulfilled, i.e. the MSP and enough BSPs confirmed
+    /// storing the file, which completes its upload.
+    ///
+    /// The event is received for the storage requests of every user, so it is ignored for files
+    /// this node is not uploading.
+    async fn handle_event(&mut self, event: StorageRequestFulfilled) -> anyhow::Result<()> {
+        let file_key: H256 = event.file_key.into();
+        let upload_progress = &self.storage_hub_handler.upload_progress;
+
+        if upload_progress.get(&file_key).is_none() {
+            return Ok(());
+        }
+
+        upload_progress.set_state(&file_key, UploadState::Confirmed);
+
+        info!(
+            target: LOG_TARGET,
+            "Upload of file key {:?} complete. Its storage request has been fulfilled.",
+            file_key
+        );
+
+        Ok(())
+    }
+}
+
 impl<NT> UserSendsFileTask<NT>
 where
     NT: ShNodeType,
 {
+    /// Sends the chunks of the file to the provider with `peer_ids`, failing over to its next
+    /// peer ID with the chunks not acknowledged by the previous one.
     async fn send_chunks_to_provider(
         &mut self,
         peer_ids: Vec<PeerId>,
         file_metadata: &FileMetadata,
     ) -> Result<(), anyhow::Error> {
         let file_key = file_metadata.file_key::<HashT<StorageProofsMerkleTrieLayout>>();
-        let chunk_count = file_metadata.chunks_count();
-
-        // Iterates and tries to send file to peer.
-        // Breaks loop after first successful attempt since all peer ids belong to the same provider.
-        for peer_id in peer_ids {
-            match self
-                .send_chunks(peer_id, file_metadata, file_key, chunk_count)
-                .await
-            {
-                Err(err) => {
-                    // If sending chunk failed with one peer id, we try with the next one.
-                    warn!(target: LOG_TARGET, "{:?}", err);
-                    continue;
-                }
-                Ok(()) => {
-                    // If successful our job is done. No need to try with the next peer id.
-                    return Ok(());
-                }
-            };
-        }
+        let fingerprint = file_metadata.fingerprint();
+        debug!(target: LOG_TARGET, "Attempting to send chunks of file key {:?} to peers {:?}", file_key, peer_ids);
 
-        Err(anyhow::anyhow!(
-            "Failed to send file {:?} to any of the peers",
-            file_metadata.fingerprint()
-        ))
-    }
+        let transport = UserFileTransport {
+            file_transfer: self.storage_hub_handler.file_transfer.clone(),
+            file_storage: self.storage_hub_handler.file_storage.clone(),
+            file_key,
+        };
+        let report = self
+            .chunk_uploader
+            .upload(
+                &transport,
+                &peer_ids,
+                batch_chunk_ids(file_metadata.file_size()),
+            )
+            .await;
 
-    async fn send_chunks(
-        &mut self,
-        peer_id: PeerId,
-        file_metadata: &FileMetadata,
-        file_key: H256,
-        chunk_count: u64,
-    ) -> Result<(), anyhow::Error> {
-        debug!(target: LOG_TARGET, "Attempting to send chunks of file key {:?} to peer {:?}", file_key, peer_id);
+        for (peer_id, stats) in &report.peer_stats {
+            info!(target: LOG_TARGET, "Sent {} batch(es) of file fingerprint {:x} to peer {:?}: {} acknowledged ({} chunks), {} retried, {} timed out, {} failed over", stats.batches_sent, fingerprint, peer_id, stats.batches_acknowledged, stats.chunks_acknowledged, stats.retries, stats.timeouts, stats.failovers);
+            if let Some(metrics) = &self.storage_hub_handler.metrics {
+                metrics.record_chunk_upload(stats);
+            }
+        }
 
-        let mut current_batch = Vec::new();
-        let mut current_batch_size = 0;
+        report.result.map_err(|e| {
+            anyhow::anyhow!("Failed to send file fingerprint {:x}: {}", fingerprint, e)
+        })?;
 
-        let fingerprint = file_metadata.fingerprint();
+        info!(target: LOG_TARGET, "Successfully sent file fingerprint {:x} to peers {:?}", fingerprint, peer_ids);
+        Ok(())
+    }
+}
 
-        for chunk_id in 0..chunk_count {
-            let chunk_data = self
-                .storage_hub_handler
-                .file_storage
-                .read()
-                .await
-                .get_chunk(&file_key, &ChunkId::new(chunk_id))
-                .map_err(|e| anyhow::anyhow!("Failed to get chunk: {:?}", e))?;
-
-            // Check if adding this chunk would exceed the batch size limit
-            if current_batch_size + chunk_data.len() > BATCH_CHUNK_FILE_TRANSFER_MAX_SIZE {
-                // Send current batch before adding new chunk
-                debug!(
-                    target: LOG_TARGET,
-                    "Sending batch of {} chunks (total size: {} bytes) for file {:?} to peer {:?}",
-                    current_batch.len(),
-                    current_batch_size,
-                    file_key,
-                    peer_id
-                );
-
-                // Generate proof for the entire batch
-                let proof = match self
-                    .storage_hub_handler
-                    .file_storage
-                    .read()
-                    .await
-                    .generate_proof(
-                        &file_key,
-                        &HashSet::from_iter(current_batch.iter().cloned()),
-                    ) {
-                    Ok(proof) => proof,
-                    Err(e) => {
-                        return Err(anyhow::anyhow!(
-                            "Failed to generate proof for batch of file {:?}\n Error: {:?}",
-                            file_key,
-                            e
-                        ));
-                    }
-                };
-
-                let mut retry_attempts = 0;
-                loop {
-                    let upload_response = self
-                        .storage_hub_handler
-                        .file_transfer
-                        .upload_request(peer_id, file_key.as_ref().into(), proof.clone(), None)
-                        .await;
-
-                    match upload_response {
-                        Ok(r) => {
-                            debug!(
-                                target: LOG_TARGET,
-                                "Successfully uploaded batch for file fingerprint {:x} to peer {:?}",
-                                fingerprint,
-                                peer_id
-                            );
-
-                            // If the provider signals they have the entire file, we can stop
-                            if r.file_complete {
-                                info!(
-                                    target: LOG_TARGET,
-                                    "Stopping file upload process. Peer {:?} has the entire file fingerprint {:x}",
-                                    peer_id,
-                                    fingerprint
-                                );
-                                return Ok(());
-                            }
-
-                            break;
-                        }
-                        Err(RequestError::RequestFailure(RequestFailure::Refused))
-                            if retry_attempts < 3 =>
-                        {
-                            warn!(
-                                target: LOG_TARGET,
-                                "Batch upload rejected by peer {:?}, retrying... (attempt {})",
-                                peer_id,
-                                retry_attempts + 1
-                            );
-                            retry_attempts += 1;
-
-                            // Wait for a short time before retrying
-                            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-                        }
-                        Err(RequestError::RequestFailure(RequestFailure::Refused)) => {
-                            // Return an error if the provider refused to answer.
-                            return Err(anyhow::anyhow!("Failed to send file {:?}", file_key));
-                        }
-                        Err(e) => {
-                            return Err(anyhow::anyhow!(
-                                "Unexpected error while trying to upload batch to peer {:?} (Error: {:?})",
-                                peer_id,
-                                e
-                            ));
-                        }
-                    }
-                }
+/// Sends the chunks of a file in the File Storage of the user through the File Transfer
+/// Service, along with their proof.
+struct UserFileTransport<FL> {
+    file_transfer: ActorHandle<FileTransferService>,
+    file_storage: Arc<RwLock<FL>>,
+    file_key: H256,
+}
 
-                // Clear the batch for next iteration
-                current_batch.clear();
-                current_batch_size = 0;
-            }
+#[async_trait]
+impl<FL> ChunkUploadTransport for UserFileTransport<FL>
+where
+    FL: FileStorageT,
+{
+    type Batch = FileKeyProof;
 
-            // Add chunk to current batch
-            current_batch.push(ChunkId::new(chunk_id));
-            current_batch_size += chunk_data.len();
-
-            // If this is the last chunk, send the final batch
-            if chunk_id == chunk_count - 1 && !current_batch.is_empty() {
-                debug!(
-                    target: LOG_TARGET,
-                    "Sending final batch of {} chunks (total size: {} bytes) for file {:?} to peer {:?}",
-                    current_batch.len(),
-                    current_batch_size,
-                    file_key,
-                    peer_id
-                );
-
-                // Generate proof for the final batch
-                let proof = match self
-                    .storage_hub_handler
-                    .file_storage
-                    .read()
-                    .await
-                    .generate_proof(
-                        &file_key,
-                        &HashSet::from_iter(current_batch.iter().cloned()),
-                    ) {
-                    Ok(proof) => proof,
-                    Err(e) => {
-                        return Err(anyhow::anyhow!(
-                            "Failed to generate proof for final batch of file {:?}\n Error: {:?}",
-                            file_key,
-                            e
-                        ));
-                    }
-                };
-
-                let mut retry_attempts = 0;
-                loop {
-                    let upload_response = self
-                        .storage_hub_handler
-                        .file_transfer
-                        .upload_request(peer_id, file_key.as_ref().into(), proof.clone(), None)
-                        .await;
-
-                    match upload_response {
-                        Ok(r) => {
-                            debug!(
-                                target: LOG_TARGET,
-                                "Successfully uploaded final batch for file fingerprint {:x} to peer {:?}",
-                                fingerprint,
-                                peer_id
-                            );
-
-                            if r.file_complete {
-                                info!(
-                                    target: LOG_TARGET,
-                                    "File upload complete. Peer {:?} has the entire file fingerprint {:x}",
-                                    peer_id,
-                                    fingerprint
-                                );
-                            }
-                            break;
-                        }
-                        Err(RequestError::RequestFailure(RequestFailure::Refused))
-                            if retry_attempts < 3 =>
-                        {
-                            warn!(
-                                target: LOG_TARGET,
-                                "Final batch upload rejected by peer {:?}, retrying... (attempt {})",
-                                peer_id,
-                                retry_attempts + 1
-                            );
-                            retry_attempts += 1;
-
-                            // Wait for a short time before retrying
-                            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-                        }
-                        Err(RequestError::RequestFailure(RequestFailure::Refused)) => {
-                            // Return an error if the provider refused to answer.
-                            return Err(anyhow::anyhow!("Failed to send file {:?}", file_key));
-                        }
-                        Err(e) => {
-                            return Err(anyhow::anyhow!(
-                                "Unexpected error while trying to upload final batch to peer {:?} (Error: {:?})",
-                                peer_id,
-                                e
-                            ));
-                        }
-                    }
-                }
-            }
-        }
+    async fn batch(&self, chunk_ids: &[ChunkId]) -> anyhow::Result<FileKeyProof> {
+        self.file_storage
+            .read()
+            .await
+            .generate_proof(
+                &self.file_key,
+                &HashSet::from_iter(chunk_ids.iter().cloned()),
+            )
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "Failed to generate proof for batch of file {:?}\n Error: {:?}",
+                    self.file_key,
+                    e
+                )
+            })
+    }
 
-        info!(target: LOG_TARGET, "Successfully sent file fingerprint {:x} to peer {:?}", fingerprint, peer_id);
-        Ok(())
+    async fn send(&self, peer_id: PeerId, batch: FileKeyProof) -> Result<bool, RequestError> {
+        self.file_transfer
+            .upload_request(peer_id, self.file_key.as_ref().into(), batch, None)
+            .await
+            .map(|response| response.file_complete)
     }
 }
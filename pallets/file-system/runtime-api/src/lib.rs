@@ -47,6 +47,39 @@ pub enum QueryConfirmChunksToProveForFileError {
     FailedToGenerateChunkChallenges,
 }
 
+/// Error type for each file of the `query_bsp_confirm_chunks_to_prove_for_files` and
+/// `query_msp_confirm_chunks_to_prove_for_files` runtime API calls.
+#[derive(Eq, PartialEq, Encode, Decode, RuntimeDebug, TypeInfo)]
+pub enum QueryConfirmChunksToProveForFilesError {
+    StorageRequestNotFound,
+    ConfirmChunks(QueryConfirmChunksToProveForFileError),
+    InternalError,
+}
+
+impl From<QueryBspConfirmChunksToProveForFileError> for QueryConfirmChunksToProveForFilesError {
+    fn from(error: QueryBspConfirmChunksToProveForFileError) -> Self {
+        match error {
+            QueryBspConfirmChunksToProveForFileError::StorageRequestNotFound => {
+                Self::StorageRequestNotFound
+            }
+            QueryBspConfirmChunksToProveForFileError::ConfirmChunks(e) => Self::ConfirmChunks(e),
+            QueryBspConfirmChunksToProveForFileError::InternalError => Self::InternalError,
+        }
+    }
+}
+
+impl From<QueryMspConfirmChunksToProveForFileError> for QueryConfirmChunksToProveForFilesError {
+    fn from(error: QueryMspConfirmChunksToProveForFileError) -> Self {
+        match error {
+            QueryMspConfirmChunksToProveForFileError::StorageRequestNotFound => {
+                Self::StorageRequestNotFound
+            }
+            QueryMspConfirmChunksToProveForFileError::ConfirmChunks(e) => Self::ConfirmChunks(e),
+            QueryMspConfirmChunksToProveForFileError::InternalError => Self::InternalError,
+        }
+    }
+}
+
 /// Error type for `decode_generic_apply_delta_event_info`.
 #[derive(Eq, PartialEq, Encode, Decode, RuntimeDebug, TypeInfo)]
 pub enum GenericApplyDeltaEventInfoError {
@@ -54,7 +87,7 @@ pub enum GenericApplyDeltaEventInfoError {
 }
 
 sp_api::decl_runtime_apis! {
-    #[api_version(1)]
+    #[api_version(2)]
     pub trait FileSystemApi<BackupStorageProviderId, MainStorageProviderId, FileKey, TickNumber, ChunkId, GenericApplyDeltaEventInfo>
     where
         BackupStorageProviderId: Codec,
@@ -69,5 +102,9 @@ sp_api::decl_runtime_apis! {
         fn query_bsp_confirm_chunks_to_prove_for_file(bsp_id: BackupStorageProviderId, file_key: FileKey) -> Result<Vec<ChunkId>, QueryBspConfirmChunksToProveForFileError>;
         fn query_msp_confirm_chunks_to_prove_for_file(msp_id: MainStorageProviderId, file_key: FileKey) -> Result<Vec<ChunkId>, QueryMspConfirmChunksToProveForFileError>;
         fn decode_generic_apply_delta_event_info(encoded_event_info: Vec<u8>) -> Result<GenericApplyDeltaEventInfo, GenericApplyDeltaEventInfoError>;
+        #[api_version(2)]
+        fn query_bsp_confirm_chunks_to_prove_for_files(bsp_id: BackupStorageProviderId, file_keys: Vec<FileKey>) -> Vec<(FileKey, Result<Vec<ChunkId>, QueryConfirmChunksToProveForFilesError>)>;
+        #[api_version(2)]
+        fn query_msp_confirm_chunks_to_prove_for_files(msp_id: MainStorageProviderId, file_keys: Vec<FileKey>) -> Vec<(FileKey, Result<Vec<ChunkId>, QueryConfirmChunksToProveForFilesError>)>;
     }
 }
@@ -12545,6 +12545,94 @@ mod msp_stop_storing_bucket {
     }
 }
 
+mod query_confirm_chunks_to_prove_for_files {
+    use super::*;
+    use pallet_file_system_runtime_api::QueryConfirmChunksToProveForFilesError;
+
+    #[test]
+    fn returns_the_chunks_to_prove_of_every_requested_file() {
+        new_test_ext().execute_with(|| {
+            let owner = Keyring::Alice.to_account_id();
+            let origin = RuntimeOrigin::signed(owner.clone());
+            let bsp_account_id = Keyring::Bob.to_account_id();
+            let bsp_signed = RuntimeOrigin::signed(bsp_account_id.clone());
+            let msp = Keyring::Charlie.to_account_id();
+            let size = 4;
+            let peer_id = BoundedVec::try_from(vec![1]).unwrap();
+            let peer_ids: PeerIds<Test> = BoundedVec::try_from(vec![peer_id]).unwrap();
+
+            let (msp_id, value_prop_id) = add_msp_to_provider_storage(&msp);
+
+            let name = BoundedVec::try_from(b"bucket".to_vec()).unwrap();
+            let (bucket_id, _) = create_bucket(&owner, name, msp_id, value_prop_id, false);
+
+            // Sign up account as a Backup Storage Provider
+            assert_ok!(bsp_sign_up(bsp_signed.clone(), 100));
+            let bsp_id = Providers::get_provider_id(&bsp_account_id).unwrap();
+
+            // Issue a storage request for each file.
+            let mut file_keys = Vec::new();
+            for (location, fingerprint) in [(b"file_1", [1u8; 32]), (b"file_2", [2u8; 32])] {
+                let location = FileLocation::<Test>::try_from(location.to_vec()).unwrap();
+                let fingerprint = H256::from(fingerprint);
+                assert_ok!(FileSystem::issue_storage_request(
+                    origin.clone(),
+                    bucket_id,
+                    location.clone(),
+                    fingerprint,
+                    size,
+                    msp_id,
+                    peer_ids.clone(),
+                    ReplicationTarget::Standard
+                ));
+                file_keys.push(FileSystem::compute_file_key(
+                    owner.clone(),
+                    bucket_id,
+                    location,
+                    size,
+                    fingerprint,
+                ));
+            }
+
+            // A file without a storage request is reported, without failing the others.
+            let unknown_file_key = H256::repeat_byte(0xff);
+            let mut requested_file_keys = file_keys.clone();
+            requested_file_keys.push(unknown_file_key);
+
+            let chunks_to_prove =
+                FileSystem::query_confirm_chunks_to_prove_for_files(bsp_id, requested_file_keys);
+
+            assert_eq!(chunks_to_prove.len(), 3);
+            for ((file_key, chunks), expected_file_key) in chunks_to_prove.iter().zip(&file_keys) {
+                assert_eq!(file_key, expected_file_key);
+                let chunks = chunks.as_ref().expect("Chunks to prove should be found");
+                assert!(!chunks.is_empty());
+                assert_eq!(
+                    Ok(chunks.clone()),
+                    FileSystem::query_bsp_confirm_chunks_to_prove_for_file(bsp_id, *file_key)
+                );
+            }
+            assert_eq!(
+                chunks_to_prove[2],
+                (
+                    unknown_file_key,
+                    Err(QueryConfirmChunksToProveForFilesError::StorageRequestNotFound)
+                )
+            );
+
+            // MSPs are challenged the same way as BSPs.
+            let chunks_to_prove =
+                FileSystem::query_confirm_chunks_to_prove_for_files(msp_id, file_keys.clone());
+            for (file_key, chunks) in chunks_to_prove {
+                assert_eq!(
+                    chunks.ok(),
+                    FileSystem::query_msp_confirm_chunks_to_prove_for_file(msp_id, file_key).ok()
+                );
+            }
+        });
+    }
+}
+
 /// Helper function that registers an account as a Backup Storage Provider
 fn bsp_sign_up(
     bsp_signed: RuntimeOrigin,
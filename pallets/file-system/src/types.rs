@@ -216,6 +216,8 @@ pub enum RejectedStorageRequestReason {
     FileKeyAlreadyStored,
     RequestExpired,
     InternalError,
+    ReachedBucketDataLimit,
+    MaxConcurrentUploadsReached,
 }
 
 #[derive(Encode, Decode, MaxEncodedLen, TypeInfo, PartialEq, Eq, Clone)]
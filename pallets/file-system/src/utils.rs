@@ -23,7 +23,8 @@ use sp_std::{collections::btree_set::BTreeSet, vec::Vec};
 use pallet_file_system_runtime_api::{
     GenericApplyDeltaEventInfoError, IsStorageRequestOpenToVolunteersError,
     QueryBspConfirmChunksToProveForFileError, QueryConfirmChunksToProveForFileError,
-    QueryFileEarliestVolunteerTickError, QueryMspConfirmChunksToProveForFileError,
+    QueryConfirmChunksToProveForFilesError, QueryFileEarliestVolunteerTickError,
+    QueryMspConfirmChunksToProveForFileError,
 };
 use pallet_nfts::{CollectionConfig, CollectionSettings, ItemSettings, MintSettings, MintType};
 use shp_constants::GIGAUNIT;
@@ -341,6 +342,33 @@ where
             .map_err(|e| QueryMspConfirmChunksToProveForFileError::ConfirmChunks(e))
     }
 
+    /// Query the chunks that `provider_id` has to prove to confirm storing each of `file_keys`,
+    /// either as a BSP or as an MSP, since both are challenged the same way.
+    pub fn query_confirm_chunks_to_prove_for_files(
+        provider_id: ProviderIdFor<T>,
+        file_keys: Vec<MerkleHash<T>>,
+    ) -> Vec<(
+        MerkleHash<T>,
+        Result<Vec<ChunkId>, QueryConfirmChunksToProveForFilesError>,
+    )> {
+        file_keys
+            .into_iter()
+            .map(|file_key| {
+                let chunks_to_prove = match <StorageRequests<T>>::get(&file_key) {
+                    Some(storage_request_metadata) => Self::query_confirm_chunks_to_prove_for_file(
+                        provider_id,
+                        storage_request_metadata,
+                        file_key,
+                    )
+                    .map_err(QueryConfirmChunksToProveForFilesError::ConfirmChunks),
+                    None => Err(QueryConfirmChunksToProveForFilesError::StorageRequestNotFound),
+                };
+
+                (file_key, chunks_to_prove)
+            })
+            .collect()
+    }
+
     pub fn decode_generic_apply_delta_event_info(
         encoded_event_info: Vec<u8>,
     ) -> Result<BucketIdFor<T>, GenericApplyDeltaEventInfoError> {
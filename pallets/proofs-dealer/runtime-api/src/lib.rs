@@ -28,6 +28,7 @@ sp_api::decl_runtime_apis! {
         fn get_forest_challenges_from_seed(seed: &RandomnessOutput, provider_id: &ProviderId) -> Vec<Key>;
         fn get_current_tick() -> BlockNumber;
         fn get_next_deadline_tick(provider_id: &ProviderId) -> Result<BlockNumber, GetNextDeadlineTickError>;
+        fn get_file_size_to_challenges() -> u64;
     }
 }
 
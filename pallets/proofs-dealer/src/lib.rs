@@ -693,6 +693,11 @@ pub mod pallet {
 
             let last_tick_proven = Self::do_submit_proof(&provider, &proof)?;
 
+            // Reward the Provider's standing for the accepted proof.
+            <T::ProvidersPallet as MutateChallengeableProvidersInterface>::on_proof_accepted(
+                &provider,
+            );
+
             // Emit event.
             Self::deposit_event(Event::ProofAccepted {
                 provider_id: provider,
@@ -1042,6 +1042,14 @@ fn submit_proof_success() {
             TickToProvidersDeadlines::<Test>::get(new_deadline, provider_id),
             Some(()),
         );
+
+        // Check that the Provider's reputation weight increased with the accepted proof.
+        assert_eq!(
+            pallet_storage_providers::BackupStorageProviders::<Test>::get(&provider_id)
+                .unwrap()
+                .reputation_weight,
+            <Test as pallet_storage_providers::Config>::StartingReputationWeight::get() + 1
+        );
     });
 }
 
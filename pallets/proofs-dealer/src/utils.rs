@@ -568,6 +568,11 @@ where
                     next_tick_to_submit_proof_for;
                 ProviderToProofSubmissionRecord::<T>::set(provider, Some(proof_submission_record));
 
+                // Penalise the Provider's standing for missing its deadline.
+                <T::ProvidersPallet as MutateChallengeableProvidersInterface>::on_provider_slashable(
+                    &provider,
+                );
+
                 // Emit slashable provider event.
                 Self::deposit_event(Event::SlashableProvider {
                     provider,
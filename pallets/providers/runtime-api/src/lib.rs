@@ -2,6 +2,7 @@
 
 use codec::{Codec, Decode, Encode};
 use scale_info::TypeInfo;
+use sp_core::H256;
 use sp_runtime::RuntimeDebug;
 
 sp_api::decl_runtime_apis! {
@@ -25,6 +26,8 @@ sp_api::decl_runtime_apis! {
         fn get_storage_provider_id(who: &AccountId) -> Option<StorageProviderId>;
         fn query_provider_multiaddresses(provider_id: &ProviderId) -> Result<Multiaddresses, QueryProviderMultiaddressesError>;
         fn query_msp_id_of_bucket_id(bucket_id: &BucketId) -> Result<Option<ProviderId>, QueryMspIdOfBucketIdError>;
+        fn query_bucket_size_and_data_limit(bucket_id: &BucketId) -> Result<(StorageDataUnit, StorageDataUnit), QueryBucketSizeAndDataLimitError>;
+        fn query_bucket_root(bucket_id: &BucketId) -> Result<H256, QueryBucketRootError>;
         fn query_storage_provider_capacity(provider_id: &ProviderId) -> Result<StorageDataUnit, QueryStorageProviderCapacityError>;
         fn query_available_storage_capacity(provider_id: &ProviderId) -> Result<StorageDataUnit, QueryAvailableStorageCapacityError>;
         fn query_earliest_change_capacity_block(bsp_id: &BspId) -> Result<BlockNumber, QueryEarliestChangeCapacityBlockError>;
@@ -35,6 +38,7 @@ sp_api::decl_runtime_apis! {
         fn can_delete_provider(provider_id: &ProviderId) -> bool;
         fn query_buckets_for_msp(msp_id: &MspId) -> Result<sp_runtime::Vec<BucketId>, QueryBucketsForMspError>;
         fn query_buckets_of_user_stored_by_msp(msp_id: &ProviderId, user: &AccountId) -> Result<sp_runtime::Vec<BucketId>, QueryBucketsOfUserStoredByMspError>;
+        fn query_bucket_read_access(bucket_id: &BucketId, user: &AccountId) -> Result<BucketReadAccess<AccountId>, QueryBucketReadAccessError>;
     }
 }
 
@@ -73,6 +77,22 @@ pub enum QueryMspIdOfBucketIdError {
     InternalError,
 }
 
+/// Error type for the `query_bucket_size_and_data_limit` runtime API call.
+#[derive(Eq, PartialEq, Encode, Decode, RuntimeDebug, TypeInfo)]
+pub enum QueryBucketSizeAndDataLimitError {
+    BucketNotFound,
+    BucketNotStoredByMsp,
+    ValuePropositionNotFound,
+    InternalError,
+}
+
+/// Error type for the `query_bucket_root` runtime API call.
+#[derive(Eq, PartialEq, Encode, Decode, RuntimeDebug, TypeInfo)]
+pub enum QueryBucketRootError {
+    BucketNotFound,
+    InternalError,
+}
+
 /// Error type for the `query_provider_multiaddresses` runtime API call.
 #[derive(Eq, PartialEq, Encode, Decode, RuntimeDebug, TypeInfo)]
 pub enum QueryProviderMultiaddressesError {
@@ -100,3 +120,23 @@ pub enum QueryBucketsOfUserStoredByMspError {
     NotAnMsp,
     InternalError,
 }
+
+/// Error type for the `query_bucket_read_access` runtime API call.
+#[derive(Eq, PartialEq, Encode, Decode, RuntimeDebug, TypeInfo)]
+pub enum QueryBucketReadAccessError {
+    BucketNotFound,
+    InternalError,
+}
+
+/// What determines whether a user can read the files of a bucket.
+///
+/// Read access is not enforced by the runtime, but by the MSP storing the bucket.
+#[derive(Eq, PartialEq, Encode, Decode, RuntimeDebug, TypeInfo)]
+pub struct BucketReadAccess<AccountId> {
+    /// The owner of the bucket.
+    pub owner: AccountId,
+    /// Whether the bucket is private.
+    pub private: bool,
+    /// Whether the queried user holds an item of the bucket's read access group.
+    pub holds_read_access_item: bool,
+}
@@ -301,6 +301,7 @@ mod benchmarks {
             capacity: capacity.into(),
             multiaddresses: multiaddresses.clone(),
             root: T::DefaultMerkleRoot::get(),
+            payment_account: user_account.clone(),
         });
         frame_system::Pallet::<T>::assert_last_event(expected_event.into());
 
@@ -399,6 +400,7 @@ mod benchmarks {
             capacity: 100000u32.into(),
             multiaddresses: multiaddresses.clone(),
             value_prop: value_prop_with_id,
+            payment_account: user_account.clone(),
         });
         frame_system::Pallet::<T>::assert_last_event(expected_event.into());
 
@@ -1716,6 +1718,7 @@ mod benchmarks {
                     commitment.clone(),
                     value_prop_max_data_limit.into(),
                 ),
+                payment_account: user_account.clone(),
             });
         frame_system::Pallet::<T>::assert_has_event(msp_sign_up_event.into());
 
@@ -1791,6 +1794,7 @@ mod benchmarks {
                 multiaddresses: multiaddresses.clone(),
                 capacity: capacity.into(),
                 root: T::DefaultMerkleRoot::get(),
+                payment_account: user_account.clone(),
             });
         frame_system::Pallet::<T>::assert_has_event(bsp_sign_up_event.into());
 
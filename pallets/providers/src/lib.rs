@@ -280,6 +280,13 @@ pub mod pallet {
         #[pallet::constant]
         type StartingReputationWeight: Get<Self::ReputationWeightType>;
 
+        /// Maximum reputation weight a BSP can reach by having its proofs accepted.
+        ///
+        /// The reputation weight of a BSP increases by one with every accepted proof, up to this
+        /// value, and decreases by one every time the BSP is marked as slashable, down to one.
+        #[pallet::constant]
+        type MaxReputationWeight: Get<Self::ReputationWeightType>;
+
         /// The amount of blocks that a BSP must wait before being able to sign off, after being signed up.
         ///
         /// This is to prevent BSPs from signing up and off too quickly, thus making it harder for an attacker
@@ -537,13 +544,15 @@ pub mod pallet {
         },
 
         /// Event emitted when a Main Storage Provider has confirmed its sign up successfully. Provides information about
-        /// that MSP's account id, the total data it can store according to its stake, its multiaddress, and its value proposition.
+        /// that MSP's account id, the total data it can store according to its stake, its multiaddress, its value proposition
+        /// and the account that receives its payments.
         MspSignUpSuccess {
             who: T::AccountId,
             msp_id: MainStorageProviderId<T>,
             multiaddresses: Multiaddresses<T>,
             capacity: StorageDataUnit<T>,
             value_prop: ValuePropositionWithId<T>,
+            payment_account: T::AccountId,
         },
 
         /// Event emitted when a Backup Storage Provider has requested to sign up successfully. Provides information about
@@ -556,13 +565,14 @@ pub mod pallet {
 
         /// Event emitted when a Backup Storage Provider has confirmed its sign up successfully. Provides information about
         /// that BSP's account id, the initial root of the Merkle Patricia Trie that it stores, the total data it can store
-        /// according to its stake, and its multiaddress.
+        /// according to its stake, its multiaddress and the account that receives its payments.
         BspSignUpSuccess {
             who: T::AccountId,
             bsp_id: BackupStorageProviderId<T>,
             root: MerklePatriciaRoot<T>,
             multiaddresses: Multiaddresses<T>,
             capacity: StorageDataUnit<T>,
+            payment_account: T::AccountId,
         },
 
         /// Event emitted when a sign up request has been canceled successfully. Provides information about
@@ -27,8 +27,8 @@ use frame_support::{
 use frame_system::pallet_prelude::BlockNumberFor;
 use shp_constants::GIGAUNIT;
 use shp_traits::{
-    MutateBucketsInterface, MutateStorageProvidersInterface, PaymentStreamsInterface,
-    ReadBucketsInterface, ReadProvidersInterface, StorageHubTickGetter,
+    MutateBucketsInterface, MutateChallengeableProvidersInterface, MutateStorageProvidersInterface,
+    PaymentStreamsInterface, ReadBucketsInterface, ReadProvidersInterface, StorageHubTickGetter,
 };
 use sp_arithmetic::{MultiplyRational, Rounding};
 use sp_core::H256;
@@ -261,6 +261,7 @@ mod sign_up {
                                 id: value_prop.derive_id(),
                                 value_prop,
                             },
+                            payment_account: alice,
                         }
                         .into(),
                     );
@@ -358,6 +359,7 @@ mod sign_up {
                                 value_prop,
                             },
                             msp_id: alice_sp_id.unwrap(),
+                            payment_account: alice,
                         }
                         .into(),
                     );
@@ -765,6 +767,7 @@ mod sign_up {
                             multiaddresses,
                             capacity: storage_amount,
                             bsp_id: alice_sp_id.unwrap(),
+                            payment_account: alice,
                         }
                         .into(),
                     );
@@ -870,6 +873,7 @@ mod sign_up {
                             multiaddresses,
                             capacity: storage_amount,
                             bsp_id: alice_sp_id.unwrap(),
+                            payment_account: alice,
                         }
                         .into(),
                     );
@@ -1265,6 +1269,7 @@ mod sign_up {
                                 id: value_prop.derive_id(),
                                 value_prop,
                             },
+                            payment_account: alice,
                         }
                         .into(),
                     );
@@ -1358,6 +1363,7 @@ mod sign_up {
                                 id: value_prop.derive_id(),
                                 value_prop,
                             },
+                            payment_account: alice,
                         }
                         .into(),
                     );
@@ -1381,6 +1387,7 @@ mod sign_up {
                             root: DefaultMerkleRoot::get(),
                             capacity: storage_amount_bob,
                             bsp_id: bob_sp_id.unwrap(),
+                            payment_account: bob,
                         }
                         .into(),
                     );
@@ -1470,6 +1477,7 @@ mod sign_up {
                                 id: value_prop.derive_id(),
                                 value_prop,
                             },
+                            payment_account: alice,
                         }
                         .into(),
                     );
@@ -6866,6 +6874,128 @@ mod stop_all_cycles {
     }
 }
 
+mod reputation_weight {
+    use super::*;
+
+    type MaxReputationWeight = <Test as crate::Config>::MaxReputationWeight;
+    type StartingReputationWeight = <Test as crate::Config>::StartingReputationWeight;
+
+    mod success {
+        use super::*;
+
+        #[test]
+        fn accepted_proofs_increase_reputation_weight_up_to_max() {
+            ExtBuilder::build().execute_with(|| {
+                let bob: AccountId = accounts::BOB.0;
+                register_account_as_bsp(bob, 100);
+                let bsp_id = StorageProviders::get_provider_id(&bob).unwrap();
+
+                let starting_weight = StartingReputationWeight::get();
+                assert_eq!(GlobalBspsReputationWeight::<Test>::get(), starting_weight);
+
+                StorageProviders::on_proof_accepted(&bsp_id);
+
+                assert_eq!(
+                    BackupStorageProviders::<Test>::get(&bsp_id)
+                        .unwrap()
+                        .reputation_weight,
+                    starting_weight + 1
+                );
+                assert_eq!(
+                    GlobalBspsReputationWeight::<Test>::get(),
+                    starting_weight + 1
+                );
+
+                // Accepting more proofs than needed to reach the maximum does not go over it.
+                for _ in 0..MaxReputationWeight::get() {
+                    StorageProviders::on_proof_accepted(&bsp_id);
+                }
+
+                assert_eq!(
+                    BackupStorageProviders::<Test>::get(&bsp_id)
+                        .unwrap()
+                        .reputation_weight,
+                    MaxReputationWeight::get()
+                );
+                assert_eq!(
+                    GlobalBspsReputationWeight::<Test>::get(),
+                    MaxReputationWeight::get()
+                );
+            });
+        }
+
+        #[test]
+        fn slashable_providers_lose_reputation_weight_down_to_one() {
+            ExtBuilder::build().execute_with(|| {
+                let alice: AccountId = accounts::ALICE.0;
+                let bob: AccountId = accounts::BOB.0;
+                register_account_as_bsp(alice, 100);
+                register_account_as_bsp(bob, 100);
+                let alice_id = StorageProviders::get_provider_id(&alice).unwrap();
+                let bob_id = StorageProviders::get_provider_id(&bob).unwrap();
+
+                for _ in 0..3 {
+                    StorageProviders::on_proof_accepted(&bob_id);
+                }
+                let bob_weight = StartingReputationWeight::get() + 3;
+                let alice_weight = StartingReputationWeight::get();
+                assert_eq!(
+                    GlobalBspsReputationWeight::<Test>::get(),
+                    alice_weight + bob_weight
+                );
+
+                StorageProviders::on_provider_slashable(&bob_id);
+
+                assert_eq!(
+                    BackupStorageProviders::<Test>::get(&bob_id)
+                        .unwrap()
+                        .reputation_weight,
+                    bob_weight - 1
+                );
+                assert_eq!(
+                    GlobalBspsReputationWeight::<Test>::get(),
+                    alice_weight + bob_weight - 1
+                );
+
+                // Being slashable more times than the BSP has reputation leaves it at one.
+                for _ in 0..bob_weight {
+                    StorageProviders::on_provider_slashable(&bob_id);
+                }
+
+                assert_eq!(
+                    BackupStorageProviders::<Test>::get(&bob_id)
+                        .unwrap()
+                        .reputation_weight,
+                    1
+                );
+                assert_eq!(GlobalBspsReputationWeight::<Test>::get(), alice_weight + 1);
+                // Other BSPs are not affected.
+                assert_eq!(
+                    BackupStorageProviders::<Test>::get(&alice_id)
+                        .unwrap()
+                        .reputation_weight,
+                    alice_weight
+                );
+            });
+        }
+
+        #[test]
+        fn msps_have_no_reputation_weight_to_update() {
+            ExtBuilder::build().execute_with(|| {
+                let alice: AccountId = accounts::ALICE.0;
+                register_account_as_msp(alice, 100, None, None);
+                let msp_id = StorageProviders::get_provider_id(&alice).unwrap();
+
+                StorageProviders::on_proof_accepted(&msp_id);
+                StorageProviders::on_provider_slashable(&msp_id);
+
+                assert!(BackupStorageProviders::<Test>::get(&msp_id).is_none());
+                assert_eq!(GlobalBspsReputationWeight::<Test>::get(), 0);
+            });
+        }
+    }
+}
+
 // Helper functions for testing:
 
 /// Helper function that registers an account as a Main Storage Provider, with storage_amount StorageDataUnit units
@@ -6950,6 +7080,7 @@ fn register_account_as_msp(
                 id: value_prop_id,
                 value_prop,
             },
+            payment_account: account,
         }
         .into(),
     );
@@ -7038,6 +7169,7 @@ fn register_account_as_bsp(
             root: DefaultMerkleRoot::get(),
             multiaddresses: multiaddresses.clone(),
             capacity: storage_amount,
+            payment_account: account,
         }
         .into(),
     );
@@ -329,6 +329,9 @@ pub type ReputationWeightType<T> = <T as crate::Config>::ReputationWeightType;
 /// Type alias for the `StartingReputationWeight` type used in the Storage Providers pallet.
 pub type StartingReputationWeight<T> = <T as crate::Config>::StartingReputationWeight;
 
+/// Type alias for the `MaxReputationWeight` type used in the Storage Providers pallet.
+pub type MaxReputationWeight<T> = <T as crate::Config>::MaxReputationWeight;
+
 /// Type alias for the `StorageHubTickGetter` type used in the Storage Providers pallet.
 pub type ShTickGetter<T> = <T as crate::Config>::StorageHubTickGetter;
 
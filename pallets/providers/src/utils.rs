@@ -15,9 +15,11 @@ use frame_support::{
 };
 use frame_system::pallet_prelude::BlockNumberFor;
 use pallet_storage_providers_runtime_api::{
-    GetBspInfoError, GetStakeError, QueryAvailableStorageCapacityError, QueryBucketsForMspError,
-    QueryBucketsOfUserStoredByMspError, QueryEarliestChangeCapacityBlockError,
-    QueryMspIdOfBucketIdError, QueryProviderMultiaddressesError, QueryStorageProviderCapacityError,
+    BucketReadAccess, GetBspInfoError, GetStakeError, QueryAvailableStorageCapacityError,
+    QueryBucketReadAccessError, QueryBucketRootError, QueryBucketSizeAndDataLimitError,
+    QueryBucketsForMspError, QueryBucketsOfUserStoredByMspError,
+    QueryEarliestChangeCapacityBlockError, QueryMspIdOfBucketIdError,
+    QueryProviderMultiaddressesError, QueryStorageProviderCapacityError,
 };
 use shp_constants::GIGAUNIT;
 use shp_traits::{
@@ -314,6 +316,7 @@ where
                 id: value_prop_id,
                 value_prop: value_prop.clone(),
             },
+            payment_account: sign_up_request.msp_info.payment_account.clone(),
         });
 
         Ok(())
@@ -374,6 +377,7 @@ where
             root: bsp_info.root,
             multiaddresses: bsp_info.multiaddresses.clone(),
             capacity: bsp_info.capacity,
+            payment_account: bsp_info.payment_account.clone(),
         });
 
         Ok(())
@@ -1668,6 +1672,27 @@ where
 
         Ok(new_expiration_tick)
     }
+
+    /// Sets the reputation weight of the BSP with `bsp_id` to the result of `update`, keeping
+    /// [`GlobalBspsReputationWeight`] in sync with it.
+    ///
+    /// Does nothing if `bsp_id` is not a registered BSP, since MSPs have no reputation weight.
+    pub(crate) fn update_bsp_reputation_weight(
+        bsp_id: &BackupStorageProviderId<T>,
+        update: impl FnOnce(ReputationWeightType<T>) -> ReputationWeightType<T>,
+    ) {
+        if let Some(mut bsp) = BackupStorageProviders::<T>::get(bsp_id) {
+            let previous_weight = bsp.reputation_weight;
+            bsp.reputation_weight = update(previous_weight);
+
+            GlobalBspsReputationWeight::<T>::mutate(|n| {
+                *n = n
+                    .saturating_sub(previous_weight)
+                    .saturating_add(bsp.reputation_weight);
+            });
+            BackupStorageProviders::<T>::insert(bsp_id, bsp);
+        }
+    }
 }
 
 impl<T: Config> From<MainStorageProvider<T>> for BackupStorageProvider<T> {
@@ -2635,6 +2660,30 @@ impl<T: pallet::Config> MutateChallengeableProvidersInterface for pallet::Pallet
 
         Ok(())
     }
+
+    fn on_proof_accepted(who: &Self::ProviderId) {
+        // Increase the reputation weight of the BSP by one, up to the maximum. Weights that were
+        // set above the maximum (e.g. when force signing up the BSP) are left as they are.
+        Self::update_bsp_reputation_weight(who, |weight| {
+            if weight < T::MaxReputationWeight::get() {
+                weight.saturating_add(One::one())
+            } else {
+                weight
+            }
+        });
+    }
+
+    fn on_provider_slashable(who: &Self::ProviderId) {
+        // Decrease the reputation weight of the BSP by one, down to one. A BSP with no reputation
+        // weight at all could never volunteer to store files again.
+        Self::update_bsp_reputation_weight(who, |weight| {
+            if weight > One::one() {
+                weight.saturating_sub(One::one())
+            } else {
+                weight
+            }
+        });
+    }
 }
 
 /// Implement the SystemMetricsInterface for the Storage Providers pallet.
@@ -2731,6 +2780,30 @@ where
         Ok(bucket.msp_id)
     }
 
+    /// Returns the current size of the bucket and the data limit of the value proposition it is
+    /// associated with.
+    pub fn query_bucket_size_and_data_limit(
+        bucket_id: &BucketId<T>,
+    ) -> Result<(StorageDataUnit<T>, StorageDataUnit<T>), QueryBucketSizeAndDataLimitError> {
+        let bucket =
+            Buckets::<T>::get(bucket_id).ok_or(QueryBucketSizeAndDataLimitError::BucketNotFound)?;
+        let msp_id = bucket
+            .msp_id
+            .ok_or(QueryBucketSizeAndDataLimitError::BucketNotStoredByMsp)?;
+        let value_prop =
+            MainStorageProviderIdsToValuePropositions::<T>::get(msp_id, bucket.value_prop_id)
+                .ok_or(QueryBucketSizeAndDataLimitError::ValuePropositionNotFound)?;
+        Ok((bucket.size, value_prop.bucket_data_limit))
+    }
+
+    /// Returns the root of the bucket's Merkle Patricia Forest, as currently stored on-chain.
+    pub fn query_bucket_root(
+        bucket_id: &BucketId<T>,
+    ) -> Result<MerklePatriciaRoot<T>, QueryBucketRootError> {
+        let bucket = Buckets::<T>::get(bucket_id).ok_or(QueryBucketRootError::BucketNotFound)?;
+        Ok(bucket.root)
+    }
+
     pub fn query_provider_multiaddresses(
         provider_id: &ProviderIdFor<T>,
     ) -> Result<Multiaddresses<T>, QueryProviderMultiaddressesError> {
@@ -2819,6 +2892,32 @@ where
 
         Ok(buckets)
     }
+
+    /// Get what determines whether `user` can read the files of the bucket.
+    ///
+    /// This pallet doesn't know about the items of read access groups, so whether `user` holds
+    /// one is checked with `holds_read_access_item`, only if the bucket has a read access group.
+    pub fn query_bucket_read_access(
+        bucket_id: &BucketId<T>,
+        user: &T::AccountId,
+        holds_read_access_item: impl FnOnce(&T::ReadAccessGroupId, &T::AccountId) -> bool,
+    ) -> Result<BucketReadAccess<T::AccountId>, QueryBucketReadAccessError> {
+        let bucket =
+            Buckets::<T>::get(bucket_id).ok_or(QueryBucketReadAccessError::BucketNotFound)?;
+
+        let holds_read_access_item = bucket
+            .read_access_group_id
+            .as_ref()
+            .map_or(false, |read_access_group_id| {
+                holds_read_access_item(read_access_group_id, user)
+            });
+
+        Ok(BucketReadAccess {
+            owner: bucket.user_id,
+            private: bucket.private,
+            holds_read_access_item,
+        })
+    }
 }
 
 /**************** Hooks Implementations ****************/
@@ -1072,3 +1072,24 @@ fn chunk_id_convert_to_and_from_trie_key() {
     let chunk_id_decoded = ChunkId::from_trie_key(&chunk_id_bytes).unwrap();
     assert_eq!(chunk_id, chunk_id_decoded);
 }
+
+#[test]
+fn chunk_id_trie_key_is_compact() {
+    // Small chunk IDs take a single byte.
+    assert_eq!(ChunkId::new(0).as_trie_key().len(), 1);
+    assert_eq!(ChunkId::new(63).as_trie_key().len(), 1);
+    // The key grows with the chunk ID.
+    assert_eq!(ChunkId::new(127).as_trie_key().len(), 2);
+    assert_eq!(ChunkId::new(0x3FFF).as_trie_key().len(), 2);
+    assert_eq!(ChunkId::new(0x4000).as_trie_key().len(), 4);
+    // Large chunk IDs take at most the 8 bytes of the u64, plus the compact prefix byte.
+    assert_eq!(ChunkId::new(u64::MAX).as_trie_key().len(), 9);
+
+    for id in [0u64, 63, 64, 127, 128, 0x3FFF, 0x4000, u64::MAX] {
+        let chunk_id = ChunkId::new(id);
+        assert_eq!(
+            ChunkId::from_trie_key(&chunk_id.as_trie_key()).unwrap(),
+            chunk_id
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use codec::{Decode, Encode};
-use core::fmt::Debug;
+use core::{fmt::Debug, ops::RangeInclusive};
 use scale_info::TypeInfo;
 use shp_file_metadata::{
     Chunk, ChunkId, ChunkIdError, ChunkWithId, FileMetadata, Fingerprint, Leaf,
@@ -56,6 +56,11 @@ pub enum ProvenFileKeyError {
     KeyNotFoundInTrie,
     /// Internal error: failed to convert trie key to ChunkId.
     ChunkIdFromKeyError(ChunkIdError),
+    /// The proof is valid, but the number of chunks it proves is not in the expected range.
+    UnexpectedChunkCount {
+        expected: RangeInclusive<usize>,
+        got: usize,
+    },
 }
 
 impl<const H_LENGTH: usize, const CHUNK_SIZE: u64, const SIZE_TO_CHALLENGES: u64>
@@ -128,4 +133,25 @@ impl<const H_LENGTH: usize, const CHUNK_SIZE: u64, const SIZE_TO_CHALLENGES: u64
 
         Ok(proven)
     }
+
+    /// Verifies and extracts proven chunks like [`Self::proven`], failing with
+    /// [`ProvenFileKeyError::UnexpectedChunkCount`] if the number of chunks proven is not in
+    /// `expected`.
+    pub fn proven_expecting<T: TrieLayout>(
+        &self,
+        expected: RangeInclusive<usize>,
+    ) -> Result<Vec<Leaf<ChunkId, Chunk>>, ProvenFileKeyError>
+    where
+        <T::Hash as sp_core::Hasher>::Out: TryFrom<[u8; H_LENGTH]>,
+    {
+        let proven = self.proven::<T>()?;
+        if !expected.contains(&proven.len()) {
+            return Err(ProvenFileKeyError::UnexpectedChunkCount {
+                expected,
+                got: proven.len(),
+            });
+        }
+
+        Ok(proven)
+    }
 }
@@ -14,6 +14,9 @@ use sp_std::vec::Vec;
 /// Maximum number of chunks a Storage Provider would need to prove for a file.
 const MAX_CHUNKS_TO_CHECK: u32 = 10;
 
+/// Maximum length of the location of a file, matching the `MaxFilePathSize` of the runtime.
+pub const MAX_LOCATION_LENGTH: usize = 512;
+
 /// A struct containing all the information about a file in StorageHub.
 ///
 /// It also provides utility functions like calculating the number of chunks in a file,
@@ -41,33 +44,86 @@ impl<const H_LENGTH: usize, const CHUNK_SIZE: u64, const SIZE_TO_CHALLENGES: u64
         size: u64,
         fingerprint: Fingerprint<H_LENGTH>,
     ) -> Result<Self, FileMetadataError> {
-        if owner.is_empty() {
+        let metadata = Self {
+            owner,
+            bucket_id,
+            location,
+            file_size: size,
+            fingerprint,
+        };
+        metadata.validate()?;
+
+        Ok(metadata)
+    }
+
+    /// Same as [`Self::new`], but also checks the metadata with [`Self::validate_strict`].
+    ///
+    /// Meant for off-chain code building metadata of files that are to be stored, so that
+    /// malformed metadata is rejected upfront instead of failing later on as a trie error.
+    pub fn validated(
+        owner: Vec<u8>,
+        bucket_id: Vec<u8>,
+        location: Vec<u8>,
+        size: u64,
+        fingerprint: Fingerprint<H_LENGTH>,
+    ) -> Result<Self, FileMetadataError> {
+        let metadata = Self::new(owner, bucket_id, location, size, fingerprint)?;
+        metadata.validate_strict()?;
+
+        Ok(metadata)
+    }
+
+    /// Checks that the metadata is well formed, i.e. that it could have been created with
+    /// [`Self::new`].
+    ///
+    /// Useful for metadata that was not built with [`Self::new`] but deserialised from storage,
+    /// where a corrupted record would otherwise only fail later on, in code that assumes a
+    /// well formed [`FileMetadata`]. A non-zero `file_size` ensures the file has at least one
+    /// chunk, so that [`Self::chunks_count`] and [`Self::last_chunk_id`] are consistent with it.
+    pub fn validate(&self) -> Result<(), FileMetadataError> {
+        if self.owner.is_empty() {
             return Err(FileMetadataError::InvalidOwner);
         }
 
-        if bucket_id.is_empty() {
+        if self.bucket_id.is_empty() {
             return Err(FileMetadataError::InvalidBucketId);
         }
 
-        if location.is_empty() {
+        if self.location.is_empty() {
             return Err(FileMetadataError::InvalidLocation);
         }
 
-        if size == 0 {
+        if self.file_size == 0 {
             return Err(FileMetadataError::InvalidFileSize);
         }
 
-        if fingerprint.0.is_empty() {
+        if self.fingerprint.0.is_empty() {
             return Err(FileMetadataError::InvalidFingerprint);
         }
 
-        Ok(Self {
-            owner,
-            bucket_id,
-            location,
-            file_size: size,
-            fingerprint,
-        })
+        Ok(())
+    }
+
+    /// Same as [`Self::validate`], but also checks that the metadata is that of a file that
+    /// can actually be stored: the bucket ID is a hash of `H_LENGTH` bytes, the location is at
+    /// most [`MAX_LOCATION_LENGTH`] bytes long and the fingerprint is not the default one (all
+    /// zeroes), which is not the root of any file trie.
+    pub fn validate_strict(&self) -> Result<(), FileMetadataError> {
+        self.validate()?;
+
+        if self.bucket_id.len() != H_LENGTH {
+            return Err(FileMetadataError::InvalidBucketId);
+        }
+
+        if self.location.len() > MAX_LOCATION_LENGTH {
+            return Err(FileMetadataError::InvalidLocation);
+        }
+
+        if self.fingerprint == Fingerprint::default() {
+            return Err(FileMetadataError::InvalidFingerprint);
+        }
+
+        Ok(())
     }
 
     pub fn owner(&self) -> &Vec<u8> {
@@ -95,9 +151,21 @@ impl<const H_LENGTH: usize, const CHUNK_SIZE: u64, const SIZE_TO_CHALLENGES: u64
     }
 
     pub fn chunks_to_check(&self) -> u32 {
+        self.chunks_to_check_with(SIZE_TO_CHALLENGES)
+    }
+
+    /// Same as [`Self::chunks_to_check`], but with one challenge for every `size_to_challenges`
+    /// bytes instead of `SIZE_TO_CHALLENGES`.
+    ///
+    /// Useful off-chain, where the file size to challenges ratio can be queried from the runtime
+    /// instead of relying on the one this crate was compiled with. A `size_to_challenges` of 0 is
+    /// treated as 1.
+    pub fn chunks_to_check_with(&self, size_to_challenges: u64) -> u32 {
+        let size_to_challenges = size_to_challenges.max(1);
+
         // In here we downcast and saturate to u32, as we're going to saturate to MAX_CHUNKS_TO_CHECK anyway.
-        let chunks = (self.file_size / SIZE_TO_CHALLENGES
-            + (self.file_size % SIZE_TO_CHALLENGES != 0) as u64)
+        let chunks = (self.file_size / size_to_challenges
+            + (self.file_size % size_to_challenges != 0) as u64)
             .saturated_into::<u32>();
 
         // Cap chunks to check at MAX_CHUNKS_TO_CHECK.
@@ -171,7 +239,7 @@ impl<const H_LENGTH: usize, const CHUNK_SIZE: u64, const SIZE_TO_CHALLENGES: u64
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum FileMetadataError {
     InvalidOwner,
     InvalidBucketId,
@@ -297,6 +365,12 @@ impl<'de, const H_LENGTH: usize> Deserialize<'de> for Fingerprint<H_LENGTH> {
         D: serde::de::Deserializer<'de>,
     {
         let vec = Vec::<u8>::deserialize(deserializer)?;
+        if vec.len() != H_LENGTH {
+            return Err(serde::de::Error::invalid_length(
+                vec.len(),
+                &"a fingerprint of H_LENGTH bytes",
+            ));
+        }
         let mut hash = [0u8; H_LENGTH];
         hash.copy_from_slice(&vec);
         Ok(Self(hash))
@@ -377,6 +451,13 @@ impl ChunkId {
         self.0
     }
 
+    /// Encodes the chunk ID as a trie key.
+    ///
+    /// The ID is SCALE compact encoded, so small IDs take a single byte (IDs up to 63)
+    /// and the key only grows for larger IDs, instead of always taking 8 bytes.
+    ///
+    /// The byte order of the keys is therefore not the numeric order of the IDs, so iterating
+    /// a file trie does not yield its chunks in order.
     pub fn as_trie_key(&self) -> Vec<u8> {
         AsCompact(self.0).encode()
     }
@@ -511,4 +592,107 @@ mod tests {
         assert!(!metadata.is_valid_chunk_size(2, TEST_CHUNK_SIZE as usize));
         assert!(!metadata.is_valid_chunk_size(100, TEST_CHUNK_SIZE as usize));
     }
+
+    #[test]
+    fn test_chunks_to_check_with_size_to_challenges() {
+        let metadata = FileMetadata::<32, TEST_CHUNK_SIZE, 1024> {
+            file_size: TEST_CHUNK_SIZE * 4 + 1,
+            fingerprint: Fingerprint::from([0u8; 32]),
+            owner: vec![],
+            location: vec![],
+            bucket_id: vec![],
+        };
+
+        // Matches the compile-time ratio when given the same value.
+        assert_eq!(
+            metadata.chunks_to_check_with(1024),
+            metadata.chunks_to_check()
+        );
+        assert_eq!(metadata.chunks_to_check_with(2048), 3);
+
+        // Still capped at the maximum number of chunks to check.
+        assert_eq!(metadata.chunks_to_check_with(1), MAX_CHUNKS_TO_CHECK);
+        assert_eq!(metadata.chunks_to_check_with(0), MAX_CHUNKS_TO_CHECK);
+    }
+
+    #[test]
+    fn test_validate_rejects_malformed_metadata() {
+        let metadata = FileMetadata::<32, TEST_CHUNK_SIZE, 1024> {
+            file_size: TEST_CHUNK_SIZE,
+            fingerprint: Fingerprint::from([1u8; 32]),
+            owner: vec![1],
+            location: vec![2],
+            bucket_id: vec![3],
+        };
+        assert_eq!(metadata.validate(), Ok(()));
+
+        let mut no_bucket = metadata.clone();
+        no_bucket.bucket_id = vec![];
+        assert_eq!(
+            no_bucket.validate(),
+            Err(FileMetadataError::InvalidBucketId)
+        );
+
+        let mut no_owner = metadata.clone();
+        no_owner.owner = vec![];
+        assert_eq!(no_owner.validate(), Err(FileMetadataError::InvalidOwner));
+
+        let mut no_location = metadata.clone();
+        no_location.location = vec![];
+        assert_eq!(
+            no_location.validate(),
+            Err(FileMetadataError::InvalidLocation)
+        );
+
+        let mut empty_file = metadata;
+        empty_file.file_size = 0;
+        assert_eq!(
+            empty_file.validate(),
+            Err(FileMetadataError::InvalidFileSize)
+        );
+    }
+
+    #[test]
+    fn test_validate_strict_rejects_metadata_that_cannot_be_stored() {
+        let metadata = FileMetadata::<32, TEST_CHUNK_SIZE, 1024>::validated(
+            vec![1],
+            vec![3u8; 32],
+            vec![2],
+            TEST_CHUNK_SIZE,
+            Fingerprint::from([1u8; 32]),
+        )
+        .unwrap();
+
+        let mut short_bucket = metadata.clone();
+        short_bucket.bucket_id = vec![3u8; 31];
+        assert_eq!(short_bucket.validate(), Ok(()));
+        assert_eq!(
+            short_bucket.validate_strict(),
+            Err(FileMetadataError::InvalidBucketId)
+        );
+
+        let mut long_location = metadata.clone();
+        long_location.location = vec![2u8; MAX_LOCATION_LENGTH + 1];
+        assert_eq!(
+            long_location.validate_strict(),
+            Err(FileMetadataError::InvalidLocation)
+        );
+        long_location.location.pop();
+        assert_eq!(long_location.validate_strict(), Ok(()));
+
+        let mut no_fingerprint = metadata.clone();
+        no_fingerprint.fingerprint = Fingerprint::default();
+        assert_eq!(
+            no_fingerprint.validate_strict(),
+            Err(FileMetadataError::InvalidFingerprint)
+        );
+
+        // Everything rejected by `validate` is rejected too.
+        let mut empty_file = metadata;
+        empty_file.file_size = 0;
+        assert_eq!(
+            empty_file.validate_strict(),
+            Err(FileMetadataError::InvalidFileSize)
+        );
+    }
 }
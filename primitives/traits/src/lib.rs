@@ -611,6 +611,13 @@ pub trait MutateChallengeableProvidersInterface {
         who: &Self::ProviderId,
         removed_trie_value: &Vec<u8>,
     ) -> DispatchResult;
+
+    /// Update the standing of a registered challengeable Provider after one of its proofs was accepted.
+    fn on_proof_accepted(who: &Self::ProviderId);
+
+    /// Update the standing of a registered challengeable Provider after it was marked as slashable,
+    /// for not submitting a proof before its challenge deadline.
+    fn on_provider_slashable(who: &Self::ProviderId);
 }
 
 /// A trait to read information about generic Providers, such as their ID, owner, root, stake, etc.
@@ -348,6 +348,14 @@ impl_runtime_apis! {
         fn decode_generic_apply_delta_event_info(encoded_event_info: Vec<u8>) -> Result<BucketId<Runtime>, GenericApplyDeltaEventInfoError> {
             FileSystem::decode_generic_apply_delta_event_info(encoded_event_info)
         }
+
+        fn query_bsp_confirm_chunks_to_prove_for_files(bsp_id: BackupStorageProviderId<Runtime>, file_keys: Vec<H256>) -> Vec<(H256, Result<Vec<ChunkId>, QueryConfirmChunksToProveForFilesError>)> {
+            FileSystem::query_confirm_chunks_to_prove_for_files(bsp_id, file_keys)
+        }
+
+        fn query_msp_confirm_chunks_to_prove_for_files(msp_id: MainStorageProviderId<Runtime>, file_keys: Vec<H256>) -> Vec<(H256, Result<Vec<ChunkId>, QueryConfirmChunksToProveForFilesError>)> {
+            FileSystem::query_confirm_chunks_to_prove_for_files(msp_id, file_keys)
+        }
     }
 
     impl pallet_payment_streams_runtime_api::PaymentStreamsApi<Block, ProviderIdFor<Runtime>, Balance, AccountId> for Runtime {
@@ -408,6 +416,10 @@ impl_runtime_apis! {
         fn get_next_deadline_tick(provider_id: &ProofsDealerProviderIdFor<Runtime>) -> Result<BlockNumber, GetNextDeadlineTickError> {
             ProofsDealer::get_next_deadline_tick(provider_id)
         }
+
+        fn get_file_size_to_challenges() -> u64 {
+            shp_constants::FILE_SIZE_TO_CHALLENGES
+        }
     }
 
 
@@ -424,6 +436,14 @@ impl_runtime_apis! {
             Providers::query_msp_id_of_bucket_id(bucket_id)
         }
 
+        fn query_bucket_size_and_data_limit(bucket_id: &BucketId<Runtime>) -> Result<(StorageDataUnit<Runtime>, StorageDataUnit<Runtime>), QueryBucketSizeAndDataLimitError> {
+            Providers::query_bucket_size_and_data_limit(bucket_id)
+        }
+
+        fn query_bucket_root(bucket_id: &BucketId<Runtime>) -> Result<H256, QueryBucketRootError> {
+            Providers::query_bucket_root(bucket_id)
+        }
+
         fn query_provider_multiaddresses(provider_id: &ProviderIdFor<Runtime>) -> Result<Multiaddresses<Runtime>, QueryProviderMultiaddressesError> {
             Providers::query_provider_multiaddresses(provider_id)
         }
@@ -467,5 +487,13 @@ impl_runtime_apis! {
         fn query_buckets_of_user_stored_by_msp(msp_id: &ProviderIdFor<Runtime>, user: &AccountId) -> Result<sp_runtime::Vec<BucketId<Runtime>>, QueryBucketsOfUserStoredByMspError> {
             Ok(sp_runtime::Vec::from_iter(Providers::query_buckets_of_user_stored_by_msp(msp_id, user)?))
         }
+
+        fn query_bucket_read_access(bucket_id: &BucketId<Runtime>, user: &AccountId) -> Result<BucketReadAccess<AccountId>, QueryBucketReadAccessError> {
+            Providers::query_bucket_read_access(bucket_id, user, |collection_id, who| {
+                pallet_nfts::Account::<Runtime>::iter_key_prefix((who, collection_id))
+                    .next()
+                    .is_some()
+            })
+        }
     }
 }
@@ -525,6 +525,7 @@ impl pallet_storage_providers::Config for Runtime {
     type SlashAmountPerMaxFileSize =
         runtime_params::dynamic_params::runtime_config::SlashAmountPerMaxFileSize;
     type StartingReputationWeight = ConstU32<1>;
+    type MaxReputationWeight = ConstU32<100>;
     type BspSignUpLockPeriod = BspSignUpLockPeriod;
     type MaxCommitmentSize = ConstU32<1000>;
     type ZeroSizeBucketFixedRate =
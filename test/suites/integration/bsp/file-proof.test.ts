@@ -0,0 +1,43 @@
+import assert, { deepStrictEqual, strictEqual } from "node:assert";
+import { describeBspNet, type EnrichedBspApi } from "../../../util";
+
+describeBspNet(
+  "BSP: Proves it holds a file on request",
+  ({ before, createBspApi, createUserApi, it }) => {
+    let userApi: EnrichedBspApi;
+    let bspApi: EnrichedBspApi;
+
+    before(async () => {
+      userApi = await createUserApi();
+      bspApi = await createBspApi();
+    });
+
+    it("Proof generated by the BSP verifies on another node", async () => {
+      const source = "res/whatsup.jpg";
+      const destination = "test/whatsup-file-proof.jpg";
+      const bucketName = "file-proof";
+
+      const { fileKey } = await userApi.file.createBucketAndSendNewStorageRequest(
+        source,
+        destination,
+        bucketName
+      );
+      await userApi.wait.bspVolunteer(1);
+      await bspApi.wait.fileStorageComplete(fileKey);
+
+      const proof = await bspApi.rpc.storagehubclient.generateFileProof(fileKey, [1, 0]);
+
+      const fingerprint = userApi.shConsts.TEST_ARTEFACTS[source].fingerprint;
+      const verified = await userApi.rpc.storagehubclient.verifyFileProof(proof, fingerprint);
+      strictEqual(verified.file_key.toString(), fileKey.toString());
+      deepStrictEqual(verified.chunk_ids.map((chunkId) => chunkId.toNumber()), [0, 1]);
+
+      // The proof is only valid for the fingerprint of its file.
+      const otherFingerprint = userApi.shConsts.TEST_ARTEFACTS["res/adolphus.jpg"].fingerprint;
+      await assert.rejects(
+        userApi.rpc.storagehubclient.verifyFileProof(proof, otherFingerprint),
+        /File proof is for fingerprint/
+      );
+    });
+  }
+);
@@ -0,0 +1,56 @@
+import { describeBspNet, registerToxic, shUser, type EnrichedBspApi } from "../../../util";
+
+describeBspNet(
+  "BSP: Storage request revoked mid-upload",
+  { initialised: false, networkConfig: "noisy" },
+  ({ before, it, createUserApi, createBspApi }) => {
+    let userApi: EnrichedBspApi;
+    let bspApi: EnrichedBspApi;
+
+    before(async () => {
+      userApi = await createUserApi();
+      bspApi = await createBspApi();
+    });
+
+    it("bsp deletes partial file when storage request is revoked halfway through upload", async () => {
+      const source = "res/whatsup.jpg";
+      const destination = "test/whatsup.jpg";
+      const bucketName = "revoked-mid-upload";
+
+      const { fileKey } = await userApi.file.createBucketAndSendNewStorageRequest(
+        source,
+        destination,
+        bucketName
+      );
+
+      //  use toxiproxy to close the connection after 50 KB, leaving the upload halfway through
+      await registerToxic({
+        type: "limit_data",
+        name: "limit_data",
+        toxicity: 1,
+        stream: "upstream",
+        attributes: {
+          bytes: 51200
+        }
+      });
+
+      // Wait for the BSP to submit the volunteer extrinsic
+      await userApi.wait.bspVolunteer();
+
+      // Revoke the storage request while the BSP only has part of the file
+      await userApi.block.seal({
+        calls: [userApi.tx.fileSystem.revokeStorageRequest(fileKey)],
+        signer: shUser
+      });
+      await userApi.assert.eventPresent("fileSystem", "StorageRequestRevoked");
+
+      await bspApi.assert.log({
+        searchString: "was revoked",
+        containerName: "docker-sh-bsp-1"
+      });
+
+      // The partial file should be removed from the BSP's file storage
+      await bspApi.wait.fileDeletionFromFileStorage(fileKey);
+    });
+  }
+);
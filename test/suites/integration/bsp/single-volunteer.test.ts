@@ -120,6 +120,17 @@ describeBspNet("Single BSP Volunteering", ({ before, createBspApi, it, createUse
           .isFileFound
     });
 
+    // The BSP reports all the chunks of the file as received
+    const uploadProgress = (
+      await bspApi.rpc.storagehubclient.uploadProgress(newStorageRequestDataBlob.fileKey)
+    ).unwrap();
+    assert(uploadProgress.state.isComplete, "Upload should be complete");
+    strictEqual(
+      uploadProgress.received_chunks.toBigInt(),
+      uploadProgress.expected_chunks.toBigInt()
+    );
+    assert(uploadProgress.last_chunk_at.isSome, "Last chunk timestamp should be set");
+
     // Wait for the BSP confirm extrinsic to be submitted to the TX pool
     await userApi.wait.bspStored({
       expectedExts: 1,
@@ -141,6 +152,13 @@ describeBspNet("Single BSP Volunteering", ({ before, createBspApi, it, createUse
 
     strictEqual(bspConfirmRes_bspId.toHuman(), userApi.shConsts.TEST_ARTEFACTS[source].fingerprint);
 
+    await waitFor({
+      lambda: async () =>
+        (
+          await bspApi.rpc.storagehubclient.uploadProgress(newStorageRequestDataBlob.fileKey)
+        ).unwrap().state.isConfirmed
+    });
+
     // TODO: Investigate what needs to be added to poll
     await sleep(1000); // to avoid extFailure- IssueRequest already registered for file
     await waitFor({
@@ -0,0 +1,170 @@
+import assert, { strictEqual } from "node:assert";
+import { describeMspNet, shUser, waitFor, type EnrichedBspApi } from "../../../util";
+
+describeMspNet(
+  "MSP processes file deletion requests",
+  ({ before, createMsp1Api, it, createUserApi }) => {
+    let userApi: EnrichedBspApi;
+    let mspApi: EnrichedBspApi;
+    const source = "res/whatsup.jpg";
+    const destination = "test/whatsup-delete.jpg";
+    const bucketName = "delete-file-bucket";
+    let bucketId: string;
+    let fileKey: string;
+
+    before(async () => {
+      userApi = await createUserApi();
+      const maybeMspApi = await createMsp1Api();
+      assert(maybeMspApi, "MSP API not available");
+      mspApi = maybeMspApi;
+    });
+
+    it("MSP stores a file", async () => {
+      const valueProps = await userApi.call.storageProvidersApi.queryValuePropositionsForMsp(
+        userApi.shConsts.DUMMY_MSP_ID
+      );
+      const newBucketEvent = await userApi.createBucket(bucketName, valueProps[0].id);
+      const newBucketEventDataBlob =
+        userApi.events.fileSystem.NewBucket.is(newBucketEvent) && newBucketEvent.data;
+      assert(newBucketEventDataBlob, "NewBucket event data does not match expected type");
+      bucketId = newBucketEventDataBlob.bucketId.toString();
+
+      const {
+        file_metadata: { location, fingerprint, file_size }
+      } = await userApi.rpc.storagehubclient.loadFileInStorage(
+        source,
+        destination,
+        userApi.shConsts.NODE_INFOS.user.AddressId,
+        bucketId
+      );
+      await userApi.block.seal({
+        calls: [
+          userApi.tx.fileSystem.issueStorageRequest(
+            bucketId,
+            location,
+            fingerprint,
+            file_size,
+            userApi.shConsts.DUMMY_MSP_ID,
+            [userApi.shConsts.NODE_INFOS.user.expectedPeerId],
+            {
+              Basic: null
+            }
+          )
+        ],
+        signer: shUser
+      });
+
+      const { event } = await userApi.assert.eventPresent("fileSystem", "NewStorageRequest");
+      const newStorageRequestDataBlob =
+        userApi.events.fileSystem.NewStorageRequest.is(event) && event.data;
+      assert(newStorageRequestDataBlob, "Event doesn't match NewStorageRequest type");
+      fileKey = newStorageRequestDataBlob.fileKey.toString();
+
+      await mspApi.wait.fileStorageComplete(fileKey);
+      await userApi.wait.mspResponseInTxPool();
+      await userApi.block.seal();
+      await userApi.assert.eventPresent("fileSystem", "MspAcceptedStorageRequest");
+
+      await waitFor({
+        lambda: async () =>
+          (await mspApi.rpc.storagehubclient.isFileInForest(bucketId, fileKey)).isTrue
+      });
+    });
+
+    it("Deleting a stored file removes it from the Forest and the File Storage", async () => {
+      const bucketOption = userApi.createType("Option<H256>", bucketId);
+      const fileMetadata = (
+        await mspApi.rpc.storagehubclient.getFileMetadata(bucketOption, fileKey)
+      ).unwrap();
+      const rootBefore = (await mspApi.rpc.storagehubclient.getForestRoot(bucketId)).unwrap();
+
+      await userApi.block.seal({
+        calls: [
+          userApi.tx.fileSystem.deleteFile(
+            bucketId,
+            fileKey,
+            fileMetadata.location,
+            fileMetadata.file_size,
+            fileMetadata.fingerprint,
+            null
+          )
+        ],
+        signer: shUser
+      });
+
+      // The MSP task submits an inclusion proof for the pending deletion request.
+      await userApi.wait.mspPendingFileDeletionRequestSubmitProof(1);
+      await userApi.block.seal();
+      const { event } = await userApi.assert.eventPresent(
+        "fileSystem",
+        "ProofSubmittedForPendingFileDeletionRequest"
+      );
+      const proofSubmittedDataBlob =
+        userApi.events.fileSystem.ProofSubmittedForPendingFileDeletionRequest.is(event) &&
+        event.data;
+      assert(
+        proofSubmittedDataBlob,
+        "Event doesn't match ProofSubmittedForPendingFileDeletionRequest type"
+      );
+      strictEqual(proofSubmittedDataBlob.fileKey.toString(), fileKey);
+      strictEqual(proofSubmittedDataBlob.proofOfInclusion.isTrue, true);
+
+      // The file key is removed from the bucket's Forest, and its data once the proof is finalised.
+      await waitFor({
+        lambda: async () =>
+          (await mspApi.rpc.storagehubclient.isFileInForest(bucketId, fileKey)).isFalse
+      });
+      const rootAfter = (await mspApi.rpc.storagehubclient.getForestRoot(bucketId)).unwrap();
+      assert(!rootAfter.eq(rootBefore), "Bucket root should change once the file is deleted");
+      await mspApi.wait.fileDeletionFromFileStorage(fileKey);
+    });
+
+    it("Deleting a file the MSP does not store leaves its Forest untouched", async () => {
+      // The file is only loaded in the user's File Storage, no storage request is issued for it.
+      const {
+        file_key: missingFileKey,
+        file_metadata: { location, fingerprint, file_size }
+      } = await userApi.rpc.storagehubclient.loadFileInStorage(
+        "res/smile.jpg",
+        "test/never-stored.jpg",
+        userApi.shConsts.NODE_INFOS.user.AddressId,
+        bucketId
+      );
+      const rootBefore = (await mspApi.rpc.storagehubclient.getForestRoot(bucketId)).unwrap();
+
+      await userApi.block.seal({
+        calls: [
+          userApi.tx.fileSystem.deleteFile(
+            bucketId,
+            missingFileKey,
+            location,
+            file_size,
+            fingerprint,
+            null
+          )
+        ],
+        signer: shUser
+      });
+
+      // The MSP task submits a non-inclusion proof, with which the runtime drops the request.
+      await userApi.wait.mspPendingFileDeletionRequestSubmitProof(1);
+      await userApi.block.seal();
+      const { event } = await userApi.assert.eventPresent(
+        "fileSystem",
+        "ProofSubmittedForPendingFileDeletionRequest"
+      );
+      const proofSubmittedDataBlob =
+        userApi.events.fileSystem.ProofSubmittedForPendingFileDeletionRequest.is(event) &&
+        event.data;
+      assert(
+        proofSubmittedDataBlob,
+        "Event doesn't match ProofSubmittedForPendingFileDeletionRequest type"
+      );
+      strictEqual(proofSubmittedDataBlob.fileKey.toString(), missingFileKey.toString());
+      strictEqual(proofSubmittedDataBlob.proofOfInclusion.isFalse, true);
+
+      const rootAfter = (await mspApi.rpc.storagehubclient.getForestRoot(bucketId)).unwrap();
+      assert(rootAfter.eq(rootBefore), "Bucket root should not change");
+    });
+  }
+);
@@ -1,15 +1,27 @@
 import assert, { equal, strictEqual } from "node:assert";
-import { describeMspNet, type EnrichedBspApi, type SqlClient } from "../../../util";
+import {
+  bob,
+  describeMspNet,
+  shUser,
+  waitFor,
+  type EnrichedBspApi,
+  type SqlClient
+} from "../../../util";
+import { MSP_CHARGING_PERIOD } from "../../../util/bspNet/consts";
 
 describeMspNet(
   "Indexer Sanity Checks",
   { initialised: false, indexer: true },
-  ({ before, it, createUserApi, createSqlClient }) => {
+  ({ before, it, createUserApi, createMsp1Api, createSqlClient }) => {
     let userApi: EnrichedBspApi;
+    let mspApi: EnrichedBspApi;
     let sql: SqlClient;
 
     before(async () => {
       userApi = await createUserApi();
+      const maybeMspApi = await createMsp1Api();
+      assert(maybeMspApi, "MSP API not available");
+      mspApi = maybeMspApi;
       sql = createSqlClient();
     });
 
@@ -40,6 +52,42 @@ describeMspNet(
       );
     });
 
+    it("msp table stores value propositions that decode to the on-chain ones", async () => {
+      const msps = await sql`
+            SELECT value_prop, value_prop_encoded
+            FROM msp
+            WHERE account = ${userApi.shConsts.NODE_INFOS.msp1.AddressId};
+        `;
+      strictEqual(msps.length, 1, "MSP should be indexed");
+      const { value_prop: valueProp, value_prop_encoded: valuePropEncoded } = msps[0];
+
+      const onChainValueProps = await userApi.call.storageProvidersApi.queryValuePropositionsForMsp(
+        userApi.shConsts.DUMMY_MSP_ID
+      );
+      const onChainValueProp = onChainValueProps.find((vp) => vp.id.toHex() === valueProp.id);
+      assert(onChainValueProp, "Indexed value proposition should be one of the MSP's");
+
+      // The JSON value proposition holds every field of the on-chain one.
+      strictEqual(
+        valueProp.price_per_giga_unit_of_data_per_block,
+        onChainValueProp.value_prop.price_per_giga_unit_of_data_per_block.toString()
+      );
+      strictEqual(valueProp.commitment, onChainValueProp.value_prop.commitment.toHex());
+      strictEqual(
+        valueProp.bucket_data_limit,
+        onChainValueProp.value_prop.bucket_data_limit.toNumber()
+      );
+      strictEqual(valueProp.available, onChainValueProp.value_prop.available.isTrue);
+
+      // The SCALE-encoded value proposition decodes to the on-chain one.
+      assert(valuePropEncoded, "Encoded value proposition should be stored");
+      const decodedValueProp = userApi.createType("ValuePropositionWithId", valuePropEncoded);
+      assert(
+        decodedValueProp.eq(onChainValueProp),
+        "Encoded value proposition should decode to the on-chain one"
+      );
+    });
+
     it("standard network setup populates table", async () => {
       const bsps = await sql`
             SELECT COUNT(*)
@@ -85,5 +133,266 @@ describeMspNet(
         "Bucket name should match the one created"
       );
     });
+
+    it("file is marked as deletion pending when its deletion is queued", async () => {
+      const source = "res/whatsup.jpg";
+      const destination = "test/whatsup.jpg";
+      const bucketName = "deletion-pending-bucket";
+
+      const valueProps = await userApi.call.storageProvidersApi.queryValuePropositionsForMsp(
+        userApi.shConsts.DUMMY_MSP_ID
+      );
+      const newBucketEvent = await userApi.createBucket(bucketName, valueProps[0].id);
+      const newBucketEventDataBlob =
+        userApi.events.fileSystem.NewBucket.is(newBucketEvent) && newBucketEvent.data;
+      assert(newBucketEventDataBlob, "NewBucket event data does not match expected type");
+      const bucketId = newBucketEventDataBlob.bucketId.toString();
+
+      const {
+        file_metadata: { location, fingerprint, file_size }
+      } = await userApi.rpc.storagehubclient.loadFileInStorage(
+        source,
+        destination,
+        userApi.shConsts.NODE_INFOS.user.AddressId,
+        bucketId
+      );
+
+      await userApi.block.seal({
+        calls: [
+          userApi.tx.fileSystem.issueStorageRequest(
+            bucketId,
+            location,
+            fingerprint,
+            file_size,
+            userApi.shConsts.DUMMY_MSP_ID,
+            [userApi.shConsts.NODE_INFOS.user.expectedPeerId],
+            {
+              Basic: null
+            }
+          )
+        ],
+        signer: shUser
+      });
+      const { event } = await userApi.assert.eventPresent("fileSystem", "NewStorageRequest");
+      const newStorageRequestDataBlob =
+        userApi.events.fileSystem.NewStorageRequest.is(event) && event.data;
+      assert(
+        newStorageRequestDataBlob,
+        "NewStorageRequest event data does not match expected type"
+      );
+      const fileKey = newStorageRequestDataBlob.fileKey.toString();
+
+      await mspApi.wait.fileStorageComplete(fileKey);
+      await userApi.wait.mspResponseInTxPool();
+      await userApi.block.seal();
+      await waitFor({
+        lambda: async () =>
+          (await mspApi.rpc.storagehubclient.isFileInForest(bucketId, fileKey)).isTrue
+      });
+
+      // Deleting the file without a proof of inclusion makes the MSP provide it, which then
+      // queues the priority challenge to remove the file from the BSPs.
+      await userApi.block.seal({
+        calls: [
+          userApi.tx.fileSystem.deleteFile(
+            bucketId,
+            fileKey,
+            location,
+            file_size,
+            fingerprint,
+            null
+          )
+        ],
+        signer: shUser
+      });
+      await userApi.wait.mspPendingFileDeletionRequestSubmitProof(1);
+      await userApi.block.seal();
+      await userApi.assert.eventPresent("fileSystem", "PriorityChallengeForFileDeletionQueued");
+      const deletionQueuedAt = (await userApi.rpc.chain.getHeader()).number.toNumber();
+
+      await waitFor({
+        lambda: async () => {
+          const files = await sql`
+                SELECT step, deletion_queued_at_block
+                FROM file
+                WHERE file_key = decode(${fileKey.slice(2)}, 'hex');
+            `;
+          return files.length === 1 && files[0].deletion_queued_at_block !== null;
+        }
+      });
+
+      const files = await sql`
+            SELECT step, deletion_queued_at_block
+            FROM file
+            WHERE file_key = decode(${fileKey.slice(2)}, 'hex');
+        `;
+      strictEqual(files[0].step, 2, "File should be marked as deletion pending");
+      strictEqual(
+        Number(files[0].deletion_queued_at_block),
+        deletionQueuedAt,
+        "File should record the block at which its deletion was queued"
+      );
+    });
+
+    it("bucket total size is refreshed when its privacy is updated", async () => {
+      const source = "res/adolphus.jpg";
+      const destination = "test/adolphus.jpg";
+      const bucketName = "total-size-bucket";
+
+      const valueProps = await userApi.call.storageProvidersApi.queryValuePropositionsForMsp(
+        userApi.shConsts.DUMMY_MSP_ID
+      );
+      const newBucketEvent = await userApi.createBucket(bucketName, valueProps[0].id);
+      const newBucketEventDataBlob =
+        userApi.events.fileSystem.NewBucket.is(newBucketEvent) && newBucketEvent.data;
+      assert(newBucketEventDataBlob, "NewBucket event data does not match expected type");
+      const bucketId = newBucketEventDataBlob.bucketId.toString();
+
+      const {
+        file_metadata: { location, fingerprint, file_size }
+      } = await userApi.rpc.storagehubclient.loadFileInStorage(
+        source,
+        destination,
+        userApi.shConsts.NODE_INFOS.user.AddressId,
+        bucketId
+      );
+
+      await userApi.block.seal({
+        calls: [
+          userApi.tx.fileSystem.issueStorageRequest(
+            bucketId,
+            location,
+            fingerprint,
+            file_size,
+            userApi.shConsts.DUMMY_MSP_ID,
+            [userApi.shConsts.NODE_INFOS.user.expectedPeerId],
+            {
+              Basic: null
+            }
+          )
+        ],
+        signer: shUser
+      });
+      const { event } = await userApi.assert.eventPresent("fileSystem", "NewStorageRequest");
+      const newStorageRequestDataBlob =
+        userApi.events.fileSystem.NewStorageRequest.is(event) && event.data;
+      assert(
+        newStorageRequestDataBlob,
+        "NewStorageRequest event data does not match expected type"
+      );
+      const fileKey = newStorageRequestDataBlob.fileKey.toString();
+
+      // Only files whose storage request was fulfilled count towards the size of the bucket.
+      await mspApi.wait.fileStorageComplete(fileKey);
+      await userApi.wait.storageRequestNotOnChain(fileKey);
+
+      await userApi.block.seal({
+        calls: [userApi.tx.fileSystem.updateBucketPrivacy(bucketId, true)],
+        signer: shUser
+      });
+      await userApi.assert.eventPresent("fileSystem", "BucketPrivacyUpdated");
+
+      await waitFor({
+        lambda: async () => {
+          const buckets = await sql`
+                SELECT private, total_size
+                FROM bucket
+                WHERE name = ${bucketName};
+            `;
+          return buckets.length === 1 && buckets[0].private === true;
+        }
+      });
+
+      const buckets = await sql`
+            SELECT total_size
+            FROM bucket
+            WHERE name = ${bucketName};
+        `;
+      strictEqual(
+        BigInt(buckets[0].total_size),
+        userApi.shConsts.TEST_ARTEFACTS[source].size,
+        "Bucket total size should be the size of its stored file"
+      );
+    });
+
+    it("payment streams of a user without funds are marked as user insolvent", async () => {
+      const userAccount = userApi.shConsts.NODE_INFOS.user.AddressId;
+
+      // Another user with a payment stream with the MSP, which has to stay active.
+      await userApi.block.seal({
+        calls: [
+          userApi.tx.sudo.sudo(
+            userApi.tx.paymentStreams.createFixedRatePaymentStream(
+              userApi.shConsts.DUMMY_MSP_ID,
+              bob.address,
+              1
+            )
+          )
+        ]
+      });
+      await userApi.assert.eventPresent("paymentStreams", "FixedRatePaymentStreamCreated");
+
+      // Raise the rate of the user's payment stream with the MSP, then leave the user only enough
+      // funds to pay for a single tick of it.
+      const existentialDeposit = userApi.consts.balances.existentialDeposit;
+      await userApi.block.seal({
+        calls: [
+          userApi.tx.sudo.sudo(
+            userApi.tx.paymentStreams.updateFixedRatePaymentStream(
+              userApi.shConsts.DUMMY_MSP_ID,
+              userAccount,
+              existentialDeposit.muln(10)
+            )
+          )
+        ]
+      });
+      const paymentStream = (
+        await userApi.query.paymentStreams.fixedRatePaymentStreams(
+          userApi.shConsts.DUMMY_MSP_ID,
+          userAccount
+        )
+      ).unwrap();
+      await userApi.block.seal({
+        calls: [
+          userApi.tx.sudo.sudo(userApi.tx.balances.forceSetBalance(userAccount, paymentStream.rate))
+        ]
+      });
+
+      // The first charge the user can't pay flags its payment stream as without funds, and the
+      // first charge after the grace period marks the user as without funds.
+      const gracePeriod = userApi.consts.paymentStreams.newStreamDeposit.toNumber();
+      const currentBlockNumber = (await userApi.rpc.chain.getHeader()).number.toNumber();
+      for (const fromBlock of [currentBlockNumber, currentBlockNumber + gracePeriod]) {
+        await userApi.block.skipTo(
+          fromBlock + MSP_CHARGING_PERIOD - (fromBlock % MSP_CHARGING_PERIOD)
+        );
+        await userApi.assert.extrinsicPresent({
+          module: "paymentStreams",
+          method: "chargeMultipleUsersPaymentStreams",
+          checkTxPool: true
+        });
+        await userApi.block.seal();
+      }
+      await userApi.assert.eventPresent("paymentStreams", "UserWithoutFunds");
+
+      await waitFor({
+        lambda: async () => {
+          const streams = await sql`
+                SELECT status
+                FROM paymentstream
+                WHERE account = ${userAccount};
+            `;
+          return streams.length > 0 && streams.every((s) => s.status === 1);
+        }
+      });
+
+      const otherStreams = await sql`
+            SELECT status
+            FROM paymentstream
+            WHERE account = ${bob.address};
+        `;
+      strictEqual(otherStreams.length, 1, "The other user should have a payment stream");
+      strictEqual(otherStreams[0].status, 0, "Payment streams of other users should stay active");
+    });
   }
 );
@@ -10,14 +10,16 @@ import {
   ShConsts,
   describeMspNet,
   shUser,
-  type EnrichedBspApi
+  type EnrichedBspApi,
+  type SqlClient
 } from "../../../util";
 
 describeMspNet(
   "MSP moves bucket to another MSP",
   { initialised: false, indexer: true },
-  ({ before, createMsp1Api, createMsp2Api, it, createUserApi }) => {
+  ({ before, createMsp1Api, createMsp2Api, it, createUserApi, createSqlClient }) => {
     let userApi: EnrichedBspApi;
+    let sql: SqlClient;
     let msp1Api: EnrichedBspApi;
     let msp2Api: EnrichedBspApi;
     const source = ["res/whatsup.jpg", "res/adolphus.jpg", "res/smile.jpg"];
@@ -28,6 +30,7 @@ describeMspNet(
 
     before(async () => {
       userApi = await createUserApi();
+      sql = createSqlClient();
       const maybeMsp1Api = await createMsp1Api();
       if (maybeMsp1Api) {
         msp1Api = maybeMsp1Api;
@@ -366,5 +369,41 @@ describeMspNet(
         delay: 1000
       });
     });
+
+    it("Indexer records the bucket move transitions", async () => {
+      // Finalise the block with the `MoveBucketAccepted` event so that it gets indexed.
+      await userApi.block.seal({ finaliseBlock: true });
+
+      await waitFor({
+        lambda: async () => {
+          const bucketMoves = await sql`
+            SELECT status, resolved_at_block
+            FROM bucket_move
+            WHERE onchain_bucket_id = decode(${bucketId.slice(2)}, 'hex');
+          `;
+          return bucketMoves.length === 1 && bucketMoves[0].status === 1;
+        },
+        iterations: 30,
+        delay: 1000
+      });
+
+      const bucketMoves = await sql`
+        SELECT old_msp_id, new_msp_id, requested_at_block, resolved_at_block
+        FROM bucket_move
+        WHERE onchain_bucket_id = decode(${bucketId.slice(2)}, 'hex');
+      `;
+
+      strictEqual(bucketMoves.length, 1, "There should be a single bucket move");
+      const bucketMove = bucketMoves[0];
+      assert(bucketMove.old_msp_id !== null, "Bucket move should record the source MSP");
+      assert(
+        bucketMove.old_msp_id !== bucketMove.new_msp_id,
+        "Source and destination MSPs should differ"
+      );
+      assert(
+        Number(bucketMove.resolved_at_block) > Number(bucketMove.requested_at_block),
+        "Bucket move should be accepted after it was requested"
+      );
+    });
   }
 );
@@ -0,0 +1,110 @@
+import assert, { strictEqual } from "node:assert";
+import { describeMspNet, mspKey, shUser, type EnrichedBspApi } from "../../../util";
+
+describeMspNet(
+  "MSP batches rejections of storage requests",
+  { initialised: false },
+  ({ before, createMsp1Api, it, createUserApi }) => {
+    let userApi: EnrichedBspApi;
+    let mspApi: EnrichedBspApi;
+
+    before(async () => {
+      userApi = await createUserApi();
+      const maybeMspApi = await createMsp1Api();
+      assert(maybeMspApi, "MSP API not available");
+      mspApi = maybeMspApi;
+    });
+
+    it("Rejections for files in multiple buckets are sent in a single extrinsic", async () => {
+      const source = ["res/whatsup.jpg", "res/adolphus.jpg"];
+      const destination = ["test/whatsup.jpg", "test/adolphus.jpg"];
+      const bucketNames = ["reject-batched-0", "reject-batched-1"];
+
+      // Add a value proposition whose buckets can't hold any of the files.
+      await userApi.block.seal({
+        calls: [userApi.tx.providers.addValueProp(1, "Tiny buckets", 1)],
+        signer: mspKey
+      });
+      const { event: valuePropAddedEvent } = await userApi.assert.eventPresent(
+        "providers",
+        "ValuePropAdded"
+      );
+      const valuePropAddedDataBlob =
+        userApi.events.providers.ValuePropAdded.is(valuePropAddedEvent) &&
+        valuePropAddedEvent.data;
+      assert(valuePropAddedDataBlob, "ValuePropAdded event data does not match expected type");
+
+      const txs = [];
+      for (let i = 0; i < source.length; i++) {
+        const newBucketEvent = await userApi.createBucket(
+          bucketNames[i],
+          valuePropAddedDataBlob.valuePropId
+        );
+        const newBucketEventDataBlob =
+          userApi.events.fileSystem.NewBucket.is(newBucketEvent) && newBucketEvent.data;
+        assert(newBucketEventDataBlob, "NewBucket event data does not match expected type");
+
+        const {
+          file_metadata: { location, fingerprint, file_size }
+        } = await userApi.rpc.storagehubclient.loadFileInStorage(
+          source[i],
+          destination[i],
+          userApi.shConsts.NODE_INFOS.user.AddressId,
+          newBucketEventDataBlob.bucketId
+        );
+
+        txs.push(
+          userApi.tx.fileSystem.issueStorageRequest(
+            newBucketEventDataBlob.bucketId,
+            location,
+            fingerprint,
+            file_size,
+            userApi.shConsts.DUMMY_MSP_ID,
+            [userApi.shConsts.NODE_INFOS.user.expectedPeerId],
+            {
+              Basic: null
+            }
+          )
+        );
+      }
+      await userApi.block.seal({ calls: txs, signer: shUser });
+
+      const storageRequestEvents = await userApi.assert.eventMany(
+        "fileSystem",
+        "NewStorageRequest"
+      );
+      strictEqual(storageRequestEvents.length, source.length);
+      const fileKeys = storageRequestEvents.map((event) => {
+        const dataBlob =
+          userApi.events.fileSystem.NewStorageRequest.is(event.event) && event.event.data;
+        assert(dataBlob, "Event doesn't match NewStorageRequest type");
+        return dataBlob.fileKey.toString();
+      });
+
+      // Both rejections are queued and sent in a single extrinsic.
+      await userApi.wait.mspResponseInTxPool();
+      const responses = await userApi.assert.extrinsicPresent({
+        module: "fileSystem",
+        method: "mspRespondStorageRequestsMultipleBuckets",
+        checkTxPool: true
+      });
+      strictEqual(responses.length, 1, "MSP should respond to both buckets in one extrinsic");
+      await userApi.block.seal();
+
+      const rejectedEvents = await userApi.assert.eventMany("fileSystem", "StorageRequestRejected");
+      strictEqual(rejectedEvents.length, fileKeys.length);
+      for (const { event } of rejectedEvents) {
+        const dataBlob = userApi.events.fileSystem.StorageRequestRejected.is(event) && event.data;
+        assert(dataBlob, "Event doesn't match StorageRequestRejected type");
+        assert(fileKeys.includes(dataBlob.fileKey.toString()), "Unexpected file key rejected");
+        assert(dataBlob.reason.isReachedBucketDataLimit, "Unexpected rejection reason");
+      }
+
+      // Rejected files are not kept by the MSP.
+      for (const fileKey of fileKeys) {
+        const result = await mspApi.rpc.storagehubclient.isFileInFileStorage(fileKey);
+        assert(!result.isFileFound, "Rejected file should not be in the MSP's file storage");
+      }
+    });
+  }
+);
@@ -0,0 +1,65 @@
+import assert, { strictEqual } from "node:assert";
+import { describeBspNet, waitFor, type EnrichedBspApi } from "../../../util";
+
+describeBspNet("User: Upload files through RPC", ({ before, createBspApi, createUserApi, it }) => {
+  let userApi: EnrichedBspApi;
+  let bspApi: EnrichedBspApi;
+
+  before(async () => {
+    userApi = await createUserApi();
+    bspApi = await createBspApi();
+  });
+
+  it("uploadFile issues the storage request and sends the file to the BSP", async () => {
+    const source = "res/whatsup.jpg";
+    const destination = "test/whatsup-upload-file.jpg";
+    const bucketName = "upload-file-rpc";
+
+    const newBucketEvent = await userApi.createBucket(bucketName);
+    const newBucketEventDataBlob =
+      userApi.events.fileSystem.NewBucket.is(newBucketEvent) && newBucketEvent.data;
+    assert(newBucketEventDataBlob, "NewBucket event data does not match expected type");
+
+    const { file_key: fileKey, file_metadata: fileMetadata } =
+      await userApi.rpc.storagehubclient.uploadFile(
+        source,
+        destination,
+        newBucketEventDataBlob.bucketId,
+        1
+      );
+    strictEqual(fileMetadata.location.toHuman(), destination);
+    strictEqual(
+      fileMetadata.fingerprint.toString(),
+      userApi.shConsts.TEST_ARTEFACTS[source].fingerprint
+    );
+
+    // The user node drains its upload queue and issues the storage request by itself.
+    await userApi.wait.waitForTxInPool({
+      module: "fileSystem",
+      method: "issueStorageRequest",
+      checkQuantity: 1,
+      shouldSeal: true,
+      expectedEvent: "NewStorageRequest"
+    });
+
+    const { event } = await userApi.assert.eventPresent("fileSystem", "NewStorageRequest");
+    const newStorageRequestDataBlob =
+      userApi.events.fileSystem.NewStorageRequest.is(event) && event.data;
+    assert(newStorageRequestDataBlob, "NewStorageRequest event data does not match expected type");
+    strictEqual(newStorageRequestDataBlob.fileKey.toString(), fileKey.toString());
+    strictEqual(
+      newStorageRequestDataBlob.who.toString(),
+      userApi.shConsts.NODE_INFOS.user.AddressId
+    );
+    strictEqual(newStorageRequestDataBlob.location.toHuman(), destination);
+    strictEqual(newStorageRequestDataBlob.peerIds.length, 1);
+
+    // The BSP volunteers and receives the file from the user node.
+    await userApi.wait.bspVolunteer(1);
+    await waitFor({
+      lambda: async () =>
+        (await bspApi.rpc.storagehubclient.isFileInFileStorage(fileKey)).isFileFound
+    });
+    await userApi.wait.bspStored({ expectedExts: 1 });
+  });
+});
@@ -121,6 +121,16 @@ export const rpcDefinitions = {
       ],
       type: "GetFileFromFileStorageResult"
     },
+    uploadProgress: {
+      description: "Get the progress of a file being uploaded to this node.",
+      params: [
+        {
+          name: "file_key",
+          type: "H256"
+        }
+      ],
+      type: "Option<UploadProgress>"
+    },
     getFileMetadata: {
       description: "Get the metadata of a file from the Forest storage.",
       params: [
@@ -199,6 +209,36 @@ export const rpcDefinitions = {
       ],
       type: "Vec<u8>"
     },
+    generateFileProof: {
+      description:
+        "Generate a SCALE-encoded proof that this node holds some chunks of a file. Rate limited.",
+      params: [
+        {
+          name: "file_key",
+          type: "H256"
+        },
+        {
+          name: "chunk_ids",
+          type: "Vec<u64>"
+        }
+      ],
+      type: "Bytes"
+    },
+    verifyFileProof: {
+      description:
+        "Verify a SCALE-encoded file proof against the fingerprint of its file.",
+      params: [
+        {
+          name: "proof",
+          type: "Bytes"
+        },
+        {
+          name: "fingerprint",
+          type: "H256"
+        }
+      ],
+      type: "VerifiedFileProof"
+    },
     insertBcsvKeys: {
       description: "Generate and insert new keys of type BCSV into the keystore.",
       params: [
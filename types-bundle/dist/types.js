@@ -46,6 +46,19 @@ export const ALL_TYPES = {
   Key: "H256",
   RandomnessOutput: "H256",
   TrieRemoveMutation: "Null",
+  UploadState: {
+    _enum: ["AwaitingVolunteer", "Receiving", "Complete", "Confirmed", "Rejected"]
+  },
+  UploadProgress: {
+    expected_chunks: "u64",
+    received_chunks: "u64",
+    last_chunk_at: "Option<u64>",
+    state: "UploadState"
+  },
+  VerifiedFileProof: {
+    file_key: "H256",
+    chunk_ids: "Vec<u64>"
+  },
   CheckpointChallenge: {
     file_key: "H256",
     should_remove_file: "bool"
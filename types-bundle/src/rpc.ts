@@ -25,6 +25,29 @@ export const rpcDefinitions: Record<string, Record<string, DefinitionRpc | Defin
       ],
       type: "LoadFileInStorageResult"
     },
+    uploadFile: {
+      description:
+        "Load a file in the local storage and upload it to the network, issuing its storage request.",
+      params: [
+        {
+          name: "file_path",
+          type: "String"
+        },
+        {
+          name: "location",
+          type: "String"
+        },
+        {
+          name: "bucket_id",
+          type: "H256"
+        },
+        {
+          name: "replication_target",
+          type: "Option<u32>"
+        }
+      ],
+      type: "LoadFileInStorageResult"
+    },
     removeFilesFromFileStorage: {
       description:
         "Remove a list of files from the file storage. Useful when doing manual maintenance.",
@@ -123,6 +146,16 @@ export const rpcDefinitions: Record<string, Record<string, DefinitionRpc | Defin
       ],
       type: "GetFileFromFileStorageResult"
     },
+    uploadProgress: {
+      description: "Get the progress of a file being uploaded to this node.",
+      params: [
+        {
+          name: "file_key",
+          type: "H256"
+        }
+      ],
+      type: "Option<UploadProgress>"
+    },
     getFileMetadata: {
       description: "Get the metadata of a file from the Forest storage.",
       params: [
@@ -201,6 +234,36 @@ export const rpcDefinitions: Record<string, Record<string, DefinitionRpc | Defin
       ],
       type: "Vec<u8>"
     },
+    generateFileProof: {
+      description:
+        "Generate a SCALE-encoded proof that this node holds some chunks of a file. Rate limited.",
+      params: [
+        {
+          name: "file_key",
+          type: "H256"
+        },
+        {
+          name: "chunk_ids",
+          type: "Vec<u64>"
+        }
+      ],
+      type: "Bytes"
+    },
+    verifyFileProof: {
+      description:
+        "Verify a SCALE-encoded file proof against the fingerprint of its file.",
+      params: [
+        {
+          name: "proof",
+          type: "Bytes"
+        },
+        {
+          name: "fingerprint",
+          type: "H256"
+        }
+      ],
+      type: "VerifiedFileProof"
+    },
     insertBcsvKeys: {
       description: "Generate and insert new keys of type BCSV into the keystore.",
       params: [
@@ -173,6 +173,11 @@ const PROOFS_DEALER_V1: Record<string, DefinitionCall> = {
       }
     ],
     type: "Result<BlockNumber, GetNextDeadlineTickError>"
+  },
+  get_file_size_to_challenges: {
+    description: "Get the file size (in bytes) for which a single chunk of a file is challenged.",
+    params: [],
+    type: "u64"
   }
 };
 
@@ -252,6 +257,26 @@ const STORAGE_PROVIDERS_V1: Record<string, DefinitionCall> = {
     ],
     type: "Result<ProviderId, QueryMspIdOfBucketIdError>"
   },
+  query_bucket_size_and_data_limit: {
+    description: "Query the current size of a bucket and the data limit of its value proposition.",
+    params: [
+      {
+        name: "bucketId",
+        type: "H256"
+      }
+    ],
+    type: "Result<(StorageDataUnit, StorageDataUnit), QueryBucketSizeAndDataLimitError>"
+  },
+  query_bucket_root: {
+    description: "Query the current on-chain root of a bucket's Forest.",
+    params: [
+      {
+        name: "bucketId",
+        type: "H256"
+      }
+    ],
+    type: "Result<H256, QueryBucketRootError>"
+  },
   query_provider_multiaddresses: {
     description: "Query the provider's multiaddresses.",
     params: [
@@ -315,6 +340,20 @@ const STORAGE_PROVIDERS_V1: Record<string, DefinitionCall> = {
       }
     ],
     type: "Result<Vec<H256>, QueryBucketsOfUserStoredByMspError>"
+  },
+  query_bucket_read_access: {
+    description: "Query what determines whether a user can read the files of a bucket.",
+    params: [
+      {
+        name: "bucketId",
+        type: "BucketId"
+      },
+      {
+        name: "user",
+        type: "AccountId"
+      }
+    ],
+    type: "Result<BucketReadAccess, QueryBucketReadAccessError>"
   }
 };
 
@@ -48,6 +48,19 @@ export const ALL_TYPES: RegistryTypes = {
   Key: "H256",
   RandomnessOutput: "H256",
   TrieRemoveMutation: "Null",
+  UploadState: {
+    _enum: ["AwaitingVolunteer", "Receiving", "Complete", "Confirmed", "Rejected"]
+  },
+  UploadProgress: {
+    expected_chunks: "u64",
+    received_chunks: "u64",
+    last_chunk_at: "Option<u64>",
+    state: "UploadState"
+  },
+  VerifiedFileProof: {
+    file_key: "H256",
+    chunk_ids: "Vec<u64>"
+  },
   CheckpointChallenge: {
     file_key: "H256",
     should_remove_file: "bool"
@@ -222,10 +235,35 @@ export const ALL_TYPES: RegistryTypes = {
       InternalApiError: null
     }
   },
+  QueryBucketSizeAndDataLimitError: {
+    _enum: {
+      BucketNotFound: null,
+      BucketNotStoredByMsp: null,
+      ValuePropositionNotFound: null,
+      InternalError: null
+    }
+  },
+  QueryBucketRootError: {
+    _enum: {
+      BucketNotFound: null,
+      InternalError: null
+    }
+  },
   QueryBucketsOfUserStoredByMspError: {
     _enum: {
       NotAnMsp: null,
       InternalError: null
     }
+  },
+  QueryBucketReadAccessError: {
+    _enum: {
+      BucketNotFound: null,
+      InternalError: null
+    }
+  },
+  BucketReadAccess: {
+    owner: "AccountId",
+    private: "bool",
+    holds_read_access_item: "bool"
   }
 };
@@ -346,6 +346,14 @@ impl_runtime_apis! {
         fn decode_generic_apply_delta_event_info(encoded_event_info: Vec<u8>) -> Result<BucketId<Runtime>, GenericApplyDeltaEventInfoError> {
             FileSystem::decode_generic_apply_delta_event_info(encoded_event_info)
         }
+
+        fn query_bsp_confirm_chunks_to_prove_for_files(bsp_id: BackupStorageProviderId<Runtime>, file_keys: Vec<H256>) -> Vec<(H256, Result<Vec<ChunkId>, QueryConfirmChunksToProveForFilesError>)> {
+            FileSystem::query_confirm_chunks_to_prove_for_files(bsp_id, file_keys)
+        }
+
+        fn query_msp_confirm_chunks_to_prove_for_files(msp_id: MainStorageProviderId<Runtime>, file_keys: Vec<H256>) -> Vec<(H256, Result<Vec<ChunkId>, QueryConfirmChunksToProveForFilesError>)> {
+            FileSystem::query_confirm_chunks_to_prove_for_files(msp_id, file_keys)
+        }
     }
 
     impl pallet_payment_streams_runtime_api::PaymentStreamsApi<Block, ProviderIdFor<Runtime>, Balance, AccountId> for Runtime {
@@ -406,6 +414,10 @@ impl_runtime_apis! {
         fn get_next_deadline_tick(provider_id: &ProofsDealerProviderIdFor<Runtime>) -> Result<BlockNumber, GetNextDeadlineTickError> {
             ProofsDealer::get_next_deadline_tick(provider_id)
         }
+
+        fn get_file_size_to_challenges() -> u64 {
+            shp_constants::FILE_SIZE_TO_CHALLENGES
+        }
     }
 
     impl pallet_storage_providers_runtime_api::StorageProvidersApi<Block, BlockNumber, BackupStorageProviderId<Runtime>, BackupStorageProvider<Runtime>, MainStorageProviderId<Runtime>, AccountId, ProviderIdFor<Runtime>, StorageProviderId<Runtime>, StorageDataUnit<Runtime>, Balance, BucketId<Runtime>, Multiaddresses<Runtime>, ValuePropositionWithId<Runtime>> for Runtime {
@@ -421,6 +433,14 @@ impl_runtime_apis! {
             Providers::query_msp_id_of_bucket_id(bucket_id)
         }
 
+        fn query_bucket_size_and_data_limit(bucket_id: &BucketId<Runtime>) -> Result<(StorageDataUnit<Runtime>, StorageDataUnit<Runtime>), QueryBucketSizeAndDataLimitError> {
+            Providers::query_bucket_size_and_data_limit(bucket_id)
+        }
+
+        fn query_bucket_root(bucket_id: &BucketId<Runtime>) -> Result<H256, QueryBucketRootError> {
+            Providers::query_bucket_root(bucket_id)
+        }
+
         fn query_storage_provider_capacity(provider_id: &ProviderIdFor<Runtime>) -> Result<StorageDataUnit<Runtime>, QueryStorageProviderCapacityError> {
             Providers::query_storage_provider_capacity(provider_id)
         }
@@ -464,5 +484,13 @@ impl_runtime_apis! {
         fn query_buckets_of_user_stored_by_msp(msp_id: &ProviderIdFor<Runtime>, user: &AccountId) -> Result<Vec<BucketId<Runtime>>, QueryBucketsOfUserStoredByMspError> {
             Providers::query_buckets_of_user_stored_by_msp(msp_id, user)
         }
+
+        fn query_bucket_read_access(bucket_id: &BucketId<Runtime>, user: &AccountId) -> Result<BucketReadAccess<AccountId>, QueryBucketReadAccessError> {
+            Providers::query_bucket_read_access(bucket_id, user, |collection_id, who| {
+                pallet_nfts::Account::<Runtime>::iter_key_prefix((who, collection_id))
+                    .next()
+                    .is_some()
+            })
+        }
     }
 }